@@ -0,0 +1,57 @@
+// Import-path throughput for the RocksDB wrapper: block-sized atomic commits
+// while concurrent readers hammer hot keys, as during validation.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+use omnitensor_core::storage::columns::Column;
+use omnitensor_core::storage::db::Database;
+use omnitensor_core::storage::transaction::StorageTransaction;
+
+const WRITES_PER_BLOCK: u64 = 500;
+const BLOCKS_PER_ITER: u64 = 20;
+
+async fn import_blocks(db: Arc<Database>, readers: usize) {
+    let mut handles = Vec::new();
+    for reader in 0..readers as u64 {
+        let db = Arc::clone(&db);
+        handles.push(tokio::spawn(async move {
+            for i in 0..WRITES_PER_BLOCK * BLOCKS_PER_ITER {
+                let _: Option<u64> = db.get_cf(Column::State, &((reader + i) % 64)).await.unwrap();
+            }
+        }));
+    }
+
+    for height in 0..BLOCKS_PER_ITER {
+        let mut txn = StorageTransaction::new();
+        for i in 0..WRITES_PER_BLOCK {
+            txn.put(Column::State, &(i % 64), &height).unwrap();
+        }
+        txn.put(Column::Headers, &height.to_be_bytes(), &[0u8; 128]).unwrap();
+        db.commit(txn).await.unwrap();
+    }
+
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+fn bench_import(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("storage_import");
+    group.throughput(Throughput::Elements(WRITES_PER_BLOCK * BLOCKS_PER_ITER));
+
+    for readers in [0usize, 4, 16] {
+        group.bench_with_input(BenchmarkId::from_parameter(readers), &readers, |b, &readers| {
+            let temp_dir = TempDir::new().unwrap();
+            let db = Arc::new(Database::new(temp_dir.path()).unwrap());
+            b.to_async(&runtime).iter(|| import_blocks(Arc::clone(&db), readers));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_import);
+criterion_main!(benches);
@@ -1,4 +1,9 @@
-
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/omnitensor/v1/node.proto");
+        tonic_build::compile_protos("proto/omnitensor/v1/node.proto").expect("failed to compile protobuf definitions");
+    }
 }
@@ -0,0 +1,18 @@
+#![no_main]
+
+// Transactions and headers in the hashed encoding, as read from raw
+// transactions and chain archives. Whatever decodes must re-encode to the
+// same bytes, or two encodings would share a hash.
+use libfuzzer_sys::fuzz_target;
+use omnitensor_core::chain::block::BlockHeader;
+use omnitensor_core::chain::canonical;
+use omnitensor_core::chain::transaction::Transaction;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(transaction) = canonical::from_bytes::<Transaction>(data) {
+        assert_eq!(canonical::to_bytes(&transaction), data);
+    }
+    if let Ok(header) = canonical::from_bytes::<BlockHeader>(data) {
+        assert_eq!(canonical::to_bytes(&header), data);
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+// The compression frame around every gossip and sync payload
+use libfuzzer_sys::fuzz_target;
+use omnitensor_core::network::compression::{CompressionConfig, MessageCompressor, PROTOCOL_VERSION};
+
+const MAX_LEN: usize = 64 * 1024;
+
+fuzz_target!(|data: &[u8]| {
+    let compressor = MessageCompressor::new(CompressionConfig::default());
+    for version in [1, PROTOCOL_VERSION] {
+        if let Ok(payload) = compressor.decode(version, data, MAX_LEN) {
+            assert!(payload.len() <= MAX_LEN);
+        }
+    }
+});
@@ -0,0 +1,27 @@
+#![no_main]
+
+// Every payload decoded from gossip or a request/response protocol, as it
+// arrives after decompression
+use libfuzzer_sys::fuzz_target;
+use omnitensor_core::chain::block::{Block, BlockHeader};
+use omnitensor_core::chain::transaction::{Transaction, TransactionHash, TransactionReceipt};
+use omnitensor_core::network::block_gossip::{BlockAnnouncement, BlockHash};
+use omnitensor_core::network::codec::decode_bounded;
+use omnitensor_core::network::header_sync::HeadersRequest;
+use omnitensor_core::network::light_client::SignedHeader;
+use omnitensor_core::network::tx_gossip::TxAnnouncement;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_bounded::<BlockAnnouncement>(data);
+    let _ = decode_bounded::<TxAnnouncement>(data);
+    if let Ok(signed) = decode_bounded::<SignedHeader>(data) {
+        let _ = signed.check_signatures();
+    }
+    let _ = decode_bounded::<HeadersRequest>(data);
+    let _ = decode_bounded::<Vec<BlockHeader>>(data);
+    let _ = decode_bounded::<Vec<BlockHash>>(data);
+    let _ = decode_bounded::<Vec<TransactionHash>>(data);
+    let _ = decode_bounded::<Vec<Transaction>>(data);
+    let _ = decode_bounded::<Vec<Block>>(data);
+    let _ = decode_bounded::<Vec<(Block, Vec<TransactionReceipt>)>>(data);
+});
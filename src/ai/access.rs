@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::chain::events::{self, EventError, Events};
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::types::{Address, BlockHeight};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccessPolicy {
+    // Anyone may invoke the model
+    Public,
+    // Only addresses explicitly granted by the owner may invoke the model
+    Allowlist,
+    // Only holders of an unexpired license may invoke the model; licenses can be transferred
+    License,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entitlement {
+    pub granted_at: BlockHeight,
+    pub expires_at: Option<BlockHeight>,
+}
+
+impl Entitlement {
+    pub fn is_active(&self, height: BlockHeight) -> bool {
+        match self.expires_at {
+            Some(expires_at) => height < expires_at,
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelAccess {
+    pub owner: Address,
+    pub policy: AccessPolicy,
+    pub entitlements: HashMap<Address, Entitlement>,
+}
+
+// Payload carried in `data` of GrantAccess / RevokeAccess transactions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessGrant {
+    pub model: Address,
+    pub grantee: Address,
+    pub expires_at: Option<BlockHeight>,
+}
+
+#[derive(Debug, Error)]
+pub enum AccessError {
+    #[error("Model is already registered")]
+    ModelAlreadyRegistered,
+    #[error("Model not found")]
+    ModelNotFound,
+    #[error("Only the model owner can change access")]
+    NotOwner,
+    #[error("Caller is not entitled to invoke this model")]
+    AccessDenied,
+    #[error("License not found for address")]
+    LicenseNotFound,
+    #[error("Model policy does not allow license transfers")]
+    NotTransferable,
+    #[error("Unexpected transaction type")]
+    UnexpectedTransactionType,
+    #[error("Malformed access payload")]
+    MalformedPayload,
+    #[error("Event error: {0}")]
+    Event(#[from] EventError),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+pub struct ModelAccessManager {
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl ModelAccessManager {
+    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
+        Self { storage }
+    }
+
+    pub async fn register_model(&mut self, model: Address, owner: Address, policy: AccessPolicy) -> Result<(), AccessError> {
+        let mut models = self.get_models().await?;
+        if models.contains_key(&model) {
+            return Err(AccessError::ModelAlreadyRegistered);
+        }
+
+        models.insert(model, ModelAccess {
+            owner,
+            policy,
+            entitlements: HashMap::new(),
+        });
+        self.storage.put_value(b"model_access", &models).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_policy(&mut self, caller: Address, model: Address, policy: AccessPolicy) -> Result<(), AccessError> {
+        let mut models = self.get_models().await?;
+        let access = models.get_mut(&model).ok_or(AccessError::ModelNotFound)?;
+        if access.owner != caller {
+            return Err(AccessError::NotOwner);
+        }
+
+        access.policy = policy;
+        self.storage.put_value(b"model_access", &models).await?;
+
+        Ok(())
+    }
+
+    // Emits AccessGranted / AccessRevoked, from the model's address with the
+    // grantee as second topic; a grant's data is its bincode `expires_at`.
+    // Invocations only have their access checked.
+    pub async fn apply_transaction(
+        &mut self,
+        tx: &Transaction,
+        height: BlockHeight,
+        events: &mut Events,
+    ) -> Result<(), AccessError> {
+        if matches!(tx.transaction_type, TransactionType::AIModelInvoke) {
+            return self.check_invoke(tx, height).await;
+        }
+        let grant: AccessGrant = bincode::deserialize(&tx.data).map_err(|_| AccessError::MalformedPayload)?;
+        let (model, grantee) = (grant.model, grant.grantee);
+        let (name, data) = match tx.transaction_type {
+            TransactionType::GrantAccess => {
+                let data = bincode::serialize(&grant.expires_at).map_err(|_| AccessError::MalformedPayload)?;
+                self.grant_access(tx.from, grant, height).await?;
+                ("AccessGranted", data)
+            }
+            TransactionType::RevokeAccess => {
+                self.revoke_access(tx.from, model, grantee).await?;
+                ("AccessRevoked", Vec::new())
+            }
+            _ => return Err(AccessError::UnexpectedTransactionType),
+        };
+        events.emit(model, vec![events::topic(name), events::address_topic(&grantee)], data)?;
+        Ok(())
+    }
+
+    pub async fn grant_access(&mut self, caller: Address, grant: AccessGrant, height: BlockHeight) -> Result<(), AccessError> {
+        let mut models = self.get_models().await?;
+        let access = models.get_mut(&grant.model).ok_or(AccessError::ModelNotFound)?;
+        if access.owner != caller {
+            return Err(AccessError::NotOwner);
+        }
+
+        access.entitlements.insert(grant.grantee, Entitlement {
+            granted_at: height,
+            expires_at: grant.expires_at,
+        });
+        self.storage.put_value(b"model_access", &models).await?;
+
+        Ok(())
+    }
+
+    pub async fn revoke_access(&mut self, caller: Address, model: Address, grantee: Address) -> Result<(), AccessError> {
+        let mut models = self.get_models().await?;
+        let access = models.get_mut(&model).ok_or(AccessError::ModelNotFound)?;
+        if access.owner != caller {
+            return Err(AccessError::NotOwner);
+        }
+
+        access.entitlements.remove(&grantee);
+        self.storage.put_value(b"model_access", &models).await?;
+
+        Ok(())
+    }
+
+    pub async fn transfer_license(&mut self, from: Address, to: Address, model: Address) -> Result<(), AccessError> {
+        let mut models = self.get_models().await?;
+        let access = models.get_mut(&model).ok_or(AccessError::ModelNotFound)?;
+        if access.policy != AccessPolicy::License {
+            return Err(AccessError::NotTransferable);
+        }
+
+        let entitlement = access.entitlements.remove(&from).ok_or(AccessError::LicenseNotFound)?;
+        access.entitlements.insert(to, entitlement);
+        self.storage.put_value(b"model_access", &models).await?;
+
+        Ok(())
+    }
+
+    pub async fn has_access(&self, model: Address, caller: Address, height: BlockHeight) -> Result<bool, AccessError> {
+        let models = self.get_models().await?;
+        let access = match models.get(&model) {
+            Some(access) => access,
+            // Models without an access record are unrestricted
+            None => return Ok(true),
+        };
+
+        if access.owner == caller {
+            return Ok(true);
+        }
+
+        Ok(match access.policy {
+            AccessPolicy::Public => true,
+            AccessPolicy::Allowlist | AccessPolicy::License => {
+                access.entitlements.get(&caller).map_or(false, |entitlement| entitlement.is_active(height))
+            }
+        })
+    }
+
+    // Called while validating an AIModelInvoke transaction; `to` is the model address
+    pub async fn check_invoke(&self, tx: &Transaction, height: BlockHeight) -> Result<(), AccessError> {
+        if !matches!(tx.transaction_type, TransactionType::AIModelInvoke) {
+            return Ok(());
+        }
+
+        if self.has_access(tx.to, tx.from, height).await? {
+            Ok(())
+        } else {
+            Err(AccessError::AccessDenied)
+        }
+    }
+
+    pub async fn get_entitlements(&self, model: Address) -> Result<HashMap<Address, Entitlement>, AccessError> {
+        let models = self.get_models().await?;
+        models
+            .get(&model)
+            .map(|access| access.entitlements.clone())
+            .ok_or(AccessError::ModelNotFound)
+    }
+
+    pub async fn owner_of(&self, model: &Address) -> Result<Option<Address>, AccessError> {
+        Ok(self.get_models().await?.get(model).map(|access| access.owner))
+    }
+
+    async fn get_models(&self) -> Result<HashMap<Address, ModelAccess>, AccessError> {
+        Ok(self.storage.get_value(b"model_access").await?.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    fn invoke(from: Address, model: Address) -> Transaction {
+        Transaction::new(0, from, model, 0, 10, 21000, vec![], TransactionType::AIModelInvoke)
+    }
+
+    #[tokio::test]
+    async fn test_allowlist_enforcement() {
+        let mut manager = ModelAccessManager::new(Arc::new(MemoryBackend::new()));
+        let owner = Address::random();
+        let model = Address::random();
+        let user = Address::random();
+
+        manager.register_model(model, owner, AccessPolicy::Allowlist).await.unwrap();
+        assert!(manager.check_invoke(&invoke(owner, model), BlockHeight::from(1)).await.is_ok());
+        assert!(manager.check_invoke(&invoke(user, model), BlockHeight::from(1)).await.is_err());
+
+        let grant = AccessGrant { model, grantee: user, expires_at: None };
+        let mut tx = Transaction::new(0, owner, model, 0, 10, 21000, bincode::serialize(&grant).unwrap(), TransactionType::GrantAccess);
+        let mut events = Events::new();
+        manager.apply_transaction(&tx, BlockHeight::from(1), &mut events).await.unwrap();
+        assert!(manager.check_invoke(&invoke(user, model), BlockHeight::from(2)).await.is_ok());
+        assert!(manager.apply_transaction(&invoke(user, model), BlockHeight::from(2), &mut events).await.is_ok());
+
+        tx.transaction_type = TransactionType::RevokeAccess;
+        manager.apply_transaction(&tx, BlockHeight::from(3), &mut events).await.unwrap();
+        assert!(manager.check_invoke(&invoke(user, model), BlockHeight::from(4)).await.is_err());
+        assert!(matches!(
+            manager.apply_transaction(&invoke(user, model), BlockHeight::from(4), &mut events).await,
+            Err(AccessError::AccessDenied)
+        ));
+
+        // Allowlist entries expire like licenses
+        let grant = AccessGrant { model, grantee: user, expires_at: Some(BlockHeight::from(10)) };
+        manager.grant_access(owner, grant, BlockHeight::from(5)).await.unwrap();
+        assert!(manager.has_access(model, user, BlockHeight::from(9)).await.unwrap());
+        assert!(!manager.has_access(model, user, BlockHeight::from(10)).await.unwrap());
+
+        let logs = events.logs();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].topics[0].as_bytes(), events::topic("AccessGranted").as_bytes());
+        assert_eq!(logs[1].topics[0].as_bytes(), events::topic("AccessRevoked").as_bytes());
+        for log in logs {
+            assert_eq!(log.address, model);
+            assert_eq!(log.topics[1].as_bytes(), events::address_topic(&user).as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_license_expiry_and_transfer() {
+        let mut manager = ModelAccessManager::new(Arc::new(MemoryBackend::new()));
+        let owner = Address::random();
+        let model = Address::random();
+        let holder = Address::random();
+        let buyer = Address::random();
+
+        manager.register_model(model, owner, AccessPolicy::License).await.unwrap();
+        let grant = AccessGrant { model, grantee: holder, expires_at: Some(BlockHeight::from(100)) };
+        manager.grant_access(owner, grant, BlockHeight::from(1)).await.unwrap();
+
+        assert!(manager.has_access(model, holder, BlockHeight::from(50)).await.unwrap());
+        assert!(!manager.has_access(model, holder, BlockHeight::from(100)).await.unwrap());
+
+        manager.transfer_license(holder, buyer, model).await.unwrap();
+        assert!(!manager.has_access(model, holder, BlockHeight::from(50)).await.unwrap());
+        assert!(manager.has_access(model, buyer, BlockHeight::from(50)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_only_owner_can_grant() {
+        let mut manager = ModelAccessManager::new(Arc::new(MemoryBackend::new()));
+        let model = Address::random();
+        let attacker = Address::random();
+
+        manager.register_model(model, Address::random(), AccessPolicy::Allowlist).await.unwrap();
+        let grant = AccessGrant { model, grantee: attacker, expires_at: None };
+        assert!(matches!(
+            manager.grant_access(attacker, grant, BlockHeight::from(1)).await,
+            Err(AccessError::NotOwner)
+        ));
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::convert::TryInto;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::crypto::hasher::{HashAlgorithm, TaggedHash};
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::types::{Address, BlockHeight};
+
+// Size of a weight chunk committed to by the model owner; only the last one
+// may be shorter
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+// Chunk tree hashes are prefixed so a leaf can't pass for an inner node
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingConfig {
+    // Chunks sampled per challenge round
+    pub samples: usize,
+    // Blocks the owner or hosting providers have to answer a challenge
+    pub response_blocks: u64,
+    // Successful rounds before the model becomes invocable
+    pub required_rounds: u32,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            samples: 8,
+            response_blocks: 50,
+            required_rounds: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AvailabilityStatus {
+    Pending,
+    Challenged { indices: Vec<u32>, deadline: BlockHeight },
+    Available,
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightCommitment {
+    pub owner: Address,
+    // Merkle root over the hashes of each weight chunk
+    pub root: [u8; 32],
+    // Chosen by the owner; commitments from before the choice are SHA3-256
+    #[serde(default)]
+    pub algorithm: HashAlgorithm,
+    pub chunk_count: u32,
+    pub passed_rounds: u32,
+    pub status: AvailabilityStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkProof {
+    pub index: u32,
+    pub chunk: Vec<u8>,
+    // Sibling hashes from the leaf up to the root
+    pub path: Vec<[u8; 32]>,
+}
+
+#[derive(Debug, Error)]
+pub enum AvailabilityError {
+    #[error("Model weights already committed")]
+    AlreadyCommitted,
+    #[error("Model weights not committed")]
+    NotCommitted,
+    #[error("Commitment must cover at least one chunk")]
+    EmptyCommitment,
+    #[error("No open challenge for model")]
+    NoOpenChallenge,
+    #[error("Challenge deadline passed at height {0}")]
+    DeadlinePassed(BlockHeight),
+    #[error("Missing proof for sampled chunk {0}")]
+    MissingChunk(u32),
+    #[error("Invalid proof for chunk {0}")]
+    InvalidChunkProof(u32),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+pub struct AvailabilitySampler {
+    storage: Arc<dyn StorageBackend>,
+    config: SamplingConfig,
+}
+
+impl AvailabilitySampler {
+    pub fn new(storage: Arc<dyn StorageBackend>, config: SamplingConfig) -> Self {
+        Self { storage, config }
+    }
+
+    pub async fn commit_weights(&mut self, model: Address, owner: Address, root: TaggedHash, chunk_count: u32) -> Result<(), AvailabilityError> {
+        if chunk_count == 0 {
+            return Err(AvailabilityError::EmptyCommitment);
+        }
+
+        let mut commitments = self.get_commitments().await?;
+        if commitments.contains_key(&model) {
+            return Err(AvailabilityError::AlreadyCommitted);
+        }
+
+        commitments.insert(model, WeightCommitment {
+            owner,
+            root: root.digest,
+            algorithm: root.algorithm,
+            chunk_count,
+            passed_rounds: 0,
+            status: AvailabilityStatus::Pending,
+        });
+        self.storage.put_value(b"weight_commitments", &commitments).await?;
+
+        Ok(())
+    }
+
+    // `seed` must come from consensus randomness (e.g. the proposer's `crypto::vrf` output) so owners can't predict samples
+    pub async fn open_challenge(&mut self, model: Address, seed: &[u8], height: BlockHeight) -> Result<Vec<u32>, AvailabilityError> {
+        let mut commitments = self.get_commitments().await?;
+        let commitment = commitments.get_mut(&model).ok_or(AvailabilityError::NotCommitted)?;
+
+        let indices = sample_indices(seed, &model, commitment.chunk_count, self.config.samples);
+        commitment.status = AvailabilityStatus::Challenged {
+            indices: indices.clone(),
+            deadline: height + self.config.response_blocks,
+        };
+        self.storage.put_value(b"weight_commitments", &commitments).await?;
+
+        Ok(indices)
+    }
+
+    pub async fn respond(&mut self, model: Address, proofs: &[ChunkProof], height: BlockHeight) -> Result<AvailabilityStatus, AvailabilityError> {
+        let mut commitments = self.get_commitments().await?;
+        let commitment = commitments.get_mut(&model).ok_or(AvailabilityError::NotCommitted)?;
+
+        let (indices, deadline) = match &commitment.status {
+            AvailabilityStatus::Challenged { indices, deadline } => (indices.clone(), *deadline),
+            _ => return Err(AvailabilityError::NoOpenChallenge),
+        };
+        if height >= deadline {
+            return Err(AvailabilityError::DeadlinePassed(deadline));
+        }
+
+        for index in indices {
+            let proof = proofs
+                .iter()
+                .find(|proof| proof.index == index)
+                .ok_or(AvailabilityError::MissingChunk(index))?;
+            let root = TaggedHash { algorithm: commitment.algorithm, digest: commitment.root };
+            if !verify_chunk(&root, commitment.chunk_count, proof) {
+                return Err(AvailabilityError::InvalidChunkProof(index));
+            }
+        }
+
+        commitment.passed_rounds += 1;
+        commitment.status = if commitment.passed_rounds >= self.config.required_rounds {
+            AvailabilityStatus::Available
+        } else {
+            AvailabilityStatus::Pending
+        };
+        let status = commitment.status.clone();
+        self.storage.put_value(b"weight_commitments", &commitments).await?;
+
+        Ok(status)
+    }
+
+    // Marks models whose challenge went unanswered as unavailable; returns the affected models
+    pub async fn expire_challenges(&mut self, height: BlockHeight) -> Result<Vec<Address>, AvailabilityError> {
+        let mut commitments = self.get_commitments().await?;
+        let mut expired = Vec::new();
+
+        for (model, commitment) in commitments.iter_mut() {
+            if let AvailabilityStatus::Challenged { deadline, .. } = commitment.status {
+                if height >= deadline {
+                    commitment.status = AvailabilityStatus::Unavailable;
+                    expired.push(*model);
+                }
+            }
+        }
+
+        self.storage.put_value(b"weight_commitments", &commitments).await?;
+
+        Ok(expired)
+    }
+
+    pub async fn is_invocable(&self, model: Address) -> Result<bool, AvailabilityError> {
+        Ok(self
+            .get_commitments()
+            .await?
+            .get(&model)
+            .map(|commitment| commitment.status == AvailabilityStatus::Available)
+            .unwrap_or(false))
+    }
+
+    async fn get_commitments(&self) -> Result<HashMap<Address, WeightCommitment>, AvailabilityError> {
+        Ok(self.storage.get_value(b"weight_commitments").await?.unwrap_or_default())
+    }
+}
+
+fn sample_indices(seed: &[u8], model: &Address, chunk_count: u32, samples: usize) -> Vec<u32> {
+    let mut indices = Vec::new();
+    let mut counter: u64 = 0;
+    while indices.len() < samples.min(chunk_count as usize) {
+        let mut hasher = Sha3_256::new();
+        hasher.update(seed);
+        hasher.update(bincode::serialize(model).unwrap());
+        hasher.update(counter.to_le_bytes());
+        let digest = hasher.finalize();
+        let index = (u64::from_le_bytes(digest[..8].try_into().unwrap()) % chunk_count as u64) as u32;
+        if !indices.contains(&index) {
+            indices.push(index);
+        }
+        counter += 1;
+    }
+    indices
+}
+
+fn leaf_hash(algorithm: HashAlgorithm, chunk: &[u8]) -> [u8; 32] {
+    let mut hasher = algorithm.hasher();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(chunk);
+    hasher.finalize()
+}
+
+fn node_hash(algorithm: HashAlgorithm, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = algorithm.hasher();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize()
+}
+
+fn next_level(algorithm: HashAlgorithm, level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level.chunks(2).map(|pair| node_hash(algorithm, &pair[0], pair.get(1).unwrap_or(&pair[0]))).collect()
+}
+
+// Levels above the leaves in a tree over `chunk_count` chunks, i.e. the
+// length of every proof against it
+fn tree_depth(chunk_count: u32) -> usize {
+    let mut width = chunk_count;
+    let mut depth = 0;
+    while width > 1 {
+        width = (width + 1) / 2;
+        depth += 1;
+    }
+    depth
+}
+
+// Chunk tree uses the same odd-node duplication rule as the block merkle
+// root. BLAKE3 hashes large models several times faster than SHA3-256.
+pub fn chunk_root(algorithm: HashAlgorithm, chunks: &[Vec<u8>]) -> TaggedHash {
+    let mut level: Vec<[u8; 32]> = chunks.iter().map(|chunk| leaf_hash(algorithm, chunk)).collect();
+    while level.len() > 1 {
+        level = next_level(algorithm, &level);
+    }
+    TaggedHash { algorithm, digest: level.first().copied().unwrap_or([0; 32]) }
+}
+
+// `None` if there is no chunk at `index`
+pub fn chunk_proof(algorithm: HashAlgorithm, chunks: &[Vec<u8>], index: u32) -> Option<ChunkProof> {
+    let chunk = chunks.get(index as usize)?.clone();
+    let mut level: Vec<[u8; 32]> = chunks.iter().map(|chunk| leaf_hash(algorithm, chunk)).collect();
+    let mut position = index as usize;
+    let mut path = Vec::new();
+    while level.len() > 1 {
+        path.push(*level.get(position ^ 1).unwrap_or(&level[position]));
+        level = next_level(algorithm, &level);
+        position /= 2;
+    }
+    Some(ChunkProof { index, chunk, path })
+}
+
+// Checks `proof` against a commitment to `chunk_count` chunks. The path has
+// to be exactly as long as the tree is deep, so a proof can't stop at an
+// inner node or climb past the root.
+pub fn verify_chunk(root: &TaggedHash, chunk_count: u32, proof: &ChunkProof) -> bool {
+    if proof.index >= chunk_count || proof.chunk.len() > CHUNK_SIZE || proof.path.len() != tree_depth(chunk_count) {
+        return false;
+    }
+    let algorithm = root.algorithm;
+    let mut hash = leaf_hash(algorithm, &proof.chunk);
+    let mut position = proof.index as usize;
+    for sibling in &proof.path {
+        hash = if position % 2 == 0 {
+            node_hash(algorithm, &hash, sibling)
+        } else {
+            node_hash(algorithm, sibling, &hash)
+        };
+        position /= 2;
+    }
+    position == 0 && hash == root.digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    fn weights() -> Vec<Vec<u8>> {
+        (0..5u8).map(|i| vec![i; 64]).collect()
+    }
+
+    #[test]
+    fn test_chunk_proofs() {
+        let chunks = weights();
+        for algorithm in [HashAlgorithm::Sha3_256, HashAlgorithm::Blake3] {
+            let root = chunk_root(algorithm, &chunks);
+            for index in 0..chunks.len() as u32 {
+                assert!(verify_chunk(&root, 5, &chunk_proof(algorithm, &chunks, index).unwrap()));
+            }
+            assert!(chunk_proof(algorithm, &chunks, 5).is_none());
+
+            let mut tampered = chunk_proof(algorithm, &chunks, 2).unwrap();
+            tampered.chunk[0] ^= 1;
+            assert!(!verify_chunk(&root, 5, &tampered));
+        }
+        let sha3_proof = chunk_proof(HashAlgorithm::Sha3_256, &chunks, 1).unwrap();
+        assert!(!verify_chunk(&chunk_root(HashAlgorithm::Blake3, &chunks), 5, &sha3_proof));
+    }
+
+    #[test]
+    fn test_rejects_proofs_that_stop_short_or_overrun() {
+        let algorithm = HashAlgorithm::Sha3_256;
+        let chunks = weights();
+        let root = chunk_root(algorithm, &chunks);
+        let proof = chunk_proof(algorithm, &chunks, 4).unwrap();
+
+        // The two children of the root's left child, passed off as a chunk
+        let left = chunk_root(algorithm, &chunks[..2]).digest;
+        let right = chunk_root(algorithm, &chunks[2..4]).digest;
+        let first = chunk_proof(algorithm, &chunks, 0).unwrap();
+        let inner = ChunkProof { index: 0, chunk: [left, right].concat(), path: first.path[2..].to_vec() };
+        assert!(!verify_chunk(&root, 5, &inner));
+
+        // The same path from an index past the last chunk wraps onto it
+        let wrapped = ChunkProof { index: 12, ..proof.clone() };
+        assert!(!verify_chunk(&root, 5, &wrapped));
+        assert!(!verify_chunk(&root, 16, &wrapped));
+
+        let mut long = proof.clone();
+        long.path.push(root.digest);
+        assert!(!verify_chunk(&root, 5, &long));
+        assert!(verify_chunk(&root, 5, &proof));
+    }
+
+    #[tokio::test]
+    async fn test_model_becomes_available_after_sampling() {
+        let chunks = weights();
+        let model = Address::random();
+        let mut sampler = AvailabilitySampler::new(Arc::new(MemoryBackend::new()), SamplingConfig {
+            samples: 3,
+            response_blocks: 10,
+            required_rounds: 2,
+        });
+        let root = chunk_root(HashAlgorithm::Blake3, &chunks);
+        sampler.commit_weights(model, Address::random(), root, chunks.len() as u32).await.unwrap();
+
+        for round in 0..2u64 {
+            assert!(!sampler.is_invocable(model).await.unwrap());
+            let indices = sampler.open_challenge(model, &round.to_le_bytes(), BlockHeight::from(round * 20)).await.unwrap();
+            assert_eq!(indices.len(), 3);
+            let proofs: Vec<ChunkProof> = indices.iter().map(|index| chunk_proof(HashAlgorithm::Blake3, &chunks, *index).unwrap()).collect();
+            sampler.respond(model, &proofs, BlockHeight::from(round * 20 + 1)).await.unwrap();
+        }
+
+        assert!(sampler.is_invocable(model).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_challenge_marks_ghost_model() {
+        let model = Address::random();
+        let mut sampler = AvailabilitySampler::new(Arc::new(MemoryBackend::new()), SamplingConfig::default());
+        let root = TaggedHash { algorithm: HashAlgorithm::Sha3_256, digest: [7; 32] };
+        sampler.commit_weights(model, Address::random(), root, 16).await.unwrap();
+        sampler.open_challenge(model, b"seed", BlockHeight::from(1)).await.unwrap();
+
+        assert!(sampler.respond(model, &[], BlockHeight::from(60)).await.is_err());
+        assert_eq!(sampler.expire_challenges(BlockHeight::from(60)).await.unwrap(), vec![model]);
+        assert!(!sampler.is_invocable(model).await.unwrap());
+    }
+}
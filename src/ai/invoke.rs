@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::crypto::hash::Hash;
+
+pub const MAX_BATCH_SIZE: usize = 256;
+pub const INVOKE_BASE_GAS: u64 = 21_000;
+pub const INVOKE_ITEM_GAS: u64 = 5_000;
+pub const INVOKE_BYTE_GAS: u64 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailurePolicy {
+    // Any failed item reverts the whole batch
+    AllOrNothing,
+    // Successful items settle, failed items are reported and not charged compute fees
+    BestEffort,
+}
+
+// Payload carried in `data` of an AIModelInvoke transaction. A single invocation
+// is simply a batch of one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvokeRequest {
+    pub inputs: Vec<Vec<u8>>,
+    pub failure_policy: FailurePolicy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ItemOutcome {
+    Completed { output_hash: Hash },
+    Failed { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemResult {
+    pub index: u32,
+    pub input_hash: Hash,
+    pub outcome: ItemOutcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchReceipt {
+    pub items: Vec<ItemResult>,
+    pub succeeded: u32,
+    pub failed: u32,
+    // False if the batch was reverted under `AllOrNothing`
+    pub status: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum InvokeError {
+    #[error("Transaction is not a model invocation")]
+    NotAnInvocation,
+    #[error("Malformed invoke payload")]
+    MalformedPayload,
+    #[error("Batch is empty")]
+    EmptyBatch,
+    #[error("Batch of {0} items exceeds the maximum of {MAX_BATCH_SIZE}")]
+    BatchTooLarge(usize),
+    #[error("Expected {expected} outcomes, got {actual}")]
+    OutcomeCountMismatch { expected: usize, actual: usize },
+}
+
+impl InvokeRequest {
+    pub fn single(input: Vec<u8>) -> Self {
+        Self {
+            inputs: vec![input],
+            failure_policy: FailurePolicy::AllOrNothing,
+        }
+    }
+
+    pub fn from_transaction(tx: &Transaction) -> Result<Self, InvokeError> {
+        if !matches!(tx.transaction_type, TransactionType::AIModelInvoke) {
+            return Err(InvokeError::NotAnInvocation);
+        }
+
+        let request: Self = bincode::deserialize(&tx.data).map_err(|_| InvokeError::MalformedPayload)?;
+        request.validate()?;
+        Ok(request)
+    }
+
+    pub fn validate(&self) -> Result<(), InvokeError> {
+        if self.inputs.is_empty() {
+            return Err(InvokeError::EmptyBatch);
+        }
+        if self.inputs.len() > MAX_BATCH_SIZE {
+            return Err(InvokeError::BatchTooLarge(self.inputs.len()));
+        }
+        Ok(())
+    }
+
+    pub fn input_hashes(&self) -> Vec<Hash> {
+        self.inputs.iter().map(|input| Hash::hash(input)).collect()
+    }
+
+    // The base cost is paid once per transaction, which is where batching saves
+    pub fn intrinsic_gas(&self) -> u64 {
+        let bytes: u64 = self.inputs.iter().map(|input| input.len() as u64).sum();
+        INVOKE_BASE_GAS + INVOKE_ITEM_GAS * self.inputs.len() as u64 + INVOKE_BYTE_GAS * bytes
+    }
+
+    pub fn settle(&self, outcomes: Vec<ItemOutcome>) -> Result<BatchReceipt, InvokeError> {
+        if outcomes.len() != self.inputs.len() {
+            return Err(InvokeError::OutcomeCountMismatch {
+                expected: self.inputs.len(),
+                actual: outcomes.len(),
+            });
+        }
+
+        let items: Vec<ItemResult> = self
+            .input_hashes()
+            .into_iter()
+            .zip(outcomes)
+            .enumerate()
+            .map(|(index, (input_hash, outcome))| ItemResult {
+                index: index as u32,
+                input_hash,
+                outcome,
+            })
+            .collect();
+
+        let failed = items
+            .iter()
+            .filter(|item| matches!(item.outcome, ItemOutcome::Failed { .. }))
+            .count() as u32;
+        let succeeded = items.len() as u32 - failed;
+        let status = match self.failure_policy {
+            FailurePolicy::AllOrNothing => failed == 0,
+            FailurePolicy::BestEffort => succeeded > 0,
+        };
+
+        Ok(BatchReceipt {
+            items,
+            succeeded,
+            failed,
+            status,
+        })
+    }
+}
+
+impl BatchReceipt {
+    // Output hashes of the items that settle, in batch order
+    pub fn settled_outputs(&self) -> Vec<(u32, Hash)> {
+        if !self.status {
+            return Vec::new();
+        }
+
+        self.items
+            .iter()
+            .filter_map(|item| match &item.outcome {
+                ItemOutcome::Completed { output_hash } => Some((item.index, output_hash.clone())),
+                ItemOutcome::Failed { .. } => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcomes() -> Vec<ItemOutcome> {
+        vec![
+            ItemOutcome::Completed { output_hash: Hash::hash(b"a") },
+            ItemOutcome::Failed { reason: "timeout".to_string() },
+            ItemOutcome::Completed { output_hash: Hash::hash(b"c") },
+        ]
+    }
+
+    #[test]
+    fn test_batch_partial_failure() {
+        let request = InvokeRequest {
+            inputs: vec![b"x".to_vec(), b"y".to_vec(), b"z".to_vec()],
+            failure_policy: FailurePolicy::BestEffort,
+        };
+
+        let receipt = request.settle(outcomes()).unwrap();
+        assert!(receipt.status);
+        assert_eq!((receipt.succeeded, receipt.failed), (2, 1));
+        assert_eq!(receipt.settled_outputs().len(), 2);
+        assert_eq!(receipt.items[1].input_hash, Hash::hash(b"y"));
+    }
+
+    #[test]
+    fn test_batch_all_or_nothing() {
+        let request = InvokeRequest {
+            inputs: vec![b"x".to_vec(), b"y".to_vec(), b"z".to_vec()],
+            failure_policy: FailurePolicy::AllOrNothing,
+        };
+
+        let receipt = request.settle(outcomes()).unwrap();
+        assert!(!receipt.status);
+        assert!(receipt.settled_outputs().is_empty());
+        assert!(request.settle(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_batching_amortizes_gas() {
+        let batch = InvokeRequest {
+            inputs: vec![vec![0; 10]; 10],
+            failure_policy: FailurePolicy::BestEffort,
+        };
+        let single = InvokeRequest::single(vec![0; 10]);
+        assert!(batch.intrinsic_gas() < single.intrinsic_gas() * 10);
+
+        let oversized = InvokeRequest {
+            inputs: vec![vec![]; MAX_BATCH_SIZE + 1],
+            failure_policy: FailurePolicy::BestEffort,
+        };
+        assert!(matches!(oversized.validate(), Err(InvokeError::BatchTooLarge(_))));
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, info_span, instrument, warn};
+
+use crate::crypto::hash::Hash;
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::storage::transaction::StorageTransaction;
+use crate::types::{Address, Balance, BlockHeight};
+use crate::utils::decimal::Decimal;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobConfig {
+    // Blocks a provider has to deliver a result after assignment
+    pub timeout_blocks: u64,
+    // Share of the provider's collateral slashed per missed deadline, in basis points
+    pub timeout_slash_bps: u32,
+    // Assignments attempted before the job is expired and the requester refunded
+    pub max_attempts: u32,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            timeout_blocks: 20,
+            timeout_slash_bps: 500,
+            max_attempts: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobStatus {
+    Assigned { provider: Address, deadline: BlockHeight },
+    Completed { provider: Address, output_hash: Hash },
+    Expired,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InferenceJob {
+    pub id: Hash,
+    pub requester: Address,
+    pub model: Address,
+    pub fee: Balance,
+    pub status: JobStatus,
+    pub attempts: u32,
+    // Providers that already missed this job, never reassigned to it
+    pub failed_providers: Vec<Address>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeoutEvent {
+    Slashed { job: Hash, provider: Address, amount: Balance },
+    Reassigned { job: Hash, requester: Address, provider: Address, deadline: BlockHeight },
+    Refunded { job: Hash, requester: Address, amount: Balance },
+}
+
+#[derive(Debug, Error)]
+pub enum JobError {
+    #[error("Job already exists")]
+    JobExists,
+    #[error("Job not found")]
+    JobNotFound,
+    #[error("Provider is not registered")]
+    UnknownProvider,
+    #[error("Job is not assigned to this provider")]
+    NotAssigned,
+    #[error("Deadline passed at height {0}")]
+    DeadlinePassed(BlockHeight),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// `inference_jobs` holds only the jobs still assigned, which every block
+// checks for missed deadlines; completed and expired ones are moved to a
+// record of their own
+pub struct JobScheduler {
+    storage: Arc<dyn StorageBackend>,
+    config: JobConfig,
+}
+
+impl JobScheduler {
+    pub fn new(storage: Arc<dyn StorageBackend>, config: JobConfig) -> Self {
+        Self { storage, config }
+    }
+
+    pub async fn deposit_collateral(&mut self, provider: Address, amount: Balance) -> Result<(), JobError> {
+        let mut collateral = self.get_collateral().await?;
+        *collateral.entry(provider).or_insert_with(Balance::zero) += amount;
+        self.storage.put_value(b"provider_collateral", &collateral).await?;
+
+        Ok(())
+    }
+
+    pub async fn collateral_of(&self, provider: Address) -> Result<Balance, JobError> {
+        Ok(self.get_collateral().await?.get(&provider).copied().unwrap_or_else(Balance::zero))
+    }
+
+    #[instrument(name = "inference_job", skip_all, fields(job = ?id, height = ?height))]
+    pub async fn assign(&mut self, id: Hash, requester: Address, model: Address, fee: Balance, provider: Address, height: BlockHeight) -> Result<BlockHeight, JobError> {
+        if !self.get_collateral().await?.contains_key(&provider) {
+            return Err(JobError::UnknownProvider);
+        }
+
+        let mut jobs = self.get_jobs().await?;
+        if jobs.contains_key(&id) || self.get_finished(&id).await?.is_some() {
+            return Err(JobError::JobExists);
+        }
+
+        let deadline = height + self.config.timeout_blocks;
+        jobs.insert(id.clone(), InferenceJob {
+            id,
+            requester,
+            model,
+            fee,
+            status: JobStatus::Assigned { provider, deadline },
+            attempts: 1,
+            failed_providers: Vec::new(),
+        });
+        self.storage.put_value(b"inference_jobs", &jobs).await?;
+        debug!(provider = ?provider, deadline = ?deadline, "Assigned inference job");
+
+        Ok(deadline)
+    }
+
+    #[instrument(name = "inference_job", skip_all, fields(job = ?id, height = ?height))]
+    pub async fn submit_result(&mut self, id: &Hash, provider: Address, output_hash: Hash, height: BlockHeight) -> Result<(), JobError> {
+        let mut jobs = self.get_jobs().await?;
+        let job = jobs.get_mut(id).ok_or(JobError::JobNotFound)?;
+
+        match job.status {
+            JobStatus::Assigned { provider: assigned, deadline } if assigned == provider => {
+                if height >= deadline {
+                    return Err(JobError::DeadlinePassed(deadline));
+                }
+            }
+            _ => return Err(JobError::NotAssigned),
+        }
+
+        job.status = JobStatus::Completed { provider, output_hash };
+        let job = jobs.remove(id).expect("found above");
+        let mut transaction = StorageTransaction::new();
+        transaction.put_value(b"inference_jobs", &jobs)?.put_value(&finished_key(id), &job)?;
+        self.storage.write(transaction).await?;
+        debug!(provider = ?provider, "Inference job completed");
+
+        Ok(())
+    }
+
+    // Run once per block. `next_provider` picks a replacement, skipping the given providers;
+    // one without collateral is refused like in `assign`.
+    pub async fn process_timeouts<F>(&mut self, height: BlockHeight, mut next_provider: F) -> Result<Vec<TimeoutEvent>, JobError>
+    where
+        F: FnMut(&InferenceJob) -> Option<Address>,
+    {
+        let mut jobs = self.get_jobs().await?;
+        let mut collateral = self.get_collateral().await?;
+        let mut events = Vec::new();
+
+        // Every node must slash, reassign and emit events in the same order
+        let mut ids: Vec<Hash> = jobs.keys().cloned().collect();
+        ids.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+
+        for id in &ids {
+            let job = jobs.get_mut(id).expect("ids come from the map");
+            let provider = match job.status {
+                JobStatus::Assigned { provider, deadline } if height >= deadline => provider,
+                _ => continue,
+            };
+            let _span = info_span!("inference_job", job = ?job.id, height = ?height).entered();
+            warn!(provider = ?provider, attempt = job.attempts, "Provider missed the deadline");
+
+            if let Some(stake) = collateral.get_mut(&provider) {
+                let amount = Decimal::from_bps(self.config.timeout_slash_bps).share_of(*stake);
+                *stake -= amount;
+                events.push(TimeoutEvent::Slashed { job: job.id.clone(), provider, amount });
+            }
+            job.failed_providers.push(provider);
+
+            let replacement = if job.attempts < self.config.max_attempts {
+                next_provider(job)
+                    .filter(|candidate| !job.failed_providers.contains(candidate) && collateral.contains_key(candidate))
+            } else {
+                None
+            };
+
+            match replacement {
+                Some(provider) => {
+                    let deadline = height + self.config.timeout_blocks;
+                    job.attempts += 1;
+                    job.status = JobStatus::Assigned { provider, deadline };
+                    events.push(TimeoutEvent::Reassigned { job: job.id.clone(), requester: job.requester, provider, deadline });
+                }
+                None => {
+                    job.status = JobStatus::Expired;
+                    events.push(TimeoutEvent::Refunded {
+                        job: job.id.clone(),
+                        requester: job.requester,
+                        amount: job.fee,
+                    });
+                }
+            }
+        }
+
+        if events.is_empty() {
+            return Ok(events);
+        }
+
+        // Slashes, reassignments and refunds land together or not at all
+        let mut transaction = StorageTransaction::new();
+        transaction.put_value(b"provider_collateral", &collateral)?;
+        for id in &ids {
+            if !matches!(jobs[id].status, JobStatus::Assigned { .. }) {
+                let job = jobs.remove(id).expect("ids come from the map");
+                transaction.put_value(&finished_key(id), &job)?;
+            }
+        }
+        transaction.put_value(b"inference_jobs", &jobs)?;
+        self.storage.write(transaction).await?;
+
+        Ok(events)
+    }
+
+    pub async fn get_job(&self, id: &Hash) -> Result<Option<InferenceJob>, JobError> {
+        match self.get_jobs().await?.remove(id) {
+            Some(job) => Ok(Some(job)),
+            None => self.get_finished(id).await,
+        }
+    }
+
+    async fn get_finished(&self, id: &Hash) -> Result<Option<InferenceJob>, JobError> {
+        Ok(self.storage.get_value(&finished_key(id)).await?)
+    }
+
+    async fn get_jobs(&self) -> Result<HashMap<Hash, InferenceJob>, JobError> {
+        Ok(self.storage.get_value(b"inference_jobs").await?.unwrap_or_default())
+    }
+
+    async fn get_collateral(&self) -> Result<HashMap<Address, Balance>, JobError> {
+        Ok(self.storage.get_value(b"provider_collateral").await?.unwrap_or_default())
+    }
+}
+
+fn finished_key(id: &Hash) -> Vec<u8> {
+    [b"finished_jobs/".as_slice(), id.as_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    async fn setup(providers: &[Address]) -> JobScheduler {
+        let mut scheduler = JobScheduler::new(Arc::new(MemoryBackend::new()), JobConfig {
+            timeout_blocks: 10,
+            timeout_slash_bps: 1_000,
+            max_attempts: 2,
+        });
+        for provider in providers {
+            scheduler.deposit_collateral(*provider, Balance::from(1_000)).await.unwrap();
+        }
+        scheduler
+    }
+
+    #[tokio::test]
+    async fn test_result_before_deadline() {
+        let provider = Address::random();
+        let mut scheduler = setup(&[provider]).await;
+        let id = Hash::hash(b"job");
+
+        scheduler.assign(id.clone(), Address::random(), Address::random(), Balance::from(50), provider, BlockHeight::from(1)).await.unwrap();
+        scheduler.submit_result(&id, provider, Hash::hash(b"out"), BlockHeight::from(5)).await.unwrap();
+
+        let events = scheduler.process_timeouts(BlockHeight::from(20), |_| None).await.unwrap();
+        assert!(events.is_empty());
+        assert!(matches!(scheduler.get_job(&id).await.unwrap().unwrap().status, JobStatus::Completed { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_slash_reassign_and_refund() {
+        let (first, second) = (Address::random(), Address::random());
+        let requester = Address::random();
+        let mut scheduler = setup(&[first, second]).await;
+        let id = Hash::hash(b"job");
+
+        scheduler.assign(id.clone(), requester, Address::random(), Balance::from(50), first, BlockHeight::from(1)).await.unwrap();
+        assert!(scheduler.process_timeouts(BlockHeight::from(10), |_| Some(second)).await.unwrap().is_empty());
+
+        let events = scheduler.process_timeouts(BlockHeight::from(11), |_| Some(second)).await.unwrap();
+        assert_eq!(events[0], TimeoutEvent::Slashed { job: id.clone(), provider: first, amount: Balance::from(100) });
+        assert!(matches!(events[1], TimeoutEvent::Reassigned { provider, .. } if provider == second));
+        assert_eq!(scheduler.collateral_of(first).await.unwrap(), Balance::from(900));
+
+        // The late provider can no longer settle
+        assert!(scheduler.submit_result(&id, first, Hash::hash(b"out"), BlockHeight::from(12)).await.is_err());
+
+        let events = scheduler.process_timeouts(BlockHeight::from(21), |_| Some(first)).await.unwrap();
+        assert_eq!(events[1], TimeoutEvent::Refunded { job: id.clone(), requester, amount: Balance::from(50) });
+        assert_eq!(scheduler.get_job(&id).await.unwrap().unwrap().status, JobStatus::Expired);
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_are_processed_in_job_order() {
+        let provider = Address::random();
+        let mut scheduler = setup(&[provider]).await;
+        let mut ids: Vec<Hash> = (0u8..8).map(|i| Hash::hash(&[i])).collect();
+        for id in &ids {
+            scheduler.assign(id.clone(), Address::random(), Address::random(), Balance::from(1), provider, BlockHeight::from(1)).await.unwrap();
+        }
+
+        let events = scheduler.process_timeouts(BlockHeight::from(11), |_| None).await.unwrap();
+        let slashed: Vec<Hash> = events
+            .iter()
+            .filter_map(|event| match event {
+                TimeoutEvent::Slashed { job, .. } => Some(job.clone()),
+                _ => None,
+            })
+            .collect();
+        ids.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        assert_eq!(slashed, ids);
+    }
+
+    #[tokio::test]
+    async fn test_replacement_needs_collateral_and_finished_jobs_leave_the_active_set() {
+        let provider = Address::random();
+        let mut scheduler = setup(&[provider]).await;
+        let (missed, done) = (Hash::hash(b"missed"), Hash::hash(b"done"));
+        for id in [&missed, &done] {
+            scheduler.assign(id.clone(), Address::random(), Address::random(), Balance::from(1), provider, BlockHeight::from(1)).await.unwrap();
+        }
+        scheduler.submit_result(&done, provider, Hash::hash(b"out"), BlockHeight::from(2)).await.unwrap();
+
+        let events = scheduler.process_timeouts(BlockHeight::from(11), |_| Some(Address::random())).await.unwrap();
+        assert!(matches!(events[1], TimeoutEvent::Refunded { .. }));
+        assert!(scheduler.get_jobs().await.unwrap().is_empty());
+        assert_eq!(scheduler.get_job(&missed).await.unwrap().unwrap().status, JobStatus::Expired);
+        assert!(matches!(scheduler.get_job(&done).await.unwrap().unwrap().status, JobStatus::Completed { .. }));
+        let again = scheduler.assign(done, Address::random(), Address::random(), Balance::from(1), provider, BlockHeight::from(12)).await;
+        assert!(matches!(again, Err(JobError::JobExists)));
+    }
+}
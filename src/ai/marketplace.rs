@@ -0,0 +1,265 @@
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::storage::db::{Database, DatabaseError};
+use crate::storage::transaction::StorageTransaction;
+use crate::types::{Address, Balance, BlockHeight};
+
+const MAX_ROYALTY_BPS: u32 = 5_000;
+const MAX_PRICE_HISTORY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ListingStatus {
+    Active,
+    Paused,
+    Delisted,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PricePoint {
+    pub height: BlockHeight,
+    pub price: Balance,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoyaltySettings {
+    pub recipient: Address,
+    pub bps: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InvocationStats {
+    pub total_invocations: u64,
+    pub total_fees: Balance,
+    pub last_invoked_at: Option<BlockHeight>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Listing {
+    pub model: Address,
+    pub owner: Address,
+    pub status: ListingStatus,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub price: Balance,
+    pub price_history: Vec<PricePoint>,
+    pub royalty: Option<RoyaltySettings>,
+    pub stats: InvocationStats,
+}
+
+#[derive(Debug, Error)]
+pub enum MarketplaceError {
+    #[error("Model is already listed")]
+    ListingExists,
+    #[error("Listing not found")]
+    ListingNotFound,
+    #[error("Only the model owner can modify the listing")]
+    NotOwner,
+    #[error("Royalty of {0} bps exceeds the maximum")]
+    InvalidRoyalty(u32),
+    #[error("Database error: {0}")]
+    Database(#[from] DatabaseError),
+}
+
+pub type Result<T> = std::result::Result<T, MarketplaceError>;
+
+// Listings live under ("listing", model); secondary indexes map
+// ("owner", owner, model) and ("tag", tag, model) back to the model address
+// so queries are a single prefix scan.
+pub struct Marketplace {
+    db: Arc<Database>,
+}
+
+impl Marketplace {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+
+    pub async fn create_listing(&self, listing: Listing, height: BlockHeight) -> Result<()> {
+        if self.get_listing(listing.model).await?.is_some() {
+            return Err(MarketplaceError::ListingExists);
+        }
+        if let Some(royalty) = &listing.royalty {
+            if royalty.bps > MAX_ROYALTY_BPS {
+                return Err(MarketplaceError::InvalidRoyalty(royalty.bps));
+            }
+        }
+
+        let mut listing = listing;
+        listing.price_history = vec![PricePoint { height, price: listing.price }];
+        listing.stats = InvocationStats::default();
+
+        // The listing and its index entries land together, so a query never
+        // finds a model that isn't listed or misses one that is
+        let mut transaction = StorageTransaction::new();
+        transaction.put_default(&("owner", listing.owner, listing.model), &listing.model)?;
+        for tag in &listing.tags {
+            transaction.put_default(&("tag", tag.as_str(), listing.model), &listing.model)?;
+        }
+        transaction.put_default(&("listing", listing.model), &listing)?;
+        self.db.commit(transaction).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_listing(&self, model: Address) -> Result<Option<Listing>> {
+        Ok(self.db.get(&("listing", model)).await?)
+    }
+
+    pub async fn update_price(&self, caller: Address, model: Address, price: Balance, height: BlockHeight) -> Result<()> {
+        let mut listing = self.owned_listing(caller, model).await?;
+        listing.price = price;
+        listing.price_history.push(PricePoint { height, price });
+        if listing.price_history.len() > MAX_PRICE_HISTORY {
+            listing.price_history.remove(0);
+        }
+        self.db.put(&("listing", model), &listing).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_status(&self, caller: Address, model: Address, status: ListingStatus) -> Result<()> {
+        let mut listing = self.owned_listing(caller, model).await?;
+        listing.status = status;
+        self.db.put(&("listing", model), &listing).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_tags(&self, caller: Address, model: Address, tags: Vec<String>) -> Result<()> {
+        let mut listing = self.owned_listing(caller, model).await?;
+        let mut transaction = StorageTransaction::new();
+        for tag in &listing.tags {
+            transaction.delete_default(&("tag", tag.as_str(), model))?;
+        }
+        for tag in &tags {
+            transaction.put_default(&("tag", tag.as_str(), model), &model)?;
+        }
+        listing.tags = tags;
+        transaction.put_default(&("listing", model), &listing)?;
+        self.db.commit(transaction).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_royalty(&self, caller: Address, model: Address, royalty: Option<RoyaltySettings>) -> Result<()> {
+        if let Some(royalty) = &royalty {
+            if royalty.bps > MAX_ROYALTY_BPS {
+                return Err(MarketplaceError::InvalidRoyalty(royalty.bps));
+            }
+        }
+
+        let mut listing = self.owned_listing(caller, model).await?;
+        listing.royalty = royalty;
+        self.db.put(&("listing", model), &listing).await?;
+
+        Ok(())
+    }
+
+    // Called when an invocation of the model settles
+    pub async fn record_invocation(&self, model: Address, fee: Balance, height: BlockHeight) -> Result<()> {
+        let mut listing = self.get_listing(model).await?.ok_or(MarketplaceError::ListingNotFound)?;
+        listing.stats.total_invocations += 1;
+        listing.stats.total_fees += fee;
+        listing.stats.last_invoked_at = Some(height);
+        self.db.put(&("listing", model), &listing).await?;
+
+        Ok(())
+    }
+
+    pub async fn list_models_by_owner(&self, owner: Address) -> Result<Vec<Listing>> {
+        let index = self.db.prefix_stream::<(String, Address), Address>(None, &("owner".to_string(), owner))?;
+        self.resolve(index).await
+    }
+
+    pub async fn list_models_by_tag(&self, tag: &str) -> Result<Vec<Listing>> {
+        let index = self.db.prefix_stream::<(String, String), Address>(None, &("tag".to_string(), tag.to_string()))?;
+        self.resolve(index).await
+    }
+
+    // Resolves index entries one at a time as the scan streams them in
+    async fn resolve<K>(&self, index: impl Stream<Item = std::result::Result<(K, Address), DatabaseError>>) -> Result<Vec<Listing>> {
+        futures::pin_mut!(index);
+        let mut listings = Vec::new();
+        while let Some(entry) = index.next().await {
+            let (_, model) = entry?;
+            if let Some(listing) = self.get_listing(model).await? {
+                listings.push(listing);
+            }
+        }
+        Ok(listings)
+    }
+
+    async fn owned_listing(&self, caller: Address, model: Address) -> Result<Listing> {
+        let listing = self.get_listing(model).await?.ok_or(MarketplaceError::ListingNotFound)?;
+        if listing.owner != caller {
+            return Err(MarketplaceError::NotOwner);
+        }
+        Ok(listing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn listing(owner: Address, tags: &[&str]) -> Listing {
+        Listing {
+            model: Address::random(),
+            owner,
+            status: ListingStatus::Active,
+            category: "vision".to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            price: Balance::from(10),
+            price_history: Vec::new(),
+            royalty: None,
+            stats: InvocationStats::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_listing_queries() {
+        let temp_dir = TempDir::new().unwrap();
+        let marketplace = Marketplace::new(Arc::new(Database::new(temp_dir.path()).unwrap()));
+        let owner = Address::random();
+
+        let first = listing(owner, &["llm", "chat"]);
+        let second = listing(owner, &["llm"]);
+        let other = listing(Address::random(), &["diffusion"]);
+        for entry in [&first, &second, &other] {
+            marketplace.create_listing(entry.clone(), BlockHeight::from(1)).await.unwrap();
+        }
+
+        assert_eq!(marketplace.list_models_by_owner(owner).await.unwrap().len(), 2);
+        assert_eq!(marketplace.list_models_by_tag("llm").await.unwrap().len(), 2);
+        assert_eq!(marketplace.list_models_by_tag("chat").await.unwrap()[0].model, first.model);
+
+        marketplace.set_tags(owner, first.model, vec!["vision".to_string()]).await.unwrap();
+        assert!(marketplace.list_models_by_tag("chat").await.unwrap().is_empty());
+        assert_eq!(marketplace.list_models_by_tag("vision").await.unwrap()[0].model, first.model);
+    }
+
+    #[tokio::test]
+    async fn test_price_history_and_stats() {
+        let temp_dir = TempDir::new().unwrap();
+        let marketplace = Marketplace::new(Arc::new(Database::new(temp_dir.path()).unwrap()));
+        let owner = Address::random();
+        let entry = listing(owner, &[]);
+        marketplace.create_listing(entry.clone(), BlockHeight::from(1)).await.unwrap();
+
+        marketplace.update_price(owner, entry.model, Balance::from(12), BlockHeight::from(5)).await.unwrap();
+        assert!(matches!(
+            marketplace.update_price(Address::random(), entry.model, Balance::from(1), BlockHeight::from(6)).await,
+            Err(MarketplaceError::NotOwner)
+        ));
+
+        marketplace.record_invocation(entry.model, Balance::from(12), BlockHeight::from(7)).await.unwrap();
+        let stored = marketplace.get_listing(entry.model).await.unwrap().unwrap();
+        assert_eq!(stored.price_history.len(), 2);
+        assert_eq!(stored.stats.total_invocations, 1);
+        assert_eq!(stored.stats.last_invoked_at, Some(BlockHeight::from(7)));
+    }
+}
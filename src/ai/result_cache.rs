@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::crypto::hash::Hash;
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::types::{Address, Balance, BlockHeight};
+use crate::utils::decimal::Decimal;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheConfig {
+    // Blocks a verified result stays eligible for reuse
+    pub retention_blocks: u64,
+    // Fee charged for a cache hit, as a share of the full invocation fee in basis points
+    pub cached_fee_bps: u32,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            retention_blocks: 10_000,
+            cached_fee_bps: 1_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedResult {
+    pub output_hash: Hash,
+    // Job whose verified result populated the entry
+    pub source_job: Hash,
+    pub verified_at: BlockHeight,
+    pub hits: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum ResultCacheError {
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// Only deterministic models may be cached: the same (model, version, input) must
+// always produce the same output for a hit to be equivalent to re-execution.
+pub struct ResultCache {
+    storage: Arc<dyn StorageBackend>,
+    config: CacheConfig,
+}
+
+impl ResultCache {
+    pub fn new(storage: Arc<dyn StorageBackend>, config: CacheConfig) -> Self {
+        Self { storage, config }
+    }
+
+    pub fn request_hash(model: Address, version: u32, input_hash: &Hash) -> Hash {
+        let bytes = bincode::serialize(&(model, version, input_hash)).expect("tuple serialization cannot fail");
+        Hash::hash(&bytes)
+    }
+
+    pub async fn insert(&mut self, request: Hash, output_hash: Hash, source_job: Hash, height: BlockHeight) -> Result<(), ResultCacheError> {
+        let mut entries = self.get_entries().await?;
+        // The first verified result wins; later identical requests settle against it
+        entries.entry(request).or_insert(CachedResult {
+            output_hash,
+            source_job,
+            verified_at: height,
+            hits: 0,
+        });
+        self.storage.put_value(b"result_cache", &entries).await?;
+
+        Ok(())
+    }
+
+    pub async fn lookup(&self, request: &Hash, height: BlockHeight) -> Result<Option<CachedResult>, ResultCacheError> {
+        let entries = self.get_entries().await?;
+        Ok(entries
+            .get(request)
+            .filter(|entry| !self.is_expired(entry, height))
+            .cloned())
+    }
+
+    // Settles a request against the cache, returning the result and the reduced fee on a hit
+    pub async fn settle(&mut self, request: &Hash, full_fee: Balance, height: BlockHeight) -> Result<Option<(CachedResult, Balance)>, ResultCacheError> {
+        let mut entries = self.get_entries().await?;
+        let entry = match entries.get_mut(request) {
+            Some(entry) if !self.is_expired(entry, height) => entry,
+            _ => return Ok(None),
+        };
+
+        entry.hits += 1;
+        let result = entry.clone();
+        self.storage.put_value(b"result_cache", &entries).await?;
+
+        Ok(Some((result, self.cached_fee(full_fee))))
+    }
+
+    pub fn cached_fee(&self, full_fee: Balance) -> Balance {
+        Decimal::from_bps(self.config.cached_fee_bps).share_of(full_fee)
+    }
+
+    // Drops entries past retention; returns how many were removed
+    pub async fn prune(&mut self, height: BlockHeight) -> Result<usize, ResultCacheError> {
+        let mut entries = self.get_entries().await?;
+        let before = entries.len();
+        entries.retain(|_, entry| !self.is_expired(entry, height));
+        let removed = before - entries.len();
+        if removed > 0 {
+            self.storage.put_value(b"result_cache", &entries).await?;
+        }
+
+        Ok(removed)
+    }
+
+    fn is_expired(&self, entry: &CachedResult, height: BlockHeight) -> bool {
+        height >= entry.verified_at + self.config.retention_blocks
+    }
+
+    async fn get_entries(&self) -> Result<HashMap<Hash, CachedResult>, ResultCacheError> {
+        Ok(self.storage.get_value(b"result_cache").await?.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    fn cache() -> ResultCache {
+        ResultCache::new(Arc::new(MemoryBackend::new()), CacheConfig {
+            retention_blocks: 100,
+            cached_fee_bps: 2_500,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_identical_request_settles_from_cache() {
+        let mut cache = cache();
+        let model = Address::random();
+        let request = ResultCache::request_hash(model, 1, &Hash::hash(b"input"));
+
+        assert!(cache.settle(&request, Balance::from(100), BlockHeight::from(1)).await.unwrap().is_none());
+        cache.insert(request.clone(), Hash::hash(b"output"), Hash::hash(b"job"), BlockHeight::from(1)).await.unwrap();
+
+        let (result, fee) = cache.settle(&request, Balance::from(100), BlockHeight::from(2)).await.unwrap().unwrap();
+        assert_eq!(result.output_hash, Hash::hash(b"output"));
+        assert_eq!(fee, Balance::from(25));
+        assert_eq!(cache.lookup(&request, BlockHeight::from(3)).await.unwrap().unwrap().hits, 1);
+
+        // A new model version is a different request
+        let upgraded = ResultCache::request_hash(model, 2, &Hash::hash(b"input"));
+        assert!(cache.lookup(&upgraded, BlockHeight::from(3)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retention() {
+        let mut cache = cache();
+        let request = Hash::hash(b"request");
+        cache.insert(request.clone(), Hash::hash(b"output"), Hash::hash(b"job"), BlockHeight::from(10)).await.unwrap();
+
+        assert!(cache.lookup(&request, BlockHeight::from(109)).await.unwrap().is_some());
+        assert!(cache.lookup(&request, BlockHeight::from(110)).await.unwrap().is_none());
+        assert_eq!(cache.prune(BlockHeight::from(110)).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use thiserror::Error;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, Mutex, Semaphore};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::ai::jobs::TimeoutEvent;
+use crate::crypto::hash::Hash;
+use crate::crypto::scheme::{SchemeError, SchemeSignature, SignatureScheme};
+use crate::network::codec::decode_bounded;
+use crate::types::Address;
+
+// Prefixed to what providers sign for each chunk and requesters for each subscription
+pub const CHUNK_DOMAIN: &[u8] = b"omnitensor/output-chunk/v1";
+pub const SUBSCRIBE_DOMAIN: &[u8] = b"omnitensor/stream-subscribe/v1";
+
+const STREAM_BUFFER: usize = 256;
+// A subscription request is one job hash and a signature
+const MAX_MESSAGE_BYTES: usize = 4096;
+// Subscription requests expiring further out are refused, so a leaked one
+// can't be replayed for long
+const MAX_SUBSCRIPTION_TTL_SECS: u64 = 300;
+
+// `[ai.streaming]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamConfig {
+    pub listen: SocketAddr,
+    // Further connections are refused
+    pub max_connections: usize,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self { listen: SocketAddr::from(([127, 0, 0, 1], 8548)), max_connections: 100 }
+    }
+}
+
+// Partial output of a job, produced by the provider it is assigned to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputChunk {
+    pub job: Hash,
+    pub sequence: u64,
+    pub data: Vec<u8>,
+    pub is_final: bool,
+}
+
+impl OutputChunk {
+    fn signing_bytes(&self) -> Vec<u8> {
+        [CHUNK_DOMAIN, self.job.as_bytes(), &self.sequence.to_le_bytes(), &[self.is_final as u8], &self.data].concat()
+    }
+}
+
+// What providers publish on the inference output topic, see
+// `P2PNetwork::publish_output_chunk`.
+// Ed25519 signatures don't name their signer, so they carry the public key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedOutputChunk {
+    pub chunk: OutputChunk,
+    pub public_key: Option<Vec<u8>>,
+    pub signature: SchemeSignature,
+}
+
+impl SignedOutputChunk {
+    pub fn sign(chunk: OutputChunk, scheme: SignatureScheme, secret_key: &[u8]) -> Result<Self, SchemeError> {
+        let signature = SchemeSignature::sign(scheme, &chunk.signing_bytes(), secret_key)?;
+        let public_key = ed25519_public_key(scheme, secret_key)?;
+        Ok(Self { chunk, public_key, signature })
+    }
+
+    pub fn signer(&self) -> Option<Address> {
+        self.signature.signer(&self.chunk.signing_bytes(), self.public_key.as_deref()).map(Address::from)
+    }
+}
+
+// The first text frame of a stream connection, as JSON: the job to follow,
+// signed by the account that requested it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscribeRequest {
+    pub job: Hash,
+    // Unix seconds
+    pub expires_at: u64,
+    pub public_key: Option<Vec<u8>>,
+    pub signature: SchemeSignature,
+}
+
+impl SubscribeRequest {
+    pub fn sign(job: Hash, expires_at: u64, scheme: SignatureScheme, secret_key: &[u8]) -> Result<Self, SchemeError> {
+        let signature = SchemeSignature::sign(scheme, &subscribe_message(&job, expires_at), secret_key)?;
+        let public_key = ed25519_public_key(scheme, secret_key)?;
+        Ok(Self { job, expires_at, public_key, signature })
+    }
+
+    fn signer(&self) -> Option<Address> {
+        let message = subscribe_message(&self.job, self.expires_at);
+        self.signature.signer(&message, self.public_key.as_deref()).map(Address::from)
+    }
+}
+
+fn subscribe_message(job: &Hash, expires_at: u64) -> Vec<u8> {
+    [SUBSCRIBE_DOMAIN, job.as_bytes(), &expires_at.to_le_bytes()].concat()
+}
+
+fn ed25519_public_key(scheme: SignatureScheme, secret_key: &[u8]) -> Result<Option<Vec<u8>>, SchemeError> {
+    match scheme {
+        SignatureScheme::Ed25519 => scheme.public_key(secret_key).map(Some),
+        SignatureScheme::Secp256k1 => Ok(None),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum StreamError {
+    #[error("Out of order chunk: expected {expected}, got {got}")]
+    OutOfOrder { expected: u64, got: u64 },
+    #[error("Job {0:?} is not running")]
+    NotRunning(Hash),
+    #[error("Chunk for job {0:?} is not signed by its provider")]
+    NotProvider(Hash),
+    #[error("Subscription to job {0:?} is not signed by its requester")]
+    NotRequester(Hash),
+    #[error("Subscription request expiring at {0} is outside the accepted window")]
+    Expired(u64),
+    #[error("Malformed chunk: {0}")]
+    Malformed(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+struct JobStream {
+    requester: Address,
+    provider: Address,
+    sender: broadcast::Sender<OutputChunk>,
+    next_sequence: u64,
+    hasher: Sha3_256,
+}
+
+impl JobStream {
+    fn new(requester: Address, provider: Address) -> Self {
+        let (sender, _) = broadcast::channel(STREAM_BUFFER);
+        Self {
+            requester,
+            provider,
+            sender,
+            next_sequence: 0,
+            hasher: Sha3_256::new(),
+        }
+    }
+}
+
+// Relays chunks from compute nodes to WebSocket subscribers. Only the final output
+// hash leaves the relay for settlement; partial outputs are never committed on-chain.
+// A job has a stream from its assignment until its final chunk, or until it
+// expires; chunks and subscriptions for any other job are refused. Only its
+// provider can feed a stream and only its requester can follow it.
+#[derive(Clone, Default)]
+pub struct StreamRelay {
+    streams: Arc<Mutex<HashMap<Hash, JobStream>>>,
+}
+
+impl StreamRelay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Called when a job is assigned to a provider; a reassigned job starts over
+    pub async fn open(&self, job: Hash, requester: Address, provider: Address) {
+        self.streams.lock().await.insert(job, JobStream::new(requester, provider));
+    }
+
+    // Drops the job's stream, which ends its subscriptions
+    pub async fn close(&self, job: &Hash) {
+        self.streams.lock().await.remove(job);
+    }
+
+    // Follows `JobScheduler::process_timeouts`: reassigned jobs restart from
+    // the first chunk, refunded ones are over
+    pub async fn apply_timeouts(&self, events: &[TimeoutEvent]) {
+        for event in events {
+            match event {
+                TimeoutEvent::Reassigned { job, requester, provider, .. } => {
+                    self.open(job.clone(), *requester, *provider).await
+                }
+                TimeoutEvent::Refunded { job, .. } => self.close(job).await,
+                TimeoutEvent::Slashed { .. } => {}
+            }
+        }
+    }
+
+    pub async fn subscribe(&self, request: &SubscribeRequest) -> Result<broadcast::Receiver<OutputChunk>, StreamError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        if request.expires_at < now || request.expires_at > now + MAX_SUBSCRIPTION_TTL_SECS {
+            return Err(StreamError::Expired(request.expires_at));
+        }
+        let streams = self.streams.lock().await;
+        let stream = streams.get(&request.job).ok_or_else(|| StreamError::NotRunning(request.job.clone()))?;
+        if request.signer() != Some(stream.requester) {
+            return Err(StreamError::NotRequester(request.job.clone()));
+        }
+        Ok(stream.sender.subscribe())
+    }
+
+    // Chunks as received on the inference output topic
+    pub async fn ingest_gossip(&self, data: &[u8]) -> Result<Option<Hash>, StreamError> {
+        let signed: SignedOutputChunk = decode_bounded(data).map_err(|e| StreamError::Malformed(e.to_string()))?;
+        self.ingest(signed).await
+    }
+
+    // Returns the output hash to commit once the final chunk arrives
+    pub async fn ingest(&self, signed: SignedOutputChunk) -> Result<Option<Hash>, StreamError> {
+        let signer = signed.signer();
+        let chunk = signed.chunk;
+        let mut streams = self.streams.lock().await;
+        let stream = streams.get_mut(&chunk.job).ok_or_else(|| StreamError::NotRunning(chunk.job.clone()))?;
+        if signer != Some(stream.provider) {
+            return Err(StreamError::NotProvider(chunk.job));
+        }
+
+        if chunk.sequence != stream.next_sequence {
+            return Err(StreamError::OutOfOrder {
+                expected: stream.next_sequence,
+                got: chunk.sequence,
+            });
+        }
+
+        stream.next_sequence += 1;
+        stream.hasher.update(&chunk.data);
+        let is_final = chunk.is_final;
+        // No subscribers is fine, the requester may only care about the settled hash
+        let _ = stream.sender.send(chunk.clone());
+
+        if !is_final {
+            return Ok(None);
+        }
+
+        let output_hash = Hash::from(stream.hasher.clone().finalize().as_slice());
+        // Dropping the stream closes subscriber channels after the final chunk is delivered
+        streams.remove(&chunk.job);
+
+        Ok(Some(output_hash))
+    }
+
+    pub async fn active_streams(&self) -> usize {
+        self.streams.lock().await.len()
+    }
+}
+
+// Hash committed at settlement: SHA3-256 over the concatenated chunk payloads
+pub fn stream_output_hash(chunks: &[OutputChunk]) -> Hash {
+    let mut hasher = Sha3_256::new();
+    for chunk in chunks {
+        hasher.update(&chunk.data);
+    }
+    Hash::from(hasher.finalize().as_slice())
+}
+
+// Connections need no credential; each one follows a single job and only
+// with a `SubscribeRequest` its requester signed
+pub async fn serve_websocket(config: &StreamConfig, relay: StreamRelay) -> Result<(), StreamError> {
+    let listener = TcpListener::bind(config.listen).await?;
+    info!("Inference stream server listening on {}", config.listen);
+    let connections = Arc::new(Semaphore::new(config.max_connections));
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let permit = match connections.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                debug!("Refusing stream connection from {}, at the limit", peer);
+                continue;
+            }
+        };
+        let relay = relay.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, relay).await {
+                warn!("Stream connection from {} closed with error: {}", peer, e);
+            }
+            drop(permit);
+        });
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    relay: StreamRelay,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config = WebSocketConfig { max_message_size: Some(MAX_MESSAGE_BYTES), ..WebSocketConfig::default() };
+    let mut ws = tokio_tungstenite::accept_async_with_config(stream, Some(config)).await?;
+
+    // The first text frame selects the job to follow
+    let request: SubscribeRequest = loop {
+        match ws.next().await {
+            Some(Ok(Message::Text(text))) => break serde_json::from_str(&text)?,
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+            None => return Ok(()),
+        }
+    };
+
+    let mut receiver = relay.subscribe(&request).await?;
+    loop {
+        match receiver.recv().await {
+            Ok(chunk) => {
+                let is_final = chunk.is_final;
+                ws.send(Message::Text(serde_json::to_string(&chunk)?)).await?;
+                if is_final {
+                    break;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("Stream subscriber lagged, skipped {} chunks", skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+
+    ws.close(None).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROVIDER_KEY: [u8; 32] = [1; 32];
+    const REQUESTER_KEY: [u8; 32] = [2; 32];
+
+    fn address(secret_key: &[u8]) -> Address {
+        let public_key = SignatureScheme::Ed25519.public_key(secret_key).unwrap();
+        Address::from(SignatureScheme::Ed25519.address(&public_key).unwrap())
+    }
+
+    fn chunk(job: &Hash, sequence: u64, data: &[u8], is_final: bool) -> OutputChunk {
+        OutputChunk {
+            job: job.clone(),
+            sequence,
+            data: data.to_vec(),
+            is_final,
+        }
+    }
+
+    fn signed(chunk: &OutputChunk, secret_key: &[u8]) -> SignedOutputChunk {
+        SignedOutputChunk::sign(chunk.clone(), SignatureScheme::Ed25519, secret_key).unwrap()
+    }
+
+    fn subscription(job: &Hash, secret_key: &[u8]) -> SubscribeRequest {
+        let expires_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 60;
+        SubscribeRequest::sign(job.clone(), expires_at, SignatureScheme::Ed25519, secret_key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_relay_forwards_and_hashes() {
+        let relay = StreamRelay::new();
+        let job = Hash::hash(b"job");
+        assert!(matches!(relay.subscribe(&subscription(&job, &REQUESTER_KEY)).await, Err(StreamError::NotRunning(_))));
+        let first = chunk(&job, 0, b"a", false);
+        assert!(matches!(relay.ingest(signed(&first, &PROVIDER_KEY)).await, Err(StreamError::NotRunning(_))));
+        relay.open(job.clone(), address(&REQUESTER_KEY), address(&PROVIDER_KEY)).await;
+        let mut receiver = relay.subscribe(&subscription(&job, &REQUESTER_KEY)).await.unwrap();
+
+        let chunks = vec![chunk(&job, 0, b"Hello", false), chunk(&job, 1, b", world", true)];
+        assert_eq!(relay.ingest(signed(&chunks[0], &PROVIDER_KEY)).await.unwrap(), None);
+        // Gossiped chunks take the same path
+        let gossiped = bincode::serialize(&signed(&chunks[1], &PROVIDER_KEY)).unwrap();
+        let output_hash = relay.ingest_gossip(&gossiped).await.unwrap().unwrap();
+
+        assert_eq!(output_hash, stream_output_hash(&chunks));
+        assert_eq!(receiver.recv().await.unwrap(), chunks[0]);
+        assert_eq!(receiver.recv().await.unwrap(), chunks[1]);
+        assert_eq!(relay.active_streams().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_relay_only_trusts_the_provider_and_the_requester() {
+        let relay = StreamRelay::new();
+        let job = Hash::hash(b"job");
+        relay.open(job.clone(), address(&REQUESTER_KEY), address(&PROVIDER_KEY)).await;
+
+        let first = chunk(&job, 0, b"a", false);
+        assert!(matches!(relay.ingest(signed(&first, &REQUESTER_KEY)).await, Err(StreamError::NotProvider(_))));
+        let mut forged = signed(&first, &PROVIDER_KEY);
+        forged.chunk.data = b"b".to_vec();
+        assert!(matches!(relay.ingest(forged).await, Err(StreamError::NotProvider(_))));
+        assert!(matches!(relay.ingest_gossip(b"garbage").await, Err(StreamError::Malformed(_))));
+
+        assert!(matches!(
+            relay.subscribe(&subscription(&job, &PROVIDER_KEY)).await,
+            Err(StreamError::NotRequester(_))
+        ));
+        let mut expired = subscription(&job, &REQUESTER_KEY);
+        expired.expires_at = 1;
+        assert!(matches!(relay.subscribe(&expired).await, Err(StreamError::Expired(1))));
+        let mut replayed = subscription(&job, &REQUESTER_KEY);
+        replayed.job = Hash::hash(b"other");
+        relay.open(replayed.job.clone(), address(&REQUESTER_KEY), address(&PROVIDER_KEY)).await;
+        assert!(matches!(relay.subscribe(&replayed).await, Err(StreamError::NotRequester(_))));
+    }
+
+    #[tokio::test]
+    async fn test_relay_rejects_out_of_order() {
+        let relay = StreamRelay::new();
+        let job = Hash::hash(b"job");
+        let requester = address(&REQUESTER_KEY);
+        relay.open(job.clone(), requester, address(&PROVIDER_KEY)).await;
+
+        relay.ingest(signed(&chunk(&job, 0, b"a", false), &PROVIDER_KEY)).await.unwrap();
+        assert!(matches!(
+            relay.ingest(signed(&chunk(&job, 2, b"c", false), &PROVIDER_KEY)).await,
+            Err(StreamError::OutOfOrder { expected: 1, got: 2 })
+        ));
+
+        // A new provider starts over; an expired job's subscribers are let go
+        let replacement_key = [3; 32];
+        let provider = address(&replacement_key);
+        let deadline = crate::types::BlockHeight::from(20);
+        relay.apply_timeouts(&[TimeoutEvent::Reassigned { job: job.clone(), requester, provider, deadline }]).await;
+        let first = chunk(&job, 0, b"a", false);
+        assert!(matches!(relay.ingest(signed(&first, &PROVIDER_KEY)).await, Err(StreamError::NotProvider(_))));
+        relay.ingest(signed(&first, &replacement_key)).await.unwrap();
+        let mut receiver = relay.subscribe(&subscription(&job, &REQUESTER_KEY)).await.unwrap();
+        let refund = TimeoutEvent::Refunded { job: job.clone(), requester, amount: 1 };
+        relay.apply_timeouts(&[refund]).await;
+        assert!(matches!(receiver.recv().await, Err(broadcast::error::RecvError::Closed)));
+        assert_eq!(relay.active_streams().await, 0);
+    }
+}
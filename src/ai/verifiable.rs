@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::ai::access::{AccessError, ModelAccessManager};
+use crate::chain::verifying_keys::{verify_gas, KeyId, ProofSubmission, VerifyingKeyError, VerifyingKeyRegistry};
+use crate::crypto::hash::Hash;
+use crate::crypto::zk::ZkError;
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::types::Address;
+
+// The input and output hashes, each as its low and high 16 bytes so every
+// public input is a canonical scalar
+pub const INFERENCE_PUBLIC_INPUTS: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InferenceProof {
+    pub model: Address,
+    pub input_hash: Hash,
+    pub output_hash: Hash,
+    pub proof: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Settlement {
+    // The proof checked out, the result can be settled without re-execution
+    Verified,
+    // The model has no verifying key registered, fall back to redundant execution
+    RequiresExecution,
+}
+
+#[derive(Debug, Error)]
+pub enum VerifiableInferenceError {
+    #[error("Verifying key already registered for model")]
+    KeyAlreadyRegistered,
+    #[error("Model not found")]
+    ModelNotFound,
+    #[error("Only the model owner can set its verifying key")]
+    NotOwner,
+    #[error("Inference proof rejected")]
+    InvalidProof,
+    #[error("Out of gas: required {required}, available {available}")]
+    OutOfGas { required: u64, available: u64 },
+    #[error("Malformed precompile input")]
+    MalformedInput,
+    #[error("ZK error: {0}")]
+    Zk(#[from] ZkError),
+    #[error("Verifying key error: {0}")]
+    VerifyingKey(#[from] VerifyingKeyError),
+    #[error("Access error: {0}")]
+    Access(#[from] AccessError),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// Lets models settle results with a Groth16 proof instead of re-execution.
+// The key lives in the chain's `VerifyingKeyRegistry`; a model only points
+// at one.
+pub struct InferenceVerifier {
+    storage: Arc<dyn StorageBackend>,
+    keys: VerifyingKeyRegistry,
+    models: ModelAccessManager,
+}
+
+impl InferenceVerifier {
+    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
+        Self {
+            keys: VerifyingKeyRegistry::new(storage.clone()),
+            models: ModelAccessManager::new(storage.clone()),
+            storage,
+        }
+    }
+
+    // Only the model's owner may opt it in, with a key already registered
+    // that takes the inference public inputs
+    pub async fn register_verifying_key(&mut self, caller: Address, model: Address, key: KeyId) -> Result<(), VerifiableInferenceError> {
+        let owner = self.models.owner_of(&model).await?.ok_or(VerifiableInferenceError::ModelNotFound)?;
+        if owner != caller {
+            return Err(VerifiableInferenceError::NotOwner);
+        }
+        let registered = self.keys.get(&key).await?.ok_or(VerifyingKeyError::NotFound)?;
+        if registered.public_inputs as usize != INFERENCE_PUBLIC_INPUTS {
+            return Err(ZkError::PublicInputCount {
+                expected: INFERENCE_PUBLIC_INPUTS,
+                actual: registered.public_inputs as usize,
+            }
+            .into());
+        }
+
+        let mut model_keys = self.get_model_keys().await?;
+        if model_keys.contains_key(&model) {
+            return Err(VerifiableInferenceError::KeyAlreadyRegistered);
+        }
+
+        model_keys.insert(model, key);
+        self.storage.put_value(b"inference_model_keys", &model_keys).await?;
+
+        Ok(())
+    }
+
+    pub async fn supports_proofs(&self, model: Address) -> Result<bool, VerifiableInferenceError> {
+        Ok(self.get_model_keys().await?.contains_key(&model))
+    }
+
+    pub async fn verify_inference(&self, proof: &InferenceProof) -> Result<Settlement, VerifiableInferenceError> {
+        let key = match self.get_model_keys().await?.remove(&proof.model) {
+            Some(key) => key,
+            None => return Ok(Settlement::RequiresExecution),
+        };
+
+        let submission = ProofSubmission {
+            key,
+            proof: proof.proof.clone(),
+            public_inputs: public_inputs(&proof.input_hash, &proof.output_hash),
+        };
+        match self.keys.verify(&submission).await {
+            Ok(()) => Ok(Settlement::Verified),
+            Err(VerifyingKeyError::InvalidProof) => Err(VerifiableInferenceError::InvalidProof),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Entry point used by transaction execution. Input is a bincode-encoded `InferenceProof`;
+    // output is a single byte (1 = verified, 0 = model requires execution) and the gas used,
+    // priced like a VerifyProof transaction.
+    pub async fn execute_precompile(&self, input: &[u8], gas_limit: u64) -> Result<(Vec<u8>, u64), VerifiableInferenceError> {
+        let gas = verify_gas(INFERENCE_PUBLIC_INPUTS);
+        if gas_limit < gas {
+            return Err(VerifiableInferenceError::OutOfGas {
+                required: gas,
+                available: gas_limit,
+            });
+        }
+
+        let proof: InferenceProof = bincode::deserialize(input).map_err(|_| VerifiableInferenceError::MalformedInput)?;
+        let output = match self.verify_inference(&proof).await? {
+            Settlement::Verified => vec![1],
+            Settlement::RequiresExecution => vec![0],
+        };
+
+        Ok((output, gas))
+    }
+
+    async fn get_model_keys(&self) -> Result<HashMap<Address, KeyId>, VerifiableInferenceError> {
+        Ok(self.storage.get_value(b"inference_model_keys").await?.unwrap_or_default())
+    }
+}
+
+// Splitting each hash keeps the encoding injective; reducing a 256-bit hash
+// modulo the field would let two hashes share a proof
+pub fn public_inputs(input_hash: &Hash, output_hash: &Hash) -> Vec<[u8; 32]> {
+    [input_hash, output_hash]
+        .into_iter()
+        .flat_map(|hash| hash.as_bytes().chunks(16))
+        .map(|limb| {
+            let mut scalar = [0u8; 32];
+            scalar[..limb.len()].copy_from_slice(limb);
+            scalar
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::access::AccessPolicy;
+    use crate::crypto::zk::test_circuit::InputsCircuit;
+    use crate::storage::backend::MemoryBackend;
+    use crate::types::BlockHeight;
+    use ark_bn254::{Bn254, Fr};
+    use ark_groth16::Groth16;
+    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+    use ark_snark::SNARK;
+    use ark_std::test_rng;
+
+    #[tokio::test]
+    async fn test_verify_inference_proof() {
+        let mut rng = test_rng();
+        let input_hash = Hash::hash(b"prompt");
+        let output_hash = Hash::hash(b"completion");
+        let inputs = public_inputs(&input_hash, &output_hash)
+            .iter()
+            .map(|scalar| Fr::deserialize_compressed(&scalar[..]).unwrap())
+            .collect();
+        let circuit = InputsCircuit { inputs };
+
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(circuit.clone(), &mut rng).unwrap();
+        let groth_proof = Groth16::<Bn254>::prove(&pk, circuit, &mut rng).unwrap();
+        let mut vk_bytes = Vec::new();
+        vk.serialize_compressed(&mut vk_bytes).unwrap();
+        let mut proof_bytes = Vec::new();
+        groth_proof.serialize_compressed(&mut proof_bytes).unwrap();
+
+        let storage: Arc<dyn StorageBackend> = Arc::new(MemoryBackend::new());
+        let mut verifier = InferenceVerifier::new(storage.clone());
+        let (model, owner) = (Address::random(), Address::random());
+        ModelAccessManager::new(storage.clone()).register_model(model, owner, AccessPolicy::Public).await.unwrap();
+        let key = VerifyingKeyRegistry::new(storage).register(owner, vk_bytes, BlockHeight::from(1)).await.unwrap();
+        let proof = InferenceProof { model, input_hash, output_hash, proof: proof_bytes };
+
+        // Without a key the caller has to fall back to execution
+        assert_eq!(verifier.verify_inference(&proof).await.unwrap(), Settlement::RequiresExecution);
+
+        assert!(matches!(
+            verifier.register_verifying_key(Address::random(), model, key).await,
+            Err(VerifiableInferenceError::NotOwner)
+        ));
+        assert!(matches!(
+            verifier.register_verifying_key(owner, Address::random(), key).await,
+            Err(VerifiableInferenceError::ModelNotFound)
+        ));
+        verifier.register_verifying_key(owner, model, key).await.unwrap();
+        assert_eq!(verifier.verify_inference(&proof).await.unwrap(), Settlement::Verified);
+
+        let forged = InferenceProof { output_hash: Hash::hash(b"forged"), ..proof.clone() };
+        assert!(matches!(verifier.verify_inference(&forged).await, Err(VerifiableInferenceError::InvalidProof)));
+
+        let gas = verify_gas(INFERENCE_PUBLIC_INPUTS);
+        let input = bincode::serialize(&proof).unwrap();
+        assert!(verifier.execute_precompile(&input, gas - 1).await.is_err());
+        assert_eq!(verifier.execute_precompile(&input, gas).await.unwrap(), (vec![1], gas));
+    }
+}
@@ -0,0 +1,251 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+use crate::chain::block::Block;
+use crate::chain::import::ImportPipeline;
+use crate::chain::store::{ChainStore, ChainStoreError};
+use crate::chain::transaction::TransactionReceipt;
+
+// Portable chain archive (`.otar`), version 1. All integers are big-endian.
+//
+//     file    := magic "OTAR" | version: u8 | record* | end
+//     record  := length: u32 | crc32(payload): u32 | payload
+//     end     := length 0 | crc32 0
+//     payload := bincode(ArchiveRecord)
+//
+// Records are consecutive heights. The end marker distinguishes a complete
+// archive from a truncated download. Archives carry blocks and receipts only;
+// state comes from state sync or re-execution.
+const MAGIC: &[u8; 4] = b"OTAR";
+const VERSION: u8 = 1;
+// Guards against allocating absurd buffers from a corrupt length field
+const MAX_RECORD_LEN: u32 = 256 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] bincode::Error),
+    #[error("Not a chain archive")]
+    BadMagic,
+    #[error("Unsupported archive version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Record {0} is larger than {MAX_RECORD_LEN} bytes")]
+    RecordTooLarge(u64),
+    #[error("Checksum mismatch in record {0}")]
+    ChecksumMismatch(u64),
+    #[error("Archive ends without an end marker after {0} records")]
+    Truncated(u64),
+    #[error("Expected height {expected}, archive has {found}")]
+    UnexpectedHeight { expected: u64, found: u64 },
+    #[error("Block {0} does not match its merkle root")]
+    InvalidBody(u64),
+    #[error("Receipts of block {0} do not match its header")]
+    InvalidReceipts(u64),
+    #[error("Block {0} is not in the local chain")]
+    MissingBlock(u64),
+    #[error("Chain store error: {0}")]
+    Store(#[from] ChainStoreError),
+}
+
+pub type Result<T> = std::result::Result<T, ArchiveError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveRecord {
+    pub height: u64,
+    pub block: Block,
+    pub receipts: Vec<TransactionReceipt>,
+}
+
+pub struct ArchiveWriter<W: Write> {
+    inner: W,
+    records: u64,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(mut inner: W) -> Result<Self> {
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[VERSION])?;
+        Ok(Self { inner, records: 0 })
+    }
+
+    pub fn write(&mut self, record: &ArchiveRecord) -> Result<()> {
+        let payload = bincode::serialize(record)?;
+        if payload.len() > MAX_RECORD_LEN as usize {
+            return Err(ArchiveError::RecordTooLarge(self.records));
+        }
+        self.inner.write_all(&(payload.len() as u32).to_be_bytes())?;
+        self.inner.write_all(&crc32fast::hash(&payload).to_be_bytes())?;
+        self.inner.write_all(&payload)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.inner.write_all(&[0; 8])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+pub struct ArchiveReader<R: Read> {
+    inner: R,
+    records: u64,
+    done: bool,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut header = [0u8; 5];
+        inner.read_exact(&mut header).map_err(|_| ArchiveError::BadMagic)?;
+        if &header[..4] != MAGIC {
+            return Err(ArchiveError::BadMagic);
+        }
+        if header[4] != VERSION {
+            return Err(ArchiveError::UnsupportedVersion(header[4]));
+        }
+        Ok(Self { inner, records: 0, done: false })
+    }
+
+    fn read_record(&mut self) -> Result<Option<ArchiveRecord>> {
+        let mut prefix = [0u8; 8];
+        if let Err(e) = self.inner.read_exact(&mut prefix) {
+            return match e.kind() {
+                io::ErrorKind::UnexpectedEof => Err(ArchiveError::Truncated(self.records)),
+                _ => Err(e.into()),
+            };
+        }
+        let length = u32::from_be_bytes(prefix[..4].try_into().unwrap());
+        let checksum = u32::from_be_bytes(prefix[4..].try_into().unwrap());
+        if length == 0 {
+            return Ok(None);
+        }
+        if length > MAX_RECORD_LEN {
+            return Err(ArchiveError::RecordTooLarge(self.records));
+        }
+
+        let mut payload = vec![0u8; length as usize];
+        self.inner.read_exact(&mut payload).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => ArchiveError::Truncated(self.records),
+            _ => e.into(),
+        })?;
+        if crc32fast::hash(&payload) != checksum {
+            return Err(ArchiveError::ChecksumMismatch(self.records));
+        }
+        self.records += 1;
+        Ok(Some(bincode::deserialize(&payload)?))
+    }
+}
+
+impl<R: Read> Iterator for ArchiveReader<R> {
+    type Item = Result<ArchiveRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record = self.read_record().transpose();
+        if !matches!(record, Some(Ok(_))) {
+            self.done = true;
+        }
+        record
+    }
+}
+
+pub async fn export<W: Write>(store: &ChainStore, from: u64, to: u64, writer: W) -> Result<u64> {
+    let mut archive = ArchiveWriter::new(writer)?;
+    for height in from..=to {
+        let block = store.block(height).await?.ok_or(ArchiveError::MissingBlock(height))?;
+        let receipts = store.receipts(height).await?.ok_or(ArchiveError::MissingBlock(height))?;
+        archive.write(&ArchiveRecord { height, block, receipts })?;
+    }
+    let exported = archive.records;
+    archive.finish()?;
+    Ok(exported)
+}
+
+// Imports on top of the pipeline's current head; records the chain already has
+// are skipped so an interrupted import can simply be re-run.
+pub async fn import<R: Read>(pipeline: &mut ImportPipeline, reader: R) -> Result<u64> {
+    let mut imported = 0;
+    let mut next = pipeline.head().await?.map_or(0, |head| head.height + 1);
+    for record in ArchiveReader::new(reader)? {
+        let record = record?;
+        if record.height < next {
+            continue;
+        }
+        if record.height != next {
+            return Err(ArchiveError::UnexpectedHeight { expected: next, found: record.height });
+        }
+        if Block::calculate_merkle_root(&record.block.transactions) != record.block.header.merkle_root {
+            return Err(ArchiveError::InvalidBody(record.height));
+        }
+        if !record.block.receipts_match(record.height, &record.receipts) {
+            return Err(ArchiveError::InvalidReceipts(record.height));
+        }
+        pipeline.import_block(record.height, &record.block, &record.receipts, &[]).await?;
+        next += 1;
+        imported += 1;
+    }
+    pipeline.flush().await?;
+    info!("Imported {} blocks from archive", imported);
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::import::ImportConfig;
+    use crate::storage::db::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    async fn source_chain(temp_dir: &TempDir, length: u64) -> ChainStore {
+        let store = ChainStore::new(Arc::new(Database::new(temp_dir.path().join("source")).unwrap()));
+        let mut parent = [0; 32];
+        for height in 0..length {
+            let block = Block::new(parent, vec![], 1).unwrap();
+            store.import_block(height, &block, &[], &[]).await.unwrap();
+            parent = block.hash();
+        }
+        store
+    }
+
+    #[tokio::test]
+    async fn test_export_import_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = source_chain(&temp_dir, 5).await;
+        let mut file = Vec::new();
+        assert_eq!(export(&source, 0, 4, &mut file).await.unwrap(), 5);
+
+        let target = ChainStore::new(Arc::new(Database::new(temp_dir.path().join("target")).unwrap()));
+        let mut pipeline = ImportPipeline::new(target, ImportConfig::default());
+        assert_eq!(import(&mut pipeline, file.as_slice()).await.unwrap(), 5);
+        assert_eq!(pipeline.persisted_head().await.unwrap(), source.head().await.unwrap());
+        // Re-running is a no-op
+        assert_eq!(import(&mut pipeline, file.as_slice()).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_detects_corruption_and_truncation() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = source_chain(&temp_dir, 3).await;
+        let mut file = Vec::new();
+        export(&source, 0, 2, &mut file).await.unwrap();
+
+        let mut flipped = file.clone();
+        flipped[20] ^= 0x01;
+        let records: Vec<_> = ArchiveReader::new(flipped.as_slice()).unwrap().collect();
+        assert!(matches!(records.last(), Some(Err(ArchiveError::ChecksumMismatch(0)))));
+
+        let truncated = &file[..file.len() - 8];
+        let records: Vec<_> = ArchiveReader::new(truncated).unwrap().collect();
+        assert_eq!(records.len(), 4);
+        assert!(matches!(records[3], Err(ArchiveError::Truncated(3))));
+
+        assert!(matches!(ArchiveReader::new(&b"NOPE\x01"[..]), Err(ArchiveError::BadMagic)));
+    }
+}
@@ -1,13 +1,15 @@
 use chrono::Utc;
+use parity_scale_codec::{Decode, Encode};
 use serde::{Deserialize, Serialize};
-use sha3::{Digest, Sha3_256};
-use std::convert::TryInto;
 
-use crate::chain::transaction::Transaction;
+use crate::chain::transaction::{Transaction, TransactionReceipt};
 use crate::consensus::proof::Proof;
+use crate::crypto::hasher::HashAlgorithm;
 use crate::errors::BlockError;
 
 const MAX_TRANSACTIONS: usize = 1000;
+// Of headers and the transaction and receipt trees
+const BLOCK_HASH: HashAlgorithm = HashAlgorithm::Sha3_256;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
@@ -15,11 +17,19 @@ pub struct Block {
     pub transactions: Vec<Transaction>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Fields are hashed in this order, see chain::canonical
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
 pub struct BlockHeader {
     pub version: u32,
     pub prev_block_hash: [u8; 32],
     pub merkle_root: [u8; 32],
+    // Root of the state trie after executing this block, see `storage::trie`
+    #[serde(default)]
+    pub state_root: [u8; 32],
+    // Root over the receipts of executing this block, in transaction order,
+    // so history can be imported from peers without re-executing it
+    #[serde(default)]
+    pub receipts_root: [u8; 32],
     pub timestamp: i64,
     pub difficulty: u32,
     pub nonce: u64,
@@ -38,6 +48,8 @@ impl Block {
                 version: 1,
                 prev_block_hash,
                 merkle_root,
+                state_root: [0; 32],
+                receipts_root: [0; 32],
                 timestamp: Utc::now().timestamp(),
                 difficulty,
                 nonce: 0,
@@ -56,37 +68,36 @@ impl Block {
     }
 
     pub fn hash(&self) -> [u8; 32] {
-        let mut hasher = Sha3_256::new();
-        hasher.update(bincode::serialize(&self.header).unwrap());
-        hasher.finalize().try_into().unwrap()
+        BLOCK_HASH.digest(&self.header.encode())
     }
 
-    fn calculate_merkle_root(transactions: &[Transaction]) -> [u8; 32] {
-        if transactions.is_empty() {
-            return [0; 32];
-        }
+    pub(crate) fn calculate_merkle_root(transactions: &[Transaction]) -> [u8; 32] {
+        Self::merkle_root(transactions.iter().map(|tx| tx.hash()).collect())
+    }
 
-        let mut hashes: Vec<[u8; 32]> = transactions
+    pub(crate) fn calculate_receipts_root(receipts: &[TransactionReceipt]) -> [u8; 32] {
+        let hashes = receipts
             .iter()
-            .map(|tx| tx.hash())
+            .map(|receipt| BLOCK_HASH.digest(&receipt.encode()))
             .collect();
+        Self::merkle_root(hashes)
+    }
 
-        while hashes.len() > 1 {
-            let mut next_level = Vec::new();
-            for chunk in hashes.chunks(2) {
-                let mut hasher = Sha3_256::new();
-                hasher.update(&chunk[0]);
-                if chunk.len() > 1 {
-                    hasher.update(&chunk[1]);
-                } else {
-                    hasher.update(&chunk[0]);  // If odd number, duplicate last hash
-                }
-                next_level.push(hasher.finalize().try_into().unwrap());
-            }
-            hashes = next_level;
-        }
+    // Receipts from a peer or an archive are as good as the header: one per
+    // transaction, naming this block, and committed to by `receipts_root`
+    pub fn receipts_match(&self, height: u64, receipts: &[TransactionReceipt]) -> bool {
+        let hash = self.hash();
+        receipts.len() == self.transactions.len()
+            && receipts.iter().zip(&self.transactions).all(|(receipt, tx)| {
+                receipt.block_hash == hash
+                    && receipt.block_number == height
+                    && tx.hash().ok().as_ref() == Some(&receipt.transaction_hash)
+            })
+            && Self::calculate_receipts_root(receipts) == self.header.receipts_root
+    }
 
-        hashes[0]
+    fn merkle_root(hashes: Vec<[u8; 32]>) -> [u8; 32] {
+        BLOCK_HASH.merkle_root(hashes)
     }
 
     pub fn validate(&self) -> Result<(), BlockError> {
@@ -144,4 +155,13 @@ mod tests {
         block.header.merkle_root = [1; 32];
         assert!(block.validate().is_err());
     }
+
+    #[test]
+    fn test_receipts_must_match_header() {
+        let mut block = Block::new([0; 32], vec![], 1).unwrap();
+        assert!(block.receipts_match(1, &[]));
+
+        block.header.receipts_root = [1; 32];
+        assert!(!block.receipts_match(1, &[]));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,88 @@
+use crate::chain::transaction::{Log, TransactionReceipt};
+use crate::crypto::hash::Hash;
+use crate::types::Address;
+
+pub const BLOOM_BYTES: usize = 256;
+
+// 2048-bit bloom over the addresses and topics of every log in a block, kept
+// next to the block so log queries only read the receipts of blocks that may
+// match. Each entry sets three bits taken from its hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogBloom([u8; BLOOM_BYTES]);
+
+impl Default for LogBloom {
+    fn default() -> Self {
+        Self([0; BLOOM_BYTES])
+    }
+}
+
+impl LogBloom {
+    pub fn from_receipts(receipts: &[TransactionReceipt]) -> Self {
+        let mut bloom = Self::default();
+        for log in receipts.iter().flat_map(|receipt| &receipt.logs) {
+            bloom.accrue_log(log);
+        }
+        bloom
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok().map(Self)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn accrue_log(&mut self, log: &Log) {
+        self.accrue(&address_bytes(&log.address));
+        for topic in &log.topics {
+            self.accrue(topic.as_bytes());
+        }
+    }
+
+    pub fn accrue(&mut self, input: &[u8]) {
+        for (byte, mask) in bits(input) {
+            self.0[byte] |= mask;
+        }
+    }
+
+    // False positives are possible, false negatives are not
+    pub fn may_contain(&self, input: &[u8]) -> bool {
+        bits(input).into_iter().all(|(byte, mask)| self.0[byte] & mask != 0)
+    }
+
+    pub fn may_contain_address(&self, address: &Address) -> bool {
+        self.may_contain(&address_bytes(address))
+    }
+}
+
+fn address_bytes(address: &Address) -> Vec<u8> {
+    bincode::serialize(address).expect("addresses serialize")
+}
+
+fn bits(input: &[u8]) -> [(usize, u8); 3] {
+    let hash = Hash::hash(input);
+    let hash = hash.as_bytes();
+    [0, 2, 4].map(|i| {
+        let bit = (u16::from_be_bytes([hash[i], hash[i + 1]]) & 0x07ff) as usize;
+        (BLOOM_BYTES - 1 - bit / 8, 1 << (bit % 8))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains_every_log_address_and_topic() {
+        let log = Log { address: Address::random(), topics: vec![Hash::hash(b"ModelDeployed")], data: vec![] };
+        let mut bloom = LogBloom::default();
+        assert!(!bloom.may_contain_address(&log.address));
+        bloom.accrue_log(&log);
+        assert!(bloom.may_contain_address(&log.address));
+        assert!(bloom.may_contain(Hash::hash(b"ModelDeployed").as_bytes()));
+        assert!(!bloom.may_contain(Hash::hash(b"JobCompleted").as_bytes()));
+        assert_eq!(LogBloom::from_bytes(bloom.as_bytes()), Some(bloom));
+        assert_eq!(LogBloom::from_bytes(&[0; 8]), None);
+    }
+}
@@ -0,0 +1,223 @@
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::store::{ChainHead, ChainStore, Result, TransactionLocation};
+use crate::chain::transaction::{Transaction, TransactionReceipt};
+use crate::storage::columns::Column;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum IssueKind {
+    Missing(&'static str),
+    Corrupt { column: &'static str, reason: String },
+    BrokenParentLink,
+    MerkleRootMismatch,
+    BlockIndexMismatch,
+    TransactionIndexMismatch(u32),
+    HeadMismatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Issue {
+    pub height: u64,
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            IssueKind::Missing(column) => write!(f, "height {}: missing {}", self.height, column),
+            IssueKind::Corrupt { column, reason } => write!(f, "height {}: corrupt {} ({})", self.height, column, reason),
+            IssueKind::BrokenParentLink => write!(f, "height {}: parent hash does not match previous header", self.height),
+            IssueKind::MerkleRootMismatch => write!(f, "height {}: body does not match header merkle root", self.height),
+            IssueKind::BlockIndexMismatch => write!(f, "height {}: block hash index is missing or wrong", self.height),
+            IssueKind::TransactionIndexMismatch(index) => {
+                write!(f, "height {}: index of transaction {} is missing or wrong", self.height, index)
+            }
+            IssueKind::HeadMismatch => write!(f, "height {}: head pointer does not match stored header", self.height),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CheckReport {
+    pub head: Option<u64>,
+    pub blocks_checked: u64,
+    pub issues: Vec<Issue>,
+}
+
+impl CheckReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    // Everything from here up to the head has to be re-fetched
+    pub fn first_bad_height(&self) -> Option<u64> {
+        self.issues.iter().map(|issue| issue.height).min()
+    }
+}
+
+// Walks the stored chain verifying that every record decodes, headers link by
+// hash, bodies match their merkle roots and lookup indexes point back at the
+// right block. Reads raw bytes so corruption is reported instead of panicking
+// somewhere deep in deserialization.
+pub struct IntegrityChecker<'a> {
+    store: &'a ChainStore,
+}
+
+impl<'a> IntegrityChecker<'a> {
+    pub fn new(store: &'a ChainStore) -> Self {
+        Self { store }
+    }
+
+    pub async fn check(&self) -> Result<CheckReport> {
+        let head = self.store.head().await?;
+        let mut report = CheckReport { head: head.map(|head| head.height), ..CheckReport::default() };
+        let head = match head {
+            Some(head) => head,
+            None => return Ok(report),
+        };
+
+        let mut parent_hash: Option<[u8; 32]> = None;
+        for height in 0..=head.height {
+            parent_hash = self.check_block(height, parent_hash, &head, &mut report.issues).await?;
+            report.blocks_checked += 1;
+        }
+        Ok(report)
+    }
+
+    // Deletes everything from the first bad height onward so sync re-fetches it
+    pub async fn repair(&self, report: &CheckReport) -> Result<Option<ChainHead>> {
+        match report.first_bad_height() {
+            Some(height) => {
+                warn!("Truncating chain from height {} to repair {} issue(s)", height, report.issues.len());
+                self.store.truncate(height).await
+            }
+            None => self.store.head().await,
+        }
+    }
+
+    async fn check_block(
+        &self,
+        height: u64,
+        parent_hash: Option<[u8; 32]>,
+        head: &ChainHead,
+        issues: &mut Vec<Issue>,
+    ) -> Result<Option<[u8; 32]>> {
+        let mut report = |kind| issues.push(Issue { height, kind });
+
+        let header: Option<BlockHeader> = match self.read(Column::Headers, height).await? {
+            Ok(header) => header,
+            Err(kind) => {
+                report(kind);
+                None
+            }
+        };
+        let body: Option<Vec<Transaction>> = match self.read(Column::Bodies, height).await? {
+            Ok(body) => body,
+            Err(kind) => {
+                report(kind);
+                None
+            }
+        };
+        if let Err(kind) = self.read::<Vec<TransactionReceipt>>(Column::Receipts, height).await? {
+            report(kind);
+        }
+
+        let header = match header {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let block = Block { header, transactions: body.unwrap_or_default() };
+        let hash = block.hash();
+
+        if parent_hash.map_or(false, |parent| parent != block.header.prev_block_hash) {
+            report(IssueKind::BrokenParentLink);
+        }
+        if Block::calculate_merkle_root(&block.transactions) != block.header.merkle_root {
+            report(IssueKind::MerkleRootMismatch);
+        }
+        if height == head.height && hash != head.hash {
+            report(IssueKind::HeadMismatch);
+        }
+        if self.store.height_of(hash).await.ok().flatten() != Some(height) {
+            report(IssueKind::BlockIndexMismatch);
+        }
+        for (index, tx) in block.transactions.iter().enumerate() {
+            let expected = TransactionLocation { height, index: index as u32 };
+            let location = match tx.hash() {
+                Ok(tx_hash) => self.store.transaction_location(&tx_hash).await.ok().flatten(),
+                Err(_) => None,
+            };
+            if location != Some(expected) {
+                report(IssueKind::TransactionIndexMismatch(index as u32));
+            }
+        }
+
+        Ok(Some(hash))
+    }
+
+    // Outer error: the database itself failed. Inner error: the record is missing or corrupt.
+    async fn read<T: DeserializeOwned>(&self, column: Column, height: u64) -> Result<std::result::Result<Option<T>, IssueKind>> {
+        let bytes = match self.store.block_part_raw(column, height).await {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return Ok(Err(IssueKind::Missing(column.name()))),
+            Err(e) => return Ok(Err(IssueKind::Corrupt { column: column.name(), reason: e.to_string() })),
+        };
+        Ok(bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|e| IssueKind::Corrupt { column: column.name(), reason: e.to_string() }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::store::height_key;
+    use crate::storage::backend::StorageBackend;
+    use crate::storage::db::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    async fn build_chain(store: &ChainStore, length: u64) {
+        let mut parent = [0; 32];
+        for height in 0..length {
+            let block = Block::new(parent, vec![], 1).unwrap();
+            store.import_block(height, &block, &[], &[]).await.unwrap();
+            parent = block.hash();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clean_chain_passes() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap()));
+        build_chain(&store, 5).await;
+
+        let report = IntegrityChecker::new(&store).check().await.unwrap();
+        assert!(report.is_clean(), "{:?}", report.issues);
+        assert_eq!(report.blocks_checked, 5);
+    }
+
+    #[tokio::test]
+    async fn test_detects_and_repairs_corruption() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap()));
+        build_chain(&store, 6).await;
+
+        // Bit-flipped header at height 3
+        store.database().put_raw(Some(Column::Headers), &height_key(3), &[0xff; 3]).await.unwrap();
+
+        let checker = IntegrityChecker::new(&store);
+        let report = checker.check().await.unwrap();
+        assert_eq!(report.first_bad_height(), Some(3));
+        assert!(matches!(report.issues[0].kind, IssueKind::Corrupt { column: "headers", .. }));
+        // Height 4 can no longer be linked to its parent
+        assert!(report.issues.iter().all(|issue| issue.height == 3));
+
+        let head = checker.repair(&report).await.unwrap();
+        assert_eq!(head.map(|head| head.height), Some(2));
+        assert!(checker.check().await.unwrap().is_clean());
+    }
+}
@@ -0,0 +1,326 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::events::{self, EventError, Events};
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::crypto::scheme::ADDRESS_LEN;
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::types::{Address, BlockHeight};
+
+// The address breaker events are logged from
+pub fn address() -> Address {
+    let mut address = [0u8; ADDRESS_LEN];
+    address[1..8].copy_from_slice(b"breaker");
+    Address::from(address)
+}
+
+// Part of the chain spec. With no guardians nothing can be paused.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuardianConfig {
+    pub guardians: Vec<Address>,
+    // Approvals an action needs
+    pub threshold: usize,
+    // Longest a pause may last; it lifts by itself afterwards
+    pub max_pause_blocks: u64,
+    // Blocks an action has to gather its approvals
+    pub approval_window: u64,
+}
+
+impl Default for GuardianConfig {
+    fn default() -> Self {
+        Self { guardians: Vec::new(), threshold: 0, max_pause_blocks: 14_400, approval_window: 600 }
+    }
+}
+
+impl GuardianConfig {
+    // Checked when the spec loads: configured guardians must be able to reach
+    // their threshold, and no action may pass without an approval
+    pub fn validate(&self) -> Result<(), BreakerError> {
+        if self.guardians.is_empty() || (1..=self.guardians.len()).contains(&self.threshold) {
+            return Ok(());
+        }
+        Err(BreakerError::InvalidThreshold { threshold: self.threshold, guardians: self.guardians.len() })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Subsystem {
+    // Settling inference jobs and paying providers
+    JobSettlement,
+    // Unstaking and other withdrawals
+    Withdrawals,
+    ModelInvocation,
+    ContractExecution,
+}
+
+// Payload of GuardianAction transactions. Identical actions from different
+// guardians count towards the same approval.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GuardianAction {
+    // From `at`, or from when approved if that is later, for `blocks`
+    Pause { subsystem: Subsystem, at: BlockHeight, blocks: u64 },
+    Resume { subsystem: Subsystem },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pause {
+    pub subsystem: Subsystem,
+    pub from: BlockHeight,
+    pub until: BlockHeight,
+}
+
+// One entry of the audit trail; every entry is also logged as an event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BreakerRecord {
+    Approved { height: BlockHeight, guardian: Address, action: GuardianAction },
+    Paused { height: BlockHeight, pause: Pause, approvals: Vec<Address> },
+    Resumed { height: BlockHeight, subsystem: Subsystem, approvals: Vec<Address> },
+    Expired { height: BlockHeight, pause: Pause },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingAction {
+    action: GuardianAction,
+    opened_at: BlockHeight,
+    approvals: Vec<Address>,
+}
+
+#[derive(Debug, Error)]
+pub enum BreakerError {
+    #[error("Sender is not a guardian")]
+    NotGuardian,
+    #[error("Guardian already approved this action")]
+    AlreadyApproved,
+    #[error("Pause of {blocks} blocks exceeds the maximum of {max}")]
+    PauseTooLong { blocks: u64, max: u64 },
+    #[error("Subsystem is not paused")]
+    NotPaused,
+    #[error("Unexpected transaction type")]
+    UnexpectedTransactionType,
+    #[error("Malformed payload")]
+    MalformedPayload,
+    #[error("Threshold {threshold} is not between 1 and the {guardians} guardians")]
+    InvalidThreshold { threshold: usize, guardians: usize },
+    #[error("Event error: {0}")]
+    Event(#[from] EventError),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// Lets a threshold of guardians pause subsystems when an exploit is found,
+// without waiting out a governance vote. Pauses are capped in length and
+// lift by themselves, and every approval, pause, resume and expiry is kept
+// on chain and logged.
+pub struct CircuitBreaker {
+    storage: Arc<dyn StorageBackend>,
+    config: GuardianConfig,
+}
+
+impl CircuitBreaker {
+    pub fn new(storage: Arc<dyn StorageBackend>, config: GuardianConfig) -> Result<Self, BreakerError> {
+        config.validate()?;
+        Ok(Self { storage, config })
+    }
+
+    pub async fn apply_transaction(
+        &mut self,
+        tx: &Transaction,
+        height: BlockHeight,
+        events: &mut Events,
+    ) -> Result<(), BreakerError> {
+        match tx.transaction_type {
+            TransactionType::GuardianAction => {
+                let action = bincode::deserialize(&tx.data).map_err(|_| BreakerError::MalformedPayload)?;
+                self.approve(tx.from, action, height, events).await
+            }
+            _ => Err(BreakerError::UnexpectedTransactionType),
+        }
+    }
+
+    pub async fn approve(
+        &mut self,
+        guardian: Address,
+        action: GuardianAction,
+        height: BlockHeight,
+        events: &mut Events,
+    ) -> Result<(), BreakerError> {
+        if !self.config.guardians.contains(&guardian) {
+            return Err(BreakerError::NotGuardian);
+        }
+        match &action {
+            GuardianAction::Pause { blocks, .. } if *blocks > self.config.max_pause_blocks => {
+                return Err(BreakerError::PauseTooLong { blocks: *blocks, max: self.config.max_pause_blocks })
+            }
+            GuardianAction::Resume { subsystem } if !self.active(*subsystem, height).await? => {
+                return Err(BreakerError::NotPaused)
+            }
+            _ => {}
+        }
+
+        let mut pending = self.get_pending().await?;
+        let index = match pending.iter().position(|open| open.action == action) {
+            Some(index) => index,
+            None => {
+                pending.push(PendingAction { action: action.clone(), opened_at: height, approvals: Vec::new() });
+                pending.len() - 1
+            }
+        };
+        if pending[index].approvals.contains(&guardian) {
+            return Err(BreakerError::AlreadyApproved);
+        }
+        pending[index].approvals.push(guardian);
+        let mut records = vec![BreakerRecord::Approved { height, guardian, action: action.clone() }];
+
+        if pending[index].approvals.len() >= self.config.threshold {
+            let approvals = pending.remove(index).approvals;
+            let mut pauses = self.get_pauses().await?;
+            match action {
+                GuardianAction::Pause { subsystem, at, blocks } => {
+                    let from = if at > height { at } else { height };
+                    let pause = Pause { subsystem, from, until: from + blocks };
+                    pauses.push(pause.clone());
+                    records.push(BreakerRecord::Paused { height, pause, approvals });
+                }
+                GuardianAction::Resume { subsystem } => {
+                    pauses.retain(|pause| pause.subsystem != subsystem);
+                    records.push(BreakerRecord::Resumed { height, subsystem, approvals });
+                }
+            }
+            self.storage.put_value(b"breaker_pauses", &pauses).await?;
+        }
+        self.storage.put_value(b"breaker_pending", &pending).await?;
+        self.record(records, events).await
+    }
+
+    // Whether `subsystem` must not run at `height`. The executor checks this
+    // before settling jobs, paying out withdrawals and so on.
+    pub async fn is_paused(&self, subsystem: Subsystem, height: BlockHeight) -> Result<bool, BreakerError> {
+        Ok(self
+            .get_pauses()
+            .await?
+            .iter()
+            .any(|pause| pause.subsystem == subsystem && pause.from <= height && height < pause.until))
+    }
+
+    // Run once per block: lifts pauses that have run out and drops actions
+    // that didn't gather their approvals in time
+    pub async fn end_block(&mut self, height: BlockHeight, events: &mut Events) -> Result<(), BreakerError> {
+        let (expired, pauses): (Vec<_>, Vec<_>) =
+            self.get_pauses().await?.into_iter().partition(|pause| pause.until <= height + 1);
+        let mut pending = self.get_pending().await?;
+        let open = pending.len();
+        pending.retain(|action| height < action.opened_at + self.config.approval_window);
+
+        if pending.len() != open {
+            self.storage.put_value(b"breaker_pending", &pending).await?;
+        }
+        if expired.is_empty() {
+            return Ok(());
+        }
+        self.storage.put_value(b"breaker_pauses", &pauses).await?;
+        self.record(expired.into_iter().map(|pause| BreakerRecord::Expired { height, pause }).collect(), events).await
+    }
+
+    pub async fn pauses(&self) -> Result<Vec<Pause>, BreakerError> {
+        self.get_pauses().await
+    }
+
+    // The full audit trail, oldest first
+    pub async fn history(&self) -> Result<Vec<BreakerRecord>, BreakerError> {
+        Ok(self.storage.get_value(b"breaker_history").await?.unwrap_or_default())
+    }
+
+    async fn active(&self, subsystem: Subsystem, height: BlockHeight) -> Result<bool, BreakerError> {
+        Ok(self.get_pauses().await?.iter().any(|pause| pause.subsystem == subsystem && height < pause.until))
+    }
+
+    async fn record(&mut self, records: Vec<BreakerRecord>, events: &mut Events) -> Result<(), BreakerError> {
+        for record in &records {
+            let name = match record {
+                BreakerRecord::Approved { .. } => "GuardianApproved",
+                BreakerRecord::Paused { .. } => "SubsystemPaused",
+                BreakerRecord::Resumed { .. } => "SubsystemResumed",
+                BreakerRecord::Expired { .. } => "PauseExpired",
+            };
+            let data = bincode::serialize(record).expect("record serialization cannot fail");
+            events.emit(address(), vec![events::topic(name)], data)?;
+        }
+        let mut history = self.history().await?;
+        history.extend(records);
+        self.storage.put_value(b"breaker_history", &history).await?;
+        Ok(())
+    }
+
+    async fn get_pauses(&self) -> Result<Vec<Pause>, BreakerError> {
+        Ok(self.storage.get_value(b"breaker_pauses").await?.unwrap_or_default())
+    }
+
+    async fn get_pending(&self) -> Result<Vec<PendingAction>, BreakerError> {
+        Ok(self.storage.get_value(b"breaker_pending").await?.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    #[tokio::test]
+    async fn test_threshold_of_guardians_pauses_until_expiry() {
+        let guardians: Vec<Address> = (0..3).map(|_| Address::random()).collect();
+        let config = GuardianConfig {
+            guardians: guardians.clone(),
+            threshold: 2,
+            max_pause_blocks: 100,
+            ..GuardianConfig::default()
+        };
+        let storage: Arc<dyn StorageBackend> = Arc::new(MemoryBackend::new());
+        for threshold in [0, 4] {
+            let config = GuardianConfig { threshold, ..config.clone() };
+            assert!(matches!(
+                CircuitBreaker::new(storage.clone(), config),
+                Err(BreakerError::InvalidThreshold { guardians: 3, .. })
+            ));
+        }
+        let mut breaker = CircuitBreaker::new(storage, config).unwrap();
+        let mut events = Events::new();
+        let height = |height: u64| BlockHeight::from(height);
+        let pause = GuardianAction::Pause { subsystem: Subsystem::Withdrawals, at: height(0), blocks: 50 };
+
+        assert!(matches!(
+            breaker.approve(Address::random(), pause.clone(), height(10), &mut events).await,
+            Err(BreakerError::NotGuardian)
+        ));
+        let too_long = GuardianAction::Pause { subsystem: Subsystem::Withdrawals, at: height(0), blocks: 101 };
+        assert!(matches!(
+            breaker.approve(guardians[0], too_long, height(10), &mut events).await,
+            Err(BreakerError::PauseTooLong { .. })
+        ));
+        breaker.approve(guardians[0], pause.clone(), height(10), &mut events).await.unwrap();
+        assert!(matches!(
+            breaker.approve(guardians[0], pause.clone(), height(10), &mut events).await,
+            Err(BreakerError::AlreadyApproved)
+        ));
+        assert!(!breaker.is_paused(Subsystem::Withdrawals, height(11)).await.unwrap());
+        breaker.approve(guardians[1], pause, height(11), &mut events).await.unwrap();
+
+        assert!(breaker.is_paused(Subsystem::Withdrawals, height(11)).await.unwrap());
+        assert!(breaker.is_paused(Subsystem::Withdrawals, height(60)).await.unwrap());
+        assert!(!breaker.is_paused(Subsystem::JobSettlement, height(11)).await.unwrap());
+        breaker.end_block(height(59), &mut events).await.unwrap();
+        assert_eq!(breaker.pauses().await.unwrap().len(), 1);
+        breaker.end_block(height(60), &mut events).await.unwrap();
+        assert!(breaker.pauses().await.unwrap().is_empty());
+        assert!(!breaker.is_paused(Subsystem::Withdrawals, height(61)).await.unwrap());
+
+        let history = breaker.history().await.unwrap();
+        assert_eq!(history.len(), 4);
+        assert!(matches!(&history[2], BreakerRecord::Paused { approvals, .. } if approvals.len() == 2));
+        assert!(matches!(history[3], BreakerRecord::Expired { .. }));
+        assert_eq!(events.logs().len(), 4);
+        assert!(events.logs().iter().all(|log| log.address == address()));
+    }
+}
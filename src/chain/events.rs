@@ -0,0 +1,111 @@
+use thiserror::Error;
+
+use crate::chain::block::BlockHash;
+use crate::chain::transaction::{Log, TransactionHash, TransactionReceipt};
+use crate::crypto::hash::Hash;
+use crate::crypto::scheme::ADDRESS_LEN;
+use crate::types::Address;
+
+// Charged to contracts per event, on top of the metered instructions
+pub const EVENT_GAS: u64 = 375;
+pub const EVENT_TOPIC_GAS: u64 = 375;
+pub const EVENT_BYTE_GAS: u64 = 8;
+pub const MAX_TOPICS: usize = 4;
+pub const MAX_EVENT_DATA: usize = 16 * 1024;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EventError {
+    #[error("Event has {0} topics, at most {MAX_TOPICS} are allowed")]
+    TooManyTopics(usize),
+    #[error("Event data is {0} bytes, at most {MAX_EVENT_DATA} are allowed")]
+    TooLarge(usize),
+}
+
+// The first topic of an event: the hash of its name, e.g. "AccessGranted"
+pub fn topic(name: &str) -> Hash {
+    Hash::hash(name.as_bytes())
+}
+
+// An address as a topic, left-padded with zeros to 32 bytes, so logs can be
+// filtered by the accounts they involve
+pub fn address_topic(address: &Address) -> Hash {
+    let mut bytes = [0u8; 32];
+    bytes[32 - ADDRESS_LEN..].copy_from_slice(address.as_ref());
+    Hash::from(&bytes[..])
+}
+
+pub fn gas(topics: usize, data_len: usize) -> u64 {
+    EVENT_GAS + EVENT_TOPIC_GAS * topics as u64 + EVENT_BYTE_GAS * data_len as u64
+}
+
+// The events emitted while executing one transaction, by contracts through
+// the `env.emit_event` host function and by native modules directly. They
+// become the receipt's logs, and so enter the block bloom, only if the
+// transaction succeeds.
+#[derive(Debug, Default)]
+pub struct Events {
+    logs: Vec<Log>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn emit(&mut self, address: Address, topics: Vec<Hash>, data: Vec<u8>) -> Result<(), EventError> {
+        if topics.len() > MAX_TOPICS {
+            return Err(EventError::TooManyTopics(topics.len()));
+        }
+        if data.len() > MAX_EVENT_DATA {
+            return Err(EventError::TooLarge(data.len()));
+        }
+        self.logs.push(Log { address, topics, data });
+        Ok(())
+    }
+
+    pub fn logs(&self) -> &[Log] {
+        &self.logs
+    }
+
+    pub fn into_receipt(
+        self,
+        transaction_hash: TransactionHash,
+        block_hash: BlockHash,
+        block_number: u64,
+        gas_used: u64,
+        status: bool,
+    ) -> TransactionReceipt {
+        let logs = if status { self.logs } else { Vec::new() };
+        TransactionReceipt { transaction_hash, block_hash, block_number, gas_used, status, logs }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::bloom::LogBloom;
+
+    #[test]
+    fn test_events_become_logs_of_successful_receipts_only() {
+        let (contract, holder) = (Address::random(), Address::random());
+        let mut events = Events::new();
+        events.emit(contract, vec![topic("Transfer"), address_topic(&holder)], vec![1, 2, 3]).unwrap();
+        assert_eq!(events.emit(contract, vec![topic("Transfer"); 5], vec![]), Err(EventError::TooManyTopics(5)));
+        assert_eq!(
+            events.emit(contract, vec![], vec![0; MAX_EVENT_DATA + 1]),
+            Err(EventError::TooLarge(MAX_EVENT_DATA + 1))
+        );
+        assert_eq!(gas(2, 3), EVENT_GAS + 2 * EVENT_TOPIC_GAS + 3 * EVENT_BYTE_GAS);
+
+        let receipt = events.into_receipt(Hash::hash(b"tx"), BlockHash::default(), 1, 21_000, true);
+        assert_eq!(receipt.logs.len(), 1);
+        let bloom = LogBloom::from_receipts(&[receipt]);
+        assert!(bloom.may_contain_address(&contract));
+        assert!(bloom.may_contain(topic("Transfer").as_bytes()));
+        assert!(bloom.may_contain(address_topic(&holder).as_bytes()));
+
+        let mut failed = Events::new();
+        failed.emit(contract, vec![topic("Transfer")], vec![]).unwrap();
+        assert!(failed.into_receipt(Hash::hash(b"tx"), BlockHash::default(), 1, 21_000, false).logs.is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+use crate::chain::block::Block;
+use crate::chain::history::balance_key;
+use crate::chain::store::{ChainHead, ChainStore, ChainStoreError};
+use crate::storage::db::DatabaseError;
+use crate::storage::transaction::StorageTransaction;
+use crate::storage::trie::{StateTrie, EMPTY_ROOT};
+use crate::types::{Address, Balance};
+
+#[derive(Debug, Error)]
+pub enum GenesisError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed genesis file: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("Database already holds genesis {}, not {}", hex(.existing), hex(.expected))]
+    Mismatch { existing: [u8; 32], expected: [u8; 32] },
+    #[error("Chain store error: {0}")]
+    Store(#[from] ChainStoreError),
+    #[error("Database error: {0}")]
+    Database(#[from] DatabaseError),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisAccount {
+    pub address: Address,
+    pub balance: Balance,
+}
+
+// `genesis.json`. Every node of a network must derive the same genesis block,
+// so nothing in it may depend on the clock or on local keys.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenesisConfig {
+    pub timestamp: i64,
+    pub difficulty: u32,
+    // Balances at genesis. They go into the genesis state root, so nodes with
+    // different allocations don't share a genesis block.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alloc: Vec<GenesisAccount>,
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        // Mainnet
+        Self { timestamp: 1_672_531_200, difficulty: 1, alloc: Vec::new() }
+    }
+}
+
+impl GenesisConfig {
+    pub fn load(path: &Path) -> Result<Self, GenesisError> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), GenesisError> {
+        Ok(fs::write(path, serde_json::to_vec_pretty(self)?)?)
+    }
+
+    // Without its state root, which is only known once `alloc` is staged
+    // into the trie. Equal to the full block when nothing is allocated.
+    pub fn block(&self) -> Block {
+        let mut block = Block::new([0; 32], vec![], self.difficulty).expect("an empty block is valid");
+        block.header.timestamp = self.timestamp;
+        block
+    }
+
+    // Imports the genesis block and its allocations into an empty store. A
+    // store that already has one is left alone, as long as it is this one.
+    pub async fn initialize(&self, store: &ChainStore) -> Result<ChainHead, GenesisError> {
+        let changes: Vec<_> = self
+            .alloc
+            .iter()
+            .map(|account| {
+                let balance = bincode::serialize(&account.balance).expect("balances serialize");
+                (balance_key(&account.address), Some(balance))
+            })
+            .collect();
+        let mut state = StorageTransaction::new();
+        let mut block = self.block();
+        block.header.state_root =
+            StateTrie::new(store.database().clone()).stage(&EMPTY_ROOT, &changes, &mut state).await?;
+
+        let expected = block.hash();
+        match store.header(0).await? {
+            Some(header) => {
+                let existing = Block { header, transactions: vec![] }.hash();
+                if existing != expected {
+                    return Err(GenesisError::Mismatch { existing, expected });
+                }
+                Ok(ChainHead { height: 0, hash: existing })
+            }
+            // The allocations, block 0 and the head land in one write, so a
+            // node killed while initializing has all or none of them
+            None => {
+                state.append(store.stage_block(0, &block, &[], &[])?);
+                store.database().commit_durable(state).await?;
+                Ok(ChainHead { height: 0, hash: expected })
+            }
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::history::StateHistory;
+    use crate::storage::db::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_initializes_once_and_rejects_another_genesis() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = ChainStore::new(Arc::new(Database::new(temp_dir.path().join("db")).unwrap()));
+        let genesis = GenesisConfig::default();
+        assert_eq!(genesis.block().hash(), genesis.block().hash());
+
+        let head = genesis.initialize(&store).await.unwrap();
+        assert_eq!(head, ChainHead { height: 0, hash: genesis.block().hash() });
+        assert_eq!(genesis.initialize(&store).await.unwrap(), head);
+
+        let testnet = GenesisConfig { timestamp: 1_700_000_000, ..GenesisConfig::default() };
+        assert!(matches!(testnet.initialize(&store).await, Err(GenesisError::Mismatch { .. })));
+
+        let path = temp_dir.path().join("genesis.json");
+        testnet.save(&path).unwrap();
+        assert_eq!(GenesisConfig::load(&path).unwrap(), testnet);
+
+        let funded = GenesisAccount { address: Address::random(), balance: Balance::from(500u64) };
+        let dev = GenesisConfig { alloc: vec![funded.clone()], ..GenesisConfig::default() };
+        let store = Arc::new(ChainStore::new(Arc::new(Database::new(temp_dir.path().join("dev")).unwrap())));
+        let head = dev.initialize(&store).await.unwrap();
+        assert_ne!(head.hash, genesis.block().hash());
+        let state = StateHistory::new(store, None);
+        assert_eq!(state.balance_at(&funded.address, 0).await.unwrap(), funded.balance);
+    }
+
+    #[tokio::test]
+    async fn test_initialize_killed_between_state_and_block_restarts_cleanly() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("db");
+        let funded = GenesisAccount { address: Address::random(), balance: Balance::from(500u64) };
+        let genesis = GenesisConfig { alloc: vec![funded.clone()], ..GenesisConfig::default() };
+
+        // What a kill after the allocations but before block 0 used to leave
+        {
+            let store = ChainStore::new(Arc::new(Database::new(&path).unwrap()));
+            let changes = vec![(balance_key(&funded.address), Some(bincode::serialize(&funded.balance).unwrap()))];
+            let mut state = StorageTransaction::new();
+            StateTrie::new(store.database().clone()).stage(&EMPTY_ROOT, &changes, &mut state).await.unwrap();
+            store.database().commit(state).await.unwrap();
+            assert!(store.head().await.unwrap().is_none());
+        }
+
+        let store = Arc::new(ChainStore::new(Arc::new(Database::new(&path).unwrap())));
+        let head = genesis.initialize(&store).await.unwrap();
+        // Block 0 carries the root of the allocations, so initializing again
+        // finds the same genesis
+        assert_eq!(genesis.initialize(&store).await.unwrap(), head);
+        assert_eq!(store.head().await.unwrap(), Some(head));
+        let state = StateHistory::new(store, None);
+        assert_eq!(state.balance_at(&funded.address, 0).await.unwrap(), funded.balance);
+    }
+}
@@ -0,0 +1,476 @@
+use parity_scale_codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::params::{ChainParams, ParamsError};
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::storage::transaction::StorageTransaction;
+use crate::types::{Address, Balance, BlockHeight};
+
+pub type ProposalId = u64;
+
+// Part of the chain spec, so every node tallies the same way
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GovernanceParams {
+    // Deposits a proposal needs before it is put to a vote
+    pub min_deposit: Balance,
+    // Blocks a proposal has to collect its deposit
+    pub deposit_period: u64,
+    pub voting_period: u64,
+    // Share of all stake that has to vote, in basis points
+    pub quorum_bps: u32,
+    // Share of Yes among the non-abstaining votes needed to pass, in basis points
+    pub threshold_bps: u32,
+    // Share of NoWithVeto among all votes that rejects the proposal and burns
+    // its deposits, in basis points
+    pub veto_bps: u32,
+}
+
+impl Default for GovernanceParams {
+    fn default() -> Self {
+        Self {
+            min_deposit: Balance::from(10_000u64),
+            deposit_period: 20_160,
+            voting_period: 20_160,
+            quorum_bps: 3_340,
+            threshold_bps: 5_000,
+            veto_bps: 3_340,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParamChange {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProposalKind {
+    // Only records the outcome
+    Text,
+    // Applied together from `height`, see chain::params
+    ParameterChange { changes: Vec<ParamChange>, height: BlockHeight },
+    // Nodes stop at `height` unless they can run it, see chain::upgrade
+    Upgrade { name: String, height: BlockHeight },
+    // Paid from the treasury when the proposal passes, see chain::treasury
+    TreasurySpend { recipient: Address, amount: Balance },
+}
+
+// Payload carried in `data` of SubmitProposal transactions. The
+// transaction's value is the proposer's initial deposit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposalSubmission {
+    pub title: String,
+    pub description: String,
+    pub kind: ProposalKind,
+}
+
+// Payload of DepositProposal transactions; the value is the deposit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposalDeposit {
+    pub proposal: ProposalId,
+}
+
+// Payload of Vote transactions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ballot {
+    pub proposal: ProposalId,
+    pub option: VoteOption,
+}
+
+// Also part of the governance system contract's ABI, see chain::system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub enum VoteOption {
+    Yes,
+    No,
+    Abstain,
+    NoWithVeto,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProposalStatus {
+    DepositPeriod { ends: BlockHeight },
+    VotingPeriod { ends: BlockHeight },
+    Passed,
+    Rejected,
+    Vetoed,
+    // The deposit period ended below the minimum deposit
+    Expired,
+}
+
+// Stake behind each option, counted when voting ends
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tally {
+    pub yes: Balance,
+    pub no: Balance,
+    pub abstain: Balance,
+    pub no_with_veto: Balance,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proposal {
+    pub id: ProposalId,
+    pub proposer: Address,
+    pub title: String,
+    pub description: String,
+    pub kind: ProposalKind,
+    pub status: ProposalStatus,
+    pub submitted_at: BlockHeight,
+    pub total_deposit: Balance,
+    pub deposits: Vec<(Address, Balance)>,
+    // Set once voting has ended
+    pub tally: Option<Tally>,
+}
+
+// What the executor has to act on after `end_block`. Deposits are held in
+// the governance account from the moment they are made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GovernanceEvent {
+    Passed { proposal: ProposalId, kind: ProposalKind },
+    Rejected { proposal: ProposalId },
+    Vetoed { proposal: ProposalId },
+    Expired { proposal: ProposalId },
+    Refunded { proposal: ProposalId, depositor: Address, amount: Balance },
+    Burned { proposal: ProposalId, amount: Balance },
+}
+
+#[derive(Debug, Error)]
+pub enum GovernanceError {
+    #[error("Proposal {0} not found")]
+    NotFound(ProposalId),
+    #[error("Proposal {0} is not collecting deposits")]
+    NotInDepositPeriod(ProposalId),
+    #[error("Proposal {0} is not open for voting")]
+    NotInVotingPeriod(ProposalId),
+    #[error("Proposal title must not be empty")]
+    EmptyTitle,
+    #[error("Invalid parameter change: {0}")]
+    InvalidParamChange(#[from] ParamsError),
+    #[error("Upgrade name must not be empty")]
+    EmptyUpgradeName,
+    #[error("Treasury spend must not be zero")]
+    EmptySpend,
+    #[error("Unexpected transaction type")]
+    UnexpectedTransactionType,
+    #[error("Malformed payload")]
+    MalformedPayload,
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// Proposals, their deposits and votes. Voting power is the voter's stake
+// when the voting period ends, so stake moved during a vote counts where it
+// ends up.
+pub struct Governance {
+    storage: Arc<dyn StorageBackend>,
+    params: GovernanceParams,
+}
+
+impl Governance {
+    pub fn new(storage: Arc<dyn StorageBackend>, params: GovernanceParams) -> Self {
+        Self { storage, params }
+    }
+
+    pub async fn apply_transaction(&mut self, tx: &Transaction, height: BlockHeight) -> Result<(), GovernanceError> {
+        match tx.transaction_type {
+            TransactionType::SubmitProposal => {
+                let submission: ProposalSubmission =
+                    bincode::deserialize(&tx.data).map_err(|_| GovernanceError::MalformedPayload)?;
+                self.submit(tx.from, submission, tx.value, height).await.map(|_| ())
+            }
+            TransactionType::DepositProposal => {
+                let deposit: ProposalDeposit =
+                    bincode::deserialize(&tx.data).map_err(|_| GovernanceError::MalformedPayload)?;
+                self.deposit(tx.from, deposit.proposal, tx.value, height).await
+            }
+            TransactionType::Vote => {
+                let ballot: Ballot = bincode::deserialize(&tx.data).map_err(|_| GovernanceError::MalformedPayload)?;
+                self.vote(tx.from, ballot.proposal, ballot.option, height).await
+            }
+            _ => Err(GovernanceError::UnexpectedTransactionType),
+        }
+    }
+
+    pub async fn submit(
+        &mut self,
+        proposer: Address,
+        submission: ProposalSubmission,
+        deposit: Balance,
+        height: BlockHeight,
+    ) -> Result<ProposalId, GovernanceError> {
+        if submission.title.trim().is_empty() {
+            return Err(GovernanceError::EmptyTitle);
+        }
+        match &submission.kind {
+            ProposalKind::ParameterChange { changes, .. } => ChainParams::validate(changes)?,
+            ProposalKind::Upgrade { name, .. } if name.trim().is_empty() => {
+                return Err(GovernanceError::EmptyUpgradeName)
+            }
+            ProposalKind::TreasurySpend { amount, .. } if amount.is_zero() => return Err(GovernanceError::EmptySpend),
+            _ => {}
+        }
+        let mut proposals = self.get_proposals().await?;
+        let id = proposals.keys().next_back().map_or(1, |last| last + 1);
+        let mut proposal = Proposal {
+            id,
+            proposer,
+            title: submission.title,
+            description: submission.description,
+            kind: submission.kind,
+            status: ProposalStatus::DepositPeriod { ends: height + self.params.deposit_period },
+            submitted_at: height,
+            total_deposit: Balance::zero(),
+            deposits: Vec::new(),
+            tally: None,
+        };
+        self.add_deposit(&mut proposal, proposer, deposit, height);
+        proposals.insert(id, proposal);
+        self.storage.put_value(b"governance_proposals", &proposals).await?;
+        Ok(id)
+    }
+
+    pub async fn deposit(
+        &mut self,
+        depositor: Address,
+        id: ProposalId,
+        amount: Balance,
+        height: BlockHeight,
+    ) -> Result<(), GovernanceError> {
+        let mut proposals = self.get_proposals().await?;
+        let proposal = proposals.get_mut(&id).ok_or(GovernanceError::NotFound(id))?;
+        match proposal.status {
+            ProposalStatus::DepositPeriod { ends } if height < ends => {}
+            _ => return Err(GovernanceError::NotInDepositPeriod(id)),
+        }
+        self.add_deposit(proposal, depositor, amount, height);
+        self.storage.put_value(b"governance_proposals", &proposals).await?;
+        Ok(())
+    }
+
+    // A later vote replaces the voter's earlier one
+    pub async fn vote(
+        &mut self,
+        voter: Address,
+        id: ProposalId,
+        option: VoteOption,
+        height: BlockHeight,
+    ) -> Result<(), GovernanceError> {
+        let proposal = self.proposal(id).await?.ok_or(GovernanceError::NotFound(id))?;
+        match proposal.status {
+            ProposalStatus::VotingPeriod { ends } if height < ends => {}
+            _ => return Err(GovernanceError::NotInVotingPeriod(id)),
+        }
+        let mut votes = self.get_votes().await?;
+        votes.entry(id).or_default().insert(voter, option);
+        self.storage.put_value(b"governance_votes", &votes).await?;
+        Ok(())
+    }
+
+    // Run once per block, after its transactions. Closes deposit and voting
+    // periods ending at `height`; `voting_power` is an address's stake and
+    // `total_stake` the stake of all, both at `height`.
+    pub async fn end_block<F>(
+        &mut self,
+        height: BlockHeight,
+        total_stake: Balance,
+        mut voting_power: F,
+    ) -> Result<Vec<GovernanceEvent>, GovernanceError>
+    where
+        F: FnMut(&Address) -> Balance,
+    {
+        let mut proposals = self.get_proposals().await?;
+        let mut votes = self.get_votes().await?;
+        let mut events = Vec::new();
+
+        for proposal in proposals.values_mut() {
+            let id = proposal.id;
+            match proposal.status {
+                ProposalStatus::DepositPeriod { ends } if height >= ends => {
+                    proposal.status = ProposalStatus::Expired;
+                    events.push(GovernanceEvent::Expired { proposal: id });
+                    if !proposal.total_deposit.is_zero() {
+                        events.push(GovernanceEvent::Burned { proposal: id, amount: proposal.total_deposit });
+                    }
+                }
+                ProposalStatus::VotingPeriod { ends } if height >= ends => {
+                    let mut tally = Tally::default();
+                    for (voter, option) in votes.remove(&id).unwrap_or_default() {
+                        let power = voting_power(&voter);
+                        match option {
+                            VoteOption::Yes => tally.yes += power,
+                            VoteOption::No => tally.no += power,
+                            VoteOption::Abstain => tally.abstain += power,
+                            VoteOption::NoWithVeto => tally.no_with_veto += power,
+                        }
+                    }
+                    proposal.status = self.outcome(&tally, total_stake);
+                    proposal.tally = Some(tally);
+
+                    events.push(match proposal.status {
+                        ProposalStatus::Passed => GovernanceEvent::Passed { proposal: id, kind: proposal.kind.clone() },
+                        ProposalStatus::Vetoed => GovernanceEvent::Vetoed { proposal: id },
+                        _ => GovernanceEvent::Rejected { proposal: id },
+                    });
+                    if proposal.status == ProposalStatus::Vetoed {
+                        events.push(GovernanceEvent::Burned { proposal: id, amount: proposal.total_deposit });
+                    } else {
+                        events.extend(proposal.deposits.iter().map(|(depositor, amount)| GovernanceEvent::Refunded {
+                            proposal: id,
+                            depositor: *depositor,
+                            amount: *amount,
+                        }));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut transaction = StorageTransaction::new();
+        transaction.put_value(b"governance_proposals", &proposals)?.put_value(b"governance_votes", &votes)?;
+        self.storage.write(transaction).await?;
+        Ok(events)
+    }
+
+    pub async fn proposal(&self, id: ProposalId) -> Result<Option<Proposal>, GovernanceError> {
+        Ok(self.get_proposals().await?.remove(&id))
+    }
+
+    // Every proposal, oldest first
+    pub async fn proposals(&self) -> Result<Vec<Proposal>, GovernanceError> {
+        Ok(self.get_proposals().await?.into_values().collect())
+    }
+
+    pub async fn votes(&self, id: ProposalId) -> Result<HashMap<Address, VoteOption>, GovernanceError> {
+        Ok(self.get_votes().await?.remove(&id).unwrap_or_default())
+    }
+
+    fn add_deposit(&self, proposal: &mut Proposal, depositor: Address, amount: Balance, height: BlockHeight) {
+        if amount.is_zero() {
+            return;
+        }
+        proposal.total_deposit += amount;
+        match proposal.deposits.iter_mut().find(|(address, _)| *address == depositor) {
+            Some((_, deposited)) => *deposited += amount,
+            None => proposal.deposits.push((depositor, amount)),
+        }
+        if proposal.total_deposit >= self.params.min_deposit {
+            proposal.status = ProposalStatus::VotingPeriod { ends: height + self.params.voting_period };
+        }
+    }
+
+    fn outcome(&self, tally: &Tally, total_stake: Balance) -> ProposalStatus {
+        let [yes, no, abstain, veto] = [tally.yes, tally.no, tally.abstain, tally.no_with_veto].map(u128::from);
+        let voted = yes + no + abstain + veto;
+        let above = |part: u128, of: u128, bps: u32| part * 10_000 > of * bps as u128;
+        if voted == 0 || voted * 10_000 < u128::from(total_stake) * self.params.quorum_bps as u128 {
+            ProposalStatus::Rejected
+        } else if above(veto, voted, self.params.veto_bps) {
+            ProposalStatus::Vetoed
+        } else if above(yes, yes + no + veto, self.params.threshold_bps) {
+            ProposalStatus::Passed
+        } else {
+            ProposalStatus::Rejected
+        }
+    }
+
+    async fn get_proposals(&self) -> Result<BTreeMap<ProposalId, Proposal>, GovernanceError> {
+        Ok(self.storage.get_value(b"governance_proposals").await?.unwrap_or_default())
+    }
+
+    async fn get_votes(&self) -> Result<HashMap<ProposalId, HashMap<Address, VoteOption>>, GovernanceError> {
+        Ok(self.storage.get_value(b"governance_votes").await?.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    fn submission(title: &str) -> ProposalSubmission {
+        ProposalSubmission { title: title.to_string(), description: String::new(), kind: ProposalKind::Text }
+    }
+
+    #[tokio::test]
+    async fn test_deposits_open_voting_and_stake_decides_the_outcome() {
+        let params = GovernanceParams {
+            min_deposit: Balance::from(100u64),
+            deposit_period: 10,
+            voting_period: 10,
+            ..GovernanceParams::default()
+        };
+        let mut governance = Governance::new(Arc::new(MemoryBackend::new()), params);
+        let (alice, bob, carol) = (Address::random(), Address::random(), Address::random());
+        let height = |height: u64| BlockHeight::from(height);
+        let stake = move |address: &Address| {
+            Balance::from(if *address == alice {
+                600u64
+            } else if *address == bob {
+                300
+            } else {
+                100
+            })
+        };
+
+        let passing =
+            governance.submit(alice, submission("raise min stake"), Balance::from(40u64), height(1)).await.unwrap();
+        let vetoed = governance.submit(bob, submission("spam"), Balance::from(100u64), height(1)).await.unwrap();
+        let expiring =
+            governance.submit(carol, submission("nobody cares"), Balance::from(5u64), height(1)).await.unwrap();
+        assert!(matches!(
+            governance.vote(bob, passing, VoteOption::Yes, height(2)).await,
+            Err(GovernanceError::NotInVotingPeriod(_))
+        ));
+        governance.deposit(bob, passing, Balance::from(60u64), height(2)).await.unwrap();
+        assert_eq!(
+            governance.proposal(passing).await.unwrap().unwrap().status,
+            ProposalStatus::VotingPeriod { ends: height(12) }
+        );
+
+        governance.vote(alice, passing, VoteOption::No, height(3)).await.unwrap();
+        governance.vote(alice, passing, VoteOption::Yes, height(4)).await.unwrap();
+        governance.vote(bob, passing, VoteOption::No, height(4)).await.unwrap();
+        governance.vote(alice, vetoed, VoteOption::NoWithVeto, height(4)).await.unwrap();
+        governance.vote(bob, vetoed, VoteOption::Yes, height(4)).await.unwrap();
+
+        let events = governance.end_block(height(11), Balance::from(1_000u64), stake).await.unwrap();
+        assert_eq!(
+            events,
+            vec![
+                GovernanceEvent::Vetoed { proposal: vetoed },
+                GovernanceEvent::Burned { proposal: vetoed, amount: Balance::from(100u64) },
+                GovernanceEvent::Expired { proposal: expiring },
+                GovernanceEvent::Burned { proposal: expiring, amount: Balance::from(5u64) },
+            ]
+        );
+        let events = governance.end_block(height(12), Balance::from(1_000u64), stake).await.unwrap();
+        assert_eq!(
+            events,
+            vec![
+                GovernanceEvent::Passed { proposal: passing, kind: ProposalKind::Text },
+                GovernanceEvent::Refunded { proposal: passing, depositor: alice, amount: Balance::from(40u64) },
+                GovernanceEvent::Refunded { proposal: passing, depositor: bob, amount: Balance::from(60u64) },
+            ]
+        );
+        let tally = governance.proposal(passing).await.unwrap().unwrap().tally.unwrap();
+        assert_eq!((tally.yes, tally.no), (Balance::from(600u64), Balance::from(300u64)));
+        assert!(governance.votes(passing).await.unwrap().is_empty());
+        assert!(matches!(
+            governance.vote(carol, passing, VoteOption::No, height(13)).await,
+            Err(GovernanceError::NotInVotingPeriod(_))
+        ));
+
+        // Under a third of the stake voting misses the quorum
+        let quiet = governance.submit(carol, submission("quiet"), Balance::from(100u64), height(20)).await.unwrap();
+        governance.vote(carol, quiet, VoteOption::Yes, height(21)).await.unwrap();
+        let events = governance.end_block(height(30), Balance::from(1_000u64), stake).await.unwrap();
+        assert_eq!(events[0], GovernanceEvent::Rejected { proposal: quiet });
+        assert_eq!(governance.proposals().await.unwrap().len(), 4);
+    }
+}
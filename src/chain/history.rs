@@ -0,0 +1,140 @@
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::store::{ChainStore, ChainStoreError};
+use crate::storage::db::DatabaseError;
+use crate::storage::trie::StateTrie;
+use crate::types::{Address, Balance};
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("Height {height} is above the head {head}")]
+    AboveHead { height: u64, head: u64 },
+    #[error("State at height {height} has been pruned, this node keeps it from height {oldest} on")]
+    Pruned { height: u64, oldest: u64 },
+    #[error("Malformed state value under {0}")]
+    Malformed(String),
+    #[error("Chain store error: {0}")]
+    Store(#[from] ChainStoreError),
+    #[error("Database error: {0}")]
+    Database(#[from] DatabaseError),
+}
+
+pub type Result<T> = std::result::Result<T, HistoryError>;
+
+// Trie keys of per-account state; values are bincode-encoded
+pub fn balance_key(address: &Address) -> Vec<u8> {
+    state_key("balance", address)
+}
+
+pub fn stake_key(address: &Address) -> Vec<u8> {
+    state_key("stake", address)
+}
+
+fn state_key(kind: &str, address: &Address) -> Vec<u8> {
+    let mut key = format!("{}:", kind).into_bytes();
+    key.extend(bincode::serialize(address).expect("addresses serialize"));
+    key
+}
+
+// Reads state as of any stored block, through the state root in its header.
+// Trie nodes are never overwritten, so this works for every height whose
+// nodes haven't been pruned: all of them on archive nodes, the last
+// `retained` blocks elsewhere. Heights outside that window are refused even
+// if their nodes happen to still be there, so answers don't depend on when
+// pruning last ran.
+pub struct StateHistory {
+    chain: Arc<ChainStore>,
+    trie: StateTrie,
+    // `None` keeps everything
+    retained: Option<u64>,
+}
+
+impl StateHistory {
+    pub fn new(chain: Arc<ChainStore>, retained: Option<u64>) -> Self {
+        let trie = StateTrie::new(chain.database().clone());
+        Self { chain, trie, retained }
+    }
+
+    pub async fn get(&self, height: u64, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let head = self.chain.head().await?.map_or(0, |head| head.height);
+        if height > head {
+            return Err(HistoryError::AboveHead { height, head });
+        }
+        let oldest = self.retained.map_or(0, |retained| head.saturating_sub(retained));
+        let pruned = HistoryError::Pruned { height, oldest };
+        if height < oldest {
+            return Err(pruned);
+        }
+        // Below a fast sync pivot neither the header nor the state was ever stored
+        let header = match self.chain.header(height).await? {
+            Some(header) => header,
+            None => return Err(pruned),
+        };
+        match self.trie.get(&header.state_root, key).await {
+            Ok(value) => Ok(value),
+            Err(DatabaseError::KeyNotFound(_)) => Err(pruned),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn balance_at(&self, address: &Address, height: u64) -> Result<Balance> {
+        self.decode(height, &balance_key(address)).await
+    }
+
+    pub async fn stake_at(&self, address: &Address, height: u64) -> Result<Balance> {
+        self.decode(height, &stake_key(address)).await
+    }
+
+    // Missing keys read as zero
+    async fn decode(&self, height: u64, key: &[u8]) -> Result<Balance> {
+        match self.get(height, key).await? {
+            Some(bytes) => {
+                bincode::deserialize(&bytes).map_err(|_| HistoryError::Malformed(String::from_utf8_lossy(key).into()))
+            }
+            None => Ok(Balance::from(0u64)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::block::Block;
+    use crate::storage::db::Database;
+    use crate::storage::transaction::StorageTransaction;
+    use crate::storage::trie::EMPTY_ROOT;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_reads_state_at_retained_heights() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path()).unwrap());
+        let chain = Arc::new(ChainStore::new(db.clone()));
+        let trie = StateTrie::new(db.clone());
+        let alice = Address::random();
+
+        let (mut root, mut parent) = (EMPTY_ROOT, [0; 32]);
+        for height in 0..4u64 {
+            let balance = Balance::from(height * 100);
+            let changes = vec![(balance_key(&alice), Some(bincode::serialize(&balance).unwrap()))];
+            let mut txn = StorageTransaction::new();
+            root = trie.stage(&root, &changes, &mut txn).await.unwrap();
+            db.commit(txn).await.unwrap();
+            let mut block = Block::new(parent, vec![], 1).unwrap();
+            block.header.state_root = root;
+            chain.import_block(height, &block, &[], &[]).await.unwrap();
+            parent = block.hash();
+        }
+
+        let archive = StateHistory::new(chain.clone(), None);
+        assert_eq!(archive.balance_at(&alice, 1).await.unwrap(), Balance::from(100u64));
+        assert_eq!(archive.balance_at(&alice, 3).await.unwrap(), Balance::from(300u64));
+        assert_eq!(archive.stake_at(&alice, 3).await.unwrap(), Balance::from(0u64));
+        assert!(matches!(archive.balance_at(&alice, 4).await, Err(HistoryError::AboveHead { height: 4, head: 3 })));
+
+        let full = StateHistory::new(chain, Some(2));
+        assert_eq!(full.balance_at(&alice, 1).await.unwrap(), Balance::from(100u64));
+        assert!(matches!(full.balance_at(&alice, 0).await, Err(HistoryError::Pruned { height: 0, oldest: 1 })));
+    }
+}
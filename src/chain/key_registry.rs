@@ -0,0 +1,343 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::chain::verify::{SignatureCheck, SignedMessage};
+use crate::crypto::scheme::{SchemeSignature, SignatureScheme};
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::types::{Address, BlockHeight};
+
+// Prefixed to what a new key signs to show its holder agreed to control the account
+pub const ROTATION_DOMAIN: &[u8] = b"omnitensor/key-rotation/v1";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRecord {
+    pub scheme: SignatureScheme,
+    pub public_key: Vec<u8>,
+    pub active_from: BlockHeight,
+    // Set once the key was rotated away; it signs nothing from then on
+    pub retired_at: Option<BlockHeight>,
+    // Declared stolen: signatures by the key from this height on are void,
+    // whether or not it was still in use
+    pub compromised_at: Option<BlockHeight>,
+}
+
+impl KeyRecord {
+    pub fn is_compromised(&self, height: BlockHeight) -> bool {
+        self.compromised_at.map_or(false, |compromised_at| compromised_at <= height)
+    }
+}
+
+// Payload of RotateKey transactions. Transactions are signed with ed25519,
+// so that is what an account rotates to; `proof` is the new key's signature
+// over `rotation_message`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRotation {
+    pub public_key: Vec<u8>,
+    pub proof: Vec<u8>,
+}
+
+// Payload of RevokeKey transactions. Revoking the key in use takes a
+// replacement, so the account and its stake stay reachable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRevocation {
+    pub public_key: Vec<u8>,
+    pub compromised_at: BlockHeight,
+    pub replacement: Option<KeyRotation>,
+}
+
+#[derive(Debug, Error)]
+pub enum KeyRegistryError {
+    #[error("Key does not control the account")]
+    NotController,
+    #[error("Key was revoked as compromised")]
+    Revoked,
+    #[error("Key never controlled the account")]
+    UnknownKey,
+    #[error("Key has controlled the account before")]
+    KeyReused,
+    #[error("Revoking the key in use requires a replacement")]
+    ReplacementRequired,
+    #[error("New key did not sign the rotation")]
+    InvalidProof,
+    #[error("Keys can't be revoked as of a future height")]
+    FutureRevocation,
+    #[error("Unexpected transaction type")]
+    UnexpectedTransactionType,
+    #[error("Malformed payload")]
+    MalformedPayload,
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// Which keys control each account. An account starts out controlled by the
+// key its address was derived from and has no entry here until it rotates
+// or revokes; from then on its history is kept, current key last.
+pub struct KeyRegistry {
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl KeyRegistry {
+    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
+        Self { storage }
+    }
+
+    pub async fn history(&self, address: &Address) -> Result<Vec<KeyRecord>, KeyRegistryError> {
+        Ok(self.storage.get_value(&account_key(address)).await?.unwrap_or_default())
+    }
+
+    // The histories of `senders` for `signature_check`, read up front so
+    // checking a batch doesn't go to storage per transaction. Accounts that
+    // never changed keys are left out.
+    pub async fn histories(
+        &self,
+        senders: impl IntoIterator<Item = Address>,
+    ) -> Result<HashMap<Address, Vec<KeyRecord>>, KeyRegistryError> {
+        let mut histories = HashMap::new();
+        for sender in senders {
+            let history = self.history(&sender).await?;
+            if !history.is_empty() {
+                histories.insert(sender, history);
+            }
+        }
+        Ok(histories)
+    }
+
+    // Whether `public_key` may sign for `address` at `height`
+    pub async fn check_signer(
+        &self,
+        address: &Address,
+        public_key: &[u8],
+        height: BlockHeight,
+    ) -> Result<(), KeyRegistryError> {
+        let history = self.history(address).await?;
+        let current = match history.last() {
+            Some(current) => current,
+            None => return derives(address, public_key).then_some(()).ok_or(KeyRegistryError::NotController),
+        };
+        match history.iter().find(|record| record.public_key == public_key) {
+            Some(record) if record.is_compromised(height) => Err(KeyRegistryError::Revoked),
+            Some(record) if record == current => Ok(()),
+            _ => Err(KeyRegistryError::NotController),
+        }
+    }
+
+    // Applies a RotateKey or RevokeKey transaction whose signature verified
+    // against `signer_key`
+    pub async fn apply_transaction(
+        &mut self,
+        tx: &Transaction,
+        signer_key: &[u8],
+        height: BlockHeight,
+    ) -> Result<(), KeyRegistryError> {
+        match tx.transaction_type {
+            TransactionType::RotateKey => {
+                let rotation = bincode::deserialize(&tx.data).map_err(|_| KeyRegistryError::MalformedPayload)?;
+                self.rotate(tx.from, signer_key, rotation, tx.nonce, height).await
+            }
+            TransactionType::RevokeKey => {
+                let revocation = bincode::deserialize(&tx.data).map_err(|_| KeyRegistryError::MalformedPayload)?;
+                self.revoke(tx.from, signer_key, revocation, tx.nonce, height).await
+            }
+            _ => Err(KeyRegistryError::UnexpectedTransactionType),
+        }
+    }
+
+    pub async fn rotate(
+        &mut self,
+        address: Address,
+        signer_key: &[u8],
+        rotation: KeyRotation,
+        nonce: u64,
+        height: BlockHeight,
+    ) -> Result<(), KeyRegistryError> {
+        self.check_signer(&address, signer_key, height).await?;
+        let mut history = self.history(&address).await?;
+        seed(&mut history, &address, signer_key);
+        push_rotation(&mut history, &address, rotation, nonce, height)?;
+        self.storage.put_value(&account_key(&address), &history).await?;
+        Ok(())
+    }
+
+    pub async fn revoke(
+        &mut self,
+        address: Address,
+        signer_key: &[u8],
+        revocation: KeyRevocation,
+        nonce: u64,
+        height: BlockHeight,
+    ) -> Result<(), KeyRegistryError> {
+        self.check_signer(&address, signer_key, height).await?;
+        if revocation.compromised_at > height {
+            return Err(KeyRegistryError::FutureRevocation);
+        }
+        let mut history = self.history(&address).await?;
+        seed(&mut history, &address, signer_key);
+        let index = history
+            .iter()
+            .position(|record| record.public_key == revocation.public_key)
+            .ok_or(KeyRegistryError::UnknownKey)?;
+        if index == history.len() - 1 {
+            let replacement = revocation.replacement.ok_or(KeyRegistryError::ReplacementRequired)?;
+            push_rotation(&mut history, &address, replacement, nonce, height)?;
+        }
+        let record = &mut history[index];
+        // The earliest declaration stands
+        record.compromised_at = match record.compromised_at {
+            Some(earlier) if earlier < revocation.compromised_at => Some(earlier),
+            _ => Some(revocation.compromised_at),
+        };
+        self.storage.put_value(&account_key(&address), &history).await?;
+        Ok(())
+    }
+}
+
+// Each account's history is its own record, so checking a signature reads
+// only the sender's
+fn account_key(address: &Address) -> Vec<u8> {
+    [b"account_keys/".as_slice(), address.as_ref()].concat()
+}
+
+// What the new key signs: the domain, the account and the nonce of the
+// transaction rotating to it, so the proof can't be replayed elsewhere
+pub fn rotation_message(address: &Address, nonce: u64) -> Vec<u8> {
+    [ROTATION_DOMAIN, address.as_ref(), &nonce.to_le_bytes()].concat()
+}
+
+// Honours rotations and revocations on top of how the node resolves the key
+// a transaction's signature must verify against: rotated accounts verify
+// against their current key, and nothing verifies under a revoked one
+pub fn signature_check(
+    histories: Arc<HashMap<Address, Vec<KeyRecord>>>,
+    height: BlockHeight,
+    resolve: SignatureCheck,
+) -> SignatureCheck {
+    Arc::new(move |tx: &Transaction| match histories.get(&tx.from).and_then(|history| history.last()) {
+        None => resolve(tx),
+        Some(current) if current.is_compromised(height) => None,
+        Some(current) => SignedMessage::of(tx, &current.public_key),
+    })
+}
+
+fn derives(address: &Address, public_key: &[u8]) -> bool {
+    let scheme = address.as_ref().first().and_then(|&tag| SignatureScheme::try_from(tag).ok());
+    scheme.and_then(|scheme| scheme.address(public_key)).map_or(false, |derived| derived[..] == *address.as_ref())
+}
+
+// Records the key the address was derived from before the first change
+fn seed(history: &mut Vec<KeyRecord>, address: &Address, original: &[u8]) {
+    if history.is_empty() {
+        let scheme = SignatureScheme::try_from(address.as_ref()[0]).unwrap_or(SignatureScheme::Ed25519);
+        history.push(KeyRecord {
+            scheme,
+            public_key: original.to_vec(),
+            active_from: BlockHeight::from(0),
+            retired_at: None,
+            compromised_at: None,
+        });
+    }
+}
+
+fn push_rotation(
+    history: &mut Vec<KeyRecord>,
+    address: &Address,
+    rotation: KeyRotation,
+    nonce: u64,
+    height: BlockHeight,
+) -> Result<(), KeyRegistryError> {
+    if history.iter().any(|record| record.public_key == rotation.public_key) {
+        return Err(KeyRegistryError::KeyReused);
+    }
+    let proof = rotation.proof.as_slice().try_into().map_err(|_| KeyRegistryError::InvalidProof)?;
+    if !SchemeSignature::Ed25519(proof).verify(&rotation_message(address, nonce), &rotation.public_key) {
+        return Err(KeyRegistryError::InvalidProof);
+    }
+    if let Some(current) = history.last_mut() {
+        current.retired_at = Some(height);
+    }
+    history.push(KeyRecord {
+        scheme: SignatureScheme::Ed25519,
+        public_key: rotation.public_key,
+        active_from: height,
+        retired_at: None,
+        compromised_at: None,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    fn key(seed: u8) -> Vec<u8> {
+        SignatureScheme::Ed25519.public_key(&[seed; 32]).unwrap()
+    }
+
+    fn rotation(address: &Address, seed: u8, nonce: u64) -> KeyRotation {
+        let proof = SchemeSignature::sign(SignatureScheme::Ed25519, &rotation_message(address, nonce), &[seed; 32]);
+        let SchemeSignature::Ed25519(proof) = proof.unwrap() else { unreachable!() };
+        KeyRotation { public_key: key(seed), proof: proof.to_vec() }
+    }
+
+    #[tokio::test]
+    async fn test_rotated_and_revoked_keys_stop_signing() {
+        let mut registry = KeyRegistry::new(Arc::new(MemoryBackend::new()));
+        let address = Address::from(SignatureScheme::Ed25519.address(&key(1)).unwrap());
+        let height = |height: u64| BlockHeight::from(height);
+        registry.check_signer(&address, &key(1), height(5)).await.unwrap();
+        assert!(matches!(
+            registry.check_signer(&address, &key(2), height(5)).await,
+            Err(KeyRegistryError::NotController)
+        ));
+
+        // The proof has to be for this account and nonce
+        let replayed = registry.rotate(address, &key(1), rotation(&address, 2, 3), 4, height(10)).await;
+        assert!(matches!(replayed, Err(KeyRegistryError::InvalidProof)));
+        registry.rotate(address, &key(1), rotation(&address, 2, 4), 4, height(10)).await.unwrap();
+        assert!(matches!(
+            registry.check_signer(&address, &key(1), height(11)).await,
+            Err(KeyRegistryError::NotController)
+        ));
+        registry.check_signer(&address, &key(2), height(11)).await.unwrap();
+
+        let stolen = KeyRevocation { public_key: key(2), compromised_at: height(12), replacement: None };
+        let unreplaced = registry.revoke(address, &key(2), stolen.clone(), 5, height(20)).await;
+        assert!(matches!(unreplaced, Err(KeyRegistryError::ReplacementRequired)));
+        let revocation = KeyRevocation { replacement: Some(rotation(&address, 3, 5)), ..stolen };
+        let tx = Transaction::new(
+            5,
+            address,
+            address,
+            0,
+            1,
+            50_000,
+            bincode::serialize(&revocation).unwrap(),
+            TransactionType::RevokeKey,
+        );
+        registry.apply_transaction(&tx, &key(2), height(20)).await.unwrap();
+
+        assert!(matches!(registry.check_signer(&address, &key(2), height(12)).await, Err(KeyRegistryError::Revoked)));
+        registry.check_signer(&address, &key(3), height(21)).await.unwrap();
+        let history = registry.history(&address).await.unwrap();
+        assert_eq!(
+            history.iter().map(|record| record.retired_at).collect::<Vec<_>>(),
+            [Some(height(10)), Some(height(20)), None]
+        );
+        assert!(history[1].is_compromised(height(12)) && !history[1].is_compromised(height(11)));
+        // Stored per account, not as one map of every account
+        assert_eq!(registry.storage.get_value::<Vec<KeyRecord>>(&account_key(&address)).await.unwrap(), Some(history));
+        assert!(registry.history(&Address::random()).await.unwrap().is_empty());
+
+        let histories = registry.histories([address, Address::random()]).await.unwrap();
+        assert_eq!(histories.len(), 1);
+        let check = signature_check(Arc::new(histories), height(21), Arc::new(|_: &Transaction| None));
+        assert!(check(&tx).is_some());
+
+        let reused = registry.rotate(address, &key(3), rotation(&address, 1, 6), 6, height(30)).await;
+        assert!(matches!(reused, Err(KeyRegistryError::KeyReused)));
+    }
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::governance::{GovernanceEvent, ParamChange, ProposalId, ProposalKind};
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::storage::transaction::StorageTransaction;
+use crate::types::{Balance, BlockHeight};
+
+// Parameters governance can change on a live chain. Genesis values come from
+// the chain spec; every later value from a passed proposal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainParams {
+    // Smallest stake a validator may bond
+    pub min_stake: Balance,
+    // Gas all transactions of a block may use together
+    pub block_gas_limit: u64,
+    // Share of inference results re-executed by verifiers, in basis points
+    pub inference_verification_bps: u32,
+    // Share of transaction fees paid into the treasury, in basis points
+    pub treasury_fee_bps: u32,
+    // Deposit held per byte of long-lived state, see chain::rent
+    pub storage_deposit_per_byte: Balance,
+    // Blocks an entry lives without being renewed
+    pub storage_lease_blocks: u64,
+    // Share of the deposit of an expired entry paid to the treasury, in basis points
+    pub storage_expiry_penalty_bps: u32,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self {
+            min_stake: Balance::from(1_000u64),
+            block_gas_limit: 30_000_000,
+            inference_verification_bps: 500,
+            treasury_fee_bps: 1_000,
+            storage_deposit_per_byte: Balance::from(10u64),
+            // About a year of 6-second blocks
+            storage_lease_blocks: 5_256_000,
+            storage_expiry_penalty_bps: 1_000,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParamsError {
+    #[error("Unknown parameter {0}")]
+    UnknownKey(String),
+    #[error("Invalid value {value:?} for {key}")]
+    InvalidValue { key: String, value: String },
+}
+
+impl ChainParams {
+    pub fn set(&mut self, change: &ParamChange) -> Result<(), ParamsError> {
+        let invalid = || ParamsError::InvalidValue { key: change.key.clone(), value: change.value.clone() };
+        let amount = || change.value.parse::<u64>().map(Balance::from).map_err(|_| invalid());
+        let positive = || change.value.parse::<u64>().ok().filter(|value| *value > 0).ok_or_else(invalid);
+        let bps = || change.value.parse::<u32>().ok().filter(|bps| *bps <= 10_000).ok_or_else(invalid);
+        match change.key.as_str() {
+            "min_stake" => self.min_stake = amount()?,
+            "block_gas_limit" => self.block_gas_limit = positive()?,
+            "inference_verification_bps" => self.inference_verification_bps = bps()?,
+            "treasury_fee_bps" => self.treasury_fee_bps = bps()?,
+            "storage_deposit_per_byte" => self.storage_deposit_per_byte = amount()?,
+            "storage_lease_blocks" => self.storage_lease_blocks = positive()?,
+            "storage_expiry_penalty_bps" => self.storage_expiry_penalty_bps = bps()?,
+            _ => return Err(ParamsError::UnknownKey(change.key.clone())),
+        }
+        Ok(())
+    }
+
+    // Checked when a proposal is submitted, so a passed one always applies
+    pub fn validate(changes: &[ParamChange]) -> Result<(), ParamsError> {
+        let mut params = Self::default();
+        changes.iter().try_for_each(|change| params.set(change))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledChange {
+    pub proposal: ProposalId,
+    pub height: BlockHeight,
+    pub changes: Vec<ParamChange>,
+}
+
+#[derive(Debug, Error)]
+pub enum ParamStoreError {
+    #[error("Invalid parameter change: {0}")]
+    Invalid(#[from] ParamsError),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// The parameters in force and the changes waiting for their height. Changes
+// due at the same height apply in the order their proposals passed.
+pub struct ParamStore {
+    storage: Arc<dyn StorageBackend>,
+    genesis: ChainParams,
+}
+
+impl ParamStore {
+    pub fn new(storage: Arc<dyn StorageBackend>, genesis: ChainParams) -> Self {
+        Self { storage, genesis }
+    }
+
+    pub async fn current(&self) -> Result<ChainParams, ParamStoreError> {
+        Ok(self.storage.get_value(b"chain_params").await?.unwrap_or_else(|| self.genesis.clone()))
+    }
+
+    pub async fn scheduled(&self) -> Result<Vec<ScheduledChange>, ParamStoreError> {
+        Ok(self.storage.get_value(b"scheduled_param_changes").await?.unwrap_or_default())
+    }
+
+    // Queues the parameter changes of proposals that passed at `height`. One
+    // whose activation height has already gone by applies at the next block.
+    pub async fn schedule_passed(
+        &mut self,
+        events: &[GovernanceEvent],
+        height: BlockHeight,
+    ) -> Result<(), ParamStoreError> {
+        let mut scheduled = self.scheduled().await?;
+        for event in events {
+            if let GovernanceEvent::Passed { proposal, kind: ProposalKind::ParameterChange { changes, height: at } } =
+                event
+            {
+                ChainParams::validate(changes)?;
+                let activation = if *at > height { *at } else { height + 1 };
+                scheduled.push(ScheduledChange { proposal: *proposal, height: activation, changes: changes.clone() });
+            }
+        }
+        self.storage.put_value(b"scheduled_param_changes", &scheduled).await?;
+        Ok(())
+    }
+
+    // Run at the start of every block, before its transactions. Returns the
+    // changes that took effect.
+    pub async fn begin_block(&mut self, height: BlockHeight) -> Result<Vec<ScheduledChange>, ParamStoreError> {
+        let (due, pending): (Vec<_>, Vec<_>) =
+            self.scheduled().await?.into_iter().partition(|change| change.height <= height);
+        if due.is_empty() {
+            return Ok(due);
+        }
+        let mut params = self.current().await?;
+        for change in due.iter().flat_map(|scheduled| &scheduled.changes) {
+            params.set(change)?;
+        }
+        let mut transaction = StorageTransaction::new();
+        transaction.put_value(b"chain_params", &params)?.put_value(b"scheduled_param_changes", &pending)?;
+        self.storage.write(transaction).await?;
+        Ok(due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    fn change(key: &str, value: &str) -> ParamChange {
+        ParamChange { key: key.to_string(), value: value.to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_passed_changes_apply_at_their_height() {
+        assert_eq!(
+            ChainParams::validate(&[change("max_stake", "1")]),
+            Err(ParamsError::UnknownKey("max_stake".into()))
+        );
+        assert!(ChainParams::validate(&[change("inference_verification_bps", "10001")]).is_err());
+        assert!(ChainParams::validate(&[change("block_gas_limit", "0")]).is_err());
+
+        let mut store = ParamStore::new(Arc::new(MemoryBackend::new()), ChainParams::default());
+        let passed = |proposal, changes, at: u64| GovernanceEvent::Passed {
+            proposal,
+            kind: ProposalKind::ParameterChange { changes, height: BlockHeight::from(at) },
+        };
+        let events = vec![
+            passed(1, vec![change("min_stake", "5000"), change("block_gas_limit", "20000000")], 50),
+            passed(2, vec![change("min_stake", "7000")], 50),
+            passed(3, vec![change("inference_verification_bps", "1000")], 5),
+            GovernanceEvent::Rejected { proposal: 4 },
+        ];
+        store.schedule_passed(&events, BlockHeight::from(10)).await.unwrap();
+        assert_eq!(store.scheduled().await.unwrap().len(), 3);
+
+        let applied = store.begin_block(BlockHeight::from(11)).await.unwrap();
+        assert_eq!(applied.iter().map(|change| change.proposal).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(store.current().await.unwrap().inference_verification_bps, 1_000);
+        assert_eq!(store.current().await.unwrap().min_stake, ChainParams::default().min_stake);
+
+        assert!(store.begin_block(BlockHeight::from(49)).await.unwrap().is_empty());
+        assert_eq!(store.begin_block(BlockHeight::from(50)).await.unwrap().len(), 2);
+        let params = store.current().await.unwrap();
+        assert_eq!((params.min_stake, params.block_gas_limit), (Balance::from(7_000u64), 20_000_000));
+        assert!(store.scheduled().await.unwrap().is_empty());
+    }
+}
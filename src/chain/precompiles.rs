@@ -0,0 +1,236 @@
+use ark_bn254::{Bn254, G1Affine, G2Affine};
+use ark_ec::pairing::Pairing;
+use ark_ff::One;
+use ark_serialize::CanonicalDeserialize;
+use thiserror::Error;
+
+use crate::crypto::hasher::{HashAlgorithm, DIGEST_LEN};
+use crate::crypto::scheme::{SchemeSignature, ADDRESS_LEN};
+use crate::types::Address;
+
+// Fixed costs, charged before any work is done. Inputs of any length also
+// pay per 32-byte word.
+pub const WORD_GAS: u64 = 12;
+pub const SHA3_GAS: u64 = 60;
+pub const ED25519_VERIFY_GAS: u64 = 3_000;
+pub const ECDSA_RECOVER_GAS: u64 = 3_000;
+pub const PAIRING_GAS: u64 = 45_000;
+pub const PAIRING_PAIR_GAS: u64 = 34_000;
+pub const MATMUL_GAS: u64 = 2_000;
+pub const MATMUL_MAC_GAS: u64 = 2;
+// Keeps a single call's work bounded whatever the gas limit
+pub const MAX_MATMUL_MACS: u64 = 1 << 24;
+
+// arkworks compressed encodings, as for verifying keys and proofs
+const G1_LEN: usize = 32;
+const G2_LEN: usize = 64;
+const PAIR_LEN: usize = G1_LEN + G2_LEN;
+const MATMUL_HEADER_LEN: usize = 12;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PrecompileError {
+    #[error("Out of gas: required {required}, available {available}")]
+    OutOfGas { required: u64, available: u64 },
+    #[error("Malformed precompile input")]
+    MalformedInput,
+}
+
+// Native functions at fixed addresses `0x00..00NN`, run directly instead of
+// as contract code. Inputs and outputs:
+//   - Sha3: any bytes -> their SHA3-256
+//   - Ed25519Verify: public key (32), signature (64), message -> 1 or 0
+//   - EcdsaRecover: secp256k1 signature (64) and recovery id (1), message ->
+//     the signer's 21-byte address, or nothing if it doesn't recover
+//   - Bn254Pairing: (G1, G2) pairs -> 1 if the product of their pairings is
+//     the identity, else 0
+//   - MatmulCommit: m, k, n as u32, then an m x k and a k x n matrix of i32,
+//     row-major, all little-endian -> the SHA3-256 of m and n as u32 followed
+//     by their product as i64, which wraps on overflow
+// Verification failures are outputs, not errors; only malformed input and
+// running out of gas fail the call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precompile {
+    Sha3 = 1,
+    Ed25519Verify = 2,
+    EcdsaRecover = 3,
+    Bn254Pairing = 4,
+    MatmulCommit = 5,
+}
+
+impl Precompile {
+    pub const ALL: [Precompile; 5] = [
+        Precompile::Sha3,
+        Precompile::Ed25519Verify,
+        Precompile::EcdsaRecover,
+        Precompile::Bn254Pairing,
+        Precompile::MatmulCommit,
+    ];
+
+    pub fn address(self) -> Address {
+        let mut address = [0u8; ADDRESS_LEN];
+        address[ADDRESS_LEN - 1] = self as u8;
+        Address::from(address)
+    }
+
+    pub fn from_address(address: &Address) -> Option<Self> {
+        Self::ALL.into_iter().find(|precompile| precompile.address() == *address)
+    }
+
+    pub fn gas(self, input: &[u8]) -> Result<u64, PrecompileError> {
+        let words = (input.len() as u64 + 31) / 32;
+        match self {
+            Precompile::Sha3 => Ok(SHA3_GAS + WORD_GAS * words),
+            Precompile::Ed25519Verify => Ok(ED25519_VERIFY_GAS + WORD_GAS * words),
+            Precompile::EcdsaRecover => Ok(ECDSA_RECOVER_GAS + WORD_GAS * words),
+            Precompile::Bn254Pairing => {
+                if input.len() % PAIR_LEN != 0 {
+                    return Err(PrecompileError::MalformedInput);
+                }
+                Ok(PAIRING_GAS + PAIRING_PAIR_GAS * (input.len() / PAIR_LEN) as u64)
+            }
+            Precompile::MatmulCommit => {
+                let (m, k, n) = matmul_dimensions(input)?;
+                // Every output cell is paid for, even with k = 0
+                let macs = m as u128 * n as u128 * k.max(1) as u128;
+                if macs > MAX_MATMUL_MACS as u128 {
+                    return Err(PrecompileError::MalformedInput);
+                }
+                Ok(MATMUL_GAS + MATMUL_MAC_GAS * macs as u64 + WORD_GAS * words)
+            }
+        }
+    }
+
+    // Runs the precompile and returns its output and the gas it used
+    pub fn execute(self, input: &[u8], gas_limit: u64) -> Result<(Vec<u8>, u64), PrecompileError> {
+        let gas = self.gas(input)?;
+        if gas > gas_limit {
+            return Err(PrecompileError::OutOfGas { required: gas, available: gas_limit });
+        }
+        let output = match self {
+            Precompile::Sha3 => HashAlgorithm::Sha3_256.digest(input).to_vec(),
+            Precompile::Ed25519Verify => {
+                if input.len() < 96 {
+                    return Err(PrecompileError::MalformedInput);
+                }
+                let signature = SchemeSignature::Ed25519(input[32..96].try_into().expect("64 bytes"));
+                vec![signature.verify(&input[96..], &input[..32]) as u8]
+            }
+            Precompile::EcdsaRecover => {
+                if input.len() < 65 {
+                    return Err(PrecompileError::MalformedInput);
+                }
+                let signature = SchemeSignature::Secp256k1 {
+                    signature: input[..64].try_into().expect("64 bytes"),
+                    recovery: input[64],
+                };
+                signature.signer(&input[65..], None).map(|address| address.to_vec()).unwrap_or_default()
+            }
+            Precompile::Bn254Pairing => pairing(input)?,
+            Precompile::MatmulCommit => matmul_commit(input)?,
+        };
+        Ok((output, gas))
+    }
+}
+
+fn pairing(input: &[u8]) -> Result<Vec<u8>, PrecompileError> {
+    let mut g1 = Vec::with_capacity(input.len() / PAIR_LEN);
+    let mut g2 = Vec::with_capacity(input.len() / PAIR_LEN);
+    for pair in input.chunks(PAIR_LEN) {
+        // Checks the points are on the curve and in the subgroup
+        g1.push(G1Affine::deserialize_compressed(&pair[..G1_LEN]).map_err(|_| PrecompileError::MalformedInput)?);
+        g2.push(G2Affine::deserialize_compressed(&pair[G1_LEN..]).map_err(|_| PrecompileError::MalformedInput)?);
+    }
+    Ok(vec![Bn254::multi_pairing(g1, g2).0.is_one() as u8])
+}
+
+fn matmul_dimensions(input: &[u8]) -> Result<(usize, usize, usize), PrecompileError> {
+    if input.len() < MATMUL_HEADER_LEN {
+        return Err(PrecompileError::MalformedInput);
+    }
+    let dimension = |at: usize| u32::from_le_bytes(input[at..at + 4].try_into().expect("4 bytes")) as usize;
+    let (m, k, n) = (dimension(0), dimension(4), dimension(8));
+    let bytes = (m as u64 * k as u64).checked_add(k as u64 * n as u64).and_then(|elements| elements.checked_mul(4));
+    if bytes != Some((input.len() - MATMUL_HEADER_LEN) as u64) {
+        return Err(PrecompileError::MalformedInput);
+    }
+    Ok((m, k, n))
+}
+
+fn matmul_commit(input: &[u8]) -> Result<Vec<u8>, PrecompileError> {
+    let (m, k, n) = matmul_dimensions(input)?;
+    let values: Vec<i64> = input[MATMUL_HEADER_LEN..]
+        .chunks(4)
+        .map(|bytes| i32::from_le_bytes(bytes.try_into().expect("4 bytes")) as i64)
+        .collect();
+    let (left, right) = values.split_at(m * k);
+
+    let mut hasher = HashAlgorithm::Sha3_256.hasher();
+    hasher.update((m as u32).to_le_bytes());
+    hasher.update((n as u32).to_le_bytes());
+    for row in 0..m {
+        for column in 0..n {
+            let cell = (0..k).fold(0i64, |sum, i| sum.wrapping_add(left[row * k + i] * right[i * n + column]));
+            hasher.update(cell.to_le_bytes());
+        }
+    }
+    let commitment: [u8; DIGEST_LEN] = hasher.finalize();
+    Ok(commitment.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::scheme::SignatureScheme;
+    use ark_ec::AffineRepr;
+    use ark_serialize::CanonicalSerialize;
+
+    #[test]
+    fn test_precompiles_compute_and_charge_fixed_gas() {
+        assert_eq!(Precompile::from_address(&Precompile::EcdsaRecover.address()), Some(Precompile::EcdsaRecover));
+        assert_eq!(Precompile::from_address(&Address::from([0xff; ADDRESS_LEN])), None);
+
+        let (hash, gas) = Precompile::Sha3.execute(b"omnitensor", 1_000).unwrap();
+        assert_eq!(hash, HashAlgorithm::Sha3_256.digest(b"omnitensor").to_vec());
+        assert_eq!(gas, SHA3_GAS + WORD_GAS);
+        assert_eq!(
+            Precompile::Sha3.execute(&[0; 64], SHA3_GAS),
+            Err(PrecompileError::OutOfGas { required: SHA3_GAS + 2 * WORD_GAS, available: SHA3_GAS })
+        );
+
+        let message = b"settle inference 42";
+        let public_key = SignatureScheme::Ed25519.public_key(&[3; 32]).unwrap();
+        let signature = SchemeSignature::sign(SignatureScheme::Ed25519, message, &[3; 32]).unwrap().to_bytes();
+        let mut input = [&public_key[..], &signature[1..], &message[..]].concat();
+        assert_eq!(Precompile::Ed25519Verify.execute(&input, 10_000).unwrap().0, vec![1]);
+        input[40] ^= 1;
+        assert_eq!(Precompile::Ed25519Verify.execute(&input, 10_000).unwrap().0, vec![0]);
+
+        let public_key = SignatureScheme::Secp256k1.public_key(&[5; 32]).unwrap();
+        let signature = SchemeSignature::sign(SignatureScheme::Secp256k1, message, &[5; 32]).unwrap().to_bytes();
+        let input = [&signature[1..], &message[..]].concat();
+        let (signer, _) = Precompile::EcdsaRecover.execute(&input, 10_000).unwrap();
+        assert_eq!(signer, SignatureScheme::Secp256k1.address(&public_key).unwrap().to_vec());
+
+        let mut input = Vec::new();
+        for g1 in [G1Affine::generator(), -G1Affine::generator()] {
+            g1.serialize_compressed(&mut input).unwrap();
+            G2Affine::generator().serialize_compressed(&mut input).unwrap();
+        }
+        let (output, gas) = Precompile::Bn254Pairing.execute(&input, 1_000_000).unwrap();
+        assert_eq!((output, gas), (vec![1], PAIRING_GAS + 2 * PAIRING_PAIR_GAS));
+        input.truncate(PAIR_LEN);
+        assert_eq!(Precompile::Bn254Pairing.execute(&input, 1_000_000).unwrap().0, vec![0]);
+
+        let matrices: Vec<u8> = [2u32, 2, 2]
+            .iter()
+            .flat_map(|dimension| dimension.to_le_bytes())
+            .chain([1i32, 2, 3, 4, 5, 6, 7, 8].iter().flat_map(|value| value.to_le_bytes()))
+            .collect();
+        let mut expected = [2u32.to_le_bytes(), 2u32.to_le_bytes()].concat();
+        expected.extend([19i64, 22, 43, 50].iter().flat_map(|value| value.to_le_bytes()));
+        let (commitment, gas) = Precompile::MatmulCommit.execute(&matrices, 10_000).unwrap();
+        assert_eq!(commitment, HashAlgorithm::Sha3_256.digest(&expected).to_vec());
+        assert_eq!(gas, MATMUL_GAS + 8 * MATMUL_MAC_GAS + 2 * WORD_GAS);
+        assert_eq!(Precompile::MatmulCommit.execute(&matrices[..40], 10_000), Err(PrecompileError::MalformedInput));
+    }
+}
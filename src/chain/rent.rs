@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::params::ChainParams;
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::types::{Address, Balance, BlockHeight};
+use crate::utils::decimal::Decimal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum StateKind {
+    Model,
+    Contract,
+    Dataset,
+}
+
+// A piece of long-lived state, e.g. a model listing by its address
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EntryId {
+    pub kind: StateKind,
+    pub id: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageDeposit {
+    pub owner: Address,
+    pub bytes: u64,
+    pub deposit: Balance,
+    pub expires_at: BlockHeight,
+}
+
+// Fund movements for the executor, between the owner and the deposit account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DepositChange {
+    Charged { owner: Address, amount: Balance },
+    Refunded { owner: Address, amount: Balance },
+}
+
+// An entry whose lease ran out. The module holding it archives it; the
+// owner gets the deposit back less the penalty, which goes to the treasury.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expired {
+    pub entry: EntryId,
+    pub owner: Address,
+    pub refund: Balance,
+    pub forfeited: Balance,
+}
+
+#[derive(Debug, Error)]
+pub enum RentError {
+    #[error("Entry already holds a deposit")]
+    AlreadyReserved,
+    #[error("No deposit for entry")]
+    NotReserved,
+    #[error("Only the owner may renew an entry")]
+    NotOwner,
+    #[error("Deposit for {0} bytes overflows")]
+    Overflow(u64),
+    #[error("Unexpected transaction type")]
+    UnexpectedTransactionType,
+    #[error("Malformed payload")]
+    MalformedPayload,
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// Storage deposits for models, contracts and datasets. Creating an entry
+// locks a deposit proportional to its size, refunded when it is deleted.
+// Entries live for a lease that their owner renews with a RenewStorage
+// transaction; those left to lapse expire, so abandoned state doesn't stay
+// on every full node forever.
+pub struct StorageDeposits {
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl StorageDeposits {
+    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
+        Self { storage }
+    }
+
+    pub async fn apply_transaction(
+        &mut self,
+        tx: &Transaction,
+        height: BlockHeight,
+        params: &ChainParams,
+    ) -> Result<(), RentError> {
+        match tx.transaction_type {
+            TransactionType::RenewStorage => {
+                let entry: EntryId = bincode::deserialize(&tx.data).map_err(|_| RentError::MalformedPayload)?;
+                self.renew(&entry, tx.from, height, params).await.map(|_| ())
+            }
+            _ => Err(RentError::UnexpectedTransactionType),
+        }
+    }
+
+    pub async fn deposit(&self, entry: &EntryId) -> Result<Option<StorageDeposit>, RentError> {
+        Ok(self.get_deposits().await?.remove(entry))
+    }
+
+    // Called when an entry is created
+    pub async fn reserve(
+        &mut self,
+        entry: EntryId,
+        owner: Address,
+        bytes: u64,
+        height: BlockHeight,
+        params: &ChainParams,
+    ) -> Result<DepositChange, RentError> {
+        let mut deposits = self.get_deposits().await?;
+        if deposits.contains_key(&entry) {
+            return Err(RentError::AlreadyReserved);
+        }
+        let deposit = required(bytes, params)?;
+        let expires_at = height + params.storage_lease_blocks;
+        deposits.insert(entry, StorageDeposit { owner, bytes, deposit, expires_at });
+        self.storage.put_value(b"storage_deposits", &deposits).await?;
+        Ok(DepositChange::Charged { owner, amount: deposit })
+    }
+
+    // Called when an entry changes size; the deposit follows it up or down
+    pub async fn resize(
+        &mut self,
+        entry: &EntryId,
+        bytes: u64,
+        params: &ChainParams,
+    ) -> Result<DepositChange, RentError> {
+        let mut deposits = self.get_deposits().await?;
+        let held = deposits.get_mut(entry).ok_or(RentError::NotReserved)?;
+        let deposit = required(bytes, params)?;
+        let change = if deposit >= held.deposit {
+            DepositChange::Charged { owner: held.owner, amount: deposit - held.deposit }
+        } else {
+            DepositChange::Refunded { owner: held.owner, amount: held.deposit - deposit }
+        };
+        held.bytes = bytes;
+        held.deposit = deposit;
+        self.storage.put_value(b"storage_deposits", &deposits).await?;
+        Ok(change)
+    }
+
+    // Called when an entry is deleted
+    pub async fn release(&mut self, entry: &EntryId) -> Result<DepositChange, RentError> {
+        let mut deposits = self.get_deposits().await?;
+        let held = deposits.remove(entry).ok_or(RentError::NotReserved)?;
+        self.storage.put_value(b"storage_deposits", &deposits).await?;
+        Ok(DepositChange::Refunded { owner: held.owner, amount: held.deposit })
+    }
+
+    // Extends the lease to a full term from `height`
+    pub async fn renew(
+        &mut self,
+        entry: &EntryId,
+        owner: Address,
+        height: BlockHeight,
+        params: &ChainParams,
+    ) -> Result<BlockHeight, RentError> {
+        let mut deposits = self.get_deposits().await?;
+        let held = deposits.get_mut(entry).ok_or(RentError::NotReserved)?;
+        if held.owner != owner {
+            return Err(RentError::NotOwner);
+        }
+        held.expires_at = height + params.storage_lease_blocks;
+        let expires_at = held.expires_at;
+        self.storage.put_value(b"storage_deposits", &deposits).await?;
+        Ok(expires_at)
+    }
+
+    // Run once per block. Returns the entries whose lease ended at `height`,
+    // in entry order.
+    pub async fn end_block(&mut self, height: BlockHeight, params: &ChainParams) -> Result<Vec<Expired>, RentError> {
+        let mut deposits = self.get_deposits().await?;
+        let lapsed: Vec<EntryId> =
+            deposits.iter().filter(|(_, held)| held.expires_at <= height).map(|(entry, _)| entry.clone()).collect();
+        if lapsed.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let penalty = Decimal::from_bps(params.storage_expiry_penalty_bps);
+        let expired = lapsed
+            .into_iter()
+            .map(|entry| {
+                let held = deposits.remove(&entry).expect("just found");
+                let forfeited = penalty.share_of(held.deposit);
+                Expired { entry, owner: held.owner, refund: held.deposit - forfeited, forfeited }
+            })
+            .collect();
+        self.storage.put_value(b"storage_deposits", &deposits).await?;
+        Ok(expired)
+    }
+
+    async fn get_deposits(&self) -> Result<BTreeMap<EntryId, StorageDeposit>, RentError> {
+        Ok(self.storage.get_value(b"storage_deposits").await?.unwrap_or_default())
+    }
+}
+
+fn required(bytes: u64, params: &ChainParams) -> Result<Balance, RentError> {
+    u128::from(params.storage_deposit_per_byte)
+        .checked_mul(bytes as u128)
+        .and_then(|deposit| Balance::try_from(deposit).ok())
+        .ok_or(RentError::Overflow(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    #[tokio::test]
+    async fn test_deposits_follow_size_and_lapsed_entries_expire() {
+        let params = ChainParams {
+            storage_deposit_per_byte: Balance::from(2u64),
+            storage_lease_blocks: 100,
+            storage_expiry_penalty_bps: 1_000,
+            ..ChainParams::default()
+        };
+        let mut deposits = StorageDeposits::new(Arc::new(MemoryBackend::new()));
+        let (owner, other) = (Address::random(), Address::random());
+        let model = EntryId { kind: StateKind::Model, id: vec![1] };
+        let dataset = EntryId { kind: StateKind::Dataset, id: vec![2] };
+        let height = |height: u64| BlockHeight::from(height);
+
+        let charged = deposits.reserve(model.clone(), owner, 500, height(1), &params).await.unwrap();
+        assert_eq!(charged, DepositChange::Charged { owner, amount: Balance::from(1_000u64) });
+        assert!(matches!(
+            deposits.reserve(model.clone(), owner, 1, height(1), &params).await,
+            Err(RentError::AlreadyReserved)
+        ));
+        deposits.reserve(dataset.clone(), owner, 50, height(1), &params).await.unwrap();
+        assert_eq!(
+            deposits.resize(&model, 200, &params).await.unwrap(),
+            DepositChange::Refunded { owner, amount: Balance::from(600u64) }
+        );
+
+        assert!(matches!(deposits.renew(&model, other, height(90), &params).await, Err(RentError::NotOwner)));
+        assert_eq!(deposits.renew(&model, owner, height(90), &params).await.unwrap(), height(190));
+        let expired = deposits.end_block(height(100), &params).await.unwrap();
+        assert_eq!((expired.len(), &expired[0].entry), (1, &dataset));
+        assert_eq!(deposits.deposit(&dataset).await.unwrap(), None);
+
+        let expired = deposits.end_block(height(190), &params).await.unwrap();
+        assert_eq!(
+            expired,
+            vec![Expired { entry: model, owner, refund: Balance::from(360u64), forfeited: Balance::from(40u64) }]
+        );
+        assert!(matches!(deposits.release(&dataset).await, Err(RentError::NotReserved)));
+    }
+}
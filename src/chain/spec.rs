@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+use crate::chain::circuit_breaker::{BreakerError, GuardianConfig};
+use crate::chain::genesis::GenesisConfig;
+use crate::chain::governance::GovernanceParams;
+use crate::chain::params::ChainParams;
+use crate::network::p2p::NetworkConfig;
+use crate::node::dev;
+use crate::types::Balance;
+
+#[derive(Debug, Error)]
+pub enum ChainSpecError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed chain spec: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("Unknown chain {0}; expected mainnet, testnet, dev or a spec file")]
+    Unknown(String),
+    #[error("Invalid guardians: {0}")]
+    Guardians(BreakerError),
+}
+
+// Consensus parameters every validator of a chain must agree on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConsensusParams {
+    pub validator_count: usize,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self { validator_count: 21 }
+    }
+}
+
+// What makes a network: `--chain mainnet|testnet|dev`, or a spec file in
+// this shape for any other network
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainSpec {
+    pub name: String,
+    pub chain_id: u64,
+    pub genesis: GenesisConfig,
+    #[serde(default)]
+    pub bootstrap_peers: Vec<String>,
+    #[serde(default)]
+    pub dns_seeds: Vec<String>,
+    #[serde(default)]
+    pub consensus: ConsensusParams,
+    #[serde(default)]
+    pub governance: GovernanceParams,
+    // At genesis; governance changes them later
+    #[serde(default)]
+    pub params: ChainParams,
+    // Who may pause subsystems in an emergency; nobody by default
+    #[serde(default)]
+    pub guardians: GuardianConfig,
+}
+
+impl ChainSpec {
+    pub fn mainnet() -> Self {
+        Self {
+            name: "mainnet".to_string(),
+            chain_id: 1,
+            genesis: GenesisConfig::default(),
+            bootstrap_peers: vec![
+                "/dns4/node1.omnitensor.io/tcp/3030".to_string(),
+                "/dns4/node2.omnitensor.io/tcp/3030".to_string(),
+                "/dns4/node3.omnitensor.io/tcp/3030".to_string(),
+            ],
+            dns_seeds: vec!["seed.omnitensor.io".to_string()],
+            consensus: ConsensusParams::default(),
+            governance: GovernanceParams::default(),
+            params: ChainParams::default(),
+            guardians: GuardianConfig::default(),
+        }
+    }
+
+    pub fn testnet() -> Self {
+        Self {
+            name: "testnet".to_string(),
+            chain_id: 2,
+            genesis: GenesisConfig { timestamp: 1_700_000_000, ..GenesisConfig::default() },
+            bootstrap_peers: vec![
+                "/dns4/testnet-node1.omnitensor.io/tcp/3030".to_string(),
+                "/dns4/testnet-node2.omnitensor.io/tcp/3030".to_string(),
+            ],
+            dns_seeds: vec!["testnet-seed.omnitensor.io".to_string()],
+            consensus: ConsensusParams { validator_count: 7 },
+            governance: GovernanceParams { deposit_period: 2_880, voting_period: 2_880, ..GovernanceParams::default() },
+            params: ChainParams::default(),
+            guardians: GuardianConfig::default(),
+        }
+    }
+
+    // A single local validator, found by mDNS if at all, with the dev
+    // accounts funded
+    pub fn dev() -> Self {
+        Self {
+            name: "dev".to_string(),
+            chain_id: 1337,
+            genesis: GenesisConfig { timestamp: 0, difficulty: 1, alloc: dev::genesis_alloc() },
+            bootstrap_peers: Vec::new(),
+            dns_seeds: Vec::new(),
+            consensus: ConsensusParams { validator_count: 1 },
+            // Proposals settle within minutes
+            governance: GovernanceParams {
+                min_deposit: Balance::from(1u64),
+                deposit_period: 10,
+                voting_period: 20,
+                ..GovernanceParams::default()
+            },
+            params: ChainParams { min_stake: Balance::from(1u64), ..ChainParams::default() },
+            guardians: GuardianConfig::default(),
+        }
+    }
+
+    // A built-in chain by name, otherwise a spec file
+    pub fn load(chain: &str) -> Result<Self, ChainSpecError> {
+        match chain {
+            "mainnet" => Ok(Self::mainnet()),
+            "testnet" => Ok(Self::testnet()),
+            "dev" | "devnet" => Ok(Self::dev()),
+            path if Path::new(path).is_file() => {
+                let spec: Self = serde_json::from_slice(&fs::read(path)?)?;
+                spec.guardians.validate().map_err(ChainSpecError::Guardians)?;
+                Ok(spec)
+            }
+            _ => Err(ChainSpecError::Unknown(chain.to_string())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ChainSpecError> {
+        Ok(fs::write(path, serde_json::to_vec_pretty(self)?)?)
+    }
+
+    // Sets the chain id and puts the spec's bootstrap peers and DNS seeds
+    // ahead of the configured ones, which only add to them
+    pub fn apply(&self, network: &mut NetworkConfig) {
+        network.chain_id = self.chain_id;
+        prepend(&mut network.bootstrap_peers, &self.bootstrap_peers);
+        prepend(&mut network.dns_seeds, &self.dns_seeds);
+    }
+}
+
+fn prepend(configured: &mut Vec<String>, from_spec: &[String]) {
+    let extra: Vec<String> = configured.drain(..).filter(|entry| !from_spec.contains(entry)).collect();
+    configured.extend(from_spec.iter().cloned());
+    configured.extend(extra);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Address;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_loads_built_in_and_custom_specs() {
+        assert_eq!(ChainSpec::load("testnet").unwrap(), ChainSpec::testnet());
+        assert_eq!(ChainSpec::load("devnet").unwrap().chain_id, 1337);
+        assert_eq!(ChainSpec::dev().genesis.alloc.len(), dev::DEV_ACCOUNTS as usize);
+        assert!(matches!(ChainSpec::load("moonnet"), Err(ChainSpecError::Unknown(_))));
+        assert_ne!(ChainSpec::mainnet().genesis.block().hash(), ChainSpec::testnet().genesis.block().hash());
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("spec.json");
+        std::fs::write(&path, r#"{"name": "staging", "chain_id": 90, "genesis": {"timestamp": 5}}"#).unwrap();
+        let staging = ChainSpec::load(path.to_str().unwrap()).unwrap();
+        assert_eq!((staging.chain_id, staging.genesis.timestamp), (90, 5));
+        assert_eq!(staging.consensus, ConsensusParams::default());
+        assert_eq!(staging.governance, GovernanceParams::default());
+        assert_eq!(staging.params, ChainParams::default());
+        assert!(staging.guardians.guardians.is_empty());
+        staging.save(&path).unwrap();
+        assert_eq!(ChainSpec::load(path.to_str().unwrap()).unwrap(), staging);
+
+        let guardians =
+            GuardianConfig { guardians: vec![Address::random()], threshold: 2, ..GuardianConfig::default() };
+        ChainSpec { guardians, ..staging.clone() }.save(&path).unwrap();
+        assert!(matches!(ChainSpec::load(path.to_str().unwrap()), Err(ChainSpecError::Guardians(_))));
+
+        let mut network = NetworkConfig {
+            bootstrap_peers: vec![
+                "/ip4/10.0.0.1/tcp/3030".to_string(),
+                "/dns4/testnet-node1.omnitensor.io/tcp/3030".to_string(),
+            ],
+            ..NetworkConfig::default()
+        };
+        ChainSpec::testnet().apply(&mut network);
+        assert_eq!(network.chain_id, 2);
+        assert_eq!(network.bootstrap_peers.len(), 3);
+        assert_eq!(network.bootstrap_peers[2], "/ip4/10.0.0.1/tcp/3030");
+    }
+}
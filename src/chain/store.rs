@@ -0,0 +1,399 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::bloom::LogBloom;
+use crate::chain::transaction::{Transaction, TransactionHash, TransactionReceipt};
+use crate::errors::TransactionError;
+use crate::network::clock::TimestampError;
+use crate::storage::ancient::{AncientError, AncientStore, ANCIENT_TABLES};
+use crate::storage::backend::StorageBackend;
+use crate::storage::columns::Column;
+use crate::storage::db::{Database, DatabaseError};
+use crate::storage::keys::{domain_key, height_suffix, Domain};
+use crate::storage::transaction::StorageTransaction;
+
+const HEAD_KEY: &str = "head";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainHead {
+    pub height: u64,
+    pub hash: [u8; 32],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionLocation {
+    pub height: u64,
+    pub index: u32,
+}
+
+// A single state write produced by executing a block; `None` deletes the key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateChange {
+    pub key: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Error)]
+pub enum ChainStoreError {
+    #[error("Block at height {height} does not extend head {head}")]
+    NonContiguous { head: u64, height: u64 },
+    #[error("Parent hash mismatch at height {0}")]
+    ParentMismatch(u64),
+    #[error("Transaction hashing failed: {0:?}")]
+    Transaction(TransactionError),
+    #[error("Invalid block timestamp: {0}")]
+    Timestamp(#[from] TimestampError),
+    #[error("Block {0} is missing from the database")]
+    MissingBlock(u64),
+    #[error("Height {0} is already in the ancient store")]
+    Frozen(u64),
+    #[error("Database error: {0}")]
+    Database(#[from] DatabaseError),
+    #[error("Ancient store error: {0}")]
+    Ancient(#[from] AncientError),
+}
+
+pub type Result<T> = std::result::Result<T, ChainStoreError>;
+
+pub fn height_key(height: u64) -> [u8; 8] {
+    height.to_be_bytes()
+}
+
+// Persists imported blocks. Everything a block touches (header, body, receipts,
+// lookup indexes, state and the head pointer) is staged into one
+// `StorageTransaction`, so a crash mid-import can never leave a torn block.
+// Old finalized blocks can be moved to an `AncientStore`; reads fall through to
+// it transparently.
+pub struct ChainStore {
+    db: Arc<Database>,
+    ancient: Option<Arc<AncientStore>>,
+}
+
+impl ChainStore {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db, ancient: None }
+    }
+
+    pub fn with_ancient(db: Arc<Database>, ancient: Arc<AncientStore>) -> Result<Self> {
+        if db.is_encrypted() {
+            return Err(AncientError::Encrypted.into());
+        }
+        Ok(Self { db, ancient: Some(ancient) })
+    }
+
+    pub fn database(&self) -> &Arc<Database> {
+        &self.db
+    }
+
+    pub async fn head(&self) -> Result<Option<ChainHead>> {
+        Ok(self.db.get_cf(Column::Consensus, &HEAD_KEY).await?)
+    }
+
+    pub async fn import_block(
+        &self,
+        height: u64,
+        block: &Block,
+        receipts: &[TransactionReceipt],
+        state_changes: &[StateChange],
+    ) -> Result<ChainHead> {
+        check_extends(self.head().await?, height, block)?;
+
+        let txn = self.stage_block(height, block, receipts, state_changes)?;
+        self.db.commit(txn).await?;
+
+        Ok(ChainHead { height, hash: block.hash() })
+    }
+
+    // Moves the head to a block taken on trust, e.g. the pivot of a fast sync
+    // whose state was downloaded rather than built by importing history. Only
+    // its header is stored; blocks below it may never be.
+    pub async fn start_at(&self, height: u64, header: &BlockHeader) -> Result<ChainHead> {
+        let head = ChainHead { height, hash: Block { header: header.clone(), transactions: vec![] }.hash() };
+        let index = bincode::serialize(&height).map_err(DatabaseError::from)?;
+        let mut txn = StorageTransaction::new();
+        txn.put(Column::Headers, &height_key(height), header)?
+            .put_raw(Column::Indexes, block_index_key(&head.hash), index)
+            .put(Column::Consensus, &HEAD_KEY, &head)?;
+        self.db.commit_durable(txn).await?;
+        Ok(head)
+    }
+
+    pub fn stage_block(
+        &self,
+        height: u64,
+        block: &Block,
+        receipts: &[TransactionReceipt],
+        state_changes: &[StateChange],
+    ) -> Result<StorageTransaction> {
+        let hash = block.hash();
+        let mut txn = StorageTransaction::new();
+
+        txn.put(Column::Headers, &height_key(height), &block.header)?
+            .put(Column::Bodies, &height_key(height), &block.transactions)?
+            .put(Column::Receipts, &height_key(height), &receipts)?
+            .put_raw(Column::Indexes, block_index_key(&hash), bincode::serialize(&height).map_err(DatabaseError::from)?)
+            .put_raw(Column::Indexes, bloom_key(height), LogBloom::from_receipts(receipts).as_bytes().to_vec());
+
+        for (index, tx) in block.transactions.iter().enumerate() {
+            let tx_hash = tx.hash().map_err(ChainStoreError::Transaction)?;
+            let location = TransactionLocation { height, index: index as u32 };
+            txn.put_raw(
+                Column::Indexes,
+                tx_index_key(&tx_hash),
+                bincode::serialize(&location).map_err(DatabaseError::from)?,
+            );
+        }
+
+        for change in state_changes {
+            match &change.value {
+                Some(value) => txn.put_raw(Column::State, change.key.clone(), value.clone()),
+                None => txn.delete_raw(Column::State, change.key.clone()),
+            };
+        }
+
+        txn.put(Column::Consensus, &HEAD_KEY, &ChainHead { height, hash })?;
+
+        Ok(txn)
+    }
+
+    pub async fn header(&self, height: u64) -> Result<Option<BlockHeader>> {
+        self.get_block_part(Column::Headers, height).await
+    }
+
+    pub async fn block(&self, height: u64) -> Result<Option<Block>> {
+        let header: Option<BlockHeader> = self.header(height).await?;
+        let transactions: Option<Vec<Transaction>> = self.get_block_part(Column::Bodies, height).await?;
+        Ok(match (header, transactions) {
+            (Some(header), Some(transactions)) => Some(Block { header, transactions }),
+            _ => None,
+        })
+    }
+
+    pub async fn receipts(&self, height: u64) -> Result<Option<Vec<TransactionReceipt>>> {
+        self.get_block_part(Column::Receipts, height).await
+    }
+
+    // Blooms stay in RocksDB when their block is frozen. Blocks imported
+    // before blooms were stored have none and have to be read in full.
+    pub async fn log_bloom(&self, height: u64) -> Result<Option<LogBloom>> {
+        let bytes = self.db.get_raw(Some(Column::Indexes), &bloom_key(height)).await?;
+        Ok(bytes.and_then(|bytes| LogBloom::from_bytes(&bytes)))
+    }
+
+    // Undecoded header, body or receipts, wherever they currently live
+    pub async fn block_part_raw(&self, column: Column, height: u64) -> Result<Option<Vec<u8>>> {
+        if let Some(bytes) = self.db.get_raw(Some(column), &height_key(height)).await? {
+            return Ok(Some(bytes));
+        }
+        match &self.ancient {
+            Some(ancient) => Ok(ancient.get(column, height)?),
+            None => Ok(None),
+        }
+    }
+
+    // Moves blocks more than `retain_blocks` behind the finalized height into the
+    // ancient store. They are fsynced there before being removed from RocksDB,
+    // so a crash in between only leaves a duplicate that the next run cleans up.
+    pub async fn freeze(&self, finalized_height: u64, retain_blocks: u64) -> Result<u64> {
+        let (ancient, head) = match (&self.ancient, self.head().await?) {
+            (Some(ancient), Some(head)) => (ancient, head),
+            _ => return Ok(0),
+        };
+        let limit = (finalized_height.min(head.height) + 1).saturating_sub(retain_blocks);
+        let start = ancient.frozen();
+
+        for height in start..limit {
+            let mut parts = Vec::with_capacity(ANCIENT_TABLES.len());
+            for column in ANCIENT_TABLES {
+                let part = self.db.get_raw(Some(column), &height_key(height)).await?;
+                parts.push(part.ok_or(ChainStoreError::MissingBlock(height))?);
+            }
+            ancient.append(height, &parts[0], &parts[1], &parts[2])?;
+        }
+        ancient.sync()?;
+
+        let frozen = ancient.frozen();
+        for column in ANCIENT_TABLES {
+            self.db.delete_range_cf(column, &height_key(0), &height_key(frozen)).await?;
+        }
+        Ok(frozen - start)
+    }
+
+    pub async fn height_of(&self, hash: [u8; 32]) -> Result<Option<u64>> {
+        self.get_index(block_index_key(&hash)).await
+    }
+
+    pub async fn transaction_location(&self, hash: &TransactionHash) -> Result<Option<TransactionLocation>> {
+        self.get_index(tx_index_key(hash)).await
+    }
+
+    // Drops every block from `from_height` up to the head and rewinds the head to
+    // `from_height - 1`, so sync re-fetches the range. Index entries are removed
+    // where the body can still be decoded.
+    pub async fn truncate(&self, from_height: u64) -> Result<Option<ChainHead>> {
+        let head = match self.head().await? {
+            Some(head) if head.height >= from_height => head,
+            head => return Ok(head),
+        };
+        if let Some(ancient) = &self.ancient {
+            if from_height < ancient.frozen() {
+                return Err(ChainStoreError::Frozen(from_height));
+            }
+        }
+
+        let mut txn = StorageTransaction::new();
+        for height in from_height..=head.height {
+            if let Ok(Some(header)) = self.header(height).await {
+                let hash = Block { header, transactions: vec![] }.hash();
+                txn.delete_raw(Column::Indexes, block_index_key(&hash));
+            }
+            if let Ok(Some(transactions)) = self.get_block_part::<Vec<Transaction>>(Column::Bodies, height).await {
+                for tx in transactions {
+                    if let Ok(tx_hash) = tx.hash() {
+                        txn.delete_raw(Column::Indexes, tx_index_key(&tx_hash));
+                    }
+                }
+            }
+            txn.delete_raw(Column::Indexes, bloom_key(height));
+            txn.delete(Column::Headers, &height_key(height))?
+                .delete(Column::Bodies, &height_key(height))?
+                .delete(Column::Receipts, &height_key(height))?;
+        }
+
+        let new_head = match from_height {
+            0 => None,
+            _ => {
+                let parent = self.header(from_height - 1).await?;
+                parent.map(|header| ChainHead { height: from_height - 1, hash: Block { header, transactions: vec![] }.hash() })
+            }
+        };
+        match new_head {
+            Some(new_head) => txn.put(Column::Consensus, &HEAD_KEY, &new_head)?,
+            None => txn.delete(Column::Consensus, &HEAD_KEY)?,
+        };
+        self.db.commit(txn).await?;
+        Ok(new_head)
+    }
+
+    async fn get_block_part<V: for<'de> Deserialize<'de>>(&self, column: Column, height: u64) -> Result<Option<V>> {
+        match self.block_part_raw(column, height).await? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes).map_err(DatabaseError::from)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_index<V: for<'de> Deserialize<'de>>(&self, key: Vec<u8>) -> Result<Option<V>> {
+        match self.db.get_raw(Some(Column::Indexes), &key).await? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes).map_err(DatabaseError::from)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+pub fn check_extends(head: Option<ChainHead>, height: u64, block: &Block) -> Result<()> {
+    if let Some(head) = head {
+        if height != head.height + 1 {
+            return Err(ChainStoreError::NonContiguous { head: head.height, height });
+        }
+        if block.header.prev_block_hash != head.hash {
+            return Err(ChainStoreError::ParentMismatch(height));
+        }
+    }
+    Ok(())
+}
+
+pub fn block_index_key(hash: &[u8; 32]) -> Vec<u8> {
+    domain_key(Domain::BlockByHash, hash, &[])
+}
+
+// Under one all-zero id, so the blooms of a height range are stored side by side
+pub fn bloom_key(height: u64) -> Vec<u8> {
+    domain_key(Domain::LogBlooms, &[0; 32], &height_suffix(height))
+}
+
+pub fn tx_index_key(hash: &TransactionHash) -> Vec<u8> {
+    let mut id = [0u8; 32];
+    id.copy_from_slice(hash.as_bytes());
+    domain_key(Domain::TransactionByHash, &id, &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_import_block_atomically() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap()));
+
+        let genesis = Block::new([0; 32], vec![], 1).unwrap();
+        let changes = vec![StateChange { key: b"balance".to_vec(), value: Some(vec![1]) }];
+        let head = store.import_block(0, &genesis, &[], &changes).await.unwrap();
+
+        assert_eq!(store.head().await.unwrap(), Some(head));
+        assert_eq!(store.height_of(genesis.hash()).await.unwrap(), Some(0));
+        assert!(store.block(0).await.unwrap().is_some());
+        assert_eq!(store.receipts(0).await.unwrap().map(|receipts| receipts.len()), Some(0));
+        assert_eq!(store.log_bloom(0).await.unwrap(), Some(LogBloom::default()));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_disconnected_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap()));
+
+        let genesis = Block::new([0; 32], vec![], 1).unwrap();
+        store.import_block(0, &genesis, &[], &[]).await.unwrap();
+
+        let orphan = Block::new([9; 32], vec![], 1).unwrap();
+        assert!(matches!(store.import_block(1, &orphan, &[], &[]).await, Err(ChainStoreError::ParentMismatch(1))));
+        let child = Block::new(genesis.hash(), vec![], 1).unwrap();
+        assert!(matches!(store.import_block(2, &child, &[], &[]).await, Err(ChainStoreError::NonContiguous { .. })));
+        assert!(store.import_block(1, &child, &[], &[]).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_starts_at_synced_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap()));
+
+        let pivot = Block::new([7; 32], vec![], 1).unwrap();
+        let head = store.start_at(500, &pivot.header).await.unwrap();
+        assert_eq!(store.head().await.unwrap(), Some(head));
+        assert_eq!(store.height_of(pivot.hash()).await.unwrap(), Some(500));
+        assert!(store.block(499).await.unwrap().is_none());
+
+        let child = Block::new(pivot.hash(), vec![], 1).unwrap();
+        assert!(store.import_block(501, &child, &[], &[]).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reads_fall_through_to_ancient_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("db")).unwrap());
+        let ancient = Arc::new(AncientStore::open(temp_dir.path().join("ancient")).unwrap());
+        let store = ChainStore::with_ancient(db.clone(), ancient.clone()).unwrap();
+
+        let mut parent = [0; 32];
+        for height in 0..10 {
+            let block = Block::new(parent, vec![], 1).unwrap();
+            store.import_block(height, &block, &[], &[]).await.unwrap();
+            parent = block.hash();
+        }
+
+        // Finalized up to 7, keep the last 3 finalized blocks hot
+        assert_eq!(store.freeze(7, 3).await.unwrap(), 5);
+        assert_eq!(ancient.frozen(), 5);
+        assert_eq!(db.get_raw(Some(Column::Headers), &height_key(4)).await.unwrap(), None);
+        assert!(db.get_raw(Some(Column::Headers), &height_key(5)).await.unwrap().is_some());
+
+        let block = store.block(4).await.unwrap().unwrap();
+        assert_eq!(store.height_of(block.hash()).await.unwrap(), Some(4));
+        assert_eq!(store.receipts(0).await.unwrap().map(|receipts| receipts.len()), Some(0));
+        assert_eq!(store.freeze(7, 3).await.unwrap(), 0);
+        assert!(matches!(store.truncate(3).await, Err(ChainStoreError::Frozen(3))));
+    }
+}
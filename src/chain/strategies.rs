@@ -0,0 +1,130 @@
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::canonical;
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::crypto::scheme::ADDRESS_LEN;
+use crate::types::{Address, Balance};
+
+// Proptest generators for chain types, for property tests across the crate
+
+pub fn address() -> impl Strategy<Value = Address> {
+    any::<[u8; ADDRESS_LEN]>().prop_map(Address::from)
+}
+
+// Every variant with a codec index, so new ones are covered without edits here
+pub fn transaction_type() -> impl Strategy<Value = TransactionType> {
+    any::<u8>().prop_filter_map("no variant at this index", |index| canonical::from_bytes(&[index]).ok())
+}
+
+pub fn transaction() -> impl Strategy<Value = Transaction> {
+    (
+        any::<u64>(),
+        address(),
+        address(),
+        any::<u64>(),
+        any::<u64>(),
+        any::<u64>(),
+        vec(any::<u8>(), 0..256),
+        transaction_type(),
+        any::<u64>(),
+        any::<bool>(),
+    )
+        .prop_map(|(nonce, from, to, value, gas_price, gas_limit, data, transaction_type, timestamp, signed)| {
+            let mut transaction = Transaction {
+                nonce,
+                from,
+                to,
+                value: Balance::from(value),
+                gas_price,
+                gas_limit,
+                data,
+                transaction_type,
+                timestamp,
+                signature: None,
+            };
+            if signed {
+                transaction.sign(&[7; 32]).expect("any 32 bytes are a key");
+            }
+            transaction
+        })
+}
+
+pub fn block_header() -> impl Strategy<Value = BlockHeader> {
+    (any::<u32>(), any::<[[u8; 32]; 4]>(), any::<i64>(), any::<u32>(), any::<u64>()).prop_map(
+        |(version, [prev_block_hash, merkle_root, state_root, receipts_root], timestamp, difficulty, nonce)| {
+            BlockHeader {
+                version,
+                prev_block_hash,
+                merkle_root,
+                state_root,
+                receipts_root,
+                timestamp,
+                difficulty,
+                nonce,
+            }
+        },
+    )
+}
+
+pub fn block() -> impl Strategy<Value = Block> {
+    (block_header(), vec(transaction(), 0..8)).prop_map(|(header, transactions)| Block { header, transactions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::codec::decode_bounded;
+    use crate::network::compression::{CompressionConfig, MessageCompressor, PROTOCOL_VERSION};
+
+    // Neither type is PartialEq; equal canonical bytes mean equal values
+    fn block_bytes(block: &Block) -> Vec<u8> {
+        let mut bytes = canonical::to_bytes(&block.header);
+        block.transactions.iter().for_each(|transaction| bytes.extend(canonical::to_bytes(transaction)));
+        bytes
+    }
+
+    proptest! {
+        #[test]
+        fn transactions_round_trip(transaction in transaction()) {
+            let encoded = canonical::to_bytes(&transaction);
+            let decoded: Transaction = canonical::from_bytes(&encoded).unwrap();
+            prop_assert_eq!(canonical::to_bytes(&decoded), encoded.clone());
+            // Any strict prefix is rejected rather than read as a shorter value
+            prop_assert!(canonical::from_bytes::<Transaction>(&encoded[..encoded.len() - 1]).is_err());
+
+            let gossiped: Transaction = decode_bounded(&bincode::serialize(&transaction).unwrap()).unwrap();
+            prop_assert_eq!(canonical::to_bytes(&gossiped), encoded);
+        }
+
+        #[test]
+        fn blocks_round_trip(block in block()) {
+            let header = canonical::to_bytes(&block.header);
+            prop_assert_eq!(canonical::to_bytes(&canonical::from_bytes::<BlockHeader>(&header).unwrap()), header);
+
+            let wire = bincode::serialize(&block).unwrap();
+            let decoded: Block = decode_bounded(&wire).unwrap();
+            prop_assert_eq!(block_bytes(&decoded), block_bytes(&block));
+
+            let compressor = MessageCompressor::new(CompressionConfig::default());
+            let framed = compressor.encode(PROTOCOL_VERSION, wire.clone()).unwrap();
+            prop_assert_eq!(compressor.decode(PROTOCOL_VERSION, &framed, wire.len()).unwrap(), wire);
+        }
+
+        // Peer input: decoding may fail but must not panic, and whatever
+        // decodes has exactly one encoding
+        #[test]
+        fn arbitrary_bytes_never_panic(bytes in vec(any::<u8>(), 0..1024)) {
+            if let Ok(transaction) = canonical::from_bytes::<Transaction>(&bytes) {
+                prop_assert_eq!(canonical::to_bytes(&transaction), bytes.clone());
+            }
+            let _ = decode_bounded::<Block>(&bytes);
+            let _ = decode_bounded::<Vec<Transaction>>(&bytes);
+            let compressor = MessageCompressor::new(CompressionConfig::default());
+            if let Ok(payload) = compressor.decode(PROTOCOL_VERSION, &bytes, 4096) {
+                prop_assert!(payload.len() <= 4096);
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use parity_scale_codec::{Decode, DecodeAll, Encode};
+use thiserror::Error;
+
+use crate::ai::invoke::{FailurePolicy, InvokeRequest};
+use crate::chain::governance::{Ballot, ProposalDeposit, ProposalId, VoteOption};
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::crypto::scheme::ADDRESS_LEN;
+use crate::types::{Address, Balance};
+
+// Charged per call, plus per byte of input, before it is decoded
+pub const SYSTEM_CALL_GAS: u64 = 5_000;
+pub const SYSTEM_CALL_BYTE_GAS: u64 = 16;
+pub const MAX_SYSTEM_CALL_INPUT: usize = 64 * 1024;
+pub const MAX_SYSTEM_CALLS: usize = 16;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SystemError {
+    #[error("No system contract at this address")]
+    UnknownContract,
+    #[error("Malformed system call input")]
+    MalformedInput,
+    #[error("Amount out of range")]
+    AmountOutOfRange,
+}
+
+// Native modules callable from contracts, at fixed addresses `0x00..01NN`
+// next to the precompiles. Contracts call them through the
+// `env.system_call` host function with a SCALE-encoded call of the
+// contract's ABI below.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemContract {
+    Staking = 1,
+    Governance = 2,
+    ModelRegistry = 3,
+}
+
+impl SystemContract {
+    pub const ALL: [SystemContract; 3] =
+        [SystemContract::Staking, SystemContract::Governance, SystemContract::ModelRegistry];
+
+    pub fn address(self) -> Address {
+        let mut address = [0u8; ADDRESS_LEN];
+        address[ADDRESS_LEN - 2] = 1;
+        address[ADDRESS_LEN - 1] = self as u8;
+        Address::from(address)
+    }
+
+    pub fn from_address(address: &Address) -> Option<Self> {
+        Self::ALL.into_iter().find(|contract| contract.address() == *address)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum StakingCall {
+    Stake { amount: u128 },
+    Unstake { amount: u128 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum GovernanceCall {
+    Deposit { proposal: ProposalId, amount: u128 },
+    Vote { proposal: ProposalId, option: VoteOption },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum ModelRegistryCall {
+    Invoke { model: [u8; ADDRESS_LEN], inputs: Vec<Vec<u8>>, fee: u128, best_effort: bool },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemCall {
+    Staking(StakingCall),
+    Governance(GovernanceCall),
+    ModelRegistry(ModelRegistryCall),
+}
+
+impl SystemCall {
+    pub fn decode(address: &Address, input: &[u8]) -> Result<Self, SystemError> {
+        let mut input = input;
+        let call = match SystemContract::from_address(address).ok_or(SystemError::UnknownContract)? {
+            SystemContract::Staking => StakingCall::decode_all(&mut input).map(SystemCall::Staking),
+            SystemContract::Governance => GovernanceCall::decode_all(&mut input).map(SystemCall::Governance),
+            SystemContract::ModelRegistry => ModelRegistryCall::decode_all(&mut input).map(SystemCall::ModelRegistry),
+        };
+        call.map_err(|_| SystemError::MalformedInput)
+    }
+
+    pub fn gas(input_len: usize) -> u64 {
+        SYSTEM_CALL_GAS + SYSTEM_CALL_BYTE_GAS * input_len as u64
+    }
+
+    // The call as the transaction its module already applies, sent by the
+    // calling contract. Such transactions are internal: already paid for in
+    // the contract's gas, and never signed or gossiped.
+    pub fn into_transaction(self, caller: Address) -> Result<Transaction, SystemError> {
+        let amount = |amount: u128| Balance::try_from(amount).map_err(|_| SystemError::AmountOutOfRange);
+        let (to, value, data, transaction_type) = match self {
+            SystemCall::Staking(StakingCall::Stake { amount: value }) => {
+                (SystemContract::Staking.address(), amount(value)?, Vec::new(), TransactionType::StakeDeposit)
+            }
+            SystemCall::Staking(StakingCall::Unstake { amount: value }) => {
+                (SystemContract::Staking.address(), amount(value)?, Vec::new(), TransactionType::StakeWithdraw)
+            }
+            SystemCall::Governance(GovernanceCall::Deposit { proposal, amount: value }) => (
+                SystemContract::Governance.address(),
+                amount(value)?,
+                payload(&ProposalDeposit { proposal }),
+                TransactionType::DepositProposal,
+            ),
+            SystemCall::Governance(GovernanceCall::Vote { proposal, option }) => (
+                SystemContract::Governance.address(),
+                Balance::zero(),
+                payload(&Ballot { proposal, option }),
+                TransactionType::Vote,
+            ),
+            SystemCall::ModelRegistry(ModelRegistryCall::Invoke { model, inputs, fee, best_effort }) => {
+                let failure_policy = if best_effort { FailurePolicy::BestEffort } else { FailurePolicy::AllOrNothing };
+                (
+                    Address::from(model),
+                    amount(fee)?,
+                    payload(&InvokeRequest { inputs, failure_policy }),
+                    TransactionType::AIModelInvoke,
+                )
+            }
+        };
+        Ok(Transaction {
+            nonce: 0,
+            from: caller,
+            to,
+            value,
+            gas_price: 0,
+            gas_limit: 0,
+            data,
+            transaction_type,
+            timestamp: 0,
+            signature: None,
+        })
+    }
+}
+
+fn payload<T: serde::Serialize>(payload: &T) -> Vec<u8> {
+    bincode::serialize(payload).expect("payload serialization cannot fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::wasm::{self, GasSchedule};
+
+    #[test]
+    fn test_contracts_call_system_contracts_through_their_abi() {
+        let vote = GovernanceCall::Vote { proposal: 7, option: VoteOption::NoWithVeto }.encode();
+        let governance = SystemContract::Governance.address();
+        assert_eq!(SystemContract::from_address(&governance), Some(SystemContract::Governance));
+        assert_eq!(
+            SystemCall::decode(&governance, &vote),
+            Ok(SystemCall::Governance(GovernanceCall::Vote { proposal: 7, option: VoteOption::NoWithVeto }))
+        );
+        assert_eq!(SystemCall::decode(&governance, &vote[..vote.len() - 1]), Err(SystemError::MalformedInput));
+        assert_eq!(SystemCall::decode(&SystemContract::Staking.address(), &vote), Err(SystemError::MalformedInput));
+        assert_eq!(SystemCall::decode(&Address::random(), &vote), Err(SystemError::UnknownContract));
+
+        // Stakes 5 from the contract: variant 0, then the amount as a little-endian u128
+        let staker = wat::parse_str(
+            r#"(module
+            (import "env" "system_call" (func $call (param i32 i32 i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 0) "\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\01\01")
+            (data (i32.const 32) "\00\05\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00")
+            (func (export "run") (call $call (i32.const 0) (i32.const 32) (i32.const 17)))
+            (func (export "garbage") (call $call (i32.const 0) (i32.const 32) (i32.const 3))))"#,
+        )
+        .unwrap();
+        let schedule = GasSchedule::default();
+        let code = wasm::instrument(&staker, &schedule).unwrap();
+        let contract = Address::random();
+        let execution = wasm::execute(&code, &schedule, "run", contract, 100_000).unwrap();
+        assert!(execution.gas_used > SystemCall::gas(17));
+        assert_eq!(execution.calls, vec![SystemCall::Staking(StakingCall::Stake { amount: 5 })]);
+        assert!(matches!(wasm::execute(&code, &schedule, "garbage", contract, 100_000), Err(wasm::WasmError::Trap(_))));
+
+        let tx = execution.calls[0].clone().into_transaction(contract).unwrap();
+        assert!(matches!(tx.transaction_type, TransactionType::StakeDeposit));
+        assert_eq!((tx.from, tx.value), (contract, Balance::from(5u64)));
+    }
+}
@@ -1,20 +1,64 @@
 use crate::chain::block::BlockHash;
+use crate::chain::canonical;
 use crate::crypto::{hash::Hash, signature::Signature, public_key::PublicKey};
 use crate::errors::TransactionError;
 use crate::types::{Address, Balance, Nonce};
+use parity_scale_codec::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub type TransactionHash = Hash;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub const TX_BASE_GAS: u64 = 21_000;
+pub const TX_DATA_BYTE_GAS: u64 = 16;
+
+// The indexes are part of every transaction hash and never change
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
 pub enum TransactionType {
+    #[codec(index = 0)]
     Transfer,
+    #[codec(index = 1)]
     StakeDeposit,
+    #[codec(index = 2)]
     StakeWithdraw,
+    #[codec(index = 3)]
     AIModelDeploy,
+    #[codec(index = 4)]
     AIModelInvoke,
+    #[codec(index = 5)]
     DataValidation,
+    #[codec(index = 6)]
+    GrantAccess,
+    #[codec(index = 7)]
+    RevokeAccess,
+    // `data` is a Groth16 verifying key, see chain::verifying_keys
+    #[codec(index = 8)]
+    RegisterVerifyingKey,
+    // `data` is a bincode-encoded `ProofSubmission`
+    #[codec(index = 9)]
+    VerifyProof,
+    // `data` is a bincode-encoded `KeyRotation`, see chain::key_registry
+    #[codec(index = 10)]
+    RotateKey,
+    // `data` is a bincode-encoded `KeyRevocation`
+    #[codec(index = 11)]
+    RevokeKey,
+    // `data` is a bincode-encoded `ProposalSubmission`, see chain::governance;
+    // `value` is the initial deposit
+    #[codec(index = 12)]
+    SubmitProposal,
+    // `data` is a bincode-encoded `ProposalDeposit`
+    #[codec(index = 13)]
+    DepositProposal,
+    // `data` is a bincode-encoded `Ballot`
+    #[codec(index = 14)]
+    Vote,
+    // `data` is a bincode-encoded `EntryId`, see chain::rent
+    #[codec(index = 15)]
+    RenewStorage,
+    // `data` is a bincode-encoded `GuardianAction`, see chain::circuit_breaker
+    #[codec(index = 16)]
+    GuardianAction,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,23 +106,33 @@ impl Transaction {
     }
 
     pub fn sign(&mut self, private_key: &[u8]) -> Result<(), TransactionError> {
-        let message = self.hash()?;
+        let message = self.signing_hash()?;
         self.signature = Some(Signature::sign(&message, private_key)?);
         Ok(())
     }
 
     pub fn verify(&self, public_key: &PublicKey) -> Result<bool, TransactionError> {
-        let message = self.hash()?;
+        let message = self.signing_hash()?;
         match &self.signature {
             Some(signature) => Ok(signature.verify(&message, public_key)),
             None => Err(TransactionError::MissingSignature),
         }
     }
 
+    // Of the canonical encoding, signature included
     pub fn hash(&self) -> Result<TransactionHash, TransactionError> {
-        let bytes = bincode::serialize(self)
-            .map_err(|_| TransactionError::SerializationError)?;
-        Ok(TransactionHash::hash(&bytes))
+        Ok(TransactionHash::hash(&self.encode()))
+    }
+
+    // What the sender signs: the hash of the canonical encoding without the
+    // signature, under the transaction domain
+    pub fn signing_hash(&self) -> Result<TransactionHash, TransactionError> {
+        Ok(TransactionHash::hash(&canonical::signing_bytes(self)))
+    }
+
+    // Gas charged before execution starts: a flat cost plus the calldata
+    pub fn intrinsic_gas(&self) -> u64 {
+        TX_BASE_GAS + TX_DATA_BYTE_GAS * self.data.len() as u64
     }
 
     pub fn gas_cost(&self) -> u64 {
@@ -90,6 +144,15 @@ impl Transaction {
     }
 }
 
+// What clients submit to `tx_sendRawTransaction`, bincode-encoded. The chain
+// ID lets a node turn away transactions built for another network with a
+// clear reason; it is not covered by the signature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawTransaction {
+    pub chain_id: u64,
+    pub transaction: Transaction,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionReceipt {
     pub transaction_hash: TransactionHash,
@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::governance::{GovernanceEvent, ProposalId, ProposalKind};
+use crate::crypto::scheme::ADDRESS_LEN;
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::storage::transaction::StorageTransaction;
+use crate::types::{Address, Balance, BlockHeight};
+use crate::utils::decimal::Decimal;
+
+// The module account holding treasury funds. No key hashes to it, so only
+// this module moves them.
+pub fn address() -> Address {
+    let mut address = [0u8; ADDRESS_LEN];
+    address[1..9].copy_from_slice(b"treasury");
+    Address::from(address)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Spend {
+    pub proposal: ProposalId,
+    pub recipient: Address,
+    pub amount: Balance,
+}
+
+// Everything that moved in or out of the treasury in one block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockAccount {
+    pub height: BlockHeight,
+    pub fees: Balance,
+    pub slashed: Balance,
+    pub spends: Vec<Spend>,
+    // Spends that passed but exceeded the funds, and so paid nothing
+    pub unfunded: Vec<Spend>,
+    // After the block
+    pub balance: Balance,
+}
+
+impl BlockAccount {
+    fn new(height: BlockHeight, balance: Balance) -> Self {
+        Self {
+            height,
+            fees: Balance::zero(),
+            slashed: Balance::zero(),
+            spends: Vec::new(),
+            unfunded: Vec::new(),
+            balance,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TreasuryError {
+    #[error("Block {0:?} is below the last accounted block")]
+    OutOfOrder(BlockHeight),
+    #[error("Fee share of {0} basis points exceeds 10000")]
+    InvalidShare(u32),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// Accrues its share of transaction fees and all slashed funds, and pays out
+// spend proposals once they pass. Every block that moves funds gets a
+// `BlockAccount`, so each balance change can be traced to its source.
+pub struct Treasury {
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl Treasury {
+    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
+        Self { storage }
+    }
+
+    pub async fn balance(&self) -> Result<Balance, TreasuryError> {
+        Ok(self.storage.get_value(b"treasury_balance").await?.unwrap_or_default())
+    }
+
+    // Takes `share_bps` of the block's fees and returns it; the rest is the
+    // block producer's
+    pub async fn accrue_fees(
+        &mut self,
+        height: BlockHeight,
+        fees: Balance,
+        share_bps: u32,
+    ) -> Result<Balance, TreasuryError> {
+        if share_bps > 10_000 {
+            return Err(TreasuryError::InvalidShare(share_bps));
+        }
+        let share = Decimal::from_bps(share_bps).share_of(fees);
+        if !share.is_zero() {
+            self.record(height, |account| {
+                account.fees += share;
+                account.balance += share;
+            })
+            .await?;
+        }
+        Ok(share)
+    }
+
+    pub async fn accrue_slashed(&mut self, height: BlockHeight, amount: Balance) -> Result<(), TreasuryError> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        self.record(height, |account| {
+            account.slashed += amount;
+            account.balance += amount;
+        })
+        .await
+    }
+
+    // Pays the spend proposals that passed at `height`, in proposal order,
+    // while funds last. Returns the payments for the executor to credit.
+    pub async fn end_block(
+        &mut self,
+        events: &[GovernanceEvent],
+        height: BlockHeight,
+    ) -> Result<Vec<Spend>, TreasuryError> {
+        let requested: Vec<Spend> = events
+            .iter()
+            .filter_map(|event| match event {
+                GovernanceEvent::Passed { proposal, kind: ProposalKind::TreasurySpend { recipient, amount } } => {
+                    Some(Spend { proposal: *proposal, recipient: *recipient, amount: *amount })
+                }
+                _ => None,
+            })
+            .collect();
+        if requested.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut paid = Vec::new();
+        self.record(height, |account| {
+            for spend in requested {
+                if spend.amount <= account.balance {
+                    account.balance -= spend.amount;
+                    account.spends.push(spend.clone());
+                    paid.push(spend);
+                } else {
+                    account.unfunded.push(spend);
+                }
+            }
+        })
+        .await?;
+        Ok(paid)
+    }
+
+    pub async fn block(&self, height: BlockHeight) -> Result<Option<BlockAccount>, TreasuryError> {
+        Ok(self.get_ledger().await?.into_iter().find(|account| account.height == height))
+    }
+
+    // Accounts of the blocks in `from..=to` that moved funds
+    pub async fn blocks(&self, from: BlockHeight, to: BlockHeight) -> Result<Vec<BlockAccount>, TreasuryError> {
+        let ledger = self.get_ledger().await?;
+        Ok(ledger.into_iter().filter(|account| from <= account.height && account.height <= to).collect())
+    }
+
+    // Applies `update` to the account of `height`, opening it with the
+    // current balance if this is the block's first movement
+    async fn record<F>(&mut self, height: BlockHeight, update: F) -> Result<(), TreasuryError>
+    where
+        F: FnOnce(&mut BlockAccount),
+    {
+        let mut ledger = self.get_ledger().await?;
+        let balance = self.balance().await?;
+        let mut account = match ledger.last() {
+            Some(last) if last.height == height => ledger.pop().expect("not empty"),
+            Some(last) if height < last.height => return Err(TreasuryError::OutOfOrder(height)),
+            _ => BlockAccount::new(height, balance),
+        };
+        update(&mut account);
+
+        // Balance and ledger change together or not at all
+        let mut transaction = StorageTransaction::new();
+        transaction.put_value(b"treasury_balance", &account.balance)?;
+        ledger.push(account);
+        transaction.put_value(b"treasury_ledger", &ledger)?;
+        self.storage.write(transaction).await?;
+        Ok(())
+    }
+
+    async fn get_ledger(&self) -> Result<Vec<BlockAccount>, TreasuryError> {
+        Ok(self.storage.get_value(b"treasury_ledger").await?.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    #[tokio::test]
+    async fn test_accrues_fees_and_slashes_and_pays_passed_spends() {
+        let mut treasury = Treasury::new(Arc::new(MemoryBackend::new()));
+        let height = |height: u64| BlockHeight::from(height);
+        assert_eq!(
+            treasury.accrue_fees(height(1), Balance::from(1_000u64), 1_000).await.unwrap(),
+            Balance::from(100u64)
+        );
+        treasury.accrue_slashed(height(1), Balance::from(50u64)).await.unwrap();
+        assert!(matches!(
+            treasury.accrue_fees(height(1), Balance::from(1u64), 10_001).await,
+            Err(TreasuryError::InvalidShare(_))
+        ));
+
+        let (grantee, other) = (Address::random(), Address::random());
+        let spend = |proposal, recipient, amount: u64| GovernanceEvent::Passed {
+            proposal,
+            kind: ProposalKind::TreasurySpend { recipient, amount: Balance::from(amount) },
+        };
+        let events = vec![spend(1, grantee, 120), spend(2, other, 40), GovernanceEvent::Rejected { proposal: 3 }];
+        let paid = treasury.end_block(&events, height(2)).await.unwrap();
+        assert_eq!(paid, vec![Spend { proposal: 1, recipient: grantee, amount: Balance::from(120u64) }]);
+        assert_eq!(treasury.balance().await.unwrap(), Balance::from(30u64));
+
+        let first = treasury.block(height(1)).await.unwrap().unwrap();
+        assert_eq!(
+            (first.fees, first.slashed, first.balance),
+            (Balance::from(100u64), Balance::from(50u64), Balance::from(150u64))
+        );
+        let second = treasury.block(height(2)).await.unwrap().unwrap();
+        assert_eq!((second.spends.len(), second.unfunded[0].proposal, second.balance), (1, 2, Balance::from(30u64)));
+        assert_eq!(treasury.blocks(height(0), height(10)).await.unwrap().len(), 2);
+        assert!(matches!(
+            treasury.accrue_slashed(height(1), Balance::from(1u64)).await,
+            Err(TreasuryError::OutOfOrder(_))
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+use futures::future::BoxFuture;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::governance::{GovernanceEvent, ProposalId, ProposalKind};
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::storage::transaction::StorageTransaction;
+use crate::types::BlockHeight;
+
+// Migrates state to what the upgraded binary expects
+pub type UpgradeFn = for<'a> fn(&'a dyn StorageBackend) -> BoxFuture<'a, Result<(), DatabaseError>>;
+
+pub struct UpgradeHandler {
+    pub name: &'static str,
+    pub run: UpgradeFn,
+}
+
+// The upgrades this binary knows how to perform. A release that changes
+// consensus rules registers a handler under the name its upgrade proposal
+// uses, even one with no state to migrate.
+pub struct UpgradeRegistry {
+    handlers: Vec<UpgradeHandler>,
+}
+
+impl Default for UpgradeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UpgradeRegistry {
+    pub fn new() -> Self {
+        Self { handlers: Vec::new() }
+    }
+
+    pub fn register(&mut self, handler: UpgradeHandler) -> &mut Self {
+        assert!(self.handler(handler.name).is_none(), "upgrade {} registered twice", handler.name);
+        self.handlers.push(handler);
+        self
+    }
+
+    pub fn handler(&self, name: &str) -> Option<&UpgradeHandler> {
+        self.handlers.iter().find(|handler| handler.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpgradePlan {
+    pub proposal: ProposalId,
+    pub name: String,
+    pub height: BlockHeight,
+}
+
+#[derive(Debug, Error)]
+pub enum UpgradeError {
+    // Stops the node before it executes a block under the old rules
+    #[error("Upgrade {name} is required at height {height:?}; restart with a release that supports it")]
+    Required { name: String, height: BlockHeight },
+    #[error("Upgrade {name} failed: {source}")]
+    Failed { name: String, source: DatabaseError },
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// The upgrade governance has scheduled, if any, and those already done. A
+// newly passed plan replaces one still pending.
+pub struct Upgrades {
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl Upgrades {
+    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
+        Self { storage }
+    }
+
+    pub async fn plan(&self) -> Result<Option<UpgradePlan>, UpgradeError> {
+        Ok(self.storage.get_value(b"upgrade_plan").await?.unwrap_or_default())
+    }
+
+    pub async fn applied(&self) -> Result<Vec<UpgradePlan>, UpgradeError> {
+        Ok(self.storage.get_value(b"applied_upgrades").await?.unwrap_or_default())
+    }
+
+    // Schedules upgrades that passed at `height`. Plans for a height that
+    // is no longer ahead are returned instead, since nodes would have no
+    // warning.
+    pub async fn schedule_passed(
+        &mut self,
+        events: &[GovernanceEvent],
+        height: BlockHeight,
+    ) -> Result<Vec<UpgradePlan>, UpgradeError> {
+        let mut missed = Vec::new();
+        for event in events {
+            if let GovernanceEvent::Passed { proposal, kind: ProposalKind::Upgrade { name, height: at } } = event {
+                let plan = UpgradePlan { proposal: *proposal, name: name.clone(), height: *at };
+                if plan.height > height {
+                    info!("Scheduled upgrade {} at height {:?}", plan.name, plan.height);
+                    self.storage.put_value(b"upgrade_plan", &Some(plan)).await?;
+                } else {
+                    missed.push(plan);
+                }
+            }
+        }
+        Ok(missed)
+    }
+
+    // Run before the block at `height` is executed. At the planned height it
+    // runs the upgrade's handler, or fails if this binary has none. Returns
+    // the upgrade performed.
+    pub async fn begin_block(
+        &mut self,
+        height: BlockHeight,
+        registry: &UpgradeRegistry,
+    ) -> Result<Option<UpgradePlan>, UpgradeError> {
+        let plan = match self.plan().await? {
+            Some(plan) if plan.height <= height => plan,
+            _ => return Ok(None),
+        };
+        let handler = registry
+            .handler(&plan.name)
+            .ok_or_else(|| UpgradeError::Required { name: plan.name.clone(), height: plan.height })?;
+
+        info!("Applying upgrade {} at height {:?}", plan.name, height);
+        (handler.run)(&*self.storage)
+            .await
+            .map_err(|source| UpgradeError::Failed { name: plan.name.clone(), source })?;
+        let mut applied = self.applied().await?;
+        applied.push(plan.clone());
+        let mut transaction = StorageTransaction::new();
+        transaction.put_value(b"applied_upgrades", &applied)?.put_value(b"upgrade_plan", &None::<UpgradePlan>)?;
+        self.storage.write(transaction).await?;
+        Ok(Some(plan))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    fn rename_stakes(storage: &dyn StorageBackend) -> BoxFuture<'_, Result<(), DatabaseError>> {
+        Box::pin(storage.put_value(b"stakes_v2", &true))
+    }
+
+    #[tokio::test]
+    async fn test_halts_at_the_planned_height_without_a_handler() {
+        let mut upgrades = Upgrades::new(Arc::new(MemoryBackend::new()));
+        let upgrade = |proposal, name: &str, at: u64| GovernanceEvent::Passed {
+            proposal,
+            kind: ProposalKind::Upgrade { name: name.to_string(), height: BlockHeight::from(at) },
+        };
+        let missed =
+            upgrades.schedule_passed(&[upgrade(1, "v2", 100), upgrade(2, "late", 5)], BlockHeight::from(10)).await;
+        assert_eq!(missed.unwrap()[0].name, "late");
+
+        let old_release = UpgradeRegistry::new();
+        assert!(upgrades.begin_block(BlockHeight::from(99), &old_release).await.unwrap().is_none());
+        assert!(matches!(
+            upgrades.begin_block(BlockHeight::from(100), &old_release).await,
+            Err(UpgradeError::Required { name, .. }) if name == "v2"
+        ));
+
+        let mut new_release = UpgradeRegistry::new();
+        new_release.register(UpgradeHandler { name: "v2", run: rename_stakes });
+        let applied = upgrades.begin_block(BlockHeight::from(100), &new_release).await.unwrap().unwrap();
+        assert_eq!((applied.proposal, applied.height), (1, BlockHeight::from(100)));
+        let migrated: Option<bool> = upgrades.storage.get_value(b"stakes_v2").await.unwrap();
+        assert_eq!(migrated, Some(true));
+        assert!(upgrades.plan().await.unwrap().is_none());
+        assert_eq!(upgrades.applied().await.unwrap().len(), 1);
+        assert!(upgrades.begin_block(BlockHeight::from(101), &new_release).await.unwrap().is_none());
+    }
+}
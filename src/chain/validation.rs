@@ -0,0 +1,165 @@
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::chain::transaction::{RawTransaction, Transaction};
+use crate::chain::verify::{check_batch, SignatureCheck};
+use crate::types::{Address, Balance, Nonce};
+
+// Why a transaction was refused a place in the pool. Serialized with a
+// `reason` tag so clients can act on it without parsing the message.
+#[derive(Debug, Clone, PartialEq, Serialize, Error)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum TxRejection {
+    #[error("Transaction is not signed")]
+    Unsigned,
+    #[error("Coinbase transactions are only created by block producers")]
+    Coinbase,
+    #[error("Transaction is for chain {got}, this node is on chain {expected}")]
+    WrongChain { expected: u64, got: u64 },
+    #[error("Gas limit {gas_limit} is below the intrinsic gas {intrinsic}")]
+    IntrinsicGas { gas_limit: u64, intrinsic: u64 },
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Nonce {got} is below the account nonce {expected}")]
+    NonceTooLow { expected: Nonce, got: Nonce },
+    #[error("Balance does not cover the value plus the maximum gas cost")]
+    InsufficientFunds { balance: Balance, required: Balance },
+    #[error("Value plus the maximum gas cost overflows")]
+    CostOverflow,
+    #[error("Sender may not invoke model {model:?}")]
+    AccessDenied { model: Address },
+    #[error("{message}")]
+    Pool { message: String },
+}
+
+// Everything that can be checked without state, cheapest first, so the
+// signature is only verified for transactions that could otherwise go in
+pub fn check_stateless(
+    raw: &RawTransaction,
+    chain_id: u64,
+    signature_check: &SignatureCheck,
+) -> Result<(), TxRejection> {
+    check_stateless_batch(std::slice::from_ref(raw), chain_id, signature_check).remove(0)
+}
+
+// `check_stateless` for transactions arriving together, e.g. fetched from a
+// peer, with the signatures of those passing the cheap checks verified in
+// one batch
+pub fn check_stateless_batch(
+    raws: &[RawTransaction],
+    chain_id: u64,
+    signature_check: &SignatureCheck,
+) -> Vec<Result<(), TxRejection>> {
+    let mut results: Vec<_> = raws.iter().map(|raw| check_cheap(raw, chain_id)).collect();
+    let signed = raws
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| results[*index].is_ok())
+        .map(|(index, raw)| (index, signature_check(&raw.transaction)));
+    for index in check_batch(signed) {
+        results[index] = Err(TxRejection::InvalidSignature);
+    }
+    results
+}
+
+fn check_cheap(raw: &RawTransaction, chain_id: u64) -> Result<(), TxRejection> {
+    let transaction = &raw.transaction;
+    if transaction.signature.is_none() {
+        return Err(TxRejection::Unsigned);
+    }
+    if transaction.is_coinbase() {
+        return Err(TxRejection::Coinbase);
+    }
+    if raw.chain_id != chain_id {
+        return Err(TxRejection::WrongChain { expected: chain_id, got: raw.chain_id });
+    }
+    let intrinsic = transaction.intrinsic_gas();
+    if transaction.gas_limit < intrinsic {
+        return Err(TxRejection::IntrinsicGas { gas_limit: transaction.gas_limit, intrinsic });
+    }
+    Ok(())
+}
+
+// Checks against the sender's account at the current head. Nonces above the
+// account's are accepted; the pool holds them until the gap is filled.
+pub fn check_stateful(transaction: &Transaction, nonce: Nonce, balance: Balance) -> Result<(), TxRejection> {
+    if transaction.nonce < nonce {
+        return Err(TxRejection::NonceTooLow { expected: nonce, got: transaction.nonce });
+    }
+    // The sender pays for the whole gas limit up front
+    let required = Balance::from(transaction.gas_price)
+        .checked_mul(Balance::from(transaction.gas_limit))
+        .and_then(|gas| gas.checked_add(transaction.value))
+        .ok_or(TxRejection::CostOverflow)?;
+    if balance < required {
+        return Err(TxRejection::InsufficientFunds { balance, required });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::transaction::TransactionType;
+    use crate::chain::verify::SignedMessage;
+    use crate::crypto::key_pair::KeyPair;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_rejects_with_the_first_failing_check() {
+        let key_pair = KeyPair::generate();
+        let public_key =
+            ed25519_dalek::PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(key_pair.private_key()).unwrap());
+        let signature_check: SignatureCheck =
+            Arc::new(move |transaction: &Transaction| SignedMessage::of(transaction, public_key.as_bytes()));
+        let mut transaction = Transaction::new(
+            3,
+            Address::random(),
+            Address::random(),
+            100,
+            2,
+            21000,
+            vec![0; 10],
+            TransactionType::Transfer,
+        );
+        let raw = |transaction: &Transaction, chain_id| RawTransaction { chain_id, transaction: transaction.clone() };
+        assert_eq!(check_stateless(&raw(&transaction, 1), 1, &signature_check), Err(TxRejection::Unsigned));
+
+        transaction.sign(key_pair.private_key()).unwrap();
+        assert_eq!(
+            check_stateless(&raw(&transaction, 5), 1, &signature_check),
+            Err(TxRejection::WrongChain { expected: 1, got: 5 })
+        );
+        assert_eq!(
+            check_stateless(&raw(&transaction, 1), 1, &signature_check),
+            Err(TxRejection::IntrinsicGas { gas_limit: 21000, intrinsic: 21160 })
+        );
+        transaction.gas_limit = 30000;
+        assert_eq!(check_stateless(&raw(&transaction, 1), 1, &signature_check), Ok(()));
+        let forged = Transaction { nonce: 7, ..transaction.clone() };
+        assert_eq!(check_stateless(&raw(&forged, 1), 1, &signature_check), Err(TxRejection::InvalidSignature));
+        let batch = [raw(&forged, 1), raw(&transaction, 1), raw(&forged, 5)];
+        assert_eq!(
+            check_stateless_batch(&batch, 1, &signature_check),
+            vec![Err(TxRejection::InvalidSignature), Ok(()), Err(TxRejection::WrongChain { expected: 1, got: 5 })]
+        );
+
+        assert_eq!(
+            check_stateful(&transaction, 4, Balance::from(1_000_000u64)),
+            Err(TxRejection::NonceTooLow { expected: 4, got: 3 })
+        );
+        assert_eq!(
+            check_stateful(&transaction, 3, Balance::from(60_099u64)),
+            Err(TxRejection::InsufficientFunds {
+                balance: Balance::from(60_099u64),
+                required: Balance::from(60_100u64)
+            })
+        );
+        assert_eq!(check_stateful(&transaction, 3, Balance::from(60_100u64)), Ok(()));
+        assert_eq!(check_stateful(&transaction, 0, Balance::from(60_100u64)), Ok(()));
+
+        // A value that wraps the total around must not pass as affordable
+        let overflowing = Transaction { value: Balance::MAX, ..transaction.clone() };
+        assert_eq!(check_stateful(&overflowing, 3, Balance::MAX), Err(TxRejection::CostOverflow));
+    }
+}
@@ -0,0 +1,244 @@
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+use crate::chain::block::Block;
+use crate::chain::transaction::Transaction;
+use crate::crypto::batch::{verify_batch, BatchError};
+use crate::network::clock::{self, ClockConfig, TimestampError};
+
+// Transactions checked in one batch; each batch runs on its own thread
+const BATCH_SIZE: usize = 128;
+
+// What a transaction's signature has to verify against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedMessage {
+    pub message: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub public_key: Vec<u8>,
+}
+
+impl SignedMessage {
+    // `None` for unsigned transactions
+    pub fn of(transaction: &Transaction, public_key: &[u8]) -> Option<Self> {
+        let signature = transaction.signature.as_ref()?.to_bytes().to_vec();
+        let message = transaction.signing_hash().ok()?.as_bytes().to_vec();
+        Some(Self { message, signature, public_key: public_key.to_vec() })
+    }
+}
+
+// Looks up what one transaction's signature must verify against; the caller
+// knows how to resolve the signer's key. `None` fails the transaction, e.g.
+// for an unknown signer. Coinbase transactions are never passed in.
+pub type SignatureCheck = Arc<dyn Fn(&Transaction) -> Option<SignedMessage> + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub struct VerifyConfig {
+    // Verification threads; 0 uses one per core
+    pub threads: usize,
+    // Blocks submitted ahead of the one being executed
+    pub max_queued_blocks: usize,
+    // Bounds how far ahead of the local clock a block may be timestamped
+    pub clock: ClockConfig,
+}
+
+impl Default for VerifyConfig {
+    fn default() -> Self {
+        Self { threads: 0, max_queued_blocks: 64, clock: ClockConfig::default() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum VerifyError {
+    #[error("Transaction {index} in block {height} has an invalid signature")]
+    InvalidSignature { height: u64, index: usize },
+    #[error("Block {height} has an invalid timestamp: {source}")]
+    Timestamp { height: u64, source: TimestampError },
+    #[error("Failed to start the verification pool: {0}")]
+    Pool(String),
+    #[error("Verification of block {0} was abandoned")]
+    Abandoned(u64),
+}
+
+pub type Result<T> = std::result::Result<T, VerifyError>;
+
+struct Queued {
+    height: u64,
+    block: Arc<Block>,
+    result: oneshot::Receiver<Result<()>>,
+}
+
+// Signature checks are the expensive, stateless part of importing a block.
+// Submitted blocks are checked on a dedicated pool, each block's transactions
+// in parallel batches, while the caller executes the ones before them; blocks come
+// back out of `next` in submission order. The pool is kept apart from the
+// async runtime so a long batch doesn't starve networking.
+pub struct VerificationPipeline {
+    pool: Arc<ThreadPool>,
+    check: SignatureCheck,
+    clock: ClockConfig,
+    max_queued: usize,
+    queue: VecDeque<Queued>,
+}
+
+impl VerificationPipeline {
+    pub fn new(config: VerifyConfig, check: SignatureCheck) -> Result<Self> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(config.threads)
+            .thread_name(|index| format!("sig-verify-{}", index))
+            .build()
+            .map_err(|e| VerifyError::Pool(e.to_string()))?;
+        Ok(Self {
+            pool: Arc::new(pool),
+            check,
+            clock: config.clock,
+            max_queued: config.max_queued_blocks.max(1),
+            queue: VecDeque::new(),
+        })
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.queue.len() >= self.max_queued
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    // Starts checking the block in the background: its timestamp against the
+    // local clock, then its signatures
+    pub fn submit(&mut self, height: u64, block: Block) {
+        let (sender, result) = oneshot::channel();
+        let block = Arc::new(block);
+        let (job, check, clock) = (Arc::clone(&block), Arc::clone(&self.check), self.clock.clone());
+        self.pool.spawn(move || {
+            let verified = clock
+                .check_timestamp(job.header.timestamp, clock::unix_millis())
+                .map_err(|source| VerifyError::Timestamp { height, source })
+                .and_then(|()| verify_signatures(height, &job, check.as_ref()));
+            // Released before the result is sent, so `next` gets the block back without copying it
+            drop(job);
+            let _ = sender.send(verified);
+        });
+        self.queue.push_back(Queued { height, block, result });
+    }
+
+    // Waits for the oldest submitted block, returned once its signatures
+    // check out; `None` when nothing is queued
+    pub async fn next(&mut self) -> Option<(u64, Result<Block>)> {
+        let Queued { height, block, result } = self.queue.pop_front()?;
+        let verified = result.await.unwrap_or(Err(VerifyError::Abandoned(height)));
+        Some((height, verified.map(|()| Arc::try_unwrap(block).unwrap_or_else(|block| (*block).clone()))))
+    }
+
+    // Drops the queued blocks, e.g. once one failed and the rest can't
+    // extend the chain any more; checks already running finish unobserved
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+fn verify_signatures(
+    height: u64,
+    block: &Block,
+    check: &(dyn Fn(&Transaction) -> Option<SignedMessage> + Send + Sync),
+) -> Result<()> {
+    let first_invalid = block
+        .transactions
+        .par_chunks(BATCH_SIZE)
+        .enumerate()
+        .filter_map(|(batch, transactions)| {
+            let signable = transactions.iter().enumerate().filter(|(_, tx)| !tx.is_coinbase());
+            let invalid = check_batch(signable.map(|(index, tx)| (index, check(tx))));
+            invalid.first().map(|index| batch * BATCH_SIZE + index)
+        })
+        .min();
+    match first_invalid {
+        Some(index) => Err(VerifyError::InvalidSignature { height, index }),
+        None => Ok(()),
+    }
+}
+
+// Verifies the signatures in one pass and returns the positions of the
+// transactions that failed, in order. Transactions without a signed message
+// fail without being checked.
+pub fn check_batch(signed: impl IntoIterator<Item = (usize, Option<SignedMessage>)>) -> Vec<usize> {
+    let (mut invalid, mut positions, mut messages) = (Vec::new(), Vec::new(), Vec::new());
+    for (position, message) in signed {
+        match message {
+            Some(message) => {
+                positions.push(position);
+                messages.push(message);
+            }
+            None => invalid.push(position),
+        }
+    }
+    let items: Vec<(&[u8], &[u8], &[u8])> = messages
+        .iter()
+        .map(|signed| (signed.message.as_slice(), signed.signature.as_slice(), signed.public_key.as_slice()))
+        .collect();
+    if let Err(BatchError::Invalid(indexes)) = verify_batch(&items) {
+        invalid.extend(indexes.into_iter().map(|index| positions[index]));
+    }
+    invalid.sort_unstable();
+    invalid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::transaction::TransactionType;
+    use crate::crypto::key_pair::KeyPair;
+    use crate::types::Address;
+
+    #[tokio::test]
+    async fn test_returns_blocks_in_order_and_flags_bad_signatures() {
+        let key_pair = KeyPair::generate();
+        let signed = |nonce| {
+            let mut tx = Transaction::new(
+                nonce,
+                Address::random(),
+                Address::random(),
+                1,
+                1,
+                21000,
+                vec![],
+                TransactionType::Transfer,
+            );
+            tx.sign(key_pair.private_key()).unwrap();
+            tx
+        };
+        let public_key =
+            ed25519_dalek::PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(key_pair.private_key()).unwrap());
+        let check: SignatureCheck = Arc::new(move |tx: &Transaction| SignedMessage::of(tx, public_key.as_bytes()));
+        let config = VerifyConfig { threads: 2, max_queued_blocks: 4, ..VerifyConfig::default() };
+        let mut pipeline = VerificationPipeline::new(config, check).unwrap();
+
+        let mut tampered = signed(7);
+        tampered.value += 1;
+        let mut early = Block::new([3; 32], vec![signed(9)], 1).unwrap();
+        early.header.timestamp += 60;
+        let blocks = vec![
+            Block::new([0; 32], (0..20).map(signed).collect(), 1).unwrap(),
+            Block::new([1; 32], vec![signed(5), signed(6), tampered], 1).unwrap(),
+            Block::new([2; 32], vec![signed(8)], 1).unwrap(),
+            early,
+        ];
+        let hashes: Vec<[u8; 32]> = blocks.iter().map(Block::hash).collect();
+        for (height, block) in (1..).zip(blocks) {
+            pipeline.submit(height, block);
+        }
+        assert!(pipeline.is_full());
+
+        let (height, first) = pipeline.next().await.unwrap();
+        assert_eq!((height, first.unwrap().hash()), (1, hashes[0]));
+        let (height, second) = pipeline.next().await.unwrap();
+        assert_eq!((height, second.unwrap_err()), (2, VerifyError::InvalidSignature { height: 2, index: 2 }));
+        assert_eq!(pipeline.next().await.unwrap().1.unwrap().hash(), hashes[2]);
+        assert!(matches!(pipeline.next().await.unwrap().1, Err(VerifyError::Timestamp { height: 4, .. })));
+        assert!(pipeline.next().await.is_none());
+    }
+}
@@ -0,0 +1,221 @@
+use ark_bn254::Fr;
+use ark_serialize::CanonicalDeserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::crypto::hasher::{HashAlgorithm, DIGEST_LEN};
+use crate::crypto::zk::{Groth16Verifier, ZkError};
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::types::{Address, BlockHeight};
+
+// Charged on top of the intrinsic gas: storing a key is paid per byte, a
+// verification per pairing check plus per public input
+pub const REGISTER_BYTE_GAS: u64 = 200;
+pub const VERIFY_GAS: u64 = 250_000;
+pub const VERIFY_INPUT_GAS: u64 = 10_000;
+// Keeps verification cost bounded
+pub const MAX_PUBLIC_INPUTS: usize = 64;
+
+// Keys are addressed by the SHA3-256 of their encoding, so the same circuit
+// registered twice gets the same ID
+pub type KeyId = [u8; DIGEST_LEN];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisteredKey {
+    pub owner: Address,
+    // Groth16 over BN254, arkworks compressed encoding
+    pub key: Vec<u8>,
+    pub public_inputs: u32,
+    pub registered_at: BlockHeight,
+}
+
+// Payload carried in `data` of VerifyProof transactions. Public inputs are
+// canonical little-endian BN254 scalars.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofSubmission {
+    pub key: KeyId,
+    pub proof: Vec<u8>,
+    pub public_inputs: Vec<[u8; 32]>,
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyingKeyError {
+    #[error("Verifying key is already registered")]
+    AlreadyRegistered,
+    #[error("Verifying key not found")]
+    NotFound,
+    #[error("Verifying key takes {0} public inputs, at most {MAX_PUBLIC_INPUTS} are allowed")]
+    TooManyInputs(usize),
+    #[error("Public input {0} is not a canonical field element")]
+    InvalidInput(usize),
+    #[error("Proof rejected")]
+    InvalidProof,
+    #[error("Out of gas: required {required}, available {available}")]
+    OutOfGas { required: u64, available: u64 },
+    #[error("Unexpected transaction type")]
+    UnexpectedTransactionType,
+    #[error("Malformed payload")]
+    MalformedPayload,
+    #[error("ZK error: {0}")]
+    Zk(#[from] ZkError),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// Verifying keys registered on chain, and the checks of proofs against them
+// run while executing transactions. Inference settlement points models at
+// keys here rather than keeping its own, see `ai::verifiable`.
+pub struct VerifyingKeyRegistry {
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl VerifyingKeyRegistry {
+    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
+        Self { storage }
+    }
+
+    // Applies a RegisterVerifyingKey or VerifyProof transaction and returns
+    // the gas it used; an error fails the transaction
+    pub async fn apply_transaction(&mut self, tx: &Transaction, height: BlockHeight) -> Result<u64, VerifyingKeyError> {
+        let available = tx.gas_limit.saturating_sub(tx.intrinsic_gas());
+        match tx.transaction_type {
+            TransactionType::RegisterVerifyingKey => {
+                let gas = REGISTER_BYTE_GAS.saturating_mul(tx.data.len() as u64);
+                charge(gas, available)?;
+                self.register(tx.from, tx.data.clone(), height).await?;
+                Ok(gas)
+            }
+            TransactionType::VerifyProof => {
+                let submission: ProofSubmission =
+                    bincode::deserialize(&tx.data).map_err(|_| VerifyingKeyError::MalformedPayload)?;
+                let gas = verify_gas(submission.public_inputs.len());
+                charge(gas, available)?;
+                self.verify(&submission).await?;
+                Ok(gas)
+            }
+            _ => Err(VerifyingKeyError::UnexpectedTransactionType),
+        }
+    }
+
+    pub async fn register(
+        &mut self,
+        owner: Address,
+        key: Vec<u8>,
+        height: BlockHeight,
+    ) -> Result<KeyId, VerifyingKeyError> {
+        // Undecodable keys are refused up front so proofs can't fail on them later
+        let public_inputs = Groth16Verifier::from_bytes(&key)?.public_input_count();
+        if public_inputs > MAX_PUBLIC_INPUTS {
+            return Err(VerifyingKeyError::TooManyInputs(public_inputs));
+        }
+
+        let id = HashAlgorithm::Sha3_256.digest(&key);
+        let mut keys = self.get_keys().await?;
+        if keys.contains_key(&id) {
+            return Err(VerifyingKeyError::AlreadyRegistered);
+        }
+        keys.insert(id, RegisteredKey { owner, key, public_inputs: public_inputs as u32, registered_at: height });
+        self.storage.put_value(b"verifying_keys", &keys).await?;
+
+        Ok(id)
+    }
+
+    pub async fn get(&self, id: &KeyId) -> Result<Option<RegisteredKey>, VerifyingKeyError> {
+        Ok(self.get_keys().await?.remove(id))
+    }
+
+    pub async fn verify(&self, submission: &ProofSubmission) -> Result<(), VerifyingKeyError> {
+        let registered = self.get(&submission.key).await?.ok_or(VerifyingKeyError::NotFound)?;
+        let inputs = submission
+            .public_inputs
+            .iter()
+            .enumerate()
+            .map(|(index, bytes)| {
+                Fr::deserialize_compressed(&bytes[..]).map_err(|_| VerifyingKeyError::InvalidInput(index))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if Groth16Verifier::from_bytes(&registered.key)?.verify(&submission.proof, &inputs)? {
+            Ok(())
+        } else {
+            Err(VerifyingKeyError::InvalidProof)
+        }
+    }
+
+    async fn get_keys(&self) -> Result<HashMap<KeyId, RegisteredKey>, VerifyingKeyError> {
+        Ok(self.storage.get_value(b"verifying_keys").await?.unwrap_or_default())
+    }
+}
+
+pub fn verify_gas(public_inputs: usize) -> u64 {
+    VERIFY_GAS + VERIFY_INPUT_GAS * public_inputs as u64
+}
+
+fn charge(required: u64, available: u64) -> Result<(), VerifyingKeyError> {
+    if required > available {
+        return Err(VerifyingKeyError::OutOfGas { required, available });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::zk::test_circuit::SumCircuit;
+    use crate::storage::backend::MemoryBackend;
+    use ark_bn254::Bn254;
+    use ark_ff::{BigInteger, PrimeField};
+    use ark_groth16::Groth16;
+    use ark_serialize::CanonicalSerialize;
+    use ark_snark::SNARK;
+    use ark_std::test_rng;
+
+    #[tokio::test]
+    async fn test_registers_keys_and_verifies_proofs_in_transactions() {
+        let mut rng = test_rng();
+        let (a, b) = (Fr::from(3u64), Fr::from(5u64));
+        let circuit = SumCircuit { a: Some(a), b: Some(b) };
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(circuit.clone(), &mut rng).unwrap();
+        let proof = Groth16::<Bn254>::prove(&pk, circuit, &mut rng).unwrap();
+        let mut key = Vec::new();
+        vk.serialize_compressed(&mut key).unwrap();
+        let mut proof_bytes = Vec::new();
+        proof.serialize_compressed(&mut proof_bytes).unwrap();
+        let scalar = |field: Fr| -> [u8; 32] { field.into_bigint().to_bytes_le().try_into().unwrap() };
+
+        let mut registry = VerifyingKeyRegistry::new(Arc::new(MemoryBackend::new()));
+        let owner = Address::random();
+        let tx = |data: Vec<u8>, gas_limit, transaction_type| {
+            Transaction::new(0, owner, Address::default(), 0, 1, gas_limit, data, transaction_type)
+        };
+        let register = tx(key.clone(), 1_000_000, TransactionType::RegisterVerifyingKey);
+        let gas = registry.apply_transaction(&register, BlockHeight::from(1)).await.unwrap();
+        assert_eq!(gas, REGISTER_BYTE_GAS * key.len() as u64);
+        assert!(matches!(
+            registry.apply_transaction(&register, BlockHeight::from(2)).await,
+            Err(VerifyingKeyError::AlreadyRegistered)
+        ));
+        let id = HashAlgorithm::Sha3_256.digest(&key);
+        assert_eq!(registry.get(&id).await.unwrap().unwrap().public_inputs, 2);
+
+        let submission = ProofSubmission { key: id, proof: proof_bytes, public_inputs: vec![scalar(a), scalar(b)] };
+        let verify = tx(bincode::serialize(&submission).unwrap(), 500_000, TransactionType::VerifyProof);
+        assert_eq!(registry.apply_transaction(&verify, BlockHeight::from(3)).await.unwrap(), verify_gas(2));
+        let short = Transaction { gas_limit: verify_gas(2), ..verify.clone() };
+        assert!(matches!(
+            registry.apply_transaction(&short, BlockHeight::from(3)).await,
+            Err(VerifyingKeyError::OutOfGas { .. })
+        ));
+
+        let forged = ProofSubmission { public_inputs: vec![scalar(a), scalar(a)], ..submission.clone() };
+        assert!(matches!(registry.verify(&forged).await, Err(VerifyingKeyError::InvalidProof)));
+        let unreduced = ProofSubmission { public_inputs: vec![scalar(a), [0xff; 32]], ..submission.clone() };
+        assert!(matches!(registry.verify(&unreduced).await, Err(VerifyingKeyError::InvalidInput(1))));
+        let unknown = ProofSubmission { key: [0; 32], ..submission };
+        assert!(matches!(registry.verify(&unknown).await, Err(VerifyingKeyError::NotFound)));
+    }
+}
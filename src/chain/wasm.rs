@@ -0,0 +1,425 @@
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use thiserror::Error;
+use wasm_instrument::gas_metering::{self, host_function, MemoryGrowCost, Rules};
+use wasm_instrument::parity_wasm::{self, elements::Instruction};
+use wasmi::core::Trap;
+use wasmi::{Caller, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::chain::events::{self, Events, MAX_EVENT_DATA, MAX_TOPICS};
+use crate::chain::system::{SystemCall, MAX_SYSTEM_CALLS, MAX_SYSTEM_CALL_INPUT};
+use crate::crypto::hash::Hash;
+use crate::crypto::scheme::ADDRESS_LEN;
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::types::{Address, BlockHeight};
+
+// Instrumented code calls `env.gas(amount)` at the start of every metered
+// block; contracts may not import it themselves
+const GAS_MODULE: &str = "env";
+const GAS_FUNCTION: &str = "gas";
+// `env.emit_event(topics, topic_count, data, data_len)`: pointers into the
+// contract's exported `memory` to 32-byte topics and to the event data
+const EMIT_EVENT_FUNCTION: &str = "emit_event";
+// `env.system_call(target, input, input_len)`: pointers to the 21-byte
+// address of a system contract and to the SCALE-encoded call
+const SYSTEM_CALL_FUNCTION: &str = "system_call";
+const PAGE_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum WasmError {
+    #[error("Out of gas: limit {limit}")]
+    OutOfGas { limit: u64 },
+    #[error("Invalid module: {0}")]
+    InvalidModule(String),
+    #[error("Gas schedule version {version} must be above {current} and activate after the last one")]
+    StaleSchedule { version: u32, current: u32 },
+    #[error("Execution trapped: {0}")]
+    Trap(String),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+// What contract execution costs, per instruction. It is stored on chain and
+// replaced by governance as a new version taking effect at a given height,
+// so every node charges the same for the same block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GasSchedule {
+    pub version: u32,
+    // Any instruction not priced below
+    pub instruction: u32,
+    pub memory_access: u32,
+    pub multiplication: u32,
+    pub division: u32,
+    pub call: u32,
+    pub call_indirect: u32,
+    // Charged per local of the callee on every call
+    pub local: u32,
+    // Per 64 KiB page, both the ones a module starts with and the ones
+    // added by memory.grow
+    pub memory_page: u32,
+    // Deterministic bound on recursion, in stack slots
+    pub max_stack_height: u32,
+    // Bounds on what a contract may allocate. Modules declaring more are
+    // rejected; memory.grow and table.grow past them fail.
+    pub max_memory_pages: u32,
+    pub max_table_elements: u32,
+}
+
+impl Default for GasSchedule {
+    fn default() -> Self {
+        Self {
+            version: 1,
+            instruction: 1,
+            memory_access: 3,
+            multiplication: 3,
+            division: 8,
+            call: 20,
+            call_indirect: 30,
+            local: 1,
+            memory_page: 2_048,
+            max_stack_height: 16_384,
+            max_memory_pages: 256,
+            max_table_elements: 4_096,
+        }
+    }
+}
+
+impl Rules for GasSchedule {
+    fn instruction_cost(&self, instruction: &Instruction) -> Option<u32> {
+        use Instruction::*;
+        let cost = match instruction {
+            I32Load(..) | I64Load(..) | I32Load8S(..) | I32Load8U(..) | I32Load16S(..) | I32Load16U(..)
+            | I64Load8S(..) | I64Load8U(..) | I64Load16S(..) | I64Load16U(..) | I64Load32S(..) | I64Load32U(..)
+            | I32Store(..) | I64Store(..) | I32Store8(..) | I32Store16(..) | I64Store8(..) | I64Store16(..)
+            | I64Store32(..) => self.memory_access,
+            I32Mul | I64Mul => self.multiplication,
+            I32DivS | I32DivU | I32RemS | I32RemU | I64DivS | I64DivU | I64RemS | I64RemU => self.division,
+            Call(_) => self.call,
+            CallIndirect(..) => self.call_indirect,
+            _ => self.instruction,
+        };
+        Some(cost)
+    }
+
+    fn memory_grow_cost(&self) -> MemoryGrowCost {
+        NonZeroU32::new(self.memory_page).map_or(MemoryGrowCost::Free, MemoryGrowCost::Linear)
+    }
+
+    fn call_per_local_cost(&self) -> u32 {
+        self.local
+    }
+}
+
+// Rewrites a contract so it pays for itself under `schedule`. The result is
+// what gets executed; it is only valid for that schedule's version, so
+// caches are keyed by code hash and version.
+pub fn instrument(code: &[u8], schedule: &GasSchedule) -> Result<Vec<u8>, WasmError> {
+    let module: parity_wasm::elements::Module =
+        parity_wasm::deserialize_buffer(code).map_err(|e| WasmError::InvalidModule(e.to_string()))?;
+    let imports_gas = module.import_section().map_or(false, |imports| {
+        imports.entries().iter().any(|import| import.module() == GAS_MODULE && import.field() == GAS_FUNCTION)
+    });
+    if imports_gas {
+        return Err(WasmError::InvalidModule(format!("imports {}.{}", GAS_MODULE, GAS_FUNCTION)));
+    }
+    if initial_pages(&module) > u64::from(schedule.max_memory_pages) {
+        return Err(WasmError::InvalidModule(format!("more than {} memory pages", schedule.max_memory_pages)));
+    }
+    let table_elements = module
+        .table_section()
+        .map_or(0, |tables| tables.entries().iter().map(|table| u64::from(table.limits().initial())).sum::<u64>());
+    if table_elements > u64::from(schedule.max_table_elements) {
+        return Err(WasmError::InvalidModule(format!("more than {} table elements", schedule.max_table_elements)));
+    }
+    let backend = host_function::Injector::new(GAS_MODULE, GAS_FUNCTION);
+    let module = gas_metering::inject(module, backend, schedule)
+        .map_err(|_| WasmError::InvalidModule("gas metering".to_string()))?;
+    let module = wasm_instrument::inject_stack_limiter(module, schedule.max_stack_height)
+        .map_err(|_| WasmError::InvalidModule("stack limit".to_string()))?;
+    parity_wasm::serialize(module).map_err(|e| WasmError::InvalidModule(e.to_string()))
+}
+
+// Pages of linear memory the module has before running any code. The host
+// provides no memory, so an imported one fails to link anyway.
+fn initial_pages(module: &parity_wasm::elements::Module) -> u64 {
+    module
+        .memory_section()
+        .map_or(0, |memories| memories.entries().iter().map(|memory| u64::from(memory.limits().initial())).sum())
+}
+
+pub struct Execution {
+    pub gas_used: u64,
+    pub events: Events,
+    // Dispatched in order once the contract has returned, see chain::system
+    pub calls: Vec<SystemCall>,
+}
+
+struct HostState {
+    contract: Address,
+    used: u64,
+    limit: u64,
+    exhausted: bool,
+    events: Events,
+    calls: Vec<SystemCall>,
+    limits: StoreLimits,
+}
+
+impl HostState {
+    fn charge(&mut self, amount: u64) -> Result<(), Trap> {
+        let used = self.used.saturating_add(amount);
+        if used > self.limit {
+            self.exhausted = true;
+            return Err(Trap::new("out of gas"));
+        }
+        self.used = used;
+        Ok(())
+    }
+}
+
+// Calls `entry` of the code of `contract`, instrumented for `schedule`, and
+// returns the gas it used and the events it emitted. Execution stops as soon
+// as the next metered block would go over `gas_limit`.
+pub fn execute(
+    instrumented: &[u8],
+    schedule: &GasSchedule,
+    entry: &str,
+    contract: Address,
+    gas_limit: u64,
+) -> Result<Execution, WasmError> {
+    let parsed: parity_wasm::elements::Module =
+        parity_wasm::deserialize_buffer(instrumented).map_err(|e| WasmError::InvalidModule(e.to_string()))?;
+    let engine = Engine::default();
+    let module = Module::new(&engine, instrumented).map_err(|e| WasmError::InvalidModule(e.to_string()))?;
+    let limits = StoreLimitsBuilder::new()
+        .memory_size(schedule.max_memory_pages as usize * PAGE_SIZE)
+        .table_elements(schedule.max_table_elements)
+        .instances(1)
+        .build();
+    let mut state = HostState {
+        contract,
+        used: 0,
+        limit: gas_limit,
+        exhausted: false,
+        events: Events::new(),
+        calls: Vec::new(),
+        limits,
+    };
+    // The memory a module starts with is paid for like grown memory
+    if state.charge(u64::from(schedule.memory_page).saturating_mul(initial_pages(&parsed))).is_err() {
+        return Err(WasmError::OutOfGas { limit: gas_limit });
+    }
+    let mut store = Store::new(&engine, state);
+    store.limiter(|state| &mut state.limits);
+    let mut linker = <Linker<HostState>>::new(&engine);
+    linker
+        .func_wrap(GAS_MODULE, GAS_FUNCTION, |mut caller: Caller<'_, HostState>, amount: i64| {
+            caller.data_mut().charge(amount as u64)
+        })
+        .map_err(|e| WasmError::InvalidModule(e.to_string()))?;
+    linker
+        .func_wrap(GAS_MODULE, EMIT_EVENT_FUNCTION, emit_event)
+        .map_err(|e| WasmError::InvalidModule(e.to_string()))?;
+    linker
+        .func_wrap(GAS_MODULE, SYSTEM_CALL_FUNCTION, system_call)
+        .map_err(|e| WasmError::InvalidModule(e.to_string()))?;
+
+    let result = linker
+        .instantiate(&mut store, &module)
+        .and_then(|instance| instance.start(&mut store))
+        .map_err(|e| WasmError::InvalidModule(e.to_string()))
+        .and_then(|instance| {
+            let entry = instance
+                .get_typed_func::<(), ()>(&store, entry)
+                .map_err(|e| WasmError::InvalidModule(e.to_string()))?;
+            entry.call(&mut store, ()).map_err(|e| WasmError::Trap(e.to_string()))
+        });
+    if store.data().exhausted {
+        return Err(WasmError::OutOfGas { limit: gas_limit });
+    }
+    result?;
+    let state = store.into_data();
+    Ok(Execution { gas_used: state.used, events: state.events, calls: state.calls })
+}
+
+fn emit_event(
+    mut caller: Caller<'_, HostState>,
+    topics: i32,
+    topic_count: i32,
+    data: i32,
+    data_len: i32,
+) -> Result<(), Trap> {
+    let (topic_count, data_len) = (topic_count as u32 as usize, data_len as u32 as usize);
+    if topic_count > MAX_TOPICS || data_len > MAX_EVENT_DATA {
+        return Err(Trap::new("event too large"));
+    }
+    // Paid for before any memory is read
+    caller.data_mut().charge(events::gas(topic_count, data_len))?;
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| Trap::new("emit_event needs an exported memory"))?;
+    let mut topic_bytes = vec![0u8; topic_count * 32];
+    let mut data_bytes = vec![0u8; data_len];
+    memory.read(&caller, topics as u32 as usize, &mut topic_bytes).map_err(|e| Trap::new(e.to_string()))?;
+    memory.read(&caller, data as u32 as usize, &mut data_bytes).map_err(|e| Trap::new(e.to_string()))?;
+
+    let state = caller.data_mut();
+    let topics = topic_bytes.chunks(32).map(Hash::from).collect();
+    state.events.emit(state.contract, topics, data_bytes).map_err(|e| Trap::new(e.to_string()))
+}
+
+fn system_call(mut caller: Caller<'_, HostState>, target: i32, input: i32, input_len: i32) -> Result<(), Trap> {
+    let input_len = input_len as u32 as usize;
+    if input_len > MAX_SYSTEM_CALL_INPUT || caller.data().calls.len() == MAX_SYSTEM_CALLS {
+        return Err(Trap::new("system call too large"));
+    }
+    caller.data_mut().charge(SystemCall::gas(input_len))?;
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| Trap::new("system_call needs an exported memory"))?;
+    let mut address = [0u8; ADDRESS_LEN];
+    let mut input_bytes = vec![0u8; input_len];
+    memory.read(&caller, target as u32 as usize, &mut address).map_err(|e| Trap::new(e.to_string()))?;
+    memory.read(&caller, input as u32 as usize, &mut input_bytes).map_err(|e| Trap::new(e.to_string()))?;
+
+    let call = SystemCall::decode(&Address::from(address), &input_bytes).map_err(|e| Trap::new(e.to_string()))?;
+    caller.data_mut().calls.push(call);
+    Ok(())
+}
+
+// The schedules adopted so far, each with the height it takes effect at
+pub struct GasScheduleRegistry {
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl GasScheduleRegistry {
+    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
+        Self { storage }
+    }
+
+    // The schedule in force for a block at `height`; the default one until
+    // governance adopts another
+    pub async fn active(&self, height: BlockHeight) -> Result<GasSchedule, WasmError> {
+        let schedules = self.get_schedules().await?;
+        Ok(schedules
+            .into_iter()
+            .rev()
+            .find(|(activation, _)| *activation <= height)
+            .map_or_else(GasSchedule::default, |(_, schedule)| schedule))
+    }
+
+    // Adopts `schedule` from `activation` on. Versions only go up and take
+    // effect in order, so a past block is always charged the same.
+    pub async fn adopt(&mut self, schedule: GasSchedule, activation: BlockHeight) -> Result<(), WasmError> {
+        let mut schedules = self.get_schedules().await?;
+        let (current, in_order) = match schedules.last() {
+            Some((last, adopted)) => (adopted.version, *last < activation),
+            None => (GasSchedule::default().version, true),
+        };
+        if schedule.version <= current || !in_order {
+            return Err(WasmError::StaleSchedule { version: schedule.version, current });
+        }
+        schedules.push((activation, schedule));
+        self.storage.put_value(b"gas_schedules", &schedules).await?;
+        Ok(())
+    }
+
+    async fn get_schedules(&self) -> Result<Vec<(BlockHeight, GasSchedule)>, WasmError> {
+        Ok(self.storage.get_value(b"gas_schedules").await?.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    fn wat(source: &str) -> Vec<u8> {
+        wat::parse_str(source).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_meters_execution_against_the_active_schedule() {
+        let counter = wat(r#"(module
+            (func (export "run") (local i32)
+                (loop $again
+                    (local.set 0 (i32.add (local.get 0) (i32.const 1)))
+                    (br_if $again (i32.lt_u (local.get 0) (i32.const 100))))))"#);
+        let schedule = GasSchedule::default();
+        let code = instrument(&counter, &schedule).unwrap();
+        let run = |code: &[u8], schedule: &GasSchedule, gas_limit| {
+            execute(code, schedule, "run", Address::default(), gas_limit).map(|run| run.gas_used)
+        };
+        let used = run(&code, &schedule, 1_000_000).unwrap();
+        assert!(used > 100 * 6);
+        assert_eq!(run(&code, &schedule, used).unwrap(), used);
+        assert!(matches!(run(&code, &schedule, used - 1), Err(WasmError::OutOfGas { .. })));
+
+        let pricier = GasSchedule { version: 2, instruction: 2, ..GasSchedule::default() };
+        assert!(run(&instrument(&counter, &pricier).unwrap(), &pricier, 1_000_000).unwrap() > used);
+
+        let cheater = wat(r#"(module (import "env" "gas" (func (param i64))) (func (export "run")))"#);
+        assert!(matches!(instrument(&cheater, &schedule), Err(WasmError::InvalidModule(_))));
+
+        let mut registry = GasScheduleRegistry::new(Arc::new(MemoryBackend::new()));
+        registry.adopt(pricier.clone(), BlockHeight::from(10)).await.unwrap();
+        assert_eq!(registry.active(BlockHeight::from(9)).await.unwrap(), schedule);
+        assert_eq!(registry.active(BlockHeight::from(10)).await.unwrap(), pricier);
+        assert!(matches!(registry.adopt(pricier, BlockHeight::from(20)).await, Err(WasmError::StaleSchedule { .. })));
+    }
+
+    #[test]
+    fn test_contracts_emit_events_paid_for_in_gas() {
+        let emitter = wat(r#"(module
+            (import "env" "emit_event" (func $emit (param i32 i32 i32 i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 0) "tttttttttttttttttttttttttttttttt")
+            (data (i32.const 32) "hi")
+            (func (export "run") (call $emit (i32.const 0) (i32.const 1) (i32.const 32) (i32.const 2)))
+            (func (export "flood") (call $emit (i32.const 0) (i32.const 5) (i32.const 32) (i32.const 2))))"#);
+        let schedule = GasSchedule::default();
+        let code = instrument(&emitter, &schedule).unwrap();
+        let contract = Address::random();
+
+        let execution = execute(&code, &schedule, "run", contract, 100_000).unwrap();
+        assert!(execution.gas_used > events::gas(1, 2) + u64::from(schedule.memory_page));
+        let logs = execution.events.logs();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].address, contract);
+        assert_eq!(logs[0].topics[0].as_bytes(), &[b't'; 32][..]);
+        assert_eq!(logs[0].data, b"hi".to_vec());
+
+        assert!(matches!(
+            execute(&code, &schedule, "run", contract, events::gas(1, 2)),
+            Err(WasmError::OutOfGas { .. })
+        ));
+        assert!(matches!(execute(&code, &schedule, "flood", contract, 100_000), Err(WasmError::Trap(_))));
+    }
+
+    #[test]
+    fn test_memory_and_tables_are_bounded() {
+        let schedule = GasSchedule { max_memory_pages: 4, max_table_elements: 8, ..GasSchedule::default() };
+        let hoarder = wat(r#"(module (memory 5) (func (export "run")))"#);
+        assert!(matches!(instrument(&hoarder, &schedule), Err(WasmError::InvalidModule(_))));
+        let dispatcher = wat(r#"(module (table 9 funcref) (func (export "run")))"#);
+        assert!(matches!(instrument(&dispatcher, &schedule), Err(WasmError::InvalidModule(_))));
+
+        // Traps unless memory.grow fails
+        let grower = wat(r#"(module
+            (memory 1)
+            (func (export "run") (if (i32.ne (memory.grow (i32.const 4)) (i32.const -1)) (then unreachable))))"#);
+        let code = instrument(&grower, &schedule).unwrap();
+        let execution = execute(&code, &schedule, "run", Address::default(), 100_000).unwrap();
+        assert!(execution.gas_used >= u64::from(schedule.memory_page));
+        let roomier = GasSchedule { max_memory_pages: 5, ..schedule };
+        assert!(matches!(execute(&code, &roomier, "run", Address::default(), 100_000), Err(WasmError::Trap(_))));
+        assert!(matches!(
+            execute(&code, &schedule, "run", Address::default(), u64::from(schedule.memory_page) - 1),
+            Err(WasmError::OutOfGas { .. })
+        ));
+    }
+}
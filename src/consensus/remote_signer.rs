@@ -0,0 +1,374 @@
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex, OnceCell};
+use zeroize::Zeroizing;
+
+use crate::crypto::keystore::SecretKey;
+use crate::crypto::threshold::{ConsensusSigner, LocalKey, ThresholdError};
+use crate::network::codec::decode_bounded;
+use crate::rpc::admin::load_or_generate_token;
+use crate::utils::shutdown::Shutdown;
+
+const MAX_FRAME_LEN: usize = 64 * 1024;
+const TAG_LEN: usize = 32;
+// Slots whose signing is remembered, the oldest forgotten first
+const REMEMBERED_SLOTS: usize = 4096;
+
+#[derive(Debug, Error)]
+pub enum RemoteSignerError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to read auth key {0}: {1}")]
+    AuthKey(PathBuf, io::Error),
+    #[error("Message not authenticated; do both sides use the same auth key?")]
+    Unauthenticated,
+    #[error("Malformed message: {0}")]
+    Malformed(String),
+    #[error("No answer within {0:?}")]
+    Timeout(Duration),
+    #[error("Signer refused: {0}")]
+    Refused(String),
+    #[error("Signer returned an invalid signature")]
+    InvalidSignature,
+}
+
+// `[consensus.remote_signer]`. The validator asks `omnitensor signer`, run on
+// a hardened host, for block and vote signatures and holds no key itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteSignerConfig {
+    pub enabled: bool,
+    pub address: String,
+    // Created by the signer on first start; copy it to the validator host
+    pub auth_key_file: PathBuf,
+    // What the validator itself has asked to sign, checked before asking
+    pub state_file: PathBuf,
+    pub timeout_secs: u64,
+}
+
+impl Default for RemoteSignerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:7170".to_string(),
+            auth_key_file: PathBuf::from("./data/signer.auth"),
+            state_file: PathBuf::from("./data/sign_state.bin"),
+            timeout_secs: 5,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    PublicKey,
+    Sign { slot: Vec<u8>, message: Vec<u8> },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Response {
+    PublicKey([u8; 32]),
+    Signature(Vec<u8>),
+    Refused(String),
+}
+
+// Which message was signed for each recent slot, saved before a signature is
+// handed out so a restart can't make either side forget it
+pub struct SignState {
+    path: PathBuf,
+    slots: VecDeque<(Vec<u8>, [u8; 32])>,
+}
+
+impl SignState {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, RemoteSignerError> {
+        let path = path.into();
+        let slots = match fs::read(&path) {
+            Ok(bytes) => decode_bounded(&bytes).map_err(|e| RemoteSignerError::Malformed(e.to_string()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, slots })
+    }
+
+    // Whether `message` may be signed for `slot`, i.e. nothing else was.
+    // Signing the same message again is allowed, for retries.
+    pub fn record(&mut self, slot: &[u8], message: &[u8]) -> Result<bool, RemoteSignerError> {
+        let digest: [u8; 32] = Sha256::digest(message).into();
+        if let Some((_, signed)) = self.slots.iter().find(|(signed_slot, _)| signed_slot == slot) {
+            return Ok(*signed == digest);
+        }
+        self.slots.push_back((slot.to_vec(), digest));
+        if self.slots.len() > REMEMBERED_SLOTS {
+            self.slots.pop_front();
+        }
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, bincode::serialize(&self.slots).expect("slots serialize"))?;
+        fs::File::open(&temporary)?.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(true)
+    }
+}
+
+// The validator's side: a `ConsensusSigner` that forwards to the daemon and
+// checks every signature it gets back against the daemon's public key
+pub struct RemoteSigner {
+    config: RemoteSignerConfig,
+    auth_key: Zeroizing<String>,
+    state: std::sync::Mutex<SignState>,
+    connection: Mutex<Option<TcpStream>>,
+    public_key: OnceCell<[u8; 32]>,
+}
+
+impl RemoteSigner {
+    pub fn new(config: RemoteSignerConfig) -> Result<Self, RemoteSignerError> {
+        let auth_key = Zeroizing::new(
+            fs::read_to_string(&config.auth_key_file)
+                .map_err(|e| RemoteSignerError::AuthKey(config.auth_key_file.clone(), e))?,
+        );
+        let state = SignState::open(&config.state_file)?;
+        Ok(Self {
+            config,
+            auth_key: Zeroizing::new(auth_key.trim().to_string()),
+            state: std::sync::Mutex::new(state),
+            connection: Mutex::new(None),
+            public_key: OnceCell::new(),
+        })
+    }
+
+    pub async fn public_key(&self) -> Result<[u8; 32], RemoteSignerError> {
+        let public_key = self
+            .public_key
+            .get_or_try_init(|| async {
+                match self.request(&Request::PublicKey).await? {
+                    Response::PublicKey(public_key) => Ok(public_key),
+                    response => Err(unexpected(response)),
+                }
+            })
+            .await?;
+        Ok(*public_key)
+    }
+
+    async fn request(&self, request: &Request) -> Result<Response, RemoteSignerError> {
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        tokio::time::timeout(timeout, self.exchange(request)).await.map_err(|_| RemoteSignerError::Timeout(timeout))?
+    }
+
+    // Reconnects once if the connection was dropped since the last request
+    async fn exchange(&self, request: &Request) -> Result<Response, RemoteSignerError> {
+        let mut connection = self.connection.lock().await;
+        for attempt in 0..2 {
+            if connection.is_none() {
+                *connection = Some(TcpStream::connect(&self.config.address).await?);
+            }
+            let stream = connection.as_mut().expect("connected above");
+            let result = match write_frame(stream, &self.auth_key, request).await {
+                Ok(()) => read_frame(stream, &self.auth_key).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(response) => return Ok(response),
+                Err(RemoteSignerError::Io(_)) if attempt == 0 => *connection = None,
+                Err(e) => {
+                    *connection = None;
+                    return Err(e);
+                }
+            }
+        }
+        unreachable!("the second attempt returns")
+    }
+
+    async fn sign_remotely(&self, slot: &[u8], message: &[u8]) -> Result<[u8; 64], RemoteSignerError> {
+        let public_key = self.public_key().await?;
+        let request = Request::Sign { slot: slot.to_vec(), message: message.to_vec() };
+        let signature = match self.request(&request).await? {
+            Response::Signature(signature) => signature,
+            Response::Refused(reason) => return Err(RemoteSignerError::Refused(reason)),
+            response => return Err(unexpected(response)),
+        };
+        let public_key = ed25519_dalek::PublicKey::from_bytes(&public_key);
+        let parsed = ed25519_dalek::Signature::from_bytes(&signature);
+        match (public_key, parsed) {
+            (Ok(public_key), Ok(parsed)) if public_key.verify_strict(message, &parsed).is_ok() => {
+                Ok(signature.try_into().expect("64 bytes"))
+            }
+            _ => Err(RemoteSignerError::InvalidSignature),
+        }
+    }
+}
+
+#[async_trait]
+impl ConsensusSigner for RemoteSigner {
+    async fn sign(&self, slot: &[u8], message: &[u8]) -> Result<[u8; 64], ThresholdError> {
+        let remote = |e: RemoteSignerError| ThresholdError::Remote(e.to_string());
+        if !self.state.lock().unwrap().record(slot, message).map_err(remote)? {
+            return Err(ThresholdError::AlreadySigned);
+        }
+        self.sign_remotely(slot, message).await.map_err(remote)
+    }
+}
+
+// The daemon holding the validator key. It refuses a second message for any
+// slot it signed for, whatever the validator asks.
+pub struct SignerDaemon {
+    key: LocalKey,
+    public_key: [u8; 32],
+    auth_key: Zeroizing<String>,
+    state: std::sync::Mutex<SignState>,
+}
+
+impl SignerDaemon {
+    pub fn new(secret: SecretKey, auth_key_file: &Path, state_file: &Path) -> Result<Self, RemoteSignerError> {
+        let ed25519_secret = ed25519_dalek::SecretKey::from_bytes(secret.expose())
+            .map_err(|e| RemoteSignerError::Malformed(format!("validator key: {}", e)))?;
+        let public_key = ed25519_dalek::PublicKey::from(&ed25519_secret).to_bytes();
+        let auth_key = Zeroizing::new(
+            load_or_generate_token(auth_key_file).map_err(|e| RemoteSignerError::AuthKey(auth_key_file.into(), e))?,
+        );
+        let state = std::sync::Mutex::new(SignState::open(state_file)?);
+        Ok(Self { key: LocalKey::new(secret), public_key, auth_key, state })
+    }
+
+    pub async fn serve(self: Arc<Self>, listen: SocketAddr, shutdown: Shutdown) -> Result<(), RemoteSignerError> {
+        let listener = TcpListener::bind(listen).await?;
+        info!("Remote signer listening on {}", listener.local_addr()?);
+        loop {
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                _ = shutdown.wait() => return Ok(()),
+            };
+            let daemon = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = daemon.handle(stream).await {
+                    warn!("Signer connection from {} closed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> Result<(), RemoteSignerError> {
+        loop {
+            let request = match read_frame(&mut stream, &self.auth_key).await {
+                Err(RemoteSignerError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                request => request?,
+            };
+            let response = self.respond(request).await;
+            write_frame(&mut stream, &self.auth_key, &response).await?;
+        }
+    }
+
+    async fn respond(&self, request: Request) -> Response {
+        let (slot, message) = match request {
+            Request::PublicKey => return Response::PublicKey(self.public_key),
+            Request::Sign { slot, message } => (slot, message),
+        };
+        let recorded = self.state.lock().unwrap().record(&slot, &message);
+        match recorded {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!("Refused to sign a second message for slot {:02x?}", slot);
+                return Response::Refused("another message was signed for this slot".to_string());
+            }
+            Err(e) => return Response::Refused(format!("failed to save the signing state: {}", e)),
+        }
+        match self.key.sign(&slot, &message).await {
+            Ok(signature) => Response::Signature(signature.to_vec()),
+            Err(e) => Response::Refused(e.to_string()),
+        }
+    }
+}
+
+fn unexpected(response: Response) -> RemoteSignerError {
+    RemoteSignerError::Malformed(format!("unexpected response {:?}", response))
+}
+
+// `[length][bincode][HMAC-SHA256 of the bincode]`, in both directions
+async fn write_frame<T: Serialize>(stream: &mut TcpStream, key: &str, message: &T) -> Result<(), RemoteSignerError> {
+    let mut frame = bincode::serialize(message).map_err(|e| RemoteSignerError::Malformed(e.to_string()))?;
+    frame.extend_from_slice(&mac(key, &frame).finalize().into_bytes());
+    stream.write_all(&(frame.len() as u32).to_be_bytes()).await?;
+    stream.write_all(&frame).await?;
+    Ok(stream.flush().await?)
+}
+
+async fn read_frame<T: DeserializeOwned>(stream: &mut TcpStream, key: &str) -> Result<T, RemoteSignerError> {
+    let len = stream.read_u32().await? as usize;
+    if !(TAG_LEN..=MAX_FRAME_LEN).contains(&len) {
+        return Err(RemoteSignerError::Malformed(format!("frame of {} bytes", len)));
+    }
+    let mut frame = vec![0u8; len];
+    stream.read_exact(&mut frame).await?;
+    let (payload, tag) = frame.split_at(len - TAG_LEN);
+    mac(key, payload).verify_slice(tag).map_err(|_| RemoteSignerError::Unauthenticated)?;
+    decode_bounded(payload).map_err(|e| RemoteSignerError::Malformed(e.to_string()))
+}
+
+fn mac(key: &str, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(payload);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_signs_remotely_and_refuses_double_signing() {
+        let temp_dir = TempDir::new().unwrap();
+        let auth_key_file = temp_dir.path().join("signer.auth");
+        let daemon = SignerDaemon::new(SecretKey::from(vec![4; 32]), &auth_key_file, &temp_dir.path().join("daemon"));
+        let daemon = Arc::new(daemon.unwrap());
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let shutdown = Shutdown::new();
+        let server = tokio::spawn(daemon.clone().serve(address, shutdown.clone()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let config = RemoteSignerConfig {
+            enabled: true,
+            address: address.to_string(),
+            auth_key_file: auth_key_file.clone(),
+            state_file: temp_dir.path().join("validator"),
+            ..RemoteSignerConfig::default()
+        };
+        let signer = RemoteSigner::new(config.clone()).unwrap();
+        let expected = LocalKey::new(SecretKey::from(vec![4; 32])).sign(b"parent", b"block").await.unwrap();
+        assert_eq!(signer.sign(b"parent", b"block").await.unwrap(), expected);
+        // Retrying the same block is fine, a conflicting one is refused on the validator
+        assert_eq!(signer.sign(b"parent", b"block").await.unwrap(), expected);
+        assert_eq!(signer.sign(b"parent", b"other block").await, Err(ThresholdError::AlreadySigned));
+
+        // ...and by the daemon, even if the validator's state is lost
+        let lost = RemoteSignerConfig { state_file: temp_dir.path().join("lost"), ..config.clone() };
+        let forgetful = RemoteSigner::new(lost).unwrap();
+        assert!(matches!(forgetful.sign(b"parent", b"other block").await, Err(ThresholdError::Remote(_))));
+        assert!(!SignState::open(temp_dir.path().join("daemon")).unwrap().record(b"parent", b"other").unwrap());
+
+        let wrong_key_file = temp_dir.path().join("wrong.auth");
+        std::fs::write(&wrong_key_file, "wrong").unwrap();
+        let intruder = RemoteSignerConfig {
+            auth_key_file: wrong_key_file,
+            state_file: temp_dir.path().join("intruder"),
+            ..config
+        };
+        let intruder = RemoteSigner::new(intruder).unwrap();
+        assert!(matches!(intruder.sign(b"next parent", b"block").await, Err(ThresholdError::Remote(_))));
+        shutdown.trigger();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::types::{Address, Balance, BlockHeight};
 use crate::crypto::hash::Hash;
-use crate::storage::Storage;
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::utils::decimal::Decimal;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Stake {
@@ -20,18 +23,21 @@ pub enum StakeManagerError {
     InsufficientBalance,
     #[error("Stake not found for address")]
     StakeNotFound,
+    #[error("Reward overflows")]
+    RewardOverflow,
     #[error("Storage error: {0}")]
-    StorageError(#[from] crate::storage::StorageError),
+    StorageError(#[from] DatabaseError),
 }
 
-pub struct StakeManager<S: Storage> {
-    storage: S,
+pub struct StakeManager {
+    storage: Arc<dyn StorageBackend>,
     min_stake: Balance,
-    reward_rate: f64,
+    // Reward per staked unit per block
+    reward_rate: Decimal,
 }
 
-impl<S: Storage> StakeManager<S> {
-    pub fn new(storage: S, min_stake: Balance, reward_rate: f64) -> Self {
+impl StakeManager {
+    pub fn new(storage: Arc<dyn StorageBackend>, min_stake: Balance, reward_rate: Decimal) -> Self {
         Self {
             storage,
             min_stake,
@@ -39,12 +45,17 @@ impl<S: Storage> StakeManager<S> {
         }
     }
 
-    pub fn stake(&mut self, address: Address, amount: Balance) -> Result<(), StakeManagerError> {
+    // Takes effect for new stakes; existing ones below it stay bonded
+    pub fn set_min_stake(&mut self, min_stake: Balance) {
+        self.min_stake = min_stake;
+    }
+
+    pub async fn stake(&mut self, address: Address, amount: Balance) -> Result<(), StakeManagerError> {
         if amount < self.min_stake {
             return Err(StakeManagerError::InsufficientBalance);
         }
 
-        let mut stakes = self.get_stakes()?;
+        let mut stakes = self.get_stakes().await?;
         let stake = stakes.entry(address).or_insert(Stake {
             amount: Balance::zero(),
             staked_at: Utc::now(),
@@ -52,13 +63,13 @@ impl<S: Storage> StakeManager<S> {
         });
 
         stake.amount += amount;
-        self.storage.set(b"stakes", &stakes)?;
+        self.storage.put_value(b"stakes", &stakes).await?;
 
         Ok(())
     }
 
-    pub fn unstake(&mut self, address: Address, amount: Balance) -> Result<Balance, StakeManagerError> {
-        let mut stakes = self.get_stakes()?;
+    pub async fn unstake(&mut self, address: Address, amount: Balance) -> Result<Balance, StakeManagerError> {
+        let mut stakes = self.get_stakes().await?;
         let stake = stakes.get_mut(&address).ok_or(StakeManagerError::StakeNotFound)?;
 
         if stake.amount < amount {
@@ -70,44 +81,42 @@ impl<S: Storage> StakeManager<S> {
             stakes.remove(&address);
         }
 
-        self.storage.set(b"stakes", &stakes)?;
+        self.storage.put_value(b"stakes", &stakes).await?;
 
         Ok(amount)
     }
 
-    pub fn calculate_rewards(&self, address: Address, current_height: BlockHeight) -> Result<Balance, StakeManagerError> {
-        let stakes = self.get_stakes()?;
+    pub async fn calculate_rewards(&self, address: Address, current_height: BlockHeight) -> Result<Balance, StakeManagerError> {
+        let stakes = self.get_stakes().await?;
         let stake = stakes.get(&address).ok_or(StakeManagerError::StakeNotFound)?;
 
         let blocks_since_last_reward = current_height - stake.last_reward_height;
-        let reward = (stake.amount.as_f64() * self.reward_rate * blocks_since_last_reward.as_f64()).round() as u64;
-
-        Ok(Balance::from(reward))
+        self.reward_rate
+            .checked_mul(Decimal::from_integer(blocks_since_last_reward.as_u64()))
+            .and_then(|rate| rate.checked_mul_balance(stake.amount))
+            .ok_or(StakeManagerError::RewardOverflow)
     }
 
-    pub fn distribute_rewards(&mut self, current_height: BlockHeight) -> Result<(), StakeManagerError> {
-        let mut stakes = self.get_stakes()?;
+    pub async fn distribute_rewards(&mut self, current_height: BlockHeight) -> Result<(), StakeManagerError> {
+        let mut stakes = self.get_stakes().await?;
 
         for (address, stake) in stakes.iter_mut() {
-            let reward = self.calculate_rewards(*address, current_height)?;
+            let reward = self.calculate_rewards(*address, current_height).await?;
             stake.amount += reward;
             stake.last_reward_height = current_height;
         }
 
-        self.storage.set(b"stakes", &stakes)?;
+        self.storage.put_value(b"stakes", &stakes).await?;
 
         Ok(())
     }
 
-    fn get_stakes(&self) -> Result<HashMap<Address, Stake>, StakeManagerError> {
-        self.storage
-            .get(b"stakes")
-            .map(|v| v.unwrap_or_default())
-            .map_err(StakeManagerError::from)
+    async fn get_stakes(&self) -> Result<HashMap<Address, Stake>, StakeManagerError> {
+        Ok(self.storage.get_value(b"stakes").await?.unwrap_or_default())
     }
 
-    pub fn get_total_staked(&self) -> Result<Balance, StakeManagerError> {
-        let stakes = self.get_stakes()?;
+    pub async fn get_total_staked(&self) -> Result<Balance, StakeManagerError> {
+        let stakes = self.get_stakes().await?;
         Ok(stakes.values().map(|stake| stake.amount).sum())
     }
 }
@@ -115,37 +124,37 @@ impl<S: Storage> StakeManager<S> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::storage::MemoryStorage;
+    use crate::storage::backend::MemoryBackend;
 
-    #[test]
-    fn test_stake_and_unstake() {
-        let storage = MemoryStorage::new();
-        let mut stake_manager = StakeManager::new(storage, Balance::from(100), 0.001);
+    #[tokio::test]
+    async fn test_stake_and_unstake() {
+        let storage = Arc::new(MemoryBackend::new());
+        let mut stake_manager = StakeManager::new(storage, Balance::from(100), "0.001".parse().unwrap());
 
         let address = Address::random();
         
         // Test staking
-        stake_manager.stake(address, Balance::from(500)).unwrap();
-        assert_eq!(stake_manager.get_total_staked().unwrap(), Balance::from(500));
+        stake_manager.stake(address, Balance::from(500)).await.unwrap();
+        assert_eq!(stake_manager.get_total_staked().await.unwrap(), Balance::from(500));
 
         // Test unstaking
-        let unstaked = stake_manager.unstake(address, Balance::from(200)).unwrap();
+        let unstaked = stake_manager.unstake(address, Balance::from(200)).await.unwrap();
         assert_eq!(unstaked, Balance::from(200));
-        assert_eq!(stake_manager.get_total_staked().unwrap(), Balance::from(300));
+        assert_eq!(stake_manager.get_total_staked().await.unwrap(), Balance::from(300));
     }
 
-    #[test]
-    fn test_rewards_calculation() {
-        let storage = MemoryStorage::new();
-        let mut stake_manager = StakeManager::new(storage, Balance::from(100), 0.001);
+    #[tokio::test]
+    async fn test_rewards_calculation() {
+        let storage = Arc::new(MemoryBackend::new());
+        let mut stake_manager = StakeManager::new(storage, Balance::from(100), "0.001".parse().unwrap());
 
         let address = Address::random();
-        stake_manager.stake(address, Balance::from(1000)).unwrap();
+        stake_manager.stake(address, Balance::from(1000)).await.unwrap();
 
-        let reward = stake_manager.calculate_rewards(address, BlockHeight::from(100)).unwrap();
+        let reward = stake_manager.calculate_rewards(address, BlockHeight::from(100)).await.unwrap();
         assert_eq!(reward, Balance::from(100)); // 1000 * 0.001 * 100 = 100
 
-        stake_manager.distribute_rewards(BlockHeight::from(100)).unwrap();
-        assert_eq!(stake_manager.get_total_staked().unwrap(), Balance::from(1100));
+        stake_manager.distribute_rewards(BlockHeight::from(100)).await.unwrap();
+        assert_eq!(stake_manager.get_total_staked().await.unwrap(), Balance::from(1100));
     }
 }
\ No newline at end of file
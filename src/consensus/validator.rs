@@ -5,22 +5,25 @@ use log::{info, error, warn};
 use crate::types::{Block, Transaction, Hash};
 use crate::network::P2PNetwork;
 use crate::storage::BlockchainDB;
-use crate::crypto::{sign, verify_signature};
+use crate::crypto::verify_signature;
+use crate::crypto::threshold::ConsensusSigner;
+use crate::error::Error;
 
 pub struct Validator {
     node_id: String,
     stake: u64,
-    private_key: Vec<u8>,
+    // The key unlocked from the keystore, or co-signers holding shares of it
+    signer: Arc<dyn ConsensusSigner>,
     network: Arc<P2PNetwork>,
     blockchain: Arc<Mutex<BlockchainDB>>,
 }
 
 impl Validator {
-    pub fn new(node_id: String, stake: u64, private_key: Vec<u8>, network: Arc<P2PNetwork>, blockchain: Arc<Mutex<BlockchainDB>>) -> Self {
+    pub fn new(node_id: String, stake: u64, signer: Arc<dyn ConsensusSigner>, network: Arc<P2PNetwork>, blockchain: Arc<Mutex<BlockchainDB>>) -> Self {
         Validator {
             node_id,
             stake,
-            private_key,
+            signer,
             network,
             blockchain,
         }
@@ -85,16 +88,22 @@ impl Validator {
         }
     }
 
-    async fn propose_block(&self, mut block: Block) -> Result<(), Box<dyn std::error::Error>> {
+    async fn propose_block(&self, mut block: Block) -> Result<(), Error> {
+        // Held to the same bound as blocks from peers, against the network's
+        // time: one stamped by a clock running ahead would only be rejected
+        let now_ms = self.network.clock().network_millis();
+        self.network.clock_config().check_timestamp(block.header.timestamp, now_ms)?;
+
         let block_hash = block.calculate_hash();
-        let signature = sign(&self.private_key, &block_hash);
-        block.signature = signature;
+        // Signed once per parent, so co-signers refuse a conflicting block
+        let signature = self.signer.sign(block.header.prev_hash.as_bytes(), block_hash.as_bytes()).await?;
+        block.signature = signature.to_vec();
 
-        self.network.broadcast_block(block.clone()).await?;
+        self.network.broadcast_block(block.clone()).await.map_err(Error::network)?;
 
         if let Err(e) = self.blockchain.lock().unwrap().add_block(block) {
             error!("Failed to add proposed block to local chain: {:?}", e);
-            return Err(Box::new(e));
+            return Err(Error::storage(e));
         }
 
         info!("Successfully proposed and added new block: {:?}", block_hash);
@@ -115,6 +124,8 @@ impl Validator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::keystore::SecretKey;
+    use crate::crypto::threshold::LocalKey;
     use crate::test_utils::{generate_test_transactions, setup_test_network, setup_test_blockchain};
 
     #[tokio::test]
@@ -124,7 +135,7 @@ mod tests {
         let validator = Validator::new(
             "test_validator".to_string(),
             1000,
-            vec![0; 32], // dummy private key
+            Arc::new(LocalKey::new(SecretKey::from(vec![0; 32]))), // dummy private key
             Arc::new(network),
             Arc::new(Mutex::new(blockchain)),
         );
@@ -0,0 +1,92 @@
+use bech32::{FromBase32, ToBase32, Variant};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::crypto::scheme::{SignatureScheme, ADDRESS_LEN};
+use crate::types::Address;
+
+// Human-readable part of every address string: `omni1...`
+pub const HRP: &str = "omni";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("Address checksum does not match; check for a typo")]
+    Checksum,
+    #[error("Address is for {0}, not omni")]
+    WrongPrefix(String),
+    #[error("Address is {0} bytes, expected {ADDRESS_LEN}")]
+    WrongLength(usize),
+    #[error("Unknown signature scheme {0} in address")]
+    UnknownScheme(u8),
+    #[error("Malformed address: {0}")]
+    Malformed(String),
+}
+
+// The canonical string form of a `[scheme][20 bytes]` address: bech32 with
+// the `omni` prefix, so a mistyped character is caught before funds move
+pub fn encode(address: &[u8]) -> String {
+    bech32::encode(HRP, address.to_base32(), Variant::Bech32).expect("the prefix is valid")
+}
+
+pub fn decode(text: &str) -> Result<[u8; ADDRESS_LEN], AddressError> {
+    let (hrp, data, variant) = bech32::decode(text).map_err(|e| match e {
+        bech32::Error::InvalidChecksum => AddressError::Checksum,
+        e => AddressError::Malformed(e.to_string()),
+    })?;
+    if hrp != HRP {
+        return Err(AddressError::WrongPrefix(hrp));
+    }
+    if variant != Variant::Bech32 {
+        return Err(AddressError::Malformed("bech32m is not used for addresses".to_string()));
+    }
+    let bytes = Vec::<u8>::from_base32(&data).map_err(|e| AddressError::Malformed(e.to_string()))?;
+    let address: [u8; ADDRESS_LEN] =
+        bytes.try_into().map_err(|bytes: Vec<u8>| AddressError::WrongLength(bytes.len()))?;
+    SignatureScheme::try_from(address[0]).map_err(|_| AddressError::UnknownScheme(address[0]))?;
+    Ok(address)
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&encode(self.as_ref()))
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(text: &str) -> Result<Self, AddressError> {
+        decode(text).map(Address::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips_and_catches_typos() {
+        let mut address = [0x5a; ADDRESS_LEN];
+        address[0] = SignatureScheme::Secp256k1 as u8;
+        let text = encode(&address);
+        assert!(text.starts_with("omni1"));
+        assert_eq!(decode(&text), Ok(address));
+        assert_eq!(decode(&text.to_uppercase()), Ok(address));
+
+        let mut typo = text.into_bytes();
+        let last = typo.len() - 1;
+        typo[last] = if typo[last] == b'q' { b'p' } else { b'q' };
+        assert_eq!(decode(std::str::from_utf8(&typo).unwrap()), Err(AddressError::Checksum));
+
+        let foreign = bech32::encode("cosmos", address.to_base32(), Variant::Bech32).unwrap();
+        assert_eq!(decode(&foreign), Err(AddressError::WrongPrefix("cosmos".to_string())));
+        let short = bech32::encode(HRP, address[..20].to_base32(), Variant::Bech32).unwrap();
+        assert_eq!(decode(&short), Err(AddressError::WrongLength(20)));
+        address[0] = 9;
+        assert_eq!(decode(&encode(&address)), Err(AddressError::UnknownScheme(9)));
+
+        let parsed: Address = encode(&[0; ADDRESS_LEN]).parse().unwrap();
+        assert_eq!(parsed.to_string(), encode(&[0; ADDRESS_LEN]));
+    }
+}
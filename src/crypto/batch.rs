@@ -0,0 +1,102 @@
+use ed25519_consensus::{batch, Signature, VerificationKey, VerificationKeyBytes};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BatchError {
+    // Indexes into the batch, in order
+    #[error("{} signatures in the batch do not verify", .0.len())]
+    Invalid(Vec<usize>),
+}
+
+// Checks many ed25519 `(message, signature, public key)` triples at once,
+// several times faster than one by one for the hundreds in a block. When the
+// batch fails, each is checked alone to tell which ones are bad; malformed
+// keys and signatures count as bad. Batch and single checks both follow
+// ZIP 215, the same rules as `SchemeSignature::verify`, so a signature is
+// valid or not regardless of which path checked it.
+pub fn verify_batch(items: &[(&[u8], &[u8], &[u8])]) -> Result<(), BatchError> {
+    let mut parsed = Vec::with_capacity(items.len());
+    let mut malformed = Vec::new();
+    for (index, (message, signature, public_key)) in items.iter().enumerate() {
+        let signature = <[u8; 64]>::try_from(*signature).map(Signature::from);
+        let public_key = <[u8; 32]>::try_from(*public_key).ok().and_then(|key| VerificationKey::try_from(key).ok());
+        match (signature, public_key) {
+            (Ok(signature), Some(public_key)) => parsed.push((index, *message, signature, public_key)),
+            _ => malformed.push(index),
+        }
+    }
+    if malformed.is_empty() {
+        let mut verifier = batch::Verifier::new();
+        for (_, message, signature, public_key) in &parsed {
+            verifier.queue((VerificationKeyBytes::from(*public_key), *signature, *message));
+        }
+        if verifier.verify(rand::thread_rng()).is_ok() {
+            return Ok(());
+        }
+    }
+
+    let mut invalid = malformed;
+    for (index, message, signature, public_key) in parsed {
+        if public_key.verify(&signature, message).is_err() {
+            invalid.push(index);
+        }
+    }
+    if invalid.is_empty() {
+        return Ok(());
+    }
+    invalid.sort_unstable();
+    Err(BatchError::Invalid(invalid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::scheme::SchemeSignature;
+    use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+
+    #[test]
+    fn test_names_every_bad_signature_in_a_batch() {
+        let keys: Vec<Keypair> = (1..=4u8)
+            .map(|seed| {
+                let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+                Keypair { public: PublicKey::from(&secret), secret }
+            })
+            .collect();
+        let messages: Vec<Vec<u8>> = (0..4u8).map(|index| vec![index; 40]).collect();
+        let signatures: Vec<[u8; 64]> =
+            keys.iter().zip(&messages).map(|(key, message)| key.sign(message).to_bytes()).collect();
+        let public_keys: Vec<[u8; 32]> = keys.iter().map(|key| key.public.to_bytes()).collect();
+        let items = |signatures: &[[u8; 64]]| -> Vec<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+            (0..4).map(|i| (messages[i].clone(), signatures[i].to_vec(), public_keys[i].to_vec())).collect()
+        };
+        let check = |items: &[(Vec<u8>, Vec<u8>, Vec<u8>)]| {
+            let borrowed: Vec<(&[u8], &[u8], &[u8])> =
+                items.iter().map(|(m, s, k)| (m.as_slice(), s.as_slice(), k.as_slice())).collect();
+            verify_batch(&borrowed)
+        };
+
+        assert_eq!(check(&items(&signatures)), Ok(()));
+        assert_eq!(verify_batch(&[]), Ok(()));
+
+        let mut tampered = signatures.clone();
+        tampered[1] = signatures[2];
+        let mut tampered = items(&tampered);
+        tampered[3].2.truncate(31);
+        assert_eq!(check(&tampered), Err(BatchError::Invalid(vec![1, 3])));
+    }
+
+    #[test]
+    fn test_batch_and_single_checks_agree_on_small_order_keys() {
+        // The identity as key and R, with s = 0, satisfies the cofactored
+        // equation for any message
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&identity);
+
+        let single = SchemeSignature::Ed25519(signature).verify(b"message", &identity);
+        let items: [(&[u8], &[u8], &[u8]); 1] = [(b"message", &signature, &identity)];
+        let batched = verify_batch(&items).is_ok();
+        assert_eq!(single, batched);
+    }
+}
@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Sha3_256};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+pub const DIGEST_LEN: usize = 32;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum HashError {
+    #[error("Unknown hash algorithm {0}")]
+    UnknownAlgorithm(String),
+    #[error("Malformed tagged hash")]
+    Malformed,
+}
+
+// The 32-byte hash functions in use. Block headers and the state trie stay
+// on SHA3-256; BLAKE3 is several times faster over large inputs such as
+// inference payloads and model weights.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha3_256 = 0,
+    Sha256 = 1,
+    Blake3 = 2,
+}
+
+impl HashAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha3_256 => "sha3-256",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    pub fn digest(self, data: &[u8]) -> [u8; DIGEST_LEN] {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha3_256 => Hasher::Sha3_256(Sha3_256::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    // Hash of two child nodes of a merkle tree
+    pub fn pair(self, left: &[u8; DIGEST_LEN], right: &[u8; DIGEST_LEN]) -> [u8; DIGEST_LEN] {
+        let mut hasher = self.hasher();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize()
+    }
+
+    // Root of a binary tree over `leaves`, duplicating the last node of odd
+    // levels; zero for no leaves
+    pub fn merkle_root(self, mut leaves: Vec<[u8; DIGEST_LEN]>) -> [u8; DIGEST_LEN] {
+        while leaves.len() > 1 {
+            leaves = leaves.chunks(2).map(|pair| self.pair(&pair[0], pair.get(1).unwrap_or(&pair[0]))).collect();
+        }
+        leaves.first().copied().unwrap_or([0; DIGEST_LEN])
+    }
+}
+
+impl TryFrom<u8> for HashAlgorithm {
+    type Error = HashError;
+
+    fn try_from(tag: u8) -> Result<Self, HashError> {
+        match tag {
+            0 => Ok(HashAlgorithm::Sha3_256),
+            1 => Ok(HashAlgorithm::Sha256),
+            2 => Ok(HashAlgorithm::Blake3),
+            tag => Err(HashError::UnknownAlgorithm(tag.to_string())),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = HashError;
+
+    fn from_str(name: &str) -> Result<Self, HashError> {
+        [HashAlgorithm::Sha3_256, HashAlgorithm::Sha256, HashAlgorithm::Blake3]
+            .into_iter()
+            .find(|algorithm| algorithm.name() == name)
+            .ok_or_else(|| HashError::UnknownAlgorithm(name.to_string()))
+    }
+}
+
+// Incremental hashing with any of the algorithms
+#[derive(Clone)]
+pub enum Hasher {
+    Sha3_256(Sha3_256),
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: impl AsRef<[u8]>) {
+        match self {
+            Hasher::Sha3_256(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data.as_ref());
+            }
+        }
+    }
+
+    pub fn finalize(self) -> [u8; DIGEST_LEN] {
+        match self {
+            Hasher::Sha3_256(hasher) => hasher.finalize().into(),
+            Hasher::Sha256(hasher) => hasher.finalize().into(),
+            Hasher::Blake3(hasher) => hasher.finalize().into(),
+        }
+    }
+}
+
+// A digest that names its algorithm, so data hashed with different ones can
+// be told apart: `[algorithm][digest]` in bytes, `blake3:<hex>` in text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TaggedHash {
+    pub algorithm: HashAlgorithm,
+    pub digest: [u8; DIGEST_LEN],
+}
+
+impl TaggedHash {
+    pub fn of(algorithm: HashAlgorithm, data: &[u8]) -> Self {
+        Self { algorithm, digest: algorithm.digest(data) }
+    }
+
+    // Whether `data` hashes to this, with the same algorithm
+    pub fn matches(&self, data: &[u8]) -> bool {
+        self.algorithm.digest(data) == self.digest
+    }
+
+    pub fn to_bytes(&self) -> [u8; DIGEST_LEN + 1] {
+        let mut bytes = [0u8; DIGEST_LEN + 1];
+        bytes[0] = self.algorithm as u8;
+        bytes[1..].copy_from_slice(&self.digest);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HashError> {
+        let (&tag, digest) = bytes.split_first().ok_or(HashError::Malformed)?;
+        let digest = digest.try_into().map_err(|_| HashError::Malformed)?;
+        Ok(Self { algorithm: HashAlgorithm::try_from(tag)?, digest })
+    }
+}
+
+impl fmt::Display for TaggedHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.algorithm.name())?;
+        self.digest.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl FromStr for TaggedHash {
+    type Err = HashError;
+
+    fn from_str(text: &str) -> Result<Self, HashError> {
+        let (name, hex) = text.split_once(':').ok_or(HashError::Malformed)?;
+        if hex.len() != DIGEST_LEN * 2 || !hex.is_ascii() {
+            return Err(HashError::Malformed);
+        }
+        let mut digest = [0u8; DIGEST_LEN];
+        for (i, byte) in digest.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| HashError::Malformed)?;
+        }
+        Ok(Self { algorithm: name.parse()?, digest })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_digests_with_their_algorithm() {
+        let hex = |digest: [u8; 32]| digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        assert_eq!(
+            hex(HashAlgorithm::Sha256.digest(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(HashAlgorithm::Sha3_256.digest(b"abc")),
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"
+        );
+        assert_eq!(
+            hex(HashAlgorithm::Blake3.digest(b"")),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+
+        let mut hasher = HashAlgorithm::Blake3.hasher();
+        hasher.update(b"ab");
+        hasher.update(b"c");
+        assert_eq!(hasher.finalize(), HashAlgorithm::Blake3.digest(b"abc"));
+
+        let tagged = TaggedHash::of(HashAlgorithm::Blake3, b"weights");
+        assert!(tagged.matches(b"weights"));
+        assert_eq!(tagged.to_string().parse::<TaggedHash>(), Ok(tagged));
+        assert_eq!(TaggedHash::from_bytes(&tagged.to_bytes()), Ok(tagged));
+        assert_ne!(TaggedHash::of(HashAlgorithm::Sha3_256, b"weights"), tagged);
+        assert_eq!("md5:00".parse::<TaggedHash>(), Err(HashError::Malformed));
+
+        let leaves = vec![[1; 32], [2; 32], [3; 32]];
+        let blake3 = HashAlgorithm::Blake3;
+        let expected = blake3.pair(&blake3.pair(&[1; 32], &[2; 32]), &blake3.pair(&[3; 32], &[3; 32]));
+        assert_eq!(blake3.merkle_root(leaves), expected);
+        assert_eq!(blake3.merkle_root(Vec::new()), [0; 32]);
+    }
+}
@@ -0,0 +1,236 @@
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use k256::elliptic_curve::{Field, PrimeField};
+use k256::{FieldBytes, Scalar};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha512;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::crypto::keystore::SecretKey;
+use crate::crypto::scheme::SignatureScheme;
+
+// SLIP-44 coin type of OmniTensor keys
+pub const COIN_TYPE: u32 = 9000;
+const HARDENED: u32 = 1 << 31;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum HdError {
+    #[error("Invalid mnemonic: {0}")]
+    Mnemonic(String),
+    #[error("Mnemonics have 12, 15, 18, 21 or 24 words, not {0}")]
+    WordCount(usize),
+    #[error("Malformed derivation path {0}")]
+    MalformedPath(String),
+    #[error("Ed25519 keys only derive at hardened indexes, {0} is not")]
+    NotHardened(String),
+    #[error("Derivation at {0} hit an invalid key; use the next index")]
+    InvalidChild(String),
+}
+
+// `m/44'/9000'/0'/0'/0'`; `'` or `h` marks a hardened index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl FromStr for DerivationPath {
+    type Err = HdError;
+
+    fn from_str(path: &str) -> Result<Self, HdError> {
+        let malformed = || HdError::MalformedPath(path.to_string());
+        let mut parts = path.split('/');
+        if parts.next() != Some("m") {
+            return Err(malformed());
+        }
+        parts
+            .map(|part| {
+                let (index, hardened) = match part.strip_suffix('\'').or_else(|| part.strip_suffix('h')) {
+                    Some(index) => (index, HARDENED),
+                    None => (part, 0),
+                };
+                let index: u32 = index.parse().map_err(|_| malformed())?;
+                if index >= HARDENED {
+                    return Err(malformed());
+                }
+                Ok(index | hardened)
+            })
+            .collect::<Result<_, _>>()
+            .map(DerivationPath)
+    }
+}
+
+impl DerivationPath {
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("m")?;
+        for index in &self.0 {
+            match index & HARDENED {
+                0 => write!(f, "/{}", index)?,
+                _ => write!(f, "/{}'", index & !HARDENED)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// What a key derived from the node's seed is for. Each gets its own branch
+// under `m/44'/9000'/0'`, all hardened so ed25519 can derive them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyPurpose {
+    // Transaction signing accounts, by index
+    Wallet(u32),
+    // Block proposals and votes
+    Validator,
+    // The libp2p identity behind the PeerId
+    Network,
+    // Keys of services run next to the node, e.g. an oracle reporter
+    Service(u32),
+}
+
+impl KeyPurpose {
+    pub fn path(self) -> DerivationPath {
+        let (branch, index) = match self {
+            KeyPurpose::Wallet(index) => (0, index),
+            KeyPurpose::Validator => (1, 0),
+            KeyPurpose::Network => (2, 0),
+            KeyPurpose::Service(index) => (3, index),
+        };
+        DerivationPath(vec![44 | HARDENED, COIN_TYPE | HARDENED, HARDENED, branch | HARDENED, index | HARDENED])
+    }
+}
+
+// A BIP-39 seed, locked in memory and wiped when dropped
+pub struct Seed(SecretKey);
+
+// A new English mnemonic with `words` words of entropy
+pub fn generate_mnemonic(words: usize) -> Result<Zeroizing<String>, HdError> {
+    if !(12..=24).contains(&words) || words % 3 != 0 {
+        return Err(HdError::WordCount(words));
+    }
+    let mut entropy = vec![0u8; words / 3 * 4];
+    OsRng.fill_bytes(&mut entropy);
+    let mnemonic = Mnemonic::from_entropy(&entropy).map_err(|e| HdError::Mnemonic(e.to_string()))?;
+    entropy.zeroize();
+    Ok(Zeroizing::new(mnemonic.to_string()))
+}
+
+// The seed of a mnemonic, checksum verified. `passphrase` is BIP-39's
+// optional extra word, empty for none.
+pub fn seed(phrase: &str, passphrase: &str) -> Result<Seed, HdError> {
+    let phrase = Zeroizing::new(phrase.split_whitespace().collect::<Vec<_>>().join(" "));
+    let phrase = Zeroizing::new(phrase.to_lowercase());
+    let mnemonic = Mnemonic::parse_normalized(&phrase).map_err(|e| HdError::Mnemonic(e.to_string()))?;
+    let seed = Zeroizing::new(mnemonic.to_seed_normalized(passphrase));
+    Ok(Seed(SecretKey::from(seed.to_vec())))
+}
+
+// The secret key at `path`: SLIP-10 for ed25519, BIP-32 for secp256k1, so
+// secp256k1 keys match what EVM wallets derive from the same mnemonic
+pub fn derive(seed: &Seed, scheme: SignatureScheme, path: &DerivationPath) -> Result<SecretKey, HdError> {
+    let curve_key: &[u8] = match scheme {
+        SignatureScheme::Ed25519 => b"ed25519 seed",
+        SignatureScheme::Secp256k1 => b"Bitcoin seed",
+    };
+    let (mut key, mut chain_code) = split(hmac(curve_key, &[seed.0.expose()]));
+    for (depth, &index) in path.0.iter().enumerate() {
+        let at = || DerivationPath(path.0[..=depth].to_vec()).to_string();
+        let mut data = Vec::with_capacity(37);
+        if index & HARDENED != 0 {
+            data.push(0);
+            data.extend_from_slice(&key);
+        } else if scheme == SignatureScheme::Secp256k1 {
+            data.extend_from_slice(&scheme.public_key(&key).map_err(|_| HdError::InvalidChild(at()))?);
+        } else {
+            return Err(HdError::NotHardened(at()));
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+        let (tweak, child_chain_code) = split(hmac(&chain_code, &[&data]));
+        data.zeroize();
+        let child = match scheme {
+            SignatureScheme::Ed25519 => tweak,
+            SignatureScheme::Secp256k1 => add_scalars(&tweak, &key).ok_or_else(|| HdError::InvalidChild(at()))?,
+        };
+        key.zeroize();
+        key = child;
+        chain_code = child_chain_code;
+    }
+    chain_code.zeroize();
+    let secret = SecretKey::from(key.to_vec());
+    key.zeroize();
+    Ok(secret)
+}
+
+// parse256(tweak) + key (mod n), `None` where BIP-32 says to skip the index
+fn add_scalars(tweak: &[u8; 32], key: &[u8; 32]) -> Option<[u8; 32]> {
+    let tweak = Option::<Scalar>::from(Scalar::from_repr(FieldBytes::from(*tweak)))?;
+    let key = Option::<Scalar>::from(Scalar::from_repr(FieldBytes::from(*key)))?;
+    let child = tweak + key;
+    if bool::from(child.is_zero()) {
+        return None;
+    }
+    Some(child.to_bytes().into())
+}
+
+fn hmac(key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in data {
+        mac.update(part);
+    }
+    let mut output = [0u8; 64];
+    output.copy_from_slice(&mac.finalize().into_bytes());
+    output
+}
+
+fn split(mut output: [u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let key = output[..32].try_into().expect("32 bytes");
+    let chain_code = output[32..].try_into().expect("32 bytes");
+    output.zeroize();
+    (key, chain_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_derives_published_test_vectors() {
+        // BIP-39, all-zero entropy with passphrase TREZOR
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let seed = seed(phrase, "TREZOR").unwrap();
+        assert!(
+            hex(seed.0.expose()).starts_with("c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6")
+        );
+        assert!(super::seed("abandon abandon abandon", "").is_err());
+        assert_eq!(generate_mnemonic(24).unwrap().split(' ').count(), 24);
+        assert!(super::seed(&generate_mnemonic(12).unwrap(), "").is_ok());
+        assert_eq!(generate_mnemonic(13), Err(HdError::WordCount(13)));
+
+        // BIP-32 and SLIP-10 test vector 1
+        let seed = Seed((0u8..16).collect());
+        let key = |scheme, path: &str| hex(derive(&seed, scheme, &path.parse().unwrap()).unwrap().expose());
+        let secp256k1 = SignatureScheme::Secp256k1;
+        assert_eq!(key(secp256k1, "m"), "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35");
+        assert_eq!(key(secp256k1, "m/0'"), "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea");
+        assert_eq!(key(secp256k1, "m/0'/1"), "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368");
+        let ed25519 = SignatureScheme::Ed25519;
+        assert_eq!(key(ed25519, "m"), "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7");
+        assert_eq!(key(ed25519, "m/0h"), "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3");
+        assert!(matches!(derive(&seed, ed25519, &"m/0'/1".parse().unwrap()), Err(HdError::NotHardened(_))));
+
+        assert_eq!(KeyPurpose::Wallet(3).path().to_string(), "m/44'/9000'/0'/0'/3'");
+        assert_eq!("m/44'/9000'/0'/1'/0'".parse::<DerivationPath>().unwrap(), KeyPurpose::Validator.path());
+        assert!("44'/0'".parse::<DerivationPath>().is_err());
+        assert!("m/2147483648".parse::<DerivationPath>().is_err());
+    }
+}
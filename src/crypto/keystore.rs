@@ -0,0 +1,504 @@
+use aes::Aes128;
+use argon2::{Algorithm, Argon2, Version};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use zeroize::Zeroize;
+
+use crate::crypto::key_pair::KeyPair;
+use crate::storage::encryption::{self, EncryptionError, KeySource, SALT_LEN};
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+const VERSION: u32 = 3;
+const CIPHER: &str = "aes-128-ctr";
+const DKLEN: usize = 32;
+// Associated data of key files from before the v3 format
+const LEGACY_AAD: &[u8] = b"omnitensor/account";
+
+#[derive(Debug, Error)]
+pub enum KeystoreError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed key file {0}")]
+    Malformed(PathBuf),
+    #[error("No key {0}")]
+    NotFound(String),
+    #[error("Key {0} is already in the keystore")]
+    AlreadyExists(String),
+    #[error("Wrong password for key {0}")]
+    WrongPassword(String),
+    #[error("Refusing to seal a key under an empty password")]
+    EmptyPassword,
+    #[error("{0} is readable by other users; chmod 600 it")]
+    InsecurePermissions(PathBuf),
+    #[error("Unsupported cipher {0}")]
+    UnsupportedCipher(String),
+    #[error("Unsupported or invalid KDF {0}")]
+    UnsupportedKdf(String),
+    #[error("Encryption error: {0}")]
+    Encryption(#[from] EncryptionError),
+}
+
+// A decrypted private key, wiped from memory when dropped. Its pages are
+// locked while it is held so it can't be swapped out to disk.
+pub struct SecretKey(Vec<u8>);
+
+impl SecretKey {
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for SecretKey {
+    fn from(bytes: Vec<u8>) -> Self {
+        lock(&bytes);
+        Self(bytes)
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        let (ptr, len) = (self.0.as_ptr(), self.0.len());
+        self.0.zeroize();
+        // Zeroizing truncates without freeing, so the buffer is still ours
+        unlock(ptr, len);
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey(..)")
+    }
+}
+
+// How the password is stretched into the file's encryption key. Scrypt and
+// PBKDF2 files open in any web3 keystore tooling; argon2id is our own
+// extension to the format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    Scrypt { log_n: u8, r: u32, p: u32 },
+    Pbkdf2 { c: u32 },
+    Argon2id { m: u32, t: u32, p: u32 },
+}
+
+impl Kdf {
+    // geth's standard parameters: 256 MiB and about a second per unlock
+    pub const SCRYPT: Kdf = Kdf::Scrypt { log_n: 18, r: 8, p: 1 };
+    pub const ARGON2ID: Kdf = Kdf::Argon2id { m: 64 * 1024, t: 3, p: 1 };
+
+    fn derive(&self, password: &[u8], salt: &[u8]) -> Result<[u8; DKLEN], KeystoreError> {
+        let mut key = [0u8; DKLEN];
+        let invalid = || KeystoreError::UnsupportedKdf(format!("{:?}", self));
+        match *self {
+            Kdf::Scrypt { log_n, r, p } => {
+                let params = scrypt::Params::new(log_n, r, p, DKLEN).map_err(|_| invalid())?;
+                scrypt::scrypt(password, salt, &params, &mut key).map_err(|_| invalid())?;
+            }
+            Kdf::Pbkdf2 { c } => pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, c, &mut key),
+            Kdf::Argon2id { m, t, p } => {
+                let params = argon2::Params::new(m, t, p, Some(DKLEN)).map_err(|_| invalid())?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(password, salt, &mut key)
+                    .map_err(|_| invalid())?;
+            }
+        }
+        Ok(key)
+    }
+
+    fn to_json(self, salt: &[u8]) -> (String, KdfParams) {
+        let params = KdfParams { dklen: DKLEN as u32, salt: hex(salt), ..KdfParams::default() };
+        match self {
+            Kdf::Scrypt { log_n, r, p } => {
+                ("scrypt".to_string(), KdfParams { n: Some(1 << log_n), r: Some(r), p: Some(p), ..params })
+            }
+            Kdf::Pbkdf2 { c } => {
+                ("pbkdf2".to_string(), KdfParams { c: Some(c), prf: Some("hmac-sha256".to_string()), ..params })
+            }
+            Kdf::Argon2id { m, t, p } => {
+                ("argon2id".to_string(), KdfParams { m: Some(m), t: Some(t), p: Some(p), ..params })
+            }
+        }
+    }
+
+    fn from_json(kdf: &str, params: &KdfParams) -> Option<Self> {
+        if params.dklen as usize != DKLEN {
+            return None;
+        }
+        match kdf {
+            "scrypt" => {
+                let (n, r, p) = (params.n?, params.r?, params.p?);
+                n.is_power_of_two().then(|| Kdf::Scrypt { log_n: n.trailing_zeros() as u8, r, p })
+            }
+            "pbkdf2" if params.prf.as_deref() == Some("hmac-sha256") => Some(Kdf::Pbkdf2 { c: params.c? }),
+            "argon2id" => Some(Kdf::Argon2id { m: params.m?, t: params.t?, p: params.p? }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct KdfParams {
+    dklen: u32,
+    salt: String,
+    // scrypt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    r: Option<u32>,
+    // scrypt and argon2id parallelism
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p: Option<u32>,
+    // pbkdf2
+    #[serde(skip_serializing_if = "Option::is_none")]
+    c: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prf: Option<String>,
+    // argon2id memory in KiB and passes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    m: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    t: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CipherParams {
+    iv: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Crypto {
+    cipher: String,
+    cipherparams: CipherParams,
+    ciphertext: String,
+    kdf: String,
+    kdfparams: KdfParams,
+    mac: String,
+}
+
+// A web3 secret storage (v3) key file. `address` holds the hex of the
+// bincode-encoded public key; files imported from elsewhere keep theirs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyFile {
+    pub version: u32,
+    pub id: String,
+    #[serde(default)]
+    pub address: String,
+    crypto: Crypto,
+}
+
+impl KeyFile {
+    pub fn encrypt(secret: &[u8], address: &str, password: &str, kdf: Kdf) -> Result<Self, KeystoreError> {
+        if password.is_empty() {
+            return Err(KeystoreError::EmptyPassword);
+        }
+        let mut salt = [0u8; 32];
+        let mut iv = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut iv);
+        let mut key = kdf.derive(password.as_bytes(), &salt)?;
+        let mut ciphertext = secret.to_vec();
+        Aes128Ctr::new(key[..16].into(), (&iv).into()).apply_keystream(&mut ciphertext);
+        let mac = mac(&key, &ciphertext);
+        key.zeroize();
+
+        let (kdf, kdfparams) = kdf.to_json(&salt);
+        let crypto = Crypto {
+            cipher: CIPHER.to_string(),
+            cipherparams: CipherParams { iv: hex(&iv) },
+            ciphertext: hex(&ciphertext),
+            kdf,
+            kdfparams,
+            mac: hex(&mac),
+        };
+        Ok(Self { version: VERSION, id: uuid(), address: address.to_string(), crypto })
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<SecretKey, KeystoreError> {
+        let malformed = || KeystoreError::Malformed(PathBuf::from(&self.id));
+        if self.crypto.cipher != CIPHER {
+            return Err(KeystoreError::UnsupportedCipher(self.crypto.cipher.clone()));
+        }
+        let kdf = Kdf::from_json(&self.crypto.kdf, &self.crypto.kdfparams)
+            .ok_or_else(|| KeystoreError::UnsupportedKdf(self.crypto.kdf.clone()))?;
+        let salt = from_hex(&self.crypto.kdfparams.salt).ok_or_else(malformed)?;
+        let iv: [u8; 16] =
+            from_hex(&self.crypto.cipherparams.iv).and_then(|iv| iv.try_into().ok()).ok_or_else(malformed)?;
+        let mut ciphertext = from_hex(&self.crypto.ciphertext).ok_or_else(malformed)?;
+        let expected = from_hex(&self.crypto.mac).ok_or_else(malformed)?;
+
+        let mut key = kdf.derive(password.as_bytes(), &salt)?;
+        let mac = mac(&key, &ciphertext);
+        if !constant_time_eq(&mac, &expected) {
+            key.zeroize();
+            return Err(KeystoreError::WrongPassword(self.address.clone()));
+        }
+        Aes128Ctr::new(key[..16].into(), (&iv).into()).apply_keystream(&mut ciphertext);
+        key.zeroize();
+        Ok(SecretKey::from(ciphertext))
+    }
+}
+
+// The passphrase-sealed account files written before keys moved to the v3
+// format. They still unlock, and are rewritten as v3 when they do.
+#[derive(Debug, Clone, Deserialize)]
+struct LegacyKeyFile {
+    public_key: String,
+    salt: String,
+    sealed: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum StoredKey {
+    V3(KeyFile),
+    Legacy(LegacyKeyFile),
+}
+
+impl StoredKey {
+    fn address(&self) -> &str {
+        match self {
+            StoredKey::V3(file) => &file.address,
+            StoredKey::Legacy(file) => &file.public_key,
+        }
+    }
+}
+
+// Private keys at rest, one password-encrypted `<address>.json` per key.
+// Files must be private to the node's user.
+pub struct Keystore {
+    dir: PathBuf,
+    kdf: Kdf,
+}
+
+impl Keystore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), kdf: Kdf::SCRYPT }
+    }
+
+    // KDF for keys written from now on; existing files keep theirs
+    pub fn with_kdf(mut self, kdf: Kdf) -> Self {
+        self.kdf = kdf;
+        self
+    }
+
+    // Generates a key pair, stores it and returns its address
+    pub fn create(&self, password: &str) -> Result<String, KeystoreError> {
+        let key_pair = KeyPair::generate();
+        let address = hex(&bincode::serialize(key_pair.public_key()).expect("public keys serialize"));
+        self.store(&address, &key_pair.private_key().to_vec(), password)?;
+        Ok(address)
+    }
+
+    // Stores a secret key under an address of the caller's choosing
+    pub fn store(&self, address: &str, secret: &[u8], password: &str) -> Result<(), KeystoreError> {
+        self.write(&KeyFile::encrypt(secret, address, password, self.kdf)?, false)
+    }
+
+    // Copies a v3 key file made elsewhere into the keystore, after checking
+    // that `password` opens it
+    pub fn import(&self, path: &Path, password: &str) -> Result<String, KeystoreError> {
+        let file: KeyFile =
+            serde_json::from_slice(&fs::read(path)?).map_err(|_| KeystoreError::Malformed(path.to_path_buf()))?;
+        if file.address.is_empty() || !file.address.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(KeystoreError::Malformed(path.to_path_buf()));
+        }
+        file.decrypt(password)?;
+        self.write(&file, false)?;
+        Ok(file.address)
+    }
+
+    // Addresses of every stored key, sorted
+    pub fn list(&self) -> Result<Vec<String>, KeystoreError> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut addresses = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |extension| extension == "json") {
+                addresses.push(read(&path)?.address().to_string());
+            }
+        }
+        addresses.sort();
+        Ok(addresses)
+    }
+
+    pub fn unlock(&self, address: &str, password: &str) -> Result<SecretKey, KeystoreError> {
+        let path = self.path(address);
+        if !path.exists() {
+            return Err(KeystoreError::NotFound(address.to_string()));
+        }
+        match read(&path)? {
+            StoredKey::V3(file) => file.decrypt(password),
+            StoredKey::Legacy(file) => {
+                let secret = unseal_legacy(&path, &file, password)?;
+                self.write(&KeyFile::encrypt(secret.expose(), address, password, self.kdf)?, true)?;
+                Ok(secret)
+            }
+        }
+    }
+
+    fn write(&self, file: &KeyFile, replace: bool) -> Result<(), KeystoreError> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(&file.address);
+        if !replace && path.exists() {
+            return Err(KeystoreError::AlreadyExists(file.address.clone()));
+        }
+        // Written aside and renamed, so a crash never leaves half a key
+        let partial = path.with_extension("json.partial");
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut out = options.open(&partial)?;
+        out.write_all(&serde_json::to_vec_pretty(file).expect("key files serialize"))?;
+        out.sync_all()?;
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    fn path(&self, address: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", address))
+    }
+}
+
+fn read(path: &Path) -> Result<StoredKey, KeystoreError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if fs::metadata(path)?.permissions().mode() & 0o077 != 0 {
+            return Err(KeystoreError::InsecurePermissions(path.to_path_buf()));
+        }
+    }
+    serde_json::from_slice(&fs::read(path)?).map_err(|_| KeystoreError::Malformed(path.to_path_buf()))
+}
+
+fn unseal_legacy(path: &Path, file: &LegacyKeyFile, password: &str) -> Result<SecretKey, KeystoreError> {
+    let malformed = || KeystoreError::Malformed(path.to_path_buf());
+    let salt: [u8; SALT_LEN] = from_hex(&file.salt).and_then(|salt| salt.try_into().ok()).ok_or_else(malformed)?;
+    let sealed = from_hex(&file.sealed).ok_or_else(malformed)?;
+    let cipher = KeySource::Passphrase(password.to_string()).master_key(&salt);
+    encryption::open(&cipher, 0, LEGACY_AAD, &sealed).map(SecretKey::from).map_err(|e| match e {
+        EncryptionError::AuthenticationFailed => KeystoreError::WrongPassword(file.public_key.clone()),
+        e => e.into(),
+    })
+}
+
+// Best effort: RLIMIT_MEMLOCK is often small, and an unlocked key is still
+// wiped on drop. Locks don't nest, so dropping a key also unlocks any other
+// key on the same page.
+#[cfg(unix)]
+fn lock(bytes: &[u8]) {
+    if !bytes.is_empty() && unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.len()) } != 0 {
+        log::debug!("Could not lock a secret key in memory: {}", std::io::Error::last_os_error());
+    }
+}
+
+#[cfg(unix)]
+fn unlock(ptr: *const u8, len: usize) {
+    if len > 0 {
+        unsafe { libc::munlock(ptr.cast(), len) };
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_bytes: &[u8]) {}
+
+#[cfg(not(unix))]
+fn unlock(_ptr: *const u8, _len: usize) {}
+
+fn mac(key: &[u8; DKLEN], ciphertext: &[u8]) -> Vec<u8> {
+    Keccak256::new().chain_update(&key[16..]).chain_update(ciphertext).finalize().to_vec()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Random (version 4) UUID
+fn uuid() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex(&bytes);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    // Cheap enough for tests
+    const LIGHT_SCRYPT: Kdf = Kdf::Scrypt { log_n: 10, r: 8, p: 1 };
+
+    #[test]
+    fn test_creates_imports_and_unlocks_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let keystore = Keystore::new(temp_dir.path().join("keystore")).with_kdf(LIGHT_SCRYPT);
+        assert!(keystore.list().unwrap().is_empty());
+        assert!(matches!(keystore.create(""), Err(KeystoreError::EmptyPassword)));
+
+        let address = keystore.create("correct horse").unwrap();
+        assert_eq!(keystore.list().unwrap(), vec![address.clone()]);
+        assert!(!keystore.unlock(&address, "correct horse").unwrap().expose().is_empty());
+        assert!(matches!(keystore.unlock(&address, "battery staple"), Err(KeystoreError::WrongPassword(_))));
+        assert!(matches!(keystore.unlock("00", "correct horse"), Err(KeystoreError::NotFound(_))));
+
+        for kdf in [Kdf::Pbkdf2 { c: 1000 }, Kdf::Argon2id { m: 256, t: 1, p: 1 }] {
+            let file = KeyFile::encrypt(&[7; 32], "aa", "pw", kdf).unwrap();
+            let json = serde_json::to_value(&file).unwrap();
+            assert_eq!(json["version"], 3);
+            let file: KeyFile = serde_json::from_value(json).unwrap();
+            assert_eq!(file.decrypt("pw").unwrap().expose(), &[7; 32]);
+        }
+        let path = temp_dir.path().join("external.json");
+        fs::write(&path, serde_json::to_vec(&KeyFile::encrypt(&[9; 32], "bb", "pw", LIGHT_SCRYPT).unwrap()).unwrap())
+            .unwrap();
+        assert!(matches!(keystore.import(&path, "wrong"), Err(KeystoreError::WrongPassword(_))));
+        assert_eq!(keystore.import(&path, "pw").unwrap(), "bb");
+        assert!(matches!(keystore.import(&path, "pw"), Err(KeystoreError::AlreadyExists(_))));
+
+        // Pre-v3 files unlock once with their passphrase and are upgraded
+        let salt = [3u8; SALT_LEN];
+        let cipher = KeySource::Passphrase("old".to_string()).master_key(&salt);
+        let legacy = serde_json::json!({
+            "public_key": "cc",
+            "salt": hex(&salt),
+            "sealed": hex(&encryption::seal(&cipher, 0, LEGACY_AAD, &[5; 32])),
+        });
+        keystore.write(&KeyFile::encrypt(&[0; 32], "cc", "x", LIGHT_SCRYPT).unwrap(), false).unwrap();
+        fs::write(keystore.path("cc"), legacy.to_string()).unwrap();
+        assert_eq!(keystore.unlock("cc", "old").unwrap().expose(), &[5; 32]);
+        assert!(matches!(read(&keystore.path("cc")).unwrap(), StoredKey::V3(_)));
+        assert_eq!(keystore.unlock("cc", "old").unwrap().expose(), &[5; 32]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(keystore.path(&address), fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(matches!(keystore.unlock(&address, "correct horse"), Err(KeystoreError::InsecurePermissions(_))));
+        }
+    }
+}
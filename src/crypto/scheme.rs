@@ -0,0 +1,234 @@
+use blake2::{Blake2b512, Digest as _};
+use ed25519_consensus::{Signature, VerificationKey};
+use ed25519_dalek::ExpandedSecretKey;
+use k256::ecdsa::{RecoveryId, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+pub const ADDRESS_LEN: usize = 21;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SchemeError {
+    #[error("Unknown signature scheme {0}")]
+    UnknownScheme(u8),
+    #[error("Invalid {0:?} secret key")]
+    InvalidSecretKey(SignatureScheme),
+    #[error("Empty signature")]
+    Empty,
+    #[error("Malformed {0:?} signature")]
+    Malformed(SignatureScheme),
+}
+
+// The first byte of every encoded signature and address. Ed25519 is what
+// validators sign consensus messages with; secp256k1 lets keys from
+// EVM-ecosystem wallets sign transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureScheme {
+    Ed25519 = 0,
+    Secp256k1 = 1,
+}
+
+impl TryFrom<u8> for SignatureScheme {
+    type Error = SchemeError;
+
+    fn try_from(tag: u8) -> Result<Self, SchemeError> {
+        match tag {
+            0 => Ok(SignatureScheme::Ed25519),
+            1 => Ok(SignatureScheme::Secp256k1),
+            tag => Err(SchemeError::UnknownScheme(tag)),
+        }
+    }
+}
+
+impl SignatureScheme {
+    // `[scheme][20 bytes]`: blake2b of an ed25519 key, or the Ethereum
+    // address (keccak-256 of the uncompressed point) of a secp256k1 key, so
+    // an EVM wallet's address is recognizable after the scheme byte
+    pub fn address(self, public_key: &[u8]) -> Option<[u8; ADDRESS_LEN]> {
+        let mut address = [0u8; ADDRESS_LEN];
+        address[0] = self as u8;
+        match self {
+            SignatureScheme::Ed25519 => {
+                ed25519_dalek::PublicKey::from_bytes(public_key).ok()?;
+                address[1..].copy_from_slice(&Blake2b512::digest(public_key)[..20]);
+            }
+            SignatureScheme::Secp256k1 => {
+                let point = VerifyingKey::from_sec1_bytes(public_key).ok()?.to_encoded_point(false);
+                address[1..].copy_from_slice(&Keccak256::digest(&point.as_bytes()[1..])[12..]);
+            }
+        }
+        Some(address)
+    }
+
+    // The public key for a secret key, in the form `address` and `verify` take
+    pub fn public_key(self, secret_key: &[u8]) -> Result<Vec<u8>, SchemeError> {
+        let invalid = || SchemeError::InvalidSecretKey(self);
+        match self {
+            SignatureScheme::Ed25519 => {
+                let secret = ed25519_dalek::SecretKey::from_bytes(secret_key).map_err(|_| invalid())?;
+                Ok(ed25519_dalek::PublicKey::from(&secret).to_bytes().to_vec())
+            }
+            SignatureScheme::Secp256k1 => {
+                let signing = SigningKey::from_slice(secret_key).map_err(|_| invalid())?;
+                Ok(signing.verifying_key().to_encoded_point(true).as_bytes().to_vec())
+            }
+        }
+    }
+}
+
+// A signature that names its scheme. Secp256k1 signatures are over the
+// keccak-256 of the message and carry the recovery id, like Ethereum's, so
+// the signer's address can be recovered without its public key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemeSignature {
+    Ed25519([u8; 64]),
+    Secp256k1 { signature: [u8; 64], recovery: u8 },
+}
+
+impl SchemeSignature {
+    pub fn sign(scheme: SignatureScheme, message: &[u8], secret_key: &[u8]) -> Result<Self, SchemeError> {
+        let invalid = || SchemeError::InvalidSecretKey(scheme);
+        match scheme {
+            SignatureScheme::Ed25519 => {
+                let secret = ed25519_dalek::SecretKey::from_bytes(secret_key).map_err(|_| invalid())?;
+                let public = ed25519_dalek::PublicKey::from(&secret);
+                Ok(SchemeSignature::Ed25519(ExpandedSecretKey::from(&secret).sign(message, &public).to_bytes()))
+            }
+            SignatureScheme::Secp256k1 => {
+                let signing = SigningKey::from_slice(secret_key).map_err(|_| invalid())?;
+                let (signature, recovery) =
+                    signing.sign_prehash_recoverable(&Keccak256::digest(message)).map_err(|_| invalid())?;
+                Ok(SchemeSignature::Secp256k1 { signature: signature.to_bytes().into(), recovery: recovery.to_byte() })
+            }
+        }
+    }
+
+    pub fn scheme(&self) -> SignatureScheme {
+        match self {
+            SchemeSignature::Ed25519(_) => SignatureScheme::Ed25519,
+            SchemeSignature::Secp256k1 { .. } => SignatureScheme::Secp256k1,
+        }
+    }
+
+    // Whether `public_key`, of this signature's scheme, signed `message`
+    pub fn verify(&self, message: &[u8], public_key: &[u8]) -> bool {
+        match self {
+            // ZIP 215 rules, the same as `crypto::batch`, so a transaction
+            // valid in the mempool is valid in a block
+            SchemeSignature::Ed25519(signature) => {
+                let public = <[u8; 32]>::try_from(public_key).ok().and_then(|key| VerificationKey::try_from(key).ok());
+                public.map_or(false, |public| public.verify(&Signature::from(*signature), message).is_ok())
+            }
+            SchemeSignature::Secp256k1 { .. } => {
+                match (self.recover(message), VerifyingKey::from_sec1_bytes(public_key)) {
+                    (Some(recovered), Ok(expected)) => recovered == expected,
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    // The address that signed `message`. Ed25519 signatures can't name their
+    // signer, so they are checked against the public key given.
+    pub fn signer(&self, message: &[u8], public_key: Option<&[u8]>) -> Option<[u8; ADDRESS_LEN]> {
+        match self {
+            SchemeSignature::Ed25519(_) => {
+                let public_key = public_key?;
+                self.verify(message, public_key).then(|| SignatureScheme::Ed25519.address(public_key))?
+            }
+            SchemeSignature::Secp256k1 { .. } => {
+                let recovered = self.recover(message)?.to_encoded_point(true);
+                SignatureScheme::Secp256k1.address(recovered.as_bytes())
+            }
+        }
+    }
+
+    fn recover(&self, message: &[u8]) -> Option<VerifyingKey> {
+        let SchemeSignature::Secp256k1 { signature, recovery } = self else {
+            return None;
+        };
+        let signature = k256::ecdsa::Signature::from_slice(signature).ok()?;
+        let recovery = RecoveryId::from_byte(*recovery)?;
+        VerifyingKey::recover_from_prehash(&Keccak256::digest(message), &signature, recovery).ok()
+    }
+
+    // `[scheme][signature]`, plus the recovery id for secp256k1
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.scheme() as u8];
+        match self {
+            SchemeSignature::Ed25519(signature) => bytes.extend_from_slice(signature),
+            SchemeSignature::Secp256k1 { signature, recovery } => {
+                bytes.extend_from_slice(signature);
+                bytes.push(*recovery);
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SchemeError> {
+        let (&tag, rest) = bytes.split_first().ok_or(SchemeError::Empty)?;
+        let scheme = SignatureScheme::try_from(tag)?;
+        match (scheme, rest.len()) {
+            (SignatureScheme::Ed25519, 64) => Ok(SchemeSignature::Ed25519(rest.try_into().expect("64 bytes"))),
+            (SignatureScheme::Secp256k1, 65) => Ok(SchemeSignature::Secp256k1 {
+                signature: rest[..64].try_into().expect("64 bytes"),
+                recovery: rest[64],
+            }),
+            _ => Err(SchemeError::Malformed(scheme)),
+        }
+    }
+}
+
+impl Serialize for SchemeSignature {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for SchemeSignature {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes: Vec<u8> = Deserialize::deserialize(deserializer)?;
+        SchemeSignature::from_bytes(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signs_and_verifies_with_either_scheme() {
+        let message = b"omnitensor";
+        for scheme in [SignatureScheme::Ed25519, SignatureScheme::Secp256k1] {
+            let secret = [7u8; 32];
+            let public_key = scheme.public_key(&secret).unwrap();
+            let signature = SchemeSignature::sign(scheme, message, &secret).unwrap();
+            assert_eq!(signature.scheme(), scheme);
+            assert!(signature.verify(message, &public_key));
+            assert!(!signature.verify(b"another message", &public_key));
+
+            let address = scheme.address(&public_key).unwrap();
+            assert_eq!(address[0], scheme as u8);
+            assert_eq!(signature.signer(message, Some(&public_key)), Some(address));
+
+            let decoded = SchemeSignature::from_bytes(&signature.to_bytes()).unwrap();
+            assert_eq!(decoded, signature);
+            assert_eq!(
+                bincode::deserialize::<SchemeSignature>(&bincode::serialize(&signature).unwrap()).unwrap(),
+                signature
+            );
+        }
+
+        // Recovered without the public key, like an Ethereum sender
+        let signature = SchemeSignature::sign(SignatureScheme::Secp256k1, message, &[9; 32]).unwrap();
+        let public_key = SignatureScheme::Secp256k1.public_key(&[9; 32]).unwrap();
+        assert_eq!(signature.signer(message, None), SignatureScheme::Secp256k1.address(&public_key));
+        let ed25519_key = SignatureScheme::Ed25519.public_key(&[9; 32]).unwrap();
+        assert!(!signature.verify(message, &ed25519_key));
+
+        assert_eq!(SchemeSignature::from_bytes(&[2; 65]), Err(SchemeError::UnknownScheme(2)));
+        assert_eq!(SchemeSignature::from_bytes(&[1; 65]), Err(SchemeError::Malformed(SignatureScheme::Secp256k1)));
+    }
+}
@@ -0,0 +1,320 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::chain::canonical;
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::crypto::hd::DerivationPath;
+use crate::crypto::keystore::SecretKey;
+use crate::crypto::signature::Signature;
+
+// APDUs of the OmniTensor Ledger app
+const CLA: u8 = 0xe0;
+const INS_GET_PUBLIC_KEY: u8 = 0x02;
+const INS_SIGN: u8 = 0x04;
+// P1 of INS_SIGN: the path comes first, then the transaction in chunks
+const P1_PATH: u8 = 0x00;
+const P1_MORE: u8 = 0x01;
+const P1_LAST: u8 = 0x02;
+const MAX_APDU_DATA: usize = 255;
+
+const SW_OK: u16 = 0x9000;
+const SW_REJECTED: u16 = 0x6985;
+const SW_APP_NOT_OPEN: [u16; 2] = [0x6d00, 0x6e00];
+
+// HID framing of APDUs
+const CHANNEL: u16 = 0x0101;
+const TAG_APDU: u8 = 0x05;
+pub const PACKET_SIZE: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum SignerError {
+    #[error("Transaction error: {0}")]
+    Transaction(String),
+    #[error("The device can't show {0:?} transactions for confirmation")]
+    UnsupportedTransaction(TransactionType),
+    #[error("Rejected on the device")]
+    Rejected,
+    #[error("Open the OmniTensor app on the device")]
+    AppNotOpen,
+    #[error("Device returned status {0:#06x}")]
+    Device(u16),
+    #[error("Malformed response from the device")]
+    MalformedResponse,
+    #[error("Device communication failed: {0}")]
+    Transport(String),
+}
+
+// Whatever holds the key a transaction is signed with. The wallet doesn't
+// care whether that's memory or a device the holder confirms on.
+#[async_trait]
+pub trait TransactionSigner: Send + Sync {
+    async fn sign(&self, transaction: &mut Transaction) -> Result<(), SignerError>;
+}
+
+// A key decrypted from the keystore
+pub struct LocalSigner(SecretKey);
+
+impl LocalSigner {
+    pub fn new(secret: SecretKey) -> Self {
+        Self(secret)
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for LocalSigner {
+    async fn sign(&self, transaction: &mut Transaction) -> Result<(), SignerError> {
+        transaction.sign(self.0.expose()).map_err(|e| SignerError::Transaction(format!("{:?}", e)))
+    }
+}
+
+// Exchanges one APDU with a device, blocking until it answers. Signing waits
+// for the holder to confirm, so this can take a while.
+pub trait LedgerTransport: Send + Sync {
+    fn exchange(&self, apdu: &[u8]) -> Result<Vec<u8>, SignerError>;
+}
+
+// Signs on a Ledger running the OmniTensor app, which shows the recipient,
+// amount and fee for confirmation. The key never leaves the device, so only
+// transfers and staking, which the app can display, are signed.
+pub struct LedgerSigner {
+    transport: Arc<dyn LedgerTransport>,
+    path: DerivationPath,
+}
+
+impl LedgerSigner {
+    pub fn new(transport: Arc<dyn LedgerTransport>, path: DerivationPath) -> Self {
+        Self { transport, path }
+    }
+
+    // The ed25519 public key at the signer's path
+    pub async fn public_key(&self) -> Result<Vec<u8>, SignerError> {
+        let apdus = vec![apdu(INS_GET_PUBLIC_KEY, 0, 0, &encode_path(&self.path))];
+        let public_key = self.exchange(apdus).await?;
+        if public_key.len() != 32 {
+            return Err(SignerError::MalformedResponse);
+        }
+        Ok(public_key)
+    }
+
+    async fn exchange(&self, apdus: Vec<Vec<u8>>) -> Result<Vec<u8>, SignerError> {
+        let transport = self.transport.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut data = Vec::new();
+            for apdu in apdus {
+                data = response_data(transport.exchange(&apdu)?)?;
+            }
+            Ok(data)
+        })
+        .await
+        .map_err(|e| SignerError::Transport(e.to_string()))?
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for LedgerSigner {
+    async fn sign(&self, transaction: &mut Transaction) -> Result<(), SignerError> {
+        match transaction.transaction_type {
+            TransactionType::Transfer | TransactionType::StakeDeposit | TransactionType::StakeWithdraw => {}
+            ref other => return Err(SignerError::UnsupportedTransaction(other.clone())),
+        }
+        transaction.signature = None;
+        // The app hashes the transaction itself, so what it signs is what it showed
+        let bytes = canonical::signing_bytes(transaction);
+        let mut apdus = vec![apdu(INS_SIGN, P1_PATH, 0, &encode_path(&self.path))];
+        let chunks = bytes.chunks(MAX_APDU_DATA);
+        let last = chunks.len() - 1;
+        for (i, chunk) in chunks.enumerate() {
+            apdus.push(apdu(INS_SIGN, if i == last { P1_LAST } else { P1_MORE }, 0, chunk));
+        }
+        let signature = self.exchange(apdus).await?;
+        if signature.len() != 64 {
+            return Err(SignerError::MalformedResponse);
+        }
+        transaction.signature = Some(Signature::from_bytes(&signature).map_err(|_| SignerError::MalformedResponse)?);
+        Ok(())
+    }
+}
+
+fn apdu(ins: u8, p1: u8, p2: u8, data: &[u8]) -> Vec<u8> {
+    let mut apdu = vec![CLA, ins, p1, p2, data.len() as u8];
+    apdu.extend_from_slice(data);
+    apdu
+}
+
+// The number of indexes, then each big-endian
+fn encode_path(path: &DerivationPath) -> Vec<u8> {
+    let mut encoded = vec![path.indexes().len() as u8];
+    for index in path.indexes() {
+        encoded.extend_from_slice(&index.to_be_bytes());
+    }
+    encoded
+}
+
+// Splits off and checks the trailing status word
+fn response_data(mut response: Vec<u8>) -> Result<Vec<u8>, SignerError> {
+    if response.len() < 2 {
+        return Err(SignerError::MalformedResponse);
+    }
+    let status = u16::from_be_bytes([response[response.len() - 2], response[response.len() - 1]]);
+    response.truncate(response.len() - 2);
+    match status {
+        SW_OK => Ok(response),
+        SW_REJECTED => Err(SignerError::Rejected),
+        status if SW_APP_NOT_OPEN.contains(&status) => Err(SignerError::AppNotOpen),
+        status => Err(SignerError::Device(status)),
+    }
+}
+
+// HID packets carrying an APDU: channel, tag and sequence number, then the
+// APDU's length and the APDU itself, zero padded
+pub fn frame(apdu: &[u8]) -> Vec<[u8; PACKET_SIZE]> {
+    let mut data = (apdu.len() as u16).to_be_bytes().to_vec();
+    data.extend_from_slice(apdu);
+    data.chunks(PACKET_SIZE - 5)
+        .enumerate()
+        .map(|(sequence, chunk)| {
+            let mut packet = [0u8; PACKET_SIZE];
+            packet[..2].copy_from_slice(&CHANNEL.to_be_bytes());
+            packet[2] = TAG_APDU;
+            packet[3..5].copy_from_slice(&(sequence as u16).to_be_bytes());
+            packet[5..5 + chunk.len()].copy_from_slice(chunk);
+            packet
+        })
+        .collect()
+}
+
+// Reassembles a response from packets as `read` returns them
+pub fn unframe(mut read: impl FnMut() -> Result<Vec<u8>, SignerError>) -> Result<Vec<u8>, SignerError> {
+    let mut data = Vec::new();
+    let mut length = None;
+    for sequence in 0u16.. {
+        let packet = read()?;
+        if packet.len() < 5
+            || packet[..2] != CHANNEL.to_be_bytes()
+            || packet[2] != TAG_APDU
+            || packet[3..5] != sequence.to_be_bytes()
+        {
+            return Err(SignerError::MalformedResponse);
+        }
+        let mut payload = &packet[5..];
+        if length.is_none() {
+            if payload.len() < 2 {
+                return Err(SignerError::MalformedResponse);
+            }
+            length = Some(u16::from_be_bytes([payload[0], payload[1]]) as usize);
+            payload = &payload[2..];
+        }
+        let length = length.expect("read from the first packet");
+        data.extend_from_slice(&payload[..payload.len().min(length - data.len())]);
+        if data.len() == length {
+            break;
+        }
+    }
+    Ok(data)
+}
+
+// A Ledger plugged in over USB
+#[cfg(feature = "ledger")]
+pub mod hid {
+    use hidapi::{HidApi, HidDevice};
+    use std::sync::Mutex;
+
+    use super::{frame, unframe, LedgerTransport, SignerError, PACKET_SIZE};
+
+    const VENDOR_ID: u16 = 0x2c97;
+    const USAGE_PAGE: u16 = 0xffa0;
+
+    pub struct HidTransport(Mutex<HidDevice>);
+
+    impl HidTransport {
+        // The first Ledger found
+        pub fn open() -> Result<Self, SignerError> {
+            let error = |e: hidapi::HidError| SignerError::Transport(e.to_string());
+            let api = HidApi::new().map_err(error)?;
+            let info = api
+                .device_list()
+                .find(|info| {
+                    info.vendor_id() == VENDOR_ID && (info.usage_page() == USAGE_PAGE || info.interface_number() == 0)
+                })
+                .ok_or_else(|| SignerError::Transport("no Ledger connected".to_string()))?;
+            Ok(Self(Mutex::new(info.open_device(&api).map_err(error)?)))
+        }
+    }
+
+    impl LedgerTransport for HidTransport {
+        fn exchange(&self, apdu: &[u8]) -> Result<Vec<u8>, SignerError> {
+            let error = |e: hidapi::HidError| SignerError::Transport(e.to_string());
+            let device = self.0.lock().unwrap();
+            for packet in frame(apdu) {
+                // Report ID 0, then the packet
+                let mut report = vec![0u8];
+                report.extend_from_slice(&packet);
+                device.write(&report).map_err(error)?;
+            }
+            unframe(|| {
+                let mut packet = vec![0u8; PACKET_SIZE];
+                let read = device.read(&mut packet).map_err(error)?;
+                packet.truncate(read);
+                Ok(packet)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hd::KeyPurpose;
+    use crate::types::Address;
+    use std::sync::Mutex;
+
+    // Answers each APDU from a script and records what was sent
+    struct ScriptedDevice {
+        sent: Mutex<Vec<Vec<u8>>>,
+        responses: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl LedgerTransport for ScriptedDevice {
+        fn exchange(&self, apdu: &[u8]) -> Result<Vec<u8>, SignerError> {
+            self.sent.lock().unwrap().push(apdu.to_vec());
+            Ok(self.responses.lock().unwrap().remove(0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ledger_signs_transfers_after_confirmation() {
+        let ok = |data: &[u8]| [data, &SW_OK.to_be_bytes()].concat();
+        let device = Arc::new(ScriptedDevice {
+            sent: Mutex::new(Vec::new()),
+            responses: Mutex::new(vec![ok(&[]), ok(&[]), ok(&[7; 64]), SW_REJECTED.to_be_bytes().to_vec()]),
+        });
+        let signer = LedgerSigner::new(device.clone(), KeyPurpose::Wallet(0).path());
+        let mut transfer = Transaction::new(
+            0,
+            Address::random(),
+            Address::random(),
+            1,
+            1,
+            21000,
+            vec![0; 300],
+            TransactionType::Transfer,
+        );
+
+        signer.sign(&mut transfer).await.unwrap();
+        assert!(transfer.signature.is_some());
+        let sent = device.sent.lock().unwrap().clone();
+        // Path, then 300+ bytes of transaction in two chunks
+        assert_eq!(sent.iter().map(|apdu| apdu[2]).collect::<Vec<_>>(), vec![P1_PATH, P1_MORE, P1_LAST]);
+        assert_eq!(&sent[0][5..], &encode_path(&"m/44'/9000'/0'/0'/0'".parse().unwrap())[..]);
+        assert!(matches!(signer.sign(&mut transfer).await, Err(SignerError::Rejected)));
+
+        transfer.transaction_type = TransactionType::AIModelDeploy;
+        let unsupported = signer.sign(&mut transfer).await;
+        assert!(matches!(unsupported, Err(SignerError::UnsupportedTransaction(TransactionType::AIModelDeploy))));
+
+        let apdu = vec![9u8; 100];
+        let mut packets = frame(&apdu).into_iter().map(|packet| packet.to_vec());
+        assert_eq!(unframe(|| Ok(packets.next().unwrap())).unwrap(), apdu);
+    }
+}
@@ -0,0 +1,412 @@
+use async_trait::async_trait;
+use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use futures::future::join_all;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha512};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use zeroize::Zeroize;
+
+use crate::consensus::remote_signer::SignState;
+use crate::crypto::keystore::SecretKey;
+
+// Slots a co-signer keeps nonces for at a time
+const COMMITTED_SLOTS: usize = 1024;
+const SHARE_LEN: usize = 2 + 32 + 32;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ThresholdError {
+    #[error("Threshold {threshold} of {signers} signers is not possible")]
+    InvalidThreshold { threshold: u16, signers: u16 },
+    #[error("Invalid ed25519 secret key")]
+    InvalidSecretKey,
+    #[error("Malformed key share")]
+    MalformedShare,
+    #[error("Co-signer {0} already signed another message for this slot")]
+    Equivocation(u16),
+    #[error("Co-signer {0} has no commitment for this slot")]
+    UnknownCommitment(u16),
+    #[error("Only {available} of {threshold} co-signers responded")]
+    NotEnoughSigners { available: usize, threshold: u16 },
+    #[error("The signature shares don't combine into a valid signature")]
+    InvalidSignature,
+    #[error("Another message was already signed for this slot")]
+    AlreadySigned,
+    #[error("Sign state: {0}")]
+    SignState(String),
+    #[error("Remote signer: {0}")]
+    Remote(String),
+}
+
+// Signs what a validator proposes. `slot` names the position being signed
+// for, e.g. the parent block, so no two messages are signed for the same one.
+#[async_trait]
+pub trait ConsensusSigner: Send + Sync {
+    async fn sign(&self, slot: &[u8], message: &[u8]) -> Result<[u8; 64], ThresholdError>;
+}
+
+// The whole key on this machine
+pub struct LocalKey(SecretKey);
+
+impl LocalKey {
+    pub fn new(secret: SecretKey) -> Self {
+        Self(secret)
+    }
+}
+
+#[async_trait]
+impl ConsensusSigner for LocalKey {
+    async fn sign(&self, _slot: &[u8], message: &[u8]) -> Result<[u8; 64], ThresholdError> {
+        let secret =
+            ed25519_dalek::SecretKey::from_bytes(self.0.expose()).map_err(|_| ThresholdError::InvalidSecretKey)?;
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        Ok(ed25519_dalek::ExpandedSecretKey::from(&secret).sign(message, &public).to_bytes())
+    }
+}
+
+// One co-signer's share of a validator key
+pub struct KeyShare {
+    index: u16,
+    secret: Scalar,
+    group_public_key: [u8; 32],
+}
+
+impl KeyShare {
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn group_public_key(&self) -> [u8; 32] {
+        self.group_public_key
+    }
+
+    // `[index][secret][group public key]`, for the co-signer's own key file
+    pub fn to_bytes(&self) -> SecretKey {
+        let mut bytes = self.index.to_be_bytes().to_vec();
+        bytes.extend_from_slice(self.secret.as_bytes());
+        bytes.extend_from_slice(&self.group_public_key);
+        SecretKey::from(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ThresholdError> {
+        if bytes.len() != SHARE_LEN {
+            return Err(ThresholdError::MalformedShare);
+        }
+        let index = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let secret = Scalar::from_canonical_bytes(bytes[2..34].try_into().expect("32 bytes"));
+        match secret {
+            Some(secret) if index != 0 => {
+                Ok(Self { index, secret, group_public_key: bytes[34..].try_into().expect("32 bytes") })
+            }
+            _ => Err(ThresholdError::MalformedShare),
+        }
+    }
+}
+
+impl Drop for KeyShare {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+// Splits an ed25519 validator key into `signers` shares, any `threshold` of
+// which sign for it. Signatures stay plain ed25519 under the same public key,
+// so the rest of the network can't tell. The dealer has to be trusted with the
+// whole key and should be an offline machine.
+pub fn split(secret: &[u8], threshold: u16, signers: u16) -> Result<Vec<KeyShare>, ThresholdError> {
+    if threshold == 0 || threshold > signers {
+        return Err(ThresholdError::InvalidThreshold { threshold, signers });
+    }
+    let secret = ed25519_dalek::SecretKey::from_bytes(secret).map_err(|_| ThresholdError::InvalidSecretKey)?;
+    let group_public_key = ed25519_dalek::PublicKey::from(&secret).to_bytes();
+    // The scalar ed25519 signs with: the clamped first half of SHA-512(seed)
+    let mut expanded: [u8; 32] = Sha512::digest(secret.as_bytes())[..32].try_into().expect("32 bytes");
+    expanded[0] &= 248;
+    expanded[31] &= 127;
+    expanded[31] |= 64;
+    let mut coefficients = vec![Scalar::from_bytes_mod_order(expanded)];
+    coefficients.extend((1..threshold).map(|_| random_scalar()));
+    let shares = (1..=signers)
+        .map(|index| {
+            let x = Scalar::from(index as u64);
+            let secret = coefficients.iter().rev().fold(Scalar::zero(), |value, coefficient| value * x + coefficient);
+            KeyShare { index, secret, group_public_key }
+        })
+        .collect();
+    coefficients.iter_mut().for_each(Zeroize::zeroize);
+    expanded.zeroize();
+    Ok(shares)
+}
+
+// A co-signer's nonce commitments for one slot (round one of FROST)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commitment {
+    pub index: u16,
+    pub hiding: [u8; 32],
+    pub binding: [u8; 32],
+}
+
+// What the coordinator asks the chosen co-signers to sign (round two)
+#[derive(Debug, Clone)]
+pub struct SigningPackage {
+    pub slot: Vec<u8>,
+    pub message: Vec<u8>,
+    // Sorted by index
+    pub commitments: Vec<Commitment>,
+}
+
+// A machine holding one share, local or reached over the network
+#[async_trait]
+pub trait CoSigner: Send + Sync {
+    async fn commit(&self, slot: &[u8]) -> Result<Commitment, ThresholdError>;
+    async fn sign(&self, package: &SigningPackage) -> Result<[u8; 32], ThresholdError>;
+}
+
+struct Nonces {
+    hiding: Scalar,
+    binding: Scalar,
+}
+
+// Either nonce together with the signature share reveals the key share
+impl Drop for Nonces {
+    fn drop(&mut self) {
+        self.hiding.zeroize();
+        self.binding.zeroize();
+    }
+}
+
+// A share held in this process. It signs at most one message per slot, so a
+// compromised coordinator can't get two conflicting blocks signed; the slots
+// are kept in `state_file` and survive a restart.
+pub struct ShareHolder {
+    share: KeyShare,
+    nonces: Mutex<HashMap<Vec<u8>, Nonces>>,
+    signed: Mutex<SignState>,
+}
+
+impl ShareHolder {
+    pub fn open(share: KeyShare, state_file: impl Into<PathBuf>) -> Result<Self, ThresholdError> {
+        let signed = SignState::open(state_file).map_err(|e| ThresholdError::SignState(e.to_string()))?;
+        Ok(Self { share, nonces: Mutex::new(HashMap::new()), signed: Mutex::new(signed) })
+    }
+}
+
+#[async_trait]
+impl CoSigner for ShareHolder {
+    async fn commit(&self, slot: &[u8]) -> Result<Commitment, ThresholdError> {
+        let nonces = Nonces { hiding: random_scalar(), binding: random_scalar() };
+        let commitment = Commitment {
+            index: self.share.index,
+            hiding: (&nonces.hiding * &ED25519_BASEPOINT_TABLE).compress().to_bytes(),
+            binding: (&nonces.binding * &ED25519_BASEPOINT_TABLE).compress().to_bytes(),
+        };
+        let mut all = self.nonces.lock().unwrap();
+        if all.len() >= COMMITTED_SLOTS {
+            all.clear();
+        }
+        all.insert(slot.to_vec(), nonces);
+        Ok(commitment)
+    }
+
+    async fn sign(&self, package: &SigningPackage) -> Result<[u8; 32], ThresholdError> {
+        let index = self.share.index;
+        // Nonces are used once, whatever happens next
+        let nonces = self.nonces.lock().unwrap().remove(&package.slot);
+        let nonces = nonces.ok_or(ThresholdError::UnknownCommitment(index))?;
+        let own = package.commitments.iter().find(|commitment| commitment.index == index);
+        match own {
+            Some(own) if own.hiding == (&nonces.hiding * &ED25519_BASEPOINT_TABLE).compress().to_bytes() => {}
+            _ => return Err(ThresholdError::UnknownCommitment(index)),
+        }
+        let (group_commitment, binding_factors) =
+            group_commitment(&package.message, &package.commitments).ok_or(ThresholdError::InvalidSignature)?;
+        let challenge = challenge(&group_commitment, &self.share.group_public_key, &package.message);
+        let indexes: Vec<u16> = package.commitments.iter().map(|commitment| commitment.index).collect();
+        let mut share = nonces.hiding
+            + nonces.binding * binding_factors[&index]
+            + lagrange(index, &indexes) * self.share.secret * challenge;
+
+        // On disk before the share leaves, like a remote signer's slots
+        let recorded = self.signed.lock().unwrap().record(&package.slot, &package.message);
+        match recorded {
+            Ok(true) => Ok(share.to_bytes()),
+            Ok(false) => {
+                share.zeroize();
+                Err(ThresholdError::Equivocation(index))
+            }
+            Err(e) => {
+                share.zeroize();
+                Err(ThresholdError::SignState(e.to_string()))
+            }
+        }
+    }
+}
+
+// Collects `threshold` signature shares from the co-signers into one
+// ed25519 signature under the group key
+pub struct ThresholdSigner {
+    cosigners: Vec<Arc<dyn CoSigner>>,
+    threshold: u16,
+    group_public_key: [u8; 32],
+}
+
+impl ThresholdSigner {
+    pub fn new(cosigners: Vec<Arc<dyn CoSigner>>, threshold: u16, group_public_key: [u8; 32]) -> Self {
+        Self { cosigners, threshold, group_public_key }
+    }
+}
+
+#[async_trait]
+impl ConsensusSigner for ThresholdSigner {
+    async fn sign(&self, slot: &[u8], message: &[u8]) -> Result<[u8; 64], ThresholdError> {
+        let threshold = self.threshold;
+        let committed = join_all(self.cosigners.iter().map(|cosigner| cosigner.commit(slot))).await;
+        let mut chosen: Vec<(Commitment, Arc<dyn CoSigner>)> = committed
+            .into_iter()
+            .zip(&self.cosigners)
+            .filter_map(|(commitment, cosigner)| Some((commitment.ok()?, cosigner.clone())))
+            .take(threshold as usize)
+            .collect();
+        if chosen.len() < threshold as usize {
+            return Err(ThresholdError::NotEnoughSigners { available: chosen.len(), threshold });
+        }
+        chosen.sort_by_key(|(commitment, _)| commitment.index);
+        let package = SigningPackage {
+            slot: slot.to_vec(),
+            message: message.to_vec(),
+            commitments: chosen.iter().map(|(commitment, _)| commitment.clone()).collect(),
+        };
+
+        let shares = join_all(chosen.iter().map(|(_, cosigner)| cosigner.sign(&package))).await;
+        let mut response = Scalar::zero();
+        for share in shares {
+            response += Scalar::from_canonical_bytes(share?).ok_or(ThresholdError::InvalidSignature)?;
+        }
+        let (group_commitment, _) =
+            group_commitment(message, &package.commitments).ok_or(ThresholdError::InvalidSignature)?;
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&group_commitment.compress().to_bytes());
+        signature[32..].copy_from_slice(response.as_bytes());
+
+        let public = ed25519_dalek::PublicKey::from_bytes(&self.group_public_key);
+        let verified = ed25519_dalek::Signature::from_bytes(&signature)
+            .ok()
+            .zip(public.ok())
+            .map_or(false, |(signature, public)| public.verify_strict(message, &signature).is_ok());
+        if !verified {
+            return Err(ThresholdError::InvalidSignature);
+        }
+        Ok(signature)
+    }
+}
+
+// R = sum of D_i + rho_i * E_i, with each rho_i binding the signer to the
+// message and everyone's commitments
+fn group_commitment(message: &[u8], commitments: &[Commitment]) -> Option<(EdwardsPoint, HashMap<u16, Scalar>)> {
+    let mut encoded = Vec::with_capacity(commitments.len() * 66);
+    for commitment in commitments {
+        encoded.extend_from_slice(&commitment.index.to_be_bytes());
+        encoded.extend_from_slice(&commitment.hiding);
+        encoded.extend_from_slice(&commitment.binding);
+    }
+    let mut group_commitment = EdwardsPoint::default();
+    let mut binding_factors = HashMap::new();
+    for commitment in commitments {
+        let binding_factor =
+            hash_to_scalar(&[b"omnitensor/frost/rho", &commitment.index.to_be_bytes(), message, &encoded]);
+        let hiding = CompressedEdwardsY(commitment.hiding).decompress()?;
+        let binding = CompressedEdwardsY(commitment.binding).decompress()?;
+        group_commitment += hiding + binding * binding_factor;
+        binding_factors.insert(commitment.index, binding_factor);
+    }
+    Some((group_commitment, binding_factors))
+}
+
+// The ed25519 challenge, SHA-512(R || A || M)
+fn challenge(group_commitment: &EdwardsPoint, group_public_key: &[u8; 32], message: &[u8]) -> Scalar {
+    hash_to_scalar(&[group_commitment.compress().as_bytes(), group_public_key, message])
+}
+
+fn lagrange(index: u16, indexes: &[u16]) -> Scalar {
+    let x = Scalar::from(index as u64);
+    let (numerator, denominator) =
+        indexes.iter().filter(|&&other| other != index).fold((Scalar::one(), Scalar::one()), |(num, den), &other| {
+            let other = Scalar::from(other as u64);
+            (num * other, den * (other - x))
+        });
+    numerator * denominator.invert()
+}
+
+fn hash_to_scalar(parts: &[&[u8]]) -> Scalar {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut wide = [0u8; 64];
+    wide.copy_from_slice(&hasher.finalize());
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+fn random_scalar() -> Scalar {
+    let mut bytes = [0u8; 64];
+    OsRng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_any_threshold_of_shares_signs_for_the_validator_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_file = |index: u16| temp_dir.path().join(format!("share{}", index));
+        let secret = [3u8; 32];
+        let shares = split(&secret, 2, 3).unwrap();
+        let group_public_key = shares[0].group_public_key();
+        let holders: Vec<Arc<ShareHolder>> = shares
+            .into_iter()
+            .map(|share| {
+                let file = state_file(share.index());
+                Arc::new(ShareHolder::open(share, file).unwrap())
+            })
+            .collect();
+        let cosigners = |indexes: &[usize]| -> Vec<Arc<dyn CoSigner>> {
+            indexes.iter().map(|&i| holders[i].clone() as Arc<dyn CoSigner>).collect()
+        };
+
+        let signature =
+            ThresholdSigner::new(cosigners(&[1, 2]), 2, group_public_key).sign(b"parent", b"block").await.unwrap();
+        // Verifies like a signature of the undivided key
+        let public = ed25519_dalek::PublicKey::from_bytes(&group_public_key).unwrap();
+        public.verify_strict(b"block", &ed25519_dalek::Signature::from_bytes(&signature).unwrap()).unwrap();
+        assert_eq!(
+            group_public_key,
+            ed25519_dalek::PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(&secret).unwrap()).to_bytes()
+        );
+
+        // Co-signers 1 and 2 refuse a second block on the same parent
+        let signer = ThresholdSigner::new(cosigners(&[0, 1]), 2, group_public_key);
+        assert_eq!(signer.sign(b"parent", b"other block").await, Err(ThresholdError::Equivocation(2)));
+        assert!(signer.sign(b"next parent", b"next block").await.is_ok());
+        let alone = ThresholdSigner::new(cosigners(&[0]), 2, group_public_key).sign(b"later", b"block").await;
+        assert_eq!(alone, Err(ThresholdError::NotEnoughSigners { available: 1, threshold: 2 }));
+
+        // A restarted co-signer still refuses
+        let restarted = KeyShare::from_bytes(holders[1].share.to_bytes().expose()).unwrap();
+        let restarted: Arc<dyn CoSigner> = Arc::new(ShareHolder::open(restarted, state_file(2)).unwrap());
+        let signer =
+            ThresholdSigner::new(vec![holders[0].clone() as Arc<dyn CoSigner>, restarted], 2, group_public_key);
+        assert_eq!(signer.sign(b"parent", b"other block").await, Err(ThresholdError::Equivocation(2)));
+
+        let stored = KeyShare::from_bytes(holders[0].share.to_bytes().expose()).unwrap();
+        assert_eq!((stored.index(), stored.secret), (1, holders[0].share.secret));
+        assert!(matches!(split(&secret, 4, 3), Err(ThresholdError::InvalidThreshold { .. })));
+    }
+}
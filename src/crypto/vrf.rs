@@ -0,0 +1,159 @@
+use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use sha2::{Digest, Sha512};
+use thiserror::Error;
+use zeroize::Zeroize;
+
+// ECVRF-EDWARDS25519-SHA512-TAI of RFC 9381, over the same keys as ed25519
+const SUITE: u8 = 0x03;
+const CHALLENGE_LEN: usize = 16;
+pub const PROOF_LEN: usize = 32 + CHALLENGE_LEN + 32;
+pub const OUTPUT_LEN: usize = 64;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum VrfError {
+    #[error("Invalid ed25519 secret key")]
+    InvalidSecretKey,
+    #[error("Invalid VRF public key")]
+    InvalidPublicKey,
+    #[error("Malformed VRF proof")]
+    MalformedProof,
+    #[error("VRF proof does not verify")]
+    InvalidProof,
+}
+
+// Proves what the unique pseudorandom output of a key for an input is.
+// Anyone with the public key can check it, nobody without the secret key can
+// predict it, and the key holder can't pick a different one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VrfProof {
+    gamma: EdwardsPoint,
+    challenge: Scalar,
+    response: Scalar,
+}
+
+impl VrfProof {
+    // The proven output, e.g. a seed for sampling or leader election
+    pub fn output(&self) -> [u8; OUTPUT_LEN] {
+        hash(&[&[SUITE, 0x03], self.gamma.mul_by_cofactor().compress().as_bytes(), &[0x00]])
+    }
+
+    pub fn to_bytes(&self) -> [u8; PROOF_LEN] {
+        let mut bytes = [0u8; PROOF_LEN];
+        bytes[..32].copy_from_slice(self.gamma.compress().as_bytes());
+        bytes[32..48].copy_from_slice(&self.challenge.as_bytes()[..CHALLENGE_LEN]);
+        bytes[48..].copy_from_slice(self.response.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VrfError> {
+        if bytes.len() != PROOF_LEN {
+            return Err(VrfError::MalformedProof);
+        }
+        let gamma = CompressedEdwardsY::from_slice(&bytes[..32]).decompress().ok_or(VrfError::MalformedProof)?;
+        let mut challenge = [0u8; 32];
+        challenge[..CHALLENGE_LEN].copy_from_slice(&bytes[32..48]);
+        let response = Scalar::from_canonical_bytes(bytes[48..].try_into().expect("32 bytes"));
+        Ok(Self { gamma, challenge: Scalar::from_bits(challenge), response: response.ok_or(VrfError::MalformedProof)? })
+    }
+}
+
+// Proves the output of the ed25519 secret key (seed) for `input`
+pub fn prove(secret_key: &[u8], input: &[u8]) -> Result<VrfProof, VrfError> {
+    let secret = ed25519_dalek::SecretKey::from_bytes(secret_key).map_err(|_| VrfError::InvalidSecretKey)?;
+    let public_key = ed25519_dalek::PublicKey::from(&secret).to_bytes();
+    let mut expanded = hash(&[secret.as_bytes()]);
+    let mut scalar_bytes: [u8; 32] = expanded[..32].try_into().expect("32 bytes");
+    scalar_bytes[0] &= 248;
+    scalar_bytes[31] &= 127;
+    scalar_bytes[31] |= 64;
+    let x = Scalar::from_bytes_mod_order(scalar_bytes);
+    scalar_bytes.zeroize();
+
+    let y = CompressedEdwardsY(public_key).decompress().ok_or(VrfError::InvalidSecretKey)?;
+    let h = encode_to_curve(&public_key, input);
+    let gamma = x * h;
+    // Deterministic nonce, as ed25519 derives its own
+    let k = Scalar::from_bytes_mod_order_wide(&hash(&[&expanded[32..], h.compress().as_bytes()]));
+    expanded.zeroize();
+    let challenge = challenge(&y, &h, &gamma, &(&k * &ED25519_BASEPOINT_TABLE), &(k * h));
+    Ok(VrfProof { gamma, challenge, response: k + challenge * x })
+}
+
+// The output `proof` proves for the key and input, if it does
+pub fn verify(public_key: &[u8], input: &[u8], proof: &VrfProof) -> Result<[u8; OUTPUT_LEN], VrfError> {
+    let encoded: [u8; 32] = public_key.try_into().map_err(|_| VrfError::InvalidPublicKey)?;
+    let y = CompressedEdwardsY(encoded).decompress().ok_or(VrfError::InvalidPublicKey)?;
+    if y.is_small_order() {
+        return Err(VrfError::InvalidPublicKey);
+    }
+    let h = encode_to_curve(&encoded, input);
+    // U = sB - cY, V = sH - cGamma
+    let u = EdwardsPoint::vartime_double_scalar_mul_basepoint(&-proof.challenge, &y, &proof.response);
+    let v = proof.response * h - proof.challenge * proof.gamma;
+    if challenge(&y, &h, &proof.gamma, &u, &v) != proof.challenge {
+        return Err(VrfError::InvalidProof);
+    }
+    Ok(proof.output())
+}
+
+// Try-and-increment: hash with a counter until the digest is a point
+fn encode_to_curve(public_key: &[u8; 32], input: &[u8]) -> EdwardsPoint {
+    (0..=u8::MAX)
+        .find_map(|counter| {
+            let digest = hash(&[&[SUITE, 0x01], public_key, input, &[counter, 0x00]]);
+            let point = CompressedEdwardsY::from_slice(&digest[..32]).decompress()?.mul_by_cofactor();
+            Some(point)
+        })
+        .expect("a point within 256 attempts")
+}
+
+fn challenge(y: &EdwardsPoint, h: &EdwardsPoint, gamma: &EdwardsPoint, u: &EdwardsPoint, v: &EdwardsPoint) -> Scalar {
+    let points = [y, h, gamma, u, v].map(|point| point.compress().to_bytes());
+    let mut parts: Vec<&[u8]> = vec![&[SUITE, 0x02]];
+    parts.extend(points.iter().map(|point| &point[..]));
+    parts.push(&[0x00]);
+    let mut challenge = [0u8; 32];
+    challenge[..CHALLENGE_LEN].copy_from_slice(&hash(&parts)[..CHALLENGE_LEN]);
+    Scalar::from_bits(challenge)
+}
+
+fn hash(parts: &[&[u8]]) -> [u8; 64] {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut digest = [0u8; 64];
+    digest.copy_from_slice(&hasher.finalize());
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proves_unique_verifiable_outputs() {
+        let secret = [5u8; 32];
+        let public_key = ed25519_dalek::PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(&secret).unwrap());
+        let public_key = public_key.to_bytes();
+
+        let proof = prove(&secret, b"height 42").unwrap();
+        assert_eq!(verify(&public_key, b"height 42", &proof), Ok(proof.output()));
+        assert_eq!(prove(&secret, b"height 42").unwrap(), proof);
+        assert_ne!(prove(&secret, b"height 43").unwrap().output(), proof.output());
+        assert_ne!(prove(&[6; 32], b"height 42").unwrap().output(), proof.output());
+
+        let decoded = VrfProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(decoded, proof);
+        assert_eq!(verify(&public_key, b"height 43", &proof), Err(VrfError::InvalidProof));
+        let mut tampered = proof.to_bytes();
+        tampered[40] ^= 1;
+        assert_eq!(
+            verify(&public_key, b"height 42", &VrfProof::from_bytes(&tampered).unwrap()),
+            Err(VrfError::InvalidProof)
+        );
+        assert_eq!(VrfProof::from_bytes(&[0; 10]), Err(VrfError::MalformedProof));
+    }
+}
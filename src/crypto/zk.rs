@@ -0,0 +1,135 @@
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{prepare_verifying_key, Groth16, PreparedVerifyingKey, Proof, VerifyingKey};
+use ark_serialize::CanonicalDeserialize;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ZkError {
+    #[error("Invalid verifying key encoding")]
+    InvalidVerifyingKey,
+    #[error("Invalid proof encoding")]
+    InvalidProof,
+    #[error("Expected {expected} public inputs, got {actual}")]
+    PublicInputCount { expected: usize, actual: usize },
+    #[error("Verification failed: {0}")]
+    Verification(String),
+}
+
+// Groth16 verifier over BN254 with a pre-processed verifying key.
+// Keys and proofs use the arkworks compressed canonical encoding.
+pub struct Groth16Verifier {
+    pvk: PreparedVerifyingKey<Bn254>,
+    public_inputs: usize,
+}
+
+impl Groth16Verifier {
+    pub fn from_bytes(vk_bytes: &[u8]) -> Result<Self, ZkError> {
+        let vk = VerifyingKey::<Bn254>::deserialize_compressed(vk_bytes)
+            .map_err(|_| ZkError::InvalidVerifyingKey)?;
+        // gamma_abc_g1 holds one element for the constant term plus one per public input
+        let public_inputs = vk.gamma_abc_g1.len().checked_sub(1).ok_or(ZkError::InvalidVerifyingKey)?;
+        Ok(Self {
+            pvk: prepare_verifying_key(&vk),
+            public_inputs,
+        })
+    }
+
+    pub fn public_input_count(&self) -> usize {
+        self.public_inputs
+    }
+
+    pub fn verify(&self, proof_bytes: &[u8], public_inputs: &[Fr]) -> Result<bool, ZkError> {
+        if public_inputs.len() != self.public_input_count() {
+            return Err(ZkError::PublicInputCount {
+                expected: self.public_input_count(),
+                actual: public_inputs.len(),
+            });
+        }
+
+        let proof = Proof::<Bn254>::deserialize_compressed(proof_bytes)
+            .map_err(|_| ZkError::InvalidProof)?;
+
+        Groth16::<Bn254>::verify_proof(&self.pvk, &proof, public_inputs)
+            .map_err(|e| ZkError::Verification(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_circuit {
+    use super::*;
+    use ark_relations::lc;
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError, Variable};
+
+    // Proves knowledge of `sum = a + b` for public inputs a and b
+    #[derive(Clone)]
+    pub struct SumCircuit {
+        pub a: Option<Fr>,
+        pub b: Option<Fr>,
+    }
+
+    impl ConstraintSynthesizer<Fr> for SumCircuit {
+        fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+            let a = cs.new_input_variable(|| self.a.ok_or(SynthesisError::AssignmentMissing))?;
+            let b = cs.new_input_variable(|| self.b.ok_or(SynthesisError::AssignmentMissing))?;
+            let sum = cs.new_witness_variable(|| {
+                Ok(self.a.ok_or(SynthesisError::AssignmentMissing)? + self.b.ok_or(SynthesisError::AssignmentMissing)?)
+            })?;
+            cs.enforce_constraint(lc!() + a + b, lc!() + Variable::One, lc!() + sum)?;
+            Ok(())
+        }
+    }
+
+    // The same with any number of public inputs
+    #[derive(Clone)]
+    pub struct InputsCircuit {
+        pub inputs: Vec<Fr>,
+    }
+
+    impl ConstraintSynthesizer<Fr> for InputsCircuit {
+        fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+            let mut inputs = lc!();
+            for input in &self.inputs {
+                inputs = inputs + cs.new_input_variable(|| Ok(*input))?;
+            }
+            let sum = cs.new_witness_variable(|| Ok(self.inputs.iter().sum()))?;
+            cs.enforce_constraint(inputs, lc!() + Variable::One, lc!() + sum)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_circuit::SumCircuit;
+    use super::*;
+    use ark_serialize::CanonicalSerialize;
+    use ark_snark::SNARK;
+    use ark_std::test_rng;
+
+    #[test]
+    fn test_groth16_roundtrip() {
+        let mut rng = test_rng();
+        let (a, b) = (Fr::from(3u64), Fr::from(5u64));
+        let circuit = SumCircuit { a: Some(a), b: Some(b) };
+
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(circuit.clone(), &mut rng).unwrap();
+        let proof = Groth16::<Bn254>::prove(&pk, circuit, &mut rng).unwrap();
+
+        let mut vk_bytes = Vec::new();
+        vk.serialize_compressed(&mut vk_bytes).unwrap();
+        let mut proof_bytes = Vec::new();
+        proof.serialize_compressed(&mut proof_bytes).unwrap();
+
+        let verifier = Groth16Verifier::from_bytes(&vk_bytes).unwrap();
+        assert_eq!(verifier.public_input_count(), 2);
+        assert!(verifier.verify(&proof_bytes, &[a, b]).unwrap());
+        assert!(!verifier.verify(&proof_bytes, &[a, a]).unwrap());
+        assert!(verifier.verify(&proof_bytes, &[a]).is_err());
+
+        let mut empty = vk.clone();
+        empty.gamma_abc_g1.clear();
+        let mut empty_bytes = Vec::new();
+        empty.serialize_compressed(&mut empty_bytes).unwrap();
+        assert!(matches!(Groth16Verifier::from_bytes(&empty_bytes), Err(ZkError::InvalidVerifyingKey)));
+    }
+}
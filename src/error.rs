@@ -0,0 +1,172 @@
+use std::error::Error as StdError;
+use thiserror::Error;
+
+use crate::rpc::jsonrpc::{RpcError, CONSENSUS_ERROR, EXECUTION_ERROR, NETWORK_ERROR, STORAGE_ERROR};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+type Source = Box<dyn StdError + Send + Sync>;
+
+// Every failure a node component can report, by the part of the node it
+// came from. Module errors convert into their category with `?`; errors of
+// other types are wrapped with the constructors below. The original error
+// stays reachable through `source()`.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Storage error: {0}")]
+    Storage(#[source] Source),
+    #[error("Network error: {0}")]
+    Network(#[source] Source),
+    // A block, vote or signature that breaks the chain's rules, or failing to
+    // produce one
+    #[error("Consensus error: {0}")]
+    Consensus(#[source] Source),
+    // A transaction or system module rejecting an operation
+    #[error("Execution error: {0}")]
+    Execution(#[source] Source),
+    // Already in the shape RPC returns, code included
+    #[error("RPC error {}: {}", .0.code, .0.message)]
+    Rpc(RpcError),
+}
+
+impl Error {
+    pub fn storage(error: impl Into<Source>) -> Self {
+        Error::Storage(error.into())
+    }
+
+    pub fn network(error: impl Into<Source>) -> Self {
+        Error::Network(error.into())
+    }
+
+    pub fn consensus(error: impl Into<Source>) -> Self {
+        Error::Consensus(error.into())
+    }
+
+    pub fn execution(error: impl Into<Source>) -> Self {
+        Error::Execution(error.into())
+    }
+
+    // The JSON-RPC error code the category is reported with
+    pub fn code(&self) -> i64 {
+        match self {
+            Error::Storage(_) => STORAGE_ERROR,
+            Error::Network(_) => NETWORK_ERROR,
+            Error::Consensus(_) => CONSENSUS_ERROR,
+            Error::Execution(_) => EXECUTION_ERROR,
+            Error::Rpc(error) => error.code,
+        }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(error: Error) -> Self {
+        let code = error.code();
+        match error {
+            Error::Storage(source) | Error::Network(source) | Error::Consensus(source) | Error::Execution(source) => {
+                RpcError::new(code, source.to_string())
+            }
+            Error::Rpc(error) => error,
+        }
+    }
+}
+
+impl From<RpcError> for Error {
+    fn from(error: RpcError) -> Self {
+        Error::Rpc(error)
+    }
+}
+
+macro_rules! categorize {
+    ($category:ident: $($error:ty),+ $(,)?) => {
+        $(
+            impl From<$error> for Error {
+                fn from(error: $error) -> Self {
+                    Error::$category(Box::new(error))
+                }
+            }
+        )+
+    };
+}
+
+categorize!(Storage:
+    crate::storage::StorageError,
+    crate::storage::db::DatabaseError,
+    crate::storage::ancient::AncientError,
+    crate::storage::snapshot::SnapshotError,
+    crate::storage::encryption::EncryptionError,
+    crate::chain::store::ChainStoreError,
+    crate::chain::history::HistoryError,
+    crate::chain::archive::ArchiveError,
+);
+
+categorize!(Network:
+    crate::network::header_sync::HeaderSyncError,
+    crate::network::light_client::LightClientError,
+    crate::network::compression::CompressionError,
+    crate::network::psk::PskError,
+    crate::network::keystore::IdentityError,
+);
+
+categorize!(Consensus:
+    crate::consensus::stake_manager::StakeManagerError,
+    crate::consensus::remote_signer::RemoteSignerError,
+    crate::crypto::signer::SignerError,
+    crate::crypto::threshold::ThresholdError,
+    crate::crypto::vrf::VrfError,
+    crate::crypto::batch::BatchError,
+    crate::chain::verify::VerifyError,
+    crate::network::clock::TimestampError,
+    crate::chain::genesis::GenesisError,
+    crate::chain::upgrade::UpgradeError,
+    crate::chain::params::ParamStoreError,
+);
+
+categorize!(Execution:
+    crate::chain::wasm::WasmError,
+    crate::chain::precompiles::PrecompileError,
+    crate::chain::system::SystemError,
+    crate::chain::events::EventError,
+    crate::chain::governance::GovernanceError,
+    crate::chain::params::ParamsError,
+    crate::chain::treasury::TreasuryError,
+    crate::chain::rent::RentError,
+    crate::chain::circuit_breaker::BreakerError,
+    crate::chain::key_registry::KeyRegistryError,
+    crate::chain::verifying_keys::VerifyingKeyError,
+    crate::crypto::zk::ZkError,
+    crate::ai::jobs::JobError,
+    crate::ai::invoke::InvokeError,
+    crate::ai::access::AccessError,
+    crate::ai::availability::AvailabilityError,
+    crate::ai::marketplace::MarketplaceError,
+    crate::ai::result_cache::ResultCacheError,
+    crate::ai::verifiable::VerifiableInferenceError,
+    crate::oracle::feed::OracleError,
+    crate::utils::decimal::DecimalError,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::system::SystemError;
+    use crate::network::psk::PskError;
+    use crate::rpc::jsonrpc::STATE_PRUNED;
+
+    #[test]
+    fn test_module_errors_keep_their_category_through_rpc() {
+        fn execute() -> Result<()> {
+            Err(SystemError::UnknownContract)?
+        }
+        let error = execute().unwrap_err();
+        assert!(matches!(error, Error::Execution(_)));
+        assert_eq!(error.source().unwrap().to_string(), SystemError::UnknownContract.to_string());
+        let rpc = RpcError::from(error);
+        assert_eq!((rpc.code, rpc.message.as_str()), (EXECUTION_ERROR, "No system contract at this address"));
+
+        let io = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        assert_eq!(Error::network(io).code(), NETWORK_ERROR);
+        assert_eq!(Error::from(PskError::InvalidKey).code(), NETWORK_ERROR);
+        let pruned = RpcError::new(STATE_PRUNED, "pruned");
+        assert_eq!(RpcError::from(Error::from(pruned.clone())), pruned);
+    }
+}
@@ -0,0 +1,111 @@
+use thiserror::Error;
+
+use crate::chain::block::BlockHeader;
+use crate::network::light_client::{LightClientError, SignedHeader, ValidatorSet};
+use crate::storage::proof::StateProof;
+
+// What the `light` feature builds: header and commit verification, state
+// proofs and the RPC client, without storage or networking, so wallets and
+// SDKs can check what a node tells them.
+pub use crate::network::light_client::{Validator, ValidatorSetDiff, ValidatorSignature};
+pub use crate::rpc::client::{ClientError, RpcClient};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LightError {
+    #[error("Header verification failed: {0}")]
+    Header(#[from] LightClientError),
+    #[error("No header verified at height {0}")]
+    UnknownHeight(u64),
+    #[error("State proof does not match the state root at height {0}")]
+    InvalidProof(u64),
+}
+
+// Follows the chain header by header from a trusted validator set, e.g. the
+// genesis set or one from a checkpoint, and checks state against the roots
+// of the headers it verified. Keeps the most recent `retain` headers.
+pub struct LightClient {
+    validators: ValidatorSet,
+    headers: Vec<(u64, BlockHeader)>,
+    retain: usize,
+}
+
+impl LightClient {
+    pub fn new(trusted: ValidatorSet, retain: usize) -> Self {
+        Self { validators: trusted, headers: Vec::new(), retain: retain.max(1) }
+    }
+
+    // Height of the last verified header, or of the trusted set
+    pub fn height(&self) -> u64 {
+        self.validators.height
+    }
+
+    pub fn validators(&self) -> &ValidatorSet {
+        &self.validators
+    }
+
+    pub fn header(&self, height: u64) -> Option<&BlockHeader> {
+        self.headers.iter().find(|(at, _)| *at == height).map(|(_, header)| header)
+    }
+
+    // Verifies the header following the last one; on success it becomes the
+    // new head and its validator set diff is applied
+    pub fn verify_header(&mut self, signed: &SignedHeader) -> Result<(), LightError> {
+        self.validators = self.validators.verify(signed)?;
+        self.headers.push((signed.height, signed.header.clone()));
+        if self.headers.len() > self.retain {
+            self.headers.remove(0);
+        }
+        Ok(())
+    }
+
+    // Checks that `key` held `value` (`None` for absent) after the block at
+    // `height`
+    pub fn verify_state(
+        &self,
+        height: u64,
+        key: &[u8],
+        value: Option<&[u8]>,
+        proof: &StateProof,
+    ) -> Result<(), LightError> {
+        let header = self.header(height).ok_or(LightError::UnknownHeight(height))?;
+        match proof.verify(&header.state_root, key, value) {
+            true => Ok(()),
+            false => Err(LightError::InvalidProof(height)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::block::Block;
+    use crate::crypto::key_pair::KeyPair;
+    use crate::crypto::signature::Signature;
+
+    #[test]
+    fn test_checks_state_against_verified_headers_only() {
+        let key = KeyPair::generate();
+        let trusted = ValidatorSet::new(0, vec![Validator { public_key: key.public_key().clone(), power: 1 }]);
+        let mut client = LightClient::new(trusted, 2);
+
+        let mut header = Block::new([0; 32], vec![], 1).unwrap().header;
+        let proof = StateProof { siblings: Vec::new(), leaf: None };
+        for height in 1..=3 {
+            let diff = ValidatorSetDiff::default();
+            let signature = Signature::sign(&SignedHeader::signing_bytes(height, &header, &diff), key.private_key());
+            let signatures =
+                vec![ValidatorSignature { validator: key.public_key().clone(), signature: signature.unwrap() }];
+            client.verify_header(&SignedHeader { height, header: header.clone(), diff, signatures }).unwrap();
+            header.state_root = [height as u8; 32];
+        }
+        assert_eq!(client.height(), 3);
+        assert!(client.header(1).is_none());
+        assert_eq!(client.verify_state(1, b"balance", None, &proof), Err(LightError::UnknownHeight(1)));
+        // Empty proofs only hold against the empty root
+        assert_eq!(client.verify_state(2, b"balance", None, &proof), Err(LightError::InvalidProof(2)));
+
+        let unsigned = SignedHeader { height: 4, header, diff: ValidatorSetDiff::default(), signatures: Vec::new() };
+        assert!(matches!(client.verify_header(&unsigned), Err(LightError::Header(_))));
+        assert_eq!(client.height(), 3);
+    }
+}
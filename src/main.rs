@@ -1,19 +1,57 @@
 use clap::{App, Arg};
 use log::{error, info};
 use omnitensor_core::{
+    chain::{
+        archive,
+        check::IntegrityChecker,
+        genesis::GenesisConfig,
+        import::{ImportConfig, ImportPipeline},
+        spec::ChainSpec,
+        store::ChainStore,
+    },
     config::Config,
-    consensus::ConsensusEngine,
-    network::NetworkManager,
-    node::Node,
-    storage::Storage,
+    consensus::{remote_signer::SignerDaemon, ConsensusEngine},
+    crypto::{
+        address,
+        hd::{self, KeyPurpose},
+        keystore::{Kdf, Keystore},
+        scheme::SignatureScheme,
+    },
+    network::{keystore, NetworkManager},
+    node::{
+        dev::{self, InstantSeal},
+        role::NodeRole,
+        Node,
+    },
+    rpc::client::RpcClient,
+    storage::{
+        ancient::AncientStore,
+        backend::{open_backend, BackendKind, StorageBackend},
+        db::{BackupInfo, Database, DatabaseError},
+        migration::{self, MigrationRegistry},
+    },
+    utils::{
+        config_sources::ConfigSources,
+        dashboard::Dashboard,
+        logger,
+        reload::ConfigReloader,
+        shutdown::{Shutdown, DEFAULT_HOOK_TIMEOUT},
+        telemetry::Telemetry,
+    },
 };
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
 use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+// Written by `init`, with paths moved into the chosen data directory
+const DEFAULT_CONFIG: &str = include_str!("../config/config.toml");
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Set up logging
-    env_logger::init();
-
     // Parse command line arguments
     let matches = App::new("OmniTensor Core")
         .version("0.1.0")
@@ -27,34 +65,689 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Sets a custom config file")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("chain")
+                .long("chain")
+                .value_name("CHAIN")
+                .help("mainnet, testnet, dev or a chain spec file; defaults to [core] network")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("set")
+                .long("set")
+                .value_name("KEY=VALUE")
+                .help("Overrides a config field, e.g. rpc.port=9545; wins over the file and OMNITENSOR_* variables")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("tui")
+                .long("tui")
+                .help("Shows a live status dashboard instead of the log; the log goes to omnitensor.log"),
+        )
+        .arg(
+            Arg::with_name("dev")
+                .long("dev")
+                .help("Runs a single validator on the dev chain with funded dev accounts, sealing a block for each transaction"),
+        )
+        .arg(
+            Arg::with_name("dev-block-time")
+                .long("dev-block-time")
+                .value_name("MS")
+                .help("With --dev, also seals a block this often, empty if the pool is")
+                .takes_value(true)
+                .requires("dev"),
+        )
+        .arg(
+            Arg::with_name("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .help("text, or json for one object per line with the fields of the event and its spans")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .global(true),
+        )
+        .subcommand(App::new("run").about("Runs the node (the default without a subcommand)"))
+        .subcommand(
+            App::new("init")
+                .about("Creates a data directory with a default config and the genesis block")
+                .arg(
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .value_name("DIR")
+                        .help("Data directory")
+                        .takes_value(true)
+                        .default_value("./data"),
+                )
+                .arg(
+                    Arg::with_name("genesis")
+                        .long("genesis")
+                        .value_name("FILE")
+                        .help("Genesis file of the network to join, instead of the chain spec's")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("keygen")
+                .about("Creates the node's network identity key and prints its peer id")
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .default_value("./data/node.key"),
+                )
+                .arg(
+                    Arg::with_name("mnemonic-env")
+                        .long("mnemonic-env")
+                        .value_name("VAR")
+                        .help("Derives the key from the mnemonic in this environment variable instead")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("account")
+                .about("Manages transaction signing accounts")
+                .subcommand(
+                    App::new("new")
+                        .about("Creates an account sealed under a passphrase and prints its public key")
+                        .arg(
+                            Arg::with_name("keystore")
+                                .long("keystore")
+                                .value_name("DIR")
+                                .takes_value(true)
+                                .default_value("./data/keystore"),
+                        )
+                        .arg(
+                            Arg::with_name("passphrase-env")
+                                .long("passphrase-env")
+                                .value_name("VAR")
+                                .help("Environment variable holding the passphrase")
+                                .takes_value(true)
+                                .default_value("OMNITENSOR_ACCOUNT_PASSPHRASE"),
+                        )
+                        .arg(
+                            Arg::with_name("kdf")
+                                .long("kdf")
+                                .help("Key derivation for the key file")
+                                .possible_values(&["scrypt", "argon2id"])
+                                .takes_value(true)
+                                .default_value("scrypt"),
+                        ),
+                )
+                .subcommand(
+                    App::new("mnemonic").about("Prints a new BIP-39 mnemonic to derive keys from").arg(
+                        Arg::with_name("words")
+                            .long("words")
+                            .possible_values(&["12", "15", "18", "21", "24"])
+                            .takes_value(true)
+                            .default_value("24"),
+                    ),
+                )
+                .subcommand(
+                    App::new("derive")
+                        .about("Derives a key from a mnemonic into the keystore and prints its address")
+                        .arg(
+                            Arg::with_name("keystore")
+                                .long("keystore")
+                                .value_name("DIR")
+                                .takes_value(true)
+                                .default_value("./data/keystore"),
+                        )
+                        .arg(
+                            Arg::with_name("passphrase-env")
+                                .long("passphrase-env")
+                                .value_name("VAR")
+                                .help("Environment variable holding the passphrase for the key file")
+                                .takes_value(true)
+                                .default_value("OMNITENSOR_ACCOUNT_PASSPHRASE"),
+                        )
+                        .arg(
+                            Arg::with_name("mnemonic-env")
+                                .long("mnemonic-env")
+                                .value_name("VAR")
+                                .help("Environment variable holding the mnemonic")
+                                .takes_value(true)
+                                .default_value("OMNITENSOR_MNEMONIC"),
+                        )
+                        .arg(
+                            Arg::with_name("purpose")
+                                .long("purpose")
+                                .possible_values(&["wallet", "validator", "service"])
+                                .takes_value(true)
+                                .default_value("wallet"),
+                        )
+                        .arg(
+                            Arg::with_name("index")
+                                .long("index")
+                                .value_name("N")
+                                .help("Wallet account or service number")
+                                .takes_value(true)
+                                .default_value("0"),
+                        )
+                        .arg(
+                            Arg::with_name("scheme")
+                                .long("scheme")
+                                .possible_values(&["ed25519", "secp256k1"])
+                                .takes_value(true)
+                                .default_value("ed25519"),
+                        ),
+                )
+                .subcommand(
+                    App::new("import")
+                        .about("Copies a web3 (v3) key file into the keystore after checking its passphrase")
+                        .arg(
+                            Arg::with_name("keystore")
+                                .long("keystore")
+                                .value_name("DIR")
+                                .takes_value(true)
+                                .default_value("./data/keystore"),
+                        )
+                        .arg(
+                            Arg::with_name("passphrase-env")
+                                .long("passphrase-env")
+                                .value_name("VAR")
+                                .help("Environment variable holding the passphrase")
+                                .takes_value(true)
+                                .default_value("OMNITENSOR_ACCOUNT_PASSPHRASE"),
+                        )
+                        .arg(Arg::with_name("file").value_name("FILE").help("Key file to import").required(true)),
+                )
+                .subcommand(
+                    App::new("list").about("Prints the public key of every account").arg(
+                        Arg::with_name("keystore")
+                            .long("keystore")
+                            .value_name("DIR")
+                            .takes_value(true)
+                            .default_value("./data/keystore"),
+                    ),
+                ),
+        )
+        .subcommand(
+            App::new("signer")
+                .about("Holds a validator key and signs for a validator configured with [consensus.remote_signer]")
+                .arg(
+                    Arg::with_name("account")
+                        .long("account")
+                        .value_name("ADDRESS")
+                        .help("Validator key in the keystore")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("keystore")
+                        .long("keystore")
+                        .value_name("DIR")
+                        .takes_value(true)
+                        .default_value("./data/keystore"),
+                )
+                .arg(
+                    Arg::with_name("passphrase-env")
+                        .long("passphrase-env")
+                        .value_name("VAR")
+                        .takes_value(true)
+                        .default_value("OMNITENSOR_ACCOUNT_PASSPHRASE"),
+                )
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .value_name("ADDR")
+                        .takes_value(true)
+                        .default_value("127.0.0.1:7170"),
+                )
+                .arg(
+                    Arg::with_name("auth-key")
+                        .long("auth-key")
+                        .value_name("FILE")
+                        .help("Key validators authenticate with, created if missing")
+                        .takes_value(true)
+                        .default_value("./data/signer.auth"),
+                )
+                .arg(
+                    Arg::with_name("state")
+                        .long("state")
+                        .value_name("FILE")
+                        .help("Record of signed slots, kept to refuse double signing")
+                        .takes_value(true)
+                        .default_value("./data/signer_state.bin"),
+                ),
+        )
+        .subcommand(
+            App::new("backup")
+                .about("Creates a backup of the node database, through the admin RPC while the node runs")
+                .arg(
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .value_name("DIR")
+                        .help("Backup directory")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("keep")
+                        .long("keep")
+                        .value_name("N")
+                        .help("Number of most recent backups to keep (0 keeps all)")
+                        .takes_value(true)
+                        .default_value("0"),
+                ),
+        )
+        .subcommand(
+            App::new("restore")
+                .about("Restores the node database from the latest backup; the node must be stopped")
+                .arg(
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .value_name("DIR")
+                        .help("Backup directory")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("db").about("Database maintenance").subcommand(
+                App::new("check")
+                    .about("Verifies stored headers, bodies, receipts and indexes")
+                    .arg(
+                        Arg::with_name("repair")
+                            .long("repair")
+                            .help("Deletes everything from the first corrupt block so it is re-fetched"),
+                    ),
+            ),
+        )
+        .subcommand(
+            App::new("export-chain")
+                .alias("export")
+                .about("Writes blocks and receipts to a portable chain archive")
+                .arg(Arg::with_name("from").long("from").value_name("HEIGHT").takes_value(true).default_value("0"))
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .value_name("HEIGHT")
+                        .help("Last height to export, defaults to the chain head")
+                        .takes_value(true),
+                )
+                .arg(Arg::with_name("file").value_name("FILE").help("Archive to write (.otar)").required(true)),
+        )
+        .subcommand(
+            App::new("import-chain")
+                .alias("import")
+                .about("Bootstraps the chain from a portable chain archive")
+                .arg(Arg::with_name("file").value_name("FILE").help("Archive to read (.otar)").required(true)),
+        )
         .get_matches();
 
-    // Load configuration
+    // Set up logging. RUST_LOG still filters per module; the overall level
+    // comes from `[core] log_level` once the config is read.
+    let log_file = if matches.is_present("tui") { Some(File::create("omnitensor.log")?) } else { None };
+    logger::init(matches.value_of("log-format").unwrap().parse()?, log_file)?;
+
+    // Commands that run before there is a config
+    match matches.subcommand() {
+        ("init", Some(args)) => {
+            let dir = Path::new(args.value_of("dir").unwrap());
+            let config_path = dir.join("config.toml");
+            if config_path.exists() {
+                error!("{} already exists", config_path.display());
+                process::exit(1);
+            }
+            let chain = args.value_of("chain").unwrap_or("mainnet");
+            let mut spec = ChainSpec::load(chain)?;
+            if let Some(path) = args.value_of("genesis") {
+                spec.genesis = GenesisConfig::load(Path::new(path))?;
+            }
+            let genesis = spec.genesis.clone();
+            fs::create_dir_all(dir)?;
+            genesis.save(&dir.join("genesis.json"))?;
+            // A custom spec is copied next to the config, which then names it
+            let chain = match chain {
+                "mainnet" | "testnet" | "dev" | "devnet" => chain.to_string(),
+                _ => {
+                    let path = dir.join("chain.json");
+                    spec.save(&path)?;
+                    path.display().to_string()
+                }
+            };
+            let default_config = DEFAULT_CONFIG
+                .replace("./data/", &format!("{}/", dir.display()))
+                .replacen("network = \"mainnet\"", &format!("network = {:?}", chain), 1);
+            fs::write(&config_path, default_config)?;
+            let config = Config::from_file(&config_path.to_string_lossy())?;
+            let database = Database::open(&config.storage.database_path, config.storage.database_options()?)?;
+            let store = ChainStore::new(Arc::new(database));
+            let head = genesis.initialize(&store).await?;
+            let hash: String = head.hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+            info!("Initialized {} with genesis {}", dir.display(), hash);
+            return Ok(());
+        }
+        ("keygen", Some(args)) => {
+            let path = Path::new(args.value_of("out").unwrap());
+            if path.exists() {
+                error!("{} already exists; remove it first to give the node a new identity", path.display());
+                process::exit(1);
+            }
+            let keypair = match args.value_of("mnemonic-env") {
+                Some(variable) => {
+                    let phrase =
+                        Zeroizing::new(std::env::var(variable).map_err(|_| format!("{} is not set", variable))?);
+                    let seed = hd::seed(&phrase, "")?;
+                    let secret = hd::derive(&seed, SignatureScheme::Ed25519, &KeyPurpose::Network.path())?;
+                    keystore::create_from_secret(path, secret.expose())?
+                }
+                None => keystore::load_or_generate(path)?,
+            };
+            println!("{}", keypair.public().to_peer_id());
+            return Ok(());
+        }
+        ("account", Some(account_args)) => {
+            match account_args.subcommand() {
+                ("new", Some(args)) => {
+                    let variable = args.value_of("passphrase-env").unwrap();
+                    let passphrase =
+                        Zeroizing::new(std::env::var(variable).map_err(|_| format!("{} is not set", variable))?);
+                    let kdf = match args.value_of("kdf") {
+                        Some("argon2id") => Kdf::ARGON2ID,
+                        _ => Kdf::SCRYPT,
+                    };
+                    let keystore = Keystore::new(args.value_of("keystore").unwrap()).with_kdf(kdf);
+                    println!("{}", keystore.create(&passphrase)?);
+                }
+                ("mnemonic", Some(args)) => {
+                    let phrase = hd::generate_mnemonic(args.value_of("words").unwrap().parse()?)?;
+                    println!("{}", phrase.as_str());
+                }
+                ("derive", Some(args)) => {
+                    let variable = args.value_of("mnemonic-env").unwrap();
+                    let phrase =
+                        Zeroizing::new(std::env::var(variable).map_err(|_| format!("{} is not set", variable))?);
+                    let variable = args.value_of("passphrase-env").unwrap();
+                    let passphrase =
+                        Zeroizing::new(std::env::var(variable).map_err(|_| format!("{} is not set", variable))?);
+                    let index: u32 = args.value_of("index").unwrap().parse()?;
+                    let purpose = match args.value_of("purpose").unwrap() {
+                        "validator" => KeyPurpose::Validator,
+                        "service" => KeyPurpose::Service(index),
+                        _ => KeyPurpose::Wallet(index),
+                    };
+                    let scheme = match args.value_of("scheme").unwrap() {
+                        "secp256k1" => SignatureScheme::Secp256k1,
+                        _ => SignatureScheme::Ed25519,
+                    };
+                    let secret = hd::derive(&hd::seed(&phrase, "")?, scheme, &purpose.path())?;
+                    let address = scheme.address(&scheme.public_key(secret.expose())?).ok_or("invalid derived key")?;
+                    let address = address::encode(&address);
+                    Keystore::new(args.value_of("keystore").unwrap()).store(&address, secret.expose(), &passphrase)?;
+                    println!("{} {}", address, purpose.path());
+                }
+                ("import", Some(args)) => {
+                    let variable = args.value_of("passphrase-env").unwrap();
+                    let passphrase =
+                        Zeroizing::new(std::env::var(variable).map_err(|_| format!("{} is not set", variable))?);
+                    let keystore = Keystore::new(args.value_of("keystore").unwrap());
+                    println!("{}", keystore.import(Path::new(args.value_of("file").unwrap()), &passphrase)?);
+                }
+                ("list", Some(args)) => {
+                    for account in Keystore::new(args.value_of("keystore").unwrap()).list()? {
+                        println!("{}", account);
+                    }
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+        ("signer", Some(args)) => {
+            let variable = args.value_of("passphrase-env").unwrap();
+            let passphrase =
+                Zeroizing::new(std::env::var(variable).map_err(|_| format!("{} is not set", variable))?);
+            let keystore = Keystore::new(args.value_of("keystore").unwrap());
+            let secret = keystore.unlock(args.value_of("account").unwrap(), &passphrase)?;
+            let auth_key = Path::new(args.value_of("auth-key").unwrap());
+            let daemon = SignerDaemon::new(secret, auth_key, Path::new(args.value_of("state").unwrap()))?;
+            let shutdown = Shutdown::new();
+            shutdown.listen_for_signals();
+            Arc::new(daemon).serve(args.value_of("listen").unwrap().parse()?, shutdown).await?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // Load configuration: section defaults, then the file, then OMNITENSOR_*
+    // variables, then --set flags. Only an explicitly given file must exist.
     let config_path = matches.value_of("config").unwrap_or("config/default.toml");
-    let config = match Config::from_file(config_path) {
+    let sources = match ConfigSources::new()
+        .with_file(config_path, matches.is_present("config"))
+        .with_env(std::env::vars())
+        .with_overrides(matches.values_of("set").into_iter().flatten())
+    {
+        Ok(sources) => sources,
+        Err(e) => {
+            error!("Failed to load configuration: {}", e);
+            process::exit(1);
+        }
+    };
+    let mut config: Config = match sources.load() {
         Ok(cfg) => cfg,
         Err(e) => {
             error!("Failed to load configuration: {}", e);
             process::exit(1);
         }
     };
+    let reloader = match ConfigReloader::new(sources) {
+        Ok(reloader) => reloader,
+        Err(e) => {
+            error!("Failed to load configuration: {}", e);
+            process::exit(1);
+        }
+    };
 
-    info!("Starting OmniTensor Core node...");
+    // Every command opening the database uses the node's key and tuning
+    let database_options = config.storage.database_options()?;
+    match matches.subcommand() {
+        ("backup", Some(args)) => {
+            let keep: usize = args.value_of("keep").unwrap_or("0").parse()?;
+            let dir = std::env::current_dir()?.join(args.value_of("dir").unwrap());
+            let backup = match Database::ensure_not_in_use(&config.storage.database_path) {
+                // A running node holds the lock, so it takes the backup itself
+                Err(DatabaseError::InUse(_)) => {
+                    let admin = &config.rpc.admin;
+                    if !admin.enabled {
+                        return Err("the node is running without [rpc.admin]; enable it or stop the node first".into());
+                    }
+                    let token = fs::read_to_string(&admin.token_file)?;
+                    let client = RpcClient::new(&format!("http://{}", admin.listen))?.with_bearer(token.trim())?;
+                    client.call::<BackupInfo>("admin_createBackup", serde_json::json!([dir, keep])).await?
+                }
+                unlocked => {
+                    unlocked?;
+                    let database = Database::open(&config.storage.database_path, database_options)?;
+                    database.create_backup(&dir, keep).await?
+                }
+            };
+            info!("Created backup {} ({} bytes, {} files)", backup.backup_id, backup.size, backup.num_files);
+            return Ok(());
+        }
+        ("restore", Some(args)) => {
+            Database::restore_from_backup(args.value_of("dir").unwrap(), &config.storage.database_path)?;
+            info!("Restored database into {}", config.storage.database_path.display());
+            return Ok(());
+        }
+        ("db", Some(db_args)) => {
+            if let ("check", Some(args)) = db_args.subcommand() {
+                let database = Arc::new(Database::open(&config.storage.database_path, database_options)?);
+                let store = open_chain_store(&config, database)?;
+                let checker = IntegrityChecker::new(&store);
+                let report = checker.check().await?;
+                for issue in &report.issues {
+                    error!("{}", issue);
+                }
+                info!("Checked {} blocks, found {} issue(s)", report.blocks_checked, report.issues.len());
+                if args.is_present("repair") && !report.is_clean() {
+                    let head = checker.repair(&report).await?;
+                    info!("Rewound chain head to {:?}; the removed range will be re-fetched by sync", head.map(|head| head.height));
+                } else if !report.is_clean() {
+                    process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        ("export-chain", Some(args)) => {
+            let database = Arc::new(Database::open(&config.storage.database_path, database_options)?);
+            let store = open_chain_store(&config, database)?;
+            let from: u64 = args.value_of("from").unwrap_or("0").parse()?;
+            let to: u64 = match args.value_of("to") {
+                Some(to) => to.parse()?,
+                None => store.head().await?.map(|head| head.height).ok_or("chain is empty")?,
+            };
+            let file = BufWriter::new(File::create(args.value_of("file").unwrap())?);
+            let exported = archive::export(&store, from, to, file).await?;
+            info!("Exported {} blocks ({}..={})", exported, from, to);
+            return Ok(());
+        }
+        ("import-chain", Some(args)) => {
+            let database = Arc::new(Database::open(&config.storage.database_path, database_options)?);
+            let store = open_chain_store(&config, database)?;
+            let mut pipeline = ImportPipeline::new(store, ImportConfig::default());
+            let file = BufReader::new(File::open(args.value_of("file").unwrap())?);
+            archive::import(&mut pipeline, file).await?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // A dev node keeps its own chain next to the configured one
+    let dev = matches.is_present("dev");
+    if dev {
+        config.storage.database_path = config.storage.database_path.join("dev");
+        config.node.role = Some(NodeRole::Validator);
+    }
+    let chain = if dev { "dev" } else { matches.value_of("chain").unwrap_or(&config.core.network) }.to_string();
+    let spec = match ChainSpec::load(&chain) {
+        Ok(spec) => spec,
+        Err(e) => {
+            error!("Failed to load chain spec: {}", e);
+            process::exit(1);
+        }
+    };
+    config.consensus.validator_count = spec.consensus.validator_count;
+
+    // A node that signed blocks before roles existed has to pick one
+    let remote_signer = &config.consensus.remote_signer;
+    let validator_setting = if remote_signer.enabled {
+        Some("[consensus.remote_signer] is enabled")
+    } else if remote_signer.state_file.exists() {
+        Some("this node has signed blocks before")
+    } else {
+        None
+    };
+    let role = match config.node.role(validator_setting) {
+        Ok(role) => role,
+        Err(e) => {
+            error!("Failed to start: {}", e);
+            process::exit(1);
+        }
+    };
+    info!("Starting OmniTensor Core node on {} ({} role)...", spec.name, role);
+
+    // `[storage] backend` picks where the node keeps its data. A RocksDB
+    // schema is brought up to date before any subsystem reads it.
+    let database = match config.storage.backend {
+        BackendKind::Rocksdb => {
+            let registry = MigrationRegistry::default();
+            match migration::open_with_migrations(&config.storage.database_path, database_options, &registry).await {
+                Ok(database) => Some(Arc::new(database)),
+                Err(e) => {
+                    error!("Failed to migrate database: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        BackendKind::Memory | BackendKind::Sled => None,
+    };
+    let storage: Arc<dyn StorageBackend> = match &database {
+        Some(database) => database.clone(),
+        None => open_backend(&config.storage)?,
+    };
+
+    if dev {
+        let database = database.clone().ok_or("--dev keeps its chain in RocksDB; set [storage] backend = \"rocksdb\"")?;
+        let store = ChainStore::new(database);
+        spec.genesis.initialize(&store).await?;
+        for (index, account) in dev::accounts(dev::DEV_ACCOUNTS).iter().enumerate() {
+            let secret: String = account.secret.expose().iter().map(|byte| format!("{:02x}", byte)).collect();
+            info!("Dev account {}: {} (secret key 0x{})", index, account.address, secret);
+        }
+    }
 
     // Initialize components
-    let storage = Storage::new(&config.storage_path)?;
-    let network_manager = NetworkManager::new(&config.network)?;
+    // The chain spec sets the chain id and bootstrap peers, and the role
+    // overrides the sync settings its subsystems depend on
+    let mut network_config = config.network.clone();
+    spec.apply(&mut network_config);
+    role.apply(&mut network_config);
+    if dev {
+        dev::apply(&mut network_config);
+    }
+    let network_manager = NetworkManager::new(&network_config)?;
     let consensus_engine = ConsensusEngine::new(&config.consensus, &storage)?;
 
+    // The first SIGINT or SIGTERM stops the event loop and runs the shutdown
+    // hooks the components registered; a second one exits immediately
+    let shutdown = Shutdown::new();
+    shutdown.listen_for_signals();
+    // SIGHUP re-reads the log level, RPC rate limit and peer and request
+    // limits; components follow `reloader.subscribe()`
+    reloader.listen_for_signals();
+
     // Create and start the node
-    let mut node = Node::new(storage, network_manager, consensus_engine);
-    
+    // Only the subsystems the role needs are started
+    let mut node = Node::new(role.subsystems(), storage, network_manager, consensus_engine);
+    // Blocks are sealed on demand instead of by consensus
+    if dev {
+        let period = matches.value_of("dev-block-time").map(str::parse).transpose()?.map(Duration::from_millis);
+        tokio::spawn(InstantSeal::new(period).run(node.subscribe(), node.sealer(), shutdown.clone()));
+    }
+
+    // Reads the same registry the metrics endpoint scrapes
+    let dashboard = matches
+        .is_present("tui")
+        .then(|| tokio::spawn(Dashboard::new(node.metrics(), node.chain_store()).run(shutdown.clone())));
+    if config.telemetry.enabled {
+        let telemetry = Telemetry::new(&config.telemetry, &spec.name, role, node.metrics(), node.chain_store())?;
+        info!("Sending telemetry to {}", config.telemetry.endpoint);
+        tokio::spawn(telemetry.run(shutdown.clone()));
+    }
+
     // Start the main event loop
-    if let Err(e) = node.run().await {
-        error!("Node failed: {}", e);
-        process::exit(1);
+    tokio::select! {
+        result = node.run() => {
+            if let Err(e) = result {
+                error!("Node failed: {}", e);
+                process::exit(1);
+            }
+        }
+        _ = shutdown.wait() => {}
+    }
+    // Leaves the dashboard's screen before the shutdown hooks log anything
+    if let Some(dashboard) = dashboard {
+        shutdown.trigger();
+        let _ = dashboard.await;
     }
+    shutdown.run_hooks(DEFAULT_HOOK_TIMEOUT).await;
 
     info!("OmniTensor Core node shutting down.");
     Ok(())
+}
+
+// Offline commands see the chain as the node does, including blocks already
+// moved to the ancient store
+fn open_chain_store(config: &Config, database: Arc<Database>) -> Result<ChainStore, Box<dyn std::error::Error>> {
+    let ancient_path = config.storage.ancient.path_for(&config.storage.database_path);
+    if config.storage.ancient.enabled || ancient_path.exists() {
+        Ok(ChainStore::with_ancient(database, Arc::new(AncientStore::open(ancient_path)?))?)
+    } else {
+        Ok(ChainStore::new(database))
+    }
 }
\ No newline at end of file
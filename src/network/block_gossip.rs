@@ -0,0 +1,245 @@
+use libp2p::gossipsub::MessageId;
+use libp2p::PeerId;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+use crate::chain::block::Block;
+use crate::network::codec::{BincodeCodec, Protocol};
+
+pub const BLOCK_FETCH_PROTOCOL: Protocol = Protocol("/omnitensor/block-fetch/1");
+const MAX_FETCH_RESPONSE_LEN: usize = 8 * 1024 * 1024;
+
+pub type BlockHash = [u8; 32];
+
+// Blocks are gossiped as announcements on the blocks topic. A node that lacks
+// the block fetches the body from the announcing peer over
+// `BLOCK_FETCH_PROTOCOL` and only then validates the announcement, so it is
+// forwarded by nodes that can serve the body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockAnnouncement {
+    pub hash: BlockHash,
+    pub height: u64,
+    pub parent: BlockHash,
+}
+
+impl BlockAnnouncement {
+    pub fn new(block: &Block, height: u64) -> Self {
+        Self { hash: block.hash(), height, parent: block.header.prev_block_hash }
+    }
+
+    // The body must be the announced block, not just any block
+    pub fn matches(&self, block: &Block) -> bool {
+        block.hash() == self.hash && block.header.prev_block_hash == self.parent
+    }
+}
+
+// `None` when the peer doesn't have the block
+pub type BlockFetchCodec = BincodeCodec<BlockHash, Option<Block>>;
+
+pub fn block_fetch_codec() -> BlockFetchCodec {
+    BincodeCodec::new(MAX_FETCH_RESPONSE_LEN)
+}
+
+// `[network.block_gossip]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockGossipConfig {
+    // Block hashes remembered as already fetched or produced locally
+    pub seen_cache_size: usize,
+    // After this, the body is requested from another peer
+    pub fetch_timeout_ms: u64,
+    // Peers asked for one body before the announcement is ignored. Gossipsub
+    // forgets unvalidated messages after a few heartbeats, so attempts times
+    // the timeout should stay below that.
+    pub max_fetch_attempts: usize,
+}
+
+impl Default for BlockGossipConfig {
+    fn default() -> Self {
+        Self { seen_cache_size: 1024, fetch_timeout_ms: 2000, max_fetch_attempts: 2 }
+    }
+}
+
+// The gossip message an announcement arrived in, validated once the fetch settles
+pub type PendingMessage = (MessageId, PeerId);
+
+struct InFlight {
+    announcement: BlockAnnouncement,
+    message: PendingMessage,
+    peer: PeerId,
+    requested: Instant,
+    tried: Vec<PeerId>,
+}
+
+#[derive(Debug)]
+pub struct Fetched {
+    pub block: Block,
+    pub announcement: BlockAnnouncement,
+    pub message: PendingMessage,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum NextAttempt {
+    Retry(PeerId),
+    // Nobody left to ask; the announcement should be ignored
+    GiveUp(PendingMessage),
+}
+
+// Decides which announced blocks to fetch and from whom
+pub struct BlockFetcher {
+    config: BlockGossipConfig,
+    seen: LruCache<BlockHash, ()>,
+    in_flight: HashMap<BlockHash, InFlight>,
+}
+
+impl BlockFetcher {
+    pub fn new(config: BlockGossipConfig) -> Self {
+        let capacity = NonZeroUsize::new(config.seen_cache_size).unwrap_or(NonZeroUsize::MIN);
+        Self { config, seen: LruCache::new(capacity), in_flight: HashMap::new() }
+    }
+
+    // For blocks we already have, e.g. produced or imported locally
+    pub fn mark_seen(&mut self, hash: BlockHash) {
+        self.in_flight.remove(&hash);
+        self.seen.put(hash, ());
+    }
+
+    // True if the body should be requested from `peer` now; the announcement's
+    // validation then waits for `on_body` or `on_failure`
+    pub fn on_announcement(&mut self, peer: PeerId, message: PendingMessage, announcement: BlockAnnouncement) -> bool {
+        self.on_announcement_at(peer, message, announcement, Instant::now())
+    }
+
+    fn on_announcement_at(
+        &mut self,
+        peer: PeerId,
+        message: PendingMessage,
+        announcement: BlockAnnouncement,
+        now: Instant,
+    ) -> bool {
+        let hash = announcement.hash;
+        if self.seen.contains(&hash) || self.in_flight.contains_key(&hash) {
+            return false;
+        }
+        self.in_flight.insert(hash, InFlight { announcement, message, peer, requested: now, tried: vec![peer] });
+        true
+    }
+
+    // Hands back the block if it is the one we asked `peer` for
+    pub fn on_body(&mut self, peer: PeerId, block: Block) -> Option<Fetched> {
+        let hash = block.hash();
+        match self.in_flight.get(&hash) {
+            Some(in_flight) if in_flight.peer == peer && in_flight.announcement.matches(&block) => {}
+            _ => return None,
+        }
+        let in_flight = self.in_flight.remove(&hash)?;
+        self.seen.put(hash, ());
+        Some(Fetched { block, announcement: in_flight.announcement, message: in_flight.message })
+    }
+
+    // Fetching `hash` from `peer` failed or timed out; picks the first untried
+    // candidate, if attempts remain. `None` if we aren't waiting on `peer` for it.
+    pub fn on_failure(
+        &mut self,
+        hash: &BlockHash,
+        peer: PeerId,
+        candidates: impl IntoIterator<Item = PeerId>,
+    ) -> Option<NextAttempt> {
+        self.on_failure_at(hash, peer, candidates, Instant::now())
+    }
+
+    fn on_failure_at(
+        &mut self,
+        hash: &BlockHash,
+        peer: PeerId,
+        candidates: impl IntoIterator<Item = PeerId>,
+        now: Instant,
+    ) -> Option<NextAttempt> {
+        let in_flight = self.in_flight.get_mut(hash).filter(|in_flight| in_flight.peer == peer)?;
+        let next = if in_flight.tried.len() < self.config.max_fetch_attempts {
+            candidates.into_iter().find(|peer| !in_flight.tried.contains(peer))
+        } else {
+            None
+        };
+        match next {
+            Some(peer) => {
+                in_flight.peer = peer;
+                in_flight.requested = now;
+                in_flight.tried.push(peer);
+                Some(NextAttempt::Retry(peer))
+            }
+            None => self.in_flight.remove(hash).map(|in_flight| NextAttempt::GiveUp(in_flight.message)),
+        }
+    }
+
+    // Fetches that have been waiting longer than the timeout, with the peer asked
+    pub fn timed_out(&self) -> Vec<(BlockHash, PeerId)> {
+        self.timed_out_at(Instant::now())
+    }
+
+    fn timed_out_at(&self, now: Instant) -> Vec<(BlockHash, PeerId)> {
+        let timeout = Duration::from_millis(self.config.fetch_timeout_ms);
+        self.in_flight
+            .iter()
+            .filter(|(_, in_flight)| now.duration_since(in_flight.requested) >= timeout)
+            .map(|(hash, in_flight)| (*hash, in_flight.peer))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(parent: u8) -> Block {
+        Block::new([parent; 32], vec![], 1).unwrap()
+    }
+
+    fn message(source: PeerId) -> PendingMessage {
+        (MessageId::from(vec![1, 2, 3]), source)
+    }
+
+    #[test]
+    fn test_fetches_announced_block_once() {
+        let mut fetcher = BlockFetcher::new(BlockGossipConfig::default());
+        let (first, second) = (PeerId::random(), PeerId::random());
+        let block = block(1);
+        let announcement = BlockAnnouncement::new(&block, 7);
+
+        assert!(fetcher.on_announcement(first, message(first), announcement.clone()));
+        assert!(!fetcher.on_announcement(second, message(second), announcement.clone()));
+        // Only the peer we asked can deliver
+        assert!(fetcher.on_body(second, block.clone()).is_none());
+
+        let fetched = fetcher.on_body(first, block.clone()).unwrap();
+        assert_eq!(fetched.announcement, announcement);
+        assert_eq!(fetched.message, message(first));
+        assert!(!fetcher.on_announcement(second, message(second), announcement));
+    }
+
+    #[test]
+    fn test_failed_fetch_moves_to_other_peers_then_gives_up() {
+        let mut fetcher = BlockFetcher::new(BlockGossipConfig::default());
+        let (announcer, backup) = (PeerId::random(), PeerId::random());
+        let announcement = BlockAnnouncement::new(&block(2), 8);
+        let hash = announcement.hash;
+        let start = Instant::now();
+
+        fetcher.on_announcement_at(announcer, message(announcer), announcement, start);
+        assert!(fetcher.timed_out_at(start + Duration::from_secs(1)).is_empty());
+        assert_eq!(fetcher.timed_out_at(start + Duration::from_secs(2)), vec![(hash, announcer)]);
+
+        let candidates = [announcer, backup];
+        assert_eq!(fetcher.on_failure_at(&hash, announcer, candidates, start), Some(NextAttempt::Retry(backup)));
+        // A late failure from the first peer doesn't count against the retry
+        assert_eq!(fetcher.on_failure_at(&hash, announcer, candidates, start), None);
+        assert_eq!(
+            fetcher.on_failure_at(&hash, backup, candidates, start),
+            Some(NextAttempt::GiveUp(message(announcer)))
+        );
+        assert!(fetcher.timed_out_at(start + Duration::from_secs(10)).is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::net::UdpSocket;
+
+use crate::utils::metrics::{Metric, MetricsSource};
+
+// Peers whose offsets are kept; the oldest is dropped for a new one
+const MAX_PEER_SAMPLES: usize = 64;
+// Fewer peers than this don't make an estimate, so one peer with a bad
+// clock can't raise a warning on its own
+const MIN_PEER_SAMPLES: usize = 3;
+const NTP_TIMEOUT: Duration = Duration::from_secs(5);
+// Seconds from 1900, where NTP counts from, to 1970
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+// `[network.clock]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    // Estimated skew of the local clock that gets a warning
+    pub warn_drift_ms: u64,
+    // Blocks timestamped further ahead of the local clock are not accepted
+    // until it catches up
+    pub max_future_secs: u64,
+    // "host:port" of an NTP server to check the clock against, besides the
+    // peers' clocks from the handshake, e.g. "pool.ntp.org:123"
+    pub ntp_server: Option<String>,
+    pub ntp_interval_secs: u64,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self { warn_drift_ms: 5_000, max_future_secs: 15, ntp_server: None, ntp_interval_secs: 3600 }
+    }
+}
+
+impl ClockConfig {
+    // Checks a block or header timestamp, in seconds, against the local clock
+    pub fn check_timestamp(&self, timestamp: i64, now_ms: u64) -> Result<(), TimestampError> {
+        let now = (now_ms / 1000) as i64;
+        match timestamp > now.saturating_add(self.max_future_secs as i64) {
+            true => Err(TimestampError::TooFarAhead { timestamp, now, tolerance: self.max_future_secs }),
+            false => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TimestampError {
+    #[error("Timestamp {timestamp} is more than {tolerance}s ahead of the local clock ({now})")]
+    TooFarAhead { timestamp: i64, now: i64, tolerance: u64 },
+}
+
+pub fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64)
+}
+
+// How far a remote clock is ahead of ours, in ms. `local_ms` is our clock when
+// the remote one read `remote_ms`: the arrival time minus half the round trip
+// where that is known.
+pub fn offset(remote_ms: u64, local_ms: u64) -> i64 {
+    remote_ms as i64 - local_ms as i64
+}
+
+// Estimates the local clock's drift from the offsets of peers' clocks and,
+// if configured, an NTP server's. NTP wins when there is an answer; peers
+// are combined by the median, so a minority of bad clocks doesn't move it.
+pub struct ClockDrift {
+    warn_drift_ms: u64,
+    peers: Mutex<VecDeque<(String, i64)>>,
+    ntp: Mutex<Option<i64>>,
+    warned: Mutex<bool>,
+}
+
+impl ClockDrift {
+    pub fn new(config: &ClockConfig) -> Self {
+        Self {
+            warn_drift_ms: config.warn_drift_ms,
+            peers: Mutex::new(VecDeque::new()),
+            ntp: Mutex::new(None),
+            warned: Mutex::new(false),
+        }
+    }
+
+    pub fn record_peer(&self, peer: &str, offset_ms: i64) {
+        {
+            let mut peers = self.peers.lock().unwrap();
+            peers.retain(|(known, _)| known != peer);
+            if peers.len() == MAX_PEER_SAMPLES {
+                peers.pop_front();
+            }
+            peers.push_back((peer.to_string(), offset_ms));
+        }
+        self.check();
+    }
+
+    pub fn record_ntp(&self, offset_ms: i64) {
+        *self.ntp.lock().unwrap() = Some(offset_ms);
+        self.check();
+    }
+
+    // How far the local clock is behind the reference, in ms; negative when
+    // it is ahead. `None` until there is enough to go on.
+    pub fn estimate(&self) -> Option<i64> {
+        if let Some(offset) = *self.ntp.lock().unwrap() {
+            return Some(offset);
+        }
+        let mut offsets: Vec<i64> = self.peers.lock().unwrap().iter().map(|(_, offset)| *offset).collect();
+        if offsets.len() < MIN_PEER_SAMPLES {
+            return None;
+        }
+        offsets.sort_unstable();
+        Some(offsets[offsets.len() / 2])
+    }
+
+    // The network's time by the estimate, for stamping and checking blocks
+    // produced here; the local clock until there is an estimate
+    pub fn network_millis(&self) -> u64 {
+        unix_millis().saturating_add_signed(self.estimate().unwrap_or_default())
+    }
+
+    pub fn is_skewed(&self) -> bool {
+        self.estimate().map_or(false, |drift| drift.unsigned_abs() > self.warn_drift_ms)
+    }
+
+    // Warns once when the estimate goes past the threshold, and says so when
+    // it is back within it
+    fn check(&self) {
+        let skewed = self.is_skewed();
+        let mut warned = self.warned.lock().unwrap();
+        if skewed && !*warned {
+            let drift = self.estimate().unwrap_or_default();
+            let direction = if drift > 0 { "behind" } else { "ahead of" };
+            warn!(
+                "The local clock is {}ms {} the network's; blocks produced now may be rejected, check NTP",
+                drift.unsigned_abs(),
+                direction
+            );
+        } else if !skewed && *warned {
+            info!("The local clock is back in line with the network's");
+        }
+        *warned = skewed;
+    }
+}
+
+impl MetricsSource for ClockDrift {
+    fn collect(&self) -> Vec<Metric> {
+        match self.estimate() {
+            Some(drift) => vec![Metric::gauge(
+                "clock_drift_seconds",
+                "Estimated offset of the network's clock from the local one",
+                drift as f64 / 1000.0,
+            )],
+            None => Vec::new(),
+        }
+    }
+}
+
+// Queries `server` now and then every `interval`, feeding `drift`
+pub fn spawn_ntp(server: Option<String>, interval: Duration, drift: Arc<ClockDrift>) {
+    let server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    tokio::spawn(async move {
+        loop {
+            match tokio::time::timeout(NTP_TIMEOUT, ntp_offset(&server)).await {
+                Ok(Ok(offset)) => {
+                    debug!("NTP server {} is {}ms ahead of the local clock", server, offset);
+                    drift.record_ntp(offset);
+                }
+                Ok(Err(e)) => warn!("Failed to query NTP server {}: {}", server, e),
+                Err(_) => warn!("NTP server {} did not answer", server),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+// One SNTP (RFC 4330) exchange: how far the server's clock is ahead of ours,
+// in ms
+pub async fn ntp_offset(server: &str) -> io::Result<i64> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(server).await?;
+    let mut request = [0u8; 48];
+    // No leap indicator, version 4, client mode
+    request[0] = 0x23;
+    let sent = unix_millis();
+    socket.send(&request).await?;
+    let mut response = [0u8; 48];
+    let len = socket.recv(&mut response).await?;
+    let received = unix_millis();
+    parse_ntp(&response[..len], sent, received)
+}
+
+fn parse_ntp(response: &[u8], sent_ms: u64, received_ms: u64) -> io::Result<i64> {
+    let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+    if response.len() < 48 {
+        return Err(invalid("short NTP response"));
+    }
+    // Server mode, and a stratum 0 "kiss of death" is no time at all
+    if response[0] & 0x07 != 4 || response[1] == 0 {
+        return Err(invalid("NTP server did not answer with the time"));
+    }
+    let server_received = ntp_millis(&response[32..40]).ok_or_else(|| invalid("bad NTP timestamp"))?;
+    let server_sent = ntp_millis(&response[40..48]).ok_or_else(|| invalid("bad NTP timestamp"))?;
+    Ok((offset(server_received, sent_ms) + offset(server_sent, received_ms)) / 2)
+}
+
+// An NTP timestamp as ms since the Unix epoch
+fn ntp_millis(bytes: &[u8]) -> Option<u64> {
+    let seconds = u32::from_be_bytes(bytes[..4].try_into().ok()?) as u64;
+    let fraction = u32::from_be_bytes(bytes[4..8].try_into().ok()?) as u64;
+    let unix = seconds.checked_sub(NTP_UNIX_OFFSET)?;
+    Some(unix * 1000 + ((fraction * 1000) >> 32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ntp_timestamp(unix_ms: u64) -> [u8; 8] {
+        let seconds = (unix_ms / 1000 + NTP_UNIX_OFFSET) as u32;
+        let fraction = (((unix_ms % 1000) << 32) / 1000) as u32;
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+        bytes[4..].copy_from_slice(&fraction.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_estimates_drift_from_peers_and_ntp() {
+        let config = ClockConfig::default();
+        let drift = ClockDrift::new(&config);
+        drift.record_peer("a", 8_000);
+        drift.record_peer("b", 7_000);
+        assert_eq!(drift.estimate(), None);
+        // One bad clock doesn't move the median
+        drift.record_peer("c", -90_000);
+        drift.record_peer("d", 9_000);
+        assert_eq!(drift.estimate(), Some(8_000));
+        assert!(drift.is_skewed());
+        assert!(drift.network_millis() >= unix_millis() + 7_000);
+        // A peer's newer offset replaces its old one
+        drift.record_peer("a", 100);
+        drift.record_peer("b", 200);
+        assert!(!drift.is_skewed());
+
+        // The server is 2s ahead and answers after a 100ms round trip
+        let mut response = [0u8; 48];
+        response[0] = 0x24;
+        response[1] = 2;
+        response[32..40].copy_from_slice(&ntp_timestamp(1_700_000_002_050));
+        response[40..48].copy_from_slice(&ntp_timestamp(1_700_000_002_051));
+        let offset = parse_ntp(&response, 1_700_000_000_000, 1_700_000_000_101).unwrap();
+        assert!((1_999..=2_001).contains(&offset), "{}", offset);
+        drift.record_ntp(offset);
+        assert_eq!(drift.estimate(), Some(offset));
+        response[1] = 0;
+        assert!(parse_ntp(&response, 0, 0).is_err());
+
+        let now_ms = 1_700_000_000_000;
+        assert_eq!(config.check_timestamp(1_700_000_015, now_ms), Ok(()));
+        assert!(matches!(config.check_timestamp(1_700_000_016, now_ms), Err(TimestampError::TooFarAhead { .. })));
+    }
+}
@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use bincode::Options;
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+use libp2p::request_response::RequestResponseCodec;
+use serde::{de::DeserializeOwned, Serialize};
+use std::io;
+use std::marker::PhantomData;
+
+#[derive(Debug, Clone)]
+pub struct Protocol(pub &'static str);
+
+impl ProtocolName for Protocol {
+    fn protocol_name(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+// Deserializes untrusted bytes. Every length prefix inside the payload is
+// checked against the bytes actually received before anything is allocated,
+// and trailing garbage is rejected, so a malformed message fails fast instead
+// of reaching a full decode. Wire-compatible with `bincode::serialize`.
+pub fn decode_bounded<T: DeserializeOwned>(bytes: &[u8]) -> bincode::Result<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(bytes.len() as u64)
+        .deserialize(bytes)
+}
+
+// Length-prefixed bincode in both directions, shared by our request/response
+// protocols. Messages longer than `max_len` are refused before allocating.
+#[derive(Debug)]
+pub struct BincodeCodec<Req, Resp> {
+    max_len: usize,
+    _marker: PhantomData<fn() -> (Req, Resp)>,
+}
+
+impl<Req, Resp> BincodeCodec<Req, Resp> {
+    pub fn new(max_len: usize) -> Self {
+        Self { max_len, _marker: PhantomData }
+    }
+
+    async fn read<T: AsyncRead + Unpin + Send, M: DeserializeOwned>(&self, io: &mut T) -> io::Result<M> {
+        let bytes = read_length_prefixed(io, self.max_len).await?;
+        decode_bounded(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn write<T: AsyncWrite + Unpin + Send, M: Serialize>(&self, io: &mut T, message: M) -> io::Result<()> {
+        let bytes = bincode::serialize(&message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if bytes.len() > self.max_len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "message too large"));
+        }
+        write_length_prefixed(io, bytes).await?;
+        io.close().await
+    }
+}
+
+impl<Req, Resp> Clone for BincodeCodec<Req, Resp> {
+    fn clone(&self) -> Self {
+        Self::new(self.max_len)
+    }
+}
+
+#[async_trait]
+impl<Req, Resp> RequestResponseCodec for BincodeCodec<Req, Resp>
+where
+    Req: Serialize + DeserializeOwned + Send,
+    Resp: Serialize + DeserializeOwned + Send,
+{
+    type Protocol = Protocol;
+    type Request = Req;
+    type Response = Resp;
+
+    async fn read_request<T: AsyncRead + Unpin + Send>(&mut self, _: &Protocol, io: &mut T) -> io::Result<Req> {
+        self.read(io).await
+    }
+
+    async fn read_response<T: AsyncRead + Unpin + Send>(&mut self, _: &Protocol, io: &mut T) -> io::Result<Resp> {
+        self.read(io).await
+    }
+
+    async fn write_request<T: AsyncWrite + Unpin + Send>(&mut self, _: &Protocol, io: &mut T, request: Req) -> io::Result<()> {
+        self.write(io, request).await
+    }
+
+    async fn write_response<T: AsyncWrite + Unpin + Send>(&mut self, _: &Protocol, io: &mut T, response: Resp) -> io::Result<()> {
+        self.write(io, response).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_roundtrip_and_size_limit() {
+        let mut codec = BincodeCodec::<Vec<u8>, u64>::new(64);
+        let protocol = Protocol("/test/1");
+        let mut wire = Vec::new();
+        codec.write_request(&protocol, &mut wire, vec![1, 2, 3]).await.unwrap();
+        assert_eq!(codec.read_request(&protocol, &mut wire.as_slice()).await.unwrap(), vec![1, 2, 3]);
+
+        assert!(codec.write_request(&protocol, &mut Vec::new(), vec![0; 128]).await.is_err());
+    }
+
+    #[test]
+    fn test_decode_bounded_rejects_lying_lengths() {
+        let bytes = bincode::serialize(&vec![1u8, 2, 3]).unwrap();
+        assert_eq!(decode_bounded::<Vec<u8>>(&bytes).unwrap(), vec![1, 2, 3]);
+
+        // Claims 2^60 elements in a 9 byte message
+        let mut lying = (1u64 << 60).to_le_bytes().to_vec();
+        lying.push(0);
+        assert!(decode_bounded::<Vec<u8>>(&lying).is_err());
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(decode_bounded::<Vec<u8>>(&trailing).is_err());
+    }
+}
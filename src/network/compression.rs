@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+use crate::utils::metrics::{Metric, MetricsSource};
+
+// Version of the wire format this node speaks. Version 1 payloads are raw;
+// from version 2 every payload is prefixed with a one-byte codec tag
+//
+//     payload := codec: u8 | body
+//
+// so a receiver decodes whatever codec the sender picked, and small payloads
+// that don't benefit from compression go out with codec 0.
+pub const PROTOCOL_VERSION: u32 = 2;
+const FRAMED_SINCE: u32 = 2;
+
+#[derive(Debug, Error)]
+pub enum CompressionError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Snappy error: {0}")]
+    Snappy(#[from] snap::Error),
+    #[error("Empty payload")]
+    Empty,
+    #[error("Unknown codec tag {0}")]
+    UnknownCodec(u8),
+    #[error("Payload is larger than {0} bytes")]
+    TooLarge(usize),
+}
+
+pub type Result<T> = std::result::Result<T, CompressionError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    None,
+    Snappy,
+    Zstd,
+}
+
+impl Codec {
+    fn tag(&self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Snappy => 1,
+            Codec::Zstd => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self> {
+        match tag {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Snappy),
+            2 => Ok(Codec::Zstd),
+            tag => Err(CompressionError::UnknownCodec(tag)),
+        }
+    }
+}
+
+// `[network.compression]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    // Codec for outgoing payloads; any known codec is accepted incoming
+    pub codec: Codec,
+    // Payloads smaller than this are sent uncompressed
+    pub threshold_bytes: usize,
+    pub zstd_level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self { codec: Codec::Snappy, threshold_bytes: 1024, zstd_level: 3 }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    raw_bytes: AtomicU64,
+    wire_bytes: AtomicU64,
+    compressed: AtomicU64,
+}
+
+impl Counters {
+    fn record(&self, raw: usize, wire: usize, compressed: bool) {
+        self.raw_bytes.fetch_add(raw as u64, Ordering::Relaxed);
+        self.wire_bytes.fetch_add(wire as u64, Ordering::Relaxed);
+        if compressed {
+            self.compressed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn ratio(&self) -> f64 {
+        let wire = self.wire_bytes.load(Ordering::Relaxed);
+        match wire {
+            0 => 1.0,
+            wire => self.raw_bytes.load(Ordering::Relaxed) as f64 / wire as f64,
+        }
+    }
+}
+
+// Compresses gossip and sync payloads according to the protocol version the
+// other side speaks, and keeps track of how much that saves
+pub struct MessageCompressor {
+    config: CompressionConfig,
+    outbound: Counters,
+    inbound: Counters,
+}
+
+impl MessageCompressor {
+    pub fn new(config: CompressionConfig) -> Self {
+        Self { config, outbound: Counters::default(), inbound: Counters::default() }
+    }
+
+    pub fn encode(&self, version: u32, data: Vec<u8>) -> Result<Vec<u8>> {
+        if version < FRAMED_SINCE {
+            self.outbound.record(data.len(), data.len(), false);
+            return Ok(data);
+        }
+        let raw_len = data.len();
+        let compressed = match self.config.codec {
+            _ if raw_len < self.config.threshold_bytes => None,
+            Codec::None => None,
+            Codec::Snappy => Some(snap::raw::Encoder::new().compress_vec(&data)?),
+            Codec::Zstd => Some(zstd::bulk::compress(&data, self.config.zstd_level)?),
+        };
+        // Incompressible payloads go out raw rather than grow
+        let (codec, body) = match compressed {
+            Some(body) if body.len() < raw_len => (self.config.codec, body),
+            _ => (Codec::None, data),
+        };
+        let mut framed = Vec::with_capacity(1 + body.len());
+        framed.push(codec.tag());
+        framed.extend(body);
+        self.outbound.record(raw_len, framed.len(), codec != Codec::None);
+        Ok(framed)
+    }
+
+    // `max_len` bounds the decompressed size, checked before inflating so a
+    // small payload can't expand into an arbitrarily large allocation
+    pub fn decode(&self, version: u32, data: &[u8], max_len: usize) -> Result<Vec<u8>> {
+        // Encoders never send a body larger than the raw payload, plus the tag
+        let max_wire_len = if version < FRAMED_SINCE { max_len } else { max_len.saturating_add(1) };
+        if data.len() > max_wire_len {
+            return Err(CompressionError::TooLarge(max_len));
+        }
+        if version < FRAMED_SINCE {
+            self.inbound.record(data.len(), data.len(), false);
+            return Ok(data.to_vec());
+        }
+        let (tag, body) = data.split_first().ok_or(CompressionError::Empty)?;
+        let codec = Codec::from_tag(*tag)?;
+        let raw = match codec {
+            Codec::None if body.len() > max_len => return Err(CompressionError::TooLarge(max_len)),
+            Codec::None => body.to_vec(),
+            Codec::Snappy => {
+                if snap::raw::decompress_len(body)? > max_len {
+                    return Err(CompressionError::TooLarge(max_len));
+                }
+                snap::raw::Decoder::new().decompress_vec(body)?
+            }
+            // Fails once the output would exceed `max_len`
+            Codec::Zstd => zstd::bulk::decompress(body, max_len)?,
+        };
+        self.inbound.record(raw.len(), data.len(), codec != Codec::None);
+        Ok(raw)
+    }
+
+    // Uncompressed bytes per byte on the wire for outgoing payloads
+    pub fn ratio(&self) -> f64 {
+        self.outbound.ratio()
+    }
+}
+
+impl MetricsSource for MessageCompressor {
+    fn collect(&self) -> Vec<Metric> {
+        let mut metrics = Vec::new();
+        for (direction, counters) in [("out", &self.outbound), ("in", &self.inbound)] {
+            let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed) as f64;
+            metrics.extend([
+                Metric::counter("network_payload_raw_bytes_total", "Payload bytes before compression", load(&counters.raw_bytes)),
+                Metric::counter("network_payload_wire_bytes_total", "Payload bytes on the wire", load(&counters.wire_bytes)),
+                Metric::counter("network_payloads_compressed_total", "Payloads sent or received compressed", load(&counters.compressed)),
+                Metric::gauge("network_compression_ratio", "Raw bytes per wire byte", counters.ratio()),
+            ]);
+            for metric in metrics.iter_mut().rev().take(4) {
+                metric.labels.push(("direction", direction.to_string()));
+            }
+        }
+        // Exposition groups samples by metric name
+        metrics.sort_by_key(|metric| metric.name);
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_per_codec_and_version() {
+        let payload = vec![7u8; 4096];
+        for codec in [Codec::None, Codec::Snappy, Codec::Zstd] {
+            let compressor = MessageCompressor::new(CompressionConfig { codec, ..CompressionConfig::default() });
+            let wire = compressor.encode(PROTOCOL_VERSION, payload.clone()).unwrap();
+            assert_eq!(wire[0], codec.tag());
+            assert_eq!(compressor.decode(PROTOCOL_VERSION, &wire, 4096).unwrap(), payload);
+            assert!(compressor.decode(PROTOCOL_VERSION, &wire, 4095).is_err());
+        }
+
+        // Version 1 peers get raw bytes, small payloads stay uncompressed
+        let compressor = MessageCompressor::new(CompressionConfig::default());
+        assert_eq!(compressor.encode(1, payload.clone()).unwrap(), payload);
+        assert_eq!(compressor.encode(PROTOCOL_VERSION, vec![1, 2, 3]).unwrap(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_rejects_bad_frames_and_tracks_ratio() {
+        let compressor = MessageCompressor::new(CompressionConfig::default());
+        assert!(matches!(compressor.decode(PROTOCOL_VERSION, &[], 1024), Err(CompressionError::Empty)));
+        assert!(matches!(compressor.decode(PROTOCOL_VERSION, &[9, 1], 1024), Err(CompressionError::UnknownCodec(9))));
+        assert!(compressor.decode(PROTOCOL_VERSION, &[1, 0xff, 0xff], 1024).is_err());
+
+        compressor.encode(PROTOCOL_VERSION, vec![0u8; 10_000]).unwrap();
+        assert!(compressor.ratio() > 10.0);
+    }
+}
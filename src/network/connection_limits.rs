@@ -0,0 +1,178 @@
+use libp2p::core::ConnectedPoint;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+// `[network.connections]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectionLimitsConfig {
+    pub max_peers: usize,
+    // Slots inbound peers can never take, so we always keep peers of our own choosing
+    pub reserved_outbound: usize,
+    pub max_inbound_per_ip: usize,
+}
+
+impl Default for ConnectionLimitsConfig {
+    fn default() -> Self {
+        Self { max_peers: 100, reserved_outbound: 20, max_inbound_per_ip: 2 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    Accept,
+    // Accept, but disconnect this lower-scoring peer to make room
+    Evict(PeerId),
+    Reject(&'static str),
+}
+
+struct Connected {
+    direction: Direction,
+    ip: Option<IpAddr>,
+}
+
+// Decides which peers get a slot. Inbound peers are capped below `max_peers`
+// and per IP, and a full node makes room for a newcomer by evicting its
+// lowest-scoring inbound peer, so an adversary can't fill every slot.
+pub struct ConnectionManager {
+    config: ConnectionLimitsConfig,
+    peers: HashMap<PeerId, Connected>,
+}
+
+impl ConnectionManager {
+    pub fn new(config: ConnectionLimitsConfig) -> Self {
+        Self { config, peers: HashMap::new() }
+    }
+
+    // Called for the first connection to `peer`; `score` ranks peers for eviction
+    pub fn admit(&mut self, peer: PeerId, endpoint: &ConnectedPoint, score: impl Fn(&PeerId) -> f64) -> Admission {
+        let (direction, ip) = match endpoint {
+            ConnectedPoint::Dialer { .. } => (Direction::Outbound, None),
+            ConnectedPoint::Listener { send_back_addr, .. } => (Direction::Inbound, ip_of(send_back_addr)),
+        };
+        self.admit_as(peer, direction, ip, score)
+    }
+
+    fn admit_as(&mut self, peer: PeerId, direction: Direction, ip: Option<IpAddr>, score: impl Fn(&PeerId) -> f64) -> Admission {
+        if self.peers.contains_key(&peer) {
+            return Admission::Accept;
+        }
+        let (inbound, outbound) = self.counts();
+        let full = inbound + outbound >= self.config.max_peers;
+        let admission = match direction {
+            Direction::Inbound => {
+                let same_ip = ip.map_or(0, |ip| self.peers.values().filter(|connected| connected.ip == Some(ip)).count());
+                if same_ip >= self.config.max_inbound_per_ip {
+                    return Admission::Reject("too many connections from this address");
+                }
+                let inbound_slots = self.config.max_peers.saturating_sub(self.config.reserved_outbound);
+                if inbound < inbound_slots && !full {
+                    Admission::Accept
+                } else {
+                    match self.lowest_inbound(&score) {
+                        Some((victim, victim_score)) if victim_score < score(&peer) => Admission::Evict(victim),
+                        _ => return Admission::Reject("inbound slots full"),
+                    }
+                }
+            }
+            Direction::Outbound => {
+                if !full {
+                    Admission::Accept
+                } else if outbound < self.config.reserved_outbound {
+                    match self.lowest_inbound(&score) {
+                        Some((victim, _)) => Admission::Evict(victim),
+                        None => return Admission::Reject("peer slots full"),
+                    }
+                } else {
+                    return Admission::Reject("peer slots full");
+                }
+            }
+        };
+        if let Admission::Evict(victim) = &admission {
+            self.peers.remove(victim);
+        }
+        self.peers.insert(peer, Connected { direction, ip });
+        admission
+    }
+
+    // Takes effect from the next admission; peers above a lowered limit keep
+    // their slot until they disconnect
+    pub fn set_config(&mut self, config: ConnectionLimitsConfig) {
+        self.config = config;
+    }
+
+    // Called once the last connection to `peer` is closed
+    pub fn on_closed(&mut self, peer: &PeerId) {
+        self.peers.remove(peer);
+    }
+
+    // (inbound, outbound)
+    pub fn counts(&self) -> (usize, usize) {
+        let inbound = self.peers.values().filter(|connected| connected.direction == Direction::Inbound).count();
+        (inbound, self.peers.len() - inbound)
+    }
+
+    fn lowest_inbound(&self, score: &impl Fn(&PeerId) -> f64) -> Option<(PeerId, f64)> {
+        self.peers
+            .iter()
+            .filter(|(_, connected)| connected.direction == Direction::Inbound)
+            .map(|(peer, _)| (*peer, score(peer)))
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+    }
+}
+
+fn ip_of(address: &Multiaddr) -> Option<IpAddr> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> Option<IpAddr> {
+        Some(IpAddr::from([10, 0, 0, last]))
+    }
+
+    #[test]
+    fn test_inbound_cannot_take_reserved_slots() {
+        let mut manager = ConnectionManager::new(ConnectionLimitsConfig { max_peers: 3, reserved_outbound: 1, max_inbound_per_ip: 1 });
+        let neutral = |_: &PeerId| 0.0;
+
+        assert_eq!(manager.admit_as(PeerId::random(), Direction::Inbound, ip(1), neutral), Admission::Accept);
+        assert!(matches!(manager.admit_as(PeerId::random(), Direction::Inbound, ip(1), neutral), Admission::Reject(_)));
+        assert_eq!(manager.admit_as(PeerId::random(), Direction::Inbound, ip(2), neutral), Admission::Accept);
+        // Inbound share is full and nobody scores lower than a newcomer
+        assert!(matches!(manager.admit_as(PeerId::random(), Direction::Inbound, ip(3), neutral), Admission::Reject(_)));
+        assert_eq!(manager.admit_as(PeerId::random(), Direction::Outbound, None, neutral), Admission::Accept);
+        assert_eq!(manager.counts(), (2, 1));
+    }
+
+    #[test]
+    fn test_evicts_lowest_scoring_inbound_peer() {
+        let mut manager = ConnectionManager::new(ConnectionLimitsConfig { max_peers: 2, reserved_outbound: 1, max_inbound_per_ip: 2 });
+        let (bad, good) = (PeerId::random(), PeerId::random());
+        let score = move |peer: &PeerId| if *peer == bad { -20.0 } else { 0.0 };
+
+        assert_eq!(manager.admit_as(bad, Direction::Inbound, ip(1), score), Admission::Accept);
+        assert_eq!(manager.admit_as(good, Direction::Inbound, ip(2), score), Admission::Evict(bad));
+
+        // A full node still makes room for its reserved outbound peer
+        assert_eq!(manager.admit_as(PeerId::random(), Direction::Outbound, None, score), Admission::Accept);
+        assert_eq!(manager.admit_as(PeerId::random(), Direction::Outbound, None, score), Admission::Reject("peer slots full"));
+        manager.on_closed(&good);
+        assert_eq!(manager.counts(), (0, 1));
+    }
+}
@@ -0,0 +1,79 @@
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use log::{debug, info, warn};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// Port assumed for seeds given without one
+pub const DEFAULT_SEED_PORT: u16 = 3030;
+
+// Resolves the seeds now and then every `interval`, sending the addresses
+// found until the receiver is dropped
+pub fn spawn(seeds: Vec<String>, interval: Duration, sender: mpsc::UnboundedSender<Vec<Multiaddr>>) {
+    if seeds.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            let addresses = resolve(&seeds).await;
+            info!("DNS seeds returned {} addresses", addresses.len());
+            if sender.send(addresses).is_err() {
+                break;
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+// Addresses behind every seed; seeds that fail to resolve are skipped
+pub async fn resolve(seeds: &[String]) -> Vec<Multiaddr> {
+    let mut addresses = Vec::new();
+    for seed in seeds {
+        match tokio::net::lookup_host(target(seed)).await {
+            Ok(resolved) => {
+                let before = addresses.len();
+                for address in resolved.map(|address| to_multiaddr(&address)) {
+                    if !addresses.contains(&address) {
+                        addresses.push(address);
+                    }
+                }
+                debug!("DNS seed {} resolved to {} new addresses", seed, addresses.len() - before);
+            }
+            Err(e) => warn!("Failed to resolve DNS seed {}: {}", seed, e),
+        }
+    }
+    addresses
+}
+
+// "host" or "host:port"
+fn target(seed: &str) -> String {
+    match seed.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => seed.to_string(),
+        _ => format!("{}:{}", seed, DEFAULT_SEED_PORT),
+    }
+}
+
+fn to_multiaddr(address: &SocketAddr) -> Multiaddr {
+    let ip = match address.ip() {
+        IpAddr::V4(ip) => Protocol::Ip4(ip),
+        IpAddr::V6(ip) => Protocol::Ip6(ip),
+    };
+    Multiaddr::empty().with(ip).with(Protocol::Tcp(address.port()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed_targets_and_addresses() {
+        assert_eq!(target("seed.omnitensor.io"), "seed.omnitensor.io:3030");
+        assert_eq!(target("seed.omnitensor.io:4040"), "seed.omnitensor.io:4040");
+
+        let address: SocketAddr = "10.0.0.1:3030".parse().unwrap();
+        assert_eq!(to_multiaddr(&address).to_string(), "/ip4/10.0.0.1/tcp/3030");
+        let address: SocketAddr = "[::1]:3030".parse().unwrap();
+        assert_eq!(to_multiaddr(&address).to_string(), "/ip6/::1/tcp/3030");
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+
+use crate::network::codec::{BincodeCodec, Protocol};
+
+pub const GOODBYE_PROTOCOL: Protocol = Protocol("/omnitensor/goodbye/1");
+
+// Sent to a peer right before we close the connection, so both operators can
+// see why. On the wire the reason is a single code byte; codes we don't know
+// yet are kept as `Unknown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GoodbyeReason {
+    Shutdown,
+    Banned,
+    IncompatibleVersion,
+    WrongNetwork,
+    TooManyPeers,
+    ProtocolError,
+    Unknown(u8),
+}
+
+impl GoodbyeReason {
+    pub fn code(&self) -> u8 {
+        match self {
+            GoodbyeReason::Shutdown => 1,
+            GoodbyeReason::Banned => 2,
+            GoodbyeReason::IncompatibleVersion => 3,
+            GoodbyeReason::WrongNetwork => 4,
+            GoodbyeReason::TooManyPeers => 5,
+            GoodbyeReason::ProtocolError => 6,
+            GoodbyeReason::Unknown(code) => *code,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            1 => GoodbyeReason::Shutdown,
+            2 => GoodbyeReason::Banned,
+            3 => GoodbyeReason::IncompatibleVersion,
+            4 => GoodbyeReason::WrongNetwork,
+            5 => GoodbyeReason::TooManyPeers,
+            6 => GoodbyeReason::ProtocolError,
+            code => GoodbyeReason::Unknown(code),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GoodbyeReason::Shutdown => "shutdown",
+            GoodbyeReason::Banned => "banned",
+            GoodbyeReason::IncompatibleVersion => "incompatible_version",
+            GoodbyeReason::WrongNetwork => "wrong_network",
+            GoodbyeReason::TooManyPeers => "too_many_peers",
+            GoodbyeReason::ProtocolError => "protocol_error",
+            GoodbyeReason::Unknown(_) => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for GoodbyeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoodbyeReason::Unknown(code) => write!(f, "unknown ({})", code),
+            reason => f.write_str(reason.label()),
+        }
+    }
+}
+
+// Request is the reason code, the empty response acknowledges it
+pub type GoodbyeCodec = BincodeCodec<u8, ()>;
+
+pub fn goodbye_codec() -> GoodbyeCodec {
+    BincodeCodec::new(16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reason_codes_roundtrip() {
+        for code in 0..=u8::MAX {
+            assert_eq!(GoodbyeReason::from_code(code).code(), code);
+        }
+        assert_eq!(GoodbyeReason::from_code(4), GoodbyeReason::WrongNetwork);
+        assert_eq!(GoodbyeReason::from_code(200).to_string(), "unknown (200)");
+    }
+}
@@ -0,0 +1,196 @@
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed};
+use libp2p::request_response::RequestResponseCodec;
+use serde::{Deserialize, Serialize};
+use std::io;
+use thiserror::Error;
+
+use crate::network::codec::{decode_bounded, Protocol};
+use crate::network::compression::PROTOCOL_VERSION;
+use crate::network::goodbye::GoodbyeReason;
+
+// Oldest wire protocol version we still talk to
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+const MAX_STATUS_LEN: usize = 1024;
+
+// Exchanged right after a connection is established: the dialer sends its
+// status, the listener answers with its own, and either side disconnects if
+// the other is on a different network or an incompatible version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    pub protocol_version: u32,
+    pub chain_id: u64,
+    pub genesis_hash: [u8; 32],
+    pub head_height: u64,
+    // The sender's clock in ms since the epoch, for `network::clock`; `None`
+    // from peers on the legacy handshake
+    pub timestamp_ms: Option<u64>,
+}
+
+// The status before it carried a timestamp
+#[derive(Serialize, Deserialize)]
+struct LegacyStatus {
+    protocol_version: u32,
+    chain_id: u64,
+    genesis_hash: [u8; 32],
+    head_height: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Incompatibility {
+    #[error("Protocol version {theirs} is not supported (need {min} or newer)")]
+    ProtocolVersion { theirs: u32, min: u32 },
+    #[error("Chain id {theirs} does not match ours ({ours})")]
+    ChainId { ours: u64, theirs: u64 },
+    #[error("Genesis {} does not match ours", hex(.theirs))]
+    Genesis { theirs: [u8; 32] },
+}
+
+impl Incompatibility {
+    pub fn goodbye_reason(&self) -> GoodbyeReason {
+        match self {
+            Incompatibility::ProtocolVersion { .. } => GoodbyeReason::IncompatibleVersion,
+            Incompatibility::ChainId { .. } | Incompatibility::Genesis { .. } => GoodbyeReason::WrongNetwork,
+        }
+    }
+}
+
+impl Status {
+    pub fn new(chain_id: u64, genesis_hash: [u8; 32], head_height: u64) -> Self {
+        Self { protocol_version: PROTOCOL_VERSION, chain_id, genesis_hash, head_height, timestamp_ms: None }
+    }
+
+    // This status as sent now
+    pub fn stamped(&self, now_ms: u64) -> Self {
+        Self { timestamp_ms: Some(now_ms), ..self.clone() }
+    }
+
+    pub fn check_compatible(&self, remote: &Status) -> Result<(), Incompatibility> {
+        if remote.protocol_version < MIN_PROTOCOL_VERSION {
+            return Err(Incompatibility::ProtocolVersion { theirs: remote.protocol_version, min: MIN_PROTOCOL_VERSION });
+        }
+        if remote.chain_id != self.chain_id {
+            return Err(Incompatibility::ChainId { ours: self.chain_id, theirs: remote.chain_id });
+        }
+        if remote.genesis_hash != self.genesis_hash {
+            return Err(Incompatibility::Genesis { theirs: remote.genesis_hash });
+        }
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub const HANDSHAKE_PROTOCOL: Protocol = Protocol("/omnitensor/handshake/2");
+// Still answered for nodes from before the status carried a timestamp
+pub const LEGACY_HANDSHAKE_PROTOCOL: Protocol = Protocol("/omnitensor/handshake/1");
+
+// Length-prefixed bincode like `BincodeCodec`, in the status layout of
+// whichever protocol version was negotiated
+#[derive(Debug, Clone)]
+pub struct HandshakeCodec {
+    max_len: usize,
+}
+
+pub fn handshake_codec() -> HandshakeCodec {
+    HandshakeCodec { max_len: MAX_STATUS_LEN }
+}
+
+impl HandshakeCodec {
+    async fn read<T: AsyncRead + Unpin + Send>(&self, protocol: &Protocol, io: &mut T) -> io::Result<Status> {
+        let bytes = read_length_prefixed(io, self.max_len).await?;
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+        if protocol.0 != LEGACY_HANDSHAKE_PROTOCOL.0 {
+            return decode_bounded(&bytes).map_err(invalid);
+        }
+        let legacy: LegacyStatus = decode_bounded(&bytes).map_err(invalid)?;
+        Ok(Status {
+            protocol_version: legacy.protocol_version,
+            chain_id: legacy.chain_id,
+            genesis_hash: legacy.genesis_hash,
+            head_height: legacy.head_height,
+            timestamp_ms: None,
+        })
+    }
+
+    async fn write<T: AsyncWrite + Unpin + Send>(&self, protocol: &Protocol, io: &mut T, status: Status) -> io::Result<()> {
+        let bytes = if protocol.0 == LEGACY_HANDSHAKE_PROTOCOL.0 {
+            bincode::serialize(&LegacyStatus {
+                protocol_version: status.protocol_version,
+                chain_id: status.chain_id,
+                genesis_hash: status.genesis_hash,
+                head_height: status.head_height,
+            })
+        } else {
+            bincode::serialize(&status)
+        };
+        let bytes = bytes.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_length_prefixed(io, bytes).await?;
+        io.close().await
+    }
+}
+
+#[async_trait]
+impl RequestResponseCodec for HandshakeCodec {
+    type Protocol = Protocol;
+    type Request = Status;
+    type Response = Status;
+
+    async fn read_request<T: AsyncRead + Unpin + Send>(&mut self, protocol: &Protocol, io: &mut T) -> io::Result<Status> {
+        self.read(protocol, io).await
+    }
+
+    async fn read_response<T: AsyncRead + Unpin + Send>(&mut self, protocol: &Protocol, io: &mut T) -> io::Result<Status> {
+        self.read(protocol, io).await
+    }
+
+    async fn write_request<T: AsyncWrite + Unpin + Send>(&mut self, protocol: &Protocol, io: &mut T, request: Status) -> io::Result<()> {
+        self.write(protocol, io, request).await
+    }
+
+    async fn write_response<T: AsyncWrite + Unpin + Send>(&mut self, protocol: &Protocol, io: &mut T, response: Status) -> io::Result<()> {
+        self.write(protocol, io, response).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compatibility() {
+        let ours = Status::new(1, [1; 32], 100);
+        assert_eq!(ours.check_compatible(&Status { head_height: 5, ..ours.clone() }), Ok(()));
+        assert_eq!(
+            ours.check_compatible(&Status { protocol_version: 1, ..ours.clone() }),
+            Err(Incompatibility::ProtocolVersion { theirs: 1, min: MIN_PROTOCOL_VERSION })
+        );
+        assert_eq!(
+            ours.check_compatible(&Status { chain_id: 2, ..ours.clone() }),
+            Err(Incompatibility::ChainId { ours: 1, theirs: 2 })
+        );
+        let other_genesis = Status { genesis_hash: [2; 32], ..ours.clone() };
+        assert!(ours.check_compatible(&other_genesis).unwrap_err().to_string().starts_with("Genesis 0202"));
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_only_on_the_current_protocol() {
+        let mut codec = handshake_codec();
+        let status = Status::new(1, [1; 32], 100).stamped(1_700_000_000_000);
+        for (protocol, timestamp_ms) in [(HANDSHAKE_PROTOCOL, Some(1_700_000_000_000)), (LEGACY_HANDSHAKE_PROTOCOL, None)] {
+            let mut wire = Vec::new();
+            codec.write_request(&protocol, &mut wire, status.clone()).await.unwrap();
+            let read = codec.read_request(&protocol, &mut wire.as_slice()).await.unwrap();
+            assert_eq!(read, Status { timestamp_ms, ..status.clone() });
+        }
+        // Nodes on the legacy protocol sent exactly this
+        let legacy = bincode::serialize(&(2u32, 1u64, [1u8; 32], 100u64)).unwrap();
+        let mut wire = Vec::new();
+        write_length_prefixed(&mut wire, legacy).await.unwrap();
+        let read = codec.read_request(&LEGACY_HANDSHAKE_PROTOCOL, &mut wire.as_slice()).await.unwrap();
+        assert_eq!(read, Status::new(1, [1; 32], 100));
+    }
+}
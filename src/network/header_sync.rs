@@ -0,0 +1,1041 @@
+use libp2p::PeerId;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::instrument;
+
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::store::ChainHead;
+use crate::chain::transaction::TransactionReceipt;
+use crate::consensus::proof::Proof;
+use crate::network::block_gossip::BlockHash;
+use crate::network::clock::{self, ClockConfig};
+use crate::network::codec::{BincodeCodec, Protocol};
+use crate::network::peer::Misbehavior;
+
+pub const HEADERS_PROTOCOL: Protocol = Protocol("/omnitensor/headers/1");
+pub const BODIES_PROTOCOL: Protocol = Protocol("/omnitensor/bodies/1");
+pub const HISTORY_PROTOCOL: Protocol = Protocol("/omnitensor/history/1");
+const MAX_HEADERS_RESPONSE_LEN: usize = 1024 * 1024;
+const MAX_BODIES_RESPONSE_LEN: usize = 16 * 1024 * 1024;
+// Served per request, whatever the peer asks for
+pub const MAX_HEADERS_PER_REQUEST: u32 = 1024;
+pub const MAX_BODIES_PER_REQUEST: usize = 128;
+
+// Headers for `start..start + count` on the serving peer's best chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeadersRequest {
+    pub start: u64,
+    pub count: u32,
+}
+
+pub type HeadersCodec = BincodeCodec<HeadersRequest, Vec<BlockHeader>>;
+
+// Bodies for the requested hashes, in request order, skipping unknown ones
+pub type BodiesCodec = BincodeCodec<Vec<BlockHash>, Vec<Block>>;
+
+// Bodies with the receipts of executing them, for history imported without
+// re-execution; otherwise like bodies
+pub type HistoryCodec = BincodeCodec<Vec<BlockHash>, Vec<(Block, Vec<TransactionReceipt>)>>;
+
+pub fn headers_codec() -> HeadersCodec {
+    BincodeCodec::new(MAX_HEADERS_RESPONSE_LEN)
+}
+
+pub fn bodies_codec() -> BodiesCodec {
+    BincodeCodec::new(MAX_BODIES_RESPONSE_LEN)
+}
+
+pub fn history_codec() -> HistoryCodec {
+    BincodeCodec::new(MAX_BODIES_RESPONSE_LEN)
+}
+
+// Answers a headers request, stopping at the first height we don't have
+pub fn serve_headers(request: HeadersRequest, lookup: impl Fn(u64) -> Option<BlockHeader>) -> Vec<BlockHeader> {
+    (request.start..).take(request.count.min(MAX_HEADERS_PER_REQUEST) as usize).map_while(lookup).collect()
+}
+
+// Answers a bodies request with as many blocks as fit in one response
+pub fn serve_bodies(hashes: &[BlockHash], lookup: impl Fn(&BlockHash) -> Option<Block>) -> Vec<Block> {
+    let mut size = 8;
+    let mut blocks = Vec::new();
+    for block in hashes.iter().take(MAX_BODIES_PER_REQUEST).filter_map(lookup) {
+        size += bincode::serialized_size(&block).unwrap_or(u64::MAX) as usize;
+        if size > MAX_BODIES_RESPONSE_LEN {
+            break;
+        }
+        blocks.push(block);
+    }
+    blocks
+}
+
+// Answers a history request, leaving out blocks whose receipts we don't have
+pub fn serve_history(
+    hashes: &[BlockHash],
+    lookup: impl Fn(&BlockHash) -> Option<Block>,
+    receipts: impl Fn(&BlockHash) -> Option<Vec<TransactionReceipt>>,
+) -> Vec<(Block, Vec<TransactionReceipt>)> {
+    let mut size = 8;
+    let mut history = Vec::new();
+    for hash in hashes.iter().take(MAX_BODIES_PER_REQUEST) {
+        let entry = match (lookup(hash), receipts(hash)) {
+            (Some(block), Some(receipts)) => (block, receipts),
+            _ => continue,
+        };
+        size += bincode::serialized_size(&entry).unwrap_or(u64::MAX) as usize;
+        if size > MAX_BODIES_RESPONSE_LEN {
+            break;
+        }
+        history.push(entry);
+    }
+    history
+}
+
+// `[network.header_sync]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeaderSyncConfig {
+    pub headers_per_request: u32,
+    // Header download pauses this far ahead of the last block handed on for
+    // import, until bodies catch up
+    pub max_headers_ahead: usize,
+    // Each peer has at most one bodies request outstanding
+    pub bodies_per_request: usize,
+    // Then the request is handed to another peer and the slow one penalized
+    pub request_timeout_ms: u64,
+    // Forks further below our imported head than this are never followed
+    pub max_reorg_depth: u64,
+    // A peer that fails a request is skipped for this long, doubling with
+    // every failure in a row up to the max, while others take over
+    pub retry_backoff_ms: u64,
+    pub max_retry_backoff_ms: u64,
+    // Peers serving this many invalid responses aren't synced from again
+    pub max_invalid_responses: u32,
+    pub checkpoint: Option<CheckpointConfig>,
+    // With a checkpoint, download the blocks below it with their receipts
+    // and import them without executing, instead of skipping them, e.g. for
+    // an archive node; the state is then fast synced at the checkpoint
+    pub archive_history: bool,
+}
+
+impl Default for HeaderSyncConfig {
+    fn default() -> Self {
+        Self {
+            headers_per_request: 512,
+            max_headers_ahead: 16384,
+            bodies_per_request: 32,
+            request_timeout_ms: 10_000,
+            max_reorg_depth: 1024,
+            retry_backoff_ms: 1000,
+            max_retry_backoff_ms: 60_000,
+            max_invalid_responses: 3,
+            checkpoint: None,
+            archive_history: false,
+        }
+    }
+}
+
+// `[network.header_sync.checkpoint]`: a block trusted out of band, e.g. from
+// the release notes. A node behind it starts syncing there instead of at its
+// head, only accepts chains through it, and fast syncs the state above it;
+// the history below is never downloaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointConfig {
+    pub height: u64,
+    // 64 hex characters
+    pub hash: String,
+}
+
+impl CheckpointConfig {
+    pub fn parse(&self) -> Result<ChainHead, String> {
+        let text = self.hash.trim().trim_start_matches("0x");
+        let invalid = || format!("checkpoint hash {} is not 32 hex-encoded bytes", self.hash);
+        if text.len() != 64 {
+            return Err(invalid());
+        }
+        let mut hash = [0u8; 32];
+        for (byte, pair) in hash.iter_mut().zip(text.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        if self.height == 0 {
+            return Err("checkpoint must be above genesis".to_string());
+        }
+        Ok(ChainHead { height: self.height, hash })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum HeaderSyncError {
+    #[error("Response to a request we didn't send")]
+    Unsolicited,
+    #[error("Peer returned no headers past its claimed head")]
+    NoHeaders,
+    #[error("Peer returned {got} headers, asked for {requested}")]
+    TooManyHeaders { requested: u32, got: usize },
+    #[error("Header at height {0} doesn't link to its parent")]
+    Unlinked(u64),
+    #[error("Header at height {0} has an invalid proof")]
+    InvalidProof(u64),
+    #[error("Peer's chain forks below the blocks we can reorg")]
+    ForkBelowBase,
+    #[error("Peer returned no bodies")]
+    NoBodies,
+    #[error("Peer returned a body we didn't ask for")]
+    UnrequestedBody,
+    #[error("Body at height {0} doesn't match its header")]
+    InvalidBody(u64),
+    #[error("Header at height {0} isn't the checkpoint")]
+    NotCheckpoint(u64),
+    #[error("Receipts at height {0} don't match the header")]
+    InvalidReceipts(u64),
+    #[error("Header at height {0} is timestamped too far ahead of our clock")]
+    FutureTimestamp(u64),
+}
+
+impl HeaderSyncError {
+    // How a peer answering with this is penalized, if at all
+    pub fn misbehavior(&self) -> Option<Misbehavior> {
+        match self {
+            // Our clock may be the one behind
+            HeaderSyncError::Unsolicited | HeaderSyncError::ForkBelowBase | HeaderSyncError::FutureTimestamp(_) => None,
+            HeaderSyncError::NoHeaders | HeaderSyncError::NoBodies => Some(Misbehavior::SyncTimeout),
+            HeaderSyncError::TooManyHeaders { .. } | HeaderSyncError::UnrequestedBody => {
+                Some(Misbehavior::MalformedMessage)
+            }
+            HeaderSyncError::Unlinked(_)
+            | HeaderSyncError::InvalidProof(_)
+            | HeaderSyncError::InvalidBody(_)
+            | HeaderSyncError::NotCheckpoint(_)
+            | HeaderSyncError::InvalidReceipts(_) => Some(Misbehavior::InvalidBlock),
+        }
+    }
+}
+
+struct HeadersInFlight {
+    peer: PeerId,
+    request: HeadersRequest,
+    sent: Instant,
+    // A single header asked for by the ancestor search
+    probe: bool,
+}
+
+// Binary search for the last block our imported chain shares with a peer's,
+// one header per request. The lowest height we would reorg to is checked
+// first, so a deeper fork is given up on straight away.
+struct AncestorSearch {
+    peer: PeerId,
+    floor: u64,
+    common: Option<ChainHead>,
+    // Lowest height known to differ
+    diverged: u64,
+}
+
+impl AncestorSearch {
+    fn probe(&self) -> u64 {
+        match self.common {
+            Some(common) => common.height + (self.diverged - common.height) / 2,
+            None => self.floor,
+        }
+    }
+}
+
+#[derive(Default)]
+struct PeerFailures {
+    // Failed requests since the last good response
+    in_a_row: u32,
+    invalid: u32,
+    retry_at: Option<Instant>,
+}
+
+// Our imported blocks above `ancestor`, up to `replaced`, are on a fork. They
+// are only replaced once the verified headers of the other branch go past
+// them, longest chain wins, and the blocks of that branch are handed on.
+#[derive(Debug, Clone, Copy)]
+struct Reorg {
+    ancestor: ChainHead,
+    replaced: u64,
+}
+
+struct BodiesInFlight {
+    heights: Vec<u64>,
+    sent: Instant,
+}
+
+// Header-first sync. The header chain past our head is downloaded and
+// verified first, which is cheap and exposes peers serving bogus chains
+// before any body is fetched; bodies are then backfilled in parallel from
+// several peers, only for the heights on that chain, and handed on in order.
+pub struct HeaderSync {
+    config: HeaderSyncConfig,
+    // Last block handed on for import
+    base: ChainHead,
+    // Verified headers for `base.height + 1` onwards, with their hashes
+    headers: VecDeque<(BlockHash, BlockHeader)>,
+    headers_in_flight: Option<HeadersInFlight>,
+    // Set when a peer's headers didn't link to our tip, to look for the fork
+    // point further back on its next request
+    fork_probe: Option<(PeerId, u64)>,
+    // Looks for the fork point below `base` once the probe reaches it
+    ancestor_search: Option<AncestorSearch>,
+    reorg: Option<Reorg>,
+    bodies_in_flight: HashMap<PeerId, BodiesInFlight>,
+    bodies: BTreeMap<u64, Block>,
+    failures: HashMap<PeerId, PeerFailures>,
+    // Blocks up to this checkpoint come with receipts and aren't executed
+    history: Option<ChainHead>,
+    receipts: HashMap<u64, Vec<TransactionReceipt>>,
+    // Headers too far ahead of the local clock are refused
+    clock: ClockConfig,
+}
+
+impl HeaderSync {
+    pub fn new(config: HeaderSyncConfig, base: ChainHead) -> Self {
+        Self {
+            config,
+            base,
+            headers: VecDeque::new(),
+            headers_in_flight: None,
+            fork_probe: None,
+            ancestor_search: None,
+            reorg: None,
+            bodies_in_flight: HashMap::new(),
+            bodies: BTreeMap::new(),
+            failures: HashMap::new(),
+            history: None,
+            receipts: HashMap::new(),
+            clock: ClockConfig::default(),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: ClockConfig) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Syncs up to `checkpoint`, which must be on the chain, as history: see
+    // `on_history`
+    pub fn with_history(mut self, checkpoint: ChainHead) -> Self {
+        self.history = Some(checkpoint).filter(|checkpoint| checkpoint.height > self.base.height);
+        self
+    }
+
+    // Bodies are requested with their receipts while this holds
+    pub fn in_history(&self) -> bool {
+        self.history.map_or(false, |checkpoint| self.base.height < checkpoint.height)
+    }
+
+    pub fn base(&self) -> ChainHead {
+        self.base
+    }
+
+    // End of the verified header chain
+    pub fn tip(&self) -> ChainHead {
+        match self.headers.back() {
+            Some((hash, _)) => ChainHead { height: self.base.height + self.headers.len() as u64, hash: *hash },
+            None => self.base,
+        }
+    }
+
+    pub fn header_at(&self, height: u64) -> Option<&BlockHeader> {
+        let index = height.checked_sub(self.base.height + 1)?;
+        self.headers.get(index as usize).map(|(_, header)| header)
+    }
+
+    // Moves the base up to `height` on the header chain without fetching the
+    // bodies below it, for fast sync to start from the state there instead
+    pub fn skip_to(&mut self, height: u64) -> Option<ChainHead> {
+        let hash = self.hash_at(height).filter(|_| height > self.base.height)?;
+        self.headers.drain(..(height - self.base.height) as usize);
+        self.base = ChainHead { height, hash };
+        self.bodies.retain(|body_height, _| *body_height > height);
+        self.receipts.retain(|body_height, _| *body_height > height);
+        self.bodies_in_flight.retain(|_, in_flight| in_flight.heights.iter().all(|requested| *requested > height));
+        Some(self.base)
+    }
+
+    fn hash_at(&self, height: u64) -> Option<BlockHash> {
+        if height == self.base.height {
+            return Some(self.base.hash);
+        }
+        let index = height.checked_sub(self.base.height + 1)?;
+        self.headers.get(index as usize).map(|(hash, _)| *hash)
+    }
+
+    // `peers` with their claimed head heights, best first. Returns the next
+    // headers request, if one is due; one is outstanding at a time.
+    pub fn next_headers_request(&mut self, peers: &[(PeerId, u64)]) -> Option<(PeerId, HeadersRequest)> {
+        self.next_headers_request_at(peers, Instant::now())
+    }
+
+    fn next_headers_request_at(&mut self, peers: &[(PeerId, u64)], now: Instant) -> Option<(PeerId, HeadersRequest)> {
+        if self.headers_in_flight.is_some() {
+            return None;
+        }
+        let peers = self.usable(peers, now);
+        if let Some(search) = self.ancestor_search.take() {
+            if peers.iter().any(|(peer, _)| *peer == search.peer) {
+                let (peer, request) = (search.peer, HeadersRequest { start: search.probe(), count: 1 });
+                self.headers_in_flight = Some(HeadersInFlight { peer, request, sent: now, probe: true });
+                self.ancestor_search = Some(search);
+                return Some((peer, request));
+            }
+        }
+        if self.headers.len() >= self.config.max_headers_ahead {
+            return None;
+        }
+        let tip = self.tip().height;
+        let probe =
+            self.fork_probe.take().filter(|(peer, _)| peers.iter().any(|(other, head)| other == peer && *head > tip));
+        let (peer, start) = match probe {
+            Some(probe) => probe,
+            None => (peers.iter().find(|(_, head)| *head > tip)?.0, tip + 1),
+        };
+        let request =
+            HeadersRequest { start, count: self.config.headers_per_request.clamp(1, MAX_HEADERS_PER_REQUEST) };
+        self.headers_in_flight = Some(HeadersInFlight { peer, request, sent: now, probe: false });
+        Some((peer, request))
+    }
+
+    // Verifies and merges a headers response; returns how many headers were
+    // added. A batch that forks off our header chain replaces the rest of it,
+    // as the peer was picked for claiming a longer chain. `local` gives the
+    // hashes of our imported blocks, to find where a deeper fork starts.
+    #[instrument(level = "debug", skip_all, fields(peer_id = %peer))]
+    pub fn on_headers(
+        &mut self,
+        peer: PeerId,
+        headers: Vec<BlockHeader>,
+        local: impl Fn(u64) -> Option<BlockHash>,
+    ) -> Result<usize, HeaderSyncError> {
+        let result = self.verify_headers(peer, headers, local);
+        self.record(peer, result.as_ref().err(), Instant::now());
+        result
+    }
+
+    fn verify_headers(
+        &mut self,
+        peer: PeerId,
+        headers: Vec<BlockHeader>,
+        local: impl Fn(u64) -> Option<BlockHash>,
+    ) -> Result<usize, HeaderSyncError> {
+        let (request, probe) = match self.headers_in_flight.take() {
+            Some(in_flight) if in_flight.peer == peer => (in_flight.request, in_flight.probe),
+            other => {
+                self.headers_in_flight = other;
+                return Err(HeaderSyncError::Unsolicited);
+            }
+        };
+        if headers.is_empty() {
+            return Err(HeaderSyncError::NoHeaders);
+        }
+        if probe {
+            return self.on_probe(peer, request, &headers, local);
+        }
+        // Bodies caught up past the request while it was out
+        if request.start <= self.base.height {
+            return Ok(0);
+        }
+        if headers.len() > request.count as usize {
+            return Err(HeaderSyncError::TooManyHeaders { requested: request.count, got: headers.len() });
+        }
+
+        let now_ms = clock::unix_millis();
+        let mut hashes = Vec::with_capacity(headers.len());
+        for (offset, header) in headers.iter().enumerate() {
+            let height = request.start + offset as u64;
+            if offset > 0 && header.prev_block_hash != hashes[offset - 1] {
+                return Err(HeaderSyncError::Unlinked(height));
+            }
+            if self.clock.check_timestamp(header.timestamp, now_ms).is_err() {
+                return Err(HeaderSyncError::FutureTimestamp(height));
+            }
+            if !Proof::new(header).is_valid(header.difficulty) {
+                return Err(HeaderSyncError::InvalidProof(height));
+            }
+            hashes.push(header_hash(header));
+            if let Some(checkpoint) = self.history.filter(|checkpoint| checkpoint.height == height) {
+                if hashes[offset] != checkpoint.hash {
+                    return Err(HeaderSyncError::NotCheckpoint(height));
+                }
+            }
+        }
+
+        if self.hash_at(request.start - 1) != Some(headers[0].prev_block_hash) {
+            if request.start <= self.base.height + 1 {
+                return self.search_ancestor(peer, local);
+            }
+            let back = request.start.saturating_sub(request.count as u64).max(self.base.height + 1);
+            self.fork_probe = Some((peer, back));
+            return Ok(0);
+        }
+
+        let mut added = 0;
+        for ((hash, header), height) in hashes.into_iter().zip(headers).zip(request.start..) {
+            match self.hash_at(height) {
+                Some(ours) if ours == hash => continue,
+                Some(_) => {
+                    info!("Switching header chain to the branch of {} from height {}", peer, height);
+                    self.truncate(height);
+                }
+                None => {}
+            }
+            self.headers.push_back((hash, header));
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    // The peer's chain doesn't link to `base`, so it forks off our imported
+    // blocks somewhere below
+    fn search_ancestor(
+        &mut self,
+        peer: PeerId,
+        local: impl Fn(u64) -> Option<BlockHash>,
+    ) -> Result<usize, HeaderSyncError> {
+        let floor = self.base.height.saturating_sub(self.config.max_reorg_depth);
+        if floor >= self.base.height || local(floor).is_none() {
+            return Err(HeaderSyncError::ForkBelowBase);
+        }
+        debug!("Searching for the block {}'s chain shares with ours", peer);
+        self.ancestor_search = Some(AncestorSearch { peer, floor, common: None, diverged: self.base.height });
+        Ok(0)
+    }
+
+    fn on_probe(
+        &mut self,
+        peer: PeerId,
+        request: HeadersRequest,
+        headers: &[BlockHeader],
+        local: impl Fn(u64) -> Option<BlockHash>,
+    ) -> Result<usize, HeaderSyncError> {
+        let mut search = match self.ancestor_search.take() {
+            Some(search) if search.peer == peer && search.probe() == request.start => search,
+            other => {
+                self.ancestor_search = other;
+                return Ok(0);
+            }
+        };
+        if headers.len() > 1 {
+            return Err(HeaderSyncError::TooManyHeaders { requested: 1, got: headers.len() });
+        }
+        let height = request.start;
+        if !Proof::new(&headers[0]).is_valid(headers[0].difficulty) {
+            return Err(HeaderSyncError::InvalidProof(height));
+        }
+        let ours = local(height).ok_or(HeaderSyncError::ForkBelowBase)?;
+        if header_hash(&headers[0]) == ours {
+            search.common = Some(ChainHead { height, hash: ours });
+        } else if search.common.is_none() {
+            return Err(HeaderSyncError::ForkBelowBase);
+        } else {
+            search.diverged = height;
+        }
+        match search.common {
+            Some(common) if search.diverged - common.height == 1 => {
+                info!("Chain of {} forks off ours after height {}", peer, common.height);
+                self.rebase(common);
+            }
+            _ => self.ancestor_search = Some(search),
+        }
+        Ok(0)
+    }
+
+    // Restarts the header chain from `ancestor`, below our imported head
+    fn rebase(&mut self, ancestor: ChainHead) {
+        let replaced = self.reorg.map_or(self.base.height, |reorg| reorg.replaced.max(self.base.height));
+        self.reorg = Some(Reorg { ancestor, replaced });
+        self.base = ancestor;
+        self.headers.clear();
+        self.fork_probe = None;
+        self.bodies.clear();
+        self.receipts.clear();
+        self.bodies_in_flight.clear();
+    }
+
+    // Set once the first blocks of a branch that forks below our imported
+    // head are handed on, before them: our blocks above the returned
+    // ancestor must be dropped first, see `ChainStore::truncate`
+    pub fn take_reorg(&mut self) -> Option<ChainHead> {
+        let reorg = self.reorg.filter(|reorg| self.base.height > reorg.ancestor.height)?;
+        self.reorg = None;
+        Some(reorg.ancestor)
+    }
+
+    // Drops headers from `height` on, and any bodies fetched or being fetched for them
+    fn truncate(&mut self, height: u64) {
+        self.headers.truncate((height - self.base.height - 1) as usize);
+        self.bodies.retain(|body_height, _| *body_height < height);
+        self.receipts.retain(|body_height, _| *body_height < height);
+        self.bodies_in_flight.retain(|_, in_flight| in_flight.heights.iter().all(|requested| *requested < height));
+    }
+
+    // Bodies requests to send now, at most one per peer, lowest heights first
+    // and only to peers claiming to have them
+    pub fn next_body_requests(&mut self, peers: &[(PeerId, u64)]) -> Vec<(PeerId, Vec<BlockHash>)> {
+        self.next_body_requests_at(peers, Instant::now())
+    }
+
+    fn next_body_requests_at(&mut self, peers: &[(PeerId, u64)], now: Instant) -> Vec<(PeerId, Vec<BlockHash>)> {
+        // A branch replacing our blocks has to be proven longer first
+        if self.reorg.map_or(false, |reorg| self.tip().height <= reorg.replaced) {
+            return Vec::new();
+        }
+        let peers = self.usable(peers, now);
+        let in_flight: Vec<u64> =
+            self.bodies_in_flight.values().flat_map(|in_flight| in_flight.heights.clone()).collect();
+        let last = match self.history {
+            Some(checkpoint) if self.in_history() => checkpoint.height.min(self.tip().height),
+            _ => self.tip().height,
+        };
+        let mut wanted = (self.base.height + 1..=last)
+            .filter(|height| !self.bodies.contains_key(height) && !in_flight.contains(height))
+            .peekable();
+
+        let mut requests = Vec::new();
+        for (peer, head) in &peers {
+            if self.bodies_in_flight.contains_key(peer) {
+                continue;
+            }
+            let mut heights = Vec::new();
+            while heights.len() < self.config.bodies_per_request.clamp(1, MAX_BODIES_PER_REQUEST) {
+                match wanted.next_if(|height| height <= head) {
+                    Some(height) => heights.push(height),
+                    None => break,
+                }
+            }
+            if heights.is_empty() {
+                continue;
+            }
+            let hashes = heights.iter().filter_map(|height| self.hash_at(*height)).collect();
+            self.bodies_in_flight.insert(*peer, BodiesInFlight { heights, sent: now });
+            requests.push((*peer, hashes));
+        }
+        requests
+    }
+
+    // Checks a bodies response against the headers and returns the blocks
+    // that are now ready for import, in chain order. Heights the peer left
+    // out are requested again later, possibly from someone else.
+    #[instrument(level = "debug", skip_all, fields(peer_id = %peer))]
+    pub fn on_bodies(&mut self, peer: PeerId, blocks: Vec<Block>) -> Result<Vec<(u64, Block)>, HeaderSyncError> {
+        let result = self.verify_bodies(peer, blocks).map(|received| {
+            self.bodies.extend(received);
+            self.ready()
+        });
+        self.record(peer, result.as_ref().err(), Instant::now());
+        result
+    }
+
+    // Like `on_bodies`, for history: the ready blocks come with receipts
+    // checked against their headers, to import without executing
+    #[instrument(level = "debug", skip_all, fields(peer_id = %peer))]
+    pub fn on_history(
+        &mut self,
+        peer: PeerId,
+        history: Vec<(Block, Vec<TransactionReceipt>)>,
+    ) -> Result<Vec<(u64, Block, Vec<TransactionReceipt>)>, HeaderSyncError> {
+        let result = self.verify_history(peer, history);
+        self.record(peer, result.as_ref().err(), Instant::now());
+        result
+    }
+
+    fn verify_history(
+        &mut self,
+        peer: PeerId,
+        history: Vec<(Block, Vec<TransactionReceipt>)>,
+    ) -> Result<Vec<(u64, Block, Vec<TransactionReceipt>)>, HeaderSyncError> {
+        let (blocks, receipts): (Vec<Block>, Vec<Vec<TransactionReceipt>>) = history.into_iter().unzip();
+        let received = self.verify_bodies(peer, blocks)?;
+        for ((height, block), receipts) in received.iter().zip(&receipts) {
+            if !block.receipts_match(*height, receipts) {
+                return Err(HeaderSyncError::InvalidReceipts(*height));
+            }
+        }
+        for ((height, block), receipts) in received.into_iter().zip(receipts) {
+            self.receipts.insert(height, receipts);
+            self.bodies.insert(height, block);
+        }
+        let ready = self.ready();
+        Ok(ready
+            .into_iter()
+            .map(|(height, block)| (height, block, self.receipts.remove(&height).unwrap_or_default()))
+            .collect())
+    }
+
+    // The received bodies with their heights, in response order
+    fn verify_bodies(&mut self, peer: PeerId, blocks: Vec<Block>) -> Result<Vec<(u64, Block)>, HeaderSyncError> {
+        let heights = self.bodies_in_flight.remove(&peer).ok_or(HeaderSyncError::Unsolicited)?.heights;
+        if blocks.is_empty() {
+            return Err(HeaderSyncError::NoBodies);
+        }
+        let mut received = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            let hash = block.hash();
+            let height = *heights
+                .iter()
+                .find(|height| self.hash_at(**height) == Some(hash))
+                .ok_or(HeaderSyncError::UnrequestedBody)?;
+            // The hash commits to the header, so only the transactions can be wrong
+            if block.validate().is_err() {
+                return Err(HeaderSyncError::InvalidBody(height));
+            }
+            received.push((height, block));
+        }
+        Ok(received)
+    }
+
+    fn ready(&mut self) -> Vec<(u64, Block)> {
+        let mut ready = Vec::new();
+        while let Some(block) = self.bodies.remove(&(self.base.height + 1)) {
+            let (hash, _) = self.headers.pop_front().expect("bodies are only kept for known headers");
+            self.base = ChainHead { height: self.base.height + 1, hash };
+            ready.push((self.base.height, block));
+        }
+        ready
+    }
+
+    // Forgets requests outstanding longer than the timeout so they are sent
+    // to another peer, and returns the peers that let them time out
+    pub fn expire(&mut self) -> Vec<PeerId> {
+        self.expire_at(Instant::now())
+    }
+
+    fn expire_at(&mut self, now: Instant) -> Vec<PeerId> {
+        let timeout = Duration::from_millis(self.config.request_timeout_ms);
+        let mut expired = Vec::new();
+        if let Some(in_flight) = self.headers_in_flight.take() {
+            if now.duration_since(in_flight.sent) >= timeout {
+                if in_flight.probe {
+                    self.ancestor_search = None;
+                }
+                expired.push(in_flight.peer);
+            } else {
+                self.headers_in_flight = Some(in_flight);
+            }
+        }
+        self.bodies_in_flight.retain(|peer, in_flight| {
+            let timed_out = now.duration_since(in_flight.sent) >= timeout;
+            if timed_out && !expired.contains(peer) {
+                expired.push(*peer);
+            }
+            !timed_out
+        });
+        for peer in &expired {
+            self.record_failure(*peer, false, now);
+        }
+        expired
+    }
+
+    // A request to the peer failed outright, e.g. the stream was reset
+    pub fn failed(&mut self, peer: &PeerId) {
+        self.cancel(peer);
+        self.record_failure(*peer, false, Instant::now());
+    }
+
+    pub fn is_blacklisted(&self, peer: &PeerId) -> bool {
+        self.failures.get(peer).map_or(false, |failures| failures.invalid >= self.config.max_invalid_responses)
+    }
+
+    // Peers neither blacklisted nor waiting out a backoff
+    fn usable(&self, peers: &[(PeerId, u64)], now: Instant) -> Vec<(PeerId, u64)> {
+        peers
+            .iter()
+            .filter(|(peer, _)| match self.failures.get(peer) {
+                Some(failures) => {
+                    failures.invalid < self.config.max_invalid_responses
+                        && failures.retry_at.map_or(true, |retry_at| now >= retry_at)
+                }
+                None => true,
+            })
+            .copied()
+            .collect()
+    }
+
+    fn record(&mut self, peer: PeerId, error: Option<&HeaderSyncError>, now: Instant) {
+        match error.map(HeaderSyncError::misbehavior) {
+            Some(Some(misbehavior)) => self.record_failure(peer, misbehavior != Misbehavior::SyncTimeout, now),
+            // Not the peer's fault, but there is no use asking again right away
+            Some(None) if matches!(error, Some(HeaderSyncError::FutureTimestamp(_))) => {
+                self.record_failure(peer, false, now)
+            }
+            Some(None) => {}
+            None => {
+                if let Some(failures) = self.failures.get_mut(&peer) {
+                    failures.in_a_row = 0;
+                    failures.retry_at = None;
+                }
+            }
+        }
+    }
+
+    fn record_failure(&mut self, peer: PeerId, invalid: bool, now: Instant) {
+        let failures = self.failures.entry(peer).or_default();
+        failures.in_a_row += 1;
+        let backoff = self.config.retry_backoff_ms.saturating_mul(1 << (failures.in_a_row - 1).min(16));
+        failures.retry_at = Some(now + Duration::from_millis(backoff.min(self.config.max_retry_backoff_ms)));
+        if invalid {
+            failures.invalid += 1;
+            if failures.invalid == self.config.max_invalid_responses {
+                warn!("Not syncing from {} any more after {} invalid responses", peer, failures.invalid);
+            }
+        }
+    }
+
+    // The peer disconnected
+    pub fn cancel(&mut self, peer: &PeerId) {
+        if self.headers_in_flight.as_ref().map_or(false, |in_flight| in_flight.peer == *peer) {
+            self.headers_in_flight = None;
+        }
+        if self.ancestor_search.as_ref().map_or(false, |search| search.peer == *peer) {
+            self.ancestor_search = None;
+        }
+        self.bodies_in_flight.remove(peer);
+    }
+}
+
+// Same as `Block::hash`, which covers the header only
+pub fn header_hash(header: &BlockHeader) -> BlockHash {
+    Block { header: header.clone(), transactions: Vec::new() }.hash()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A mined chain of `len` blocks on `parent`; `branch` tells competing chains apart
+    fn chain(parent: BlockHash, len: usize, branch: i64) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::with_capacity(len);
+        for _ in 0..len {
+            let parent = blocks.last().map_or(parent, |block| block.hash());
+            let mut block = Block::new(parent, vec![], 1).unwrap();
+            block.header.timestamp += branch;
+            block.mine();
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    fn headers(blocks: &[Block]) -> Vec<BlockHeader> {
+        blocks.iter().map(|block| block.header.clone()).collect()
+    }
+
+    #[test]
+    fn test_parses_checkpoint() {
+        let hash = "00ff".repeat(16);
+        let checkpoint = CheckpointConfig { height: 1000, hash: format!("0x{}", hash) }.parse().unwrap();
+        assert_eq!(checkpoint.height, 1000);
+        assert_eq!(&checkpoint.hash[..2], &[0x00, 0xff]);
+
+        assert!(CheckpointConfig { height: 1000, hash: hash[2..].to_string() }.parse().is_err());
+        assert!(CheckpointConfig { height: 1000, hash: "zz".repeat(32) }.parse().is_err());
+        assert!(CheckpointConfig { height: 0, hash }.parse().is_err());
+    }
+
+    fn no_history(_: u64) -> Option<BlockHash> {
+        None
+    }
+
+    fn config() -> HeaderSyncConfig {
+        HeaderSyncConfig { headers_per_request: 4, bodies_per_request: 2, ..HeaderSyncConfig::default() }
+    }
+
+    #[test]
+    fn test_downloads_headers_then_backfills_bodies_in_order() {
+        let genesis = ChainHead { height: 0, hash: [0; 32] };
+        let blocks = chain(genesis.hash, 6, 0);
+        let (honest, bogus, slow) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let mut sync = HeaderSync::new(config(), genesis);
+        let now = Instant::now();
+
+        // A peer whose batch doesn't hold together is caught before any body is fetched
+        let (peer, request) = sync.next_headers_request_at(&[(bogus, 6)], now).unwrap();
+        assert_eq!((peer, request), (bogus, HeadersRequest { start: 1, count: 4 }));
+        let mut broken = headers(&blocks[..4]);
+        broken.swap(1, 2);
+        assert_eq!(sync.on_headers(bogus, broken, no_history), Err(HeaderSyncError::Unlinked(2)));
+        assert!(sync.next_body_requests_at(&[(bogus, 6)], now).is_empty());
+
+        let peers = [(honest, 6), (slow, 6)];
+        sync.next_headers_request_at(&peers, now).unwrap();
+        assert_eq!(sync.on_headers(honest, headers(&blocks[..4]), no_history), Ok(4));
+        assert_eq!(sync.next_headers_request_at(&peers, now).unwrap().1, HeadersRequest { start: 5, count: 4 });
+        assert_eq!(sync.on_headers(honest, headers(&blocks[4..]), no_history), Ok(2));
+        assert_eq!(sync.tip(), ChainHead { height: 6, hash: blocks[5].hash() });
+        // Everyone's head is reached
+        assert!(sync.next_headers_request_at(&peers, now).is_none());
+
+        let requests = sync.next_body_requests_at(&peers, now);
+        assert_eq!(
+            requests,
+            vec![(honest, vec![blocks[0].hash(), blocks[1].hash()]), (slow, vec![blocks[2].hash(), blocks[3].hash()])]
+        );
+        // Nothing is ready until the lowest missing body arrives
+        assert_eq!(sync.on_bodies(honest, vec![blocks[1].clone()]), Ok(vec![]));
+
+        // The slow peer's heights go back into the pool, lowest first
+        assert_eq!(sync.expire_at(now + Duration::from_secs(10)), vec![slow]);
+        let requests = sync.next_body_requests_at(&peers, now);
+        assert_eq!(requests[0], (honest, vec![blocks[0].hash(), blocks[2].hash()]));
+        let ready = sync.on_bodies(honest, vec![blocks[2].clone(), blocks[0].clone()]).unwrap();
+        assert_eq!(ready.iter().map(|(height, _)| *height).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(sync.base(), ChainHead { height: 3, hash: blocks[2].hash() });
+    }
+
+    #[test]
+    fn test_rejects_mismatched_bodies_and_switches_to_longer_branch() {
+        let genesis = ChainHead { height: 0, hash: [0; 32] };
+        let common = chain(genesis.hash, 2, 0);
+        let ours = chain(common[1].hash(), 2, 1);
+        let theirs = chain(common[1].hash(), 4, 2);
+        let (first, second) = (PeerId::random(), PeerId::random());
+        let mut sync = HeaderSync::new(config(), genesis);
+        let now = Instant::now();
+
+        sync.next_headers_request_at(&[(first, 4)], now).unwrap();
+        let ours_headers: Vec<BlockHeader> = headers(&common).into_iter().chain(headers(&ours)).collect();
+        assert_eq!(sync.on_headers(first, ours_headers, no_history), Ok(4));
+        let requests = sync.next_body_requests_at(&[(first, 4)], now);
+        assert_eq!(requests.len(), 1);
+        // A body that isn't the block behind the header
+        let mut forged = common[0].clone();
+        forged.header.nonce += 1;
+        assert_eq!(sync.on_bodies(first, vec![forged]), Err(HeaderSyncError::UnrequestedBody));
+
+        // Headers from a longer branch don't link at our tip, so the fork point is searched for
+        let peers = [(second, 6)];
+        assert_eq!(sync.next_headers_request_at(&peers, now).unwrap().1.start, 5);
+        assert_eq!(sync.on_headers(second, headers(&theirs[2..]), no_history), Ok(0));
+        assert_eq!(sync.next_headers_request_at(&peers, now).unwrap().1.start, 1);
+        let theirs_headers: Vec<BlockHeader> = headers(&common).into_iter().chain(headers(&theirs[..2])).collect();
+        assert_eq!(sync.on_headers(second, theirs_headers, no_history), Ok(2));
+        assert_eq!(sync.tip(), ChainHead { height: 4, hash: theirs[1].hash() });
+
+        // Bodies are only fetched for the branch we are on now, once the
+        // peer that forged one has waited out its backoff
+        let requests = sync.next_body_requests_at(&[(first, 4), (second, 6)], now + Duration::from_secs(2));
+        assert_eq!(
+            requests,
+            vec![(first, vec![common[0].hash(), common[1].hash()]), (second, vec![theirs[0].hash(), theirs[1].hash()])]
+        );
+    }
+
+    #[test]
+    fn test_finds_fork_point_below_imported_head_and_reorgs_to_longer_branch() {
+        let common = chain([0; 32], 3, 0);
+        let ours = chain(common[2].hash(), 2, 1);
+        let theirs = chain(common[2].hash(), 4, 2);
+        let imported: Vec<BlockHash> = common.iter().chain(&ours).map(|block| block.hash()).collect();
+        let local = |height: u64| height.checked_sub(1).and_then(|index| imported.get(index as usize)).copied();
+        let (peer, stranger) = (PeerId::random(), PeerId::random());
+        let mut sync = HeaderSync::new(
+            HeaderSyncConfig { max_reorg_depth: 4, ..config() },
+            ChainHead { height: 5, hash: imported[4] },
+        );
+        let now = Instant::now();
+
+        // A chain sharing nothing with ours down to the reorg limit isn't followed
+        sync.next_headers_request_at(&[(stranger, 7)], now).unwrap();
+        assert_eq!(sync.on_headers(stranger, headers(&theirs[2..]), local), Ok(0));
+        assert_eq!(
+            sync.next_headers_request_at(&[(stranger, 7)], now).unwrap().1,
+            HeadersRequest { start: 1, count: 1 }
+        );
+        let unrelated = chain([1; 32], 1, 3);
+        assert_eq!(sync.on_headers(stranger, headers(&unrelated), local), Err(HeaderSyncError::ForkBelowBase));
+
+        let peers = [(peer, 7)];
+        sync.next_headers_request_at(&peers, now).unwrap();
+        assert_eq!(sync.on_headers(peer, headers(&theirs[2..]), local), Ok(0));
+        for (start, block) in [(1, &common[0]), (3, &common[2]), (4, &theirs[0])] {
+            assert_eq!(sync.next_headers_request_at(&peers, now).unwrap().1, HeadersRequest { start, count: 1 });
+            assert_eq!(sync.on_headers(peer, vec![block.header.clone()], local), Ok(0));
+        }
+        assert_eq!(sync.base(), ChainHead { height: 3, hash: imported[2] });
+
+        // No bodies until the branch is longer than the one it replaces
+        assert_eq!(sync.next_headers_request_at(&peers, now).unwrap().1.start, 4);
+        assert_eq!(sync.on_headers(peer, headers(&theirs[..2]), local), Ok(2));
+        assert!(sync.next_body_requests_at(&peers, now).is_empty());
+        sync.next_headers_request_at(&peers, now).unwrap();
+        assert_eq!(sync.on_headers(peer, headers(&theirs[2..]), local), Ok(2));
+        assert_eq!(sync.next_body_requests_at(&peers, now), vec![(peer, vec![theirs[0].hash(), theirs[1].hash()])]);
+
+        assert!(sync.take_reorg().is_none());
+        let ready = sync.on_bodies(peer, theirs[..2].to_vec()).unwrap();
+        assert_eq!(ready.iter().map(|(height, _)| *height).collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!(sync.take_reorg(), Some(ChainHead { height: 3, hash: imported[2] }));
+        assert!(sync.take_reorg().is_none());
+    }
+
+    #[test]
+    fn test_backs_off_failing_peers_and_blacklists_invalid_ones() {
+        let blocks = chain([0; 32], 2, 0);
+        let (flaky, liar, backup) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let mut sync = HeaderSync::new(config(), ChainHead { height: 0, hash: [0; 32] });
+        let now = Instant::now();
+
+        assert_eq!(sync.next_headers_request_at(&[(flaky, 2), (backup, 2)], now).unwrap().0, flaky);
+        let later = now + Duration::from_secs(10);
+        assert_eq!(sync.expire_at(later), vec![flaky]);
+        assert_eq!(sync.next_headers_request_at(&[(flaky, 2), (backup, 2)], later).unwrap().0, backup);
+        sync.cancel(&backup);
+
+        // The wait doubles with every failure in a row
+        assert_eq!(sync.next_headers_request_at(&[(flaky, 2)], later + Duration::from_secs(1)).unwrap().0, flaky);
+        let later = later + Duration::from_secs(11);
+        assert_eq!(sync.expire_at(later), vec![flaky]);
+        assert!(sync.next_headers_request_at(&[(flaky, 2)], later + Duration::from_secs(1)).is_none());
+        assert!(sync.next_headers_request_at(&[(flaky, 2)], later + Duration::from_secs(2)).is_some());
+        sync.cancel(&flaky);
+
+        let mut broken = headers(&blocks);
+        broken.swap(0, 1);
+        for attempt in 0..3 {
+            let at = later + Duration::from_secs(3600 * attempt);
+            assert_eq!(sync.next_headers_request_at(&[(liar, 2)], at).unwrap().0, liar);
+            assert_eq!(sync.on_headers(liar, broken.clone(), no_history), Err(HeaderSyncError::Unlinked(2)));
+        }
+        assert!(sync.is_blacklisted(&liar));
+        assert!(sync.next_headers_request_at(&[(liar, 2)], later + Duration::from_secs(86400)).is_none());
+
+        // Headers from the future are refused without counting against the peer
+        let ahead = PeerId::random();
+        let future = headers(&chain([0; 32], 2, 3600));
+        for attempt in 0..3 {
+            let at = later + Duration::from_secs(3600 * attempt);
+            assert_eq!(sync.next_headers_request_at(&[(ahead, 2)], at).unwrap().0, ahead);
+            assert_eq!(sync.on_headers(ahead, future.clone(), no_history), Err(HeaderSyncError::FutureTimestamp(1)));
+        }
+        assert!(!sync.is_blacklisted(&ahead));
+    }
+
+    #[test]
+    fn test_syncs_history_up_to_checkpoint_with_receipts() {
+        let genesis = ChainHead { height: 0, hash: [0; 32] };
+        let blocks = chain(genesis.hash, 3, 0);
+        let other = chain(genesis.hash, 3, 1);
+        let checkpoint = ChainHead { height: 2, hash: blocks[1].hash() };
+        let (liar, honest) = (PeerId::random(), PeerId::random());
+        let mut sync = HeaderSync::new(config(), genesis).with_history(checkpoint);
+        let now = Instant::now();
+
+        sync.next_headers_request_at(&[(liar, 3)], now).unwrap();
+        assert_eq!(sync.on_headers(liar, headers(&other), no_history), Err(HeaderSyncError::NotCheckpoint(2)));
+
+        let peers = [(honest, 3)];
+        sync.next_headers_request_at(&peers, now).unwrap();
+        assert_eq!(sync.on_headers(honest, headers(&blocks), no_history), Ok(3));
+        // Nothing past the checkpoint until the state there exists
+        assert!(sync.in_history());
+        assert_eq!(sync.next_body_requests_at(&peers, now), vec![(honest, vec![blocks[0].hash(), blocks[1].hash()])]);
+
+        let history = blocks[..2].iter().map(|block| (block.clone(), Vec::new())).collect();
+        let ready = sync.on_history(honest, history).unwrap();
+        assert_eq!(ready.iter().map(|(height, _, _)| *height).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(!sync.in_history());
+        assert_eq!(sync.next_body_requests_at(&peers, now), vec![(honest, vec![blocks[2].hash()])]);
+    }
+}
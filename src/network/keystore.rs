@@ -0,0 +1,100 @@
+use libp2p::identity::{ed25519, Keypair};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum IdentityError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Identity key {0} is readable by other users (mode {1:o}); chmod 600 it")]
+    InsecurePermissions(PathBuf, u32),
+    #[error("Identity key {0} is not a 32-byte ed25519 secret key")]
+    Malformed(PathBuf),
+}
+
+// Loads the node's ed25519 identity from `path`, creating it on first start,
+// so the PeerId survives restarts. The file holds the raw 32-byte secret and
+// must only be accessible by its owner.
+pub fn load_or_generate(path: &Path) -> Result<Keypair, IdentityError> {
+    if path.exists() {
+        check_permissions(path)?;
+        let mut secret = fs::read(path)?;
+        let secret = ed25519::SecretKey::from_bytes(&mut secret).map_err(|_| IdentityError::Malformed(path.to_path_buf()))?;
+        return Ok(Keypair::Ed25519(secret.into()));
+    }
+
+    let keypair = ed25519::Keypair::generate();
+    write(path, &keypair)?;
+    Ok(Keypair::Ed25519(keypair))
+}
+
+// Writes an identity derived elsewhere, e.g. from the node's mnemonic.
+// Like a generated one it is never overwritten.
+pub fn create_from_secret(path: &Path, secret: &[u8]) -> Result<Keypair, IdentityError> {
+    let mut secret = secret.to_vec();
+    let secret = ed25519::SecretKey::from_bytes(&mut secret).map_err(|_| IdentityError::Malformed(path.to_path_buf()))?;
+    let keypair = ed25519::Keypair::from(secret);
+    write(path, &keypair)?;
+    Ok(Keypair::Ed25519(keypair))
+}
+
+fn write(path: &Path, keypair: &ed25519::Keypair) -> Result<(), IdentityError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(keypair.secret().as_ref())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), IdentityError> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)?.permissions().mode() & 0o777;
+    if mode & 0o077 != 0 {
+        return Err(IdentityError::InsecurePermissions(path.to_path_buf(), mode));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<(), IdentityError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::PeerId;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_identity_is_stable_across_loads() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("keys").join("node.key");
+        let first = PeerId::from(load_or_generate(&path).unwrap().public());
+        let second = PeerId::from(load_or_generate(&path).unwrap().public());
+        assert_eq!(first, second);
+
+        fs::write(&path, b"short").unwrap();
+        assert!(matches!(load_or_generate(&path), Err(IdentityError::Malformed(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rejects_world_readable_key() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("node.key");
+        load_or_generate(&path).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(matches!(load_or_generate(&path), Err(IdentityError::InsecurePermissions(_, 0o644))));
+    }
+}
@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::chain::block::BlockHeader;
+use crate::crypto::{public_key::PublicKey, signature::Signature};
+
+// Signatures on one header; more than this is malformed
+const MAX_SIGNATURES: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Validator {
+    pub public_key: PublicKey,
+    pub power: u64,
+}
+
+// Changes to the validator set taking effect after the header it is part of.
+// A validator in `updated` that is already in the set gets its new power.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValidatorSetDiff {
+    pub updated: Vec<Validator>,
+    pub removed: Vec<PublicKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidatorSignature {
+    pub validator: PublicKey,
+    pub signature: Signature,
+}
+
+// What the light client topic carries instead of blocks: the header,
+// signed by the validators, and how the set signing the next one differs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedHeader {
+    pub height: u64,
+    pub header: BlockHeader,
+    pub diff: ValidatorSetDiff,
+    pub signatures: Vec<ValidatorSignature>,
+}
+
+impl SignedHeader {
+    pub fn signing_bytes(height: u64, header: &BlockHeader, diff: &ValidatorSetDiff) -> Vec<u8> {
+        bincode::serialize(&(height, header, diff)).expect("tuple serialization cannot fail")
+    }
+
+    // Checks what can be checked without knowing the validator set: every
+    // signature is valid for the key it names, and no key signs twice
+    pub fn check_signatures(&self) -> Result<(), LightClientError> {
+        if self.signatures.is_empty() || self.signatures.len() > MAX_SIGNATURES {
+            return Err(LightClientError::SignatureCount(self.signatures.len()));
+        }
+        let message = Self::signing_bytes(self.height, &self.header, &self.diff);
+        for (index, signed) in self.signatures.iter().enumerate() {
+            if self.signatures[..index].iter().any(|earlier| earlier.validator == signed.validator) {
+                return Err(LightClientError::DuplicateSigner);
+            }
+            if !signed.signature.verify(&message, &signed.validator) {
+                return Err(LightClientError::InvalidSignature);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LightClientError {
+    #[error("Header carries {0} signatures")]
+    SignatureCount(usize),
+    #[error("Validator signed the header twice")]
+    DuplicateSigner,
+    #[error("Invalid validator signature")]
+    InvalidSignature,
+    #[error("Header at height {got} does not follow {expected}")]
+    UnexpectedHeight { expected: u64, got: u64 },
+    #[error("Signers hold {signed} of {total} voting power, need more than two thirds")]
+    InsufficientPower { signed: u64, total: u64 },
+}
+
+// The validator set a light client trusts, advanced header by header
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidatorSet {
+    pub height: u64,
+    pub validators: Vec<Validator>,
+}
+
+impl ValidatorSet {
+    pub fn new(height: u64, validators: Vec<Validator>) -> Self {
+        Self { height, validators }
+    }
+
+    pub fn total_power(&self) -> u64 {
+        self.validators.iter().map(|validator| validator.power).sum()
+    }
+
+    fn power_of(&self, public_key: &PublicKey) -> u64 {
+        self.validators
+            .iter()
+            .find(|validator| &validator.public_key == public_key)
+            .map_or(0, |validator| validator.power)
+    }
+
+    // Verifies the header following this set's height and returns the set
+    // that signs the one after it
+    pub fn verify(&self, signed: &SignedHeader) -> Result<ValidatorSet, LightClientError> {
+        if signed.height != self.height + 1 {
+            return Err(LightClientError::UnexpectedHeight { expected: self.height + 1, got: signed.height });
+        }
+        signed.check_signatures()?;
+        let power: u64 = signed.signatures.iter().map(|signature| self.power_of(&signature.validator)).sum();
+        let total = self.total_power();
+        if power.saturating_mul(3) <= total.saturating_mul(2) {
+            return Err(LightClientError::InsufficientPower { signed: power, total });
+        }
+        Ok(self.apply(signed.height, &signed.diff))
+    }
+
+    fn apply(&self, height: u64, diff: &ValidatorSetDiff) -> ValidatorSet {
+        let mut validators: Vec<Validator> = self
+            .validators
+            .iter()
+            .filter(|validator| !diff.removed.contains(&validator.public_key))
+            .filter(|validator| !diff.updated.iter().any(|updated| updated.public_key == validator.public_key))
+            .cloned()
+            .collect();
+        validators.extend(diff.updated.iter().filter(|validator| validator.power > 0).cloned());
+        ValidatorSet { height, validators }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::block::Block;
+    use crate::crypto::key_pair::KeyPair;
+
+    fn sign(height: u64, header: &BlockHeader, diff: &ValidatorSetDiff, signers: &[&KeyPair]) -> SignedHeader {
+        let message = SignedHeader::signing_bytes(height, header, diff);
+        let signatures = signers
+            .iter()
+            .map(|key_pair| ValidatorSignature {
+                validator: key_pair.public_key().clone(),
+                signature: Signature::sign(&message, key_pair.private_key()).unwrap(),
+            })
+            .collect();
+        SignedHeader { height, header: header.clone(), diff: diff.clone(), signatures }
+    }
+
+    #[test]
+    fn test_follows_headers_and_validator_changes() {
+        let keys: Vec<KeyPair> = (0..4).map(|_| KeyPair::generate()).collect();
+        let validators = keys[..3].iter().map(|key| Validator { public_key: key.public_key().clone(), power: 10 }).collect();
+        let set = ValidatorSet::new(0, validators);
+        let header = Block::new([0; 32], vec![], 1).unwrap().header;
+
+        // Two of three equal validators is not more than two thirds
+        let weak = sign(1, &header, &ValidatorSetDiff::default(), &[&keys[0], &keys[1]]);
+        assert_eq!(set.verify(&weak), Err(LightClientError::InsufficientPower { signed: 20, total: 30 }));
+
+        let diff = ValidatorSetDiff {
+            updated: vec![Validator { public_key: keys[3].public_key().clone(), power: 40 }],
+            removed: vec![keys[0].public_key().clone()],
+        };
+        let next = set.verify(&sign(1, &header, &diff, &[&keys[0], &keys[1], &keys[2]])).unwrap();
+        assert_eq!((next.height, next.total_power()), (1, 60));
+
+        // The newcomer alone now holds two thirds of the power, but not more
+        let alone = sign(2, &header, &ValidatorSetDiff::default(), &[&keys[3]]);
+        assert!(matches!(next.verify(&alone), Err(LightClientError::InsufficientPower { .. })));
+        assert!(next.verify(&sign(2, &header, &ValidatorSetDiff::default(), &[&keys[3], &keys[1]])).is_ok());
+        let skipped = sign(3, &header, &diff, &[&keys[3]]);
+        assert!(matches!(next.verify(&skipped), Err(LightClientError::UnexpectedHeight { .. })));
+    }
+
+    #[test]
+    fn test_rejects_forged_and_duplicate_signatures() {
+        let (signer, other) = (KeyPair::generate(), KeyPair::generate());
+        let header = Block::new([0; 32], vec![], 1).unwrap().header;
+
+        let mut forged = sign(1, &header, &ValidatorSetDiff::default(), &[&signer]);
+        forged.signatures[0].validator = other.public_key().clone();
+        assert_eq!(forged.check_signatures(), Err(LightClientError::InvalidSignature));
+
+        let duplicate = sign(1, &header, &ValidatorSetDiff::default(), &[&signer, &signer]);
+        assert_eq!(duplicate.check_signatures(), Err(LightClientError::DuplicateSigner));
+    }
+}
@@ -1,42 +1,715 @@
+use futures::StreamExt;
 use libp2p::{
-    core::upgrade,
-    floodsub::{Floodsub, FloodsubEvent, Topic},
+    autonat::{Behaviour as Autonat, Config as AutonatConfig, Event as AutonatEvent, NatStatus},
+    core::{either::EitherOutput, muxing::StreamMuxerBox, transport::Boxed, upgrade},
+    dcutr::behaviour::{Behaviour as Dcutr, Event as DcutrEvent},
+    dns::TokioDnsConfig,
+    gossipsub::{
+        Gossipsub, GossipsubConfigBuilder, GossipsubEvent, GossipsubMessage, IdentTopic as Topic, MessageAcceptance,
+        MessageAuthenticity, MessageId, PeerScoreParams, PeerScoreThresholds, TopicHash, TopicScoreParams,
+        ValidationMode,
+    },
+    identify::{Behaviour as Identify, Config as IdentifyConfig, Event as IdentifyEvent},
+    identity,
     mdns::{Mdns, MdnsEvent},
     mplex,
     noise::{Keypair, NoiseConfig, X25519Spec},
-    swarm::{NetworkBehaviourEventProcess, Swarm, SwarmBuilder},
+    pnet::{PnetConfig, PreSharedKey},
+    quic,
+    request_response::{
+        ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig, RequestResponseEvent, RequestResponseMessage,
+        ResponseChannel,
+    },
+    relay::v2::client::{Client as RelayClient, Event as RelayClientEvent},
+    swarm::{behaviour::toggle::Toggle, NetworkBehaviourEventProcess, Swarm, SwarmBuilder, SwarmEvent},
     tcp::TokioTcpConfig,
-    NetworkBehaviour, PeerId, Transport,
+    websocket::{tls, WsConfig},
+    Multiaddr, NetworkBehaviour, PeerId, Transport,
 };
-use log::{error, info};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::error::Error;
+use std::iter;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+use crate::ai::streaming::SignedOutputChunk;
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::store::ChainHead;
+use crate::chain::transaction::{Transaction, TransactionHash, TransactionReceipt};
+use crate::network::block_gossip::{
+    block_fetch_codec, BlockAnnouncement, BlockFetchCodec, BlockFetcher, BlockGossipConfig, BlockHash, Fetched,
+    NextAttempt, BLOCK_FETCH_PROTOCOL,
+};
+use crate::network::clock::{self, ClockConfig, ClockDrift};
+use crate::network::codec::decode_bounded;
+use crate::network::compression::{CompressionConfig, MessageCompressor, PROTOCOL_VERSION};
+use crate::network::connection_limits::{Admission, ConnectionLimitsConfig, ConnectionManager};
+use crate::network::dns_seeds;
+use crate::network::goodbye::{goodbye_codec, GoodbyeCodec, GoodbyeReason, GOODBYE_PROTOCOL};
+use crate::network::handshake::{handshake_codec, HandshakeCodec, Status, HANDSHAKE_PROTOCOL, LEGACY_HANDSHAKE_PROTOCOL};
+use crate::network::header_sync::{
+    bodies_codec, header_hash, headers_codec, history_codec, serve_bodies, serve_headers, serve_history, BodiesCodec,
+    CheckpointConfig, HeaderSync, HeaderSyncConfig, HeaderSyncError, HeadersCodec, HeadersRequest, HistoryCodec,
+    BODIES_PROTOCOL, HEADERS_PROTOCOL, HISTORY_PROTOCOL,
+};
+use crate::network::keystore;
+use crate::network::light_client::SignedHeader;
+use crate::network::peer::{Misbehavior, PeerManager, PeerScoringConfig};
+use crate::network::peer_store::PeerStore;
+use crate::network::psk::PrivateNetworkConfig;
+use crate::network::rate_limit::{RateLimiter, RequestLimitsConfig};
+use crate::network::state_sync::{
+    state_codec, StateCodec, StateSync, StateSyncConfig, MAX_STATE_REQUEST_HASHES, STATE_PROTOCOL,
+};
+use crate::network::trusted::TrustedPeers;
+use crate::network::tx_gossip::{tx_fetch_codec, TxAnnouncement, TxFetchCodec, TxFetcher, TxGossipConfig, TX_FETCH_PROTOCOL};
+use crate::storage::db::Database;
+use crate::storage::snapshot::{export_chunk, SnapshotError};
+use crate::storage::trie::{StateTrie, TrieHash};
+
+pub const BLOCKS_TOPIC: &str = "omnitensor-blocks";
+pub const TRANSACTIONS_TOPIC: &str = "omnitensor-transactions";
+pub const VOTES_TOPIC: &str = "omnitensor-votes";
+pub const INFERENCE_JOBS_TOPIC: &str = "omnitensor-inference-jobs";
+pub const HEARTBEATS_TOPIC: &str = "omnitensor-heartbeats";
+// Signed partial outputs of running jobs, see `ai::streaming`
+pub const INFERENCE_OUTPUT_TOPIC: &str = "omnitensor-inference-output";
+// Signed headers and validator set changes, all a light client needs to follow the chain
+pub const LIGHT_CLIENT_TOPIC: &str = "omnitensor-light-headers";
+const TOPICS: [&str; 7] = [
+    BLOCKS_TOPIC,
+    TRANSACTIONS_TOPIC,
+    VOTES_TOPIC,
+    INFERENCE_JOBS_TOPIC,
+    INFERENCE_OUTPUT_TOPIC,
+    HEARTBEATS_TOPIC,
+    LIGHT_CLIENT_TOPIC,
+];
+
+// Stored peers redialed on startup, on top of the bootstrap list
+const STARTUP_DIALS: usize = 50;
+// Stored peers not seen for this long are forgotten
+const PEER_RETENTION: Duration = Duration::from_secs(14 * 24 * 3600);
+// How often timed out block and transaction fetches and goodbyes are dealt with
+const HOUSEKEEPING_TICK: Duration = Duration::from_secs(1);
+// Peers that don't acknowledge our goodbye are disconnected anyway after this
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(2);
+// Request/response protocol labels in per-peer traffic metrics
+const HANDSHAKE_LABEL: &str = "handshake";
+const TX_FETCH_LABEL: &str = "tx-fetch";
+const BLOCK_FETCH_LABEL: &str = "block-fetch";
+const GOODBYE_LABEL: &str = "goodbye";
+const HEADERS_LABEL: &str = "headers";
+const BODIES_LABEL: &str = "bodies";
+const STATE_LABEL: &str = "state";
+const HISTORY_LABEL: &str = "history";
+// Room for the gossipsub envelope (signature, key, sequence number) around a payload
+const GOSSIP_ENVELOPE_OVERHEAD: usize = 1024;
+
+// `[network.transports]`. Peers advertise an address per transport and the
+// dialer picks whichever one it supports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransportConfig {
+    pub tcp_listen: Vec<String>,
+    pub quic_enabled: bool,
+    pub quic_listen: Vec<String>,
+    // Lets browser clients connect directly
+    pub websocket_enabled: bool,
+    pub websocket_listen: Vec<String>,
+    // DER-encoded certificate and private key for listen addresses ending in
+    // /wss; browsers on https pages only connect to those
+    pub websocket_tls_cert: Option<PathBuf>,
+    pub websocket_tls_key: Option<PathBuf>,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            tcp_listen: vec!["/ip4/0.0.0.0/tcp/3030".to_string()],
+            quic_enabled: false,
+            quic_listen: vec!["/ip4/0.0.0.0/udp/3030/quic".to_string()],
+            websocket_enabled: false,
+            websocket_listen: vec!["/ip4/0.0.0.0/tcp/3031/ws".to_string()],
+            websocket_tls_cert: None,
+            websocket_tls_key: None,
+        }
+    }
+}
+
+impl TransportConfig {
+    fn listen_addresses(&self) -> impl Iterator<Item = &String> {
+        let quic = if self.quic_enabled { self.quic_listen.as_slice() } else { &[] };
+        let websocket = if self.websocket_enabled { self.websocket_listen.as_slice() } else { &[] };
+        self.tcp_listen.iter().chain(quic).chain(websocket)
+    }
+}
+
+// `[network.nat]`. AutoNAT always runs; a node it finds to be private
+// reserves a slot on the relays and upgrades relayed connections to direct
+// ones by hole punching where possible.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NatConfig {
+    // Relay multiaddrs including the relay's /p2p/<peer id>
+    pub relays: Vec<String>,
+    pub hole_punching: bool,
+}
+
+impl Default for NatConfig {
+    fn default() -> Self {
+        Self { relays: Vec::new(), hole_punching: true }
+    }
+}
+
+// `[network]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    // Peers on a different chain are disconnected during the handshake
+    pub chain_id: u64,
+    // Persisted ed25519 identity; without it the PeerId changes on every start
+    pub identity_key: Option<PathBuf>,
+    // Discover peers on the local network
+    pub mdns: bool,
+    // Multiaddrs dialed at startup, e.g. "/dns4/node1.omnitensor.io/tcp/3030"
+    pub bootstrap_peers: Vec<String>,
+    // Hostnames, optionally with a port, whose A/AAAA records are dialed like
+    // bootstrap peers; re-resolved every `dns_seed_interval_secs`
+    pub dns_seeds: Vec<String>,
+    pub dns_seed_interval_secs: u64,
+    // Multiaddrs ending in /p2p/<peer id>: kept connected, never banned or
+    // evicted, and preferred for sync
+    pub trusted_peers: Vec<String>,
+    pub transports: TransportConfig,
+    pub private: PrivateNetworkConfig,
+    pub connections: ConnectionLimitsConfig,
+    pub nat: NatConfig,
+    pub gossip: GossipConfig,
+    pub topics: TopicsConfig,
+    pub request_limits: RequestLimitsConfig,
+    pub block_gossip: BlockGossipConfig,
+    pub header_sync: HeaderSyncConfig,
+    pub state_sync: StateSyncConfig,
+    pub tx_gossip: TxGossipConfig,
+    pub compression: CompressionConfig,
+    pub peer_scoring: PeerScoringConfig,
+    pub clock: ClockConfig,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            chain_id: 1,
+            identity_key: None,
+            mdns: true,
+            bootstrap_peers: Vec::new(),
+            dns_seeds: Vec::new(),
+            dns_seed_interval_secs: 3600,
+            trusted_peers: Vec::new(),
+            transports: TransportConfig::default(),
+            private: PrivateNetworkConfig::default(),
+            connections: ConnectionLimitsConfig::default(),
+            nat: NatConfig::default(),
+            gossip: GossipConfig::default(),
+            topics: TopicsConfig::default(),
+            request_limits: RequestLimitsConfig::default(),
+            block_gossip: BlockGossipConfig::default(),
+            header_sync: HeaderSyncConfig::default(),
+            state_sync: StateSyncConfig::default(),
+            tx_gossip: TxGossipConfig::default(),
+            compression: CompressionConfig::default(),
+            peer_scoring: PeerScoringConfig::default(),
+            clock: ClockConfig::default(),
+        }
+    }
+}
+
+// `[network.gossip]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GossipConfig {
+    // Target, lower and upper bound of peers in each topic mesh
+    pub mesh_n: usize,
+    pub mesh_n_low: usize,
+    pub mesh_n_high: usize,
+    pub heartbeat_interval_ms: u64,
+    // Peers scoring below this are ignored for gossip, publishing and, at the
+    // graylist threshold, entirely
+    pub gossip_threshold: f64,
+    pub publish_threshold: f64,
+    pub graylist_threshold: f64,
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        Self {
+            mesh_n: 8,
+            mesh_n_low: 6,
+            mesh_n_high: 12,
+            heartbeat_interval_ms: 1000,
+            gossip_threshold: -10.0,
+            publish_threshold: -50.0,
+            graylist_threshold: -80.0,
+        }
+    }
+}
+
+// `[network.topics.<topic>]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicConfig {
+    // Unsubscribed topics can still be published to, we just don't receive them
+    pub subscribe: bool,
+    // Per peer; messages beyond the limit are dropped without being validated
+    // and cost the sender score
+    pub messages_per_sec: f64,
+    pub burst: f64,
+    // Larger messages, before or after decompression, are rejected and the sender penalized
+    pub max_message_bytes: usize,
+}
+
+impl TopicConfig {
+    fn limited(messages_per_sec: f64, burst: f64, max_message_bytes: usize) -> Self {
+        Self { subscribe: true, messages_per_sec, burst, max_message_bytes }
+    }
+}
+
+impl Default for TopicConfig {
+    fn default() -> Self {
+        Self::limited(50.0, 100.0, 64 * 1024)
+    }
+}
+
+// `[network.topics]`. Each message type has its own topic and limits so a
+// flood on one can't starve the others.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicsConfig {
+    pub blocks: TopicConfig,
+    pub transactions: TopicConfig,
+    pub votes: TopicConfig,
+    pub inference_jobs: TopicConfig,
+    pub inference_output: TopicConfig,
+    pub heartbeats: TopicConfig,
+    pub light_client: TopicConfig,
+}
+
+impl Default for TopicsConfig {
+    fn default() -> Self {
+        Self {
+            // Announcements only; bodies are fetched separately
+            blocks: TopicConfig::limited(10.0, 50.0, 1024),
+            transactions: TopicConfig::limited(200.0, 1000.0, 16 * 1024),
+            votes: TopicConfig::limited(100.0, 200.0, 4 * 1024),
+            inference_jobs: TopicConfig::limited(50.0, 100.0, 1024 * 1024),
+            inference_output: TopicConfig::limited(100.0, 500.0, 64 * 1024),
+            heartbeats: TopicConfig::limited(1.0, 5.0, 1024),
+            light_client: TopicConfig::limited(5.0, 20.0, 128 * 1024),
+        }
+    }
+}
+
+impl TopicsConfig {
+    // Gossipsub refuses anything larger outright, before our own checks
+    fn max_transmit_size(&self) -> usize {
+        let largest = TOPICS.iter().map(|name| self.get(name).max_message_bytes).max().unwrap_or(0);
+        largest + 1 + GOSSIP_ENVELOPE_OVERHEAD
+    }
+
+    fn get(&self, name: &str) -> &TopicConfig {
+        match name {
+            BLOCKS_TOPIC => &self.blocks,
+            TRANSACTIONS_TOPIC => &self.transactions,
+            VOTES_TOPIC => &self.votes,
+            INFERENCE_JOBS_TOPIC => &self.inference_jobs,
+            INFERENCE_OUTPUT_TOPIC => &self.inference_output,
+            LIGHT_CLIENT_TOPIC => &self.light_client,
+            _ => &self.heartbeats,
+        }
+    }
+}
+
+// Decides whether a received message is forwarded (`Accept`), dropped (`Ignore`)
+// or dropped with a score penalty for the sender (`Reject`)
+pub type MessageValidator = Box<dyn Fn(&[u8]) -> MessageAcceptance + Send + Sync>;
+
+// Looks up a pending transaction body for peers fetching an announced hash
+pub type TxLookup = Box<dyn Fn(&TransactionHash) -> Option<Transaction> + Send + Sync>;
+
+// Looks up a block we have by hash, for peers fetching an announced block
+pub type BlockLookup = Box<dyn Fn(&BlockHash) -> Option<Block> + Send + Sync>;
+
+// Looks up the header at a height on our best chain, for peers syncing headers
+pub type HeaderLookup = Box<dyn Fn(u64) -> Option<BlockHeader> + Send + Sync>;
+
+// Looks up the receipts of a block we have by hash, for peers syncing history
+pub type ReceiptsLookup = Box<dyn Fn(&BlockHash) -> Option<Vec<TransactionReceipt>> + Send + Sync>;
+
 // Custom behavior for the OmniTensor network
 #[derive(NetworkBehaviour)]
 #[behaviour(event_process = true)]
 struct OmniTensorBehaviour {
-    floodsub: Floodsub,
-    mdns: Mdns,
+    gossipsub: Gossipsub,
+    mdns: Toggle<Mdns>,
+    identify: Identify,
+    autonat: Autonat,
+    relay_client: RelayClient,
+    dcutr: Toggle<Dcutr>,
+    handshake: RequestResponse<HandshakeCodec>,
+    tx_fetch: RequestResponse<TxFetchCodec>,
+    block_fetch: RequestResponse<BlockFetchCodec>,
+    headers: RequestResponse<HeadersCodec>,
+    bodies: RequestResponse<BodiesCodec>,
+    history: RequestResponse<HistoryCodec>,
+    state: RequestResponse<StateCodec>,
+    goodbye: RequestResponse<GoodbyeCodec>,
     #[behaviour(ignore)]
     response_sender: mpsc::UnboundedSender<OmniTensorEvent>,
+    #[behaviour(ignore)]
+    validators: HashMap<TopicHash, MessageValidator>,
+    #[behaviour(ignore)]
+    rate_limits: HashMap<TopicHash, RateLimiter<PeerId>>,
+    // Per peer, keyed by protocol label
+    #[behaviour(ignore)]
+    request_limits: HashMap<&'static str, RateLimiter<PeerId>>,
+    #[behaviour(ignore)]
+    size_limits: HashMap<TopicHash, usize>,
+    #[behaviour(ignore)]
+    compressor: Arc<MessageCompressor>,
+    #[behaviour(ignore)]
+    tx_fetcher: TxFetcher,
+    #[behaviour(ignore)]
+    tx_lookup: Option<TxLookup>,
+    #[behaviour(ignore)]
+    block_fetcher: BlockFetcher,
+    #[behaviour(ignore)]
+    block_lookup: Option<BlockLookup>,
+    // Block each outstanding fetch is for, as responses don't carry the hash
+    #[behaviour(ignore)]
+    block_requests: HashMap<RequestId, BlockHash>,
+    // Running once `P2PNetwork::start_header_sync` is called
+    #[behaviour(ignore)]
+    header_sync: Option<HeaderSync>,
+    #[behaviour(ignore)]
+    header_lookup: Option<HeaderLookup>,
+    #[behaviour(ignore)]
+    receipts_lookup: Option<ReceiptsLookup>,
+    // Header of the checkpoint once history sync has handed it on, for the
+    // swarm loop to fast sync the state there
+    #[behaviour(ignore)]
+    history_end: Option<BlockHeader>,
+    // State requests and responses, handled by the swarm loop as they need the database
+    #[behaviour(ignore)]
+    pending_state_requests: Vec<(PeerId, Vec<TrieHash>, ResponseChannel<Vec<Vec<u8>>>)>,
+    // `None` if the request failed
+    #[behaviour(ignore)]
+    pending_state_chunks: Vec<(PeerId, Option<Vec<Vec<u8>>>)>,
+    #[behaviour(ignore)]
+    peer_manager: Arc<PeerManager>,
+    // Send times of our outstanding requests, for per-peer latency
+    #[behaviour(ignore)]
+    requests_in_flight: HashMap<(&'static str, RequestId), Instant>,
+    #[behaviour(ignore)]
+    local_status: Status,
+    // Status of every peer that completed the handshake
+    #[behaviour(ignore)]
+    peer_status: HashMap<PeerId, Status>,
+    // Peers that failed the handshake, with the reason, for the swarm loop to drop
+    #[behaviour(ignore)]
+    pending_disconnects: Vec<(PeerId, GoodbyeReason, String)>,
+    // Peers we said goodbye to and when, closed once they acknowledge or time out
+    #[behaviour(ignore)]
+    farewells: HashMap<PeerId, Instant>,
+    #[behaviour(ignore)]
+    pending_closes: Vec<PeerId>,
+    // mDNS discoveries waiting to be dialed by the swarm loop
+    #[behaviour(ignore)]
+    pending_dials: Vec<(PeerId, Multiaddr)>,
+    // Misbehaviour seen while processing events, applied by the swarm loop
+    #[behaviour(ignore)]
+    pending_reports: Vec<(PeerId, Misbehavior)>,
+    // Set when AutoNAT finds us unreachable, so the swarm loop listens via relays
+    #[behaviour(ignore)]
+    needs_relay: bool,
+    // Fed the offsets of peers' clocks from their handshakes
+    #[behaviour(ignore)]
+    clock: Arc<ClockDrift>,
+    #[behaviour(ignore)]
+    clock_config: ClockConfig,
 }
 
 // Custom events for the OmniTensor network
-enum OmniTensorEvent {
+pub enum OmniTensorEvent {
     NewPeer(PeerId),
     ExpiredPeer(PeerId),
-    Message(PeerId, Vec<u8>),
+    Message(PeerId, TopicHash, Vec<u8>),
+    // Bodies fetched for announced transactions, each delivered once
+    Transactions(PeerId, Vec<Transaction>),
+    // A valid block fetched after its announcement by the peer, with its height.
+    // While header sync catches up it may be well above our head; such blocks
+    // haven't been through the blocks topic validator.
+    Block(PeerId, Block, u64),
+    // Bodies downloaded by header sync with their heights, in chain order, to import
+    SyncedBlocks(Vec<(u64, Block)>),
+    // History below the checkpoint with verified receipts, in chain order, to
+    // import without executing; the state follows as `StateSynced` at the
+    // checkpoint
+    SyncedHistory(Vec<(u64, Block, Vec<TransactionReceipt>)>),
+    // Header sync switched to a longer branch forking off ours after this
+    // block. Ours above it must be dropped, see `ChainStore::truncate`,
+    // before the synced blocks that follow are imported.
+    Reorg(ChainHead),
+    // Fast sync has downloaded the state at this height and header. The chain
+    // should continue from there, see `ChainStore::start_at`; synced blocks
+    // above it follow.
+    StateSynced(u64, BlockHeader),
 }
 
-impl NetworkBehaviourEventProcess<FloodsubEvent> for OmniTensorBehaviour {
-    fn inject_event(&mut self, event: FloodsubEvent) {
-        if let FloodsubEvent::Message(message) = event {
-            if let Err(e) = self
-                .response_sender
-                .send(OmniTensorEvent::Message(message.source, message.data))
-            {
-                error!("Error sending message via channel: {:?}", e);
+// Where fast sync is at. Bodies are only downloaded once it is `Done`, as
+// they can't be executed before the state below them exists.
+enum FastSync {
+    // Downloading headers until the pivot can be picked near the best one.
+    // Required when starting from a checkpoint, as no state exists there.
+    Pending { required: bool },
+    // Importing history up to the checkpoint, then the state there
+    History,
+    Running(StateSync, BlockHeader),
+    Done,
+}
+
+impl OmniTensorBehaviour {
+    // `None` defers the result until the announced block is fetched
+    fn validate(
+        &mut self,
+        source: PeerId,
+        message_id: &MessageId,
+        topic: &TopicHash,
+        data: &[u8],
+    ) -> Option<MessageAcceptance> {
+        if *topic == self::topic(TRANSACTIONS_TOPIC).hash() {
+            return Some(self.on_tx_announcement(source, data));
+        }
+        if *topic == self::topic(BLOCKS_TOPIC).hash() {
+            return self.on_block_announcement(source, message_id, data);
+        }
+        // Relays can't tell whether the signers are the current validators,
+        // but can drop forgeries before they reach light clients
+        if *topic == self::topic(LIGHT_CLIENT_TOPIC).hash() {
+            let signed: Result<SignedHeader, _> = decode_bounded(data);
+            if !signed.map_or(false, |signed| signed.check_signatures().is_ok()) {
+                return Some(MessageAcceptance::Reject);
+            }
+        }
+        // Only the node serving a job's stream knows its provider; relays
+        // drop chunks nobody signed
+        if *topic == self::topic(INFERENCE_OUTPUT_TOPIC).hash() {
+            let signed: Result<SignedOutputChunk, _> = decode_bounded(data);
+            if !signed.map_or(false, |signed| signed.signer().is_some()) {
+                return Some(MessageAcceptance::Reject);
+            }
+        }
+        match self.validators.get(topic) {
+            Some(validator) => Some(validator(data)),
+            None => Some(MessageAcceptance::Accept),
+        }
+    }
+
+    fn on_block_announcement(
+        &mut self,
+        source: PeerId,
+        message_id: &MessageId,
+        data: &[u8],
+    ) -> Option<MessageAcceptance> {
+        let announcement: BlockAnnouncement = match decode_bounded(data) {
+            Ok(announcement) => announcement,
+            Err(_) => return Some(MessageAcceptance::Reject),
+        };
+        let hash = announcement.hash;
+        if self.block_lookup.as_ref().map_or(false, |lookup| lookup(&hash).is_some()) {
+            self.block_fetcher.mark_seen(hash);
+            return Some(MessageAcceptance::Ignore);
+        }
+        if !self.block_fetcher.on_announcement(source, (message_id.clone(), source), announcement) {
+            return Some(MessageAcceptance::Ignore);
+        }
+        self.request_block(source, hash);
+        None
+    }
+
+    fn request_block(&mut self, peer: PeerId, hash: BlockHash) {
+        let request_id = self.block_fetch.send_request(&peer, hash);
+        self.block_requests.insert(request_id, hash);
+        self.sent_request(BLOCK_FETCH_LABEL, request_id, &peer, &hash);
+    }
+
+    // Asks another handshaken peer, or gives up on the announcement
+    fn block_fetch_failed(&mut self, peer: PeerId, hash: BlockHash) {
+        let candidates: Vec<PeerId> = self.peer_status.keys().copied().collect();
+        match self.block_fetcher.on_failure(&hash, peer, candidates) {
+            Some(NextAttempt::Retry(next)) => self.request_block(next, hash),
+            Some(NextAttempt::GiveUp((message_id, source))) => {
+                debug!("Giving up on block {} announced by {}", message_id, source);
+                self.settle(&message_id, &source, MessageAcceptance::Ignore);
+            }
+            None => {}
+        }
+    }
+
+    // Validates the announcement now that the body is here, and hands the block on.
+    // While header sync is still below it, the block can't be checked against
+    // the state and is relayed on its own validity, so a catching-up node
+    // doesn't drop out of the blocks mesh or penalize peers for being ahead.
+    fn on_block_fetched(&mut self, fetched: Fetched) {
+        let Fetched { block, announcement, message: (message_id, source) } = fetched;
+        if let Err(e) = self.clock_config.check_timestamp(block.header.timestamp, clock::unix_millis()) {
+            // Our clock may be the one behind, so the peer isn't penalized
+            debug!("Ignoring block {} announced by {}: {}", message_id, source, e);
+            self.settle(&message_id, &source, MessageAcceptance::Ignore);
+            return;
+        }
+        let behind = self.header_sync.as_ref().map_or(false, |sync| announcement.height > sync.base().height + 1);
+        let acceptance = match block.validate() {
+            Ok(()) if behind => MessageAcceptance::Accept,
+            Ok(()) => match self.validators.get(&topic(BLOCKS_TOPIC).hash()) {
+                Some(validator) => bincode::serialize(&block).map_or(MessageAcceptance::Reject, |bytes| validator(&bytes)),
+                None => MessageAcceptance::Accept,
+            },
+            Err(e) => {
+                debug!("Invalid block {} announced by {}: {:?}", message_id, source, e);
+                MessageAcceptance::Reject
+            }
+        };
+        let accepted = matches!(acceptance, MessageAcceptance::Accept);
+        if matches!(acceptance, MessageAcceptance::Reject) {
+            self.pending_reports.push((source, Misbehavior::InvalidBlock));
+        }
+        self.settle(&message_id, &source, acceptance);
+        if accepted {
+            // Heads only come with the handshake otherwise; this keeps header
+            // sync following the tip while it catches up
+            if let Some(status) = self.peer_status.get_mut(&source) {
+                status.head_height = status.head_height.max(announcement.height);
+            }
+            if let Err(e) = self.response_sender.send(OmniTensorEvent::Block(source, block, announcement.height)) {
+                error!("Error sending block via channel: {:?}", e);
+            }
+        }
+    }
+
+    fn settle(&mut self, message_id: &MessageId, source: &PeerId, acceptance: MessageAcceptance) {
+        if let Err(e) = self.gossipsub.report_message_validation_result(message_id, source, acceptance) {
+            warn!("Failed to report validation result: {:?}", e);
+        }
+    }
+
+    fn header_sync_failed(&mut self, peer: PeerId, error: HeaderSyncError) {
+        debug!("Header sync with {} failed: {}", peer, error);
+        if let Some(misbehavior) = error.misbehavior() {
+            self.pending_reports.push((peer, misbehavior));
+        }
+    }
+
+    fn on_tx_announcement(&mut self, source: PeerId, data: &[u8]) -> MessageAcceptance {
+        let announcement: TxAnnouncement = match decode_bounded(data) {
+            Ok(announcement) => announcement,
+            Err(_) => return MessageAcceptance::Reject,
+        };
+        if announcement.hashes.is_empty() || announcement.hashes.len() > self.tx_fetcher.max_announcement_hashes() {
+            return MessageAcceptance::Reject;
+        }
+        let wanted = self.tx_fetcher.on_announcement(source, announcement.hashes);
+        if wanted.is_empty() {
+            // Nothing new for us, so nothing new for our mesh either
+            return MessageAcceptance::Ignore;
+        }
+        let request_id = self.tx_fetch.send_request(&source, wanted.clone());
+        self.sent_request(TX_FETCH_LABEL, request_id, &source, &wanted);
+        MessageAcceptance::Accept
+    }
+
+    // Takes a token for an inbound request; excess requests are reported and
+    // left unanswered
+    fn allow_request(&mut self, protocol: &'static str, peer: &PeerId) -> bool {
+        let allowed = self.request_limits.get_mut(protocol).map_or(true, |limiter| limiter.allow(*peer));
+        if !allowed {
+            debug!("Dropping {} request from {}: rate limited", protocol, peer);
+            self.pending_reports.push((*peer, Misbehavior::RateLimited));
+        }
+        allowed
+    }
+
+    fn sent_request<T: Serialize>(&mut self, protocol: &'static str, request_id: RequestId, peer: &PeerId, request: &T) {
+        self.requests_in_flight.insert((protocol, request_id), Instant::now());
+        self.sent(peer, request);
+    }
+
+    fn sent<T: Serialize>(&self, peer: &PeerId, message: &T) {
+        let bytes = bincode::serialized_size(message).unwrap_or(0) as usize;
+        self.peer_manager.record_outbound(&peer.to_base58(), bytes);
+    }
+
+    fn received<T: Serialize>(&self, peer: &PeerId, protocol: &str, message: &T) {
+        let bytes = bincode::serialized_size(message).unwrap_or(0) as usize;
+        self.peer_manager.record_inbound(&peer.to_base58(), protocol, bytes);
+    }
+
+    // Records the round trip of a request once its response or failure arrives
+    fn completed(&mut self, protocol: &'static str, request_id: RequestId, peer: &PeerId) -> Option<Duration> {
+        let round_trip = self.requests_in_flight.remove(&(protocol, request_id))?.elapsed();
+        self.peer_manager.record_latency(&peer.to_base58(), round_trip);
+        Some(round_trip)
+    }
+}
+
+impl NetworkBehaviourEventProcess<GossipsubEvent> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: GossipsubEvent) {
+        if let GossipsubEvent::Message { propagation_source, message_id, message } = event {
+            self.peer_manager.record_inbound(&propagation_source.to_base58(), message.topic.as_str(), message.data.len());
+            let within_limit = match self.rate_limits.get_mut(&message.topic) {
+                Some(limiter) => limiter.allow(propagation_source),
+                None => true,
+            };
+            let (data, acceptance) = if !within_limit {
+                self.pending_reports.push((propagation_source, Misbehavior::RateLimited));
+                (Vec::new(), MessageAcceptance::Ignore)
+            } else {
+                let max_len = self.size_limits.get(&message.topic).copied().unwrap_or(0);
+                match self.compressor.decode(PROTOCOL_VERSION, &message.data, max_len) {
+                    Ok(data) => match self.validate(propagation_source, &message_id, &message.topic, &data) {
+                        Some(acceptance) => (data, acceptance),
+                        // Settled once the block body is fetched
+                        None => return,
+                    },
+                    Err(e) => {
+                        debug!("Undecodable message {} from {}: {}", message_id, propagation_source, e);
+                        (Vec::new(), MessageAcceptance::Reject)
+                    }
+                }
+            };
+            let accepted = matches!(acceptance, MessageAcceptance::Accept);
+            if !accepted {
+                debug!("Dropping message {} from {}: {:?}", message_id, propagation_source, acceptance);
+            }
+            if matches!(acceptance, MessageAcceptance::Reject) {
+                self.pending_reports.push((propagation_source, Misbehavior::MalformedMessage));
+            }
+            self.settle(&message_id, &propagation_source, acceptance);
+
+            // Announcements are consumed here; the bodies follow as `Transactions` and `Block`
+            let announcements = [topic(TRANSACTIONS_TOPIC).hash(), topic(BLOCKS_TOPIC).hash()];
+            if accepted && !announcements.contains(&message.topic) {
+                if let Err(e) = self
+                    .response_sender
+                    .send(OmniTensorEvent::Message(propagation_source, message.topic, data))
+                {
+                    error!("Error sending message via channel: {:?}", e);
+                }
             }
         }
     }
@@ -46,8 +719,8 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for OmniTensorBehaviour {
     fn inject_event(&mut self, event: MdnsEvent) {
         match event {
             MdnsEvent::Discovered(list) => {
-                for (peer_id, _multiaddr) in list {
-                    self.floodsub.add_node_to_partial_view(peer_id);
+                for (peer_id, multiaddr) in list {
+                    self.pending_dials.push((peer_id, multiaddr));
                     if let Err(e) = self.response_sender.send(OmniTensorEvent::NewPeer(peer_id)) {
                         error!("Error sending new peer event: {:?}", e);
                     }
@@ -55,8 +728,7 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for OmniTensorBehaviour {
             }
             MdnsEvent::Expired(list) => {
                 for (peer_id, _multiaddr) in list {
-                    if !self.mdns.has_node(&peer_id) {
-                        self.floodsub.remove_node_from_partial_view(&peer_id);
+                    if !self.mdns.as_ref().map_or(false, |mdns| mdns.has_node(&peer_id)) {
                         if let Err(e) = self.response_sender.send(OmniTensorEvent::ExpiredPeer(peer_id)) {
                             error!("Error sending expired peer event: {:?}", e);
                         }
@@ -67,74 +739,1369 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for OmniTensorBehaviour {
     }
 }
 
+impl NetworkBehaviourEventProcess<IdentifyEvent> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: IdentifyEvent) {
+        if let IdentifyEvent::Received { peer_id, info } = event {
+            debug!("Peer {} observes us at {}", peer_id, info.observed_addr);
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<AutonatEvent> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: AutonatEvent) {
+        if let AutonatEvent::StatusChanged { old, new } = event {
+            info!("NAT status changed from {:?} to {:?}", old, new);
+            if matches!(new, NatStatus::Private) {
+                self.needs_relay = true;
+            }
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RelayClientEvent> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: RelayClientEvent) {
+        match event {
+            RelayClientEvent::ReservationReqAccepted { relay_peer_id, .. } => {
+                info!("Relay {} accepted our reservation", relay_peer_id)
+            }
+            RelayClientEvent::ReservationReqFailed { relay_peer_id, error, .. } => {
+                warn!("Relay {} refused our reservation: {:?}", relay_peer_id, error)
+            }
+            event => debug!("Relay client event: {:?}", event),
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<DcutrEvent> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: DcutrEvent) {
+        match event {
+            DcutrEvent::DirectConnectionUpgradeSucceeded { remote_peer_id } => {
+                info!("Hole punched a direct connection to {}", remote_peer_id)
+            }
+            DcutrEvent::DirectConnectionUpgradeFailed { remote_peer_id, error } => {
+                debug!("Hole punching to {} failed: {:?}", remote_peer_id, error)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RequestResponseEvent<Status, Status>> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: RequestResponseEvent<Status, Status>) {
+        match event {
+            RequestResponseEvent::Message { peer, message } => {
+                // Our clock when the peer read its own: on arrival for a
+                // request, half a round trip before it for a response
+                let mut local_ms = clock::unix_millis();
+                let remote = match message {
+                    RequestResponseMessage::Request { request, channel, .. } => {
+                        if !self.allow_request(HANDSHAKE_LABEL, &peer) {
+                            return;
+                        }
+                        // Answer even when incompatible so the dialer learns why it is dropped
+                        let status = self.local_status.stamped(local_ms);
+                        self.sent(&peer, &status);
+                        let _ = self.handshake.send_response(channel, status);
+                        request
+                    }
+                    RequestResponseMessage::Response { request_id, response } => {
+                        if let Some(round_trip) = self.completed(HANDSHAKE_LABEL, request_id, &peer) {
+                            local_ms -= round_trip.as_millis() as u64 / 2;
+                        }
+                        response
+                    }
+                };
+                self.received(&peer, HANDSHAKE_LABEL, &remote);
+                match self.local_status.check_compatible(&remote) {
+                    Ok(()) => {
+                        debug!("Handshake with {} done, head at {}", peer, remote.head_height);
+                        if let Some(remote_ms) = remote.timestamp_ms {
+                            self.clock.record_peer(&peer.to_base58(), clock::offset(remote_ms, local_ms));
+                        }
+                        self.peer_status.insert(peer, remote);
+                    }
+                    Err(reason) => self.pending_disconnects.push((peer, reason.goodbye_reason(), reason.to_string())),
+                }
+            }
+            RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+                self.requests_in_flight.remove(&(HANDSHAKE_LABEL, request_id));
+                let detail = format!("handshake failed: {:?}", error);
+                self.pending_disconnects.push((peer, GoodbyeReason::ProtocolError, detail))
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                debug!("Inbound handshake from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RequestResponseEvent<Vec<TransactionHash>, Vec<Transaction>>> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: RequestResponseEvent<Vec<TransactionHash>, Vec<Transaction>>) {
+        match event {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    self.received(&peer, TX_FETCH_LABEL, &request);
+                    if !self.allow_request(TX_FETCH_LABEL, &peer) {
+                        return;
+                    }
+                    let transactions: Vec<Transaction> = match &self.tx_lookup {
+                        Some(lookup) => request
+                            .iter()
+                            .take(self.tx_fetcher.max_announcement_hashes())
+                            .filter_map(|hash| lookup(hash))
+                            .collect(),
+                        None => Vec::new(),
+                    };
+                    self.sent(&peer, &transactions);
+                    let _ = self.tx_fetch.send_response(channel, transactions);
+                }
+                RequestResponseMessage::Response { request_id, response } => {
+                    self.completed(TX_FETCH_LABEL, request_id, &peer);
+                    self.received(&peer, TX_FETCH_LABEL, &response);
+                    let transactions = self.tx_fetcher.on_response(peer, response);
+                    if !transactions.is_empty() {
+                        if let Err(e) = self.response_sender.send(OmniTensorEvent::Transactions(peer, transactions)) {
+                            error!("Error sending transactions via channel: {:?}", e);
+                        }
+                    }
+                }
+            },
+            // Timed out fetches are retried from another announcer by the swarm loop
+            RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+                self.requests_in_flight.remove(&(TX_FETCH_LABEL, request_id));
+                debug!("Transaction fetch from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::InboundFailure { .. } | RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RequestResponseEvent<BlockHash, Option<Block>>> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: RequestResponseEvent<BlockHash, Option<Block>>) {
+        match event {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    self.received(&peer, BLOCK_FETCH_LABEL, &request);
+                    if !self.allow_request(BLOCK_FETCH_LABEL, &peer) {
+                        return;
+                    }
+                    let block = self.block_lookup.as_ref().and_then(|lookup| lookup(&request));
+                    self.sent(&peer, &block);
+                    let _ = self.block_fetch.send_response(channel, block);
+                }
+                RequestResponseMessage::Response { request_id, response } => {
+                    self.completed(BLOCK_FETCH_LABEL, request_id, &peer);
+                    self.received(&peer, BLOCK_FETCH_LABEL, &response);
+                    let hash = match self.block_requests.remove(&request_id) {
+                        Some(hash) => hash,
+                        None => return,
+                    };
+                    match response {
+                        Some(block) if block.hash() != hash => {
+                            self.pending_reports.push((peer, Misbehavior::MalformedMessage));
+                            self.block_fetch_failed(peer, hash);
+                        }
+                        // Late answers from a peer we stopped waiting for are dropped
+                        Some(block) => {
+                            if let Some(fetched) = self.block_fetcher.on_body(peer, block) {
+                                self.on_block_fetched(fetched);
+                            }
+                        }
+                        None => self.block_fetch_failed(peer, hash),
+                    }
+                }
+            },
+            RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+                self.requests_in_flight.remove(&(BLOCK_FETCH_LABEL, request_id));
+                debug!("Block fetch from {} failed: {:?}", peer, error);
+                if let Some(hash) = self.block_requests.remove(&request_id) {
+                    self.block_fetch_failed(peer, hash);
+                }
+            }
+            RequestResponseEvent::InboundFailure { .. } | RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RequestResponseEvent<HeadersRequest, Vec<BlockHeader>>> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: RequestResponseEvent<HeadersRequest, Vec<BlockHeader>>) {
+        match event {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    self.received(&peer, HEADERS_LABEL, &request);
+                    if !self.allow_request(HEADERS_LABEL, &peer) {
+                        return;
+                    }
+                    let headers = match &self.header_lookup {
+                        Some(lookup) => serve_headers(request, lookup),
+                        None => Vec::new(),
+                    };
+                    self.sent(&peer, &headers);
+                    let _ = self.headers.send_response(channel, headers);
+                }
+                RequestResponseMessage::Response { request_id, response } => {
+                    self.completed(HEADERS_LABEL, request_id, &peer);
+                    self.received(&peer, HEADERS_LABEL, &response);
+                    let lookup = self.header_lookup.as_ref();
+                    let local = |height| lookup.and_then(|lookup| lookup(height)).map(|header| header_hash(&header));
+                    let result = match self.header_sync.as_mut() {
+                        Some(sync) => sync.on_headers(peer, response, local),
+                        None => return,
+                    };
+                    match result {
+                        Ok(added) => debug!("Verified {} headers from {}", added, peer),
+                        Err(e) => self.header_sync_failed(peer, e),
+                    }
+                }
+            },
+            RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+                self.requests_in_flight.remove(&(HEADERS_LABEL, request_id));
+                debug!("Headers request to {} failed: {:?}", peer, error);
+                if let Some(sync) = self.header_sync.as_mut() {
+                    sync.failed(&peer);
+                }
+            }
+            RequestResponseEvent::InboundFailure { .. } | RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RequestResponseEvent<Vec<BlockHash>, Vec<Block>>> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: RequestResponseEvent<Vec<BlockHash>, Vec<Block>>) {
+        match event {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    self.received(&peer, BODIES_LABEL, &request);
+                    if !self.allow_request(BODIES_LABEL, &peer) {
+                        return;
+                    }
+                    let blocks = match &self.block_lookup {
+                        Some(lookup) => serve_bodies(&request, lookup),
+                        None => Vec::new(),
+                    };
+                    self.sent(&peer, &blocks);
+                    let _ = self.bodies.send_response(channel, blocks);
+                }
+                RequestResponseMessage::Response { request_id, response } => {
+                    self.completed(BODIES_LABEL, request_id, &peer);
+                    self.received(&peer, BODIES_LABEL, &response);
+                    let result = match self.header_sync.as_mut() {
+                        Some(sync) => sync.on_bodies(peer, response).map(|blocks| (sync.take_reorg(), blocks)),
+                        None => return,
+                    };
+                    match result {
+                        Ok((_, blocks)) if blocks.is_empty() => {}
+                        Ok((reorg, blocks)) => {
+                            if let Some(ancestor) = reorg {
+                                if let Err(e) = self.response_sender.send(OmniTensorEvent::Reorg(ancestor)) {
+                                    error!("Error sending reorg via channel: {:?}", e);
+                                }
+                            }
+                            if let Err(e) = self.response_sender.send(OmniTensorEvent::SyncedBlocks(blocks)) {
+                                error!("Error sending synced blocks via channel: {:?}", e);
+                            }
+                        }
+                        Err(e) => self.header_sync_failed(peer, e),
+                    }
+                }
+            },
+            RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+                self.requests_in_flight.remove(&(BODIES_LABEL, request_id));
+                debug!("Bodies request to {} failed: {:?}", peer, error);
+                if let Some(sync) = self.header_sync.as_mut() {
+                    sync.failed(&peer);
+                }
+            }
+            RequestResponseEvent::InboundFailure { .. } | RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RequestResponseEvent<Vec<BlockHash>, Vec<(Block, Vec<TransactionReceipt>)>>>
+    for OmniTensorBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<Vec<BlockHash>, Vec<(Block, Vec<TransactionReceipt>)>>) {
+        match event {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    self.received(&peer, HISTORY_LABEL, &request);
+                    if !self.allow_request(HISTORY_LABEL, &peer) {
+                        return;
+                    }
+                    let history = match (&self.block_lookup, &self.receipts_lookup) {
+                        (Some(lookup), Some(receipts)) => serve_history(&request, lookup, receipts),
+                        _ => Vec::new(),
+                    };
+                    self.sent(&peer, &history);
+                    let _ = self.history.send_response(channel, history);
+                }
+                RequestResponseMessage::Response { request_id, response } => {
+                    self.completed(HISTORY_LABEL, request_id, &peer);
+                    self.received(&peer, HISTORY_LABEL, &response);
+                    let result = match self.header_sync.as_mut() {
+                        Some(sync) => sync.on_history(peer, response).map(|history| (sync.in_history(), history)),
+                        None => return,
+                    };
+                    match result {
+                        Ok((_, history)) if history.is_empty() => {}
+                        Ok((in_history, history)) => {
+                            if !in_history {
+                                self.history_end = history.last().map(|(_, block, _)| block.header.clone());
+                            }
+                            if let Err(e) = self.response_sender.send(OmniTensorEvent::SyncedHistory(history)) {
+                                error!("Error sending synced history via channel: {:?}", e);
+                            }
+                        }
+                        Err(e) => self.header_sync_failed(peer, e),
+                    }
+                }
+            },
+            RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+                self.requests_in_flight.remove(&(HISTORY_LABEL, request_id));
+                debug!("History request to {} failed: {:?}", peer, error);
+                if let Some(sync) = self.header_sync.as_mut() {
+                    sync.failed(&peer);
+                }
+            }
+            RequestResponseEvent::InboundFailure { .. } | RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RequestResponseEvent<Vec<TrieHash>, Vec<Vec<u8>>>> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: RequestResponseEvent<Vec<TrieHash>, Vec<Vec<u8>>>) {
+        match event {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    self.received(&peer, STATE_LABEL, &request);
+                    if self.allow_request(STATE_LABEL, &peer) {
+                        self.pending_state_requests.push((peer, request, channel));
+                    }
+                }
+                RequestResponseMessage::Response { request_id, response } => {
+                    self.completed(STATE_LABEL, request_id, &peer);
+                    self.received(&peer, STATE_LABEL, &response);
+                    self.pending_state_chunks.push((peer, Some(response)));
+                }
+            },
+            RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+                self.requests_in_flight.remove(&(STATE_LABEL, request_id));
+                debug!("State request to {} failed: {:?}", peer, error);
+                self.pending_state_chunks.push((peer, None));
+            }
+            RequestResponseEvent::InboundFailure { .. } | RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RequestResponseEvent<u8, ()>> for OmniTensorBehaviour {
+    fn inject_event(&mut self, event: RequestResponseEvent<u8, ()>) {
+        match event {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    self.received(&peer, GOODBYE_LABEL, &request);
+                    if !self.allow_request(GOODBYE_LABEL, &peer) {
+                        return;
+                    }
+                    let reason = GoodbyeReason::from_code(request);
+                    info!("Peer {} is disconnecting: {}", peer, reason);
+                    self.peer_manager.record_goodbye_received(&peer.to_base58(), reason);
+                    let _ = self.goodbye.send_response(channel, ());
+                }
+                RequestResponseMessage::Response { request_id, .. } => {
+                    self.completed(GOODBYE_LABEL, request_id, &peer);
+                    if self.farewells.remove(&peer).is_some() {
+                        self.pending_closes.push(peer);
+                    }
+                }
+            },
+            RequestResponseEvent::OutboundFailure { peer, request_id, .. } => {
+                self.requests_in_flight.remove(&(GOODBYE_LABEL, request_id));
+                if self.farewells.remove(&peer).is_some() {
+                    self.pending_closes.push(peer);
+                }
+            }
+            RequestResponseEvent::InboundFailure { .. } | RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
+
+// The payload framing depends on the protocol version, so the version is part
+// of every topic name and nodes on different versions never share a mesh
+fn topic(name: &str) -> Topic {
+    Topic::new(format!("{}/{}", name, PROTOCOL_VERSION))
+}
+
+fn protocol_id() -> String {
+    format!("/omnitensor/{}", PROTOCOL_VERSION)
+}
+
+// Blocks are the most valuable and the most expensive to fake, so delivering
+// them earns the most score and invalid ones cost the most
+fn topic_score_params(topic: &str) -> TopicScoreParams {
+    let topic_weight = match topic {
+        BLOCKS_TOPIC => 1.0,
+        VOTES_TOPIC => 0.8,
+        TRANSACTIONS_TOPIC | INFERENCE_JOBS_TOPIC => 0.5,
+        LIGHT_CLIENT_TOPIC => 0.3,
+        _ => 0.1,
+    };
+    TopicScoreParams {
+        topic_weight,
+        time_in_mesh_weight: 0.01,
+        time_in_mesh_quantum: Duration::from_secs(1),
+        time_in_mesh_cap: 3600.0,
+        first_message_deliveries_weight: 1.0,
+        first_message_deliveries_cap: 100.0,
+        invalid_message_deliveries_weight: -100.0,
+        invalid_message_deliveries_decay: 0.5,
+        ..TopicScoreParams::default()
+    }
+}
+
+fn build_transport(
+    local_key: &identity::Keypair,
+    config: &TransportConfig,
+    psk: Option<PreSharedKey>,
+    relay_transport: libp2p::relay::v2::client::transport::ClientTransport,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, Box<dyn Error>> {
+    if psk.is_some() && config.quic_enabled {
+        return Err("QUIC can't carry a pre-shared network key; disable quic_enabled on private networks".into());
+    }
+    if let Some(psk) = &psk {
+        info!("Joining private network {}", psk.fingerprint());
+    }
+
+    let id_keys = Keypair::<X25519Spec>::new()
+        .into_authentic(local_key)
+        .expect("Can create keypair");
+
+    // DNS so bootstrap peers can be given as /dns4/... multiaddrs. Relayed
+    // circuits and WebSockets get the same noise and mplex upgrade as direct
+    // TCP, behind the private network handshake if a key is configured.
+    let tcp = relay_transport
+        .or_transport(websocket_transport(config)?)
+        .or_transport(TokioDnsConfig::system(TokioTcpConfig::new())?)
+        .and_then(move |socket, _| async move {
+            match psk {
+                Some(psk) => PnetConfig::new(psk).handshake(socket).await.map(EitherOutput::First),
+                None => Ok(EitherOutput::Second(socket)),
+            }
+        })
+        .upgrade(upgrade::Version::V1)
+        .authenticate(NoiseConfig::xx(id_keys).into_authenticated())
+        .multiplex(mplex::MplexConfig::new())
+        .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)));
+    if !config.quic_enabled {
+        return Ok(tcp.boxed());
+    }
+
+    // QUIC brings its own TLS handshake and stream multiplexing
+    let quic = quic::tokio::Transport::new(quic::Config::new(local_key))
+        .map(|(peer_id, connection), _| (peer_id, StreamMuxerBox::new(connection)));
+    Ok(quic
+        .or_transport(tcp)
+        .map(|either, _| match either {
+            EitherOutput::First(output) => output,
+            EitherOutput::Second(output) => output,
+        })
+        .boxed())
+}
+
+fn websocket_transport(config: &TransportConfig) -> Result<WsConfig<TokioDnsConfig<TokioTcpConfig>>, Box<dyn Error>> {
+    let mut websocket = WsConfig::new(TokioDnsConfig::system(TokioTcpConfig::new())?);
+    match (&config.websocket_tls_cert, &config.websocket_tls_key) {
+        (Some(cert), Some(key)) => {
+            let key = tls::PrivateKey::new(std::fs::read(key)?);
+            let cert = tls::Certificate::new(std::fs::read(cert)?);
+            websocket.set_tls_config(tls::Config::new(key, iter::once(cert))?);
+        }
+        (None, None) => {}
+        _ => return Err("websocket_tls_cert and websocket_tls_key must be set together".into()),
+    }
+    Ok(websocket)
+}
+
+fn request_limiters(limits: &RequestLimitsConfig) -> HashMap<&'static str, RateLimiter<PeerId>> {
+    HashMap::from([
+        (HANDSHAKE_LABEL, limits.handshake.limiter()),
+        (TX_FETCH_LABEL, limits.tx_fetch.limiter()),
+        (BLOCK_FETCH_LABEL, limits.block_fetch.limiter()),
+        (GOODBYE_LABEL, limits.goodbye.limiter()),
+        (HEADERS_LABEL, limits.headers.limiter()),
+        (BODIES_LABEL, limits.bodies.limiter()),
+        (HISTORY_LABEL, limits.history.limiter()),
+        (STATE_LABEL, limits.state.limiter()),
+    ])
+}
+
+fn build_gossipsub(
+    local_key: &identity::Keypair,
+    config: &GossipConfig,
+    topics: &TopicsConfig,
+) -> Result<Gossipsub, Box<dyn Error>> {
+    // Content-addressed ids so the same payload relayed by different peers is deduplicated
+    let message_id_fn = |message: &GossipsubMessage| MessageId::from(Sha256::digest(&message.data).to_vec());
+
+    let gossipsub_config = GossipsubConfigBuilder::default()
+        .mesh_n(config.mesh_n)
+        .mesh_n_low(config.mesh_n_low)
+        .mesh_n_high(config.mesh_n_high)
+        .heartbeat_interval(Duration::from_millis(config.heartbeat_interval_ms))
+        .max_transmit_size(topics.max_transmit_size())
+        .validation_mode(ValidationMode::Strict)
+        .validate_messages()
+        .message_id_fn(message_id_fn)
+        .build()?;
+    let mut gossipsub = Gossipsub::new(MessageAuthenticity::Signed(local_key.clone()), gossipsub_config)?;
+
+    let mut params = PeerScoreParams::default();
+    for name in TOPICS {
+        params.topics.insert(topic(name).hash(), topic_score_params(name));
+    }
+    let thresholds = PeerScoreThresholds {
+        gossip_threshold: config.gossip_threshold,
+        publish_threshold: config.publish_threshold,
+        graylist_threshold: config.graylist_threshold,
+        ..PeerScoreThresholds::default()
+    };
+    gossipsub.with_peer_score(params, thresholds)?;
+
+    for name in TOPICS.into_iter().filter(|name| topics.get(name).subscribe) {
+        gossipsub.subscribe(&topic(name))?;
+    }
+    Ok(gossipsub)
+}
+
 pub struct P2PNetwork {
     swarm: Swarm<OmniTensorBehaviour>,
-    topic: Topic,
+    config: NetworkConfig,
+    peer_store: PeerStore,
+    peer_manager: Arc<PeerManager>,
+    connections: ConnectionManager,
+    trusted: TrustedPeers,
+    housekeeping: tokio::time::Interval,
+    seed_addresses: mpsc::UnboundedReceiver<Vec<Multiaddr>>,
+    seed_sender: mpsc::UnboundedSender<Vec<Multiaddr>>,
+    seeds_dialed: bool,
+    // Serves state snapshots and receives fast-synced state
+    state_db: Option<Arc<Database>>,
+    fast_sync: FastSync,
+    checkpoint: Option<ChainHead>,
 }
 
 impl P2PNetwork {
-    pub async fn new() -> Result<(Self, mpsc::UnboundedReceiver<OmniTensorEvent>), Box<dyn Error>> {
-        let (response_sender, response_rcv) = mpsc::unbounded_channel();
-
-        let id_keys = Keypair::<X25519Spec>::new()
-            .into_authentic(&Keypair::generate())
-            .expect("Can create keypair");
-
-        let peer_id = PeerId::from(id_keys.public());
+    pub async fn new(
+        config: NetworkConfig,
+        genesis_hash: [u8; 32],
+        peer_store: PeerStore,
+        peer_manager: Arc<PeerManager>,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<OmniTensorEvent>), Box<dyn Error>> {
+        let local_key = match &config.identity_key {
+            Some(path) => keystore::load_or_generate(path)?,
+            None => {
+                warn!("No identity_key configured, using a throwaway PeerId");
+                identity::Keypair::generate_ed25519()
+            }
+        };
+        let peer_id = PeerId::from(local_key.public());
         info!("Local peer id: {:?}", peer_id);
 
-        let transport = TokioTcpConfig::new()
-            .upgrade(upgrade::Version::V1)
-            .authenticate(NoiseConfig::xx(id_keys).into_authenticated())
-            .multiplex(mplex::MplexConfig::new())
-            .boxed();
+        let (relay_transport, relay_client) = RelayClient::new_transport_and_behaviour(peer_id);
+        let psk = config.private.load()?;
+        let transport = build_transport(&local_key, &config.transports, psk, relay_transport)?;
+        Self::with_transport(config, genesis_hash, peer_store, peer_manager, local_key, transport, relay_client).await
+    }
 
-        let topic = Topic::new("omnitensor-messages");
+    // Lets the testkit run nodes over its simulated in-memory transport
+    pub(crate) async fn with_transport(
+        config: NetworkConfig,
+        genesis_hash: [u8; 32],
+        peer_store: PeerStore,
+        peer_manager: Arc<PeerManager>,
+        local_key: identity::Keypair,
+        transport: Boxed<(PeerId, StreamMuxerBox)>,
+        relay_client: RelayClient,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<OmniTensorEvent>), Box<dyn Error>> {
+        let (response_sender, response_rcv) = mpsc::unbounded_channel();
+        let peer_id = PeerId::from(local_key.public());
 
-        let mut behaviour = OmniTensorBehaviour {
-            floodsub: Floodsub::new(peer_id),
-            mdns: Mdns::new(Default::default()).await?,
+        let mdns = if config.mdns { Some(Mdns::new(Default::default()).await?) } else { None };
+        let behaviour = OmniTensorBehaviour {
+            gossipsub: build_gossipsub(&local_key, &config.gossip, &config.topics)?,
+            mdns: Toggle::from(mdns),
+            identify: Identify::new(IdentifyConfig::new(protocol_id(), local_key.public())),
+            autonat: Autonat::new(peer_id, AutonatConfig::default()),
+            relay_client,
+            dcutr: Toggle::from(config.nat.hole_punching.then(Dcutr::new)),
+            handshake: RequestResponse::new(
+                handshake_codec(),
+                [(HANDSHAKE_PROTOCOL, ProtocolSupport::Full), (LEGACY_HANDSHAKE_PROTOCOL, ProtocolSupport::Full)],
+                RequestResponseConfig::default(),
+            ),
+            tx_fetch: RequestResponse::new(
+                tx_fetch_codec(),
+                iter::once((TX_FETCH_PROTOCOL, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            block_fetch: RequestResponse::new(
+                block_fetch_codec(),
+                iter::once((BLOCK_FETCH_PROTOCOL, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            headers: RequestResponse::new(
+                headers_codec(),
+                iter::once((HEADERS_PROTOCOL, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            bodies: RequestResponse::new(
+                bodies_codec(),
+                iter::once((BODIES_PROTOCOL, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            history: RequestResponse::new(
+                history_codec(),
+                iter::once((HISTORY_PROTOCOL, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            state: RequestResponse::new(
+                state_codec(),
+                iter::once((STATE_PROTOCOL, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            goodbye: RequestResponse::new(
+                goodbye_codec(),
+                iter::once((GOODBYE_PROTOCOL, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            tx_fetcher: TxFetcher::new(config.tx_gossip.clone()),
+            tx_lookup: None,
+            block_fetcher: BlockFetcher::new(config.block_gossip.clone()),
+            block_lookup: None,
+            block_requests: HashMap::new(),
+            header_sync: None,
+            header_lookup: None,
+            receipts_lookup: None,
+            history_end: None,
+            pending_state_requests: Vec::new(),
+            pending_state_chunks: Vec::new(),
             response_sender,
+            validators: HashMap::new(),
+            rate_limits: TOPICS
+                .into_iter()
+                .map(|name| {
+                    let limits = config.topics.get(name);
+                    (topic(name).hash(), RateLimiter::new(limits.messages_per_sec, limits.burst))
+                })
+                .collect(),
+            request_limits: request_limiters(&config.request_limits),
+            size_limits: TOPICS.into_iter().map(|name| (topic(name).hash(), config.topics.get(name).max_message_bytes)).collect(),
+            compressor: Arc::new(MessageCompressor::new(config.compression.clone())),
+            peer_manager: peer_manager.clone(),
+            requests_in_flight: HashMap::new(),
+            local_status: Status::new(config.chain_id, genesis_hash, 0),
+            peer_status: HashMap::new(),
+            pending_disconnects: Vec::new(),
+            farewells: HashMap::new(),
+            pending_closes: Vec::new(),
+            pending_dials: Vec::new(),
+            pending_reports: Vec::new(),
+            needs_relay: false,
+            clock: Arc::new(ClockDrift::new(&config.clock)),
+            clock_config: config.clock.clone(),
         };
 
-        behaviour.floodsub.subscribe(topic.clone());
-
         let swarm = SwarmBuilder::new(transport, behaviour, peer_id)
             .executor(Box::new(|fut| {
                 tokio::spawn(fut);
             }))
             .build();
 
-        Ok((
-            Self {
-                swarm,
-                topic,
-            },
-            response_rcv,
-        ))
+        let connections = ConnectionManager::new(config.connections.clone());
+        let trusted = TrustedPeers::parse(&config.trusted_peers)?;
+        for peer_id in trusted.ids() {
+            peer_manager.trust(&peer_id.to_base58());
+        }
+        let checkpoint = config.header_sync.checkpoint.as_ref().map(CheckpointConfig::parse).transpose()?;
+        let housekeeping = tokio::time::interval(HOUSEKEEPING_TICK);
+        let (seed_sender, seed_addresses) = mpsc::unbounded_channel();
+        let network = Self {
+            swarm,
+            config,
+            peer_store,
+            peer_manager,
+            connections,
+            trusted,
+            housekeeping,
+            seed_addresses,
+            seed_sender,
+            seeds_dialed: false,
+            state_db: None,
+            fast_sync: FastSync::Done,
+            checkpoint,
+        };
+        Ok((network, response_rcv))
+    }
+
+    // The local clock's estimated drift, for metrics and block production
+    pub fn clock(&self) -> Arc<ClockDrift> {
+        self.swarm.behaviour().clock.clone()
+    }
+
+    pub fn clock_config(&self) -> &ClockConfig {
+        &self.config.clock
+    }
+
+    // Registers the validation callback for one topic, replacing any previous one.
+    // On the blocks topic it is called with the fetched block, not the announcement.
+    pub fn set_validator(&mut self, name: &str, validator: MessageValidator) {
+        self.swarm.behaviour_mut().validators.insert(topic(name).hash(), validator);
     }
 
     pub async fn start(&mut self) -> Result<(), Box<dyn Error>> {
-        self.swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+        self.listen().await?;
+        while self.step().await {}
+        Ok(())
+    }
+
+    pub(crate) async fn listen(&mut self) -> Result<(), Box<dyn Error>> {
+        for address in self.config.transports.listen_addresses() {
+            self.swarm.listen_on(address.parse()?)?;
+        }
+        self.dial_known_peers().await;
+        let interval = Duration::from_secs(self.config.dns_seed_interval_secs.max(1));
+        dns_seeds::spawn(self.config.dns_seeds.clone(), interval, self.seed_sender.clone());
+        let interval = Duration::from_secs(self.config.clock.ntp_interval_secs.max(1));
+        clock::spawn_ntp(self.config.clock.ntp_server.clone(), interval, self.swarm.behaviour().clock.clone());
+        Ok(())
+    }
+
+    // Handles one swarm event or housekeeping tick; false once the swarm is gone
+    pub(crate) async fn step(&mut self) -> bool {
+        tokio::select! {
+            event = self.swarm.next() => match event {
+                Some(event) => self.handle_swarm_event(event).await,
+                None => return false,
+            },
+            _ = self.housekeeping.tick() => {
+                self.dial_trusted_peers();
+                self.retry_tx_fetches();
+                self.retry_block_fetches();
+                self.expire_header_sync();
+                self.close_farewells();
+            }
+            Some(addresses) = self.seed_addresses.recv() => {
+                self.dial_seed_addresses(addresses);
+                return true;
+            }
+        }
+        // Work queued by the behaviour while it processed the event or tick
+        let disconnects = std::mem::take(&mut self.swarm.behaviour_mut().pending_disconnects);
+        for (peer_id, reason, detail) in disconnects {
+            info!("Disconnecting {}: {}", peer_id, detail);
+            self.disconnect(peer_id, reason);
+        }
+        self.close_acknowledged();
+        if std::mem::take(&mut self.swarm.behaviour_mut().needs_relay) {
+            self.listen_via_relays();
+        }
+        let reports = std::mem::take(&mut self.swarm.behaviour_mut().pending_reports);
+        for (peer_id, misbehavior) in reports {
+            self.report_peer(&peer_id, misbehavior).await;
+        }
+        let dials = std::mem::take(&mut self.swarm.behaviour_mut().pending_dials);
+        for (peer_id, address) in dials {
+            if let Err(e) = self.swarm.dial(address) {
+                debug!("Failed to dial discovered peer {}: {:?}", peer_id, e);
+            }
+        }
+        self.drive_header_sync();
+        self.serve_state().await;
+        self.drive_fast_sync().await;
+        true
+    }
 
-        loop {
-            match self.swarm.next().await {
-                Some(event) => {
-                    if let swarm::SwarmEvent::NewListenAddr { address, .. } = event {
-                        info!("Listening on {:?}", address);
+    async fn handle_swarm_event<E>(&mut self, event: SwarmEvent<(), E>) {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => info!("Listening on {:?}", address),
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, .. } => {
+                if self.peer_manager.is_banned(&peer_id.to_base58()).await.unwrap_or(false) {
+                    debug!("Disconnecting banned peer {}", peer_id);
+                    self.disconnect(peer_id, GoodbyeReason::Banned);
+                    return;
+                }
+                if self.trusted.contains(&peer_id) {
+                    self.trusted.on_connected(&peer_id);
+                } else if num_established.get() == 1 {
+                    let (swarm, peer_manager) = (&self.swarm, &self.peer_manager);
+                    let score = |peer: &PeerId| {
+                        swarm.behaviour().gossipsub.peer_score(peer).unwrap_or(0.0)
+                            + peer_manager.score(&peer.to_base58())
+                    };
+                    match self.connections.admit(peer_id, &endpoint, score) {
+                        Admission::Accept => {}
+                        Admission::Evict(victim) => {
+                            debug!("Evicting {} to make room for {}", victim, peer_id);
+                            self.disconnect(victim, GoodbyeReason::TooManyPeers);
+                        }
+                        Admission::Reject(reason) => {
+                            debug!("Refusing {}: {}", peer_id, reason);
+                            self.disconnect(peer_id, GoodbyeReason::TooManyPeers);
+                            return;
+                        }
                     }
                 }
-                None => break,
+                if endpoint.is_dialer() && num_established.get() == 1 {
+                    let behaviour = self.swarm.behaviour_mut();
+                    let status = behaviour.local_status.stamped(clock::unix_millis());
+                    let request_id = behaviour.handshake.send_request(&peer_id, status.clone());
+                    behaviour.sent_request(HANDSHAKE_LABEL, request_id, &peer_id, &status);
+                }
+                let address = endpoint.get_remote_address().to_string();
+                if let Err(e) = self.peer_store.record_connected(&peer_id.to_base58(), &address).await {
+                    warn!("Failed to persist peer {}: {}", peer_id, e);
+                }
+            }
+            SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                let behaviour = self.swarm.behaviour_mut();
+                behaviour.peer_status.remove(&peer_id);
+                behaviour.farewells.remove(&peer_id);
+                if let Some(sync) = behaviour.header_sync.as_mut() {
+                    sync.cancel(&peer_id);
+                }
+                if let FastSync::Running(sync, _) = &mut self.fast_sync {
+                    sync.cancel(&peer_id);
+                }
+                self.connections.on_closed(&peer_id);
+                self.trusted.on_disconnected(&peer_id);
+                self.peer_manager.forget_traffic(&peer_id.to_base58());
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), .. } => {
+                self.trusted.on_dial_failure(&peer_id);
+                if let Err(e) = self.peer_store.record_failure(&peer_id.to_base58()).await {
+                    warn!("Failed to persist peer {}: {}", peer_id, e);
+                }
             }
+            _ => {}
         }
+    }
 
-        Ok(())
+    // Tells the peer why before closing; the connection is closed once the
+    // peer acknowledges or `GOODBYE_TIMEOUT` passes
+    pub fn disconnect(&mut self, peer_id: PeerId, reason: GoodbyeReason) {
+        let behaviour = self.swarm.behaviour_mut();
+        if behaviour.farewells.contains_key(&peer_id) {
+            return;
+        }
+        let request_id = behaviour.goodbye.send_request(&peer_id, reason.code());
+        behaviour.sent_request(GOODBYE_LABEL, request_id, &peer_id, &reason.code());
+        behaviour.farewells.insert(peer_id, Instant::now());
+        self.peer_manager.record_goodbye_sent(reason);
+    }
+
+    // Says goodbye to every connected peer and waits briefly for them to acknowledge
+    pub async fn shutdown(&mut self) {
+        let peers: Vec<PeerId> = self.swarm.connected_peers().copied().collect();
+        for peer_id in peers {
+            self.disconnect(peer_id, GoodbyeReason::Shutdown);
+        }
+        let deadline = tokio::time::sleep(GOODBYE_TIMEOUT);
+        tokio::pin!(deadline);
+        while !self.swarm.behaviour().farewells.is_empty() {
+            tokio::select! {
+                _ = self.swarm.next() => self.close_acknowledged(),
+                _ = &mut deadline => break,
+            }
+        }
     }
 
-    pub fn broadcast(&mut self, message: Vec<u8>) {
-        self.swarm
-            .behaviour_mut()
-            .floodsub
-            .publish(self.topic.clone(), message);
+    fn close_acknowledged(&mut self) {
+        for peer_id in std::mem::take(&mut self.swarm.behaviour_mut().pending_closes) {
+            let _ = self.swarm.disconnect_peer_id(peer_id);
+        }
+    }
+
+    fn close_farewells(&mut self) {
+        let behaviour = self.swarm.behaviour_mut();
+        let expired: Vec<PeerId> = behaviour
+            .farewells
+            .iter()
+            .filter(|(_, sent)| sent.elapsed() >= GOODBYE_TIMEOUT)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in expired {
+            behaviour.farewells.remove(&peer_id);
+            behaviour.pending_closes.push(peer_id);
+        }
+        self.close_acknowledged();
+    }
+
+    fn dial_trusted_peers(&mut self) {
+        for address in self.trusted.due() {
+            if let Err(e) = self.swarm.dial(address.clone()) {
+                debug!("Failed to dial trusted peer {}: {:?}", address, e);
+            }
+        }
     }
-}
\ No newline at end of file
+
+    fn retry_tx_fetches(&mut self) {
+        let behaviour = self.swarm.behaviour_mut();
+        for (peer_id, hashes) in behaviour.tx_fetcher.expire() {
+            let request_id = behaviour.tx_fetch.send_request(&peer_id, hashes.clone());
+            behaviour.sent_request(TX_FETCH_LABEL, request_id, &peer_id, &hashes);
+        }
+    }
+
+    fn retry_block_fetches(&mut self) {
+        let behaviour = self.swarm.behaviour_mut();
+        for (hash, peer_id) in behaviour.block_fetcher.timed_out() {
+            behaviour.block_fetch_failed(peer_id, hash);
+        }
+    }
+
+    // Peers that let a sync request time out are penalized, and the request
+    // goes to someone else on the next drive
+    fn expire_header_sync(&mut self) {
+        let behaviour = self.swarm.behaviour_mut();
+        let mut expired = match behaviour.header_sync.as_mut() {
+            Some(sync) => sync.expire(),
+            None => return,
+        };
+        if let FastSync::Running(sync, _) = &mut self.fast_sync {
+            expired.extend(sync.expire());
+        }
+        for peer_id in expired {
+            debug!("Sync request to {} timed out", peer_id);
+            behaviour.pending_reports.push((peer_id, Misbehavior::SyncTimeout));
+        }
+    }
+
+    // Sends whatever header and body requests header sync is ready for
+    fn drive_header_sync(&mut self) {
+        let peers = self.sync_peers();
+        let fetch_bodies = matches!(self.fast_sync, FastSync::Done | FastSync::History);
+        let behaviour = self.swarm.behaviour_mut();
+        let (headers, bodies, history) = match behaviour.header_sync.as_mut() {
+            Some(sync) if fetch_bodies => {
+                (sync.next_headers_request(&peers), sync.next_body_requests(&peers), sync.in_history())
+            }
+            Some(sync) => (sync.next_headers_request(&peers), Vec::new(), false),
+            None => return,
+        };
+        if let Some((peer_id, request)) = headers {
+            let request_id = behaviour.headers.send_request(&peer_id, request);
+            behaviour.sent_request(HEADERS_LABEL, request_id, &peer_id, &request);
+        }
+        for (peer_id, hashes) in bodies {
+            if history {
+                let request_id = behaviour.history.send_request(&peer_id, hashes.clone());
+                behaviour.sent_request(HISTORY_LABEL, request_id, &peer_id, &hashes);
+            } else {
+                let request_id = behaviour.bodies.send_request(&peer_id, hashes.clone());
+                behaviour.sent_request(BODIES_LABEL, request_id, &peer_id, &hashes);
+            }
+        }
+    }
+
+    async fn serve_state(&mut self) {
+        let requests = std::mem::take(&mut self.swarm.behaviour_mut().pending_state_requests);
+        for (peer_id, hashes, channel) in requests {
+            let chunk = match &self.state_db {
+                Some(db) => {
+                    let trie = StateTrie::new(Arc::clone(db));
+                    let hashes = &hashes[..hashes.len().min(MAX_STATE_REQUEST_HASHES)];
+                    export_chunk(&trie, hashes, self.config.state_sync.nodes_per_chunk).await.unwrap_or_else(|e| {
+                        warn!("Failed to export state for {}: {}", peer_id, e);
+                        Vec::new()
+                    })
+                }
+                None => Vec::new(),
+            };
+            let behaviour = self.swarm.behaviour_mut();
+            behaviour.sent(&peer_id, &chunk);
+            let _ = behaviour.state.send_response(channel, chunk);
+        }
+    }
+
+    async fn drive_fast_sync(&mut self) {
+        let chunks = std::mem::take(&mut self.swarm.behaviour_mut().pending_state_chunks);
+        if let FastSync::Running(sync, _) = &mut self.fast_sync {
+            let mut reports = Vec::new();
+            for (peer_id, chunk) in chunks {
+                let chunk = match chunk {
+                    Some(chunk) => chunk,
+                    None => {
+                        sync.cancel(&peer_id);
+                        continue;
+                    }
+                };
+                match sync.on_chunk(peer_id, chunk).await {
+                    Ok(imported) => debug!("Imported {} state nodes from {}", imported, peer_id),
+                    Err(SnapshotError::UnexpectedNode(_)) => reports.push(peer_id),
+                    Err(e) => warn!("Failed to import state from {}: {}", peer_id, e),
+                }
+            }
+            for peer_id in reports {
+                self.report_peer(&peer_id, Misbehavior::InvalidBlock).await;
+            }
+        }
+
+        if let FastSync::Pending { required } = self.fast_sync {
+            self.pick_pivot(required).await;
+            return;
+        }
+        if matches!(self.fast_sync, FastSync::History) {
+            self.finish_history().await;
+            return;
+        }
+        let peers = self.sync_peers();
+        let requests = match &mut self.fast_sync {
+            FastSync::Running(sync, _) if !sync.is_complete() => sync.next_requests(&peers),
+            FastSync::Running(..) => {
+                if let FastSync::Running(sync, header) = std::mem::replace(&mut self.fast_sync, FastSync::Done) {
+                    let (height, (nodes, _)) = (sync.pivot().height, sync.progress());
+                    info!("Fast sync downloaded the state at height {} ({} nodes)", height, nodes);
+                    let event = OmniTensorEvent::StateSynced(height, header);
+                    if let Err(e) = self.swarm.behaviour().response_sender.send(event) {
+                        error!("Error sending synced state via channel: {:?}", e);
+                    }
+                }
+                self.drive_header_sync();
+                return;
+            }
+            _ => return,
+        };
+        let behaviour = self.swarm.behaviour_mut();
+        for (peer_id, hashes) in requests {
+            let request_id = behaviour.state.send_request(&peer_id, hashes.clone());
+            behaviour.sent_request(STATE_LABEL, request_id, &peer_id, &hashes);
+        }
+    }
+
+    // Once the headers reach the best peer's head, the pivot is picked
+    // `pivot_distance` below them. Until then, headers too far below to
+    // become the pivot are dropped instead of waiting for their bodies. When
+    // the state is `required`, the pivot is never below the first header.
+    async fn pick_pivot(&mut self, required: bool) {
+        let peers = self.sync_peers();
+        let best = peers.iter().map(|(_, head)| *head).max().unwrap_or(0);
+        let distance = self.config.state_sync.pivot_distance;
+        let window = self.config.header_sync.max_headers_ahead as u64;
+        let sync = match self.swarm.behaviour_mut().header_sync.as_mut() {
+            Some(sync) => sync,
+            None => return,
+        };
+        let (base, tip) = (sync.base().height, sync.tip().height);
+        if best == 0 || tip < best {
+            if tip - base >= window && tip.saturating_sub(distance) > base {
+                sync.skip_to(tip - distance);
+            }
+            return;
+        }
+        let mut height = tip.saturating_sub(distance);
+        if height <= base {
+            if !required {
+                debug!("Within {} blocks of the best header, syncing without fast sync", distance);
+                self.fast_sync = FastSync::Done;
+                return;
+            }
+            height = base + 1;
+        }
+
+        let header = match sync.header_at(height) {
+            Some(header) => header.clone(),
+            None => return,
+        };
+        let pivot = match sync.skip_to(height) {
+            Some(pivot) => pivot,
+            None => return,
+        };
+        let db = match &self.state_db {
+            Some(db) => Arc::clone(db),
+            None => return,
+        };
+        info!("Fast syncing the state at height {}", pivot.height);
+        self.fast_sync = match StateSync::new(self.config.state_sync.clone(), db, pivot, header.state_root).await {
+            Ok(sync) => FastSync::Running(sync, header),
+            Err(e) if required => {
+                error!("Failed to start fast sync at height {}: {}", pivot.height, e);
+                FastSync::Pending { required }
+            }
+            Err(e) => {
+                warn!("Failed to start fast sync, executing blocks from height {} instead: {}", pivot.height, e);
+                FastSync::Done
+            }
+        };
+    }
+
+    // Once history reaches the checkpoint, its state is fast synced
+    async fn finish_history(&mut self) {
+        let history_end = self.swarm.behaviour_mut().history_end.take();
+        let (header, checkpoint, db) = match (history_end, self.checkpoint, &self.state_db) {
+            (Some(header), Some(checkpoint), Some(db)) => (header, checkpoint, Arc::clone(db)),
+            _ => return,
+        };
+        info!("History imported up to the checkpoint at height {}, fast syncing its state", checkpoint.height);
+        let config = self.config.state_sync.clone();
+        self.fast_sync = match StateSync::new(config, db, checkpoint, header.state_root).await {
+            Ok(sync) => FastSync::Running(sync, header),
+            Err(e) => {
+                error!("Failed to start fast sync at the checkpoint: {}", e);
+                self.swarm.behaviour_mut().history_end = Some(header);
+                FastSync::History
+            }
+        };
+    }
+
+    // Reserves a slot on every configured relay so peers can reach us through it
+    fn listen_via_relays(&mut self) {
+        for relay in &self.config.nat.relays {
+            match format!("{}/p2p-circuit", relay).parse::<Multiaddr>() {
+                Ok(address) => {
+                    if let Err(e) = self.swarm.listen_on(address) {
+                        warn!("Failed to listen via relay {}: {:?}", relay, e);
+                    }
+                }
+                Err(e) => warn!("Ignoring invalid relay address {}: {}", relay, e),
+            }
+        }
+    }
+
+    // Entry point for sync and block validation to penalize a peer
+    pub async fn report_peer(&mut self, peer_id: &PeerId, misbehavior: Misbehavior) {
+        match self.peer_manager.report(&peer_id.to_base58(), misbehavior).await {
+            Ok(Some(_)) => self.disconnect(*peer_id, GoodbyeReason::Banned),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to record misbehaviour of {}: {}", peer_id, e),
+        }
+    }
+
+    pub fn peer_manager(&self) -> &Arc<PeerManager> {
+        &self.peer_manager
+    }
+
+    // (inbound, outbound) peers currently holding a slot
+    pub fn connection_counts(&self) -> (usize, usize) {
+        self.connections.counts()
+    }
+
+    // Applies peer and request limits reloaded at runtime. Every peer's
+    // request buckets start full again.
+    pub fn set_limits(&mut self, connections: ConnectionLimitsConfig, request_limits: RequestLimitsConfig) {
+        self.connections.set_config(connections.clone());
+        self.swarm.behaviour_mut().request_limits = request_limiters(&request_limits);
+        self.config.connections = connections;
+        self.config.request_limits = request_limits;
+    }
+
+    // Seeds are dialed at startup and afterwards only while we are short of peers
+    fn dial_seed_addresses(&mut self, addresses: Vec<Multiaddr>) {
+        let connected = self.swarm.connected_peers().count();
+        if self.seeds_dialed && connected >= self.config.gossip.mesh_n_low {
+            return;
+        }
+        self.seeds_dialed = true;
+        for address in addresses {
+            if let Err(e) = self.swarm.dial(address.clone()) {
+                debug!("Failed to dial seed address {}: {:?}", address, e);
+            }
+        }
+    }
+
+    // Bootstrap peers first, then the most reliable peers from previous runs
+    async fn dial_known_peers(&mut self) {
+        if let Err(e) = self.peer_store.prune(PEER_RETENTION).await {
+            warn!("Failed to prune peer store: {}", e);
+        }
+        let mut addresses: Vec<String> = self.config.bootstrap_peers.clone();
+        match self.peer_store.best(STARTUP_DIALS).await {
+            Ok(peers) => addresses.extend(peers.into_iter().filter_map(|(_, record)| record.addresses.into_iter().next())),
+            Err(e) => warn!("Failed to load stored peers: {}", e),
+        }
+
+        for address in addresses {
+            match address.parse::<Multiaddr>() {
+                Ok(multiaddr) => {
+                    if let Err(e) = self.swarm.dial(multiaddr) {
+                        debug!("Failed to dial {}: {:?}", address, e);
+                    }
+                }
+                Err(e) => warn!("Ignoring invalid peer address {}: {}", address, e),
+            }
+        }
+    }
+
+    pub fn publish(&mut self, name: &str, message: Vec<u8>) {
+        let max_len = self.config.topics.get(name).max_message_bytes;
+        if message.len() > max_len {
+            warn!("Not publishing {} byte message to {}, limit is {}", message.len(), name, max_len);
+            return;
+        }
+        let behaviour = self.swarm.behaviour_mut();
+        let message = match behaviour.compressor.encode(PROTOCOL_VERSION, message) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to compress message for {}: {}", name, e);
+                return;
+            }
+        };
+        if let Err(e) = behaviour.gossipsub.publish(topic(name), message) {
+            warn!("Failed to publish to {}: {:?}", name, e);
+        }
+    }
+
+    // Shared with sync so request/response payloads are compressed the same way
+    pub fn compressor(&self) -> &Arc<MessageCompressor> {
+        &self.swarm.behaviour().compressor
+    }
+
+    // Version to encode direct payloads for; peers that haven't completed the
+    // handshake yet are assumed to speak the original raw format
+    pub fn protocol_version(&self, peer_id: &PeerId) -> u32 {
+        self.peer_status(peer_id).map_or(1, |status| status.protocol_version)
+    }
+
+    // Handshaken peers to sync from: trusted peers first, then by head height
+    pub fn sync_peers(&self) -> Vec<(PeerId, u64)> {
+        let mut peers: Vec<(PeerId, u64)> = self
+            .swarm
+            .behaviour()
+            .peer_status
+            .iter()
+            .map(|(peer_id, status)| (*peer_id, status.head_height))
+            .collect();
+        peers.sort_by_key(|(peer_id, head)| (!self.trusted.contains(peer_id), std::cmp::Reverse(*head)));
+        peers
+    }
+
+    pub fn peer_status(&self, peer_id: &PeerId) -> Option<&Status> {
+        self.swarm.behaviour().peer_status.get(peer_id)
+    }
+
+    // Keeps the head height we announce in handshakes current
+    pub fn set_head_height(&mut self, height: u64) {
+        self.swarm.behaviour_mut().local_status.head_height = height;
+    }
+
+    // Serves transaction bodies to peers fetching hashes we announced
+    pub fn set_tx_lookup(&mut self, lookup: TxLookup) {
+        self.swarm.behaviour_mut().tx_lookup = Some(lookup);
+    }
+
+    // Serves block bodies to peers fetching a block we announced or forwarded
+    pub fn set_block_lookup(&mut self, lookup: BlockLookup) {
+        self.swarm.behaviour_mut().block_lookup = Some(lookup);
+    }
+
+    // Serves our header chain to peers doing header sync
+    pub fn set_header_lookup(&mut self, lookup: HeaderLookup) {
+        self.swarm.behaviour_mut().header_lookup = Some(lookup);
+    }
+
+    // Serves receipts, along with blocks from the block lookup, to peers
+    // syncing history without executing it
+    pub fn set_receipts_lookup(&mut self, lookup: ReceiptsLookup) {
+        self.swarm.behaviour_mut().receipts_lookup = Some(lookup);
+    }
+
+    // Starts, or restarts, header sync from our head, or from the trusted
+    // checkpoint if that is higher. Synced blocks arrive as
+    // `OmniTensorEvent::SyncedBlocks`; call again with the actual head if
+    // importing them fails or the head moves otherwise, e.g. by gossip.
+    pub fn start_header_sync(&mut self, head: ChainHead) {
+        let config = self.config.header_sync.clone();
+        let behaviour = self.swarm.behaviour_mut();
+        let (base, fast_sync) = match self.checkpoint {
+            Some(checkpoint)
+                if checkpoint.height > head.height && self.state_db.is_some() && config.archive_history =>
+            {
+                info!("Syncing history up to the checkpoint at height {}", checkpoint.height);
+                let sync = HeaderSync::new(config, head).with_history(checkpoint).with_clock(self.config.clock.clone());
+                behaviour.header_sync = Some(sync);
+                self.fast_sync = FastSync::History;
+                self.drive_header_sync();
+                return;
+            }
+            Some(checkpoint) if checkpoint.height > head.height && self.state_db.is_some() => {
+                info!("Syncing from the checkpoint at height {}", checkpoint.height);
+                (checkpoint, FastSync::Pending { required: true })
+            }
+            Some(checkpoint) if checkpoint.height > head.height => {
+                warn!("No state database to fast sync from the checkpoint, syncing from height {}", head.height);
+                (head, FastSync::Done)
+            }
+            checkpoint => {
+                // Already past it: all we can do is flag a chain that doesn't match
+                if let (Some(checkpoint), Some(lookup)) = (checkpoint, &behaviour.header_lookup) {
+                    if lookup(checkpoint.height).map_or(false, |header| header_hash(&header) != checkpoint.hash) {
+                        error!("Our chain doesn't pass through the checkpoint at height {}", checkpoint.height);
+                    }
+                }
+                let fast = self.config.state_sync.enabled && self.state_db.is_some();
+                (head, if fast { FastSync::Pending { required: false } } else { FastSync::Done })
+            }
+        };
+        behaviour.header_sync = Some(HeaderSync::new(config, base).with_clock(self.config.clock.clone()));
+        self.fast_sync = fast_sync;
+        self.drive_header_sync();
+    }
+
+    // Serves our state to peers fast syncing, and lets header sync fast sync
+    // into this database when far behind
+    pub fn set_state_database(&mut self, db: Arc<Database>) {
+        self.state_db = Some(db);
+    }
+
+    // Announces a new block by hash; peers that lack it fetch the body through
+    // the block lookup, so it must be able to return the block
+    pub fn announce_block(&mut self, block: &Block, height: u64) {
+        let announcement = BlockAnnouncement::new(block, height);
+        self.swarm.behaviour_mut().block_fetcher.mark_seen(announcement.hash);
+        match bincode::serialize(&announcement) {
+            Ok(message) => self.publish(BLOCKS_TOPIC, message),
+            Err(e) => warn!("Failed to encode block announcement: {}", e),
+        }
+    }
+
+    // Publishes a header signed by the validators for light clients
+    pub fn publish_signed_header(&mut self, signed: &SignedHeader) {
+        match bincode::serialize(signed) {
+            Ok(message) => self.publish(LIGHT_CLIENT_TOPIC, message),
+            Err(e) => warn!("Failed to encode signed header: {}", e),
+        }
+    }
+
+    // Publishes a chunk of a job this node is the provider for
+    pub fn publish_output_chunk(&mut self, signed: &SignedOutputChunk) {
+        match bincode::serialize(signed) {
+            Ok(message) => self.publish(INFERENCE_OUTPUT_TOPIC, message),
+            Err(e) => warn!("Failed to encode output chunk: {}", e),
+        }
+    }
+
+    // Announces new transactions by hash; peers fetch the bodies they lack
+    pub fn announce_transactions(&mut self, transactions: &[Transaction]) {
+        let mut hashes = Vec::with_capacity(transactions.len());
+        for transaction in transactions {
+            match transaction.hash() {
+                Ok(hash) => hashes.push(hash),
+                Err(e) => warn!("Not announcing unhashable transaction: {:?}", e),
+            }
+        }
+        let max_hashes = self.swarm.behaviour().tx_fetcher.max_announcement_hashes();
+        for chunk in hashes.chunks(max_hashes.max(1)) {
+            for hash in chunk {
+                self.swarm.behaviour_mut().tx_fetcher.mark_seen(hash.clone());
+            }
+            match bincode::serialize(&TxAnnouncement { hashes: chunk.to_vec() }) {
+                Ok(message) => self.publish(TRANSACTIONS_TOPIC, message),
+                Err(e) => warn!("Failed to encode transaction announcement: {}", e),
+            }
+        }
+    }
+
+    pub fn peer_score(&self, peer_id: &PeerId) -> Option<f64> {
+        self.swarm.behaviour().gossipsub.peer_score(peer_id)
+    }
+}
@@ -0,0 +1,376 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::network::goodbye::GoodbyeReason;
+use crate::storage::db::{Database, Result};
+use crate::storage::ttl::{ExpiringStore, Namespace};
+use crate::utils::metrics::{LatencyHistogram, Metric, MetricsSource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Misbehavior {
+    InvalidBlock,
+    MalformedMessage,
+    SyncTimeout,
+    // Gossip or requests beyond the per-peer rate limits
+    RateLimited,
+}
+
+impl Misbehavior {
+    fn penalty(&self) -> f64 {
+        match self {
+            Misbehavior::InvalidBlock => 50.0,
+            Misbehavior::MalformedMessage => 10.0,
+            Misbehavior::SyncTimeout => 5.0,
+            Misbehavior::RateLimited => 1.0,
+        }
+    }
+}
+
+// `[network.peer_scoring]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerScoringConfig {
+    // Peers whose score drops to this are disconnected and banned
+    pub ban_threshold: f64,
+    // Score a peer wins back per minute of good behaviour, up to zero
+    pub recovery_per_minute: f64,
+    // First ban lasts this long and doubles on every repeat offense...
+    pub base_ban_secs: u64,
+    // ...up to this
+    pub max_ban_secs: u64,
+    // How long past offenses still count towards escalation
+    pub offense_memory_secs: u64,
+}
+
+impl Default for PeerScoringConfig {
+    fn default() -> Self {
+        Self {
+            ban_threshold: -100.0,
+            recovery_per_minute: 1.0,
+            base_ban_secs: 600,
+            max_ban_secs: 7 * 24 * 3600,
+            offense_memory_secs: 30 * 24 * 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanRecord {
+    pub reason: Misbehavior,
+    // Number of bans so far, including this one
+    pub offenses: u32,
+    // Unix seconds
+    pub banned_until: u64,
+}
+
+impl BanRecord {
+    pub fn is_active(&self) -> bool {
+        self.banned_until > now_secs()
+    }
+}
+
+// Admin view of one connected peer's traffic
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PeerTraffic {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    // Messages received per protocol or gossip topic
+    pub messages: BTreeMap<String, u64>,
+    pub requests: u64,
+    pub latency_mean: Duration,
+    pub latency_p99: Duration,
+}
+
+#[derive(Default)]
+struct Traffic {
+    bytes_in: u64,
+    bytes_out: u64,
+    messages: BTreeMap<String, u64>,
+    latency: LatencyHistogram,
+}
+
+struct PeerScore {
+    score: f64,
+    updated: Instant,
+}
+
+// Tracks misbehaviour per peer. Scores live in memory and recover over time;
+// bans are persisted so a restart doesn't hand a banned peer a clean slate.
+pub struct PeerManager {
+    config: PeerScoringConfig,
+    scores: RwLock<HashMap<String, PeerScore>>,
+    bans: ExpiringStore,
+    traffic: RwLock<HashMap<String, Traffic>>,
+    goodbyes: RwLock<Goodbyes>,
+    // Operator-configured peers that are never banned for their score
+    trusted: RwLock<HashSet<String>>,
+}
+
+#[derive(Default)]
+struct Goodbyes {
+    // (reason label, "sent" or "received")
+    counts: HashMap<(&'static str, &'static str), u64>,
+    last_received: HashMap<String, GoodbyeReason>,
+}
+
+impl PeerManager {
+    pub fn new(db: Arc<Database>, config: PeerScoringConfig) -> Self {
+        Self {
+            config,
+            scores: RwLock::new(HashMap::new()),
+            bans: ExpiringStore::new(db, Namespace::PeerBan),
+            traffic: RwLock::new(HashMap::new()),
+            goodbyes: RwLock::new(Goodbyes::default()),
+            trusted: RwLock::new(HashSet::new()),
+        }
+    }
+
+    pub fn score(&self, peer_id: &str) -> f64 {
+        let mut scores = self.scores.write().unwrap();
+        match scores.get_mut(peer_id) {
+            Some(entry) => self.recover(entry),
+            None => 0.0,
+        }
+    }
+
+    // Penalizes the peer and bans it once its score crosses the threshold. The
+    // caller disconnects the peer when a ban is returned.
+    pub async fn report(&self, peer_id: &str, misbehavior: Misbehavior) -> Result<Option<BanRecord>> {
+        let crossed = {
+            let mut scores = self.scores.write().unwrap();
+            let entry = scores
+                .entry(peer_id.to_string())
+                .or_insert(PeerScore { score: 0.0, updated: Instant::now() });
+            let score = self.recover(entry) - misbehavior.penalty();
+            entry.score = score;
+            if score <= self.config.ban_threshold {
+                scores.remove(peer_id);
+                true
+            } else {
+                false
+            }
+        };
+        if !crossed || self.is_trusted(peer_id) {
+            return Ok(None);
+        }
+        self.ban(peer_id, misbehavior).await.map(Some)
+    }
+
+    pub fn trust(&self, peer_id: &str) {
+        self.trusted.write().unwrap().insert(peer_id.to_string());
+    }
+
+    pub fn is_trusted(&self, peer_id: &str) -> bool {
+        self.trusted.read().unwrap().contains(peer_id)
+    }
+
+    pub async fn ban(&self, peer_id: &str, reason: Misbehavior) -> Result<BanRecord> {
+        let offenses = self.bans.get::<BanRecord>(peer_id.as_bytes()).await?.map_or(0, |ban| ban.offenses) + 1;
+        let duration = self
+            .config
+            .base_ban_secs
+            .saturating_mul(1u64 << (offenses - 1).min(32))
+            .min(self.config.max_ban_secs);
+        let ban = BanRecord { reason, offenses, banned_until: now_secs() + duration };
+
+        // Kept past the ban itself so repeat offenses escalate
+        let retention = Duration::from_secs(duration.max(self.config.offense_memory_secs));
+        self.bans.put(peer_id.as_bytes(), &ban, retention).await?;
+        warn!("Banned peer {} for {}s ({:?}, offense {})", peer_id, duration, reason, offenses);
+        Ok(ban)
+    }
+
+    pub async fn is_banned(&self, peer_id: &str) -> Result<bool> {
+        Ok(self.bans.get::<BanRecord>(peer_id.as_bytes()).await?.map_or(false, |ban| ban.is_active()))
+    }
+
+    // Admin: currently active bans
+    pub async fn bans(&self) -> Result<Vec<(String, BanRecord)>> {
+        let bans = self.bans.entries::<BanRecord>().await?;
+        Ok(bans
+            .into_iter()
+            .filter(|(_, ban)| ban.is_active())
+            .map(|(peer_id, ban)| (String::from_utf8_lossy(&peer_id).into_owned(), ban))
+            .collect())
+    }
+
+    // Admin: lifts the ban and forgets past offenses
+    pub async fn clear_ban(&self, peer_id: &str) -> Result<()> {
+        self.scores.write().unwrap().remove(peer_id);
+        self.bans.remove(peer_id.as_bytes()).await
+    }
+
+    pub fn record_inbound(&self, peer_id: &str, protocol: &str, bytes: usize) {
+        let mut traffic = self.traffic.write().unwrap();
+        let entry = traffic.entry(peer_id.to_string()).or_default();
+        entry.bytes_in += bytes as u64;
+        *entry.messages.entry(protocol.to_string()).or_default() += 1;
+    }
+
+    pub fn record_outbound(&self, peer_id: &str, bytes: usize) {
+        self.traffic.write().unwrap().entry(peer_id.to_string()).or_default().bytes_out += bytes as u64;
+    }
+
+    // Round trip of a request we sent to the peer
+    pub fn record_latency(&self, peer_id: &str, elapsed: Duration) {
+        self.traffic.write().unwrap().entry(peer_id.to_string()).or_default().latency.record(elapsed);
+    }
+
+    // Only connected peers are tracked
+    pub fn forget_traffic(&self, peer_id: &str) {
+        self.traffic.write().unwrap().remove(peer_id);
+    }
+
+    // Admin: per-peer traffic, heaviest first
+    pub fn traffic(&self) -> Vec<(String, PeerTraffic)> {
+        let traffic = self.traffic.read().unwrap();
+        let mut peers: Vec<(String, PeerTraffic)> = traffic
+            .iter()
+            .map(|(peer_id, traffic)| {
+                let snapshot = PeerTraffic {
+                    bytes_in: traffic.bytes_in,
+                    bytes_out: traffic.bytes_out,
+                    messages: traffic.messages.clone(),
+                    requests: traffic.latency.count(),
+                    latency_mean: traffic.latency.mean(),
+                    latency_p99: traffic.latency.quantile(0.99),
+                };
+                (peer_id.clone(), snapshot)
+            })
+            .collect();
+        peers.sort_by_key(|(_, traffic)| std::cmp::Reverse(traffic.bytes_in + traffic.bytes_out));
+        peers
+    }
+
+    pub fn record_goodbye_sent(&self, reason: GoodbyeReason) {
+        *self.goodbyes.write().unwrap().counts.entry((reason.label(), "sent")).or_default() += 1;
+    }
+
+    pub fn record_goodbye_received(&self, peer_id: &str, reason: GoodbyeReason) {
+        let mut goodbyes = self.goodbyes.write().unwrap();
+        *goodbyes.counts.entry((reason.label(), "received")).or_default() += 1;
+        goodbyes.last_received.insert(peer_id.to_string(), reason);
+    }
+
+    // Admin: why the peer last hung up on us
+    pub fn last_goodbye(&self, peer_id: &str) -> Option<GoodbyeReason> {
+        self.goodbyes.read().unwrap().last_received.get(peer_id).copied()
+    }
+
+    fn recover(&self, entry: &mut PeerScore) -> f64 {
+        let minutes = entry.updated.elapsed().as_secs_f64() / 60.0;
+        entry.score = (entry.score + minutes * self.config.recovery_per_minute).min(0.0);
+        entry.updated = Instant::now();
+        entry.score
+    }
+}
+
+impl MetricsSource for PeerManager {
+    fn collect(&self) -> Vec<Metric> {
+        let traffic = self.traffic();
+        let mut metrics = Vec::new();
+        for (peer_id, peer) in &traffic {
+            metrics.push(
+                Metric::counter("network_peer_bytes_total", "Payload bytes exchanged with the peer", peer.bytes_in as f64)
+                    .with_label("peer", peer_id.as_str())
+                    .with_label("direction", "in"),
+            );
+            metrics.push(
+                Metric::counter("network_peer_bytes_total", "Payload bytes exchanged with the peer", peer.bytes_out as f64)
+                    .with_label("peer", peer_id.as_str())
+                    .with_label("direction", "out"),
+            );
+        }
+        for (peer_id, peer) in &traffic {
+            for (protocol, count) in &peer.messages {
+                metrics.push(
+                    Metric::counter("network_peer_messages_total", "Messages received from the peer", *count as f64)
+                        .with_label("peer", peer_id.as_str())
+                        .with_label("protocol", protocol.as_str()),
+                );
+            }
+        }
+        let goodbyes = self.goodbyes.read().unwrap();
+        let mut counts: Vec<_> = goodbyes.counts.iter().collect();
+        counts.sort();
+        for ((reason, direction), count) in counts {
+            metrics.push(
+                Metric::counter("network_goodbyes_total", "Disconnects announced with a reason code", *count as f64)
+                    .with_label("reason", *reason)
+                    .with_label("direction", *direction),
+            );
+        }
+        for (peer_id, peer) in traffic.iter().filter(|(_, peer)| peer.requests > 0) {
+            metrics.push(
+                Metric::gauge("network_peer_latency_p99_seconds", "99th percentile request latency", peer.latency_p99.as_secs_f64())
+                    .with_label("peer", peer_id.as_str()),
+            );
+        }
+        metrics
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_bans_escalate_on_repeat_offenses() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let manager = PeerManager::new(Arc::new(Database::new(temp_dir.path())?), PeerScoringConfig::default());
+
+        assert_eq!(manager.report("peer", Misbehavior::MalformedMessage).await?, None);
+        assert_eq!(manager.score("peer").round(), -10.0);
+        assert_eq!(manager.report("peer", Misbehavior::InvalidBlock).await?, None);
+        let first = manager.report("peer", Misbehavior::InvalidBlock).await?.unwrap();
+        assert_eq!(first.offenses, 1);
+        assert!(manager.is_banned("peer").await?);
+        assert_eq!(manager.score("peer"), 0.0);
+
+        let second = manager.ban("peer", Misbehavior::SyncTimeout).await?;
+        assert_eq!(second.offenses, 2);
+        assert!(second.banned_until - now_secs() > 600);
+
+        assert_eq!(manager.bans().await?.len(), 1);
+        manager.clear_ban("peer").await?;
+        assert!(!manager.is_banned("peer").await?);
+        assert!(manager.bans().await?.is_empty());
+
+        manager.trust("sentry");
+        for _ in 0..3 {
+            assert_eq!(manager.report("sentry", Misbehavior::InvalidBlock).await?, None);
+        }
+        assert!(!manager.is_banned("sentry").await?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracks_traffic_per_peer() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = PeerManager::new(Arc::new(Database::new(temp_dir.path()).unwrap()), PeerScoringConfig::default());
+
+        manager.record_inbound("light", "omnitensor-heartbeats/2", 10);
+        manager.record_inbound("heavy", "omnitensor-blocks/2", 5000);
+        manager.record_inbound("heavy", "omnitensor-blocks/2", 5000);
+        manager.record_outbound("heavy", 100);
+        manager.record_latency("heavy", Duration::from_millis(20));
+
+        let traffic = manager.traffic();
+        assert_eq!(traffic[0].0, "heavy");
+        assert_eq!(traffic[0].1.bytes_in, 10_000);
+        assert_eq!(traffic[0].1.messages["omnitensor-blocks/2"], 2);
+        assert_eq!(traffic[0].1.requests, 1);
+        assert_eq!(manager.collect().iter().filter(|metric| metric.name == "network_peer_bytes_total").count(), 4);
+
+        manager.forget_traffic("heavy");
+        assert_eq!(manager.traffic().len(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::Result;
+
+const PEER_PREFIX: &[u8] = b"peer/";
+// Most recently used addresses kept per peer
+const MAX_ADDRESSES: usize = 8;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PeerRecord {
+    // Multiaddrs, most recently successful first
+    pub addresses: Vec<String>,
+    // Unix seconds of the last successful connection
+    pub last_seen: u64,
+    pub successes: u32,
+    pub failures: u32,
+}
+
+impl PeerRecord {
+    // Fraction of dials that succeeded, smoothed so a single success isn't a perfect score
+    pub fn success_rate(&self) -> f64 {
+        self.successes as f64 / (self.successes + self.failures + 1) as f64
+    }
+}
+
+// Remembers peers we have connected to so a restarted node can redial known
+// good peers instead of rediscovering the network from the bootstrap list.
+pub struct PeerStore {
+    backend: Arc<dyn StorageBackend>,
+}
+
+impl PeerStore {
+    pub fn new(backend: Arc<dyn StorageBackend>) -> Self {
+        Self { backend }
+    }
+
+    pub async fn get(&self, peer_id: &str) -> Result<Option<PeerRecord>> {
+        match self.backend.get_raw(None, &peer_key(peer_id)).await? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn record_connected(&self, peer_id: &str, address: &str) -> Result<()> {
+        let mut record = self.get(peer_id).await?.unwrap_or_default();
+        record.addresses.retain(|known| known != address);
+        record.addresses.insert(0, address.to_string());
+        record.addresses.truncate(MAX_ADDRESSES);
+        record.last_seen = now_secs();
+        record.successes = record.successes.saturating_add(1);
+        self.put(peer_id, &record).await
+    }
+
+    pub async fn record_failure(&self, peer_id: &str) -> Result<()> {
+        if let Some(mut record) = self.get(peer_id).await? {
+            record.failures = record.failures.saturating_add(1);
+            self.put(peer_id, &record).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn all(&self) -> Result<Vec<(String, PeerRecord)>> {
+        let mut peers = Vec::new();
+        for (key, value) in self.backend.iterate_prefix(None, PEER_PREFIX).await? {
+            let peer_id = String::from_utf8_lossy(&key[PEER_PREFIX.len()..]).into_owned();
+            peers.push((peer_id, bincode::deserialize(&value)?));
+        }
+        Ok(peers)
+    }
+
+    // Peers to dial on startup, most reliable and most recently seen first
+    pub async fn best(&self, limit: usize) -> Result<Vec<(String, PeerRecord)>> {
+        let mut peers = self.all().await?;
+        peers.sort_by(|(_, a), (_, b)| {
+            b.success_rate()
+                .partial_cmp(&a.success_rate())
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(b.last_seen.cmp(&a.last_seen))
+        });
+        peers.truncate(limit);
+        Ok(peers)
+    }
+
+    // Forgets peers not seen within `max_age`; returns how many were removed
+    pub async fn prune(&self, max_age: Duration) -> Result<usize> {
+        let cutoff = now_secs().saturating_sub(max_age.as_secs());
+        let mut removed = 0;
+        for (peer_id, record) in self.all().await? {
+            if record.last_seen < cutoff {
+                self.backend.delete_raw(None, &peer_key(&peer_id)).await?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn put(&self, peer_id: &str, record: &PeerRecord) -> Result<()> {
+        self.backend.put_raw(None, &peer_key(peer_id), &bincode::serialize(record)?).await
+    }
+}
+
+fn peer_key(peer_id: &str) -> Vec<u8> {
+    [PEER_PREFIX, peer_id.as_bytes()].concat()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryBackend;
+
+    #[tokio::test]
+    async fn test_ranks_reliable_peers_first() -> Result<()> {
+        let store = PeerStore::new(Arc::new(MemoryBackend::new()));
+        store.record_connected("flaky", "/ip4/10.0.0.1/tcp/3030").await?;
+        for _ in 0..3 {
+            store.record_failure("flaky").await?;
+        }
+        store.record_connected("solid", "/ip4/10.0.0.2/tcp/3030").await?;
+        store.record_connected("solid", "/ip4/10.0.0.3/tcp/3030").await?;
+        // Unknown peers are not recorded on failure
+        store.record_failure("stranger").await?;
+
+        let best = store.best(10).await?;
+        assert_eq!(best.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), vec!["solid", "flaky"]);
+        assert_eq!(best[0].1.addresses[0], "/ip4/10.0.0.3/tcp/3030");
+        assert_eq!(best[0].1.successes, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prunes_stale_peers() -> Result<()> {
+        let store = PeerStore::new(Arc::new(MemoryBackend::new()));
+        store.record_connected("fresh", "/ip4/10.0.0.1/tcp/3030").await?;
+        store.put("stale", &PeerRecord { last_seen: 1, ..PeerRecord::default() }).await?;
+
+        assert_eq!(store.prune(Duration::from_secs(3600)).await?, 1);
+        assert!(store.get("stale").await?.is_none());
+        assert!(store.get("fresh").await?.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use libp2p::pnet::PreSharedKey;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+
+const KEY_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum PskError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Network key must be {KEY_LEN} hex-encoded bytes or a swarm.key file")]
+    InvalidKey,
+}
+
+// `[network.private]`. Nodes only connect to nodes holding the same key; the
+// key encrypts every TCP connection before any libp2p handshake, so a private
+// network is invisible to the public one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrivateNetworkConfig {
+    // 64 hex characters
+    pub key: Option<String>,
+    // Either the bare hex key or the go-libp2p swarm.key format
+    pub key_file: Option<PathBuf>,
+}
+
+impl PrivateNetworkConfig {
+    pub fn load(&self) -> Result<Option<PreSharedKey>, PskError> {
+        if let Some(path) = &self.key_file {
+            return parse_key(&std::fs::read_to_string(path)?).map(Some);
+        }
+        self.key.as_deref().map(parse_key).transpose()
+    }
+}
+
+fn parse_key(text: &str) -> Result<PreSharedKey, PskError> {
+    let text = text.trim();
+    if text.len() == KEY_LEN * 2 {
+        let mut key = [0u8; KEY_LEN];
+        for (byte, pair) in key.iter_mut().zip(text.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| PskError::InvalidKey)?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| PskError::InvalidKey)?;
+        }
+        return Ok(PreSharedKey::new(key));
+    }
+    PreSharedKey::from_str(text).map_err(|_| PskError::InvalidKey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_hex_and_swarm_key_formats() {
+        let hex = "0123456789abcdef".repeat(4);
+        let bare = parse_key(&hex).unwrap();
+        let swarm_key = parse_key(&format!("/key/swarm/psk/1.0.0/\n/base16/\n{}\n", hex)).unwrap();
+        assert_eq!(bare.fingerprint().to_string(), swarm_key.fingerprint().to_string());
+
+        assert!(parse_key("not a key").is_err());
+        assert!(parse_key(&"zz".repeat(32)).is_err());
+        assert!(PrivateNetworkConfig::default().load().unwrap().is_none());
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+// Buckets untouched for this long are full again and can be forgotten
+const IDLE_EVICTION: Duration = Duration::from_secs(300);
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// `[network.request_limits.<protocol>]`, per peer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestLimit {
+    pub requests_per_sec: f64,
+    pub burst: f64,
+}
+
+impl RequestLimit {
+    fn new(requests_per_sec: f64, burst: f64) -> Self {
+        Self { requests_per_sec, burst }
+    }
+
+    pub fn limiter<K: Hash + Eq>(&self) -> RateLimiter<K> {
+        RateLimiter::new(self.requests_per_sec, self.burst)
+    }
+}
+
+impl Default for RequestLimit {
+    fn default() -> Self {
+        Self::new(10.0, 20.0)
+    }
+}
+
+// `[network.request_limits]`. Requests beyond a limit are dropped unanswered
+// and count against the sender's score.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestLimitsConfig {
+    pub handshake: RequestLimit,
+    pub tx_fetch: RequestLimit,
+    pub block_fetch: RequestLimit,
+    pub goodbye: RequestLimit,
+    pub headers: RequestLimit,
+    pub bodies: RequestLimit,
+    pub history: RequestLimit,
+    pub state: RequestLimit,
+}
+
+impl Default for RequestLimitsConfig {
+    fn default() -> Self {
+        Self {
+            // Once per connection, reconnects included
+            handshake: RequestLimit::new(0.2, 3.0),
+            tx_fetch: RequestLimit::new(20.0, 50.0),
+            block_fetch: RequestLimit::new(5.0, 20.0),
+            goodbye: RequestLimit::new(1.0, 3.0),
+            // Enough for a peer syncing from us at full speed
+            headers: RequestLimit::new(10.0, 30.0),
+            bodies: RequestLimit::new(20.0, 50.0),
+            history: RequestLimit::new(20.0, 50.0),
+            state: RequestLimit::new(10.0, 30.0),
+        }
+    }
+}
+
+// Token bucket per key: `rate` tokens per second, holding at most `burst`
+pub struct RateLimiter<K> {
+    rate: f64,
+    burst: f64,
+    buckets: HashMap<K, TokenBucket>,
+    last_eviction: Instant,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self { rate, burst, buckets: HashMap::new(), last_eviction: Instant::now() }
+    }
+
+    // Takes a token for `key`; false means the caller is over its limit
+    pub fn allow(&mut self, key: K) -> bool {
+        self.allow_at(key, Instant::now())
+    }
+
+    fn allow_at(&mut self, key: K, now: Instant) -> bool {
+        if now.duration_since(self.last_eviction) > IDLE_EVICTION {
+            self.buckets.retain(|_, bucket| now.duration_since(bucket.updated) < IDLE_EVICTION);
+            self.last_eviction = now;
+        }
+
+        let (rate, burst) = (self.rate, self.burst);
+        let bucket = self.buckets.entry(key).or_insert(TokenBucket { tokens: burst, updated: now });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_each_key_independently() {
+        let mut limiter = RateLimiter::new(2.0, 3.0);
+        let start = Instant::now();
+        assert_eq!((0..5).filter(|_| limiter.allow_at("flood", start)).count(), 3);
+        assert!(limiter.allow_at("quiet", start));
+
+        // Half a second refills one token
+        assert!(limiter.allow_at("flood", start + Duration::from_millis(500)));
+        assert!(!limiter.allow_at("flood", start + Duration::from_millis(500)));
+    }
+}
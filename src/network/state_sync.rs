@@ -0,0 +1,148 @@
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::chain::store::ChainHead;
+use crate::network::codec::{BincodeCodec, Protocol};
+use crate::storage::db::Database;
+use crate::storage::snapshot::{Result, SnapshotImporter, MAX_CHUNK_BYTES};
+use crate::storage::trie::TrieHash;
+
+pub const STATE_PROTOCOL: Protocol = Protocol("/omnitensor/state/1");
+// Hashes per request, whatever the peer asks for
+pub const MAX_STATE_REQUEST_HASHES: usize = 64;
+
+// Trie node hashes in, encoded nodes of their subtrees out, see `storage::snapshot`
+pub type StateCodec = BincodeCodec<Vec<TrieHash>, Vec<Vec<u8>>>;
+
+pub fn state_codec() -> StateCodec {
+    BincodeCodec::new(MAX_CHUNK_BYTES + 1024 * 1024)
+}
+
+// `[network.state_sync]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StateSyncConfig {
+    // Fast sync: when far behind, download the state at a recent header
+    // instead of executing every block since our head
+    pub enabled: bool,
+    // The state is taken this many blocks below the best verified header,
+    // deep enough to be final. Nodes closer than this sync normally.
+    pub pivot_distance: u64,
+    // Node hashes per request; the serving peer sends each with as much of
+    // its subtree as fits in its own `nodes_per_chunk`
+    pub hashes_per_request: usize,
+    pub nodes_per_chunk: usize,
+    pub request_timeout_ms: u64,
+}
+
+impl Default for StateSyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pivot_distance: 128,
+            hashes_per_request: 16,
+            nodes_per_chunk: 4096,
+            request_timeout_ms: 10_000,
+        }
+    }
+}
+
+struct InFlight {
+    hashes: Vec<TrieHash>,
+    sent: Instant,
+}
+
+// Downloads the state at `pivot` from every peer that has it, one request
+// per peer at a time
+pub struct StateSync {
+    config: StateSyncConfig,
+    pivot: ChainHead,
+    importer: SnapshotImporter,
+    in_flight: HashMap<PeerId, InFlight>,
+    // Peers that sent an empty chunk, e.g. because they no longer have this
+    // state, and when; asked again after the request timeout
+    exhausted: HashMap<PeerId, Instant>,
+}
+
+impl StateSync {
+    pub async fn new(
+        config: StateSyncConfig,
+        db: Arc<Database>,
+        pivot: ChainHead,
+        state_root: TrieHash,
+    ) -> Result<Self> {
+        let importer = SnapshotImporter::new(db, state_root).await?;
+        Ok(Self { config, pivot, importer, in_flight: HashMap::new(), exhausted: HashMap::new() })
+    }
+
+    pub fn pivot(&self) -> ChainHead {
+        self.pivot
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.importer.is_complete()
+    }
+
+    pub fn progress(&self) -> (u64, usize) {
+        self.importer.progress()
+    }
+
+    // Requests for idle peers whose head is at or past the pivot
+    pub fn next_requests(&mut self, peers: &[(PeerId, u64)]) -> Vec<(PeerId, Vec<TrieHash>)> {
+        let mut requests = Vec::new();
+        let per_request = self.config.hashes_per_request.clamp(1, MAX_STATE_REQUEST_HASHES);
+        let timeout = Duration::from_millis(self.config.request_timeout_ms);
+        self.exhausted.retain(|_, since| since.elapsed() < timeout);
+        for (peer, head) in peers {
+            if *head < self.pivot.height || self.in_flight.contains_key(peer) || self.exhausted.contains_key(peer) {
+                continue;
+            }
+            let hashes = self.importer.next_hashes(per_request);
+            if hashes.is_empty() {
+                break;
+            }
+            self.in_flight.insert(*peer, InFlight { hashes: hashes.clone(), sent: Instant::now() });
+            requests.push((*peer, hashes));
+        }
+        requests
+    }
+
+    // Imports a chunk from `peer`; an invalid one is discarded as a whole
+    pub async fn on_chunk(&mut self, peer: PeerId, chunk: Vec<Vec<u8>>) -> Result<usize> {
+        let hashes = match self.in_flight.remove(&peer) {
+            Some(in_flight) => in_flight.hashes,
+            None => return Ok(0),
+        };
+        if chunk.is_empty() {
+            self.exhausted.insert(peer, Instant::now());
+        }
+        let imported = self.importer.import(&chunk).await;
+        self.importer.release(&hashes);
+        imported
+    }
+
+    // Forgets requests outstanding longer than the timeout and returns the
+    // peers that let them time out
+    pub fn expire(&mut self) -> Vec<PeerId> {
+        let timeout = Duration::from_millis(self.config.request_timeout_ms);
+        let expired: Vec<PeerId> = self
+            .in_flight
+            .iter()
+            .filter(|(_, in_flight)| in_flight.sent.elapsed() >= timeout)
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in &expired {
+            self.cancel(peer);
+        }
+        expired
+    }
+
+    pub fn cancel(&mut self, peer: &PeerId) {
+        if let Some(in_flight) = self.in_flight.remove(peer) {
+            self.importer.release(&in_flight.hashes);
+        }
+    }
+}
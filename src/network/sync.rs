@@ -2,11 +2,13 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use log::{info, warn, error};
 use futures::stream::StreamExt;
+use tracing::instrument;
 
 use crate::types::{Block, BlockHeader, Transaction};
 use crate::network::peer::{Peer, PeerManager};
 use crate::chain::Chain;
 use crate::consensus::ConsensusEngine;
+use crate::error::{Error, Result};
 
 pub struct Synchronizer {
     chain: Arc<RwLock<Chain>>,
@@ -58,6 +60,7 @@ impl Synchronizer {
         }
     }
 
+    #[instrument(name = "sync_round", skip_all, fields(from = start_height, to = end_height))]
     async fn sync_with_peer(&self, peer: Arc<Peer>, start_height: u64, end_height: u64) {
         info!("Syncing with peer from height {} to {}", start_height, end_height);
 
@@ -83,31 +86,31 @@ impl Synchronizer {
         info!("Sync completed successfully");
     }
 
-    async fn fetch_block_range(&self, peer: Arc<Peer>, start: u64, end: u64) -> Result<Vec<Block>, Box<dyn std::error::Error>> {
-        let headers = peer.get_block_headers(start, end).await?;
+    async fn fetch_block_range(&self, peer: Arc<Peer>, start: u64, end: u64) -> Result<Vec<Block>> {
+        let headers = peer.get_block_headers(start, end).await.map_err(Error::network)?;
         let mut blocks = Vec::new();
 
         for header in headers {
-            let transactions = peer.get_block_transactions(header.hash).await?;
+            let transactions = peer.get_block_transactions(header.hash).await.map_err(Error::network)?;
             blocks.push(Block::new(header, transactions));
         }
 
         Ok(blocks)
     }
 
-    async fn process_block(&self, block: Block) -> Result<(), Box<dyn std::error::Error>> {
+    async fn process_block(&self, block: Block) -> Result<()> {
         let mut chain = self.chain.write().await;
 
         // Verify block
-        self.consensus_engine.verify_block(&block, &chain).await?;
+        self.consensus_engine.verify_block(&block, &chain).await.map_err(Error::consensus)?;
 
         // Apply transactions
         for tx in &block.transactions {
-            chain.apply_transaction(tx).await?;
+            chain.apply_transaction(tx).await.map_err(Error::execution)?;
         }
 
         // Add block to chain
-        chain.add_block(block).await?;
+        chain.add_block(block).await.map_err(Error::storage)?;
 
         Ok(())
     }
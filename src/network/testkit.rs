@@ -0,0 +1,370 @@
+#![cfg(any(test, feature = "testkit"))]
+
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::core::transport::memory::Channel;
+use libp2p::core::transport::{Boxed, MemoryTransport};
+use libp2p::core::{muxing::StreamMuxerBox, upgrade, ConnectedPoint};
+use libp2p::identity::{self, ed25519};
+use libp2p::multiaddr::Protocol;
+use libp2p::noise::{Keypair, NoiseConfig, X25519Spec};
+use libp2p::relay::v2::client::Client as RelayClient;
+use libp2p::{mplex, Multiaddr, PeerId, Transport};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Sleep};
+
+use crate::network::p2p::{NetworkConfig, OmniTensorEvent, P2PNetwork, TransportConfig};
+use crate::network::peer::PeerManager;
+use crate::network::peer_store::PeerStore;
+use crate::storage::backend::MemoryBackend;
+use crate::storage::db::Database;
+
+// Memory ports are process-wide, so every SimNet gets its own range and
+// tests can run in parallel
+static NEXT_PORT_BASE: AtomicU64 = AtomicU64::new(1 << 20);
+const PORTS_PER_NET: u64 = 1 << 10;
+const READ_CHUNK: usize = 64 * 1024;
+const SIM_GENESIS: [u8; 32] = [0; 32];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinkConditions {
+    pub latency: Duration,
+    // Up to this much extra delay per chunk of data, drawn uniformly
+    pub jitter: Duration,
+    // Chance per chunk that the connection is reset. Streams are reliable, so
+    // lost data means a lost connection, as with a dropped TCP link.
+    pub drop_rate: f64,
+}
+
+struct State {
+    rng: StdRng,
+    default: LinkConditions,
+    links: HashMap<(usize, usize), LinkConditions>,
+    // Partition group per node; nodes in different groups can't reach each other
+    groups: HashMap<usize, usize>,
+}
+
+// In-process network of `P2PNetwork` nodes over libp2p's memory transport,
+// with per-link latency, jitter, drops and partitions. All randomness comes
+// from the seed, so a failing run can be replayed.
+#[derive(Clone)]
+pub struct SimNet {
+    base: u64,
+    nodes: Arc<AtomicUsize>,
+    state: Arc<Mutex<State>>,
+}
+
+pub struct SimNode {
+    pub index: usize,
+    pub peer_id: PeerId,
+    pub address: Multiaddr,
+    pub network: P2PNetwork,
+    pub events: mpsc::UnboundedReceiver<OmniTensorEvent>,
+    // Holds the node's ban list
+    _data_dir: TempDir,
+}
+
+impl SimNet {
+    pub fn new(seed: u64) -> Self {
+        let state = State {
+            rng: StdRng::seed_from_u64(seed),
+            default: LinkConditions::default(),
+            links: HashMap::new(),
+            groups: HashMap::new(),
+        };
+        Self {
+            base: NEXT_PORT_BASE.fetch_add(PORTS_PER_NET, Ordering::Relaxed),
+            nodes: Arc::new(AtomicUsize::new(0)),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    // Conditions for every link without its own
+    pub fn set_conditions(&self, conditions: LinkConditions) {
+        self.state.lock().unwrap().default = conditions;
+    }
+
+    pub fn set_link(&self, a: usize, b: usize, conditions: LinkConditions) {
+        self.state.lock().unwrap().links.insert(link(a, b), conditions);
+    }
+
+    // Splits the nodes into groups that can't reach each other; nodes not
+    // listed form one more group. Connections across groups are reset the
+    // next time they carry data.
+    pub fn partition(&self, groups: &[&[usize]]) {
+        let mut state = self.state.lock().unwrap();
+        state.groups.clear();
+        for (group, nodes) in groups.iter().enumerate() {
+            state.groups.extend(nodes.iter().map(|node| (*node, group)));
+        }
+    }
+
+    pub fn heal(&self) {
+        self.state.lock().unwrap().groups.clear();
+    }
+
+    pub fn address(&self, index: usize) -> Multiaddr {
+        Multiaddr::empty().with(Protocol::Memory(self.base + index as u64))
+    }
+
+    fn index_of(&self, address: &Multiaddr) -> Option<usize> {
+        let port = address.iter().find_map(|protocol| match protocol {
+            Protocol::Memory(port) => Some(port),
+            _ => None,
+        })?;
+        port.checked_sub(self.base).filter(|index| *index < PORTS_PER_NET).map(|index| index as usize)
+    }
+
+    fn reachable(&self, a: usize, b: usize) -> bool {
+        let state = self.state.lock().unwrap();
+        state.groups.get(&a) == state.groups.get(&b)
+    }
+
+    // Delay for the next chunk from `from` to `to`, or `None` if it is lost
+    fn sample(&self, from: usize, to: usize) -> Option<Duration> {
+        if !self.reachable(from, to) {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap();
+        let conditions = state.links.get(&link(from, to)).copied().unwrap_or(state.default);
+        if state.rng.gen_bool(conditions.drop_rate.clamp(0.0, 1.0)) {
+            return None;
+        }
+        Some(conditions.latency + conditions.jitter.mul_f64(state.rng.gen::<f64>()))
+    }
+
+    // Starts a node listening on the next simulated address. Its identity,
+    // transports and mDNS settings are replaced; everything else in `config`
+    // is used as given.
+    pub async fn spawn(&self, mut config: NetworkConfig) -> Result<SimNode, Box<dyn Error>> {
+        let index = self.nodes.fetch_add(1, Ordering::Relaxed);
+        let address = self.address(index);
+        config.identity_key = None;
+        config.mdns = false;
+        config.transports = TransportConfig {
+            tcp_listen: vec![address.to_string()],
+            quic_enabled: false,
+            websocket_enabled: false,
+            ..TransportConfig::default()
+        };
+
+        let mut secret = [0u8; 32];
+        self.state.lock().unwrap().rng.fill(&mut secret);
+        let secret = ed25519::SecretKey::from_bytes(&mut secret).expect("any 32 bytes are an ed25519 secret");
+        let local_key = identity::Keypair::Ed25519(secret.into());
+        let peer_id = PeerId::from(local_key.public());
+
+        let data_dir = TempDir::new()?;
+        let db = Arc::new(Database::new(data_dir.path())?);
+        let peer_manager = Arc::new(PeerManager::new(db, config.peer_scoring.clone()));
+        let peer_store = PeerStore::new(Arc::new(MemoryBackend::new()));
+        // Relayed dials aren't simulated
+        let (_, relay_client) = RelayClient::new_transport_and_behaviour(peer_id);
+        let transport = self.transport(index, &local_key);
+        let (mut network, events) =
+            P2PNetwork::with_transport(config, SIM_GENESIS, peer_store, peer_manager, local_key, transport, relay_client)
+                .await?;
+        network.listen().await?;
+        Ok(SimNode { index, peer_id, address, network, events, _data_dir: data_dir })
+    }
+
+    fn transport(&self, index: usize, local_key: &identity::Keypair) -> Boxed<(PeerId, StreamMuxerBox)> {
+        let id_keys = Keypair::<X25519Spec>::new()
+            .into_authentic(local_key)
+            .expect("Can create keypair");
+        let net = self.clone();
+        MemoryTransport::default()
+            .and_then(move |socket, endpoint| net.clone().attach(index, socket, endpoint))
+            .upgrade(upgrade::Version::V1)
+            .authenticate(NoiseConfig::xx(id_keys).into_authenticated())
+            .multiplex(mplex::MplexConfig::new())
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed()
+    }
+
+    // Memory addresses don't say who dialed, so the dialer introduces itself
+    // before the link conditions apply
+    async fn attach(
+        self,
+        local: usize,
+        mut socket: Channel<Vec<u8>>,
+        endpoint: ConnectedPoint,
+    ) -> io::Result<SimSocket<Channel<Vec<u8>>>> {
+        let remote = match endpoint {
+            ConnectedPoint::Dialer { address, .. } => {
+                let remote = self
+                    .index_of(&address)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, "not a simulated node"))?;
+                socket.write_all(&(local as u64).to_be_bytes()).await?;
+                socket.flush().await?;
+                remote
+            }
+            ConnectedPoint::Listener { .. } => {
+                let mut index = [0u8; 8];
+                socket.read_exact(&mut index).await?;
+                u64::from_be_bytes(index) as usize
+            }
+        };
+        if !self.reachable(local, remote) {
+            return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "partitioned"));
+        }
+        Ok(SimSocket { inner: socket, net: self, local, remote, queue: VecDeque::new(), timer: None, eof: false })
+    }
+}
+
+// Drives all nodes' event loops for `duration`
+pub async fn run(nodes: &mut [SimNode], duration: Duration) {
+    let loops = futures::future::join_all(nodes.iter_mut().map(|node| async move { while node.network.step().await {} }));
+    let _ = tokio::time::timeout(duration, loops).await;
+}
+
+fn link(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+fn reset(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionReset, reason)
+}
+
+// One end of a simulated connection. Incoming data is held back by the link
+// delay, in order, so each direction is delayed by its receiver.
+pub struct SimSocket<S> {
+    inner: S,
+    net: SimNet,
+    local: usize,
+    remote: usize,
+    // Received chunks and when they are delivered
+    queue: VecDeque<(Instant, Vec<u8>)>,
+    timer: Option<Pin<Box<Sleep>>>,
+    eof: bool,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for SimSocket<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if !this.net.reachable(this.local, this.remote) {
+            return Poll::Ready(Err(reset("partitioned")));
+        }
+        while !this.eof {
+            let mut chunk = vec![0; READ_CHUNK];
+            match Pin::new(&mut this.inner).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(0)) => this.eof = true,
+                Poll::Ready(Ok(n)) => {
+                    chunk.truncate(n);
+                    let delay = match this.net.sample(this.remote, this.local) {
+                        Some(delay) => delay,
+                        None => return Poll::Ready(Err(reset("dropped"))),
+                    };
+                    // Never overtake data received earlier
+                    let earliest = this.queue.back().map_or_else(Instant::now, |(at, _)| *at);
+                    this.queue.push_back(((Instant::now() + delay).max(earliest), chunk));
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            let at = match this.queue.front_mut() {
+                None if this.eof => return Poll::Ready(Ok(0)),
+                None => return Poll::Pending,
+                Some((at, chunk)) if *at <= Instant::now() => {
+                    let n = chunk.len().min(buf.len());
+                    buf[..n].copy_from_slice(&chunk[..n]);
+                    chunk.drain(..n);
+                    if chunk.is_empty() {
+                        this.queue.pop_front();
+                    }
+                    return Poll::Ready(Ok(n));
+                }
+                Some((at, _)) => *at,
+            };
+            let timer = this.timer.get_or_insert_with(|| Box::pin(tokio::time::sleep_until(at)));
+            timer.as_mut().reset(at);
+            if timer.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for SimSocket<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if !self.net.reachable(self.local, self.remote) {
+            return Poll::Ready(Err(reset("partitioned")));
+        }
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::p2p::VOTES_TOPIC;
+
+    async fn pair(net: &SimNet) -> Vec<SimNode> {
+        let first = net.spawn(NetworkConfig::default()).await.unwrap();
+        let config = NetworkConfig { bootstrap_peers: vec![first.address.to_string()], ..NetworkConfig::default() };
+        let second = net.spawn(config).await.unwrap();
+        vec![first, second]
+    }
+
+    fn received(node: &mut SimNode) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        while let Ok(event) = node.events.try_recv() {
+            if let OmniTensorEvent::Message(_, _, data) = event {
+                messages.push(data);
+            }
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn test_gossip_crosses_slow_link() {
+        let net = SimNet::new(7);
+        net.set_conditions(LinkConditions {
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(20),
+            drop_rate: 0.0,
+        });
+        let mut nodes = pair(&net).await;
+        run(&mut nodes, Duration::from_secs(3)).await;
+        assert_eq!(nodes[0].network.connection_counts(), (1, 0));
+
+        nodes[0].network.publish(VOTES_TOPIC, b"vote".to_vec());
+        run(&mut nodes, Duration::from_secs(1)).await;
+        assert_eq!(received(&mut nodes[1]), vec![b"vote".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_partition_cuts_nodes_apart() {
+        let net = SimNet::new(11);
+        let mut nodes = pair(&net).await;
+        run(&mut nodes, Duration::from_secs(3)).await;
+
+        net.partition(&[&[0], &[1]]);
+        nodes[0].network.publish(VOTES_TOPIC, b"lost".to_vec());
+        run(&mut nodes, Duration::from_secs(3)).await;
+        assert!(received(&mut nodes[1]).is_empty());
+        assert_eq!(nodes[1].network.connection_counts(), (0, 0));
+    }
+}
@@ -0,0 +1,122 @@
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+struct TrustedPeer {
+    address: Multiaddr,
+    connected: bool,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+// Operator-configured peers (e.g. a validator's sentries) that we keep
+// connected to: redialed with exponential backoff whenever the connection
+// drops, never banned for their score and never evicted.
+pub struct TrustedPeers {
+    peers: HashMap<PeerId, TrustedPeer>,
+}
+
+impl TrustedPeers {
+    // Addresses must end in /p2p/<peer id> so we know who to trust
+    pub fn parse(addresses: &[String]) -> Result<Self, String> {
+        let now = Instant::now();
+        let mut peers = HashMap::new();
+        for address in addresses {
+            let multiaddr: Multiaddr = address.parse().map_err(|e| format!("invalid trusted peer {}: {}", address, e))?;
+            let peer_id = match multiaddr.iter().last() {
+                Some(Protocol::P2p(hash)) => PeerId::from_multihash(hash).ok(),
+                _ => None,
+            }
+            .ok_or_else(|| format!("trusted peer {} has no /p2p/<peer id>", address))?;
+            peers.insert(peer_id, TrustedPeer { address: multiaddr, connected: false, backoff: MIN_BACKOFF, next_attempt: now });
+        }
+        Ok(Self { peers })
+    }
+
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.peers.contains_key(peer_id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &PeerId> {
+        self.peers.keys()
+    }
+
+    pub fn on_connected(&mut self, peer_id: &PeerId) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.connected = true;
+            peer.backoff = MIN_BACKOFF;
+        }
+    }
+
+    pub fn on_disconnected(&mut self, peer_id: &PeerId) {
+        self.on_disconnected_at(peer_id, Instant::now())
+    }
+
+    fn on_disconnected_at(&mut self, peer_id: &PeerId, now: Instant) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.connected = false;
+            peer.next_attempt = now + peer.backoff;
+        }
+    }
+
+    pub fn on_dial_failure(&mut self, peer_id: &PeerId) {
+        self.on_dial_failure_at(peer_id, Instant::now())
+    }
+
+    fn on_dial_failure_at(&mut self, peer_id: &PeerId, now: Instant) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.backoff = (peer.backoff * 2).min(MAX_BACKOFF);
+            peer.next_attempt = now + peer.backoff;
+        }
+    }
+
+    // Disconnected peers whose backoff has passed; each is returned once per attempt
+    pub fn due(&mut self) -> Vec<Multiaddr> {
+        self.due_at(Instant::now())
+    }
+
+    fn due_at(&mut self, now: Instant) -> Vec<Multiaddr> {
+        let mut due = Vec::new();
+        for peer in self.peers.values_mut() {
+            if !peer.connected && peer.next_attempt <= now {
+                peer.next_attempt = now + peer.backoff;
+                due.push(peer.address.clone());
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redials_with_backoff() {
+        let peer_id = PeerId::random();
+        let address = format!("/ip4/10.0.0.1/tcp/3030/p2p/{}", peer_id);
+        let mut trusted = TrustedPeers::parse(&[address]).unwrap();
+        assert!(trusted.contains(&peer_id));
+        let start = Instant::now();
+
+        assert_eq!(trusted.due_at(start).len(), 1);
+        assert!(trusted.due_at(start).is_empty());
+        trusted.on_dial_failure_at(&peer_id, start);
+        assert!(trusted.due_at(start + Duration::from_secs(1)).is_empty());
+        assert_eq!(trusted.due_at(start + Duration::from_secs(2)).len(), 1);
+
+        trusted.on_connected(&peer_id);
+        assert!(trusted.due_at(start + Duration::from_secs(60)).is_empty());
+        trusted.on_disconnected_at(&peer_id, start + Duration::from_secs(60));
+        assert_eq!(trusted.due_at(start + Duration::from_secs(61)).len(), 1);
+    }
+
+    #[test]
+    fn test_requires_peer_id() {
+        assert!(TrustedPeers::parse(&["/ip4/10.0.0.1/tcp/3030".to_string()]).is_err());
+    }
+}
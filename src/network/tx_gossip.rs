@@ -0,0 +1,193 @@
+use libp2p::PeerId;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+use crate::chain::transaction::{Transaction, TransactionHash};
+use crate::network::codec::{BincodeCodec, Protocol};
+
+pub const TX_FETCH_PROTOCOL: Protocol = Protocol("/omnitensor/tx-fetch/1");
+const MAX_FETCH_RESPONSE_LEN: usize = 16 * 1024 * 1024;
+
+// Transactions are gossiped as hashes on the transactions topic; bodies are
+// then fetched from exactly one announcing peer over `TX_FETCH_PROTOCOL`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxAnnouncement {
+    pub hashes: Vec<TransactionHash>,
+}
+
+pub type TxFetchCodec = BincodeCodec<Vec<TransactionHash>, Vec<Transaction>>;
+
+pub fn tx_fetch_codec() -> TxFetchCodec {
+    BincodeCodec::new(MAX_FETCH_RESPONSE_LEN)
+}
+
+// `[network.tx_gossip]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TxGossipConfig {
+    // Hashes remembered as already fetched or submitted locally
+    pub seen_cache_size: usize,
+    // Announcements carrying more hashes are rejected as malformed
+    pub max_announcement_hashes: usize,
+    // After this, a missing body is requested from the next peer that announced it
+    pub fetch_timeout_ms: u64,
+}
+
+impl Default for TxGossipConfig {
+    fn default() -> Self {
+        Self { seen_cache_size: 100_000, max_announcement_hashes: 256, fetch_timeout_ms: 5000 }
+    }
+}
+
+struct InFlight {
+    peer: PeerId,
+    requested: Instant,
+    // Other peers that announced the hash, tried in order on timeout
+    alternates: Vec<PeerId>,
+}
+
+// Decides which announced transactions to fetch and from whom
+pub struct TxFetcher {
+    config: TxGossipConfig,
+    seen: LruCache<TransactionHash, ()>,
+    in_flight: HashMap<TransactionHash, InFlight>,
+}
+
+impl TxFetcher {
+    pub fn new(config: TxGossipConfig) -> Self {
+        let capacity = NonZeroUsize::new(config.seen_cache_size).unwrap_or(NonZeroUsize::MIN);
+        Self { config, seen: LruCache::new(capacity), in_flight: HashMap::new() }
+    }
+
+    pub fn max_announcement_hashes(&self) -> usize {
+        self.config.max_announcement_hashes
+    }
+
+    pub fn is_seen(&self, hash: &TransactionHash) -> bool {
+        self.seen.contains(hash)
+    }
+
+    // For transactions we already have, e.g. submitted locally
+    pub fn mark_seen(&mut self, hash: TransactionHash) {
+        self.in_flight.remove(&hash);
+        self.seen.put(hash, ());
+    }
+
+    // Returns the announced hashes to request from `peer` now. Hashes already
+    // requested from someone else only remember `peer` as a fallback.
+    pub fn on_announcement(&mut self, peer: PeerId, hashes: Vec<TransactionHash>) -> Vec<TransactionHash> {
+        self.on_announcement_at(peer, hashes, Instant::now())
+    }
+
+    fn on_announcement_at(&mut self, peer: PeerId, hashes: Vec<TransactionHash>, now: Instant) -> Vec<TransactionHash> {
+        let mut wanted = Vec::new();
+        for hash in hashes {
+            if self.seen.contains(&hash) {
+                continue;
+            }
+            match self.in_flight.get_mut(&hash) {
+                Some(in_flight) => {
+                    if in_flight.peer != peer && !in_flight.alternates.contains(&peer) {
+                        in_flight.alternates.push(peer);
+                    }
+                }
+                None => {
+                    self.in_flight.insert(hash.clone(), InFlight { peer, requested: now, alternates: Vec::new() });
+                    wanted.push(hash);
+                }
+            }
+        }
+        wanted
+    }
+
+    // Keeps the transactions we actually asked `peer` for and drops the rest
+    pub fn on_response(&mut self, peer: PeerId, transactions: Vec<Transaction>) -> Vec<Transaction> {
+        let mut accepted = Vec::new();
+        for transaction in transactions {
+            let hash = match transaction.hash() {
+                Ok(hash) => hash,
+                Err(_) => continue,
+            };
+            if self.in_flight.get(&hash).map_or(false, |in_flight| in_flight.peer == peer) {
+                self.mark_seen(hash);
+                accepted.push(transaction);
+            }
+        }
+        accepted
+    }
+
+    // Re-requests timed out fetches from the next announcer, grouped by peer.
+    // Hashes nobody else announced are forgotten so a later announcement can
+    // fetch them again.
+    pub fn expire(&mut self) -> Vec<(PeerId, Vec<TransactionHash>)> {
+        self.expire_at(Instant::now())
+    }
+
+    fn expire_at(&mut self, now: Instant) -> Vec<(PeerId, Vec<TransactionHash>)> {
+        let timeout = Duration::from_millis(self.config.fetch_timeout_ms);
+        let mut retries: HashMap<PeerId, Vec<TransactionHash>> = HashMap::new();
+        self.in_flight.retain(|hash, in_flight| {
+            if now.duration_since(in_flight.requested) < timeout {
+                return true;
+            }
+            if in_flight.alternates.is_empty() {
+                return false;
+            }
+            in_flight.peer = in_flight.alternates.remove(0);
+            in_flight.requested = now;
+            retries.entry(in_flight.peer).or_default().push(hash.clone());
+            true
+        });
+        retries.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::transaction::TransactionType;
+    use crate::types::Address;
+
+    fn transaction(nonce: u64) -> Transaction {
+        Transaction::new(nonce, Address::default(), Address::default(), 1, 1, 21_000, vec![], TransactionType::Transfer)
+    }
+
+    #[test]
+    fn test_fetches_each_transaction_once() {
+        let mut fetcher = TxFetcher::new(TxGossipConfig::default());
+        let (first, second) = (PeerId::random(), PeerId::random());
+        let tx = transaction(1);
+        let hash = tx.hash().unwrap();
+
+        assert_eq!(fetcher.on_announcement(first, vec![hash.clone()]), vec![hash.clone()]);
+        // Already requested from the first announcer
+        assert!(fetcher.on_announcement(second, vec![hash.clone()]).is_empty());
+        // Unrequested bodies are dropped
+        assert!(fetcher.on_response(second, vec![tx.clone()]).is_empty());
+
+        assert_eq!(fetcher.on_response(first, vec![tx]).len(), 1);
+        assert!(fetcher.is_seen(&hash));
+        assert!(fetcher.on_announcement(second, vec![hash]).is_empty());
+    }
+
+    #[test]
+    fn test_timed_out_fetch_moves_to_next_announcer() {
+        let mut fetcher = TxFetcher::new(TxGossipConfig::default());
+        let (slow, backup) = (PeerId::random(), PeerId::random());
+        let hash = transaction(2).hash().unwrap();
+        let start = Instant::now();
+
+        fetcher.on_announcement_at(slow, vec![hash.clone()], start);
+        fetcher.on_announcement_at(backup, vec![hash.clone()], start);
+        assert!(fetcher.expire_at(start + Duration::from_secs(1)).is_empty());
+
+        let retries = fetcher.expire_at(start + Duration::from_secs(6));
+        assert_eq!(retries, vec![(backup, vec![hash.clone()])]);
+        // Nobody left to ask: forgotten, so the next announcement fetches again
+        assert!(fetcher.expire_at(start + Duration::from_secs(12)).is_empty());
+        assert_eq!(fetcher.on_announcement(slow, vec![hash.clone()]), vec![hash]);
+    }
+}
@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use log::{debug, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::chain::genesis::GenesisAccount;
+use crate::crypto::hd::{self, KeyPurpose};
+use crate::crypto::keystore::SecretKey;
+use crate::crypto::scheme::SignatureScheme;
+use crate::network::p2p::NetworkConfig;
+use crate::rpc::pubsub::ChainEvent;
+use crate::types::{Address, Balance};
+use crate::utils::shutdown::Shutdown;
+
+// Well known, so tools and tests can hard-code the dev accounts. Anything
+// sent to them on a real network is free for anyone to take.
+pub const DEV_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+// Accounts funded in the dev genesis
+pub const DEV_ACCOUNTS: u32 = 10;
+
+pub struct DevAccount {
+    pub address: Address,
+    pub secret: SecretKey,
+}
+
+// Wallet accounts 0..count of `DEV_MNEMONIC`, as `omnitensor account derive`
+// would derive them
+pub fn accounts(count: u32) -> Vec<DevAccount> {
+    let seed = hd::seed(DEV_MNEMONIC, "").expect("the dev mnemonic is valid");
+    (0..count)
+        .map(|index| {
+            let scheme = SignatureScheme::Ed25519;
+            let secret =
+                hd::derive(&seed, scheme, &KeyPurpose::Wallet(index).path()).expect("wallet paths are hardened");
+            let public_key = scheme.public_key(secret.expose()).expect("derived keys are valid");
+            let address = scheme.address(&public_key).expect("ed25519 keys have addresses");
+            DevAccount { address: Address::from(address), secret }
+        })
+        .collect()
+}
+
+// More than any local testing spends, for each dev account
+pub fn genesis_alloc() -> Vec<GenesisAccount> {
+    let balance = Balance::from(10u64.pow(18));
+    accounts(DEV_ACCOUNTS).into_iter().map(|account| GenesisAccount { address: account.address, balance }).collect()
+}
+
+// A node on its own: no discovery, no bootstrap peers, and only reachable
+// from this machine
+pub fn apply(network: &mut NetworkConfig) {
+    network.mdns = false;
+    network.bootstrap_peers.clear();
+    network.dns_seeds.clear();
+    network.trusted_peers.clear();
+    network.transports.tcp_listen = vec!["/ip4/127.0.0.1/tcp/3030".to_string()];
+    network.transports.quic_enabled = false;
+    network.transports.websocket_enabled = false;
+}
+
+// Builds a block from the pool and imports it, without consensus
+#[async_trait]
+pub trait Sealer: Send + Sync {
+    // The height sealed; `None` if there was nothing to seal and empty blocks
+    // weren't asked for
+    async fn seal(&self, allow_empty: bool) -> Result<Option<u64>, String>;
+}
+
+// Seals a block as soon as a transaction enters the pool, and with a period
+// also on a timer, empty if need be. Replaces consensus on dev nodes.
+pub struct InstantSeal {
+    period: Option<Duration>,
+}
+
+impl InstantSeal {
+    pub fn new(period: Option<Duration>) -> Self {
+        Self { period }
+    }
+
+    pub async fn run(self, mut events: broadcast::Receiver<ChainEvent>, sealer: Arc<dyn Sealer>, shutdown: Shutdown) {
+        let mut timer = self.period.map(|period| {
+            let mut timer = tokio::time::interval(period);
+            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            timer
+        });
+        loop {
+            let allow_empty = tokio::select! {
+                _ = shutdown.wait() => return,
+                event = events.recv() => match event {
+                    Ok(ChainEvent::PendingTransaction(_)) => false,
+                    Ok(_) => continue,
+                    // Missed some; whatever they were, the pool has them
+                    Err(RecvError::Lagged(_)) => false,
+                    Err(RecvError::Closed) => return,
+                },
+                _ = tick(&mut timer) => true,
+            };
+            match sealer.seal(allow_empty).await {
+                Ok(Some(height)) => debug!("Sealed block {}", height),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to seal a block: {}", e),
+            }
+        }
+    }
+}
+
+async fn tick(timer: &mut Option<tokio::time::Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::transaction::TransactionHash;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<bool>>);
+
+    #[async_trait]
+    impl Sealer for Recorder {
+        async fn seal(&self, allow_empty: bool) -> Result<Option<u64>, String> {
+            let mut seals = self.0.lock().unwrap();
+            seals.push(allow_empty);
+            Ok(Some(seals.len() as u64))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_seals_on_transactions_and_on_the_timer() {
+        let alloc = genesis_alloc();
+        assert_eq!(alloc.len(), DEV_ACCOUNTS as usize);
+        assert_eq!(accounts(1)[0].address, alloc[0].address);
+        assert_ne!(alloc[0].address, alloc[1].address);
+
+        let (sender, events) = broadcast::channel(16);
+        let recorder = Arc::new(Recorder::default());
+        let shutdown = Shutdown::new();
+        let task = tokio::spawn(InstantSeal::new(Some(Duration::from_millis(300))).run(
+            events,
+            recorder.clone(),
+            shutdown.clone(),
+        ));
+        // The first tick is immediate
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        sender.send(ChainEvent::PendingTransaction(TransactionHash::from(&[1u8; 32][..]))).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(*recorder.0.lock().unwrap(), vec![true, false]);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(*recorder.0.lock().unwrap(), vec![true, false, true]);
+
+        shutdown.trigger();
+        task.await.unwrap();
+    }
+}
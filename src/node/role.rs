@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+use crate::network::p2p::NetworkConfig;
+
+// `[node] role`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeRole {
+    // A full node that also proposes and votes on blocks
+    Validator,
+    // Executes every block and keeps recent state. The default for nodes not
+    // set up to validate: block production has to be asked for.
+    #[default]
+    Full,
+    // Follows verified headers only, trusting the validator set for state
+    Light,
+    // A full node that keeps all history and never prunes
+    Archive,
+}
+
+impl fmt::Display for NodeRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NodeRole::Validator => "validator",
+            NodeRole::Full => "full",
+            NodeRole::Light => "light",
+            NodeRole::Archive => "archive",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RoleError {
+    #[error(
+        "[node] role is not set but {0}; set role = \"validator\" to keep producing blocks, or another role to stop"
+    )]
+    Unset(String),
+}
+
+// `[node]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeConfig {
+    // Read through `role()`
+    pub role: Option<NodeRole>,
+    // Recent blocks whose state stays queryable; archive nodes keep all
+    pub state_history_blocks: u64,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self { role: None, state_history_blocks: 128 }
+    }
+}
+
+impl NodeConfig {
+    // Every node produced blocks before roles existed, so an old validator
+    // config without a role must not quietly come up as a full node.
+    // `validator_setting` names what marks this node as a validator, if anything.
+    pub fn role(&self, validator_setting: Option<&str>) -> Result<NodeRole, RoleError> {
+        match (self.role, validator_setting) {
+            (Some(role), _) => Ok(role),
+            (None, Some(setting)) => Err(RoleError::Unset(setting.to_string())),
+            (None, None) => Ok(NodeRole::default()),
+        }
+    }
+
+    // Blocks of state kept below the head, `None` for all of them
+    pub fn state_retention(&self) -> Option<u64> {
+        match self.role {
+            Some(NodeRole::Archive) => None,
+            _ => Some(self.state_history_blocks),
+        }
+    }
+}
+
+// The components `Node::new` starts for a role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subsystems {
+    // Proposing blocks and signing votes; needs the validator key
+    pub block_production: bool,
+    // Downloading bodies and executing them against the state
+    pub execution: bool,
+    // Keeping a transaction pool, fed by gossip and RPC
+    pub mempool: bool,
+    // Moving old blocks to the ancient store and dropping old state
+    pub pruning: bool,
+}
+
+impl NodeRole {
+    pub fn subsystems(self) -> Subsystems {
+        match self {
+            NodeRole::Validator => Subsystems { block_production: true, execution: true, mempool: true, pruning: true },
+            NodeRole::Full => Subsystems { block_production: false, execution: true, mempool: true, pruning: true },
+            NodeRole::Light => Subsystems { block_production: false, execution: false, mempool: false, pruning: true },
+            NodeRole::Archive => Subsystems { block_production: false, execution: true, mempool: true, pruning: false },
+        }
+    }
+
+    // Overrides the sync settings the role depends on. Archive nodes must
+    // build every historical state themselves, so they never fast sync and
+    // download the history below a checkpoint; light nodes never fetch state.
+    pub fn apply(self, network: &mut NetworkConfig) {
+        match self {
+            NodeRole::Archive => {
+                network.state_sync.enabled = false;
+                network.header_sync.archive_history = true;
+            }
+            NodeRole::Light => network.state_sync.enabled = false,
+            NodeRole::Validator | NodeRole::Full => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_pick_subsystems_and_sync_settings() {
+        let config: NodeConfig = serde_json::from_str(r#"{"role": "archive"}"#).unwrap();
+        assert_eq!(config.role(Some("a validator key is configured")).unwrap(), NodeRole::Archive);
+        assert_eq!(NodeConfig::default().role(None).unwrap(), NodeRole::Full);
+        assert!(matches!(
+            NodeConfig::default().role(Some("[consensus.remote_signer] is enabled")),
+            Err(RoleError::Unset(_))
+        ));
+        assert_eq!(config.state_retention(), None);
+        assert_eq!(NodeConfig::default().state_retention(), Some(128));
+        assert!(NodeRole::Validator.subsystems().block_production);
+        assert!(!NodeRole::Full.subsystems().block_production);
+        assert!(!NodeRole::Light.subsystems().execution);
+        assert!(!NodeRole::Archive.subsystems().pruning);
+
+        let mut network = NetworkConfig::default();
+        NodeRole::Full.apply(&mut network);
+        assert!(network.state_sync.enabled && !network.header_sync.archive_history);
+        NodeRole::Archive.apply(&mut network);
+        assert!(!network.state_sync.enabled && network.header_sync.archive_history);
+    }
+}
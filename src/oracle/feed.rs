@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::crypto::{public_key::PublicKey, signature::Signature};
+use crate::storage::backend::StorageBackend;
+use crate::storage::db::DatabaseError;
+use crate::storage::transaction::StorageTransaction;
+use crate::types::{Address, Balance, BlockHeight};
+use crate::utils::decimal::Decimal;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reporter {
+    pub public_key: PublicKey,
+    pub stake: Balance,
+    pub slashed: Balance,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedConfig {
+    pub decimals: u8,
+    pub min_reports: usize,
+    // Reports further than this from the median are slashed, in basis points
+    pub max_deviation_bps: u32,
+    // Share of the reporter's stake slashed per deviating report, in basis points
+    pub slash_bps: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataPoint {
+    pub feed_id: String,
+    pub round: u64,
+    pub value: u128,
+    pub reporter: Address,
+    pub signature: Signature,
+}
+
+impl DataPoint {
+    pub fn signing_bytes(feed_id: &str, round: u64, value: u128) -> Vec<u8> {
+        bincode::serialize(&(feed_id, round, value)).expect("tuple serialization cannot fail")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregatedValue {
+    pub value: u128,
+    pub round: u64,
+    pub height: BlockHeight,
+    pub reports: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Feed {
+    config: Option<FeedConfig>,
+    round: u64,
+    pending: HashMap<Address, u128>,
+    latest: Option<AggregatedValue>,
+}
+
+#[derive(Debug, Error)]
+pub enum OracleError {
+    #[error("Reporter is not registered")]
+    UnknownReporter,
+    #[error("Reporter stake below minimum")]
+    InsufficientStake,
+    #[error("Feed not found: {0}")]
+    FeedNotFound(String),
+    #[error("Report is for round {got}, current round is {expected}")]
+    WrongRound { expected: u64, got: u64 },
+    #[error("Reporter already submitted for this round")]
+    DuplicateReport,
+    #[error("Invalid data point signature")]
+    InvalidSignature,
+    #[error("Not enough reports: {got} of {required}")]
+    NotEnoughReports { required: usize, got: usize },
+    #[error("Storage error: {0}")]
+    StorageError(#[from] DatabaseError),
+}
+
+pub struct OracleManager {
+    storage: Arc<dyn StorageBackend>,
+    min_stake: Balance,
+}
+
+impl OracleManager {
+    pub fn new(storage: Arc<dyn StorageBackend>, min_stake: Balance) -> Self {
+        Self { storage, min_stake }
+    }
+
+    pub async fn register_reporter(&mut self, address: Address, public_key: PublicKey, stake: Balance) -> Result<(), OracleError> {
+        if stake < self.min_stake {
+            return Err(OracleError::InsufficientStake);
+        }
+
+        let mut reporters = self.get_reporters().await?;
+        reporters.insert(address, Reporter {
+            public_key,
+            stake,
+            slashed: Balance::zero(),
+        });
+        self.storage.put_value(b"oracle_reporters", &reporters).await?;
+
+        Ok(())
+    }
+
+    pub async fn create_feed(&mut self, feed_id: &str, config: FeedConfig) -> Result<(), OracleError> {
+        let mut feeds = self.get_feeds().await?;
+        feeds.entry(feed_id.to_string()).or_default().config = Some(config);
+        self.storage.put_value(b"oracle_feeds", &feeds).await?;
+
+        Ok(())
+    }
+
+    pub async fn submit(&mut self, point: DataPoint) -> Result<(), OracleError> {
+        let reporters = self.get_reporters().await?;
+        let reporter = reporters.get(&point.reporter).ok_or(OracleError::UnknownReporter)?;
+        if reporter.stake < self.min_stake {
+            return Err(OracleError::InsufficientStake);
+        }
+
+        let message = DataPoint::signing_bytes(&point.feed_id, point.round, point.value);
+        if !point.signature.verify(&message, &reporter.public_key) {
+            return Err(OracleError::InvalidSignature);
+        }
+
+        let mut feeds = self.get_feeds().await?;
+        let feed = feeds
+            .get_mut(&point.feed_id)
+            .filter(|feed| feed.config.is_some())
+            .ok_or_else(|| OracleError::FeedNotFound(point.feed_id.clone()))?;
+        if point.round != feed.round {
+            return Err(OracleError::WrongRound {
+                expected: feed.round,
+                got: point.round,
+            });
+        }
+        if feed.pending.contains_key(&point.reporter) {
+            return Err(OracleError::DuplicateReport);
+        }
+
+        feed.pending.insert(point.reporter, point.value);
+        self.storage.put_value(b"oracle_feeds", &feeds).await?;
+
+        Ok(())
+    }
+
+    // Aggregates the current round into a median, slashes outliers and opens the next round
+    pub async fn finalize_round(&mut self, feed_id: &str, height: BlockHeight) -> Result<AggregatedValue, OracleError> {
+        let mut feeds = self.get_feeds().await?;
+        let feed = feeds
+            .get_mut(feed_id)
+            .ok_or_else(|| OracleError::FeedNotFound(feed_id.to_string()))?;
+        let config = feed.config.clone().ok_or_else(|| OracleError::FeedNotFound(feed_id.to_string()))?;
+
+        if feed.pending.len() < config.min_reports {
+            return Err(OracleError::NotEnoughReports {
+                required: config.min_reports,
+                got: feed.pending.len(),
+            });
+        }
+
+        let mut values: Vec<u128> = feed.pending.values().copied().collect();
+        let value = median(&mut values);
+
+        let mut reporters = self.get_reporters().await?;
+        for (address, reported) in feed.pending.iter() {
+            if deviation(*reported, value) > Decimal::from_bps(config.max_deviation_bps) {
+                if let Some(reporter) = reporters.get_mut(address) {
+                    let penalty = Decimal::from_bps(config.slash_bps).share_of(reporter.stake);
+                    reporter.stake -= penalty;
+                    reporter.slashed += penalty;
+                }
+            }
+        }
+
+        let aggregated = AggregatedValue {
+            value,
+            round: feed.round,
+            height,
+            reports: feed.pending.len(),
+        };
+        feed.latest = Some(aggregated.clone());
+        feed.pending.clear();
+        feed.round += 1;
+
+        let mut transaction = StorageTransaction::new();
+        transaction.put_value(b"oracle_reporters", &reporters)?.put_value(b"oracle_feeds", &feeds)?;
+        self.storage.write(transaction).await?;
+
+        Ok(aggregated)
+    }
+
+    pub async fn latest_value(&self, feed_id: &str) -> Result<Option<AggregatedValue>, OracleError> {
+        let feeds = self.get_feeds().await?;
+        Ok(feeds.get(feed_id).and_then(|feed| feed.latest.clone()))
+    }
+
+    pub async fn current_round(&self, feed_id: &str) -> Result<u64, OracleError> {
+        let feeds = self.get_feeds().await?;
+        feeds
+            .get(feed_id)
+            .map(|feed| feed.round)
+            .ok_or_else(|| OracleError::FeedNotFound(feed_id.to_string()))
+    }
+
+    pub async fn get_reporter(&self, address: Address) -> Result<Option<Reporter>, OracleError> {
+        Ok(self.get_reporters().await?.get(&address).cloned())
+    }
+
+    async fn get_reporters(&self) -> Result<HashMap<Address, Reporter>, OracleError> {
+        Ok(self.storage.get_value(b"oracle_reporters").await?.unwrap_or_default())
+    }
+
+    async fn get_feeds(&self) -> Result<HashMap<String, Feed>, OracleError> {
+        Ok(self.storage.get_value(b"oracle_feeds").await?.unwrap_or_default())
+    }
+}
+
+// Lower median for even counts, so the result is always a reported value
+fn median(values: &mut [u128]) -> u128 {
+    values.sort_unstable();
+    values[(values.len() - 1) / 2]
+}
+
+fn deviation(value: u128, reference: u128) -> Decimal {
+    Decimal::from_ratio(value.abs_diff(reference), reference)
+        .unwrap_or(if value == reference { Decimal::ZERO } else { Decimal::MAX })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::key_pair::KeyPair;
+    use crate::storage::backend::MemoryBackend;
+
+    async fn setup() -> (OracleManager, Vec<(Address, KeyPair)>) {
+        let mut oracle = OracleManager::new(Arc::new(MemoryBackend::new()), Balance::from(100));
+        let reporters: Vec<(Address, KeyPair)> = (0..4).map(|_| (Address::random(), KeyPair::generate())).collect();
+        for (address, key_pair) in &reporters {
+            oracle
+                .register_reporter(*address, key_pair.public_key().clone(), Balance::from(10_000))
+                .await
+                .unwrap();
+        }
+        oracle
+            .create_feed("OMNI/USD", FeedConfig {
+                decimals: 8,
+                min_reports: 3,
+                max_deviation_bps: 500,
+                slash_bps: 1_000,
+            })
+            .await
+            .unwrap();
+        (oracle, reporters)
+    }
+
+    fn report(address: Address, key_pair: &KeyPair, round: u64, value: u128) -> DataPoint {
+        let message = DataPoint::signing_bytes("OMNI/USD", round, value);
+        DataPoint {
+            feed_id: "OMNI/USD".to_string(),
+            round,
+            value,
+            reporter: address,
+            signature: Signature::sign(&message, key_pair.private_key()).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_median_aggregation_and_slashing() {
+        let (mut oracle, reporters) = setup().await;
+        let values = [100, 101, 99, 150];
+        for ((address, key_pair), value) in reporters.iter().zip(values) {
+            oracle.submit(report(*address, key_pair, 0, value)).await.unwrap();
+        }
+
+        let aggregated = oracle.finalize_round("OMNI/USD", BlockHeight::from(10)).await.unwrap();
+        assert_eq!(aggregated.value, 100);
+        assert_eq!(aggregated.reports, 4);
+        assert_eq!(oracle.current_round("OMNI/USD").await.unwrap(), 1);
+
+        let outlier = oracle.get_reporter(reporters[3].0).await.unwrap().unwrap();
+        assert_eq!(outlier.slashed, Balance::from(1_000));
+        let honest = oracle.get_reporter(reporters[0].0).await.unwrap().unwrap();
+        assert_eq!(honest.slashed, Balance::zero());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_bad_reports() {
+        let (mut oracle, reporters) = setup().await;
+        let (address, key_pair) = &reporters[0];
+
+        oracle.submit(report(*address, key_pair, 0, 100)).await.unwrap();
+        assert!(matches!(oracle.submit(report(*address, key_pair, 0, 100)).await, Err(OracleError::DuplicateReport)));
+        assert!(matches!(oracle.submit(report(*address, key_pair, 1, 100)).await, Err(OracleError::WrongRound { .. })));
+
+        let forged = report(reporters[1].0, key_pair, 0, 100);
+        assert!(matches!(oracle.submit(forged).await, Err(OracleError::InvalidSignature)));
+
+        assert!(matches!(
+            oracle.finalize_round("OMNI/USD", BlockHeight::from(1)).await,
+            Err(OracleError::NotEnoughReports { .. })
+        ));
+    }
+}
@@ -0,0 +1,344 @@
+use async_trait::async_trait;
+use hyper::header::{HeaderValue, WWW_AUTHENTICATE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request as HttpRequest, Response as HttpResponse, Server, StatusCode};
+use libp2p::{Multiaddr, PeerId};
+use log::{info, warn, LevelFilter};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::rpc::auth::{ApiKey, Authenticator, Scoped};
+use crate::rpc::jsonrpc::{Methods, Params, RpcError, METHOD_NOT_FOUND};
+use crate::rpc::server::{serve_json_rpc, status, RpcServerError};
+use crate::storage::db::Database;
+use crate::utils::logger;
+
+// Admin calls are small and rare
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+const MAX_BATCH_SIZE: usize = 16;
+
+// `[rpc.admin]`. Off by default and on its own listener, so it can stay on
+// loopback while the public RPC is exposed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub enabled: bool,
+    pub listen: SocketAddr,
+    // Bearer token with access to everything, created on first start (mode 600)
+    pub token_file: PathBuf,
+    // Shared HS256 secret for JWT bearer tokens, created like the token file
+    pub jwt_secret_file: Option<PathBuf>,
+    // Further bearer credentials, each limited to some namespaces
+    pub api_keys: Vec<ApiKey>,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: SocketAddr::from(([127, 0, 0, 1], 8551)),
+            token_file: PathBuf::from("./data/admin.token"),
+            jwt_secret_file: None,
+            api_keys: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerView {
+    pub peer_id: String,
+    pub address: String,
+    pub inbound: bool,
+    pub score: f64,
+    pub protocol_version: u32,
+}
+
+// What admin methods need from the running node. Errors are reasons shown to
+// the caller.
+#[async_trait]
+pub trait NodeAdmin: Send + Sync {
+    async fn add_peer(&self, address: Multiaddr) -> Result<(), String>;
+
+    // Whether the peer was connected
+    async fn remove_peer(&self, peer: PeerId) -> Result<bool, String>;
+
+    // Disconnects the peer and refuses it until the ban expires
+    async fn ban_peer(&self, peer: PeerId) -> Result<(), String>;
+
+    async fn peers(&self) -> Vec<PeerView>;
+
+    // Switches block signing to a freshly generated key; returns its public key
+    async fn rotate_validator_key(&self) -> Result<String, String>;
+
+    async fn set_block_production(&self, paused: bool) -> Result<(), String>;
+}
+
+// Operator methods, served only by `AdminServer`:
+//   admin_addPeer([multiaddr]), admin_removePeer([peer id]), admin_banPeer([peer id]),
+//   admin_peers(), admin_setLogLevel([level]), admin_compactDatabase(),
+//   admin_createBackup([dir, keep?]), admin_rotateKeys(), admin_pauseBlockProduction(),
+//   admin_resumeBlockProduction()
+pub struct AdminApi {
+    database: Arc<Database>,
+    node: Arc<dyn NodeAdmin>,
+}
+
+impl AdminApi {
+    pub fn new(database: Arc<Database>, node: Arc<dyn NodeAdmin>) -> Self {
+        Self { database, node }
+    }
+
+    async fn dispatch(&self, method: &str, params: Params) -> Result<Value, RpcError> {
+        match method {
+            "admin_addPeer" => {
+                let address = params.get::<String>(0)?;
+                let address = address.parse().map_err(|e| RpcError::invalid_params(format!("{}: {}", address, e)))?;
+                self.node.add_peer(address).await.map_err(RpcError::internal)?;
+                Ok(json!(true))
+            }
+            "admin_removePeer" => {
+                Ok(json!(self.node.remove_peer(peer_id(&params)?).await.map_err(RpcError::internal)?))
+            }
+            "admin_banPeer" => {
+                self.node.ban_peer(peer_id(&params)?).await.map_err(RpcError::internal)?;
+                Ok(json!(true))
+            }
+            "admin_peers" => serde_json::to_value(self.node.peers().await).map_err(RpcError::internal),
+            "admin_setLogLevel" => {
+                let level = params.get::<String>(0)?;
+                let level: LevelFilter =
+                    level.parse().map_err(|_| RpcError::invalid_params(format!("Invalid log level: {}", level)))?;
+                let previous = log::max_level();
+                logger::set_max_level(level);
+                Ok(json!(previous.to_string().to_lowercase()))
+            }
+            "admin_compactDatabase" => {
+                if self.database.is_secondary() {
+                    return Err(RpcError::internal("A secondary database is compacted by its primary"));
+                }
+                let database = self.database.clone();
+                tokio::task::spawn_blocking(move || database.compact())
+                    .await
+                    .map_err(RpcError::internal)?
+                    .map_err(RpcError::internal)?;
+                Ok(json!(true))
+            }
+            // The running node holds the database lock, so offline backups go through here
+            "admin_createBackup" => {
+                if self.database.is_secondary() {
+                    return Err(RpcError::internal("A secondary database is backed up by its primary"));
+                }
+                let dir = params.get::<PathBuf>(0)?;
+                let keep = params.get::<Option<usize>>(1)?.unwrap_or(0);
+                let backup = self.database.create_backup(dir, keep).await.map_err(RpcError::internal)?;
+                serde_json::to_value(backup).map_err(RpcError::internal)
+            }
+            "admin_rotateKeys" => {
+                let validator_key = self.node.rotate_validator_key().await.map_err(RpcError::internal)?;
+                // Only encrypted databases have a data key
+                let data_key = if self.database.is_encrypted() {
+                    Some(self.database.rotate_data_key().map_err(RpcError::internal)?)
+                } else {
+                    None
+                };
+                Ok(json!({ "validatorKey": validator_key, "dataKey": data_key }))
+            }
+            "admin_pauseBlockProduction" | "admin_resumeBlockProduction" => {
+                let paused = method == "admin_pauseBlockProduction";
+                self.node.set_block_production(paused).await.map_err(RpcError::internal)?;
+                Ok(json!(true))
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method {} not found", method))),
+        }
+    }
+}
+
+#[async_trait]
+impl Methods for AdminApi {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let outcome = self.dispatch(method, Params::parse(params)?).await;
+        match &outcome {
+            Ok(_) => info!("Admin RPC: {} done", method),
+            Err(e) => warn!("Admin RPC: {} failed: {}", method, e.message),
+        }
+        outcome
+    }
+}
+
+fn peer_id(params: &Params) -> Result<PeerId, RpcError> {
+    let peer = params.get::<String>(0)?;
+    peer.parse().map_err(|_| RpcError::invalid_params(format!("Invalid peer id: {}", peer)))
+}
+
+// Reads the token from `path`, creating a random one on first start. An
+// empty file is an error rather than an empty credential.
+pub fn load_or_generate_token(path: &Path) -> std::io::Result<String> {
+    if path.exists() {
+        let token = fs::read_to_string(path)?.trim().to_string();
+        if token.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "the file is empty"));
+        }
+        return Ok(token);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let token: String = secret.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(token.as_bytes())?;
+    file.sync_all()?;
+    Ok(token)
+}
+
+// JSON-RPC over HTTP POST for operator namespaces, `AdminApi` and the
+// wallet; every request must carry `Authorization: Bearer <credential>`,
+// see `Authenticator`
+pub struct AdminServer {
+    local_addr: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl AdminServer {
+    pub fn start(config: &AdminConfig, api: Arc<dyn Methods>) -> Result<Self, RpcServerError> {
+        let load =
+            |path: &PathBuf| load_or_generate_token(path).map_err(|e| RpcServerError::AdminToken(path.clone(), e));
+        let token = load(&config.token_file)?;
+        let jwt_secret = config.jwt_secret_file.as_ref().map(load).transpose()?;
+        let auth = Authenticator::new(token, jwt_secret.as_deref(), &config.api_keys)
+            .map_err(|e| RpcServerError::AdminAuth(e.to_string()))?;
+        let auth = Arc::new(auth);
+        let make_service = make_service_fn(move |_| {
+            let (api, auth) = (api.clone(), auth.clone());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let (api, auth) = (api.clone(), auth.clone());
+                    async move { Ok::<_, Infallible>(serve(api.as_ref(), &auth, request).await) }
+                }))
+            }
+        });
+        let server = Server::try_bind(&config.listen)
+            .map_err(|e| RpcServerError::AdminBind(config.listen, e))?
+            .serve(make_service);
+        let local_addr = server.local_addr();
+        let (shutdown, stopped) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            if let Err(e) = server.with_graceful_shutdown(async { stopped.await.unwrap_or(()) }).await {
+                warn!("Admin RPC server failed: {}", e);
+            }
+        });
+        info!("Admin RPC listening on http://{}", local_addr);
+        Ok(Self { local_addr, shutdown, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub async fn stop(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+async fn serve(api: &dyn Methods, auth: &Authenticator, request: HttpRequest<Body>) -> HttpResponse<Body> {
+    let grant = match auth.authenticate(request.headers()) {
+        Some(grant) => grant,
+        None => {
+            let mut response = status(StatusCode::UNAUTHORIZED);
+            response.headers_mut().insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            return response;
+        }
+    };
+    match *request.method() {
+        Method::POST => serve_json_rpc(&Scoped::new(api, grant), request, MAX_REQUEST_BYTES, MAX_BATCH_SIZE).await,
+        _ => status(StatusCode::METHOD_NOT_ALLOWED),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::jsonrpc::INVALID_PARAMS;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    #[derive(Default)]
+    struct StubAdmin {
+        banned: Mutex<Vec<PeerId>>,
+        paused: Mutex<bool>,
+    }
+
+    #[async_trait]
+    impl NodeAdmin for StubAdmin {
+        async fn add_peer(&self, _address: Multiaddr) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn remove_peer(&self, _peer: PeerId) -> Result<bool, String> {
+            Ok(false)
+        }
+
+        async fn ban_peer(&self, peer: PeerId) -> Result<(), String> {
+            self.banned.lock().unwrap().push(peer);
+            Ok(())
+        }
+
+        async fn peers(&self) -> Vec<PeerView> {
+            Vec::new()
+        }
+
+        async fn rotate_validator_key(&self) -> Result<String, String> {
+            Err("not a validator".to_string())
+        }
+
+        async fn set_block_production(&self, paused: bool) -> Result<(), String> {
+            *self.paused.lock().unwrap() = paused;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatches_admin_methods() {
+        let temp_dir = TempDir::new().unwrap();
+        let node = Arc::new(StubAdmin::default());
+        let api = AdminApi::new(Arc::new(Database::new(temp_dir.path().join("db")).unwrap()), node.clone());
+
+        let peer = PeerId::random();
+        assert_eq!(api.call("admin_banPeer", json!([peer.to_base58()])).await.unwrap(), json!(true));
+        assert_eq!(*node.banned.lock().unwrap(), vec![peer]);
+        assert_eq!(api.call("admin_banPeer", json!(["nope"])).await.unwrap_err().code, INVALID_PARAMS);
+        assert_eq!(api.call("admin_addPeer", json!(["/ip4/1.2.3.4/tcp/3030"])).await.unwrap(), json!(true));
+        api.call("admin_pauseBlockProduction", json!([])).await.unwrap();
+        assert!(*node.paused.lock().unwrap());
+        api.call("admin_compactDatabase", json!([])).await.unwrap();
+        let backups = temp_dir.path().join("backups");
+        let backup = api.call("admin_createBackup", json!([backups, 1])).await.unwrap();
+        assert_eq!(backup["backup_id"], json!(1));
+        assert_eq!(Database::list_backups(&backups).unwrap().len(), 1);
+        assert!(api.call("admin_rotateKeys", json!([])).await.is_err());
+        assert_eq!(api.call("admin_setLogLevel", json!(["loud"])).await.unwrap_err().code, INVALID_PARAMS);
+
+        let path = temp_dir.path().join("admin.token");
+        let token = load_or_generate_token(&path).unwrap();
+        assert_eq!((token.len(), load_or_generate_token(&path).unwrap()), (64, token));
+        fs::write(&path, "\n").unwrap();
+        assert!(load_or_generate_token(&path).is_err());
+    }
+}
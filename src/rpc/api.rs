@@ -0,0 +1,511 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::{field, instrument, Span};
+
+use crate::ai::marketplace::Listing;
+use crate::chain::block::Block;
+use crate::chain::governance::{Proposal, ProposalId};
+use crate::chain::history::{HistoryError, StateHistory};
+use crate::chain::store::ChainStore;
+use crate::chain::transaction::{RawTransaction, Transaction, TransactionHash, TransactionReceipt, TransactionType};
+use crate::chain::treasury::BlockAccount;
+use crate::chain::upgrade::UpgradePlan;
+use crate::chain::validation::{self, TxRejection};
+use crate::chain::verify::SignatureCheck;
+use crate::network::codec::decode_bounded;
+use crate::network::light_client::ValidatorSet;
+use crate::node::role::NodeConfig;
+use crate::rpc::jsonrpc::{
+    from_hex, hash_from_hex, to_hex, Methods, Params, RpcError, METHOD_NOT_FOUND, STATE_PRUNED, TRANSACTION_REJECTED,
+};
+use crate::rpc::logs::{LogFilters, LogsConfig};
+use crate::rpc::txpool::{self, AccountPool, PoolStatus};
+use crate::types::{Address, Balance, Nonce};
+
+// What the RPC needs from the running node beyond the chain store. Errors
+// are reasons shown to the caller.
+#[async_trait]
+pub trait NodeApi: Send + Sync {
+    fn chain_id(&self) -> u64;
+
+    fn signature_check(&self) -> SignatureCheck;
+
+    async fn balance(&self, address: &Address) -> Result<Balance, String>;
+
+    // The next nonce the account may use, at the current head
+    async fn nonce(&self, address: &Address) -> Result<Nonce, String>;
+
+    // Queues an already validated transaction for inclusion and gossip.
+    // Errors are the pool's reasons, e.g. that it is full.
+    async fn submit_transaction(&self, transaction: Transaction) -> Result<(), String>;
+
+    // Every transaction in the pool, in arrival order
+    async fn pool_transactions(&self) -> Result<Vec<Transaction>, String>;
+
+    // Lowest gas price block producers include
+    fn min_gas_price(&self) -> u64;
+
+    async fn validators(&self) -> ValidatorSet;
+
+    async fn model(&self, model: &Address) -> Result<Option<Listing>, String>;
+
+    // Whether `caller` may invoke `model` at the current head, under the
+    // model's access policy
+    async fn has_model_access(&self, model: &Address, caller: &Address) -> Result<bool, String>;
+
+    async fn proposal(&self, id: ProposalId) -> Result<Option<Proposal>, String>;
+
+    async fn proposals(&self) -> Result<Vec<Proposal>, String>;
+
+    async fn upgrade_plan(&self) -> Result<Option<UpgradePlan>, String>;
+
+    async fn treasury_balance(&self) -> Result<Balance, String>;
+
+    // The treasury's accounts of the blocks in `from..=to` that moved funds
+    async fn treasury_accounts(&self, from: u64, to: u64) -> Result<Vec<BlockAccount>, String>;
+}
+
+// A height, a 0x-prefixed block hash or "latest"
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum BlockId {
+    Height(u64),
+    Tag(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockView {
+    pub height: u64,
+    pub hash: String,
+    #[serde(flatten)]
+    pub block: Block,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionView {
+    pub block_height: u64,
+    pub block_hash: String,
+    pub index: u32,
+    pub transaction: Transaction,
+    // Missing for blocks imported without execution results
+    pub receipt: Option<TransactionReceipt>,
+}
+
+// Answers RPC methods from the chain store and the node. Shared by every
+// transport; each maps `RpcError`s onto its own error format.
+pub struct RpcApi {
+    chain: Arc<ChainStore>,
+    node: Arc<dyn NodeApi>,
+    logs: LogFilters,
+    history: StateHistory,
+}
+
+impl RpcApi {
+    pub fn new(chain: Arc<ChainStore>, node: Arc<dyn NodeApi>) -> Self {
+        let history = StateHistory::new(chain.clone(), NodeConfig::default().state_retention());
+        Self { chain, node, logs: LogFilters::new(LogsConfig::default()), history }
+    }
+
+    // `None` serves state at every stored height, for archive nodes
+    pub fn with_state_retention(mut self, retained: Option<u64>) -> Self {
+        self.history = StateHistory::new(self.chain.clone(), retained);
+        self
+    }
+
+    pub fn with_logs_config(mut self, config: LogsConfig) -> Self {
+        self.logs = LogFilters::new(config);
+        self
+    }
+
+    // `None` for an unknown hash, or "latest" on an empty chain
+    pub async fn height(&self, id: BlockId) -> Result<Option<u64>, RpcError> {
+        match id {
+            BlockId::Height(height) => Ok(Some(height)),
+            BlockId::Tag(tag) if tag == "latest" => {
+                Ok(self.chain.head().await.map_err(RpcError::internal)?.map(|head| head.height))
+            }
+            BlockId::Tag(hash) => self.chain.height_of(hash_from_hex(&hash)?).await.map_err(RpcError::internal),
+        }
+    }
+
+    pub async fn block(&self, id: BlockId) -> Result<Option<BlockView>, RpcError> {
+        let height = self.height(id).await?;
+        let block = match height {
+            Some(height) => self.chain.block(height).await.map_err(RpcError::internal)?,
+            None => None,
+        };
+        Ok(height.zip(block).map(|(height, block)| BlockView { height, hash: to_hex(&block.hash()), block }))
+    }
+
+    pub async fn transaction(&self, hash: &str) -> Result<Option<TransactionView>, RpcError> {
+        let hash = TransactionHash::from(&hash_from_hex(hash)?[..]);
+        let location = match self.chain.transaction_location(&hash).await.map_err(RpcError::internal)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let block = match self.chain.block(location.height).await.map_err(RpcError::internal)? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+        let receipts = self.chain.receipts(location.height).await.map_err(RpcError::internal)?;
+        let index = location.index as usize;
+        Ok(block.transactions.get(index).cloned().map(|transaction| TransactionView {
+            block_height: location.height,
+            block_hash: to_hex(&block.hash()),
+            index: location.index,
+            transaction,
+            receipt: receipts.and_then(|receipts| receipts.get(index).cloned()),
+        }))
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.node.chain_id()
+    }
+
+    pub async fn balance(&self, address: &Address) -> Result<Balance, RpcError> {
+        self.node.balance(address).await.map_err(RpcError::internal)
+    }
+
+    pub async fn balance_at(&self, address: &Address, id: BlockId) -> Result<Balance, RpcError> {
+        let height = self.height(id).await?.ok_or_else(|| RpcError::invalid_params("Unknown block"))?;
+        self.history.balance_at(address, height).await.map_err(history_error)
+    }
+
+    pub async fn stake_at(&self, address: &Address, id: BlockId) -> Result<Balance, RpcError> {
+        let height = self.height(id).await?.ok_or_else(|| RpcError::invalid_params("Unknown block"))?;
+        self.history.stake_at(address, height).await.map_err(history_error)
+    }
+
+    pub async fn validators(&self) -> ValidatorSet {
+        self.node.validators().await
+    }
+
+    pub async fn model(&self, model: &Address) -> Result<Option<Listing>, RpcError> {
+        self.node.model(model).await.map_err(RpcError::internal)
+    }
+
+    pub async fn proposal(&self, id: ProposalId) -> Result<Option<Proposal>, RpcError> {
+        self.node.proposal(id).await.map_err(RpcError::internal)
+    }
+
+    pub async fn proposals(&self) -> Result<Vec<Proposal>, RpcError> {
+        self.node.proposals().await.map_err(RpcError::internal)
+    }
+
+    pub async fn upgrade_plan(&self) -> Result<Option<UpgradePlan>, RpcError> {
+        self.node.upgrade_plan().await.map_err(RpcError::internal)
+    }
+
+    pub async fn treasury_balance(&self) -> Result<Balance, RpcError> {
+        self.node.treasury_balance().await.map_err(RpcError::internal)
+    }
+
+    pub async fn treasury_accounts(&self, from: u64, to: u64) -> Result<Vec<BlockAccount>, RpcError> {
+        if to < from {
+            return Err(RpcError::invalid_params("to is below from"));
+        }
+        self.node.treasury_accounts(from, to).await.map_err(RpcError::internal)
+    }
+
+    // `raw` is the hex-encoded bincode of a `RawTransaction`. Runs every
+    // check a transaction has to pass to enter the pool and returns its hash;
+    // rejections carry the reason as error data.
+    #[instrument(skip_all, fields(tx_hash = field::Empty))]
+    pub async fn send_raw_transaction(&self, raw: &str) -> Result<String, RpcError> {
+        let bytes = from_hex(raw).ok_or_else(|| RpcError::invalid_params("transaction is not hex-encoded"))?;
+        let raw: RawTransaction =
+            decode_bounded(&bytes).map_err(|e| RpcError::invalid_params(format!("malformed transaction: {}", e)))?;
+        validation::check_stateless(&raw, self.node.chain_id(), &self.node.signature_check()).map_err(rejected)?;
+
+        let transaction = raw.transaction;
+        let nonce = self.node.nonce(&transaction.from).await.map_err(RpcError::internal)?;
+        let balance = self.node.balance(&transaction.from).await.map_err(RpcError::internal)?;
+        validation::check_stateful(&transaction, nonce, balance).map_err(rejected)?;
+        if matches!(transaction.transaction_type, TransactionType::AIModelInvoke)
+            && !self.node.has_model_access(&transaction.to, &transaction.from).await.map_err(RpcError::internal)?
+        {
+            return Err(rejected(TxRejection::AccessDenied { model: transaction.to }));
+        }
+
+        let hash = transaction.hash().map_err(|e| RpcError::internal(format!("{:?}", e)))?;
+        Span::current().record("tx_hash", to_hex(hash.as_bytes()));
+        self.node.submit_transaction(transaction).await.map_err(|message| rejected(TxRejection::Pool { message }))?;
+        Ok(to_hex(hash.as_bytes()))
+    }
+
+    // The pool by sender, with each sender's transactions split into pending
+    // and queued
+    pub async fn txpool_content(&self) -> Result<Vec<AccountPool>, RpcError> {
+        let transactions = self.node.pool_transactions().await.map_err(RpcError::internal)?;
+        let mut accounts = Vec::new();
+        for (address, transactions) in txpool::by_sender(transactions) {
+            accounts.push(self.account_pool(address, transactions).await?);
+        }
+        Ok(accounts)
+    }
+
+    pub async fn txpool_status(&self) -> Result<PoolStatus, RpcError> {
+        Ok(PoolStatus::of(&self.txpool_content().await?))
+    }
+
+    // One sender's transactions and why any of them are stuck
+    pub async fn txpool_inspect(&self, address: &Address) -> Result<AccountPool, RpcError> {
+        let transactions = self.node.pool_transactions().await.map_err(RpcError::internal)?;
+        let own = transactions.into_iter().filter(|transaction| transaction.from == *address).collect();
+        self.account_pool(*address, own).await
+    }
+
+    async fn account_pool(&self, address: Address, transactions: Vec<Transaction>) -> Result<AccountPool, RpcError> {
+        let nonce = self.node.nonce(&address).await.map_err(RpcError::internal)?;
+        txpool::classify(address, nonce, transactions, self.node.min_gas_price())
+    }
+}
+
+#[async_trait]
+impl Methods for RpcApi {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let params = Params::parse(params)?;
+        match method {
+            "chain_getBlock" => to_value(self.block(params.get(0)?).await?),
+            "chain_getTransaction" => to_value(self.transaction(&params.get::<String>(0)?).await?),
+            "state_getBalance" => to_value(self.balance(&params.get(0)?).await?),
+            "state_getBalanceAt" => to_value(self.balance_at(&params.get(0)?, params.get(1)?).await?),
+            "staking_getStakeAt" => to_value(self.stake_at(&params.get(0)?, params.get(1)?).await?),
+            "tx_sendRawTransaction" => to_value(self.send_raw_transaction(&params.get::<String>(0)?).await?),
+            "txpool_content" => to_value(self.txpool_content().await?),
+            "txpool_status" => to_value(self.txpool_status().await?),
+            "txpool_inspect" => to_value(self.txpool_inspect(&params.get(0)?).await?),
+            "consensus_getValidators" => to_value(self.validators().await),
+            "gov_getProposal" => to_value(self.proposal(params.get(0)?).await?),
+            "gov_getProposals" => to_value(self.proposals().await?),
+            "gov_getUpgradePlan" => to_value(self.upgrade_plan().await?),
+            "treasury_getBalance" => to_value(self.treasury_balance().await?),
+            "treasury_getAccounts" => to_value(self.treasury_accounts(params.get(0)?, params.get(1)?).await?),
+            "logs_getLogs" => {
+                to_value(self.logs.logs(&self.chain, params.get::<Option<_>>(0)?.unwrap_or_default()).await?)
+            }
+            "logs_newFilter" => {
+                to_value(self.logs.install(&self.chain, params.get::<Option<_>>(0)?.unwrap_or_default()).await?)
+            }
+            "logs_getFilterChanges" => to_value(self.logs.changes(&self.chain, &params.get::<String>(0)?).await?),
+            "logs_getFilterLogs" => to_value(self.logs.filter_logs(&self.chain, &params.get::<String>(0)?).await?),
+            "logs_uninstallFilter" => to_value(self.logs.uninstall(&params.get::<String>(0)?)),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method {} not found", method))),
+        }
+    }
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(RpcError::internal)
+}
+
+fn history_error(error: HistoryError) -> RpcError {
+    match error {
+        HistoryError::Pruned { height, oldest } => RpcError {
+            data: Some(json!({ "height": height, "oldest": oldest })),
+            ..RpcError::new(STATE_PRUNED, error.to_string())
+        },
+        HistoryError::AboveHead { .. } => RpcError::invalid_params(error.to_string()),
+        error => RpcError::internal(error),
+    }
+}
+
+fn rejected(rejection: TxRejection) -> RpcError {
+    RpcError {
+        data: serde_json::to_value(&rejection).ok(),
+        ..RpcError::new(TRANSACTION_REJECTED, rejection.to_string())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_node {
+    use super::*;
+    use crate::chain::verify::SignedMessage;
+    use crate::crypto::key_pair::KeyPair;
+    use std::sync::Mutex;
+
+    pub const CHAIN_ID: u64 = 1;
+
+    // Accepts every signed transaction on chain 1, vouching for it with its
+    // own key, and reports the same balance and nonce for every account.
+    // Submitted transactions make up the pool. Models in `restricted` may not
+    // be invoked.
+    #[derive(Default)]
+    pub struct StubNode {
+        pub nonce: Nonce,
+        pub min_gas_price: u64,
+        pub submitted: Mutex<Vec<Transaction>>,
+        pub restricted: Vec<Address>,
+    }
+
+    #[async_trait]
+    impl NodeApi for StubNode {
+        fn chain_id(&self) -> u64 {
+            CHAIN_ID
+        }
+
+        fn signature_check(&self) -> SignatureCheck {
+            Arc::new(|transaction: &Transaction| {
+                transaction.signature.as_ref()?;
+                let secret = ed25519_dalek::SecretKey::from_bytes(&[7; 32]).unwrap();
+                let public_key = ed25519_dalek::PublicKey::from(&secret);
+                let message = transaction.signing_hash().ok()?.as_bytes().to_vec();
+                let signature = ed25519_dalek::ExpandedSecretKey::from(&secret).sign(&message, &public_key);
+                Some(SignedMessage {
+                    message,
+                    signature: signature.to_bytes().to_vec(),
+                    public_key: public_key.to_bytes().to_vec(),
+                })
+            })
+        }
+
+        async fn balance(&self, _address: &Address) -> Result<Balance, String> {
+            Ok(Balance::from(1_000_000u64))
+        }
+
+        async fn nonce(&self, _address: &Address) -> Result<Nonce, String> {
+            Ok(self.nonce)
+        }
+
+        async fn submit_transaction(&self, transaction: Transaction) -> Result<(), String> {
+            self.submitted.lock().unwrap().push(transaction);
+            Ok(())
+        }
+
+        async fn pool_transactions(&self) -> Result<Vec<Transaction>, String> {
+            Ok(self.submitted.lock().unwrap().clone())
+        }
+
+        fn min_gas_price(&self) -> u64 {
+            self.min_gas_price
+        }
+
+        async fn validators(&self) -> ValidatorSet {
+            ValidatorSet::new(0, vec![])
+        }
+
+        async fn model(&self, _model: &Address) -> Result<Option<Listing>, String> {
+            Ok(None)
+        }
+
+        async fn has_model_access(&self, model: &Address, _caller: &Address) -> Result<bool, String> {
+            Ok(!self.restricted.contains(model))
+        }
+
+        async fn proposal(&self, _id: ProposalId) -> Result<Option<Proposal>, String> {
+            Ok(None)
+        }
+
+        async fn proposals(&self) -> Result<Vec<Proposal>, String> {
+            Ok(Vec::new())
+        }
+
+        async fn upgrade_plan(&self) -> Result<Option<UpgradePlan>, String> {
+            Ok(None)
+        }
+
+        async fn treasury_balance(&self) -> Result<Balance, String> {
+            Ok(Balance::zero())
+        }
+
+        async fn treasury_accounts(&self, _from: u64, _to: u64) -> Result<Vec<BlockAccount>, String> {
+            Ok(Vec::new())
+        }
+    }
+
+    pub fn signed_transaction(nonce: u64) -> Transaction {
+        let key_pair = KeyPair::generate();
+        let mut tx = Transaction::new(
+            nonce,
+            Address::random(),
+            Address::random(),
+            1,
+            1,
+            21000,
+            vec![],
+            TransactionType::Transfer,
+        );
+        tx.sign(key_pair.private_key()).unwrap();
+        tx
+    }
+
+    // Hex of the envelope `tx_sendRawTransaction` takes
+    pub fn raw(transaction: &Transaction, chain_id: u64) -> String {
+        to_hex(&bincode::serialize(&RawTransaction { chain_id, transaction: transaction.clone() }).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_node::{raw, signed_transaction, StubNode, CHAIN_ID};
+    use super::*;
+    use crate::rpc::jsonrpc::INVALID_PARAMS;
+    use crate::storage::db::Database;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_serves_blocks_transactions_and_submissions() {
+        let temp_dir = TempDir::new().unwrap();
+        let chain = Arc::new(ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap())));
+        let model = Address::random();
+        let node = Arc::new(StubNode { nonce: 1, restricted: vec![model], ..StubNode::default() });
+        let api = RpcApi::new(chain.clone(), node.clone());
+
+        let genesis = Block::new([0; 32], vec![], 1).unwrap();
+        chain.import_block(0, &genesis, &[], &[]).await.unwrap();
+        let tx = signed_transaction(0);
+        let block = Block::new(genesis.hash(), vec![tx.clone()], 1).unwrap();
+        chain.import_block(1, &block, &[], &[]).await.unwrap();
+
+        let by_height = api.call("chain_getBlock", json!([1])).await.unwrap();
+        assert_eq!(by_height["hash"], json!(to_hex(&block.hash())));
+        assert_eq!(api.call("chain_getBlock", json!(["latest"])).await.unwrap(), by_height);
+        assert_eq!(api.call("chain_getBlock", json!([to_hex(&block.hash())])).await.unwrap(), by_height);
+        assert_eq!(api.call("chain_getBlock", json!([9])).await.unwrap(), Value::Null);
+
+        let tx_hash = to_hex(tx.hash().unwrap().as_bytes());
+        let found = api.call("chain_getTransaction", json!([tx_hash])).await.unwrap();
+        assert_eq!((found["block_height"].clone(), found["index"].clone()), (json!(1), json!(0)));
+        assert_eq!(found["receipt"], Value::Null);
+
+        assert_eq!(
+            api.call("state_getBalance", json!([Address::random()])).await.unwrap(),
+            json!(Balance::from(1_000_000u64))
+        );
+        let at = api.call("state_getBalanceAt", json!([Address::random(), 1])).await.unwrap();
+        assert_eq!(at, json!(Balance::from(0u64)));
+        assert_eq!(
+            api.call("staking_getStakeAt", json!([Address::random(), 9])).await.unwrap_err().code,
+            INVALID_PARAMS
+        );
+        let sent = api.call("tx_sendRawTransaction", json!([raw(&signed_transaction(1), CHAIN_ID)])).await;
+        assert_eq!(sent.unwrap(), json!(to_hex(node.submitted.lock().unwrap()[0].hash().unwrap().as_bytes())));
+
+        let send = |transaction: Transaction, chain_id| {
+            let api = &api;
+            async move { api.call("tx_sendRawTransaction", json!([raw(&transaction, chain_id)])).await.unwrap_err() }
+        };
+        let unsigned = send(Transaction { signature: None, ..signed_transaction(2) }, CHAIN_ID).await;
+        assert_eq!((unsigned.code, unsigned.data), (TRANSACTION_REJECTED, Some(json!({"reason": "unsigned"}))));
+        let wrong_chain = send(signed_transaction(2), 5).await;
+        assert_eq!(wrong_chain.data.unwrap(), json!({"reason": "wrong_chain", "expected": 1, "got": 5}));
+        assert_eq!(send(signed_transaction(0), CHAIN_ID).await.data.unwrap()["reason"], json!("nonce_too_low"));
+        let expensive = Transaction { gas_price: 1_000, ..signed_transaction(2) };
+        assert_eq!(send(expensive, CHAIN_ID).await.data.unwrap()["reason"], json!("insufficient_funds"));
+        let invoke = Transaction { to: model, transaction_type: TransactionType::AIModelInvoke, ..signed_transaction(2) };
+        assert_eq!(send(invoke, CHAIN_ID).await.data.unwrap()["reason"], json!("access_denied"));
+        assert_eq!(node.submitted.lock().unwrap().len(), 1);
+        assert_eq!(api.call("txpool_status", json!([])).await.unwrap(), json!({"pending": 1, "queued": 0}));
+        let sender = node.submitted.lock().unwrap()[0].from;
+        let inspected = api.call("txpool_inspect", json!([sender])).await.unwrap();
+        assert_eq!(
+            (inspected["account_nonce"].clone(), inspected["pending"][0]["nonce"].clone()),
+            (json!(1), json!(1))
+        );
+        assert_eq!(api.call("chain_getBlock", json!(["0x12"])).await.unwrap_err().code, INVALID_PARAMS);
+        assert_eq!(api.call("chain_mine", json!([])).await.unwrap_err().code, METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,243 @@
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use hyper::header::{HeaderMap, AUTHORIZATION};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::rpc::jsonrpc::{Methods, RpcError, FORBIDDEN};
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("API key {0} needs key_sha256 as 64 hex characters")]
+    MalformedKeyHash(String),
+    #[error("JWT secret must be hex")]
+    MalformedSecret,
+    #[error("JWT secret is {0} bytes, at least {MIN_JWT_SECRET_LEN} are required")]
+    WeakSecret(usize),
+    #[error("Admin token is empty")]
+    EmptyToken,
+}
+
+// HS256 keys shorter than the hash output weaken the MAC
+const MIN_JWT_SECRET_LEN: usize = 32;
+
+// `[[rpc.admin.api_keys]]`. Only a hash of the key is configured, so the
+// config file doesn't hold a usable credential.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    // Hex SHA-256 of the key
+    pub key_sha256: String,
+    // Namespaces the key may call, all when empty
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+}
+
+// Who a request was authenticated as and what it may call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    pub subject: String,
+    // All when empty
+    pub namespaces: Vec<String>,
+}
+
+impl Grant {
+    pub fn allows(&self, method: &str) -> bool {
+        let namespace = method.split_once('_').map_or(method, |(namespace, _)| namespace);
+        self.namespaces.is_empty() || self.namespaces.iter().any(|allowed| allowed == namespace)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    exp: u64,
+    #[serde(default)]
+    sub: Option<String>,
+    #[serde(default)]
+    namespaces: Vec<String>,
+}
+
+struct Key {
+    name: String,
+    hash: [u8; 32],
+    namespaces: Vec<String>,
+}
+
+// Checks `Authorization: Bearer <credential>` against, in turn, the admin
+// token (full access), HS256 JWTs signed with the shared secret (which must
+// carry `exp` and may restrict `namespaces`) and the configured API keys
+pub struct Authenticator {
+    token: Zeroizing<String>,
+    jwt_secret: Option<Zeroizing<Vec<u8>>>,
+    keys: Vec<Key>,
+}
+
+impl Authenticator {
+    pub fn new(token: String, jwt_secret: Option<&str>, api_keys: &[ApiKey]) -> Result<Self, AuthError> {
+        if token.is_empty() {
+            return Err(AuthError::EmptyToken);
+        }
+        let jwt_secret = jwt_secret.map(|secret| from_hex(secret).ok_or(AuthError::MalformedSecret)).transpose()?;
+        if let Some(secret) = &jwt_secret {
+            if secret.len() < MIN_JWT_SECRET_LEN {
+                return Err(AuthError::WeakSecret(secret.len()));
+            }
+        }
+        let keys = api_keys
+            .iter()
+            .map(|key| {
+                let hash = from_hex(&key.key_sha256).and_then(|hash| hash.try_into().ok());
+                let hash = hash.ok_or_else(|| AuthError::MalformedKeyHash(key.name.clone()))?;
+                Ok(Key { name: key.name.clone(), hash, namespaces: key.namespaces.clone() })
+            })
+            .collect::<Result<_, AuthError>>()?;
+        Ok(Self { token: Zeroizing::new(token), jwt_secret: jwt_secret.map(Zeroizing::new), keys })
+    }
+
+    pub fn authenticate(&self, headers: &HeaderMap) -> Option<Grant> {
+        let presented = headers.get(AUTHORIZATION)?.as_bytes().strip_prefix(b"Bearer ")?;
+        if constant_time_eq(presented, self.token.as_bytes()) {
+            return Some(Grant { subject: "admin token".to_string(), namespaces: Vec::new() });
+        }
+        if let Some(secret) = &self.jwt_secret {
+            if presented.iter().filter(|byte| **byte == b'.').count() == 2 {
+                return verify_jwt(secret, presented);
+            }
+        }
+        let hash: [u8; 32] = Sha256::digest(presented).into();
+        self.keys
+            .iter()
+            .find(|key| constant_time_eq(&key.hash, &hash))
+            .map(|key| Grant { subject: format!("API key {}", key.name), namespaces: key.namespaces.clone() })
+    }
+}
+
+fn verify_jwt(secret: &[u8], token: &[u8]) -> Option<Grant> {
+    let token = std::str::from_utf8(token).ok()?;
+    let (signed, signature) = token.rsplit_once('.')?;
+    let (header, claims) = signed.split_once('.')?;
+    let header: JwtHeader = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+    // Anything else, "none" in particular, is refused
+    if header.alg != "HS256" {
+        return None;
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).ok()?;
+    mac.update(signed.as_bytes());
+    mac.verify_slice(&URL_SAFE_NO_PAD.decode(signature).ok()?).ok()?;
+    let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).ok()?).ok()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+    if claims.exp <= now {
+        return None;
+    }
+    let subject = format!("JWT {}", claims.sub.as_deref().unwrap_or("without subject"));
+    Some(Grant { subject, namespaces: claims.namespaces })
+}
+
+// The methods a grant allows; other calls fail without reaching `api`
+pub struct Scoped<'a> {
+    api: &'a dyn Methods,
+    grant: Grant,
+}
+
+impl<'a> Scoped<'a> {
+    pub fn new(api: &'a dyn Methods, grant: Grant) -> Self {
+        Self { api, grant }
+    }
+}
+
+#[async_trait]
+impl<'a> Methods for Scoped<'a> {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        if !self.grant.allows(method) {
+            warn!("Refused {} to {}", method, self.grant.subject);
+            return Err(RpcError::new(FORBIDDEN, format!("{} may not call {}", self.grant.subject, method)));
+        }
+        debug!("{} calls {}", self.grant.subject, method);
+        self.api.call(method, params).await
+    }
+}
+
+// Compares in constant time so a credential can't be guessed byte by byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+    use serde_json::json;
+
+    struct Echo;
+
+    #[async_trait]
+    impl Methods for Echo {
+        async fn call(&self, method: &str, _params: Value) -> Result<Value, RpcError> {
+            Ok(json!(method))
+        }
+    }
+
+    fn bearer(credential: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", credential)).unwrap());
+        headers
+    }
+
+    fn jwt(secret: &[u8], alg: &str, claims: Value) -> String {
+        let encode = |value: Value| URL_SAFE_NO_PAD.encode(value.to_string());
+        let signed = format!("{}.{}", encode(json!({ "alg": alg, "typ": "JWT" })), encode(claims));
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(signed.as_bytes());
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    #[tokio::test]
+    async fn test_authenticates_token_jwt_and_api_keys() {
+        let secret = [7u8; 32];
+        let secret_hex: String = secret.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let key_hash: String = Sha256::digest(b"wallet-key").iter().map(|byte| format!("{:02x}", byte)).collect();
+        let keys = [ApiKey { name: "signer".into(), key_sha256: key_hash, namespaces: vec!["wallet".into()] }];
+        let auth = Authenticator::new("t0ken".into(), Some(&secret_hex), &keys).unwrap();
+
+        assert!(auth.authenticate(&HeaderMap::new()).is_none());
+        assert_eq!(auth.authenticate(&bearer("t0ken")).unwrap().namespaces, Vec::<String>::new());
+        assert!(auth.authenticate(&bearer("t0ken0")).is_none());
+        let signer = auth.authenticate(&bearer("wallet-key")).unwrap();
+        assert_eq!(signer.subject, "API key signer");
+
+        let exp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 60;
+        let claims = json!({ "sub": "ops", "exp": exp, "namespaces": ["admin"] });
+        assert_eq!(auth.authenticate(&bearer(&jwt(&secret, "HS256", claims.clone()))).unwrap().subject, "JWT ops");
+        assert!(auth.authenticate(&bearer(&jwt(&[8; 32], "HS256", claims.clone()))).is_none());
+        assert!(auth.authenticate(&bearer(&jwt(&secret, "none", claims))).is_none());
+        assert!(auth.authenticate(&bearer(&jwt(&secret, "HS256", json!({ "exp": exp - 120 })))).is_none());
+
+        let scoped = Scoped::new(&Echo, signer);
+        assert_eq!(scoped.call("wallet_list", json!([])).await.unwrap(), json!("wallet_list"));
+        assert_eq!(scoped.call("admin_peers", json!([])).await.unwrap_err().code, FORBIDDEN);
+        assert!(matches!(Authenticator::new("t".into(), Some("xyz"), &[]), Err(AuthError::MalformedSecret)));
+        assert!(matches!(Authenticator::new("t".into(), Some(""), &[]), Err(AuthError::WeakSecret(0))));
+        assert!(matches!(Authenticator::new("t".into(), Some(&secret_hex[..62]), &[]), Err(AuthError::WeakSecret(31))));
+        assert!(matches!(Authenticator::new(String::new(), None, &[]), Err(AuthError::EmptyToken)));
+    }
+}
@@ -0,0 +1,151 @@
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use hyper::{Body, Client, Method, Request, Uri};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+use crate::chain::block::BlockHeader;
+use crate::chain::transaction::RawTransaction;
+use crate::network::light_client::ValidatorSet;
+use crate::rpc::jsonrpc::{to_hex, Response, RpcError};
+use crate::types::{Address, Balance};
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("Invalid node URL: {0}")]
+    InvalidUrl(String),
+    #[error("Bearer token is not a valid header value")]
+    InvalidToken,
+    #[error("HTTP error: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("Node answered with HTTP status {0}")]
+    Status(u16),
+    #[error("Malformed response: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("RPC error {}: {}", .0.code, .0.message)]
+    Rpc(RpcError),
+}
+
+#[derive(Deserialize)]
+struct HeaderView {
+    header: BlockHeader,
+}
+
+// JSON-RPC over HTTP to a node, for wallets and SDKs. Answers are only as
+// trustworthy as the node; check them against verified headers with
+// `light::LightClient`.
+pub struct RpcClient {
+    url: Uri,
+    http: Client<HttpConnector>,
+    next_id: AtomicU64,
+    // Sent as `Authorization: Bearer`, e.g. for the admin listener
+    authorization: Option<HeaderValue>,
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> Result<Self, ClientError> {
+        let url = url.parse().map_err(|_| ClientError::InvalidUrl(url.to_string()))?;
+        Ok(Self { url, http: Client::new(), next_id: AtomicU64::new(1), authorization: None })
+    }
+
+    pub fn with_bearer(mut self, token: &str) -> Result<Self, ClientError> {
+        let value = HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|_| ClientError::InvalidToken)?;
+        self.authorization = Some(value);
+        Ok(self)
+    }
+
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, ClientError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let body = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": id });
+        let mut request =
+            Request::builder().method(Method::POST).uri(self.url.clone()).header(CONTENT_TYPE, "application/json");
+        if let Some(authorization) = &self.authorization {
+            request = request.header(AUTHORIZATION, authorization.clone());
+        }
+        let request = request.body(Body::from(body.to_string())).expect("method, URI and header are valid");
+        let response = self.http.request(request).await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status().as_u16()));
+        }
+        let response: Response = serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await?)?;
+        match response.error {
+            Some(error) => Err(ClientError::Rpc(error)),
+            None => Ok(serde_json::from_value(response.result.unwrap_or(Value::Null))?),
+        }
+    }
+
+    pub async fn header(&self, height: u64) -> Result<Option<BlockHeader>, ClientError> {
+        let view: Option<HeaderView> = self.call("chain_getBlock", json!([height])).await?;
+        Ok(view.map(|view| view.header))
+    }
+
+    pub async fn balance(&self, address: &Address) -> Result<Balance, ClientError> {
+        self.call("state_getBalance", json!([address])).await
+    }
+
+    pub async fn validators(&self) -> Result<ValidatorSet, ClientError> {
+        self.call("consensus_getValidators", json!([])).await
+    }
+
+    // Returns the transaction hash
+    pub async fn send_raw_transaction(&self, raw: &RawTransaction) -> Result<String, ClientError> {
+        let bytes = bincode::serialize(raw).expect("transactions serialize");
+        self.call("tx_sendRawTransaction", json!([to_hex(&bytes)])).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::jsonrpc::{Methods, METHOD_NOT_FOUND};
+    use crate::rpc::server::handle_body;
+    use async_trait::async_trait;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Response as HttpResponse, Server};
+    use std::convert::Infallible;
+    use std::sync::Arc;
+
+    struct Validators;
+
+    #[async_trait]
+    impl Methods for Validators {
+        async fn call(&self, method: &str, _params: Value) -> Result<Value, RpcError> {
+            match method {
+                "consensus_getValidators" => Ok(json!({"height": 7, "validators": []})),
+                _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method {} not found", method))),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_calls_methods_and_surfaces_rpc_errors() {
+        let api = Arc::new(Validators);
+        let make_service = make_service_fn(move |_| {
+            let api = api.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let api = api.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                        let reply = handle_body(api.as_ref(), &body, 10).await.unwrap_or_default();
+                        Ok::<_, Infallible>(HttpResponse::new(Body::from(reply)))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        let client = RpcClient::new(&url).unwrap();
+        assert_eq!(client.validators().await.unwrap(), ValidatorSet::new(7, vec![]));
+        match client.header(1).await {
+            Err(ClientError::Rpc(error)) => assert_eq!(error.code, METHOD_NOT_FOUND),
+            other => panic!("expected an RPC error, got {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(RpcClient::new("not a url"), Err(ClientError::InvalidUrl(_))));
+    }
+}
@@ -0,0 +1,324 @@
+#![cfg(feature = "grpc")]
+
+use futures::stream::{self, Stream};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+use tonic::{Request, Response, Status};
+
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::transaction::TransactionReceipt;
+use crate::network::codec::decode_bounded;
+use crate::rpc::api::{BlockId, RpcApi};
+use crate::rpc::jsonrpc::{from_hex, to_hex, RpcError, INVALID_PARAMS, TRANSACTION_REJECTED};
+use crate::rpc::pubsub::ChainEvent;
+use crate::rpc::server::RpcServerError;
+
+pub mod proto {
+    tonic::include_proto!("omnitensor.v1");
+}
+
+use proto::get_block_request::Id;
+use proto::node_server::{Node, NodeServer};
+
+// `[rpc.grpc]`, only with the `grpc` feature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+    pub listen: SocketAddr,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self { enabled: false, listen: SocketAddr::from(([127, 0, 0, 1], 9090)) }
+    }
+}
+
+type EventStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+// The `Node` service from proto/omnitensor/v1/node.proto, answered by the
+// same `RpcApi` as JSON-RPC
+pub struct NodeService {
+    api: Arc<RpcApi>,
+    events: broadcast::Sender<ChainEvent>,
+}
+
+impl NodeService {
+    pub fn new(api: Arc<RpcApi>, events: broadcast::Sender<ChainEvent>) -> Self {
+        Self { api, events }
+    }
+}
+
+#[tonic::async_trait]
+impl Node for NodeService {
+    async fn get_block(&self, request: Request<proto::GetBlockRequest>) -> Result<Response<proto::Block>, Status> {
+        let id = match request.into_inner().id {
+            Some(Id::Height(height)) => BlockId::Height(height),
+            Some(Id::Hash(hash)) => BlockId::Tag(to_hex(&hash)),
+            Some(Id::Latest(_)) | None => BlockId::Tag("latest".to_string()),
+        };
+        let view = self.api.block(id).await.map_err(status)?.ok_or_else(|| Status::not_found("Block not found"))?;
+        Ok(Response::new(block_message(view.height, &view.block)))
+    }
+
+    async fn get_transaction(
+        &self,
+        request: Request<proto::GetTransactionRequest>,
+    ) -> Result<Response<proto::Transaction>, Status> {
+        let hash = to_hex(&request.into_inner().hash);
+        let view = self
+            .api
+            .transaction(&hash)
+            .await
+            .map_err(status)?
+            .ok_or_else(|| Status::not_found("Transaction not found"))?;
+        Ok(Response::new(proto::Transaction {
+            block_height: view.block_height,
+            block_hash: from_hex(&view.block_hash).unwrap_or_default(),
+            index: view.index,
+            transaction: encode(&view.transaction),
+            receipt: view.receipt.as_ref().map(receipt_message),
+        }))
+    }
+
+    async fn get_balance(
+        &self,
+        request: Request<proto::GetBalanceRequest>,
+    ) -> Result<Response<proto::GetBalanceResponse>, Status> {
+        let address = decode_bounded(&request.into_inner().address)
+            .map_err(|e| Status::invalid_argument(format!("malformed address: {}", e)))?;
+        let balance = self.api.balance(&address).await.map_err(status)?;
+        let balance = serde_json::to_string(&balance).map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(proto::GetBalanceResponse { balance }))
+    }
+
+    async fn send_raw_transaction(
+        &self,
+        request: Request<proto::SendRawTransactionRequest>,
+    ) -> Result<Response<proto::SendRawTransactionResponse>, Status> {
+        let hash = self.api.send_raw_transaction(&to_hex(&request.into_inner().transaction)).await.map_err(status)?;
+        Ok(Response::new(proto::SendRawTransactionResponse { hash: from_hex(&hash).unwrap_or_default() }))
+    }
+
+    async fn get_validators(
+        &self,
+        _request: Request<proto::GetValidatorsRequest>,
+    ) -> Result<Response<proto::ValidatorSet>, Status> {
+        let set = self.api.validators().await;
+        let validators = set
+            .validators
+            .iter()
+            .map(|validator| proto::Validator { public_key: encode(&validator.public_key), power: validator.power })
+            .collect();
+        Ok(Response::new(proto::ValidatorSet { height: set.height, validators }))
+    }
+
+    type StreamBlocksStream = EventStream<proto::Block>;
+
+    async fn stream_blocks(
+        &self,
+        _request: Request<proto::StreamBlocksRequest>,
+    ) -> Result<Response<Self::StreamBlocksStream>, Status> {
+        Ok(Response::new(event_stream(self.events.subscribe(), |event| match event {
+            ChainEvent::NewBlock { height, block, .. } => Some(block_message(height, &block)),
+            _ => None,
+        })))
+    }
+
+    type StreamInferenceResultsStream = EventStream<proto::InferenceResult>;
+
+    async fn stream_inference_results(
+        &self,
+        request: Request<proto::StreamInferenceResultsRequest>,
+    ) -> Result<Response<Self::StreamInferenceResultsStream>, Status> {
+        let jobs = request.into_inner().jobs;
+        Ok(Response::new(event_stream(self.events.subscribe(), move |event| match event {
+            ChainEvent::InferenceResult { height, job, provider, output_hash }
+                if jobs.is_empty() || jobs.iter().any(|wanted| wanted.as_slice() == job.as_bytes()) =>
+            {
+                Some(proto::InferenceResult {
+                    height,
+                    job: job.as_bytes().to_vec(),
+                    provider: encode(&provider),
+                    output_hash: output_hash.as_bytes().to_vec(),
+                })
+            }
+            _ => None,
+        })))
+    }
+}
+
+// Ends with DATA_LOSS once the receiver falls behind the channel, like a
+// lagging WebSocket connection is closed
+fn event_stream<T: Send + 'static>(
+    events: broadcast::Receiver<ChainEvent>,
+    pick: impl Fn(ChainEvent) -> Option<T> + Send + 'static,
+) -> EventStream<T> {
+    Box::pin(stream::unfold((events, pick), |(mut events, pick)| async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Some(item) = pick(event) {
+                        return Some((Ok(item), (events, pick)));
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    let error = Status::data_loss(format!("Missed {} events, resubscribe", missed));
+                    return Some((Err(error), (events, pick)));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }))
+}
+
+fn status(error: RpcError) -> Status {
+    match error.code {
+        INVALID_PARAMS => Status::invalid_argument(error.message),
+        TRANSACTION_REJECTED => Status::failed_precondition(error.message),
+        _ => Status::internal(error.message),
+    }
+}
+
+fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    bincode::serialize(value).expect("chain types serialize")
+}
+
+fn header_message(header: &BlockHeader) -> proto::BlockHeader {
+    proto::BlockHeader {
+        version: header.version,
+        prev_block_hash: header.prev_block_hash.to_vec(),
+        merkle_root: header.merkle_root.to_vec(),
+        state_root: header.state_root.to_vec(),
+        receipts_root: header.receipts_root.to_vec(),
+        timestamp: header.timestamp,
+        difficulty: header.difficulty,
+        nonce: header.nonce,
+    }
+}
+
+fn block_message(height: u64, block: &Block) -> proto::Block {
+    proto::Block {
+        height,
+        hash: block.hash().to_vec(),
+        header: Some(header_message(&block.header)),
+        transactions: block.transactions.iter().map(encode).collect(),
+    }
+}
+
+fn receipt_message(receipt: &TransactionReceipt) -> proto::Receipt {
+    proto::Receipt {
+        transaction_hash: receipt.transaction_hash.as_bytes().to_vec(),
+        gas_used: receipt.gas_used,
+        status: receipt.status,
+        logs: receipt
+            .logs
+            .iter()
+            .map(|log| proto::Log {
+                address: encode(&log.address),
+                topics: log.topics.iter().map(|topic| topic.as_bytes().to_vec()).collect(),
+                data: log.data.clone(),
+            })
+            .collect(),
+    }
+}
+
+pub struct GrpcServer {
+    local_addr: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl GrpcServer {
+    pub async fn start(
+        config: &GrpcConfig,
+        api: Arc<RpcApi>,
+        events: broadcast::Sender<ChainEvent>,
+    ) -> Result<Self, RpcServerError> {
+        let listener =
+            TcpListener::bind(config.listen).await.map_err(|e| RpcServerError::GrpcBind(config.listen, e))?;
+        let local_addr = listener.local_addr().map_err(|e| RpcServerError::GrpcBind(config.listen, e))?;
+        let incoming = Box::pin(stream::unfold(listener, |listener| async move {
+            let accepted = listener.accept().await.map(|(stream, _)| stream);
+            Some((accepted, listener))
+        }));
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        let service = NodeServer::new(NodeService::new(api, events));
+        let task = tokio::spawn(async move {
+            let served = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(incoming, async {
+                    let _ = stopped.await;
+                })
+                .await;
+            if let Err(e) = served {
+                warn!("gRPC server failed: {}", e);
+            }
+        });
+        info!("gRPC listening on {}", local_addr);
+        Ok(Self { local_addr, shutdown, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub async fn stop(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::store::ChainStore;
+    use crate::crypto::hash::Hash;
+    use crate::rpc::api::test_node::StubNode;
+    use crate::storage::db::Database;
+    use crate::types::Address;
+    use futures::StreamExt;
+    use tempfile::TempDir;
+    use tonic::Code;
+
+    #[tokio::test]
+    async fn test_serves_blocks_and_streams_inference_results() {
+        let temp_dir = TempDir::new().unwrap();
+        let chain = Arc::new(ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap())));
+        let genesis = Block::new([0; 32], vec![], 1).unwrap();
+        chain.import_block(0, &genesis, &[], &[]).await.unwrap();
+        let (events, _) = broadcast::channel(16);
+        let service = NodeService::new(Arc::new(RpcApi::new(chain, Arc::new(StubNode::default()))), events.clone());
+
+        let latest = service.get_block(Request::new(proto::GetBlockRequest { id: None })).await.unwrap().into_inner();
+        assert_eq!((latest.height, latest.hash), (0, genesis.hash().to_vec()));
+        let missing = service.get_block(Request::new(proto::GetBlockRequest { id: Some(Id::Height(5)) })).await;
+        assert_eq!(missing.unwrap_err().code(), Code::NotFound);
+        let malformed =
+            service.send_raw_transaction(Request::new(proto::SendRawTransactionRequest { transaction: vec![1] }));
+        assert_eq!(malformed.await.unwrap_err().code(), Code::InvalidArgument);
+
+        let job = Hash::hash(b"job");
+        let request = proto::StreamInferenceResultsRequest { jobs: vec![job.as_bytes().to_vec()] };
+        let mut results = service.stream_inference_results(Request::new(request)).await.unwrap().into_inner();
+        let result = |job: &Hash, height| ChainEvent::InferenceResult {
+            height,
+            job: job.clone(),
+            provider: Address::random(),
+            output_hash: Hash::hash(b"output"),
+        };
+        events.send(result(&Hash::hash(b"other"), 7)).unwrap();
+        events.send(ChainEvent::PendingTransaction(Hash::hash(b"tx"))).unwrap();
+        events.send(result(&job, 8)).unwrap();
+
+        let delivered = results.next().await.unwrap().unwrap();
+        assert_eq!((delivered.height, delivered.job), (8, job.as_bytes().to_vec()));
+    }
+}
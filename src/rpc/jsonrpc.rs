@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+// Implementation-defined server errors, -32000 to -32099
+pub const TRANSACTION_REJECTED: i64 = -32010;
+pub const ACCOUNT_LOCKED: i64 = -32020;
+pub const LIMIT_EXCEEDED: i64 = -32030;
+pub const STATE_PRUNED: i64 = -32040;
+pub const FORBIDDEN: i64 = -32050;
+pub const RATE_LIMITED: i64 = -32060;
+pub const SIGNING_REJECTED: i64 = -32070;
+// Failures inside the node, by the category of crate::error::Error
+pub const STORAGE_ERROR: i64 = -32080;
+pub const NETWORK_ERROR: i64 = -32081;
+pub const CONSENSUS_ERROR: i64 = -32082;
+pub const EXECUTION_ERROR: i64 = -32083;
+
+// A namespace of methods a transport dispatches calls to
+#[async_trait]
+pub trait Methods: Send + Sync {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError>;
+}
+
+// Routes each call to the methods registered for its `<namespace>_` prefix
+#[derive(Default)]
+pub struct Namespaces {
+    namespaces: Vec<(&'static str, Arc<dyn Methods>)>,
+}
+
+impl Namespaces {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, namespace: &'static str, methods: Arc<dyn Methods>) -> Self {
+        self.namespaces.push((namespace, methods));
+        self
+    }
+}
+
+#[async_trait]
+impl Methods for Namespaces {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let namespace = method.split_once('_').map_or(method, |(namespace, _)| namespace);
+        match self.namespaces.iter().find(|(name, _)| *name == namespace) {
+            Some((_, methods)) => methods.call(method, params).await,
+            None => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method {} not found", method))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    // Absent for notifications, which get no response
+    pub id: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), data: None }
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+
+    pub fn internal(error: impl std::fmt::Display) -> Self {
+        Self::new(INTERNAL_ERROR, error.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+    pub id: Value,
+}
+
+impl Response {
+    pub fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self { jsonrpc: "2.0".to_string(), result, error, id }
+    }
+}
+
+// Positional (`[a, b]`) or omitted params; trailing ones may be left out
+// when the target type is an `Option`
+pub struct Params(Vec<Value>);
+
+impl Params {
+    pub fn parse(params: Value) -> Result<Self, RpcError> {
+        match params {
+            Value::Null => Ok(Self(Vec::new())),
+            Value::Array(values) => Ok(Self(values)),
+            _ => Err(RpcError::invalid_params("params must be an array")),
+        }
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, index: usize) -> Result<T, RpcError> {
+        let value = self.0.get(index).cloned().unwrap_or(Value::Null);
+        serde_json::from_value(value).map_err(|e| RpcError::invalid_params(format!("param {}: {}", index, e)))
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(2 + bytes.len() * 2);
+    text.push_str("0x");
+    for byte in bytes {
+        text.push_str(&format!("{:02x}", byte));
+    }
+    text
+}
+
+pub fn from_hex(text: &str) -> Option<Vec<u8>> {
+    let text = text.strip_prefix("0x").unwrap_or(text);
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| std::str::from_utf8(pair).ok().and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+pub fn hash_from_hex(text: &str) -> Result<[u8; 32], RpcError> {
+    from_hex(text)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| RpcError::invalid_params(format!("{} is not a 32-byte hex hash", text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hex_round_trip_and_params() {
+        assert_eq!(to_hex(&[0, 171, 255]), "0x00abff");
+        assert_eq!(from_hex("0x00abff"), Some(vec![0, 171, 255]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert!(hash_from_hex(&to_hex(&[7; 32])).is_ok());
+        assert_eq!(hash_from_hex("0x07").unwrap_err().code, INVALID_PARAMS);
+
+        let params = Params::parse(json!([5])).unwrap();
+        assert_eq!(params.get::<u64>(0).unwrap(), 5);
+        assert_eq!(params.get::<Option<bool>>(1).unwrap(), None);
+        assert!(params.get::<String>(0).is_err());
+        assert!(Params::parse(json!({"height": 5})).is_err());
+    }
+}
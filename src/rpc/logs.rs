@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::chain::block::Block;
+use crate::chain::store::ChainStore;
+use crate::rpc::api::BlockId;
+use crate::rpc::jsonrpc::{hash_from_hex, to_hex, RpcError, LIMIT_EXCEEDED};
+use crate::rpc::pubsub::{LogFilter, LogFilterParams};
+
+// `[rpc.logs]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogsConfig {
+    // Blocks a single query may cover; filter changes are returned in
+    // chunks of this size
+    pub max_block_range: u64,
+    // Queries matching more logs fail instead of returning a partial list
+    pub max_results: usize,
+    pub max_filters: usize,
+    // Filters not polled for this long are uninstalled
+    pub filter_timeout_secs: u64,
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self { max_block_range: 10_000, max_results: 10_000, max_filters: 1_000, filter_timeout_secs: 300 }
+    }
+}
+
+// A `LogFilter` over a block range. Both ends default to the head; a filter
+// installed without `from_block` only sees blocks imported after it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LogQuery {
+    from_block: Option<BlockId>,
+    to_block: Option<BlockId>,
+    #[serde(flatten)]
+    filter: LogFilterParams,
+}
+
+struct Installed {
+    filter: LogFilter,
+    from: u64,
+    // `None` follows the head
+    to: Option<u64>,
+    // First height the next poll covers
+    next: u64,
+    polled: Instant,
+}
+
+#[derive(Default)]
+struct Filters {
+    next_id: u64,
+    installed: HashMap<String, Installed>,
+}
+
+// Log queries and polling filters, answered from stored receipts. Blocks
+// whose bloom rules the filter out are skipped without reading receipts.
+pub struct LogFilters {
+    config: LogsConfig,
+    filters: Mutex<Filters>,
+}
+
+impl LogFilters {
+    pub fn new(config: LogsConfig) -> Self {
+        Self { config, filters: Mutex::new(Filters::default()) }
+    }
+
+    pub async fn logs(&self, chain: &ChainStore, query: LogQuery) -> Result<Vec<Value>, RpcError> {
+        let head = match head(chain).await? {
+            Some(head) => head,
+            None => return Ok(Vec::new()),
+        };
+        let from = resolve(chain, query.from_block, head).await?;
+        let to = resolve(chain, query.to_block, head).await?;
+        self.find(chain, &LogFilter::parse(query.filter)?, from, to).await
+    }
+
+    // Returns the new filter's id
+    pub async fn install(&self, chain: &ChainStore, query: LogQuery) -> Result<String, RpcError> {
+        let head = head(chain).await?;
+        let from = match query.from_block {
+            Some(id) => resolve(chain, Some(id), head.unwrap_or(0)).await?,
+            None => head.map_or(0, |head| head + 1),
+        };
+        let to = match query.to_block {
+            Some(BlockId::Tag(tag)) if tag == "latest" => None,
+            Some(id) => Some(resolve(chain, Some(id), head.unwrap_or(0)).await?),
+            None => None,
+        };
+        let filter = LogFilter::parse(query.filter)?;
+
+        let mut filters = self.filters.lock().unwrap();
+        let (now, timeout) = (Instant::now(), Duration::from_secs(self.config.filter_timeout_secs));
+        filters.installed.retain(|_, installed| now.duration_since(installed.polled) < timeout);
+        if filters.installed.len() >= self.config.max_filters {
+            return Err(RpcError::new(LIMIT_EXCEEDED, format!("At most {} filters", self.config.max_filters)));
+        }
+        filters.next_id += 1;
+        let id = to_hex(&filters.next_id.to_be_bytes());
+        filters.installed.insert(id.clone(), Installed { filter, from, to, next: from, polled: now });
+        Ok(id)
+    }
+
+    // Logs of the blocks imported since the last poll, at most
+    // `max_block_range` blocks at a time
+    pub async fn changes(&self, chain: &ChainStore, id: &str) -> Result<Vec<Value>, RpcError> {
+        let (filter, next, to) = self.poll(id, |installed| (installed.filter.clone(), installed.next, installed.to))?;
+        let head = match head(chain).await? {
+            Some(head) => head,
+            None => return Ok(Vec::new()),
+        };
+        let end =
+            to.map_or(head, |to| to.min(head)).min(next.saturating_add(self.config.max_block_range.saturating_sub(1)));
+        if next > end {
+            return Ok(Vec::new());
+        }
+        let logs = self.scan(chain, &filter, next, end).await?;
+        if let Some(installed) = self.filters.lock().unwrap().installed.get_mut(id) {
+            installed.next = installed.next.max(end + 1);
+        }
+        Ok(logs)
+    }
+
+    // Every log the filter matches, regardless of earlier polls
+    pub async fn filter_logs(&self, chain: &ChainStore, id: &str) -> Result<Vec<Value>, RpcError> {
+        let (filter, from, to) = self.poll(id, |installed| (installed.filter.clone(), installed.from, installed.to))?;
+        let head = match head(chain).await? {
+            Some(head) => head,
+            None => return Ok(Vec::new()),
+        };
+        self.find(chain, &filter, from, to.map_or(head, |to| to.min(head))).await
+    }
+
+    pub fn uninstall(&self, id: &str) -> bool {
+        self.filters.lock().unwrap().installed.remove(id).is_some()
+    }
+
+    fn poll<T>(&self, id: &str, read: impl FnOnce(&Installed) -> T) -> Result<T, RpcError> {
+        let mut filters = self.filters.lock().unwrap();
+        let installed = filters
+            .installed
+            .get_mut(id)
+            .ok_or_else(|| RpcError::invalid_params(format!("Filter {} not found", id)))?;
+        installed.polled = Instant::now();
+        Ok(read(installed))
+    }
+
+    async fn find(&self, chain: &ChainStore, filter: &LogFilter, from: u64, to: u64) -> Result<Vec<Value>, RpcError> {
+        if to < from {
+            return Ok(Vec::new());
+        }
+        if to - from >= self.config.max_block_range {
+            let message = format!("Queries are limited to {} blocks", self.config.max_block_range);
+            return Err(RpcError::new(LIMIT_EXCEEDED, message));
+        }
+        self.scan(chain, filter, from, to).await
+    }
+
+    async fn scan(&self, chain: &ChainStore, filter: &LogFilter, from: u64, to: u64) -> Result<Vec<Value>, RpcError> {
+        let mut logs = Vec::new();
+        for height in from..=to {
+            if let Some(bloom) = chain.log_bloom(height).await.map_err(RpcError::internal)? {
+                if !filter.may_match(&bloom) {
+                    continue;
+                }
+            }
+            let header = chain.header(height).await.map_err(RpcError::internal)?;
+            let receipts = chain.receipts(height).await.map_err(RpcError::internal)?;
+            let (header, receipts) = match header.zip(receipts) {
+                Some(found) => found,
+                None => continue,
+            };
+            let block_hash = to_hex(&Block { header, transactions: vec![] }.hash());
+            logs.extend(filter.block_logs(height, &block_hash, &receipts));
+            if logs.len() > self.config.max_results {
+                let message = format!("Query matches more than {} logs, narrow it", self.config.max_results);
+                return Err(RpcError::new(LIMIT_EXCEEDED, message));
+            }
+        }
+        Ok(logs)
+    }
+}
+
+async fn head(chain: &ChainStore) -> Result<Option<u64>, RpcError> {
+    Ok(chain.head().await.map_err(RpcError::internal)?.map(|head| head.height))
+}
+
+async fn resolve(chain: &ChainStore, id: Option<BlockId>, head: u64) -> Result<u64, RpcError> {
+    match id {
+        Some(BlockId::Height(height)) => Ok(height),
+        Some(BlockId::Tag(tag)) if tag != "latest" => chain
+            .height_of(hash_from_hex(&tag)?)
+            .await
+            .map_err(RpcError::internal)?
+            .ok_or_else(|| RpcError::invalid_params(format!("Unknown block {}", tag))),
+        _ => Ok(head),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::transaction::{Log, TransactionReceipt};
+    use crate::crypto::hash::Hash;
+    use crate::rpc::api::test_node::signed_transaction;
+    use crate::storage::db::Database;
+    use crate::types::Address;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    async fn import(chain: &ChainStore, height: u64, parent: [u8; 32], logs: Vec<Log>) -> [u8; 32] {
+        let block = Block::new(parent, vec![signed_transaction(height)], 1).unwrap();
+        let receipt = TransactionReceipt {
+            transaction_hash: block.transactions[0].hash().unwrap(),
+            block_hash: block.hash(),
+            block_number: height,
+            gas_used: 21000,
+            status: true,
+            logs,
+        };
+        chain.import_block(height, &block, &[receipt], &[]).await.unwrap();
+        block.hash()
+    }
+
+    fn query(value: Value) -> LogQuery {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_queries_and_polls_logs() {
+        let temp_dir = TempDir::new().unwrap();
+        let chain = ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap()));
+        let config = LogsConfig { max_block_range: 3, ..LogsConfig::default() };
+        let filters = LogFilters::new(config);
+        let (model, deployed) = (Address::random(), Hash::hash(b"ModelDeployed"));
+        let log = Log { address: model, topics: vec![deployed.clone()], data: vec![] };
+        let other = Log { address: Address::random(), topics: vec![Hash::hash(b"JobCompleted")], data: vec![] };
+
+        let mut parent = import(&chain, 0, [0; 32], vec![]).await;
+        parent = import(&chain, 1, parent, vec![log.clone(), other.clone()]).await;
+        let watching = json!({ "address": [model], "topics": [[to_hex(deployed.as_bytes())]] });
+        let from_start = filters.install(&chain, query(json!({ "from_block": 0, "address": [model] }))).await.unwrap();
+        let new_only = filters.install(&chain, query(watching.clone())).await.unwrap();
+        parent = import(&chain, 2, parent, vec![other]).await;
+        parent = import(&chain, 3, parent, vec![log.clone()]).await;
+        import(&chain, 4, parent, vec![log]).await;
+
+        let logs = filters.logs(&chain, query(json!({ "from_block": 1, "to_block": 3, "address": [model] }))).await;
+        let heights: Vec<Value> = logs.unwrap().iter().map(|log| log["block_height"].clone()).collect();
+        assert_eq!(heights, vec![json!(1), json!(3)]);
+        let too_wide = filters.logs(&chain, query(json!({ "from_block": 0, "to_block": 4 }))).await;
+        assert_eq!(too_wide.unwrap_err().code, LIMIT_EXCEEDED);
+
+        // Changes come in chunks of `max_block_range` blocks
+        assert_eq!(filters.changes(&chain, &from_start).await.unwrap().len(), 1);
+        assert_eq!(filters.changes(&chain, &from_start).await.unwrap().len(), 2);
+        assert!(filters.changes(&chain, &from_start).await.unwrap().is_empty());
+        let changes = filters.changes(&chain, &new_only).await.unwrap();
+        assert_eq!(changes.iter().map(|log| log["block_height"].clone()).collect::<Vec<_>>(), vec![json!(3), json!(4)]);
+        assert_eq!(filters.filter_logs(&chain, &new_only).await.unwrap().len(), 2);
+
+        assert!(filters.uninstall(&new_only));
+        assert!(!filters.uninstall(&new_only));
+        assert!(filters.changes(&chain, &new_only).await.is_err());
+    }
+}
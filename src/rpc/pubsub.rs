@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::bloom::LogBloom;
+use crate::chain::transaction::{Log, TransactionHash, TransactionReceipt};
+use crate::crypto::hash::Hash;
+use crate::rpc::api::RpcApi;
+use crate::rpc::jsonrpc::{
+    hash_from_hex, to_hex, Params, Request, Response, RpcError, INVALID_REQUEST, METHOD_NOT_FOUND,
+};
+use crate::rpc::server::handle_body;
+use crate::types::Address;
+
+// Published by the node on a broadcast channel; every streaming connection
+// holds a receiver and filters it against its subscriptions
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    // A block became the head, after it and its receipts were stored
+    NewBlock { height: u64, block: Arc<Block>, receipts: Arc<Vec<TransactionReceipt>> },
+    // A transaction was accepted into the pool
+    PendingTransaction(TransactionHash),
+    // A provider's result for an inference job was accepted at `height`
+    InferenceResult { height: u64, job: Hash, provider: Address, output_hash: Hash },
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct LogFilterParams {
+    address: Vec<Address>,
+    topics: Vec<Option<Vec<String>>>,
+}
+
+// Logs emitted by any of `addresses` (all when empty) whose topics match
+// position by position: `None` matches anything, otherwise any of the hashes
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    pub addresses: Vec<Address>,
+    pub topics: Vec<Option<Vec<Hash>>>,
+}
+
+impl LogFilter {
+    pub(crate) fn parse(params: LogFilterParams) -> Result<Self, RpcError> {
+        let topics = params
+            .topics
+            .into_iter()
+            .map(|position| {
+                position
+                    .map(|any| any.iter().map(|topic| hash_from_hex(topic).map(|hash| Hash::from(&hash[..]))).collect())
+                    .transpose()
+            })
+            .collect::<Result<_, RpcError>>()?;
+        Ok(Self { addresses: params.address, topics })
+    }
+
+    pub fn matches(&self, log: &Log) -> bool {
+        if !self.addresses.is_empty() && !self.addresses.contains(&log.address) {
+            return false;
+        }
+        self.topics.iter().enumerate().all(|(position, wanted)| match wanted {
+            None => true,
+            Some(any) => log.topics.get(position).map_or(false, |topic| any.contains(topic)),
+        })
+    }
+
+    // Whether a block with this bloom can hold a matching log
+    pub fn may_match(&self, bloom: &LogBloom) -> bool {
+        (self.addresses.is_empty() || self.addresses.iter().any(|address| bloom.may_contain_address(address)))
+            && self.topics.iter().flatten().all(|any| any.iter().any(|topic| bloom.may_contain(topic.as_bytes())))
+    }
+
+    // The matching logs of one block, as `LogView`s in block order
+    pub fn block_logs(&self, height: u64, block_hash: &str, receipts: &[TransactionReceipt]) -> Vec<Value> {
+        let logs = receipts.iter().enumerate().flat_map(|(transaction_index, receipt)| {
+            receipt.logs.iter().enumerate().map(move |(log_index, log)| (transaction_index, receipt, log_index, log))
+        });
+        logs.filter(|(_, _, _, log)| self.matches(log))
+            .map(|(transaction_index, receipt, log_index, log)| {
+                json!(LogView {
+                    block_height: height,
+                    block_hash: block_hash.to_string(),
+                    transaction_hash: &receipt.transaction_hash,
+                    transaction_index,
+                    log_index,
+                    log,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Subscription {
+    NewHeads,
+    Logs(LogFilter),
+    PendingTransactions,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeadView<'a> {
+    pub height: u64,
+    pub hash: String,
+    pub header: &'a BlockHeader,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogView<'a> {
+    pub block_height: u64,
+    pub block_hash: String,
+    pub transaction_hash: &'a TransactionHash,
+    pub transaction_index: usize,
+    pub log_index: usize,
+    #[serde(flatten)]
+    pub log: &'a Log,
+}
+
+impl Subscription {
+    // What `event` means to this subscription, one notification result per entry
+    pub fn results(&self, event: &ChainEvent) -> Vec<Value> {
+        match (self, event) {
+            (Subscription::NewHeads, ChainEvent::NewBlock { height, block, .. }) => {
+                let head = HeadView { height: *height, hash: to_hex(&block.hash()), header: &block.header };
+                vec![json!(head)]
+            }
+            (Subscription::Logs(filter), ChainEvent::NewBlock { height, block, receipts }) => {
+                filter.block_logs(*height, &to_hex(&block.hash()), receipts)
+            }
+            (Subscription::PendingTransactions, ChainEvent::PendingTransaction(hash)) => {
+                vec![json!(to_hex(hash.as_bytes()))]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+// The subscriptions of one connection. Calls other than `subscribe_*` and
+// `unsubscribe` are passed on to the regular RPC methods; subscribing is
+// only possible in single calls, not in batches.
+pub struct Subscriptions {
+    max: usize,
+    next_id: u64,
+    active: HashMap<String, Subscription>,
+}
+
+impl Subscriptions {
+    pub fn new(max: usize) -> Self {
+        Self { max, next_id: 0, active: HashMap::new() }
+    }
+
+    pub async fn handle(&mut self, api: &RpcApi, body: &[u8], max_batch_size: usize) -> Option<String> {
+        let request = match serde_json::from_slice::<Request>(body) {
+            Ok(request) if request.method.starts_with("subscribe_") || request.method == "unsubscribe" => request,
+            _ => return handle_body(api, body, max_batch_size).await.map(|reply| String::from_utf8(reply).unwrap()),
+        };
+        let outcome = if request.jsonrpc != "2.0" {
+            Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""))
+        } else {
+            Params::parse(request.params).and_then(|params| self.call(&request.method, params))
+        };
+        let reply = Response::new(request.id.unwrap_or(Value::Null), outcome);
+        Some(serde_json::to_string(&reply).expect("responses serialize"))
+    }
+
+    fn call(&mut self, method: &str, params: Params) -> Result<Value, RpcError> {
+        let subscription = match method {
+            "unsubscribe" => return Ok(json!(self.active.remove(&params.get::<String>(0)?).is_some())),
+            "subscribe_newHeads" => Subscription::NewHeads,
+            "subscribe_logs" => Subscription::Logs(LogFilter::parse(params.get::<Option<_>>(0)?.unwrap_or_default())?),
+            "subscribe_pendingTransactions" => Subscription::PendingTransactions,
+            _ => return Err(RpcError::new(METHOD_NOT_FOUND, format!("Method {} not found", method))),
+        };
+        if self.active.len() >= self.max {
+            return Err(RpcError::new(INVALID_REQUEST, format!("At most {} subscriptions per connection", self.max)));
+        }
+        self.next_id += 1;
+        let id = to_hex(&self.next_id.to_be_bytes());
+        self.active.insert(id.clone(), subscription);
+        Ok(json!(id))
+    }
+
+    // Notification messages `event` produces for this connection
+    pub fn notifications(&self, event: &ChainEvent) -> Vec<String> {
+        self.active
+            .iter()
+            .flat_map(|(id, subscription)| subscription.results(event).into_iter().map(move |result| (id, result)))
+            .map(|(id, result)| {
+                let params = json!({ "subscription": id, "result": result });
+                json!({ "jsonrpc": "2.0", "method": "subscription", "params": params }).to_string()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::store::ChainStore;
+    use crate::rpc::api::test_node::{signed_transaction, StubNode};
+    use crate::storage::db::Database;
+    use tempfile::TempDir;
+
+    fn receipt(block: &Block, index: usize, logs: Vec<Log>) -> TransactionReceipt {
+        TransactionReceipt {
+            transaction_hash: block.transactions[index].hash().unwrap(),
+            block_hash: block.hash(),
+            block_number: 1,
+            gas_used: 21000,
+            status: true,
+            logs,
+        }
+    }
+
+    async fn call(subscriptions: &mut Subscriptions, api: &RpcApi, method: &str, params: Value) -> Value {
+        let body = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+        let reply = subscriptions.handle(api, &serde_json::to_vec(&body).unwrap(), 10).await.unwrap();
+        serde_json::from_str(&reply).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_filters_events_per_subscription() {
+        let temp_dir = TempDir::new().unwrap();
+        let chain = Arc::new(ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap())));
+        let api = RpcApi::new(chain, Arc::new(StubNode::default()));
+        let mut subscriptions = Subscriptions::new(3);
+
+        let (contract, transfer) = (Address::random(), Hash::hash(b"Transfer"));
+        let filter = json!({ "address": [contract], "topics": [[to_hex(transfer.as_bytes())]] });
+        let heads = call(&mut subscriptions, &api, "subscribe_newHeads", json!([])).await["result"].clone();
+        let logs = call(&mut subscriptions, &api, "subscribe_logs", json!([filter])).await["result"].clone();
+        let pending =
+            call(&mut subscriptions, &api, "subscribe_pendingTransactions", json!([])).await["result"].clone();
+        assert!(call(&mut subscriptions, &api, "subscribe_newHeads", json!([])).await["error"].is_object());
+        let validators = call(&mut subscriptions, &api, "consensus_getValidators", json!([])).await;
+        assert_eq!(validators["result"]["validators"], json!([]));
+
+        let block = Block::new([0; 32], vec![signed_transaction(0), signed_transaction(1)], 1).unwrap();
+        let matching = Log { address: contract, topics: vec![transfer.clone(), Hash::hash(b"from")], data: vec![] };
+        let other_topic = Log { address: contract, topics: vec![Hash::hash(b"Approval")], data: vec![] };
+        let other_address = Log { address: Address::random(), topics: vec![transfer.clone()], data: vec![] };
+        let receipts = vec![receipt(&block, 0, vec![other_topic, matching]), receipt(&block, 1, vec![other_address])];
+        let event = ChainEvent::NewBlock { height: 1, block: Arc::new(block.clone()), receipts: Arc::new(receipts) };
+
+        let notifications: Vec<Value> =
+            subscriptions.notifications(&event).iter().map(|text| serde_json::from_str(text).unwrap()).collect();
+        assert_eq!(notifications.len(), 2);
+        let for_logs = notifications.iter().find(|n| n["params"]["subscription"] == logs).unwrap();
+        let result = &for_logs["params"]["result"];
+        assert_eq!((result["transaction_index"].clone(), result["log_index"].clone()), (json!(0), json!(1)));
+        let for_heads = notifications.iter().find(|n| n["params"]["subscription"] == heads).unwrap();
+        assert_eq!(for_heads["params"]["result"]["hash"], json!(to_hex(&block.hash())));
+
+        let hash = block.transactions[0].hash().unwrap();
+        let notifications = subscriptions.notifications(&ChainEvent::PendingTransaction(hash));
+        assert_eq!(notifications.len(), 1);
+        assert!(notifications[0].contains(pending.as_str().unwrap()));
+
+        assert_eq!(call(&mut subscriptions, &api, "unsubscribe", json!([heads])).await["result"], json!(true));
+        assert_eq!(call(&mut subscriptions, &api, "unsubscribe", json!([heads])).await["result"], json!(false));
+        assert!(call(&mut subscriptions, &api, "subscribe_newHeads", json!([])).await["result"].is_string());
+    }
+}
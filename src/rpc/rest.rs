@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::crypto::address::AddressError;
+use crate::rpc::api::{BlockId, RpcApi};
+use crate::rpc::jsonrpc::{RpcError, INVALID_PARAMS};
+use crate::types::Address;
+
+// `[rpc.rest]`. Served by the JSON-RPC server for GET requests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestConfig {
+    pub enabled: bool,
+    pub default_page_size: usize,
+    pub max_page_size: usize,
+}
+
+impl Default for RestConfig {
+    fn default() -> Self {
+        Self { enabled: true, default_page_size: 20, max_page_size: 100 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RestError {
+    #[error("Not found")]
+    NotFound,
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl RestError {
+    pub fn status(&self) -> u16 {
+        match self {
+            RestError::NotFound => 404,
+            RestError::BadRequest(_) => 400,
+            RestError::Internal(_) => 500,
+        }
+    }
+}
+
+impl From<RpcError> for RestError {
+    fn from(error: RpcError) -> Self {
+        match error.code {
+            INVALID_PARAMS => RestError::BadRequest(error.message),
+            _ => RestError::Internal(error.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockSummary {
+    pub height: u64,
+    pub hash: String,
+    pub timestamp: i64,
+    pub transaction_count: usize,
+}
+
+// One page of a listing, newest first. `next` is the cursor to pass as
+// `before` for the following page, absent on the last one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<u64>,
+}
+
+// Explorer-style read-only routes:
+//   /blocks?before=<height>&limit=<n>   block summaries, newest first
+//   /blocks/{height or hash}            a block with its transactions
+//   /txs/{hash}                         a transaction with its receipt
+//   /accounts/{address}                 an account's balance
+//   /models/{id}                        a model's marketplace listing
+pub async fn handle(api: &RpcApi, config: &RestConfig, path: &str, query: Option<&str>) -> Result<Value, RestError> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["blocks"] => {
+            let before = query_param(query, "before")?;
+            let limit = query_param(query, "limit")?.unwrap_or(config.default_page_size).clamp(1, config.max_page_size);
+            to_value(blocks(api, before, limit).await?)
+        }
+        ["blocks", id] => {
+            let id = id.parse().map(BlockId::Height).unwrap_or_else(|_| BlockId::Tag(id.to_string()));
+            to_value(api.block(id).await?.ok_or(RestError::NotFound)?)
+        }
+        ["txs", hash] => to_value(api.transaction(hash).await?.ok_or(RestError::NotFound)?),
+        ["accounts", address] => {
+            let parsed = parse_address(address)?;
+            Ok(json!({ "address": address, "balance": api.balance(&parsed).await? }))
+        }
+        ["models", id] => to_value(api.model(&parse_address(id)?).await?.ok_or(RestError::NotFound)?),
+        _ => Err(RestError::NotFound),
+    }
+}
+
+async fn blocks(api: &RpcApi, before: Option<u64>, limit: usize) -> Result<Page<BlockSummary>, RestError> {
+    let head = match api.block(BlockId::Tag("latest".to_string())).await? {
+        Some(head) => head.height,
+        None => return Ok(Page { items: Vec::new(), next: None }),
+    };
+    let mut items = Vec::with_capacity(limit);
+    let mut heights = (0..before.map_or(head + 1, |before| before.min(head + 1))).rev();
+    for height in heights.by_ref().take(limit) {
+        // History below a fast sync pivot was never downloaded
+        let view = match api.block(BlockId::Height(height)).await? {
+            Some(view) => view,
+            None => return Ok(Page { items, next: None }),
+        };
+        items.push(BlockSummary {
+            height,
+            hash: view.hash,
+            timestamp: view.block.header.timestamp,
+            transaction_count: view.block.transactions.len(),
+        });
+    }
+    let next = items.last().filter(|_| heights.next().is_some()).map(|last| last.height);
+    Ok(Page { items, next })
+}
+
+fn query_param<T: std::str::FromStr>(query: Option<&str>, name: &str) -> Result<Option<T>, RestError> {
+    let value = query.unwrap_or_default().split('&').find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='));
+    value
+        .map(|value| value.parse().map_err(|_| RestError::BadRequest(format!("invalid {}: {}", name, value))))
+        .transpose()
+}
+
+fn parse_address(text: &str) -> Result<Address, RestError> {
+    text.parse().map_err(|e: AddressError| RestError::BadRequest(format!("invalid address {}: {}", text, e)))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RestError> {
+    serde_json::to_value(value).map_err(|e| RestError::Internal(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::block::Block;
+    use crate::chain::store::ChainStore;
+    use crate::rpc::api::test_node::StubNode;
+    use crate::rpc::jsonrpc::to_hex;
+    use crate::storage::db::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_pages_through_blocks_and_routes_lookups() {
+        let temp_dir = TempDir::new().unwrap();
+        let chain = Arc::new(ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap())));
+        let mut parent = [0; 32];
+        for height in 0..5 {
+            let block = Block::new(parent, vec![], 1).unwrap();
+            chain.import_block(height, &block, &[], &[]).await.unwrap();
+            parent = block.hash();
+        }
+        let api = RpcApi::new(chain, Arc::new(StubNode::default()));
+        let config = RestConfig { default_page_size: 2, ..RestConfig::default() };
+
+        let first = handle(&api, &config, "/blocks", None).await.unwrap();
+        assert_eq!((first["items"][0]["height"].clone(), first["next"].clone()), (json!(4), json!(3)));
+        let last = handle(&api, &config, "/blocks", Some("before=2&limit=5")).await.unwrap();
+        assert_eq!((last["items"].as_array().unwrap().len(), last["next"].clone()), (2, Value::Null));
+        assert_eq!(handle(&api, &config, "/blocks", Some("limit=x")).await.unwrap_err().status(), 400);
+
+        let by_height = handle(&api, &config, "/blocks/4", None).await.unwrap();
+        assert_eq!(by_height["hash"], json!(to_hex(&parent)));
+        assert_eq!(handle(&api, &config, &format!("/blocks/{}", to_hex(&parent)), None).await.unwrap(), by_height);
+        assert_eq!(handle(&api, &config, "/blocks/9", None).await, Err(RestError::NotFound));
+        assert_eq!(handle(&api, &config, &format!("/txs/{}", to_hex(&[1; 32])), None).await, Err(RestError::NotFound));
+        assert_eq!(handle(&api, &config, "/txs/0x12", None).await.unwrap_err().status(), 400);
+        assert_eq!(handle(&api, &config, "/validators", None).await, Err(RestError::NotFound));
+    }
+}
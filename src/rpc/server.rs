@@ -0,0 +1,343 @@
+use async_trait::async_trait;
+use futures::future::join_all;
+use hyper::body::HttpBody;
+use hyper::header::{
+    HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE,
+    ORIGIN,
+};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request as HttpRequest, Response as HttpResponse, Server, StatusCode};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{info_span, Instrument};
+
+use crate::network::rate_limit::{RateLimiter, RequestLimit};
+use crate::rpc::admin::AdminConfig;
+use crate::rpc::api::RpcApi;
+#[cfg(feature = "grpc")]
+use crate::rpc::grpc::GrpcConfig;
+use crate::rpc::jsonrpc::{Methods, Request, Response, RpcError, INVALID_REQUEST, PARSE_ERROR, RATE_LIMITED};
+use crate::rpc::logs::LogsConfig;
+use crate::rpc::rest::{self, RestConfig};
+use crate::rpc::wallet::WalletConfig;
+use crate::rpc::ws::WsConfig;
+
+// `[rpc]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RpcConfig {
+    pub enabled: bool,
+    // Loopback by default; anything else exposes transaction submission
+    pub listen: SocketAddr,
+    pub max_request_bytes: usize,
+    // Calls in one batch request
+    pub max_batch_size: usize,
+    // Origins browsers may call from, "*" for any; empty sends no CORS headers
+    pub cors_origins: Vec<String>,
+    // Per client IP, over HTTP; excess requests get 429
+    pub rate_limit: RequestLimit,
+    // Per client IP and method on top of `rate_limit`, for expensive calls;
+    // calls over the limit fail on their own, also inside batches
+    pub method_limits: HashMap<String, RequestLimit>,
+    pub admin: AdminConfig,
+    pub wallet: WalletConfig,
+    pub logs: LogsConfig,
+    pub rest: RestConfig,
+    pub ws: WsConfig,
+    #[cfg(feature = "grpc")]
+    pub grpc: GrpcConfig,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            listen: SocketAddr::from(([127, 0, 0, 1], 8545)),
+            max_request_bytes: 5 * 1024 * 1024,
+            max_batch_size: 100,
+            cors_origins: Vec::new(),
+            rate_limit: RequestLimit { requests_per_sec: 100.0, burst: 200.0 },
+            method_limits: HashMap::from([
+                ("logs_getLogs".to_string(), RequestLimit { requests_per_sec: 5.0, burst: 10.0 }),
+                ("logs_getFilterLogs".to_string(), RequestLimit { requests_per_sec: 5.0, burst: 10.0 }),
+                ("tx_sendRawTransaction".to_string(), RequestLimit { requests_per_sec: 10.0, burst: 50.0 }),
+            ]),
+            admin: AdminConfig::default(),
+            wallet: WalletConfig::default(),
+            logs: LogsConfig::default(),
+            rest: RestConfig::default(),
+            ws: WsConfig::default(),
+            #[cfg(feature = "grpc")]
+            grpc: GrpcConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RpcServerError {
+    #[error("Failed to bind RPC server to {0}: {1}")]
+    Bind(SocketAddr, hyper::Error),
+    #[error("Failed to bind WebSocket RPC server to {0}: {1}")]
+    WsBind(SocketAddr, std::io::Error),
+    #[error("Failed to bind gRPC server to {0}: {1}")]
+    GrpcBind(SocketAddr, std::io::Error),
+    #[error("Failed to bind admin RPC server to {0}: {1}")]
+    AdminBind(SocketAddr, hyper::Error),
+    #[error("Failed to load admin token {0}: {1}")]
+    AdminToken(std::path::PathBuf, std::io::Error),
+    #[error("Invalid admin credentials: {0}")]
+    AdminAuth(String),
+}
+
+// JSON-RPC 2.0 over HTTP POST, single calls and batches, and the REST
+// routes over GET
+pub struct RpcServer {
+    local_addr: SocketAddr,
+    limiter: Arc<Mutex<RateLimiter<IpAddr>>>,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl RpcServer {
+    pub fn start(config: &RpcConfig, api: Arc<RpcApi>) -> Result<Self, RpcServerError> {
+        let listen = config.listen;
+        let limiter = Arc::new(Mutex::new(config.rate_limit.limiter()));
+        let method_limiters: MethodLimiters =
+            config.method_limits.iter().map(|(method, limit)| (method.clone(), limit.limiter())).collect();
+        let method_limiters = Arc::new(Mutex::new(method_limiters));
+        let config = Arc::new(config.clone());
+        let connection_limiter = limiter.clone();
+        let make_service = make_service_fn(move |connection: &AddrStream| {
+            let (api, config, limiter) = (api.clone(), config.clone(), connection_limiter.clone());
+            let method_limiters = method_limiters.clone();
+            let client = connection.remote_addr().ip();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let (api, config, method_limiters) = (api.clone(), config.clone(), method_limiters.clone());
+                    let allowed = limiter.lock().unwrap().allow(client);
+                    async move {
+                        if !allowed {
+                            return Ok::<_, Infallible>(status(StatusCode::TOO_MANY_REQUESTS));
+                        }
+                        let api = MethodLimited { api: &api, client, limiters: &method_limiters };
+                        Ok(serve(&api, &config, request).await)
+                    }
+                }))
+            }
+        });
+        let server = Server::try_bind(&listen).map_err(|e| RpcServerError::Bind(listen, e))?.serve(make_service);
+        let local_addr = server.local_addr();
+        let (shutdown, stopped) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            if let Err(e) = server.with_graceful_shutdown(async { stopped.await.unwrap_or(()) }).await {
+                warn!("RPC server failed: {}", e);
+            }
+        });
+        info!("JSON-RPC listening on http://{}", local_addr);
+        Ok(Self { local_addr, limiter, shutdown, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Replaces the per-client limit of a running server; every client starts
+    // with a full burst again
+    pub fn set_rate_limit(&self, limit: &RequestLimit) {
+        *self.limiter.lock().unwrap() = limit.limiter();
+    }
+
+    // Stops accepting connections and waits for open requests to finish
+    pub async fn stop(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+type MethodLimiters = HashMap<String, RateLimiter<IpAddr>>;
+
+// `[rpc.method_limits]` applied to one client's calls
+struct MethodLimited<'a> {
+    api: &'a RpcApi,
+    client: IpAddr,
+    limiters: &'a Mutex<MethodLimiters>,
+}
+
+#[async_trait]
+impl<'a> Methods for MethodLimited<'a> {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let allowed = self.limiters.lock().unwrap().get_mut(method).map_or(true, |limiter| limiter.allow(self.client));
+        if !allowed {
+            return Err(RpcError::new(RATE_LIMITED, format!("Rate limit for {} exceeded", method)));
+        }
+        self.api.call(method, params).await
+    }
+}
+
+async fn serve(api: &MethodLimited<'_>, config: &RpcConfig, request: HttpRequest<Body>) -> HttpResponse<Body> {
+    let origin = request.headers().get(ORIGIN).filter(|origin| {
+        config.cors_origins.iter().any(|allowed| allowed == "*" || origin.as_bytes() == allowed.as_bytes())
+    });
+    let origin = origin.cloned();
+    let mut response = match *request.method() {
+        Method::POST => serve_json_rpc(api, request, config.max_request_bytes, config.max_batch_size).await,
+        Method::GET if config.rest.enabled => {
+            let uri = request.uri();
+            match rest::handle(api.api, &config.rest, uri.path(), uri.query()).await {
+                Ok(reply) => json(StatusCode::OK, &reply),
+                Err(e) => {
+                    let code = StatusCode::from_u16(e.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    json(code, &json!({ "error": e.to_string() }))
+                }
+            }
+        }
+        // CORS preflight
+        Method::OPTIONS if origin.is_some() => {
+            let mut response = status(StatusCode::NO_CONTENT);
+            let headers = response.headers_mut();
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST, OPTIONS"));
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static("content-type"));
+            response
+        }
+        _ => status(StatusCode::METHOD_NOT_ALLOWED),
+    };
+    if let Some(origin) = origin {
+        response.headers_mut().insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    }
+    response
+}
+
+pub(crate) async fn serve_json_rpc<M: Methods + ?Sized>(
+    api: &M,
+    request: HttpRequest<Body>,
+    max_request_bytes: usize,
+    max_batch_size: usize,
+) -> HttpResponse<Body> {
+    let mut body = request.into_body();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) if bytes.len() + chunk.len() <= max_request_bytes => bytes.extend_from_slice(&chunk),
+            Ok(_) => return status(StatusCode::PAYLOAD_TOO_LARGE),
+            Err(_) => return status(StatusCode::BAD_REQUEST),
+        }
+    }
+    match handle_body(api, &bytes, max_batch_size).await {
+        Some(reply) => with_json_type(HttpResponse::new(Body::from(reply))),
+        // Only notifications, which get no reply
+        None => status(StatusCode::NO_CONTENT),
+    }
+}
+
+fn json(code: StatusCode, value: &Value) -> HttpResponse<Body> {
+    let mut response = with_json_type(HttpResponse::new(Body::from(value.to_string())));
+    *response.status_mut() = code;
+    response
+}
+
+fn with_json_type(mut response: HttpResponse<Body>) -> HttpResponse<Body> {
+    response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+pub(crate) fn status(code: StatusCode) -> HttpResponse<Body> {
+    let mut response = HttpResponse::new(Body::empty());
+    *response.status_mut() = code;
+    response
+}
+
+// Answers one request body, a single call or a batch; `None` when every
+// call in it was a notification
+pub async fn handle_body<M: Methods + ?Sized>(api: &M, body: &[u8], max_batch_size: usize) -> Option<Vec<u8>> {
+    let reply = match serde_json::from_slice::<Value>(body) {
+        Err(e) => Some(error_reply(RpcError::new(PARSE_ERROR, e.to_string()))),
+        Ok(Value::Array(calls)) if calls.is_empty() || calls.len() > max_batch_size => {
+            let message = format!("Batches must hold 1 to {} calls", max_batch_size);
+            Some(error_reply(RpcError::new(INVALID_REQUEST, message)))
+        }
+        Ok(Value::Array(calls)) => {
+            let replies: Vec<Response> =
+                join_all(calls.into_iter().map(|call| handle_call(api, call))).await.into_iter().flatten().collect();
+            (!replies.is_empty()).then(|| serde_json::to_value(replies).expect("responses serialize"))
+        }
+        Ok(call) => handle_call(api, call).await.map(|reply| serde_json::to_value(reply).expect("responses serialize")),
+    };
+    reply.map(|reply| serde_json::to_vec(&reply).expect("values serialize"))
+}
+
+async fn handle_call<M: Methods + ?Sized>(api: &M, call: Value) -> Option<Response> {
+    let request: Request = match serde_json::from_value(call) {
+        Ok(request) => request,
+        Err(e) => return Some(Response::new(Value::Null, Err(RpcError::new(INVALID_REQUEST, e.to_string())))),
+    };
+    if request.jsonrpc != "2.0" {
+        let error = RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"");
+        return Some(Response::new(request.id.unwrap_or(Value::Null), Err(error)));
+    }
+    let span = info_span!("rpc", method = %request.method);
+    let outcome = api.call(&request.method, request.params).instrument(span).await;
+    request.id.map(|id| Response::new(id, outcome))
+}
+
+fn error_reply(error: RpcError) -> Value {
+    serde_json::to_value(Response::new(Value::Null, Err(error))).expect("responses serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::store::ChainStore;
+    use crate::rpc::api::test_node::StubNode;
+    use crate::rpc::jsonrpc::{INVALID_PARAMS, METHOD_NOT_FOUND};
+    use crate::storage::db::Database;
+    use crate::types::Address;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_handles_batches_notifications_and_malformed_bodies() {
+        let temp_dir = TempDir::new().unwrap();
+        let chain = Arc::new(ChainStore::new(Arc::new(Database::new(temp_dir.path()).unwrap())));
+        let api = RpcApi::new(chain, Arc::new(StubNode::default()));
+        let reply = |body: Value| {
+            let api = &api;
+            async move {
+                let reply = handle_body(api, &serde_json::to_vec(&body).unwrap(), 2).await;
+                reply.map(|reply| serde_json::from_slice::<Value>(&reply).unwrap())
+            }
+        };
+
+        let single = reply(json!({"jsonrpc": "2.0", "method": "state_getBalance", "params": [null], "id": 1})).await;
+        assert_eq!(single.unwrap()["error"]["code"], json!(INVALID_PARAMS));
+
+        let batch = reply(json!([
+            {"jsonrpc": "2.0", "method": "consensus_getValidators", "id": "a"},
+            {"jsonrpc": "2.0", "method": "chain_mine", "id": "b"},
+        ]))
+        .await
+        .unwrap();
+        assert_eq!(batch[0]["result"]["validators"], json!([]));
+        assert_eq!((batch[1]["id"].clone(), batch[1]["error"]["code"].clone()), (json!("b"), json!(METHOD_NOT_FOUND)));
+
+        assert_eq!(reply(json!({"jsonrpc": "2.0", "method": "consensus_getValidators"})).await, None);
+        assert_eq!(reply(json!([{}, {}, {}])).await.unwrap()["error"]["code"], json!(INVALID_REQUEST));
+        assert_eq!(reply(json!({"jsonrpc": "1.0", "method": "x", "id": 3})).await.unwrap()["id"], json!(3));
+        let garbage = handle_body(&api, b"{", 2).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&garbage).unwrap()["error"]["code"], json!(PARSE_ERROR));
+
+        let limit = RequestLimit { requests_per_sec: 0.0, burst: 1.0 };
+        let limiters = Mutex::new(HashMap::from([("consensus_getValidators".to_string(), limit.limiter())]));
+        let limited = MethodLimited { api: &api, client: IpAddr::from([10, 0, 0, 1]), limiters: &limiters };
+        assert!(limited.call("consensus_getValidators", json!([])).await.is_ok());
+        assert_eq!(limited.call("consensus_getValidators", json!([])).await.unwrap_err().code, RATE_LIMITED);
+        assert!(limited.call("state_getBalance", json!([Address::random()])).await.is_ok());
+    }
+}
@@ -0,0 +1,145 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::chain::transaction::Transaction;
+use crate::rpc::jsonrpc::{to_hex, RpcError};
+use crate::types::{Address, Balance, Nonce};
+
+// Why a pooled transaction can't go into the next block. Serialized with a
+// `reason` tag like `TxRejection`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Stall {
+    // The account's next nonce is missing from the pool
+    NonceGap { expected: Nonce },
+    // Below the gas price block producers accept
+    Underpriced { min_gas_price: u64 },
+    // Waits for an earlier transaction of the account that is itself stalled
+    Blocked { nonce: Nonce },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolEntry {
+    pub hash: String,
+    pub nonce: Nonce,
+    pub to: Address,
+    pub value: Balance,
+    pub gas_price: u64,
+    pub gas_limit: u64,
+    // Set for queued transactions only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stall: Option<Stall>,
+}
+
+// One sender's transactions, by nonce. Pending ones can be included in
+// order from the account nonce; the rest are queued behind a stall.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountPool {
+    pub address: Address,
+    pub account_nonce: Nonce,
+    pub pending: Vec<PoolEntry>,
+    pub queued: Vec<PoolEntry>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PoolStatus {
+    pub pending: usize,
+    pub queued: usize,
+}
+
+impl PoolStatus {
+    pub fn of(accounts: &[AccountPool]) -> Self {
+        accounts.iter().fold(PoolStatus::default(), |status, account| PoolStatus {
+            pending: status.pending + account.pending.len(),
+            queued: status.queued + account.queued.len(),
+        })
+    }
+}
+
+// Groups pooled transactions by sender, in the order senders first appear
+pub fn by_sender(transactions: Vec<Transaction>) -> Vec<(Address, Vec<Transaction>)> {
+    let mut index = HashMap::new();
+    let mut senders: Vec<(Address, Vec<Transaction>)> = Vec::new();
+    for transaction in transactions {
+        let position = *index.entry(transaction.from).or_insert_with(|| {
+            senders.push((transaction.from, Vec::new()));
+            senders.len() - 1
+        });
+        senders[position].1.push(transaction);
+    }
+    senders
+}
+
+// Splits a sender's transactions into pending and queued, walking them by
+// nonce from `account_nonce` until the first one that can't be included
+pub fn classify(
+    address: Address,
+    account_nonce: Nonce,
+    mut transactions: Vec<Transaction>,
+    min_gas_price: u64,
+) -> Result<AccountPool, RpcError> {
+    transactions.sort_by_key(|transaction| transaction.nonce);
+    let mut account = AccountPool { address, account_nonce, pending: Vec::new(), queued: Vec::new() };
+    let mut expected = account_nonce;
+    let mut stalled: Option<Nonce> = None;
+    for transaction in transactions {
+        let stall = match stalled {
+            Some(nonce) => Some(Stall::Blocked { nonce }),
+            None if transaction.nonce != expected => Some(Stall::NonceGap { expected }),
+            None if transaction.gas_price < min_gas_price => Some(Stall::Underpriced { min_gas_price }),
+            None => None,
+        };
+        let hash = transaction.hash().map_err(|e| RpcError::internal(format!("{:?}", e)))?;
+        let entry = PoolEntry {
+            hash: to_hex(hash.as_bytes()),
+            nonce: transaction.nonce,
+            to: transaction.to,
+            value: transaction.value,
+            gas_price: transaction.gas_price,
+            gas_limit: transaction.gas_limit,
+            stall,
+        };
+        match entry.stall {
+            Some(Stall::Blocked { .. }) => account.queued.push(entry),
+            Some(_) => {
+                stalled = Some(entry.nonce);
+                account.queued.push(entry);
+            }
+            None => {
+                expected += 1;
+                account.pending.push(entry);
+            }
+        }
+    }
+    Ok(account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::transaction::TransactionType;
+
+    #[test]
+    fn test_queues_behind_gaps_and_underpriced_transactions() {
+        let sender = Address::random();
+        let transaction = |nonce, gas_price| {
+            Transaction::new(nonce, sender, Address::random(), 1, gas_price, 21000, vec![], TransactionType::Transfer)
+        };
+
+        let gapped = classify(sender, 5, vec![transaction(8, 10), transaction(5, 10), transaction(6, 10)], 2).unwrap();
+        assert_eq!(gapped.pending.iter().map(|entry| entry.nonce).collect::<Vec<_>>(), vec![5, 6]);
+        assert_eq!(gapped.queued.len(), 1);
+        assert_eq!(gapped.queued[0].stall, Some(Stall::NonceGap { expected: 7 }));
+
+        let underpriced =
+            classify(sender, 0, vec![transaction(0, 10), transaction(1, 1), transaction(2, 10)], 2).unwrap();
+        assert_eq!(underpriced.pending.len(), 1);
+        let stalls: Vec<_> = underpriced.queued.iter().map(|entry| entry.stall.clone().unwrap()).collect();
+        assert_eq!(stalls, vec![Stall::Underpriced { min_gas_price: 2 }, Stall::Blocked { nonce: 1 }]);
+        assert_eq!(PoolStatus::of(&[gapped, underpriced]), PoolStatus { pending: 3, queued: 3 });
+        assert_eq!(
+            serde_json::to_value(Stall::NonceGap { expected: 7 }).unwrap(),
+            serde_json::json!({"reason": "nonce_gap", "expected": 7})
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+use crate::chain::transaction::{RawTransaction, Transaction};
+use crate::crypto::keystore::{Kdf, Keystore, KeystoreError};
+use crate::crypto::signer::{LocalSigner, SignerError, TransactionSigner};
+use crate::rpc::api::RpcApi;
+use crate::rpc::jsonrpc::{to_hex, Methods, Params, RpcError, ACCOUNT_LOCKED, METHOD_NOT_FOUND, SIGNING_REJECTED};
+
+// `[rpc.wallet]`. Served next to the admin namespace, behind its token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WalletConfig {
+    pub enabled: bool,
+    pub keystore: PathBuf,
+    // How long `wallet_unlock` keeps an account unlocked when no duration is given
+    pub unlock_timeout_secs: u64,
+    pub max_unlock_secs: u64,
+    // Wallet account indexes on a connected Ledger, signed for on the device
+    pub ledger_accounts: Vec<u32>,
+}
+
+impl Default for WalletConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keystore: PathBuf::from("./data/keystore"),
+            unlock_timeout_secs: 300,
+            max_unlock_secs: 3600,
+            ledger_accounts: Vec::new(),
+        }
+    }
+}
+
+struct Unlocked {
+    signer: Arc<dyn TransactionSigner>,
+    until: Instant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WalletAccount {
+    pub public_key: String,
+    pub unlocked: bool,
+    // Held by a hardware wallet, which never needs unlocking
+    pub device: bool,
+}
+
+// Signs with the node's own keystore, for deployments without external
+// signing tooling:
+//   wallet_newAccount([passphrase]), wallet_list(),
+//   wallet_unlock([account, passphrase, seconds?]), wallet_lock([account]),
+//   wallet_signTransaction([transaction, account]), wallet_sendTransaction([transaction, account])
+// Transactions are given in their JSON form and signed by an unlocked account
+// or on the device holding it.
+pub struct WalletApi {
+    config: WalletConfig,
+    accounts: Arc<Keystore>,
+    unlocked: Mutex<HashMap<String, Unlocked>>,
+    devices: HashMap<String, Arc<dyn TransactionSigner>>,
+    api: Arc<RpcApi>,
+}
+
+impl WalletApi {
+    pub fn new(config: WalletConfig, api: Arc<RpcApi>) -> Self {
+        let accounts = Arc::new(Keystore::new(config.keystore.clone()));
+        Self { config, accounts, unlocked: Mutex::new(HashMap::new()), devices: HashMap::new(), api }
+    }
+
+    // KDF for accounts created from now on, scrypt by default
+    pub fn with_kdf(mut self, kdf: Kdf) -> Self {
+        self.accounts = Arc::new(Keystore::new(self.config.keystore.clone()).with_kdf(kdf));
+        self
+    }
+
+    // An account whose key stays on a hardware wallet
+    pub fn with_device(mut self, account: String, signer: Arc<dyn TransactionSigner>) -> Self {
+        self.devices.insert(account, signer);
+        self
+    }
+
+    // Adds `ledger_accounts` from the first Ledger connected, by public key
+    #[cfg(feature = "ledger")]
+    pub async fn connect_ledger(mut self) -> Result<Self, SignerError> {
+        use crate::crypto::hd::KeyPurpose;
+        use crate::crypto::signer::{hid::HidTransport, LedgerSigner};
+
+        if self.config.ledger_accounts.is_empty() {
+            return Ok(self);
+        }
+        let transport = Arc::new(
+            tokio::task::spawn_blocking(HidTransport::open)
+                .await
+                .map_err(|e| SignerError::Transport(e.to_string()))??,
+        );
+        for index in self.config.ledger_accounts.clone() {
+            let signer = LedgerSigner::new(transport.clone(), KeyPurpose::Wallet(index).path());
+            let public_key: String = signer.public_key().await?.iter().map(|byte| format!("{:02x}", byte)).collect();
+            self.devices.insert(public_key, Arc::new(signer));
+        }
+        Ok(self)
+    }
+
+    pub async fn new_account(&self, passphrase: String) -> Result<String, RpcError> {
+        let passphrase = Zeroizing::new(passphrase);
+        let accounts = self.accounts.clone();
+        // Key derivation takes a while on purpose
+        tokio::task::spawn_blocking(move || accounts.create(&passphrase))
+            .await
+            .map_err(RpcError::internal)?
+            .map_err(account_error)
+    }
+
+    pub fn list(&self) -> Result<Vec<WalletAccount>, RpcError> {
+        let accounts = self.accounts.list().map_err(account_error)?;
+        let unlocked = self.unlocked.lock().unwrap();
+        let now = Instant::now();
+        let devices = self.devices.keys().map(|public_key| WalletAccount {
+            public_key: public_key.clone(),
+            unlocked: true,
+            device: true,
+        });
+        Ok(accounts
+            .into_iter()
+            .map(|public_key| {
+                let unlocked = unlocked.get(&public_key).map_or(false, |account| account.until > now);
+                WalletAccount { public_key, unlocked, device: false }
+            })
+            .chain(devices)
+            .collect())
+    }
+
+    pub async fn unlock(&self, account: String, passphrase: String, seconds: Option<u64>) -> Result<u64, RpcError> {
+        let passphrase = Zeroizing::new(passphrase);
+        let seconds = seconds.unwrap_or(self.config.unlock_timeout_secs).min(self.config.max_unlock_secs);
+        let accounts = self.accounts.clone();
+        let public_key = account.clone();
+        let secret = tokio::task::spawn_blocking(move || accounts.unlock(&public_key, &passphrase))
+            .await
+            .map_err(RpcError::internal)?
+            .map_err(account_error)?;
+        let until = Instant::now() + Duration::from_secs(seconds);
+        self.unlocked.lock().unwrap().insert(account, Unlocked { signer: Arc::new(LocalSigner::new(secret)), until });
+        Ok(seconds)
+    }
+
+    // Whether the account was unlocked
+    pub fn lock(&self, account: &str) -> bool {
+        self.unlocked.lock().unwrap().remove(account).map_or(false, |account| account.until > Instant::now())
+    }
+
+    // The hex-encoded envelope `tx_sendRawTransaction` takes
+    pub async fn sign(&self, mut transaction: Transaction, account: &str) -> Result<String, RpcError> {
+        let signer = match self.devices.get(account) {
+            Some(signer) => signer.clone(),
+            None => {
+                let mut unlocked = self.unlocked.lock().unwrap();
+                let now = Instant::now();
+                unlocked.retain(|_, account| account.until > now);
+                match unlocked.get(account) {
+                    Some(account) => account.signer.clone(),
+                    None => return Err(RpcError::new(ACCOUNT_LOCKED, format!("Account {} is locked", account))),
+                }
+            }
+        };
+        transaction.signature = None;
+        signer.sign(&mut transaction).await.map_err(signer_error)?;
+        let raw = RawTransaction { chain_id: self.api.chain_id(), transaction };
+        Ok(to_hex(&bincode::serialize(&raw).map_err(RpcError::internal)?))
+    }
+}
+
+#[async_trait]
+impl Methods for WalletApi {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let params = Params::parse(params)?;
+        match method {
+            "wallet_newAccount" => Ok(json!(self.new_account(params.get(0)?).await?)),
+            "wallet_list" => serde_json::to_value(self.list()?).map_err(RpcError::internal),
+            "wallet_unlock" => Ok(json!(self.unlock(params.get(0)?, params.get(1)?, params.get(2)?).await?)),
+            "wallet_lock" => Ok(json!(self.lock(&params.get::<String>(0)?))),
+            "wallet_signTransaction" => Ok(json!(self.sign(params.get(0)?, &params.get::<String>(1)?).await?)),
+            "wallet_sendTransaction" => {
+                let raw = self.sign(params.get(0)?, &params.get::<String>(1)?).await?;
+                Ok(json!(self.api.send_raw_transaction(&raw).await?))
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method {} not found", method))),
+        }
+    }
+}
+
+fn account_error(error: KeystoreError) -> RpcError {
+    match error {
+        KeystoreError::NotFound(_) | KeystoreError::WrongPassword(_) | KeystoreError::EmptyPassword => {
+            RpcError::invalid_params(error.to_string())
+        }
+        _ => RpcError::internal(error),
+    }
+}
+
+fn signer_error(error: SignerError) -> RpcError {
+    match error {
+        SignerError::Transaction(_) => RpcError::internal(error),
+        _ => RpcError::new(SIGNING_REJECTED, error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::store::ChainStore;
+    use crate::chain::transaction::TransactionType;
+    use crate::rpc::api::test_node::StubNode;
+    use crate::rpc::jsonrpc::{from_hex, Namespaces, INVALID_PARAMS};
+    use crate::storage::db::Database;
+    use crate::types::Address;
+    use tempfile::TempDir;
+
+    // A device whose holder turns everything down
+    struct Declining;
+
+    #[async_trait]
+    impl TransactionSigner for Declining {
+        async fn sign(&self, _: &mut Transaction) -> Result<(), SignerError> {
+            Err(SignerError::Rejected)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signs_only_with_unlocked_accounts() {
+        let temp_dir = TempDir::new().unwrap();
+        let chain = Arc::new(ChainStore::new(Arc::new(Database::new(temp_dir.path().join("db")).unwrap())));
+        let node = Arc::new(StubNode::default());
+        let config = WalletConfig { keystore: temp_dir.path().join("keystore"), ..WalletConfig::default() };
+        let wallet = WalletApi::new(config, Arc::new(RpcApi::new(chain, node.clone())));
+        let wallet =
+            wallet.with_kdf(Kdf::Scrypt { log_n: 10, r: 8, p: 1 }).with_device("ab".to_string(), Arc::new(Declining));
+        let wallet = Arc::new(wallet);
+        let methods = Namespaces::new().with("wallet", wallet);
+        let transaction =
+            Transaction::new(0, Address::random(), Address::random(), 1, 1, 21000, vec![], TransactionType::Transfer);
+        let transaction = serde_json::to_value(&transaction).unwrap();
+
+        let account = methods.call("wallet_newAccount", json!(["hunter22"])).await.unwrap();
+        let sign = json!([transaction, account]);
+        assert_eq!(methods.call("wallet_signTransaction", sign.clone()).await.unwrap_err().code, ACCOUNT_LOCKED);
+        let wrong = methods.call("wallet_unlock", json!([account, "hunter2"])).await;
+        assert_eq!(wrong.unwrap_err().code, INVALID_PARAMS);
+        assert_eq!(methods.call("wallet_unlock", json!([account, "hunter22", 86400])).await.unwrap(), json!(3600));
+        let accounts = methods.call("wallet_list", json!([])).await.unwrap();
+        assert_eq!(accounts[0]["unlocked"], json!(true));
+        assert_eq!((&accounts[1]["publicKey"], &accounts[1]["device"]), (&json!("ab"), &json!(true)));
+        let declined = methods.call("wallet_signTransaction", json!([transaction, "ab"])).await;
+        assert_eq!(declined.unwrap_err().code, SIGNING_REJECTED);
+
+        let raw = methods.call("wallet_signTransaction", sign.clone()).await.unwrap();
+        let signed: RawTransaction = bincode::deserialize(&from_hex(raw.as_str().unwrap()).unwrap()).unwrap();
+        assert!(signed.transaction.signature.is_some());
+        assert_eq!(signed.chain_id, 1);
+        methods.call("wallet_sendTransaction", sign.clone()).await.unwrap();
+        assert_eq!(node.submitted.lock().unwrap().len(), 1);
+
+        assert_eq!(methods.call("wallet_lock", json!([account])).await.unwrap(), json!(true));
+        assert_eq!(methods.call("wallet_signTransaction", sign).await.unwrap_err().code, ACCOUNT_LOCKED);
+        assert_eq!(methods.call("admin_peers", json!([])).await.unwrap_err().code, METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,159 @@
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, oneshot, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, WebSocketConfig};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+use crate::rpc::api::RpcApi;
+use crate::rpc::pubsub::{ChainEvent, Subscriptions};
+use crate::rpc::server::{RpcConfig, RpcServerError};
+
+// `[rpc.ws]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WsConfig {
+    pub enabled: bool,
+    pub listen: SocketAddr,
+    // Further connections are refused
+    pub max_connections: usize,
+    pub max_subscriptions_per_connection: usize,
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            listen: SocketAddr::from(([127, 0, 0, 1], 8546)),
+            max_connections: 100,
+            max_subscriptions_per_connection: 64,
+        }
+    }
+}
+
+// JSON-RPC over WebSocket: the regular methods plus `subscribe_newHeads`,
+// `subscribe_logs` and `subscribe_pendingTransactions`, whose events are
+// pushed as `subscription` notifications. A connection that falls behind
+// the event channel is closed rather than silently missing events.
+pub struct WsServer {
+    local_addr: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl WsServer {
+    pub async fn start(
+        config: &RpcConfig,
+        api: Arc<RpcApi>,
+        events: broadcast::Sender<ChainEvent>,
+    ) -> Result<Self, RpcServerError> {
+        let (limits, config) = (Limits::from(config), &config.ws);
+        let listener = TcpListener::bind(config.listen).await.map_err(|e| RpcServerError::WsBind(config.listen, e))?;
+        let local_addr = listener.local_addr().map_err(|e| RpcServerError::WsBind(config.listen, e))?;
+        let (shutdown, mut stopped) = oneshot::channel();
+        let connections = Arc::new(Semaphore::new(config.max_connections));
+        let task = tokio::spawn(async move {
+            loop {
+                let (stream, peer) = tokio::select! {
+                    _ = &mut stopped => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            warn!("Failed to accept WebSocket RPC connection: {}", e);
+                            continue;
+                        }
+                    },
+                };
+                let permit = match connections.clone().try_acquire_owned() {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        debug!("Refusing WebSocket RPC connection from {}, at the limit", peer);
+                        continue;
+                    }
+                };
+                let (api, events) = (api.clone(), events.subscribe());
+                tokio::spawn(async move {
+                    if let Err(e) = serve_connection(stream, &api, events, limits).await {
+                        debug!("WebSocket RPC connection from {} failed: {}", peer, e);
+                    }
+                    drop(permit);
+                });
+            }
+        });
+        info!("JSON-RPC WebSocket listening on ws://{}", local_addr);
+        Ok(Self { local_addr, shutdown, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Stops accepting connections; open ones end when the event channel closes
+    pub async fn stop(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    max_request_bytes: usize,
+    max_batch_size: usize,
+    max_subscriptions: usize,
+}
+
+impl From<&RpcConfig> for Limits {
+    fn from(config: &RpcConfig) -> Self {
+        Self {
+            max_request_bytes: config.max_request_bytes,
+            max_batch_size: config.max_batch_size,
+            max_subscriptions: config.ws.max_subscriptions_per_connection,
+        }
+    }
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    api: &RpcApi,
+    mut events: broadcast::Receiver<ChainEvent>,
+    limits: Limits,
+) -> Result<(), WsError> {
+    let config = WebSocketConfig { max_message_size: Some(limits.max_request_bytes), ..WebSocketConfig::default() };
+    let mut socket = tokio_tungstenite::accept_async_with_config(stream, Some(config)).await?;
+    let mut subscriptions = Subscriptions::new(limits.max_subscriptions);
+    loop {
+        tokio::select! {
+            message = socket.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    // Pings are answered by tungstenite itself
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e),
+                };
+                if let Some(reply) = subscriptions.handle(api, text.as_bytes(), limits.max_batch_size).await {
+                    socket.send(Message::Text(reply)).await?;
+                }
+            }
+            event = events.recv() => match event {
+                Ok(event) => {
+                    for notification in subscriptions.notifications(&event) {
+                        socket.send(Message::Text(notification)).await?;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    let reason = format!("Missed {} events, resubscribe", missed);
+                    let _ = socket.close(Some(CloseFrame { code: CloseCode::Again, reason: reason.into() })).await;
+                    return Ok(());
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
@@ -0,0 +1,916 @@
+#![cfg(any(test, feature = "simulation"))]
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::ai::invoke::InvokeRequest;
+use crate::chain::block::Block;
+use crate::chain::import::{ImportConfig, ImportPipeline};
+use crate::chain::store::{self, ChainStore};
+use crate::chain::transaction::{Transaction, TransactionType};
+use crate::crypto::scheme::ADDRESS_LEN;
+use crate::network::block_gossip::BlockHash;
+use crate::storage::db::Database;
+use crate::types::{Address, Balance};
+
+// The same link model as `network::testkit`, so a scenario can move between
+// the two
+pub use crate::network::testkit::LinkConditions;
+
+pub type NodeId = usize;
+
+// Milliseconds since the simulation started
+pub type SimTime = u64;
+
+// What a scenario does to the network at a given time
+#[derive(Debug, Clone)]
+pub enum Action<M> {
+    // Nodes in different groups can't reach each other; unlisted nodes form
+    // one more group. Messages in flight across groups are lost.
+    Partition(Vec<Vec<NodeId>>),
+    Heal,
+    SetConditions(LinkConditions),
+    // The node stops and loses everything in flight to it
+    Crash(NodeId),
+    // A crashed node comes back as a new instance from the factory, with
+    // whatever it had persisted, and has to catch up with the others
+    Restart(NodeId),
+    // A message from outside the network, e.g. a client's request
+    Inject(NodeId, M),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Violation {
+    #[error("Node {node} finalized a different block at height {height}")]
+    ConflictingFinality { node: NodeId, height: u64 },
+    #[error("Node {node} is stuck at height {height} while the network is at {head}")]
+    Stalled { node: NodeId, height: u64, head: u64 },
+    #[error("Node {node} broke an invariant: {reason}")]
+    Node { node: NodeId, reason: String },
+}
+
+// Everything a node can do while handling an event. Sends and timers take
+// effect once the handler returns.
+pub struct Context<'a, M, T> {
+    id: NodeId,
+    now: SimTime,
+    rng: &'a mut StdRng,
+    sent: Vec<(Option<NodeId>, M)>,
+    timers: Vec<(SimTime, T)>,
+}
+
+impl<'a, M, T> Context<'a, M, T> {
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn now(&self) -> SimTime {
+        self.now
+    }
+
+    // The only randomness a node may use, so runs replay from the seed
+    pub fn rng(&mut self) -> &mut StdRng {
+        self.rng
+    }
+
+    pub fn send(&mut self, to: NodeId, message: M) {
+        self.sent.push((Some(to), message));
+    }
+
+    // To every other node
+    pub fn broadcast(&mut self, message: M) {
+        self.sent.push((None, message));
+    }
+
+    pub fn set_timer(&mut self, after: SimTime, timer: T) {
+        self.timers.push((after, timer));
+    }
+}
+
+// A node as the simulation drives it: a state machine reacting to messages
+// and timers, with no clock, sockets or threads of its own
+pub trait SimNode {
+    type Message: Clone + Debug;
+    type Timer: Clone + Debug;
+
+    fn start(&mut self, ctx: &mut Context<Self::Message, Self::Timer>);
+    fn on_message(&mut self, ctx: &mut Context<Self::Message, Self::Timer>, from: NodeId, message: Self::Message);
+    fn on_timer(&mut self, ctx: &mut Context<Self::Message, Self::Timer>, timer: Self::Timer);
+    // Hashes of the finalized blocks, by height
+    fn finalized(&self) -> &[BlockHash];
+    // Invariants of the node's own state, checked after every event
+    fn check(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+enum Event<M, T> {
+    Deliver { from: NodeId, to: NodeId, epoch: u64, message: M },
+    Timer { node: NodeId, epoch: u64, timer: T },
+    Action(Action<M>),
+}
+
+struct Scheduled<M, T> {
+    time: SimTime,
+    seq: u64,
+    event: Event<M, T>,
+}
+
+impl<M, T> PartialEq for Scheduled<M, T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.time, self.seq) == (other.time, other.seq)
+    }
+}
+
+impl<M, T> Eq for Scheduled<M, T> {}
+
+impl<M, T> PartialOrd for Scheduled<M, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Earliest first, then in the order scheduled
+impl<M, T> Ord for Scheduled<M, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.time, other.seq).cmp(&(self.time, self.seq))
+    }
+}
+
+type Factory<N> = Box<dyn FnMut(NodeId) -> N>;
+
+// Runs N nodes in one thread against a simulated clock and network. Events
+// run strictly in time order and all randomness comes from the seed, so a
+// failing run replays exactly. After every event the nodes' finalized
+// chains are checked against each other.
+pub struct Simulation<N: SimNode> {
+    now: SimTime,
+    seq: u64,
+    rng: StdRng,
+    factory: Factory<N>,
+    nodes: Vec<Option<N>>,
+    // Bumped on every crash and restart, so stale timers and messages are
+    // dropped
+    epochs: Vec<u64>,
+    queue: BinaryHeap<Scheduled<N::Message, N::Timer>>,
+    conditions: LinkConditions,
+    groups: BTreeMap<NodeId, usize>,
+    // The chain as finalized by anyone so far
+    finalized: Vec<BlockHash>,
+    // Heights of each node's chain already checked against it
+    checked: Vec<usize>,
+}
+
+impl<N: SimNode> Simulation<N> {
+    pub fn new(
+        seed: u64,
+        nodes: usize,
+        conditions: LinkConditions,
+        factory: impl FnMut(NodeId) -> N + 'static,
+    ) -> Self {
+        let mut simulation = Self {
+            now: 0,
+            seq: 0,
+            rng: StdRng::seed_from_u64(seed),
+            factory: Box::new(factory),
+            nodes: Vec::new(),
+            epochs: vec![0; nodes],
+            queue: BinaryHeap::new(),
+            conditions,
+            groups: BTreeMap::new(),
+            finalized: Vec::new(),
+            checked: vec![0; nodes],
+        };
+        simulation.nodes = (0..nodes).map(|id| Some((simulation.factory)(id))).collect();
+        for id in 0..nodes {
+            simulation.dispatch(id, |node, ctx| node.start(ctx)).expect("nothing is finalized at start");
+        }
+        simulation
+    }
+
+    pub fn now(&self) -> SimTime {
+        self.now
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&N> {
+        self.nodes[id].as_ref()
+    }
+
+    // Height of the chain finalized by anyone so far
+    pub fn finalized_height(&self) -> u64 {
+        self.finalized.len() as u64
+    }
+
+    pub fn schedule(&mut self, at: SimTime, action: Action<N::Message>) {
+        self.push(at, Event::Action(action));
+    }
+
+    // Processes every event up to `time`, stopping at the first violation
+    pub fn run_until(&mut self, time: SimTime) -> Result<(), Violation> {
+        while self.queue.peek().map_or(false, |next| next.time <= time) {
+            let Scheduled { time, event, .. } = self.queue.pop().expect("just peeked");
+            self.now = time;
+            match event {
+                Event::Deliver { from, to, epoch, message } => {
+                    if self.reachable(from, to) && self.epochs[to] == epoch {
+                        self.dispatch(to, |node, ctx| node.on_message(ctx, from, message))?;
+                    }
+                }
+                Event::Timer { node, epoch, timer } => {
+                    if self.epochs[node] == epoch {
+                        self.dispatch(node, |node, ctx| node.on_timer(ctx, timer))?;
+                    }
+                }
+                Event::Action(action) => self.apply(action)?,
+            }
+        }
+        self.now = time;
+        Ok(())
+    }
+
+    // Whether every running node is within `lag` blocks of the finalized
+    // chain. A node may trail by a block while the last precommits are still
+    // in flight.
+    pub fn check_synced(&self, lag: u64) -> Result<(), Violation> {
+        let head = self.finalized_height();
+        for (id, node) in self.nodes.iter().enumerate() {
+            let height = node.as_ref().map_or(head, |node| node.finalized().len() as u64);
+            if height + lag < head {
+                return Err(Violation::Stalled { node: id, height, head });
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, action: Action<N::Message>) -> Result<(), Violation> {
+        match action {
+            Action::Partition(groups) => {
+                self.groups.clear();
+                for (group, nodes) in groups.iter().enumerate() {
+                    self.groups.extend(nodes.iter().map(|node| (*node, group)));
+                }
+            }
+            Action::Heal => self.groups.clear(),
+            Action::SetConditions(conditions) => self.conditions = conditions,
+            Action::Crash(id) => {
+                self.nodes[id] = None;
+                self.epochs[id] += 1;
+            }
+            Action::Restart(id) => {
+                if self.nodes[id].is_none() {
+                    self.nodes[id] = Some((self.factory)(id));
+                    self.epochs[id] += 1;
+                    self.checked[id] = 0;
+                    self.dispatch(id, |node, ctx| node.start(ctx))?;
+                }
+            }
+            Action::Inject(id, message) => self.dispatch(id, |node, ctx| node.on_message(ctx, id, message))?,
+        }
+        Ok(())
+    }
+
+    fn dispatch(
+        &mut self,
+        id: NodeId,
+        handle: impl FnOnce(&mut N, &mut Context<N::Message, N::Timer>),
+    ) -> Result<(), Violation> {
+        let node = match self.nodes[id].as_mut() {
+            Some(node) => node,
+            None => return Ok(()),
+        };
+        let mut ctx = Context { id, now: self.now, rng: &mut self.rng, sent: Vec::new(), timers: Vec::new() };
+        handle(node, &mut ctx);
+        let Context { sent, timers, .. } = ctx;
+
+        for (to, message) in sent {
+            match to {
+                Some(to) => self.send(id, to, message),
+                None => {
+                    for to in (0..self.nodes.len()).filter(|to| *to != id) {
+                        self.send(id, to, message.clone());
+                    }
+                }
+            }
+        }
+        for (after, timer) in timers {
+            self.push(self.now + after, Event::Timer { node: id, epoch: self.epochs[id], timer });
+        }
+        self.check(id)
+    }
+
+    fn send(&mut self, from: NodeId, to: NodeId, message: N::Message) {
+        let conditions = self.conditions;
+        if !self.reachable(from, to) || self.rng.gen_bool(conditions.drop_rate.clamp(0.0, 1.0)) {
+            return;
+        }
+        let jitter = conditions.jitter.as_millis() as SimTime;
+        let delay = conditions.latency.as_millis() as SimTime + self.rng.gen_range(0..=jitter);
+        self.push(self.now + delay, Event::Deliver { from, to, epoch: self.epochs[to], message });
+    }
+
+    fn reachable(&self, a: NodeId, b: NodeId) -> bool {
+        self.groups.get(&a) == self.groups.get(&b)
+    }
+
+    fn push(&mut self, time: SimTime, event: Event<N::Message, N::Timer>) {
+        self.seq += 1;
+        self.queue.push(Scheduled { time, seq: self.seq, event });
+    }
+
+    fn check(&mut self, id: NodeId) -> Result<(), Violation> {
+        let node = match &self.nodes[id] {
+            Some(node) => node,
+            None => return Ok(()),
+        };
+        node.check().map_err(|reason| Violation::Node { node: id, reason })?;
+        let chain = node.finalized();
+        for (height, hash) in chain.iter().enumerate().skip(self.checked[id]) {
+            match self.finalized.get(height) {
+                Some(agreed) if agreed != hash => {
+                    return Err(Violation::ConflictingFinality { node: id, height: height as u64 })
+                }
+                Some(_) => {}
+                None => self.finalized.push(*hash),
+            }
+        }
+        self.checked[id] = chain.len();
+        Ok(())
+    }
+}
+
+// A validator for scenarios: Tendermint-style rounds of propose, prevote and
+// precommit among all simulated nodes, over real blocks. Finalized blocks go
+// through the `ImportPipeline` into the node's own `ChainStore`, catching up
+// is importing blocks a peer read from its store, and a restarted node
+// reopens its database and resumes from the persisted head. Inference jobs
+// are AIModelInvoke transactions that clients submit and blocks settle.
+#[derive(Debug, Clone)]
+pub struct ValidatorConfig {
+    pub validators: usize,
+    // Pause after a block before the next height starts
+    pub block_interval: SimTime,
+    // Grow with every round at a height, so a slow network eventually fits
+    pub propose_timeout: SimTime,
+    pub vote_timeout: SimTime,
+    pub max_jobs_per_block: usize,
+    pub max_sync_blocks: usize,
+}
+
+impl Default for ValidatorConfig {
+    fn default() -> Self {
+        Self {
+            validators: 4,
+            block_interval: 1_000,
+            propose_timeout: 1_000,
+            vote_timeout: 500,
+            max_jobs_per_block: 200,
+            max_sync_blocks: 64,
+        }
+    }
+}
+
+// An inference job as a client submits it: an invocation whose nonce is the
+// job's id. Everything else is fixed, so every node builds the same
+// transaction from the id.
+pub fn job_transaction(job: u64) -> Transaction {
+    let request = InvokeRequest::single(job.to_le_bytes().to_vec());
+    Transaction {
+        nonce: job,
+        from: Address::from([0; ADDRESS_LEN]),
+        to: Address::from([1; ADDRESS_LEN]),
+        value: Balance::zero(),
+        gas_price: 1,
+        gas_limit: request.intrinsic_gas(),
+        data: bincode::serialize(&request).expect("requests serialize"),
+        transaction_type: TransactionType::AIModelInvoke,
+        timestamp: 0,
+        signature: None,
+    }
+}
+
+fn jobs(block: &Block) -> impl Iterator<Item = u64> + '_ {
+    block
+        .transactions
+        .iter()
+        .filter(|tx| matches!(tx.transaction_type, TransactionType::AIModelInvoke))
+        .map(|tx| tx.nonce)
+}
+
+#[derive(Debug, Clone)]
+pub enum ValidatorMessage {
+    Proposal { height: u64, round: u32, block: Block, valid_round: Option<u32> },
+    Prevote { height: u64, round: u32, hash: Option<BlockHash> },
+    Precommit { height: u64, round: u32, hash: Option<BlockHash> },
+    SyncRequest { from: u64 },
+    // Consecutive blocks from height `from`
+    SyncResponse { from: u64, blocks: Vec<Block> },
+    // Submitted by a client and gossiped until a block settles it
+    Job(u64),
+}
+
+impl ValidatorMessage {
+    fn height(&self) -> Option<u64> {
+        match self {
+            ValidatorMessage::Proposal { height, .. }
+            | ValidatorMessage::Prevote { height, .. }
+            | ValidatorMessage::Precommit { height, .. } => Some(*height),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ValidatorTimer {
+    NewHeight { height: u64 },
+    Propose { height: u64, round: u32 },
+    Prevote { height: u64, round: u32 },
+    Precommit { height: u64, round: u32 },
+    // Gives up on a round whose votes got lost, since the vote timeouts only
+    // start once a quorum has voted
+    Round { height: u64, round: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Waiting,
+    Propose,
+    Prevote,
+    Precommit,
+}
+
+// Things that happen at most once per round
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Once {
+    PrevoteWait,
+    Lock,
+    PrecommitWait,
+}
+
+type Votes = BTreeMap<u32, BTreeMap<NodeId, Option<BlockHash>>>;
+type ValidatorContext<'a> = Context<'a, ValidatorMessage, ValidatorTimer>;
+
+pub struct SimValidator {
+    config: ValidatorConfig,
+    // Drives the store, which is async, from the simulation's handlers
+    runtime: Runtime,
+    pipeline: ImportPipeline,
+    // Hashes of the stored chain, by height
+    hashes: Vec<BlockHash>,
+    pending: BTreeSet<u64>,
+    settled: BTreeSet<u64>,
+    settled_twice: Vec<u64>,
+    // The first store error; the node is broken from then on
+    failure: Option<String>,
+    round: u32,
+    step: Step,
+    locked: Option<(u32, Block)>,
+    valid: Option<(u32, Block)>,
+    proposals: BTreeMap<u32, (Block, Option<u32>)>,
+    prevotes: Votes,
+    precommits: Votes,
+    fired: BTreeSet<(u32, Once)>,
+    // Messages for the next height, replayed once we get there
+    early: Vec<(NodeId, ValidatorMessage)>,
+    last_sync: Option<SimTime>,
+}
+
+impl SimValidator {
+    // Opens, or creates, the node's database at `path` and loads the chain
+    // stored there
+    pub fn open(config: ValidatorConfig, path: &Path) -> store::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build().expect("a current-thread runtime builds");
+        let store = ChainStore::new(Arc::new(Database::new(path)?));
+        let mut node = Self {
+            config,
+            runtime,
+            pipeline: ImportPipeline::new(store, ImportConfig::default()),
+            hashes: Vec::new(),
+            pending: BTreeSet::new(),
+            settled: BTreeSet::new(),
+            settled_twice: Vec::new(),
+            failure: None,
+            round: 0,
+            step: Step::Waiting,
+            locked: None,
+            valid: None,
+            proposals: BTreeMap::new(),
+            prevotes: Votes::new(),
+            precommits: Votes::new(),
+            fired: BTreeSet::new(),
+            early: Vec::new(),
+            last_sync: None,
+        };
+        let head = node.runtime.block_on(node.pipeline.persisted_head())?;
+        for height in head.map_or(0..0, |head| 0..head.height + 1) {
+            let block = node.runtime.block_on(node.pipeline.store().block(height))?;
+            node.settle(&block.ok_or(store::ChainStoreError::MissingBlock(height))?);
+        }
+        Ok(node)
+    }
+
+    pub fn store(&self) -> &ChainStore {
+        self.pipeline.store()
+    }
+
+    pub fn settled(&self) -> &BTreeSet<u64> {
+        &self.settled
+    }
+
+    fn height(&self) -> u64 {
+        self.hashes.len() as u64
+    }
+
+    fn parent(&self) -> BlockHash {
+        self.hashes.last().copied().unwrap_or_default()
+    }
+
+    fn quorum(&self) -> usize {
+        self.config.validators * 2 / 3 + 1
+    }
+
+    fn proposer(&self, height: u64, round: u32) -> NodeId {
+        ((height + round as u64) % self.config.validators as u64) as NodeId
+    }
+
+    fn timeout(&self, base: SimTime, round: u32) -> SimTime {
+        base * (1 + round as u64)
+    }
+
+    // Whether `block` could be the next one: it builds on our head and its
+    // header commits to its transactions
+    fn extends(&self, block: &Block) -> bool {
+        block.header.prev_block_hash == self.parent()
+            && block.header.merkle_root == Block::calculate_merkle_root(&block.transactions)
+    }
+
+    fn settle(&mut self, block: &Block) {
+        for job in jobs(block) {
+            if !self.settled.insert(job) {
+                self.settled_twice.push(job);
+            }
+            self.pending.remove(&job);
+        }
+        self.hashes.push(block.hash());
+    }
+
+    // Stages the next block in the pipeline; `flush` makes it durable
+    fn import(&mut self, block: Block) -> bool {
+        let height = self.height();
+        let runtime = &self.runtime;
+        match runtime.block_on(self.pipeline.import_block(height, &block, &[], &[])) {
+            Ok(_) => {
+                self.settle(&block);
+                true
+            }
+            Err(e) => {
+                self.failure.get_or_insert_with(|| format!("failed to import block {}: {}", height, e));
+                false
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let runtime = &self.runtime;
+        if let Err(e) = runtime.block_on(self.pipeline.flush()) {
+            self.failure.get_or_insert_with(|| format!("failed to flush blocks: {}", e));
+        }
+    }
+
+    fn enter_height(&mut self, ctx: &mut ValidatorContext) {
+        self.round = 0;
+        self.step = Step::Waiting;
+        self.locked = None;
+        self.valid = None;
+        self.proposals.clear();
+        self.prevotes.clear();
+        self.precommits.clear();
+        self.fired.clear();
+        let height = self.height();
+        let early = std::mem::take(&mut self.early);
+        for (from, message) in early.into_iter().filter(|(_, message)| message.height() == Some(height)) {
+            self.record(from, message);
+        }
+        ctx.set_timer(self.config.block_interval, ValidatorTimer::NewHeight { height });
+    }
+
+    fn start_round(&mut self, ctx: &mut ValidatorContext, round: u32) {
+        self.round = round;
+        self.step = Step::Propose;
+        let height = self.height();
+        if self.proposer(height, round) == ctx.id() {
+            let (block, valid_round) = match &self.valid {
+                Some((valid_round, block)) => (block.clone(), Some(*valid_round)),
+                None => {
+                    let transactions =
+                        self.pending.iter().take(self.config.max_jobs_per_block).map(|job| job_transaction(*job));
+                    let mut block = Block::new(self.parent(), transactions.collect(), 0)
+                        .expect("blocks hold more than max_jobs_per_block transactions");
+                    // The simulated clock, so runs replay
+                    block.header.timestamp = (ctx.now() / 1000) as i64;
+                    (block, None)
+                }
+            };
+            self.cast(ctx, ValidatorMessage::Proposal { height, round, block, valid_round });
+        }
+        ctx.set_timer(self.timeout(self.config.propose_timeout, round), ValidatorTimer::Propose { height, round });
+        let limit = self.config.propose_timeout + 2 * self.config.vote_timeout;
+        ctx.set_timer(self.timeout(limit, round), ValidatorTimer::Round { height, round });
+    }
+
+    // Sends our own message and counts it as received
+    fn cast(&mut self, ctx: &mut ValidatorContext, message: ValidatorMessage) {
+        ctx.broadcast(message.clone());
+        self.record(ctx.id(), message);
+    }
+
+    fn record(&mut self, from: NodeId, message: ValidatorMessage) {
+        match message {
+            ValidatorMessage::Proposal { height, round, block, valid_round } => {
+                if from == self.proposer(height, round) && self.extends(&block) {
+                    self.proposals.entry(round).or_insert((block, valid_round));
+                }
+            }
+            ValidatorMessage::Prevote { round, hash, .. } => {
+                self.prevotes.entry(round).or_default().entry(from).or_insert(hash);
+            }
+            ValidatorMessage::Precommit { round, hash, .. } => {
+                self.precommits.entry(round).or_default().entry(from).or_insert(hash);
+            }
+            _ => {}
+        }
+    }
+
+    fn count(votes: &Votes, round: u32, hash: Option<BlockHash>) -> usize {
+        votes.get(&round).map_or(0, |votes| votes.values().filter(|vote| **vote == hash).count())
+    }
+
+    fn total(votes: &Votes, round: u32) -> usize {
+        votes.get(&round).map_or(0, |votes| votes.len())
+    }
+
+    fn decided(&self) -> Option<Block> {
+        self.proposals
+            .iter()
+            .find(|(round, (block, _))| Self::count(&self.precommits, **round, Some(block.hash())) >= self.quorum())
+            .map(|(_, (block, _))| block.clone())
+    }
+
+    // A later round enough validators have moved to that one of them is honest
+    fn later_round(&self) -> Option<u32> {
+        let weak = self.config.validators - self.quorum() + 1;
+        let mut senders: BTreeMap<u32, BTreeSet<NodeId>> = BTreeMap::new();
+        for (round, votes) in self.prevotes.iter().chain(&self.precommits) {
+            senders.entry(*round).or_default().extend(votes.keys());
+        }
+        for round in self.proposals.keys() {
+            senders.entry(*round).or_default().insert(self.proposer(self.height(), *round));
+        }
+        senders.into_iter().rev().find(|(round, senders)| *round > self.round && senders.len() >= weak).map(|(r, _)| r)
+    }
+
+    fn acceptable(&self, block: &Block, valid_round: Option<u32>) -> bool {
+        match &self.locked {
+            None => true,
+            Some((locked_round, locked)) => {
+                locked.hash() == block.hash() || valid_round.map_or(false, |valid_round| *locked_round <= valid_round)
+            }
+        }
+    }
+
+    // Applies the consensus rules until none fires
+    fn advance(&mut self, ctx: &mut ValidatorContext) {
+        while self.step != Step::Waiting {
+            if let Some(block) = self.decided() {
+                // Durable before the next height, as a validator must not
+                // forget what it voted to finalize
+                if self.import(block) {
+                    self.flush();
+                }
+                self.enter_height(ctx);
+                return;
+            }
+            if let Some(round) = self.later_round() {
+                self.start_round(ctx, round);
+                continue;
+            }
+            let (height, round, quorum) = (self.height(), self.round, self.quorum());
+            let proposal = self.proposals.get(&round).cloned();
+
+            if let (Step::Propose, Some((block, valid_round))) = (self.step, &proposal) {
+                let hash = block.hash();
+                let ready = match valid_round {
+                    None => true,
+                    Some(valid_round) => {
+                        *valid_round < round && Self::count(&self.prevotes, *valid_round, Some(hash)) >= quorum
+                    }
+                };
+                if ready {
+                    let vote = Some(hash).filter(|_| self.acceptable(block, *valid_round));
+                    self.step = Step::Prevote;
+                    self.cast(ctx, ValidatorMessage::Prevote { height, round, hash: vote });
+                    continue;
+                }
+            }
+            if self.step == Step::Prevote
+                && Self::total(&self.prevotes, round) >= quorum
+                && self.fired.insert((round, Once::PrevoteWait))
+            {
+                ctx.set_timer(self.timeout(self.config.vote_timeout, round), ValidatorTimer::Prevote { height, round });
+            }
+            if let Some((block, _)) = proposal.filter(|_| self.step != Step::Propose) {
+                let hash = block.hash();
+                if Self::count(&self.prevotes, round, Some(hash)) >= quorum && self.fired.insert((round, Once::Lock)) {
+                    self.valid = Some((round, block.clone()));
+                    if self.step == Step::Prevote {
+                        self.locked = Some((round, block));
+                        self.step = Step::Precommit;
+                        self.cast(ctx, ValidatorMessage::Precommit { height, round, hash: Some(hash) });
+                    }
+                    continue;
+                }
+            }
+            if self.step == Step::Prevote && Self::count(&self.prevotes, round, None) >= quorum {
+                self.step = Step::Precommit;
+                self.cast(ctx, ValidatorMessage::Precommit { height, round, hash: None });
+                continue;
+            }
+            if Self::total(&self.precommits, round) >= quorum && self.fired.insert((round, Once::PrecommitWait)) {
+                ctx.set_timer(
+                    self.timeout(self.config.vote_timeout, round),
+                    ValidatorTimer::Precommit { height, round },
+                );
+            }
+            break;
+        }
+    }
+
+    fn sync_from(&mut self, ctx: &mut ValidatorContext, peer: NodeId) {
+        if self.last_sync.map_or(true, |last| ctx.now() >= last + self.config.vote_timeout) {
+            self.last_sync = Some(ctx.now());
+            ctx.send(peer, ValidatorMessage::SyncRequest { from: self.height() });
+        }
+    }
+
+    // Answers from the store, as a node serving sync does
+    fn serve(&self, ctx: &mut ValidatorContext, peer: NodeId, from: u64) {
+        let to = self.height().min(from.saturating_add(self.config.max_sync_blocks as u64));
+        let store = self.pipeline.store();
+        let blocks: Vec<Block> =
+            (from..to).map_while(|height| self.runtime.block_on(store.block(height)).ok().flatten()).collect();
+        if !blocks.is_empty() {
+            ctx.send(peer, ValidatorMessage::SyncResponse { from, blocks });
+        }
+    }
+}
+
+impl SimNode for SimValidator {
+    type Message = ValidatorMessage;
+    type Timer = ValidatorTimer;
+
+    fn start(&mut self, ctx: &mut ValidatorContext) {
+        self.enter_height(ctx);
+    }
+
+    fn on_message(&mut self, ctx: &mut ValidatorContext, from: NodeId, message: ValidatorMessage) {
+        match message {
+            ValidatorMessage::Job(job) => {
+                if !self.settled.contains(&job) && self.pending.insert(job) {
+                    ctx.broadcast(ValidatorMessage::Job(job));
+                }
+            }
+            ValidatorMessage::SyncRequest { from: height } => self.serve(ctx, from, height),
+            ValidatorMessage::SyncResponse { from: first, blocks } => {
+                let before = self.height();
+                for (height, block) in (first..).zip(blocks) {
+                    if height == self.height() && self.extends(&block) && !self.import(block) {
+                        break;
+                    }
+                }
+                self.flush();
+                if self.height() > before {
+                    self.enter_height(ctx);
+                }
+            }
+            message => {
+                let height = message.height().expect("consensus messages have a height");
+                match height.cmp(&self.height()) {
+                    Ordering::Less if from != ctx.id() => self.serve(ctx, from, height),
+                    Ordering::Less => {}
+                    Ordering::Greater => {
+                        if height == self.height() + 1 {
+                            self.early.push((from, message));
+                        }
+                        self.sync_from(ctx, from);
+                    }
+                    Ordering::Equal => {
+                        self.record(from, message);
+                        self.advance(ctx);
+                    }
+                }
+            }
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut ValidatorContext, timer: ValidatorTimer) {
+        let now = (self.height(), self.round);
+        match timer {
+            ValidatorTimer::NewHeight { height } if height == self.height() && self.step == Step::Waiting => {
+                self.start_round(ctx, 0);
+            }
+            ValidatorTimer::Propose { height, round } if (height, round) == now && self.step == Step::Propose => {
+                self.step = Step::Prevote;
+                self.cast(ctx, ValidatorMessage::Prevote { height, round, hash: None });
+            }
+            ValidatorTimer::Prevote { height, round } if (height, round) == now && self.step == Step::Prevote => {
+                self.step = Step::Precommit;
+                self.cast(ctx, ValidatorMessage::Precommit { height, round, hash: None });
+            }
+            ValidatorTimer::Precommit { height, round } | ValidatorTimer::Round { height, round }
+                if (height, round) == now && self.step != Step::Waiting =>
+            {
+                self.start_round(ctx, round + 1);
+            }
+            _ => return,
+        }
+        self.advance(ctx);
+    }
+
+    fn finalized(&self) -> &[BlockHash] {
+        &self.hashes
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if let Some(failure) = &self.failure {
+            return Err(failure.clone());
+        }
+        match self.settled_twice.first() {
+            Some(job) => Err(format!("job {} settled twice", job)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn scenario(seed: u64, dir: &Path) -> Simulation<SimValidator> {
+        let conditions =
+            LinkConditions { latency: Duration::from_millis(40), jitter: Duration::from_millis(60), drop_rate: 0.02 };
+        let dir = dir.to_path_buf();
+        let mut simulation = Simulation::new(seed, 4, conditions, move |id| {
+            SimValidator::open(ValidatorConfig::default(), &dir.join(id.to_string())).unwrap()
+        });
+        // Inference load: 3000 jobs in 15s, submitted round-robin to the
+        // nodes that stay up
+        for job in 0..3_000u64 {
+            simulation.schedule(job * 5, Action::Inject((job % 3) as NodeId, ValidatorMessage::Job(job)));
+        }
+        // Validator churn: one leaves and rejoins with only its database
+        simulation.schedule(10_000, Action::Crash(3));
+        simulation.schedule(20_000, Action::Restart(3));
+        // No side has a quorum, so nothing may finalize until it heals
+        simulation.schedule(30_000, Action::Partition(vec![vec![0, 1], vec![2, 3]]));
+        simulation.schedule(40_000, Action::Heal);
+        simulation
+    }
+
+    #[test]
+    fn test_churn_partitions_and_load_keep_one_chain_that_everyone_syncs() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut simulation = scenario(7, &temp_dir.path().join("run"));
+        simulation.run_until(30_500).unwrap();
+        let before_partition = simulation.finalized_height();
+        assert!(before_partition > 15);
+        simulation.run_until(39_000).unwrap();
+        assert!(simulation.finalized_height() <= before_partition + 1);
+
+        simulation.run_until(90_000).unwrap();
+        simulation.check_synced(1).unwrap();
+        assert!(simulation.finalized_height() > before_partition + 20);
+        for id in 0..4 {
+            let node = simulation.node(id).unwrap();
+            assert_eq!(node.settled().len(), 3_000);
+            // What consensus finalized is what the store holds
+            let head = node.runtime.block_on(node.store().head()).unwrap().unwrap();
+            assert_eq!(
+                (head.height + 1, head.hash),
+                (node.finalized().len() as u64, *node.finalized().last().unwrap())
+            );
+        }
+
+        // Replays exactly from the seed
+        let mut replay = scenario(7, &temp_dir.path().join("replay"));
+        replay.run_until(90_000).unwrap();
+        assert_eq!(replay.node(0).unwrap().finalized(), simulation.node(0).unwrap().finalized());
+    }
+}
@@ -0,0 +1,247 @@
+// OmniTensor-Project/omnitensor-core/src/storage/ancient.rs
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+use crate::storage::columns::Column;
+
+// Columns moved into the ancient store, one table per column
+pub const ANCIENT_TABLES: [Column; 3] = [Column::Headers, Column::Bodies, Column::Receipts];
+
+const INDEX_ENTRY_LEN: u64 = 8;
+
+#[derive(Debug, Error)]
+pub enum AncientError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Expected to freeze height {expected}, got {height}")]
+    NonContiguous { expected: u64, height: u64 },
+    #[error("Ancient index of {0} is corrupt")]
+    CorruptIndex(&'static str),
+    #[error("The ancient store is not encrypted and cannot back an encrypted database")]
+    Encrypted,
+}
+
+pub type Result<T> = std::result::Result<T, AncientError>;
+
+// `[storage.ancient]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AncientConfig {
+    pub enabled: bool,
+    // Defaults to an `ancient` directory next to the database
+    pub path: Option<PathBuf>,
+    // Finalized blocks this far behind the finalized height move to flat files
+    pub retain_blocks: u64,
+}
+
+impl Default for AncientConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: None,
+            retain_blocks: 90_000,
+        }
+    }
+}
+
+impl AncientConfig {
+    pub fn path_for(&self, database_path: &Path) -> PathBuf {
+        self.path.clone().unwrap_or_else(|| database_path.with_file_name("ancient"))
+    }
+}
+
+// Append-only table: `<column>.dat` holds the concatenated values and
+// `<column>.idx` one big-endian u64 end offset per item.
+struct Table {
+    name: &'static str,
+    data: File,
+    index: File,
+    data_len: u64,
+    items: u64,
+}
+
+impl Table {
+    fn open(dir: &Path, column: Column) -> Result<Self> {
+        let name = column.name();
+        let open = |ext: &str| {
+            OpenOptions::new().read(true).append(true).create(true).open(dir.join(format!("{}.{}", name, ext)))
+        };
+        let mut table = Self { name, data: open("dat")?, index: open("idx")?, data_len: 0, items: 0 };
+
+        // Drop a torn index entry, then any items whose data never reached disk
+        let index_len = table.index.metadata()?.len();
+        table.data_len = table.data.metadata()?.len();
+        table.items = index_len / INDEX_ENTRY_LEN;
+        while table.items > 0 && table.end_offset(table.items - 1)? > table.data_len {
+            table.items -= 1;
+        }
+        table.truncate(table.items)?;
+        Ok(table)
+    }
+
+    fn end_offset(&mut self, item: u64) -> Result<u64> {
+        let mut entry = [0u8; INDEX_ENTRY_LEN as usize];
+        self.index.seek(SeekFrom::Start(item * INDEX_ENTRY_LEN))?;
+        self.index.read_exact(&mut entry)?;
+        Ok(u64::from_be_bytes(entry))
+    }
+
+    fn start_offset(&mut self, item: u64) -> Result<u64> {
+        match item {
+            0 => Ok(0),
+            _ => self.end_offset(item - 1),
+        }
+    }
+
+    fn get(&mut self, item: u64) -> Result<Option<Vec<u8>>> {
+        if item >= self.items {
+            return Ok(None);
+        }
+        let start = self.start_offset(item)?;
+        let end = self.end_offset(item)?;
+        if end < start || end > self.data_len {
+            return Err(AncientError::CorruptIndex(self.name));
+        }
+        let mut value = vec![0u8; (end - start) as usize];
+        self.data.seek(SeekFrom::Start(start))?;
+        self.data.read_exact(&mut value)?;
+        Ok(Some(value))
+    }
+
+    fn append(&mut self, value: &[u8]) -> Result<()> {
+        self.data.write_all(value)?;
+        self.data_len += value.len() as u64;
+        self.index.write_all(&self.data_len.to_be_bytes())?;
+        self.items += 1;
+        Ok(())
+    }
+
+    fn truncate(&mut self, items: u64) -> Result<()> {
+        let data_len = self.start_offset(items)?;
+        self.index.set_len(items * INDEX_ENTRY_LEN)?;
+        self.data.set_len(data_len)?;
+        self.items = items;
+        self.data_len = data_len;
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.data.sync_data()?;
+        self.index.sync_data()?;
+        Ok(())
+    }
+}
+
+// Freezer-style cold storage for finalized chain data. Items are keyed by
+// height and only ever appended, so the files never need compaction and can be
+// copied with plain rsync. All tables always hold the same number of items:
+// a block frozen halfway through a crash is rolled back on open.
+pub struct AncientStore {
+    path: PathBuf,
+    tables: Mutex<Vec<Table>>,
+}
+
+impl AncientStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path)?;
+
+        let mut tables = ANCIENT_TABLES.iter().map(|column| Table::open(&path, *column)).collect::<Result<Vec<_>>>()?;
+        let frozen = tables.iter().map(|table| table.items).min().unwrap_or(0);
+        for table in tables.iter_mut() {
+            if table.items > frozen {
+                table.truncate(frozen)?;
+            }
+        }
+
+        Ok(Self { path, tables: Mutex::new(tables) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Number of frozen blocks, i.e. the next height to freeze
+    pub fn frozen(&self) -> u64 {
+        self.tables.lock().unwrap()[0].items
+    }
+
+    pub fn get(&self, column: Column, height: u64) -> Result<Option<Vec<u8>>> {
+        let position = match ANCIENT_TABLES.iter().position(|table| *table == column) {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        self.tables.lock().unwrap()[position].get(height)
+    }
+
+    // Values are not durable until `sync`
+    pub fn append(&self, height: u64, header: &[u8], body: &[u8], receipts: &[u8]) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        let expected = tables[0].items;
+        if height != expected {
+            return Err(AncientError::NonContiguous { expected, height });
+        }
+        for (table, value) in tables.iter_mut().zip([header, body, receipts]) {
+            table.append(value)?;
+        }
+        Ok(())
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.tables.lock().unwrap().iter().try_for_each(Table::sync)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_append_and_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let store = AncientStore::open(temp_dir.path()).unwrap();
+            for height in 0..3u64 {
+                let value = vec![height as u8; height as usize + 1];
+                store.append(height, &value, b"body", b"").unwrap();
+            }
+            assert!(matches!(store.append(5, b"", b"", b""), Err(AncientError::NonContiguous { expected: 3, .. })));
+            store.sync().unwrap();
+        }
+
+        let store = AncientStore::open(temp_dir.path()).unwrap();
+        assert_eq!(store.frozen(), 3);
+        assert_eq!(store.get(Column::Headers, 2).unwrap(), Some(vec![2, 2, 2]));
+        assert_eq!(store.get(Column::Bodies, 1).unwrap(), Some(b"body".to_vec()));
+        assert_eq!(store.get(Column::Receipts, 0).unwrap(), Some(vec![]));
+        assert_eq!(store.get(Column::Headers, 3).unwrap(), None);
+        assert_eq!(store.get(Column::State, 0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_rolls_back_partially_frozen_block() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let store = AncientStore::open(temp_dir.path()).unwrap();
+            store.append(0, b"h0", b"b0", b"r0").unwrap();
+            store.sync().unwrap();
+        }
+        // Simulated crash after only the header of block 1 was written
+        let mut data = OpenOptions::new().append(true).open(temp_dir.path().join("headers.dat")).unwrap();
+        data.write_all(b"h1").unwrap();
+        let mut index = OpenOptions::new().append(true).open(temp_dir.path().join("headers.idx")).unwrap();
+        index.write_all(&4u64.to_be_bytes()).unwrap();
+        index.write_all(&[0, 0, 0]).unwrap();
+
+        let store = AncientStore::open(temp_dir.path()).unwrap();
+        assert_eq!(store.frozen(), 1);
+        store.append(1, b"h1", b"b1", b"r1").unwrap();
+        assert_eq!(store.get(Column::Headers, 1).unwrap(), Some(b"h1".to_vec()));
+    }
+}
@@ -0,0 +1,415 @@
+// OmniTensor-Project/omnitensor-core/src/storage/backend.rs
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use crate::storage::ancient::AncientConfig;
+use crate::storage::cache::DEFAULT_CACHE_CAPACITY;
+use crate::storage::columns::Column;
+use crate::storage::db::{Database, DatabaseError, DatabaseOptions, Result};
+use crate::storage::encryption::EncryptionConfig;
+use crate::storage::transaction::{Operation, StorageTransaction};
+
+// Byte-level storage interface shared by every backend. `None` as the column
+// addresses the default keyspace.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn get_raw(&self, column: Option<Column>, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    async fn put_raw(&self, column: Option<Column>, key: &[u8], value: &[u8]) -> Result<()>;
+
+    async fn delete_raw(&self, column: Option<Column>, key: &[u8]) -> Result<()>;
+
+    // Applies every operation or none of them
+    async fn write(&self, transaction: StorageTransaction) -> Result<()>;
+
+    async fn iterate_prefix(&self, column: Option<Column>, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    // Reads several keys from a single consistent point in time
+    async fn snapshot_get(&self, keys: &[(Option<Column>, Vec<u8>)]) -> Result<Vec<Option<Vec<u8>>>>;
+}
+
+// Typed values in the default keyspace, bincode-encoded like `Database::put`.
+// This is how the chain's modules keep their state.
+impl<'a> dyn StorageBackend + 'a {
+    pub async fn get_value<T: DeserializeOwned>(&self, key: &[u8]) -> Result<Option<T>> {
+        match self.get_raw(None, key).await? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn put_value<T: Serialize + ?Sized>(&self, key: &[u8], value: &T) -> Result<()> {
+        self.put_raw(None, key, &bincode::serialize(value)?).await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Rocksdb,
+    Memory,
+    Sled,
+}
+
+impl Default for BackendKind {
+    fn default() -> Self {
+        BackendKind::Rocksdb
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendConfig {
+    #[serde(default)]
+    pub backend: BackendKind,
+    pub database_path: PathBuf,
+    #[serde(default = "default_cache_capacity")]
+    pub cache_capacity: usize,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    #[serde(default)]
+    pub rocksdb: RocksDbConfig,
+    #[serde(default)]
+    pub ancient: AncientConfig,
+    // Set on RPC/explorer processes to follow a validator's database read-only
+    #[serde(default)]
+    pub secondary_path: Option<PathBuf>,
+}
+
+impl BackendConfig {
+    // How the node and every maintenance command open the RocksDB database,
+    // so they all agree on its key and tuning
+    pub fn database_options(&self) -> Result<DatabaseOptions> {
+        Ok(DatabaseOptions {
+            cache_capacity: self.cache_capacity,
+            encryption: self.encryption.key_source()?,
+            rocksdb: self.rocksdb.clone(),
+            secondary_path: self.secondary_path.clone(),
+        })
+    }
+}
+
+fn default_cache_capacity() -> usize {
+    DEFAULT_CACHE_CAPACITY
+}
+
+// Applies to every column family. There is no FIFO: it drops the oldest SST
+// files once a size limit is hit, which would delete blocks and state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompactionStyle {
+    Level,
+    Universal,
+}
+
+// `[storage.rocksdb]`. Defaults suit a validator; archive RPC nodes typically want
+// a much larger block cache, universal compaction and more open files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RocksDbConfig {
+    pub compaction_style: CompactionStyle,
+    // Shared across all column families
+    pub block_cache_size_mb: usize,
+    // Per column family memtable size
+    pub write_buffer_size_mb: usize,
+    // -1 keeps every SST file open
+    pub max_open_files: i32,
+    pub max_background_jobs: i32,
+}
+
+impl Default for RocksDbConfig {
+    fn default() -> Self {
+        Self {
+            compaction_style: CompactionStyle::Level,
+            block_cache_size_mb: 256,
+            write_buffer_size_mb: 64,
+            max_open_files: 1024,
+            max_background_jobs: 4,
+        }
+    }
+}
+
+pub fn open_backend(config: &BackendConfig) -> Result<Arc<dyn StorageBackend>> {
+    if config.encryption.enabled && config.backend != BackendKind::Rocksdb {
+        return Err(DatabaseError::Backend("encryption at rest requires the rocksdb backend".to_string()));
+    }
+    match config.backend {
+        BackendKind::Rocksdb => {
+            Ok(Arc::new(Database::open(&config.database_path, config.database_options()?)?))
+        }
+        BackendKind::Memory => Ok(Arc::new(MemoryBackend::new())),
+        #[cfg(feature = "sled")]
+        BackendKind::Sled => Ok(Arc::new(sled_backend::SledBackend::open(&config.database_path)?)),
+        #[cfg(not(feature = "sled"))]
+        BackendKind::Sled => Err(DatabaseError::UnsupportedBackend("sled")),
+    }
+}
+
+type Keyspace = BTreeMap<Vec<u8>, Vec<u8>>;
+
+// Non-persistent backend for tests and light nodes
+#[derive(Default)]
+pub struct MemoryBackend {
+    columns: RwLock<HashMap<Option<Column>, Keyspace>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryBackend {
+    async fn get_raw(&self, column: Option<Column>, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let columns = self.columns.read().unwrap();
+        Ok(columns.get(&column).and_then(|keyspace| keyspace.get(key).cloned()))
+    }
+
+    async fn put_raw(&self, column: Option<Column>, key: &[u8], value: &[u8]) -> Result<()> {
+        let mut columns = self.columns.write().unwrap();
+        columns.entry(column).or_default().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    async fn delete_raw(&self, column: Option<Column>, key: &[u8]) -> Result<()> {
+        let mut columns = self.columns.write().unwrap();
+        if let Some(keyspace) = columns.get_mut(&column) {
+            keyspace.remove(key);
+        }
+        Ok(())
+    }
+
+    async fn write(&self, transaction: StorageTransaction) -> Result<()> {
+        // Holding the write lock for the whole batch makes it atomic for readers
+        let mut columns = self.columns.write().unwrap();
+        for operation in transaction.into_operations() {
+            match operation {
+                Operation::Put { column, key, value } => {
+                    columns.entry(column).or_default().insert(key, value);
+                }
+                Operation::Delete { column, key } => {
+                    if let Some(keyspace) = columns.get_mut(&column) {
+                        keyspace.remove(&key);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn iterate_prefix(&self, column: Option<Column>, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let columns = self.columns.read().unwrap();
+        Ok(columns
+            .get(&column)
+            .map(|keyspace| {
+                keyspace
+                    .range(prefix.to_vec()..)
+                    .take_while(|(key, _)| key.starts_with(prefix))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn snapshot_get(&self, keys: &[(Option<Column>, Vec<u8>)]) -> Result<Vec<Option<Vec<u8>>>> {
+        let columns = self.columns.read().unwrap();
+        Ok(keys
+            .iter()
+            .map(|(column, key)| columns.get(column).and_then(|keyspace| keyspace.get(key).cloned()))
+            .collect())
+    }
+}
+
+#[cfg(feature = "sled")]
+pub mod sled_backend {
+    use super::*;
+    use sled::transaction::{ConflictableTransactionError, TransactionError};
+    use sled::Transactional;
+    use std::path::Path;
+
+    pub struct SledBackend {
+        db: sled::Db,
+        // Index 0 is the default keyspace, followed by `Column::ALL` in order
+        trees: Vec<sled::Tree>,
+    }
+
+    impl SledBackend {
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+            let db = sled::open(path).map_err(sled_error)?;
+            let mut trees = vec![db.open_tree("default").map_err(sled_error)?];
+            for column in Column::ALL {
+                trees.push(db.open_tree(column.name()).map_err(sled_error)?);
+            }
+            Ok(Self { db, trees })
+        }
+
+        fn tree_index(column: Option<Column>) -> usize {
+            match column {
+                None => 0,
+                Some(column) => 1 + Column::ALL.iter().position(|c| *c == column).unwrap(),
+            }
+        }
+
+        fn tree(&self, column: Option<Column>) -> &sled::Tree {
+            &self.trees[Self::tree_index(column)]
+        }
+    }
+
+    fn sled_error(e: sled::Error) -> DatabaseError {
+        DatabaseError::Backend(e.to_string())
+    }
+
+    #[async_trait]
+    impl StorageBackend for SledBackend {
+        async fn get_raw(&self, column: Option<Column>, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            Ok(self.tree(column).get(key).map_err(sled_error)?.map(|value| value.to_vec()))
+        }
+
+        async fn put_raw(&self, column: Option<Column>, key: &[u8], value: &[u8]) -> Result<()> {
+            self.tree(column).insert(key, value).map_err(sled_error)?;
+            Ok(())
+        }
+
+        async fn delete_raw(&self, column: Option<Column>, key: &[u8]) -> Result<()> {
+            self.tree(column).remove(key).map_err(sled_error)?;
+            Ok(())
+        }
+
+        async fn write(&self, transaction: StorageTransaction) -> Result<()> {
+            let operations = transaction.into_operations();
+            self.trees
+                .as_slice()
+                .transaction(|trees| {
+                    for operation in &operations {
+                        match operation {
+                            Operation::Put { column, key, value } => {
+                                trees[Self::tree_index(*column)].insert(key.as_slice(), value.as_slice())?;
+                            }
+                            Operation::Delete { column, key } => {
+                                trees[Self::tree_index(*column)].remove(key.as_slice())?;
+                            }
+                        }
+                    }
+                    Ok::<_, ConflictableTransactionError<()>>(())
+                })
+                .map_err(|e: TransactionError<()>| DatabaseError::Backend(format!("{:?}", e)))?;
+            self.db.flush_async().await.map_err(sled_error)?;
+            Ok(())
+        }
+
+        async fn iterate_prefix(&self, column: Option<Column>, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            self.tree(column)
+                .scan_prefix(prefix)
+                .map(|item| {
+                    item.map(|(key, value)| (key.to_vec(), value.to_vec()))
+                        .map_err(sled_error)
+                })
+                .collect()
+        }
+
+        async fn snapshot_get(&self, keys: &[(Option<Column>, Vec<u8>)]) -> Result<Vec<Option<Vec<u8>>>> {
+            // A read-only transaction observes a consistent view across trees
+            self.trees
+                .as_slice()
+                .transaction(|trees| {
+                    Ok::<_, ConflictableTransactionError<()>>(
+                        keys.iter()
+                            .map(|(column, key)| {
+                                trees[Self::tree_index(*column)]
+                                    .get(key.as_slice())
+                                    .map(|value| value.map(|value| value.to_vec()))
+                            })
+                            .collect::<std::result::Result<Vec<_>, _>>()?,
+                    )
+                })
+                .map_err(|e: TransactionError<()>| DatabaseError::Backend(format!("{:?}", e)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn exercise(backend: &dyn StorageBackend) -> Result<()> {
+        backend.put_raw(Some(Column::State), b"acct:1", b"10").await?;
+        backend.put_raw(Some(Column::State), b"acct:2", b"20").await?;
+        backend.put_raw(Some(Column::Headers), b"acct:3", b"30").await?;
+        backend.put_raw(None, b"other", b"x").await?;
+
+        assert_eq!(backend.get_raw(Some(Column::State), b"acct:1").await?, Some(b"10".to_vec()));
+        assert_eq!(backend.iterate_prefix(Some(Column::State), b"acct:").await?.len(), 2);
+
+        let mut txn = StorageTransaction::new();
+        txn.put_raw(Column::State, b"acct:1".to_vec(), b"11".to_vec())
+            .delete_raw(Column::State, b"acct:2".to_vec());
+        backend.write(txn).await?;
+
+        let values = backend
+            .snapshot_get(&[(Some(Column::State), b"acct:1".to_vec()), (Some(Column::State), b"acct:2".to_vec())])
+            .await?;
+        assert_eq!(values, vec![Some(b"11".to_vec()), None]);
+
+        backend.delete_raw(None, b"other").await?;
+        assert_eq!(backend.get_raw(None, b"other").await?, None);
+
+        backend.put_value(b"typed", &(7u64, "seven".to_string())).await?;
+        assert_eq!(backend.get_value(b"typed").await?, Some((7u64, "seven".to_string())));
+        assert_eq!(backend.get_value::<u64>(b"untyped").await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_memory_backend() -> Result<()> {
+        exercise(&MemoryBackend::new()).await
+    }
+
+    #[tokio::test]
+    async fn test_rocksdb_backend() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let backend = open_backend(&BackendConfig {
+            backend: BackendKind::Rocksdb,
+            database_path: temp_dir.path().to_path_buf(),
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            encryption: EncryptionConfig::default(),
+            rocksdb: RocksDbConfig::default(),
+            ancient: AncientConfig::default(),
+            secondary_path: None,
+        })?;
+        exercise(backend.as_ref()).await
+    }
+
+    #[tokio::test]
+    async fn test_rocksdb_tuning_from_config() -> Result<()> {
+        let rocksdb: RocksDbConfig =
+            serde_json::from_str(r#"{"compaction_style": "universal", "block_cache_size_mb": 8, "max_open_files": -1}"#).unwrap();
+        assert_eq!(rocksdb.compaction_style, CompactionStyle::Universal);
+        assert_eq!(rocksdb.write_buffer_size_mb, RocksDbConfig::default().write_buffer_size_mb);
+        assert!(serde_json::from_str::<RocksDbConfig>(r#"{"compaction_style": "fifo"}"#).is_err());
+
+        let temp_dir = TempDir::new()?;
+        let backend = open_backend(&BackendConfig {
+            backend: BackendKind::Rocksdb,
+            database_path: temp_dir.path().to_path_buf(),
+            cache_capacity: 0,
+            encryption: EncryptionConfig::default(),
+            rocksdb,
+            ancient: AncientConfig::default(),
+            secondary_path: None,
+        })?;
+        exercise(backend.as_ref()).await
+    }
+
+    #[cfg(feature = "sled")]
+    #[tokio::test]
+    async fn test_sled_backend() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        exercise(&sled_backend::SledBackend::open(temp_dir.path())?).await
+    }
+}
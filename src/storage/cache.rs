@@ -0,0 +1,174 @@
+// OmniTensor-Project/omnitensor-core/src/storage/cache.rs
+
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::storage::columns::Column;
+
+pub const DEFAULT_CACHE_CAPACITY: usize = 16_384;
+
+type CacheKey = (Option<Column>, Vec<u8>);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+// In-process LRU over raw point reads. Readers and writers hit RocksDB
+// concurrently, so every invalidation bumps a generation counter and a fill is
+// only accepted if no invalidation happened since the reader went to disk.
+// A capacity of zero disables caching.
+pub struct ReadCache {
+    entries: Option<Mutex<LruCache<CacheKey, Vec<u8>>>>,
+    capacity: usize,
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ReadCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity))),
+            capacity,
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, column: Option<Column>, key: &[u8]) -> Option<Vec<u8>> {
+        let entries = self.entries.as_ref()?;
+        let value = entries.lock().unwrap().get(&(column, key.to_vec())).cloned();
+        let counter = if value.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    // Read before going to disk and pass to `insert_if_current`
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub fn insert(&self, column: Option<Column>, key: &[u8], value: &[u8]) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().put((column, key.to_vec()), value.to_vec());
+        }
+    }
+
+    pub fn insert_if_current(&self, generation: u64, column: Option<Column>, key: &[u8], value: &[u8]) {
+        if let Some(entries) = &self.entries {
+            let mut entries = entries.lock().unwrap();
+            if self.generation.load(Ordering::Acquire) == generation {
+                entries.put((column, key.to_vec()), value.to_vec());
+            }
+        }
+    }
+
+    pub fn invalidate(&self, column: Option<Column>, key: &[u8]) {
+        if let Some(entries) = &self.entries {
+            let mut entries = entries.lock().unwrap();
+            entries.pop(&(column, key.to_vec()));
+            self.generation.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    // Used for range deletes, where enumerating the affected keys isn't worth it
+    pub fn invalidate_column(&self, column: Option<Column>) {
+        if let Some(entries) = &self.entries {
+            let mut entries = entries.lock().unwrap();
+            self.generation.fetch_add(1, Ordering::Release);
+            let stale: Vec<CacheKey> = entries
+                .iter()
+                .filter(|((entry_column, _), _)| *entry_column == column)
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in stale {
+                entries.pop(&key);
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Some(entries) = &self.entries {
+            let mut entries = entries.lock().unwrap();
+            entries.clear();
+            self.generation.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.as_ref().map_or(0, |entries| entries.lock().unwrap().len()),
+            capacity: self.capacity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction_and_stats() {
+        let cache = ReadCache::new(2);
+        cache.insert(Some(Column::Headers), b"a", b"1");
+        cache.insert(Some(Column::Headers), b"b", b"2");
+        assert_eq!(cache.get(Some(Column::Headers), b"a"), Some(b"1".to_vec()));
+
+        // "b" is now least recently used
+        cache.insert(Some(Column::State), b"c", b"3");
+        assert_eq!(cache.get(Some(Column::Headers), b"b"), None);
+        assert_eq!(cache.get(Some(Column::Headers), b"a"), Some(b"1".to_vec()));
+
+        // Same key in another column is a different entry
+        assert_eq!(cache.get(None, b"c"), None);
+
+        cache.invalidate_column(Some(Column::State));
+        assert_eq!(cache.get(Some(Column::State), b"c"), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 3, 1));
+        assert!((stats.hit_rate() - 0.4).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_stale_fill_is_dropped() {
+        let cache = ReadCache::new(4);
+        let before_read = cache.generation();
+        // A writer lands between the reader's disk read and its fill
+        cache.invalidate(None, b"a");
+        cache.insert_if_current(before_read, None, b"a", b"old");
+        assert_eq!(cache.get(None, b"a"), None);
+
+        cache.insert_if_current(cache.generation(), None, b"a", b"new");
+        assert_eq!(cache.get(None, b"a"), Some(b"new".to_vec()));
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_cache() {
+        let cache = ReadCache::new(0);
+        cache.insert(None, b"a", b"1");
+        assert_eq!(cache.get(None, b"a"), None);
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
+}
@@ -0,0 +1,107 @@
+// OmniTensor-Project/omnitensor-core/src/storage/columns.rs
+
+use rocksdb::{BlockBasedOptions, Cache, ColumnFamilyDescriptor, DBCompactionStyle, DBCompressionType, Options, SliceTransform};
+
+use crate::storage::backend::{CompactionStyle, RocksDbConfig};
+use crate::storage::keys::DOMAIN_PREFIX_LEN;
+use crate::storage::ttl;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+    Headers,
+    Bodies,
+    Receipts,
+    State,
+    Indexes,
+    Consensus,
+    Ephemeral,
+}
+
+impl Column {
+    pub const ALL: [Column; 7] = [
+        Column::Headers,
+        Column::Bodies,
+        Column::Receipts,
+        Column::State,
+        Column::Indexes,
+        Column::Consensus,
+        Column::Ephemeral,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Headers => "headers",
+            Column::Bodies => "bodies",
+            Column::Receipts => "receipts",
+            Column::State => "state",
+            Column::Indexes => "indexes",
+            Column::Consensus => "consensus",
+            Column::Ephemeral => "ephemeral",
+        }
+    }
+
+    // Fixed prefix length configured as the column's prefix extractor, see `storage::keys`
+    pub fn prefix_len(&self) -> Option<usize> {
+        match self {
+            Column::State | Column::Indexes => Some(DOMAIN_PREFIX_LEN),
+            _ => None,
+        }
+    }
+
+    pub fn options(&self, tuning: &RocksDbConfig, block_cache: &Cache) -> Options {
+        let mut opts = Options::default();
+        opts.set_compaction_style(match tuning.compaction_style {
+            CompactionStyle::Level => DBCompactionStyle::Level,
+            CompactionStyle::Universal => DBCompactionStyle::Universal,
+        });
+        opts.set_write_buffer_size(tuning.write_buffer_size_mb * 1024 * 1024);
+        if let Some(len) = self.prefix_len() {
+            opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(len));
+            opts.set_memtable_prefix_bloom_ratio(0.1);
+        }
+        match self {
+            // Large, append-mostly and rarely read once old: favour compression ratio
+            Column::Bodies | Column::Receipts => {
+                opts.set_compression_type(DBCompressionType::Zstd);
+                let mut table = BlockBasedOptions::default();
+                table.set_block_size(64 * 1024);
+                table.set_block_cache(block_cache);
+                opts.set_block_based_table_factory(&table);
+            }
+            // Hot point lookups during validation
+            Column::Headers | Column::State | Column::Consensus => {
+                opts.set_compression_type(DBCompressionType::Lz4);
+                let mut table = BlockBasedOptions::default();
+                table.set_bloom_filter(10.0, false);
+                table.set_cache_index_and_filter_blocks(true);
+                table.set_block_cache(block_cache);
+                opts.set_block_based_table_factory(&table);
+            }
+            Column::Indexes => {
+                opts.set_compression_type(DBCompressionType::Lz4);
+                let mut table = BlockBasedOptions::default();
+                table.set_bloom_filter(10.0, false);
+                // Index lookups are almost always by prefix, not by whole key
+                table.set_whole_key_filtering(false);
+                table.set_block_cache(block_cache);
+                opts.set_block_based_table_factory(&table);
+            }
+            // Small, short-lived entries; expired ones are dropped during compaction
+            Column::Ephemeral => {
+                opts.set_compaction_filter("ttl", ttl::compaction_filter);
+                let mut table = BlockBasedOptions::default();
+                table.set_bloom_filter(10.0, false);
+                table.set_block_cache(block_cache);
+                opts.set_block_based_table_factory(&table);
+            }
+        }
+        opts
+    }
+
+    pub fn descriptors(tuning: &RocksDbConfig, block_cache: &Cache) -> Vec<ColumnFamilyDescriptor> {
+        Self::ALL
+            .iter()
+            .map(|column| ColumnFamilyDescriptor::new(column.name(), column.options(tuning, block_cache)))
+            .collect()
+    }
+}
@@ -1,11 +1,23 @@
 // OmniTensor-Project/omnitensor-core/src/storage/db.rs
 
-use rocksdb::{DB, Options, IteratorMode, WriteBatch};
+use async_trait::async_trait;
+use log::error;
+use rocksdb::{Cache, DB, Direction, Options, IteratorMode, ReadOptions, WriteBatch, WriteOptions};
+use rocksdb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
 use serde::{Serialize, Deserialize};
 use thiserror::Error;
-use std::path::Path;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use crate::storage::backend::{RocksDbConfig, StorageBackend};
+use crate::storage::cache::{CacheStats, ReadCache, DEFAULT_CACHE_CAPACITY};
+use crate::storage::columns::Column;
+use crate::storage::encryption::{envelope_key_id, EncryptionError, KeySource, Keyring, StoredKeyring, KEYRING_KEY};
+use crate::storage::stats::{ColumnStats, StorageStats};
+use crate::storage::transaction::StorageTransaction;
+use crate::utils::metrics::LatencyHistogram;
 
 #[derive(Error, Debug)]
 pub enum DatabaseError {
@@ -17,21 +29,359 @@ pub enum DatabaseError {
     Serialization(#[from] bincode::Error),
     #[error("Key not found: {0}")]
     KeyNotFound(Vec<u8>),
+    #[error("Missing column family: {0}")]
+    MissingColumnFamily(&'static str),
+    #[error("Database schema version {found} is newer than supported version {supported}")]
+    SchemaTooNew { found: u32, supported: u32 },
+    #[error("Migration to version {version} failed: {reason}")]
+    MigrationFailed { version: u32, reason: String },
+    #[error("Storage backend not compiled in: {0}")]
+    UnsupportedBackend(&'static str),
+    #[error("Backend error: {0}")]
+    Backend(String),
+    #[error("Continuation token does not belong to this scan")]
+    InvalidContinuationToken,
+    #[error("Encryption error: {0}")]
+    Encryption(#[from] EncryptionError),
+    #[error("Cannot enable encryption on a database that already holds plaintext data")]
+    PlaintextDatabase,
+    #[error("Database is opened as a read-only secondary instance")]
+    ReadOnly,
+    #[error("Checksum mismatch for key {key:?} in column {column}")]
+    Corruption { column: &'static str, key: Vec<u8> },
+    #[error("Database at {0} is in use; stop the node first")]
+    InUse(PathBuf),
 }
 
 pub type Result<T> = std::result::Result<T, DatabaseError>;
 
+// Present once every stored value carries a trailing big-endian CRC32
+const CHECKSUMS_KEY: &[u8] = b"__value_checksums";
+const CHECKSUM_LEN: usize = 4;
+// While `enable_checksums` runs: the position in its column order and the
+// last key it rewrote there
+const CHECKSUM_PROGRESS_KEY: &[u8] = b"__checksum_progress";
+const CHECKSUM_BATCH_SIZE: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub backup_id: u32,
+    pub timestamp: i64,
+    pub size: u64,
+    pub num_files: u32,
+}
+
+impl From<rocksdb::backup::BackupEngineInfo> for BackupInfo {
+    fn from(info: rocksdb::backup::BackupEngineInfo) -> Self {
+        Self {
+            backup_id: info.backup_id,
+            timestamp: info.timestamp,
+            size: info.size,
+            num_files: info.num_files,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseOptions {
+    pub cache_capacity: usize,
+    // Encrypts every value at rest when set
+    pub encryption: Option<KeySource>,
+    pub rocksdb: RocksDbConfig,
+    // Opens `path` as a read-only secondary that keeps its own logs here
+    pub secondary_path: Option<PathBuf>,
+}
+
+impl Default for DatabaseOptions {
+    fn default() -> Self {
+        Self {
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            encryption: None,
+            rocksdb: RocksDbConfig::default(),
+            secondary_path: None,
+        }
+    }
+}
+
+struct Encryption {
+    source: KeySource,
+    keyring: Keyring,
+}
+
 pub struct Database {
-    db: Arc<Mutex<DB>>,
+    db: Arc<DB>,
+    cache: ReadCache,
+    encryption: Option<RwLock<Encryption>>,
+    read_latency: LatencyHistogram,
+    write_latency: LatencyHistogram,
+    secondary: bool,
+    checksums: AtomicBool,
+    corruptions: AtomicU64,
 }
 
 impl Database {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open(path, DatabaseOptions::default())
+    }
+
+    pub fn with_cache_capacity<P: AsRef<Path>>(path: P, cache_capacity: usize) -> Result<Self> {
+        Self::open(path, DatabaseOptions { cache_capacity, ..DatabaseOptions::default() })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, options: DatabaseOptions) -> Result<Self> {
+        let tuning = &options.rocksdb;
         let mut opts = Options::default();
         opts.create_if_missing(true);
-        let db = DB::open(&opts, path)?;
+        opts.create_missing_column_families(true);
+        opts.set_max_open_files(tuning.max_open_files);
+        opts.set_max_background_jobs(tuning.max_background_jobs);
+
+        let block_cache = Cache::new_lru_cache(tuning.block_cache_size_mb * 1024 * 1024)?;
+        let db = match &options.secondary_path {
+            Some(secondary_path) => {
+                // Secondaries must keep every file open to follow the primary's compactions
+                opts.set_max_open_files(-1);
+                let columns = Column::ALL.iter().map(|column| column.name());
+                DB::open_cf_as_secondary(&opts, path.as_ref(), secondary_path.as_path(), columns)?
+            }
+            None => DB::open_cf_descriptors(&opts, path, Column::descriptors(tuning, &block_cache))?,
+        };
+        let encryption = Self::load_keyring(&db, options.encryption)?;
+        let checksums = Self::detect_checksums(&db, options.secondary_path.is_some())?;
         Ok(Self {
-            db: Arc::new(Mutex::new(db)),
+            db: Arc::new(db),
+            cache: ReadCache::new(options.cache_capacity),
+            encryption: encryption.map(RwLock::new),
+            read_latency: LatencyHistogram::new(),
+            write_latency: LatencyHistogram::new(),
+            secondary: options.secondary_path.is_some(),
+            checksums: AtomicBool::new(checksums),
+            corruptions: AtomicU64::new(0),
+        })
+    }
+
+    // New databases checksum from the start; older ones are converted by a migration
+    fn detect_checksums(db: &DB, secondary: bool) -> Result<bool> {
+        if db.get(CHECKSUMS_KEY)?.is_some() {
+            return Ok(true);
+        }
+        if secondary || !Self::raw_is_empty(db)? {
+            return Ok(false);
+        }
+        db.put(CHECKSUMS_KEY, [1])?;
+        Ok(true)
+    }
+
+    // Appends a checksum to every value of a database written before checksums
+    // existed, in bounded batches. Each batch records its progress, so a run
+    // interrupted half way resumes after the last rewritten key instead of
+    // checksumming values twice.
+    pub fn enable_checksums(&self) -> Result<usize> {
+        if self.checksums.load(Ordering::Acquire) {
+            return Ok(0);
+        }
+        let db = &*self.db;
+        let progress: Option<(u32, Vec<u8>)> =
+            db.get(CHECKSUM_PROGRESS_KEY)?.map(|bytes| bincode::deserialize(&bytes)).transpose()?;
+        let mut batch = WriteBatch::default();
+        let mut pending = 0;
+        let mut rewritten = 0;
+
+        let columns = std::iter::once(None).chain(Column::ALL.iter().copied().map(Some));
+        for (index, column) in (0u32..).zip(columns) {
+            let resume_after = match &progress {
+                Some((done, _)) if index < *done => continue,
+                Some((done, key)) if index == *done => Some(key.as_slice()),
+                _ => None,
+            };
+            let mode = match resume_after {
+                Some(key) => IteratorMode::From(key, Direction::Forward),
+                None => IteratorMode::Start,
+            };
+            let iter = match column {
+                Some(column) => db.iterator_cf(Self::cf_handle(db, column)?, mode),
+                None => db.iterator(mode),
+            };
+            for item in iter {
+                let (key, value) = item?;
+                if Self::is_reserved(column, &key) || resume_after == Some(&*key) {
+                    continue;
+                }
+                let value = with_checksum(value.to_vec());
+                match column {
+                    Some(column) => batch.put_cf(Self::cf_handle(db, column)?, &key, value),
+                    None => batch.put(&key, value),
+                }
+                rewritten += 1;
+                pending += 1;
+                if pending == CHECKSUM_BATCH_SIZE {
+                    batch.put(CHECKSUM_PROGRESS_KEY, bincode::serialize(&(index, key.to_vec()))?);
+                    let full = std::mem::take(&mut batch);
+                    self.timed_write(|| db.write(full))?;
+                    pending = 0;
+                }
+            }
+        }
+        batch.delete(CHECKSUM_PROGRESS_KEY);
+        batch.put(CHECKSUMS_KEY, [1]);
+        self.timed_write(|| db.write(batch))?;
+
+        self.checksums.store(true, Ordering::Release);
+        self.cache.clear();
+        Ok(rewritten)
+    }
+
+    // The keyring lives unencrypted in the default column so that backups carry it
+    fn load_keyring(db: &DB, source: Option<KeySource>) -> Result<Option<Encryption>> {
+        match (db.get(KEYRING_KEY)?, source) {
+            (None, None) => Ok(None),
+            (Some(_), None) => Err(EncryptionError::KeyRequired.into()),
+            (Some(bytes), Some(source)) => {
+                let stored: StoredKeyring = bincode::deserialize(&bytes)?;
+                let keyring = Keyring::unwrap(&stored, &source)?;
+                Ok(Some(Encryption { source, keyring }))
+            }
+            (None, Some(source)) => {
+                if !Self::raw_is_empty(db)? {
+                    return Err(DatabaseError::PlaintextDatabase);
+                }
+                let keyring = Keyring::generate();
+                db.put(KEYRING_KEY, bincode::serialize(&keyring.wrap(&source))?)?;
+                Ok(Some(Encryption { source, keyring }))
+            }
+        }
+    }
+
+    pub fn is_secondary(&self) -> bool {
+        self.secondary
+    }
+
+    // Replays whatever the primary wrote since the last catch-up. Secondaries only
+    // see new data after calling this, e.g. on a timer or before a heavy query.
+    // The primary may have rotated its data key or added checksums meanwhile,
+    // so both are read again.
+    pub fn catch_up(&self) -> Result<()> {
+        if !self.secondary {
+            return Ok(());
+        }
+        self.db.try_catch_up_with_primary()?;
+        match (&self.encryption, self.db.get(KEYRING_KEY)?) {
+            (Some(encryption), Some(bytes)) => {
+                let mut encryption = encryption.write().unwrap();
+                let stored: StoredKeyring = bincode::deserialize(&bytes)?;
+                encryption.keyring = Keyring::unwrap(&stored, &encryption.source)?;
+            }
+            (None, Some(_)) => return Err(EncryptionError::KeyRequired.into()),
+            _ => {}
+        }
+        if self.db.get(CHECKSUMS_KEY)?.is_some() {
+            self.checksums.store(true, Ordering::Release);
+        }
+        self.cache.clear();
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    // New writes use a fresh data key; existing values stay readable under the old one
+    pub fn rotate_data_key(&self) -> Result<u32> {
+        let mut encryption = self.encryption()?.write().unwrap();
+        let id = encryption.keyring.rotate();
+        self.db.put(KEYRING_KEY, bincode::serialize(&encryption.keyring.wrap(&encryption.source))?)?;
+        Ok(id)
+    }
+
+    // Rewraps the keyring under a new passphrase or KMS key without touching data
+    pub fn change_master_key(&self, source: KeySource) -> Result<()> {
+        let mut encryption = self.encryption()?.write().unwrap();
+        self.db.put(KEYRING_KEY, bincode::serialize(&encryption.keyring.wrap(&source))?)?;
+        encryption.source = source;
+        Ok(())
+    }
+
+    // Re-seals every value under the active data key and then drops retired keys.
+    // Maintenance operation: run it with the node stopped, as a write racing
+    // the rewrite of the same key can be overwritten. Returns the values rewritten.
+    pub fn reencrypt(&self) -> Result<usize> {
+        let mut encryption = self.encryption()?.write().unwrap();
+        let active = encryption.keyring.active();
+        let db = &*self.db;
+        let mut rewritten = 0;
+
+        let columns = std::iter::once(None).chain(Column::ALL.iter().copied().map(Some));
+        for column in columns {
+            let mut batch = WriteBatch::default();
+            let iter = match column {
+                Some(column) => db.iterator_cf(Self::cf_handle(db, column)?, IteratorMode::Start),
+                None => db.iterator(IteratorMode::Start),
+            };
+            for item in iter {
+                let (key, value) = item?;
+                if Self::is_reserved(column, &key) || !Self::is_sealed(column) {
+                    continue;
+                }
+                let value = self.verify(column, &key, value.to_vec())?;
+                if envelope_key_id(&value)? == active {
+                    continue;
+                }
+                let plaintext = encryption.keyring.decrypt(column, &key, &value)?;
+                let sealed = self.checksummed(encryption.keyring.encrypt(column, &key, &plaintext));
+                match column {
+                    Some(column) => batch.put_cf(Self::cf_handle(db, column)?, &key, sealed),
+                    None => batch.put(&key, sealed),
+                }
+                rewritten += 1;
+            }
+            self.timed_write(|| db.write(batch))?;
+        }
+
+        encryption.keyring.retire_inactive();
+        db.put(KEYRING_KEY, bincode::serialize(&encryption.keyring.wrap(&encryption.source))?)?;
+        Ok(rewritten)
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    // Compacts every column down to the bottom level, e.g. to reclaim space
+    // after a large prune. Blocks until done; can take minutes on big databases.
+    pub fn compact(&self) -> Result<()> {
+        for column in Column::ALL {
+            self.db.compact_range_cf(Self::cf_handle(&self.db, column)?, None::<&[u8]>, None::<&[u8]>);
+        }
+        Ok(())
+    }
+
+    // Point-in-time size, compaction and latency figures for operators
+    pub fn stats(&self) -> Result<StorageStats> {
+        let db = &*self.db;
+        let mut columns = Vec::with_capacity(Column::ALL.len());
+        for column in Column::ALL {
+            let cf = Self::cf_handle(db, column)?;
+            let property = |name: &str| -> Result<u64> { Ok(db.property_int_value_cf(cf, name)?.unwrap_or(0)) };
+            columns.push(ColumnStats {
+                column: column.name(),
+                sst_bytes: property("rocksdb.total-sst-files-size")?,
+                live_data_bytes: property("rocksdb.estimate-live-data-size")?,
+                memtable_bytes: property("rocksdb.cur-size-all-mem-tables")?,
+                estimated_keys: property("rocksdb.estimate-num-keys")?,
+                pending_compaction_bytes: property("rocksdb.estimate-pending-compaction-bytes")?,
+            });
+        }
+
+        Ok(StorageStats {
+            columns,
+            running_compactions: db.property_int_value("rocksdb.num-running-compactions")?.unwrap_or(0),
+            running_flushes: db.property_int_value("rocksdb.num-running-flushes")?.unwrap_or(0),
+            reads: self.read_latency.count(),
+            read_p99: self.read_latency.quantile(0.99),
+            writes: self.write_latency.count(),
+            write_p99: self.write_latency.quantile(0.99),
+            cache: self.cache.stats(),
+            corruptions: self.corruptions.load(Ordering::Relaxed),
         })
     }
 
@@ -41,8 +391,8 @@ impl Database {
         V: for<'de> Deserialize<'de>,
     {
         let key_bytes = bincode::serialize(key)?;
-        let db = self.db.lock().await;
-        match db.get(&key_bytes)? {
+        let db = &*self.db;
+        match self.read_through(db, None, &key_bytes)? {
             Some(value_bytes) => Ok(Some(bincode::deserialize(&value_bytes)?)),
             None => Ok(None),
         }
@@ -55,8 +405,9 @@ impl Database {
     {
         let key_bytes = bincode::serialize(key)?;
         let value_bytes = bincode::serialize(value)?;
-        let db = self.db.lock().await;
-        db.put(&key_bytes, &value_bytes)?;
+        let db = &*self.db;
+        self.timed_write(|| db.put(&key_bytes, self.seal(None, &key_bytes, value_bytes)))?;
+        self.cache.invalidate(None, &key_bytes);
         Ok(())
     }
 
@@ -65,8 +416,9 @@ impl Database {
         K: Serialize,
     {
         let key_bytes = bincode::serialize(key)?;
-        let db = self.db.lock().await;
-        db.delete(&key_bytes)?;
+        let db = &*self.db;
+        self.timed_write(|| db.delete(&key_bytes))?;
+        self.cache.invalidate(None, &key_bytes);
         Ok(())
     }
 
@@ -76,13 +428,18 @@ impl Database {
         V: Serialize,
     {
         let mut batch = WriteBatch::default();
+        let mut written = Vec::with_capacity(data.len());
         for (key, value) in data {
             let key_bytes = bincode::serialize(key)?;
             let value_bytes = bincode::serialize(value)?;
-            batch.put(&key_bytes, &value_bytes);
+            batch.put(&key_bytes, self.seal(None, &key_bytes, value_bytes));
+            written.push(key_bytes);
+        }
+        let db = &*self.db;
+        self.timed_write(|| db.write(batch))?;
+        for key_bytes in written {
+            self.cache.invalidate(None, &key_bytes);
         }
-        let db = self.db.lock().await;
-        db.write(batch)?;
         Ok(())
     }
 
@@ -92,7 +449,7 @@ impl Database {
         V: for<'de> Deserialize<'de>,
     {
         let prefix_bytes = bincode::serialize(prefix)?;
-        let db = self.db.lock().await;
+        let db = &*self.db;
         let iter = db.iterator(IteratorMode::From(&prefix_bytes, rocksdb::Direction::Forward));
         let mut results = Vec::new();
 
@@ -101,6 +458,10 @@ impl Database {
             if !key.starts_with(&prefix_bytes) {
                 break;
             }
+            if Self::is_reserved(None, &key) {
+                continue;
+            }
+            let value = self.unseal(None, &key, value.to_vec())?;
             let deserialized_key: K = bincode::deserialize(&key)?;
             let deserialized_value: V = bincode::deserialize(&value)?;
             results.push((deserialized_key, deserialized_value));
@@ -108,6 +469,418 @@ impl Database {
 
         Ok(results)
     }
+
+    // Reads at most `limit` raw entries under `prefix`, strictly after `after` if given.
+    // Each page is read from a fresh iterator, so long scans never pin old SST files.
+    pub async fn scan_page_raw(
+        &self,
+        column: Option<Column>,
+        prefix: &[u8],
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let db = &*self.db;
+        let start = after.unwrap_or(prefix);
+        let mode = IteratorMode::From(start, rocksdb::Direction::Forward);
+        let iter = match column {
+            Some(column) => db.iterator_cf_opt(Self::cf_handle(db, column)?, Self::total_order(), mode),
+            None => db.iterator(mode),
+        };
+
+        let mut results = Vec::with_capacity(limit);
+        for item in iter {
+            let (key, value) = item?;
+            if !key.starts_with(prefix) || results.len() == limit {
+                break;
+            }
+            if after.map_or(false, |after| &key[..] == after) || Self::is_reserved(column, &key) {
+                continue;
+            }
+            let value = self.unseal(column, &key, value.to_vec())?;
+            results.push((key.to_vec(), value));
+        }
+
+        Ok(results)
+    }
+
+    pub async fn is_empty(&self) -> Result<bool> {
+        Self::raw_is_empty(&self.db)
+    }
+
+    fn raw_is_empty(db: &DB) -> Result<bool> {
+        for item in db.iterator(IteratorMode::Start) {
+            let (key, _) = item?;
+            if !Self::is_reserved(None, &key) {
+                return Ok(false);
+            }
+        }
+        for column in Column::ALL {
+            let cf = Self::cf_handle(db, column)?;
+            if db.iterator_cf(cf, IteratorMode::Start).next().is_some() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub async fn get_cf<K, V>(&self, column: Column, key: &K) -> Result<Option<V>>
+    where
+        K: Serialize,
+        V: for<'de> Deserialize<'de>,
+    {
+        let key_bytes = bincode::serialize(key)?;
+        let db = &*self.db;
+        match self.read_through(db, Some(column), &key_bytes)? {
+            Some(value_bytes) => Ok(Some(bincode::deserialize(&value_bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn put_cf<K, V>(&self, column: Column, key: &K, value: &V) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let key_bytes = bincode::serialize(key)?;
+        let value_bytes = bincode::serialize(value)?;
+        let db = &*self.db;
+        let cf = Self::cf_handle(db, column)?;
+        self.timed_write(|| db.put_cf(cf, &key_bytes, self.seal(Some(column), &key_bytes, value_bytes)))?;
+        self.cache.invalidate(Some(column), &key_bytes);
+        Ok(())
+    }
+
+    pub async fn delete_cf<K>(&self, column: Column, key: &K) -> Result<()>
+    where
+        K: Serialize,
+    {
+        let key_bytes = bincode::serialize(key)?;
+        let db = &*self.db;
+        let cf = Self::cf_handle(db, column)?;
+        self.timed_write(|| db.delete_cf(cf, &key_bytes))?;
+        self.cache.invalidate(Some(column), &key_bytes);
+        Ok(())
+    }
+
+    pub async fn prefix_scan_cf<K, V>(&self, column: Column, prefix: &K) -> Result<Vec<(K, V)>>
+    where
+        K: Serialize + for<'de> Deserialize<'de>,
+        V: for<'de> Deserialize<'de>,
+    {
+        let prefix_bytes = bincode::serialize(prefix)?;
+        let db = &*self.db;
+        let cf = Self::cf_handle(db, column)?;
+        let iter = db.iterator_cf_opt(cf, Self::total_order(), IteratorMode::From(&prefix_bytes, rocksdb::Direction::Forward));
+        let mut results = Vec::new();
+
+        for item in iter {
+            let (key, value) = item?;
+            if !key.starts_with(&prefix_bytes) {
+                break;
+            }
+            let value = self.unseal(Some(column), &key, value.to_vec())?;
+            let deserialized_key: K = bincode::deserialize(&key)?;
+            let deserialized_value: V = bincode::deserialize(&value)?;
+            results.push((deserialized_key, deserialized_value));
+        }
+
+        Ok(results)
+    }
+
+    // Removes every key in [from, to) of a single column, used for pruning a domain
+    pub async fn delete_range_cf<K>(&self, column: Column, from: &K, to: &K) -> Result<()>
+    where
+        K: Serialize,
+    {
+        let from_bytes = bincode::serialize(from)?;
+        let to_bytes = bincode::serialize(to)?;
+        let db = &*self.db;
+        let cf = Self::cf_handle(db, column)?;
+        let mut batch = WriteBatch::default();
+        batch.delete_range_cf(cf, &from_bytes, &to_bytes);
+        self.timed_write(|| db.write(batch))?;
+        self.cache.invalidate_column(Some(column));
+        Ok(())
+    }
+
+    // Takes a consistent, incremental snapshot while the node keeps running.
+    // Memtables are flushed first so the backup doesn't depend on the WAL.
+    pub async fn create_backup<P: AsRef<Path>>(&self, backup_dir: P, keep_latest: usize) -> Result<BackupInfo> {
+        let mut engine = BackupEngine::open(&BackupEngineOptions::default(), backup_dir)?;
+        engine.create_new_backup_flush(&self.db, true)?;
+        if keep_latest > 0 {
+            engine.purge_old_backups(keep_latest)?;
+        }
+        engine
+            .get_backup_info()
+            .into_iter()
+            .max_by_key(|info| info.backup_id)
+            .map(BackupInfo::from)
+            .ok_or_else(|| DatabaseError::Io(std::io::Error::new(std::io::ErrorKind::Other, "backup was not recorded")))
+    }
+
+    pub fn list_backups<P: AsRef<Path>>(backup_dir: P) -> Result<Vec<BackupInfo>> {
+        let engine = BackupEngine::open(&BackupEngineOptions::default(), backup_dir)?;
+        Ok(engine.get_backup_info().into_iter().map(BackupInfo::from).collect())
+    }
+
+    // Fails with `InUse` while another process (normally the node) has the
+    // database at `path` open. RocksDB only takes its lock file on a
+    // read-write open, so that is what this tries.
+    pub fn ensure_not_in_use<P: AsRef<Path>>(path: P) -> Result<()> {
+        let path = path.as_ref();
+        if !path.join("CURRENT").exists() {
+            return Ok(());
+        }
+        let columns = DB::list_cf(&Options::default(), path)?;
+        match DB::open_cf(&Options::default(), path, columns) {
+            Ok(_) => Ok(()),
+            Err(e) if e.to_string().contains("LOCK") => Err(DatabaseError::InUse(path.to_path_buf())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Restores the latest backup into `db_dir`, refusing while it is open
+    pub fn restore_from_backup<P: AsRef<Path>, Q: AsRef<Path>>(backup_dir: P, db_dir: Q) -> Result<()> {
+        Self::ensure_not_in_use(&db_dir)?;
+        let mut engine = BackupEngine::open(&BackupEngineOptions::default(), backup_dir)?;
+        let mut restore_opts = RestoreOptions::default();
+        restore_opts.set_keep_log_files(false);
+        engine.restore_from_latest_backup(&db_dir, &db_dir, &restore_opts)?;
+        Ok(())
+    }
+
+    pub async fn commit(&self, transaction: StorageTransaction) -> Result<()> {
+        self.commit_with(transaction, false).await
+    }
+
+    // Like `commit`, but does not return until the WAL has been fsynced
+    pub async fn commit_durable(&self, transaction: StorageTransaction) -> Result<()> {
+        self.commit_with(transaction, true).await
+    }
+
+    async fn commit_with(&self, transaction: StorageTransaction, sync: bool) -> Result<()> {
+        if transaction.is_empty() {
+            return Ok(());
+        }
+
+        let touched = transaction.touched_keys();
+        let transaction = transaction.map_values(|column, key, value| self.seal(column, key, value));
+        let db = &*self.db;
+        let batch = transaction.into_write_batch(db)?;
+        let mut write_opts = WriteOptions::default();
+        write_opts.set_sync(sync);
+        self.timed_write(|| db.write_opt(batch, &write_opts))?;
+        for (column, key) in touched {
+            self.cache.invalidate(column, &key);
+        }
+        Ok(())
+    }
+
+    // Scans keys sharing `prefix` in one column. When the prefix covers the column's
+    // configured extractor length this is a true prefix seek that consults the
+    // prefix bloom filters; shorter prefixes fall back to a bounded total-order scan.
+    pub async fn scan_prefix_raw(&self, column: Column, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let db = &*self.db;
+        let cf = Self::cf_handle(db, column)?;
+
+        let read_opts = match column.prefix_len() {
+            Some(len) if prefix.len() >= len => {
+                let mut read_opts = ReadOptions::default();
+                read_opts.set_prefix_same_as_start(true);
+                read_opts
+            }
+            _ => Self::total_order(),
+        };
+
+        let mut results = Vec::new();
+        for item in db.iterator_cf_opt(cf, read_opts, IteratorMode::From(prefix, rocksdb::Direction::Forward)) {
+            let (key, value) = item?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            let value = self.unseal(Some(column), &key, value.to_vec())?;
+            results.push((key.to_vec(), value));
+        }
+
+        Ok(results)
+    }
+
+    // Point read through the LRU cache. The fill is dropped if any write invalidated
+    // the cache while RocksDB was being read, so a racing writer can't be shadowed.
+    fn read_through(&self, db: &DB, column: Option<Column>, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.cache.get(column, key) {
+            return Ok(Some(value));
+        }
+        let generation = self.cache.generation();
+        let cf = column.map(|column| Self::cf_handle(db, column)).transpose()?;
+        let started = Instant::now();
+        let value = match cf {
+            Some(cf) => db.get_cf(cf, key),
+            None => db.get(key),
+        };
+        self.read_latency.record(started.elapsed());
+        let value = value?;
+        let value = value.map(|value| self.unseal(column, key, value)).transpose()?;
+        if let Some(value) = &value {
+            self.cache.insert_if_current(generation, column, key, value);
+        }
+        Ok(value)
+    }
+
+    fn timed_write<T>(&self, op: impl FnOnce() -> std::result::Result<T, rocksdb::Error>) -> Result<T> {
+        if self.secondary {
+            return Err(DatabaseError::ReadOnly);
+        }
+        let started = Instant::now();
+        let result = op();
+        self.write_latency.record(started.elapsed());
+        Ok(result?)
+    }
+
+    fn seal(&self, column: Option<Column>, key: &[u8], value: Vec<u8>) -> Vec<u8> {
+        let value = match &self.encryption {
+            Some(encryption) if Self::is_sealed(column) => encryption.read().unwrap().keyring.encrypt(column, key, &value),
+            _ => value,
+        };
+        self.checksummed(value)
+    }
+
+    fn unseal(&self, column: Option<Column>, key: &[u8], value: Vec<u8>) -> Result<Vec<u8>> {
+        let value = self.verify(column, key, value)?;
+        match &self.encryption {
+            Some(encryption) if Self::is_sealed(column) => Ok(encryption.read().unwrap().keyring.decrypt(column, key, &value)?),
+            _ => Ok(value),
+        }
+    }
+
+    fn checksummed(&self, value: Vec<u8>) -> Vec<u8> {
+        match self.checksums.load(Ordering::Acquire) {
+            true => with_checksum(value),
+            false => value,
+        }
+    }
+
+    // Strips the checksum, turning a flipped bit into a typed error instead of a
+    // deserialization failure somewhere downstream
+    fn verify(&self, column: Option<Column>, key: &[u8], mut value: Vec<u8>) -> Result<Vec<u8>> {
+        if !self.checksums.load(Ordering::Acquire) {
+            return Ok(value);
+        }
+        if let Some(split) = value.len().checked_sub(CHECKSUM_LEN) {
+            let expected = u32::from_be_bytes(value[split..].try_into().unwrap());
+            if crc32fast::hash(&value[..split]) == expected {
+                value.truncate(split);
+                return Ok(value);
+            }
+        }
+        let column = column.map_or("default", |column| column.name());
+        self.corruptions.fetch_add(1, Ordering::Relaxed);
+        error!("Checksum mismatch for key {:?} in column {}", key, column);
+        Err(DatabaseError::Corruption { column, key: key.to_vec() })
+    }
+
+    // Ephemeral values stay in the clear so the TTL compaction filter can read their expiry
+    fn is_sealed(column: Option<Column>) -> bool {
+        column != Some(Column::Ephemeral)
+    }
+
+    fn encryption(&self) -> Result<&RwLock<Encryption>> {
+        self.encryption.as_ref().ok_or(DatabaseError::Encryption(EncryptionError::KeyRequired))
+    }
+
+    fn is_reserved(column: Option<Column>, key: &[u8]) -> bool {
+        column.is_none() && (key == KEYRING_KEY || key == CHECKSUMS_KEY || key == CHECKSUM_PROGRESS_KEY)
+    }
+
+    // Generic scans must not be limited to a single extracted prefix
+    fn total_order() -> ReadOptions {
+        let mut read_opts = ReadOptions::default();
+        read_opts.set_total_order_seek(true);
+        read_opts
+    }
+
+    fn cf_handle(db: &DB, column: Column) -> Result<&rocksdb::ColumnFamily> {
+        db.cf_handle(column.name())
+            .ok_or(DatabaseError::MissingColumnFamily(column.name()))
+    }
+}
+
+fn with_checksum(mut value: Vec<u8>) -> Vec<u8> {
+    let checksum = crc32fast::hash(&value);
+    value.extend_from_slice(&checksum.to_be_bytes());
+    value
+}
+
+#[async_trait]
+impl StorageBackend for Database {
+    async fn get_raw(&self, column: Option<Column>, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let db = &*self.db;
+        self.read_through(db, column, key)
+    }
+
+    async fn put_raw(&self, column: Option<Column>, key: &[u8], value: &[u8]) -> Result<()> {
+        let db = &*self.db;
+        let value = self.seal(column, key, value.to_vec());
+        let cf = column.map(|column| Self::cf_handle(db, column)).transpose()?;
+        self.timed_write(|| match cf {
+            Some(cf) => db.put_cf(cf, key, value),
+            None => db.put(key, value),
+        })?;
+        self.cache.invalidate(column, key);
+        Ok(())
+    }
+
+    async fn delete_raw(&self, column: Option<Column>, key: &[u8]) -> Result<()> {
+        let db = &*self.db;
+        let cf = column.map(|column| Self::cf_handle(db, column)).transpose()?;
+        self.timed_write(|| match cf {
+            Some(cf) => db.delete_cf(cf, key),
+            None => db.delete(key),
+        })?;
+        self.cache.invalidate(column, key);
+        Ok(())
+    }
+
+    async fn write(&self, transaction: StorageTransaction) -> Result<()> {
+        self.commit(transaction).await
+    }
+
+    async fn iterate_prefix(&self, column: Option<Column>, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let db = &*self.db;
+        let mode = IteratorMode::From(prefix, rocksdb::Direction::Forward);
+        let iter = match column {
+            Some(column) => db.iterator_cf_opt(Self::cf_handle(db, column)?, Self::total_order(), mode),
+            None => db.iterator(mode),
+        };
+        let mut results = Vec::new();
+        for item in iter {
+            let (key, value) = item?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            if Self::is_reserved(column, &key) {
+                continue;
+            }
+            let value = self.unseal(column, &key, value.to_vec())?;
+            results.push((key.to_vec(), value));
+        }
+        Ok(results)
+    }
+
+    async fn snapshot_get(&self, keys: &[(Option<Column>, Vec<u8>)]) -> Result<Vec<Option<Vec<u8>>>> {
+        let db = &*self.db;
+        let snapshot = db.snapshot();
+        let mut values = Vec::with_capacity(keys.len());
+        for (column, key) in keys {
+            let value = match column {
+                Some(column) => snapshot.get_cf(Self::cf_handle(db, *column)?, key)?,
+                None => snapshot.get(key)?,
+            };
+            values.push(value.map(|value| self.unseal(*column, key, value)).transpose()?);
+        }
+        Ok(values)
+    }
 }
 
 #[cfg(test)]
@@ -115,6 +888,67 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    #[tokio::test]
+    async fn test_secondary_instance_catches_up() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let primary = Database::new(temp_dir.path().join("primary"))?;
+        primary.put_cf(Column::State, &"a", &1u32).await?;
+
+        let options = DatabaseOptions {
+            cache_capacity: 0,
+            secondary_path: Some(temp_dir.path().join("secondary")),
+            ..DatabaseOptions::default()
+        };
+        let secondary = Database::open(temp_dir.path().join("primary"), options)?;
+        assert!(secondary.is_secondary());
+        assert_eq!(secondary.get_cf::<_, u32>(Column::State, &"a").await?, Some(1));
+
+        primary.put_cf(Column::State, &"b", &2u32).await?;
+        assert_eq!(secondary.get_cf::<_, u32>(Column::State, &"b").await?, None);
+        secondary.catch_up()?;
+        assert_eq!(secondary.get_cf::<_, u32>(Column::State, &"b").await?, Some(2));
+
+        assert!(matches!(secondary.put_cf(Column::State, &"c", &3u32).await, Err(DatabaseError::ReadOnly)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_secondary_follows_key_rotation_and_checksums() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let (primary_path, secondary_path) = (temp_dir.path().join("primary"), temp_dir.path().join("secondary"));
+        let key = |secondary_path: Option<PathBuf>| DatabaseOptions {
+            cache_capacity: 0,
+            encryption: Some(KeySource::Raw([3u8; 32])),
+            secondary_path,
+            ..DatabaseOptions::default()
+        };
+        let primary = Database::open(&primary_path, key(None))?;
+        primary.put_cf(Column::State, &"a", &1u32).await?;
+        let secondary = Database::open(&primary_path, key(Some(secondary_path.join("encrypted"))))?;
+
+        primary.rotate_data_key()?;
+        primary.put_cf(Column::State, &"b", &2u32).await?;
+        secondary.catch_up()?;
+        assert_eq!(secondary.get_cf::<_, u32>(Column::State, &"b").await?, Some(2));
+        drop((primary, secondary));
+
+        let legacy_path = temp_dir.path().join("legacy");
+        {
+            let legacy = DB::open_default(&legacy_path)?;
+            legacy.put(bincode::serialize(&"legacy")?, bincode::serialize(&7u64)?)?;
+        }
+        let primary = Database::new(&legacy_path)?;
+        let secondary_path = Some(secondary_path.join("legacy"));
+        let secondary = Database::open(&legacy_path, DatabaseOptions { secondary_path, ..DatabaseOptions::default() })?;
+        primary.enable_checksums()?;
+        primary.put(&"fresh", &8u64).await?;
+        secondary.catch_up()?;
+        assert!(secondary.checksums.load(Ordering::Acquire));
+        assert_eq!(secondary.get::<_, u64>(&"legacy").await?, Some(7));
+        assert_eq!(secondary.get::<_, u64>(&"fresh").await?, Some(8));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_database_operations() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -147,4 +981,263 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_column_families() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Database::new(temp_dir.path())?;
+
+        // The same key in different domains does not collide
+        db.put_cf(Column::Headers, &1u64, &"header").await?;
+        db.put_cf(Column::Bodies, &1u64, &"body").await?;
+        let header: Option<String> = db.get_cf(Column::Headers, &1u64).await?;
+        let body: Option<String> = db.get_cf(Column::Bodies, &1u64).await?;
+        assert_eq!(header, Some("header".to_string()));
+        assert_eq!(body, Some("body".to_string()));
+        let default: Option<String> = db.get(&1u64).await?;
+        assert_eq!(default, None);
+
+        // Pruning one domain leaves the others intact
+        for height in 0u64..10 {
+            db.put_cf(Column::Receipts, &height.to_be_bytes(), &height).await?;
+        }
+        db.delete_range_cf(Column::Receipts, &0u64.to_be_bytes(), &5u64.to_be_bytes()).await?;
+        let pruned: Option<u64> = db.get_cf(Column::Receipts, &4u64.to_be_bytes()).await?;
+        let kept: Option<u64> = db.get_cf(Column::Receipts, &5u64.to_be_bytes()).await?;
+        assert_eq!(pruned, None);
+        assert_eq!(kept, Some(5));
+        assert_eq!(db.get_cf::<_, String>(Column::Headers, &1u64).await?, Some("header".to_string()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_cache_invalidation() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Database::new(temp_dir.path())?;
+
+        db.put_cf(Column::Headers, &7u64, &"header").await?;
+        for _ in 0..3 {
+            assert_eq!(db.get_cf::<_, String>(Column::Headers, &7u64).await?, Some("header".to_string()));
+        }
+        let stats = db.cache_stats();
+        assert_eq!((stats.misses, stats.hits), (1, 2));
+
+        // Every write path must drop the stale entry
+        db.put_cf(Column::Headers, &7u64, &"updated").await?;
+        assert_eq!(db.get_cf::<_, String>(Column::Headers, &7u64).await?, Some("updated".to_string()));
+
+        let mut txn = StorageTransaction::new();
+        txn.delete(Column::Headers, &7u64)?;
+        db.commit(txn).await?;
+        assert_eq!(db.get_cf::<_, String>(Column::Headers, &7u64).await?, None);
+
+        db.put_cf(Column::Receipts, &1u64, &1u64).await?;
+        assert_eq!(db.get_cf::<_, u64>(Column::Receipts, &1u64).await?, Some(1));
+        db.delete_range_cf(Column::Receipts, &0u64, &2u64).await?;
+        assert_eq!(db.get_cf::<_, u64>(Column::Receipts, &1u64).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_access() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Arc::new(Database::new(temp_dir.path())?);
+
+        let mut tasks = Vec::new();
+        for worker in 0u64..8 {
+            let db = Arc::clone(&db);
+            tasks.push(tokio::spawn(async move {
+                for i in 0u64..100 {
+                    let key = worker * 1000 + i;
+                    db.put_cf(Column::State, &key, &i).await?;
+                    assert_eq!(db.get_cf::<_, u64>(Column::State, &key).await?, Some(i));
+                }
+                Ok::<_, DatabaseError>(())
+            }));
+        }
+        for task in tasks {
+            task.await.expect("worker panicked")?;
+        }
+
+        assert_eq!(db.get_cf::<_, u64>(Column::State, &7099u64).await?, Some(99));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_checksum_detects_corruption() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Database::new(temp_dir.path())?;
+        db.put_cf(Column::State, &"account", &42u64).await?;
+
+        let cf = Database::cf_handle(&db.db, Column::State)?;
+        let key = bincode::serialize(&"account")?;
+        let mut raw = db.db.get_cf(cf, &key)?.unwrap();
+        raw[0] ^= 0x01;
+        db.db.put_cf(cf, &key, raw)?;
+        db.cache.clear();
+
+        assert!(matches!(
+            db.get_cf::<_, u64>(Column::State, &"account").await,
+            Err(DatabaseError::Corruption { column: "state", .. })
+        ));
+        assert_eq!(db.stats()?.corruptions, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_transaction_values_are_checksummed() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Database::new(temp_dir.path())?;
+
+        let mut txn = StorageTransaction::new();
+        txn.put(Column::State, &"account", &42u64)?;
+        db.commit(txn).await?;
+        db.cache.clear();
+        assert_eq!(db.get_cf::<_, u64>(Column::State, &"account").await?, Some(42));
+        assert_eq!(db.stats()?.corruptions, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_enable_checksums_on_legacy_database() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        {
+            let legacy = DB::open_default(temp_dir.path())?;
+            legacy.put(bincode::serialize(&"legacy")?, bincode::serialize(&7u64)?)?;
+        }
+
+        let db = Database::new(temp_dir.path())?;
+        assert_eq!(db.get::<_, u64>(&"legacy").await?, Some(7));
+        assert_eq!(db.enable_checksums()?, 1);
+        assert_eq!(db.enable_checksums()?, 0);
+        drop(db);
+
+        let db = Database::new(temp_dir.path())?;
+        assert_eq!(db.get::<_, u64>(&"legacy").await?, Some(7));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_enable_checksums_resumes_after_interruption() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let (first, second) = (bincode::serialize(&"a")?, bincode::serialize(&"b")?);
+        {
+            // As left by a run that stopped after rewriting the first key
+            let legacy = DB::open_default(temp_dir.path())?;
+            legacy.put(&first, with_checksum(bincode::serialize(&1u64)?))?;
+            legacy.put(&second, bincode::serialize(&2u64)?)?;
+            legacy.put(CHECKSUM_PROGRESS_KEY, bincode::serialize(&(0u32, first.clone()))?)?;
+        }
+
+        let db = Database::new(temp_dir.path())?;
+        assert_eq!(db.enable_checksums()?, 1);
+        assert_eq!(db.db.get(CHECKSUM_PROGRESS_KEY)?, None);
+        assert_eq!(db.get::<_, u64>(&"a").await?, Some(1));
+        assert_eq!(db.get::<_, u64>(&"b").await?, Some(2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_encryption_at_rest() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let key = || DatabaseOptions { encryption: Some(KeySource::Raw([3u8; 32])), ..DatabaseOptions::default() };
+
+        {
+            let db = Database::open(temp_dir.path(), key())?;
+            db.put_cf(Column::State, &"account", &"secret balance").await?;
+            db.put(&"legacy", &1u64).await?;
+
+            let raw = db.db.get_cf(Database::cf_handle(&db.db, Column::State)?, bincode::serialize(&"account")?)?.unwrap();
+            assert!(!raw.windows(6).any(|window| window == b"secret"));
+
+            // Old values stay readable across a rotation and are moved by reencrypt
+            assert_eq!(db.rotate_data_key()?, 2);
+            db.put_cf(Column::State, &"fresh", &"value").await?;
+            assert_eq!(db.reencrypt()?, 2);
+            assert_eq!(db.get_cf::<_, String>(Column::State, &"account").await?, Some("secret balance".to_string()));
+        }
+
+        assert!(matches!(
+            Database::new(temp_dir.path()),
+            Err(DatabaseError::Encryption(EncryptionError::KeyRequired))
+        ));
+        let wrong = DatabaseOptions { encryption: Some(KeySource::Raw([4u8; 32])), ..DatabaseOptions::default() };
+        assert!(Database::open(temp_dir.path(), wrong).is_err());
+
+        let db = Database::open(temp_dir.path(), key())?;
+        assert_eq!(db.get_cf::<_, String>(Column::State, &"account").await?, Some("secret balance".to_string()));
+        assert_eq!(db.get::<_, u64>(&"legacy").await?, Some(1));
+        assert!(!db.is_empty().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refuses_to_encrypt_plaintext_database() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        Database::new(temp_dir.path())?.put(&"key", &"value").await?;
+
+        let options = DatabaseOptions { encryption: Some(KeySource::Raw([3u8; 32])), ..DatabaseOptions::default() };
+        assert!(matches!(Database::open(temp_dir.path(), options), Err(DatabaseError::PlaintextDatabase)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scan_prefix_raw() -> Result<()> {
+        use crate::storage::keys::{domain_key, domain_prefix, height_suffix, Domain};
+
+        let temp_dir = TempDir::new()?;
+        let db = Database::new(temp_dir.path())?;
+        let (alice, bob) = ([1u8; 32], [2u8; 32]);
+
+        let mut txn = StorageTransaction::new();
+        for height in [3u64, 1, 2] {
+            txn.put_raw(Column::Indexes, domain_key(Domain::LogsByAddress, &alice, &height_suffix(height)), vec![height as u8]);
+        }
+        txn.put_raw(Column::Indexes, domain_key(Domain::LogsByAddress, &bob, &height_suffix(1)), vec![9]);
+        txn.put_raw(Column::Indexes, domain_key(Domain::BlockByHash, &alice, &[]), vec![7]);
+        db.commit(txn).await?;
+
+        // Full prefix: prefix seek, ordered by height suffix
+        let logs = db.scan_prefix_raw(Column::Indexes, &domain_prefix(Domain::LogsByAddress, &alice)).await?;
+        assert_eq!(logs.iter().map(|(_, v)| v[0]).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        // Shorter than the extractor: falls back to a total-order scan across ids
+        let all_logs = db.scan_prefix_raw(Column::Indexes, &[Domain::LogsByAddress as u8]).await?;
+        assert_eq!(all_logs.len(), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_restore() -> Result<()> {
+        let db_dir = TempDir::new()?;
+        let backup_dir = TempDir::new()?;
+        let restore_dir = TempDir::new()?;
+
+        let db = Database::new(db_dir.path())?;
+        db.put(&"key1", &"value1").await?;
+        db.put_cf(Column::State, &"account", &42u64).await?;
+        let info = db.create_backup(backup_dir.path(), 2).await?;
+        assert!(info.num_files > 0);
+
+        // Writes after the backup are not part of it
+        db.put(&"key2", &"value2").await?;
+        assert_eq!(Database::list_backups(backup_dir.path())?.len(), 1);
+
+        // Never restore underneath an open database
+        assert!(matches!(
+            Database::restore_from_backup(backup_dir.path(), db_dir.path()),
+            Err(DatabaseError::InUse(_))
+        ));
+
+        Database::restore_from_backup(backup_dir.path(), restore_dir.path())?;
+        let restored = Database::new(restore_dir.path())?;
+        assert_eq!(restored.get::<_, String>(&"key1").await?, Some("value1".to_string()));
+        assert_eq!(restored.get_cf::<_, u64>(Column::State, &"account").await?, Some(42));
+        assert_eq!(restored.get::<_, String>(&"key2").await?, None);
+
+        Ok(())
+    }
 }
\ No newline at end of file
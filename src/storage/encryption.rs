@@ -0,0 +1,313 @@
+// OmniTensor-Project/omnitensor-core/src/storage/encryption.rs
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use zeroize::Zeroize;
+
+use crate::storage::columns::Column;
+
+// Envelope encryption for values at rest.
+//
+// Values are sealed with AES-256-GCM under a random data key; data keys are
+// themselves wrapped by a master key derived from an operator passphrase or
+// supplied by a KMS. Each value is stored as
+//
+//     [version: 1][data key id: 4, BE][nonce: 12][ciphertext + tag]
+//
+// with (column, key) as associated data, so a value can't be moved to another key.
+// Rotating the data key only affects new writes until `Database::reencrypt` runs;
+// changing the master key only rewraps the keyring.
+
+pub const KEYRING_KEY: &[u8] = b"__encryption_keyring";
+pub const PBKDF2_ROUNDS: u32 = 600_000;
+
+const ENVELOPE_VERSION: u8 = 1;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+pub(crate) const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = 1 + 4 + NONCE_LEN;
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("Passphrase environment variable {0} is not set")]
+    MissingPassphrase(String),
+    #[error("Master key material must be exactly {KEY_LEN} bytes")]
+    InvalidKeyMaterial,
+    #[error("Encryption is enabled but no key source is configured")]
+    NoKeySource,
+    #[error("Value was sealed with unknown data key {0}")]
+    UnknownKey(u32),
+    #[error("Malformed encrypted value")]
+    Malformed,
+    #[error("Decryption failed: wrong key or tampered data")]
+    AuthenticationFailed,
+    #[error("Database is encrypted but no key was provided")]
+    KeyRequired,
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, EncryptionError>;
+
+// `[storage.encryption]`. The passphrase itself never lives in the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // Name of the environment variable holding the passphrase
+    pub passphrase_env: Option<String>,
+    // 32-byte master key provisioned by a KMS agent, e.g. a mounted secret
+    pub key_file: Option<PathBuf>,
+}
+
+impl EncryptionConfig {
+    pub fn key_source(&self) -> Result<Option<KeySource>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        if let Some(path) = &self.key_file {
+            return KeySource::from_key_file(path).map(Some);
+        }
+        if let Some(var) = &self.passphrase_env {
+            let passphrase = std::env::var(var).map_err(|_| EncryptionError::MissingPassphrase(var.clone()))?;
+            return Ok(Some(KeySource::Passphrase(passphrase)));
+        }
+        Err(EncryptionError::NoKeySource)
+    }
+}
+
+#[derive(Clone)]
+pub enum KeySource {
+    Passphrase(String),
+    Raw([u8; KEY_LEN]),
+}
+
+impl KeySource {
+    pub fn from_key_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let key: [u8; KEY_LEN] = bytes.as_slice().try_into().map_err(|_| EncryptionError::InvalidKeyMaterial)?;
+        Ok(KeySource::Raw(key))
+    }
+
+    pub(crate) fn master_key(&self, salt: &[u8; SALT_LEN]) -> Aes256Gcm {
+        let mut key = match self {
+            KeySource::Passphrase(passphrase) => {
+                let mut key = [0u8; KEY_LEN];
+                pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+                key
+            }
+            KeySource::Raw(key) => *key,
+        };
+        let cipher = Aes256Gcm::new(&key.into());
+        key.zeroize();
+        cipher
+    }
+}
+
+impl Drop for KeySource {
+    fn drop(&mut self) {
+        match self {
+            KeySource::Passphrase(passphrase) => passphrase.zeroize(),
+            KeySource::Raw(key) => key.zeroize(),
+        }
+    }
+}
+
+impl std::fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::Passphrase(_) => f.write_str("KeySource::Passphrase(..)"),
+            KeySource::Raw(_) => f.write_str("KeySource::Raw(..)"),
+        }
+    }
+}
+
+// On-disk form of the keyring, stored unencrypted under `KEYRING_KEY`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredKeyring {
+    pub salt: [u8; SALT_LEN],
+    pub active: u32,
+    pub wrapped: BTreeMap<u32, Vec<u8>>,
+}
+
+pub struct Keyring {
+    salt: [u8; SALT_LEN],
+    active: u32,
+    keys: BTreeMap<u32, [u8; KEY_LEN]>,
+}
+
+impl Keyring {
+    pub fn generate() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let mut keyring = Self { salt, active: 0, keys: BTreeMap::new() };
+        keyring.rotate();
+        keyring
+    }
+
+    pub fn unwrap(stored: &StoredKeyring, source: &KeySource) -> Result<Self> {
+        let master = source.master_key(&stored.salt);
+        let mut keys = BTreeMap::new();
+        for (id, wrapped) in &stored.wrapped {
+            let key = open(&master, *id, &wrapping_aad(*id), wrapped)?;
+            let key: [u8; KEY_LEN] = key.as_slice().try_into().map_err(|_| EncryptionError::Malformed)?;
+            keys.insert(*id, key);
+        }
+        if !keys.contains_key(&stored.active) {
+            return Err(EncryptionError::UnknownKey(stored.active));
+        }
+        Ok(Self { salt: stored.salt, active: stored.active, keys })
+    }
+
+    pub fn wrap(&self, source: &KeySource) -> StoredKeyring {
+        let master = source.master_key(&self.salt);
+        let wrapped = self
+            .keys
+            .iter()
+            .map(|(id, key)| (*id, seal(&master, *id, &wrapping_aad(*id), key)))
+            .collect();
+        StoredKeyring { salt: self.salt, active: self.active, wrapped }
+    }
+
+    // Generates a new data key and makes it active; returns its id
+    pub fn rotate(&mut self) -> u32 {
+        let mut key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        let id = self.keys.keys().next_back().map_or(1, |id| id + 1);
+        self.keys.insert(id, key);
+        self.active = id;
+        id
+    }
+
+    // Drops every inactive data key. Only safe once nothing is sealed under them.
+    pub fn retire_inactive(&mut self) {
+        let active = self.active;
+        self.keys.retain(|id, _| *id == active);
+    }
+
+    pub fn active(&self) -> u32 {
+        self.active
+    }
+
+    pub fn key_ids(&self) -> Vec<u32> {
+        self.keys.keys().copied().collect()
+    }
+
+    pub fn encrypt(&self, column: Option<Column>, key: &[u8], value: &[u8]) -> Vec<u8> {
+        let cipher = Aes256Gcm::new(&self.keys[&self.active].into());
+        seal(&cipher, self.active, &value_aad(column, key), value)
+    }
+
+    pub fn decrypt(&self, column: Option<Column>, key: &[u8], envelope: &[u8]) -> Result<Vec<u8>> {
+        let id = envelope_key_id(envelope)?;
+        let data_key = self.keys.get(&id).ok_or(EncryptionError::UnknownKey(id))?;
+        open(&Aes256Gcm::new(&(*data_key).into()), id, &value_aad(column, key), envelope)
+    }
+}
+
+pub fn envelope_key_id(envelope: &[u8]) -> Result<u32> {
+    if envelope.len() < HEADER_LEN || envelope[0] != ENVELOPE_VERSION {
+        return Err(EncryptionError::Malformed);
+    }
+    Ok(u32::from_be_bytes([envelope[1], envelope[2], envelope[3], envelope[4]]))
+}
+
+pub(crate) fn seal(cipher: &Aes256Gcm, key_id: u32, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .expect("AES-GCM encryption of an in-memory buffer cannot fail");
+
+    let mut envelope = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    envelope.push(ENVELOPE_VERSION);
+    envelope.extend_from_slice(&key_id.to_be_bytes());
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(&ciphertext);
+    envelope
+}
+
+pub(crate) fn open(cipher: &Aes256Gcm, key_id: u32, aad: &[u8], envelope: &[u8]) -> Result<Vec<u8>> {
+    if envelope_key_id(envelope)? != key_id {
+        return Err(EncryptionError::Malformed);
+    }
+    let nonce = Nonce::from_slice(&envelope[5..HEADER_LEN]);
+    cipher
+        .decrypt(nonce, Payload { msg: &envelope[HEADER_LEN..], aad })
+        .map_err(|_| EncryptionError::AuthenticationFailed)
+}
+
+fn value_aad(column: Option<Column>, key: &[u8]) -> Vec<u8> {
+    let name = column.map_or("default", |column| column.name());
+    let mut aad = Vec::with_capacity(name.len() + 1 + key.len());
+    aad.extend_from_slice(name.as_bytes());
+    aad.push(0);
+    aad.extend_from_slice(key);
+    aad
+}
+
+fn wrapping_aad(key_id: u32) -> Vec<u8> {
+    let mut aad = b"omnitensor-keyring".to_vec();
+    aad.extend_from_slice(&key_id.to_be_bytes());
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source() -> KeySource {
+        KeySource::Raw([42u8; KEY_LEN])
+    }
+
+    #[test]
+    fn test_seal_and_open_value() {
+        let keyring = Keyring::generate();
+        let sealed = keyring.encrypt(Some(Column::State), b"account", b"balance");
+        assert_ne!(&sealed[HEADER_LEN..], b"balance");
+        assert_eq!(keyring.decrypt(Some(Column::State), b"account", &sealed).unwrap(), b"balance");
+
+        // Bound to its location
+        assert!(matches!(
+            keyring.decrypt(Some(Column::State), b"other", &sealed),
+            Err(EncryptionError::AuthenticationFailed)
+        ));
+        assert!(matches!(keyring.decrypt(None, b"account", &sealed), Err(EncryptionError::AuthenticationFailed)));
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_values_readable() {
+        let mut keyring = Keyring::generate();
+        let old = keyring.encrypt(None, b"k", b"v1");
+        assert_eq!(keyring.rotate(), 2);
+        let new = keyring.encrypt(None, b"k", b"v2");
+
+        assert_eq!(envelope_key_id(&old).unwrap(), 1);
+        assert_eq!(envelope_key_id(&new).unwrap(), 2);
+        assert_eq!(keyring.decrypt(None, b"k", &old).unwrap(), b"v1");
+
+        keyring.retire_inactive();
+        assert_eq!(keyring.key_ids(), vec![2]);
+        assert!(matches!(keyring.decrypt(None, b"k", &old), Err(EncryptionError::UnknownKey(1))));
+    }
+
+    #[test]
+    fn test_keyring_wrapping() {
+        let keyring = Keyring::generate();
+        let sealed = keyring.encrypt(None, b"k", b"v");
+        let stored = keyring.wrap(&source());
+
+        let restored = Keyring::unwrap(&stored, &source()).unwrap();
+        assert_eq!(restored.decrypt(None, b"k", &sealed).unwrap(), b"v");
+
+        let wrong = KeySource::Raw([7u8; KEY_LEN]);
+        assert!(matches!(Keyring::unwrap(&stored, &wrong), Err(EncryptionError::AuthenticationFailed)));
+    }
+}
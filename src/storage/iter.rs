@@ -0,0 +1,160 @@
+// OmniTensor-Project/omnitensor-core/src/storage/iter.rs
+
+use futures::stream::{self, Stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::columns::Column;
+use crate::storage::db::{Database, DatabaseError, Result};
+
+// Entries fetched per round trip by `prefix_stream`
+pub const STREAM_PAGE_SIZE: usize = 512;
+
+// Opaque resume point for paged scans: the last key returned by the previous page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContinuationToken(Vec<u8>);
+
+#[derive(Debug)]
+pub struct Page<K, V> {
+    pub items: Vec<(K, V)>,
+    pub next: Option<ContinuationToken>,
+}
+
+impl Database {
+    pub async fn prefix_page<K, V>(
+        &self,
+        column: Option<Column>,
+        prefix: &K,
+        token: Option<&ContinuationToken>,
+        limit: usize,
+    ) -> Result<Page<K, V>>
+    where
+        K: Serialize + for<'de> Deserialize<'de>,
+        V: for<'de> Deserialize<'de>,
+    {
+        let prefix_bytes = bincode::serialize(prefix)?;
+        if let Some(token) = token {
+            if !token.0.starts_with(&prefix_bytes) {
+                return Err(DatabaseError::InvalidContinuationToken);
+            }
+        }
+
+        // Fetch one extra entry to learn whether another page exists
+        let mut raw = self
+            .scan_page_raw(column, &prefix_bytes, token.map(|t| t.0.as_slice()), limit + 1)
+            .await?;
+        let has_more = raw.len() > limit;
+        raw.truncate(limit);
+
+        let next = if has_more {
+            raw.last().map(|(key, _)| ContinuationToken(key.clone()))
+        } else {
+            None
+        };
+        let items = raw
+            .into_iter()
+            .map(|(key, value)| Ok((bincode::deserialize(&key)?, bincode::deserialize(&value)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Page { items, next })
+    }
+
+    // Yields every entry under `prefix` without materializing the whole range
+    pub fn prefix_stream<'a, K, V>(
+        &'a self,
+        column: Option<Column>,
+        prefix: &K,
+    ) -> Result<impl Stream<Item = Result<(K, V)>> + 'a>
+    where
+        K: Serialize + for<'de> Deserialize<'de> + 'a,
+        V: for<'de> Deserialize<'de> + 'a,
+    {
+        let prefix_bytes = bincode::serialize(prefix)?;
+
+        let pages = stream::try_unfold(Some(None::<Vec<u8>>), move |state| {
+            let prefix_bytes = prefix_bytes.clone();
+            async move {
+                let after = match state {
+                    Some(after) => after,
+                    None => return Ok(None),
+                };
+                let raw = self
+                    .scan_page_raw(column, &prefix_bytes, after.as_deref(), STREAM_PAGE_SIZE)
+                    .await?;
+                if raw.is_empty() {
+                    return Ok(None);
+                }
+                let next = if raw.len() < STREAM_PAGE_SIZE {
+                    None
+                } else {
+                    raw.last().map(|(key, _)| Some(key.clone()))
+                };
+                Ok(Some((raw, next)))
+            }
+        });
+
+        Ok(pages
+            .map_ok(|raw| {
+                stream::iter(raw.into_iter().map(|(key, value)| {
+                    Ok((bincode::deserialize(&key)?, bincode::deserialize(&value)?))
+                }))
+            })
+            .try_flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_paged_scan() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Database::new(temp_dir.path())?;
+        for i in 0u32..25 {
+            db.put_cf(Column::Receipts, &("r", i.to_be_bytes()), &i).await?;
+        }
+        db.put_cf(Column::Receipts, &("s", 0u32.to_be_bytes()), &0u32).await?;
+
+        let mut token = None;
+        let mut seen = Vec::new();
+        loop {
+            let page: Page<(String, [u8; 4]), u32> = db
+                .prefix_page(Some(Column::Receipts), &("r".to_string(),), token.as_ref(), 10)
+                .await?;
+            seen.extend(page.items.into_iter().map(|(_, value)| value));
+            match page.next {
+                Some(next) => token = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(seen, (0..25).collect::<Vec<u32>>());
+
+        let foreign = ContinuationToken(b"zzz".to_vec());
+        let result: Result<Page<(String,), u32>> = db.prefix_page(Some(Column::Receipts), &("r".to_string(),), Some(&foreign), 10).await;
+        assert!(matches!(result, Err(DatabaseError::InvalidContinuationToken)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prefix_stream() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Database::new(temp_dir.path())?;
+        let total = STREAM_PAGE_SIZE as u32 * 2 + 3;
+        for i in 0..total {
+            db.put(&("k", i.to_be_bytes()), &i).await?;
+        }
+
+        let stream = db.prefix_stream::<(String, [u8; 4]), u32>(None, &("k".to_string(),))?;
+        futures::pin_mut!(stream);
+        let mut count = 0;
+        while let Some(item) = stream.next().await {
+            let (_, value) = item?;
+            assert_eq!(value, count);
+            count += 1;
+        }
+        assert_eq!(count, total);
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+// OmniTensor-Project/omnitensor-core/src/storage/keys.rs
+
+// Explicit byte layouts for keys in prefix-extracted columns.
+//
+// Every key in the `state` and `indexes` columns is
+//
+//     [domain tag: 1 byte][id: 32 bytes][suffix: 0.. bytes]
+//
+// and RocksDB is configured with a fixed 33-byte prefix extractor on those
+// columns, so everything stored under one (domain, id) pair shares a bloom
+// filter entry and can be scanned with a real prefix seek. Do not reuse tags.
+
+pub const DOMAIN_PREFIX_LEN: usize = 33;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Domain {
+    // indexes
+    BlockByHash = 0x01,
+    TransactionByHash = 0x02,
+    LogsByAddress = 0x03,
+    LogBlooms = 0x04,
+    // state
+    Account = 0x10,
+    Stake = 0x11,
+    Model = 0x12,
+    Contract = 0x13,
+    TrieNode = 0x14,
+}
+
+pub fn domain_prefix(domain: Domain, id: &[u8; 32]) -> [u8; DOMAIN_PREFIX_LEN] {
+    let mut prefix = [0u8; DOMAIN_PREFIX_LEN];
+    prefix[0] = domain as u8;
+    prefix[1..].copy_from_slice(id);
+    prefix
+}
+
+pub fn domain_key(domain: Domain, id: &[u8; 32], suffix: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(DOMAIN_PREFIX_LEN + suffix.len());
+    key.extend_from_slice(&domain_prefix(domain, id));
+    key.extend_from_slice(suffix);
+    key
+}
+
+// Big-endian so lexicographic key order matches numeric order
+pub fn height_suffix(height: u64) -> [u8; 8] {
+    height.to_be_bytes()
+}
+
+pub fn split_domain_key(key: &[u8]) -> Option<(u8, [u8; 32], &[u8])> {
+    if key.len() < DOMAIN_PREFIX_LEN {
+        return None;
+    }
+    let mut id = [0u8; 32];
+    id.copy_from_slice(&key[1..DOMAIN_PREFIX_LEN]);
+    Some((key[0], id, &key[DOMAIN_PREFIX_LEN..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_key_layout() {
+        let id = [7u8; 32];
+        let key = domain_key(Domain::LogsByAddress, &id, &height_suffix(258));
+
+        assert_eq!(key.len(), DOMAIN_PREFIX_LEN + 8);
+        assert!(key.starts_with(&domain_prefix(Domain::LogsByAddress, &id)));
+        let (tag, parsed_id, suffix) = split_domain_key(&key).unwrap();
+        assert_eq!(tag, Domain::LogsByAddress as u8);
+        assert_eq!(parsed_id, id);
+        assert_eq!(suffix, &[0, 0, 0, 0, 0, 0, 1, 2]);
+
+        assert!(domain_key(Domain::Account, &id, &height_suffix(1)) < domain_key(Domain::Account, &id, &height_suffix(256)));
+        assert!(split_domain_key(&[1, 2, 3]).is_none());
+    }
+}
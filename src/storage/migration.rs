@@ -0,0 +1,234 @@
+// OmniTensor-Project/omnitensor-core/src/storage/migration.rs
+
+use futures::future::BoxFuture;
+use log::info;
+use std::path::Path;
+
+use crate::storage::columns::Column;
+use crate::storage::db::{Database, DatabaseError, DatabaseOptions, Result};
+use crate::storage::keys::{domain_key, Domain};
+use crate::storage::transaction::StorageTransaction;
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+pub type MigrationFn = for<'a> fn(&'a Database) -> BoxFuture<'a, Result<()>>;
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub run: MigrationFn,
+}
+
+// Ordered list of schema migrations. Migration `n` upgrades a database at
+// version `n - 1` to version `n`; the highest registered version is the schema
+// this binary writes.
+pub struct MigrationRegistry {
+    migrations: Vec<Migration>,
+}
+
+impl Default for MigrationRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(Migration {
+            version: 1,
+            description: "Introduce per-domain column families",
+            // Column families are created on open; legacy default-CF data stays readable
+            run: |_| Box::pin(async { Ok(()) }),
+        });
+        registry.register(Migration {
+            version: 2,
+            description: "Re-encode chain indexes with explicit domain-prefixed keys",
+            run: reencode_chain_indexes,
+        });
+        registry.register(Migration {
+            version: 3,
+            description: "Checksum every stored value",
+            run: add_value_checksums,
+        });
+        registry
+    }
+}
+
+// Version 1 stored index keys as bincode ("block" | "tx", 32-byte hash) tuples
+fn reencode_chain_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let mut txn = StorageTransaction::new();
+        for (tag, domain) in [("block", Domain::BlockByHash), ("tx", Domain::TransactionByHash)] {
+            let legacy_prefix = bincode::serialize(tag)?;
+            for (key, value) in db.scan_prefix_raw(Column::Indexes, &legacy_prefix).await? {
+                let (_, id): (String, [u8; 32]) = bincode::deserialize(&key)?;
+                txn.delete_raw(Column::Indexes, key)
+                    .put_raw(Column::Indexes, domain_key(domain, &id, &[]), value);
+            }
+        }
+        db.commit(txn).await
+    })
+}
+
+fn add_value_checksums(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let rewritten = db.enable_checksums()?;
+        info!("Added checksums to {} stored values", rewritten);
+        Ok(())
+    })
+}
+
+impl MigrationRegistry {
+    pub fn new() -> Self {
+        Self { migrations: Vec::new() }
+    }
+
+    pub fn register(&mut self, migration: Migration) -> &mut Self {
+        let expected = self.latest_version() + 1;
+        assert_eq!(migration.version, expected, "migrations must be registered in order");
+        self.migrations.push(migration);
+        self
+    }
+
+    pub fn latest_version(&self) -> u32 {
+        self.migrations.last().map(|m| m.version).unwrap_or(0)
+    }
+
+    pub async fn current_version(db: &Database) -> Result<Option<u32>> {
+        db.get(&SCHEMA_VERSION_KEY).await
+    }
+
+    // Brings the database up to `latest_version`, recording progress after each step
+    // so an interrupted run resumes where it stopped. Returns the resulting version.
+    pub async fn run(&self, db: &Database) -> Result<u32> {
+        let latest = self.latest_version();
+        let current = match Self::current_version(db).await? {
+            Some(version) => version,
+            // A brand new database already has the latest layout
+            None if db.is_empty().await? => {
+                db.put(&SCHEMA_VERSION_KEY, &latest).await?;
+                return Ok(latest);
+            }
+            None => 0,
+        };
+
+        if current > latest {
+            return Err(DatabaseError::SchemaTooNew { found: current, supported: latest });
+        }
+
+        for migration in self.migrations.iter().filter(|m| m.version > current) {
+            info!("Applying storage migration {}: {}", migration.version, migration.description);
+            (migration.run)(db).await.map_err(|e| DatabaseError::MigrationFailed {
+                version: migration.version,
+                reason: e.to_string(),
+            })?;
+            db.put(&SCHEMA_VERSION_KEY, &migration.version).await?;
+        }
+
+        Ok(latest)
+    }
+}
+
+// Opens the database with the node's options, so an encrypted one gets its
+// keyring before the schema version is written
+pub async fn open_with_migrations<P: AsRef<Path>>(
+    path: P,
+    options: DatabaseOptions,
+    registry: &MigrationRegistry,
+) -> Result<Database> {
+    let db = Database::open(path, options)?;
+    // Secondaries can't write: the primary migrates and they follow it
+    if db.is_secondary() {
+        let latest = registry.latest_version();
+        match MigrationRegistry::current_version(&db).await? {
+            Some(found) if found > latest => return Err(DatabaseError::SchemaTooNew { found, supported: latest }),
+            _ => return Ok(db),
+        }
+    }
+    registry.run(&db).await?;
+    Ok(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::encryption::KeySource;
+    use tempfile::TempDir;
+
+    fn add_flag(db: &Database) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move { db.put(&"migrated", &true).await })
+    }
+
+    #[tokio::test]
+    async fn test_fresh_database_is_stamped() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let registry = MigrationRegistry::default();
+        let db = open_with_migrations(temp_dir.path(), DatabaseOptions::default(), &registry).await?;
+        assert_eq!(MigrationRegistry::current_version(&db).await?, Some(3));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fresh_encrypted_database_reopens() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let options = DatabaseOptions { encryption: Some(KeySource::Raw([7; 32])), ..DatabaseOptions::default() };
+        let registry = MigrationRegistry::default();
+        drop(open_with_migrations(temp_dir.path(), options.clone(), &registry).await?);
+
+        let db = open_with_migrations(temp_dir.path(), options, &registry).await?;
+        assert!(db.is_encrypted());
+        assert_eq!(MigrationRegistry::current_version(&db).await?, Some(3));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_secondary_opens_without_migrating() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let primary_path = temp_dir.path().join("primary");
+        let primary = Database::new(&primary_path)?;
+        primary.put(&SCHEMA_VERSION_KEY, &2u32).await?;
+
+        let registry = MigrationRegistry::default();
+        let secondary = || DatabaseOptions {
+            secondary_path: Some(temp_dir.path().join("secondary")),
+            ..DatabaseOptions::default()
+        };
+        let db = open_with_migrations(&primary_path, secondary(), &registry).await?;
+        assert_eq!(MigrationRegistry::current_version(&db).await?, Some(2));
+        drop(db);
+
+        primary.put(&SCHEMA_VERSION_KEY, &9u32).await?;
+        assert!(matches!(
+            open_with_migrations(&primary_path, secondary(), &registry).await,
+            Err(DatabaseError::SchemaTooNew { found: 9, supported: 3 })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_runs_pending_migrations_in_order() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Database::new(temp_dir.path())?;
+        db.put(&"legacy", &"data").await?;
+
+        let mut registry = MigrationRegistry::default();
+        registry.register(Migration {
+            version: 4,
+            description: "test",
+            run: add_flag,
+        });
+
+        assert_eq!(registry.run(&db).await?, 4);
+        assert_eq!(db.get::<_, bool>(&"migrated").await?, Some(true));
+        assert_eq!(MigrationRegistry::current_version(&db).await?, Some(4));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refuses_newer_schema() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Database::new(temp_dir.path())?;
+        db.put(&SCHEMA_VERSION_KEY, &99u32).await?;
+
+        assert!(matches!(
+            MigrationRegistry::default().run(&db).await,
+            Err(DatabaseError::SchemaTooNew { found: 99, supported: 3 })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::convert::TryInto;
+
+// Checking state against a root without a database, for light clients. The
+// tree itself is in `trie`.
+
+pub type TrieHash = [u8; 32];
+
+pub const EMPTY_ROOT: TrieHash = [0; 32];
+
+const LEAF_TAG: u8 = 0x00;
+const INTERNAL_TAG: u8 = 0x01;
+
+// Everything needed to check a single key against a root, for light clients and
+// fast-sync state verification. `leaf` is the leaf found at the end of the key's
+// path: the key itself for inclusion, another key or nothing for exclusion.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateProof {
+    pub siblings: Vec<TrieHash>,
+    pub leaf: Option<(TrieHash, TrieHash)>,
+}
+
+impl StateProof {
+    pub fn verify(&self, root: &TrieHash, key: &[u8], value: Option<&[u8]>) -> bool {
+        let key_hash = hash(key);
+        let depth = self.siblings.len();
+        if depth > 256 {
+            return false;
+        }
+
+        let leaf_ok = match (value, &self.leaf) {
+            (Some(value), Some((leaf_key, value_hash))) => *leaf_key == key_hash && *value_hash == hash(value),
+            (None, Some((leaf_key, _))) => {
+                *leaf_key != key_hash && (0..depth).all(|d| bit(leaf_key, d) == bit(&key_hash, d))
+            }
+            (None, None) => true,
+            (Some(_), None) => false,
+        };
+        if !leaf_ok {
+            return false;
+        }
+
+        let mut current = self.leaf.map_or(EMPTY_ROOT, |(leaf_key, value_hash)| leaf_hash(&leaf_key, &value_hash));
+        for (d, sibling) in self.siblings.iter().enumerate().rev() {
+            current = match bit(&key_hash, d) {
+                false => internal_hash(&current, sibling),
+                true => internal_hash(sibling, &current),
+            };
+        }
+        current == *root
+    }
+}
+
+pub(crate) fn hash(data: &[u8]) -> TrieHash {
+    Sha3_256::digest(data).as_slice().try_into().unwrap()
+}
+
+pub(crate) fn leaf_hash(key_hash: &TrieHash, value_hash: &TrieHash) -> TrieHash {
+    let mut hasher = Sha3_256::new();
+    hasher.update([LEAF_TAG]);
+    hasher.update(key_hash);
+    hasher.update(value_hash);
+    hasher.finalize().as_slice().try_into().unwrap()
+}
+
+pub(crate) fn internal_hash(left: &TrieHash, right: &TrieHash) -> TrieHash {
+    let mut hasher = Sha3_256::new();
+    hasher.update([INTERNAL_TAG]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().as_slice().try_into().unwrap()
+}
+
+// Most significant bit first
+pub(crate) fn bit(path: &TrieHash, depth: usize) -> bool {
+    path[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checks_inclusion_and_exclusion_against_a_root() {
+        // Two keys whose paths split at the first bit that differs
+        let (a, b) = (b"balance:a".as_slice(), b"balance:b".as_slice());
+        let (a_hash, b_hash) = (hash(a), hash(b));
+        let depth = (0..256).find(|depth| bit(&a_hash, *depth) != bit(&b_hash, *depth)).unwrap();
+        let (a_leaf, b_leaf) = (leaf_hash(&a_hash, &hash(b"1")), leaf_hash(&b_hash, &hash(b"2")));
+        let mut root =
+            if bit(&a_hash, depth) { internal_hash(&b_leaf, &a_leaf) } else { internal_hash(&a_leaf, &b_leaf) };
+        for depth in (0..depth).rev() {
+            root =
+                if bit(&a_hash, depth) { internal_hash(&EMPTY_ROOT, &root) } else { internal_hash(&root, &EMPTY_ROOT) };
+        }
+
+        let mut siblings = vec![EMPTY_ROOT; depth];
+        siblings.push(b_leaf);
+        let proof = StateProof { siblings, leaf: Some((a_hash, hash(b"1"))) };
+        assert!(proof.verify(&root, a, Some(b"1")));
+        assert!(!proof.verify(&root, a, Some(b"2")));
+        assert!(!proof.verify(&root, a, None));
+        assert!(!proof.verify(&EMPTY_ROOT, a, Some(b"1")));
+        assert!(StateProof { siblings: Vec::new(), leaf: None }.verify(&EMPTY_ROOT, a, None));
+    }
+}
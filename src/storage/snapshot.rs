@@ -0,0 +1,205 @@
+// OmniTensor-Project/omnitensor-core/src/storage/snapshot.rs
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::storage::db::{Database, DatabaseError};
+use crate::storage::transaction::StorageTransaction;
+use crate::storage::trie::{SnapshotNode, StateTrie, TrieHash, EMPTY_ROOT};
+
+// State snapshots are the trie itself, shipped in chunks of nodes. A chunk is
+// the subtrees under a few requested node hashes, breadth first, so every node
+// in it is reachable from a hash the client already trusts: the state root of
+// a finalized header, or a child of a node it has verified before.
+
+// Limits on one chunk, whatever the client asks for
+pub const MAX_CHUNK_NODES: usize = 16 * 1024;
+pub const MAX_CHUNK_BYTES: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Chunk carries a node that was not requested")]
+    UnexpectedNode(TrieHash),
+    #[error("Database error: {0}")]
+    Database(#[from] DatabaseError),
+}
+
+pub type Result<T> = std::result::Result<T, SnapshotError>;
+
+// Serving side: the nodes under `roots`, breadth first, up to `max_nodes`.
+// Roots we don't have are skipped.
+pub async fn export_chunk(trie: &StateTrie, roots: &[TrieHash], max_nodes: usize) -> Result<Vec<Vec<u8>>> {
+    let mut queue: VecDeque<TrieHash> = roots.iter().copied().filter(|root| *root != EMPTY_ROOT).collect();
+    let mut chunk = Vec::new();
+    let mut size = 0;
+    while let Some(node_hash) = queue.pop_front() {
+        if chunk.len() >= max_nodes.min(MAX_CHUNK_NODES) {
+            break;
+        }
+        let bytes = match trie.node_raw(&node_hash).await? {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+        size += bytes.len();
+        if size > MAX_CHUNK_BYTES {
+            break;
+        }
+        queue.extend(SnapshotNode::decode(&bytes)?.children());
+        chunk.push(bytes);
+    }
+    Ok(chunk)
+}
+
+// Client side: rebuilds the trie under `root` from chunks arriving in any
+// order. A node is only stored once it checks out against a hash we already
+// trust, so nothing outside the committed state gets in.
+pub struct SnapshotImporter {
+    db: Arc<Database>,
+    trie: StateTrie,
+    root: TrieHash,
+    // Nodes in the state that we don't have yet
+    missing: HashSet<TrieHash>,
+    // The missing nodes that aren't requested right now, in discovery order
+    queue: VecDeque<TrieHash>,
+    requested: HashSet<TrieHash>,
+    imported: u64,
+}
+
+impl SnapshotImporter {
+    // Walks whatever part of the trie is already stored, e.g. from an
+    // interrupted sync or a snapshot of an earlier root, so only the rest is
+    // downloaded
+    pub async fn new(db: Arc<Database>, root: TrieHash) -> Result<Self> {
+        let trie = StateTrie::new(Arc::clone(&db));
+        let mut missing = HashSet::new();
+        let mut stack = if root == EMPTY_ROOT { Vec::new() } else { vec![root] };
+        while let Some(node_hash) = stack.pop() {
+            match trie.node_raw(&node_hash).await? {
+                Some(bytes) => stack.extend(SnapshotNode::decode(&bytes)?.children()),
+                None => {
+                    missing.insert(node_hash);
+                }
+            }
+        }
+        let queue = missing.iter().copied().collect();
+        Ok(Self { db, trie, root, missing, queue, requested: HashSet::new(), imported: 0 })
+    }
+
+    pub fn root(&self) -> TrieHash {
+        self.root
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    // (nodes imported so far, nodes known to be missing)
+    pub fn progress(&self) -> (u64, usize) {
+        (self.imported, self.missing.len())
+    }
+
+    // Missing nodes to ask a peer for; each brings its subtree along
+    pub fn next_hashes(&mut self, max: usize) -> Vec<TrieHash> {
+        let mut hashes = Vec::new();
+        while hashes.len() < max {
+            let node_hash = match self.queue.pop_front() {
+                Some(node_hash) => node_hash,
+                None => break,
+            };
+            if self.missing.contains(&node_hash) && self.requested.insert(node_hash) {
+                hashes.push(node_hash);
+            }
+        }
+        hashes
+    }
+
+    // Requested hashes the peer didn't deliver go back into the queue
+    pub fn release(&mut self, hashes: &[TrieHash]) {
+        for node_hash in hashes {
+            if self.requested.remove(node_hash) && self.missing.contains(node_hash) {
+                self.queue.push_back(*node_hash);
+            }
+        }
+    }
+
+    // Verifies and stores a chunk, all or nothing; returns the nodes stored
+    pub async fn import(&mut self, chunk: &[Vec<u8>]) -> Result<usize> {
+        let mut nodes = Vec::with_capacity(chunk.len());
+        let mut reachable = HashSet::new();
+        for bytes in chunk {
+            let node = SnapshotNode::decode(bytes)?;
+            if !self.missing.contains(&node.hash) && !reachable.contains(&node.hash) {
+                return Err(SnapshotError::UnexpectedNode(node.hash));
+            }
+            reachable.extend(node.children());
+            nodes.push(node);
+        }
+
+        let mut txn = StorageTransaction::new();
+        let mut delivered = HashSet::new();
+        for node in &nodes {
+            node.stage(&mut txn)?;
+            delivered.insert(node.hash);
+        }
+        self.db.commit(txn).await?;
+
+        for node in &nodes {
+            self.missing.remove(&node.hash);
+            self.requested.remove(&node.hash);
+            for child in node.children() {
+                if delivered.contains(&child) || self.missing.contains(&child) {
+                    continue;
+                }
+                if self.trie.node_raw(&child).await?.is_none() {
+                    self.missing.insert(child);
+                    self.queue.push_back(child);
+                }
+            }
+        }
+        self.imported += nodes.len() as u64;
+        Ok(nodes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_imports_chunks_and_rejects_foreign_nodes() {
+        let (source_dir, target_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let source = Arc::new(Database::new(source_dir.path()).unwrap());
+        let source_trie = StateTrie::new(Arc::clone(&source));
+        let changes: Vec<_> =
+            (0..200).map(|i| (format!("account:{}", i).into_bytes(), Some(vec![i as u8; 8]))).collect();
+        let mut txn = StorageTransaction::new();
+        let root = source_trie.stage(&EMPTY_ROOT, &changes, &mut txn).await.unwrap();
+        source.commit(txn).await.unwrap();
+        let mut txn = StorageTransaction::new();
+        let other_root = source_trie.stage(&EMPTY_ROOT, &changes[..3], &mut txn).await.unwrap();
+        source.commit(txn).await.unwrap();
+
+        let target = Arc::new(Database::new(target_dir.path()).unwrap());
+        let mut importer = SnapshotImporter::new(Arc::clone(&target), root).await.unwrap();
+        assert_eq!(importer.progress(), (0, 1));
+
+        // Nodes of another state don't hash to anything we asked for
+        let foreign = export_chunk(&source_trie, &[other_root], 10).await.unwrap();
+        assert!(matches!(importer.import(&foreign).await, Err(SnapshotError::UnexpectedNode(_))));
+
+        while !importer.is_complete() {
+            let hashes = importer.next_hashes(4);
+            assert!(!hashes.is_empty());
+            let chunk = export_chunk(&source_trie, &hashes, 50).await.unwrap();
+            importer.import(&chunk).await.unwrap();
+            importer.release(&hashes);
+        }
+
+        let target_trie = StateTrie::new(Arc::clone(&target));
+        assert_eq!(target_trie.get(&root, b"account:123").await.unwrap(), Some(vec![123; 8]));
+        // A restarted import finds nothing left to fetch
+        assert!(SnapshotImporter::new(target, root).await.unwrap().is_complete());
+    }
+}
@@ -0,0 +1,115 @@
+// OmniTensor-Project/omnitensor-core/src/storage/stats.rs
+
+use log::warn;
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::storage::cache::CacheStats;
+use crate::storage::db::Database;
+use crate::utils::metrics::{Metric, MetricsSource};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnStats {
+    pub column: &'static str,
+    pub sst_bytes: u64,
+    pub live_data_bytes: u64,
+    pub memtable_bytes: u64,
+    pub estimated_keys: u64,
+    pub pending_compaction_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageStats {
+    pub columns: Vec<ColumnStats>,
+    pub running_compactions: u64,
+    pub running_flushes: u64,
+    pub reads: u64,
+    pub read_p99: Duration,
+    pub writes: u64,
+    pub write_p99: Duration,
+    pub cache: CacheStats,
+    // Values that failed checksum verification since the database was opened
+    pub corruptions: u64,
+}
+
+impl StorageStats {
+    pub fn total_size(&self) -> u64 {
+        self.columns.iter().map(|column| column.sst_bytes + column.memtable_bytes).sum()
+    }
+
+    pub fn pending_compaction_bytes(&self) -> u64 {
+        self.columns.iter().map(|column| column.pending_compaction_bytes).sum()
+    }
+}
+
+impl MetricsSource for Database {
+    fn collect(&self) -> Vec<Metric> {
+        let stats = match self.stats() {
+            Ok(stats) => stats,
+            Err(e) => {
+                warn!("Failed to collect storage stats: {}", e);
+                return Vec::new();
+            }
+        };
+
+        let per_column = |name: &'static str, help: &'static str, value: fn(&ColumnStats) -> u64| {
+            stats
+                .columns
+                .iter()
+                .map(move |column| Metric::gauge(name, help, value(column) as f64).with_label("column", column.column))
+        };
+
+        let mut metrics: Vec<Metric> = Vec::new();
+        metrics.extend(per_column("storage_sst_bytes", "Size of SST files on disk", |c| c.sst_bytes));
+        metrics.extend(per_column("storage_memtable_bytes", "Size of in-memory memtables", |c| c.memtable_bytes));
+        metrics.extend(per_column("storage_estimated_keys", "Estimated number of keys", |c| c.estimated_keys));
+        metrics.extend(per_column(
+            "storage_pending_compaction_bytes",
+            "Bytes compaction still has to rewrite",
+            |c| c.pending_compaction_bytes,
+        ));
+        metrics.extend([
+            Metric::gauge("storage_running_compactions", "Compactions in progress", stats.running_compactions as f64),
+            Metric::gauge("storage_running_flushes", "Memtable flushes in progress", stats.running_flushes as f64),
+            Metric::counter("storage_reads_total", "Point reads served by RocksDB", stats.reads as f64),
+            Metric::gauge("storage_read_p99_seconds", "99th percentile point read latency", stats.read_p99.as_secs_f64()),
+            Metric::counter("storage_writes_total", "Write operations", stats.writes as f64),
+            Metric::gauge("storage_write_p99_seconds", "99th percentile write latency", stats.write_p99.as_secs_f64()),
+            Metric::counter("storage_cache_hits_total", "Read cache hits", stats.cache.hits as f64),
+            Metric::counter("storage_cache_misses_total", "Read cache misses", stats.cache.misses as f64),
+            Metric::counter("storage_corruptions_total", "Values that failed checksum verification", stats.corruptions as f64),
+        ]);
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::columns::Column;
+    use crate::utils::metrics::MetricsRegistry;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_stats_and_metrics() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path()).unwrap());
+        for i in 0u64..100 {
+            db.put_cf(Column::Bodies, &i, &vec![0u8; 512]).await.unwrap();
+        }
+        db.get_cf::<_, Vec<u8>>(Column::Headers, &1u64).await.unwrap();
+
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.columns.len(), Column::ALL.len());
+        assert_eq!(stats.writes, 100);
+        assert_eq!(stats.reads, 1);
+        assert!(stats.total_size() > 0);
+
+        let registry = MetricsRegistry::new();
+        registry.register(db.clone());
+        let text = registry.render();
+        assert!(text.contains("storage_memtable_bytes{column=\"bodies\"}"));
+        assert!(text.contains("storage_writes_total 100"));
+    }
+}
@@ -0,0 +1,202 @@
+// OmniTensor-Project/omnitensor-core/src/storage/transaction.rs
+
+use rocksdb::WriteBatch;
+use serde::Serialize;
+
+use crate::storage::columns::Column;
+use crate::storage::db::{DatabaseError, Result};
+
+pub enum Operation {
+    Put {
+        column: Option<Column>,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Delete {
+        column: Option<Column>,
+        key: Vec<u8>,
+    },
+}
+
+// Collects writes across column families and applies them as a single RocksDB
+// write batch: either every operation lands or none do. Keys and values are
+// encoded when staged, so a serialization failure never leaves a half-built commit.
+#[derive(Default)]
+pub struct StorageTransaction {
+    operations: Vec<Operation>,
+}
+
+impl StorageTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put<K, V>(&mut self, column: Column, key: &K, value: &V) -> Result<&mut Self>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        self.operations.push(Operation::Put {
+            column: Some(column),
+            key: bincode::serialize(key)?,
+            value: bincode::serialize(value)?,
+        });
+        Ok(self)
+    }
+
+    pub fn put_raw(&mut self, column: Column, key: Vec<u8>, value: Vec<u8>) -> &mut Self {
+        self.operations.push(Operation::Put {
+            column: Some(column),
+            key,
+            value,
+        });
+        self
+    }
+
+    pub fn delete<K>(&mut self, column: Column, key: &K) -> Result<&mut Self>
+    where
+        K: Serialize,
+    {
+        self.operations.push(Operation::Delete {
+            column: Some(column),
+            key: bincode::serialize(key)?,
+        });
+        Ok(self)
+    }
+
+    pub fn delete_raw(&mut self, column: Column, key: Vec<u8>) -> &mut Self {
+        self.operations.push(Operation::Delete {
+            column: Some(column),
+            key,
+        });
+        self
+    }
+
+    // Writes to the default column family, for data that predates column families
+    pub fn put_default<K, V>(&mut self, key: &K, value: &V) -> Result<&mut Self>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        self.operations.push(Operation::Put {
+            column: None,
+            key: bincode::serialize(key)?,
+            value: bincode::serialize(value)?,
+        });
+        Ok(self)
+    }
+
+    pub fn delete_default<K>(&mut self, key: &K) -> Result<&mut Self>
+    where
+        K: Serialize,
+    {
+        self.operations.push(Operation::Delete {
+            column: None,
+            key: bincode::serialize(key)?,
+        });
+        Ok(self)
+    }
+
+    // A raw key and a bincode value in the default keyspace, as
+    // `StorageBackend::put_value` writes them
+    pub fn put_value<V>(&mut self, key: &[u8], value: &V) -> Result<&mut Self>
+    where
+        V: Serialize + ?Sized,
+    {
+        self.operations.push(Operation::Put { column: None, key: key.to_vec(), value: bincode::serialize(value)? });
+        Ok(self)
+    }
+
+    // Moves every operation of `other` after this transaction's own
+    pub fn append(&mut self, other: StorageTransaction) -> &mut Self {
+        self.operations.extend(other.operations);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    pub(crate) fn touched_keys(&self) -> Vec<(Option<Column>, Vec<u8>)> {
+        self.operations
+            .iter()
+            .map(|operation| match operation {
+                Operation::Put { column, key, .. } | Operation::Delete { column, key } => (*column, key.clone()),
+            })
+            .collect()
+    }
+
+    // Rewrites every staged value in place, e.g. to encrypt it before it is written
+    pub(crate) fn map_values<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Option<Column>, &[u8], Vec<u8>) -> Vec<u8>,
+    {
+        for operation in &mut self.operations {
+            if let Operation::Put { column, key, value } = operation {
+                *value = f(*column, key, std::mem::take(value));
+            }
+        }
+        self
+    }
+
+    pub fn into_operations(self) -> Vec<Operation> {
+        self.operations
+    }
+
+    pub(crate) fn into_write_batch(self, db: &rocksdb::DB) -> Result<WriteBatch> {
+        let mut batch = WriteBatch::default();
+        for operation in self.operations {
+            match operation {
+                Operation::Put { column: Some(column), key, value } => {
+                    let cf = db
+                        .cf_handle(column.name())
+                        .ok_or(DatabaseError::MissingColumnFamily(column.name()))?;
+                    batch.put_cf(cf, key, value);
+                }
+                Operation::Put { column: None, key, value } => batch.put(key, value),
+                Operation::Delete { column: Some(column), key } => {
+                    let cf = db
+                        .cf_handle(column.name())
+                        .ok_or(DatabaseError::MissingColumnFamily(column.name()))?;
+                    batch.delete_cf(cf, key);
+                }
+                Operation::Delete { column: None, key } => batch.delete(key),
+            }
+        }
+        Ok(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::db::Database;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_transaction_spans_columns() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Database::new(temp_dir.path())?;
+        db.put_cf(Column::State, &"stale", &1u64).await?;
+
+        let mut txn = StorageTransaction::new();
+        txn.put(Column::Headers, &7u64, &"header")?
+            .put(Column::Bodies, &7u64, &"body")?
+            .delete(Column::State, &"stale")?;
+        assert_eq!(txn.len(), 3);
+
+        // Nothing is visible before commit
+        assert_eq!(db.get_cf::<_, String>(Column::Headers, &7u64).await?, None);
+
+        db.commit(txn).await?;
+        assert_eq!(db.get_cf::<_, String>(Column::Headers, &7u64).await?, Some("header".to_string()));
+        assert_eq!(db.get_cf::<_, String>(Column::Bodies, &7u64).await?, Some("body".to_string()));
+        assert_eq!(db.get_cf::<_, u64>(Column::State, &"stale").await?, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+// OmniTensor-Project/omnitensor-core/src/storage/trie.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::storage::backend::StorageBackend;
+use crate::storage::columns::Column;
+use crate::storage::db::{Database, DatabaseError, Result};
+use crate::storage::keys::{domain_key, Domain};
+use crate::storage::proof::{bit, hash, internal_hash, leaf_hash};
+pub use crate::storage::proof::{StateProof, TrieHash, EMPTY_ROOT};
+use crate::storage::transaction::StorageTransaction;
+
+// Sparse Merkle tree over all authenticated state (accounts, stakes, model
+// registry, ...). A state key is hashed to a 256-bit path; leaves sit at the
+// shallowest depth where their path is unique, so the tree stays O(log n) deep.
+//
+//     empty    = [0; 32]
+//     leaf     = H(0x00 || H(key) || H(value))
+//     internal = H(0x01 || left || right)
+//
+// Nodes are content-addressed in the `state` column and never overwritten, so
+// every historical root stays readable until pruned.
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum Node {
+    Leaf { key_hash: TrieHash, value: Vec<u8> },
+    Internal { left: TrieHash, right: TrieHash },
+}
+
+impl Node {
+    fn hash(&self) -> TrieHash {
+        match self {
+            Node::Leaf { key_hash, value } => leaf_hash(key_hash, &hash(value)),
+            Node::Internal { left, right } => internal_hash(left, right),
+        }
+    }
+}
+
+// A trie node as shipped in state snapshots. The hash is recomputed from the
+// contents on decode, so a node from a peer can be checked against the hash
+// it was requested by.
+pub struct SnapshotNode {
+    pub hash: TrieHash,
+    node: Node,
+}
+
+impl SnapshotNode {
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let node: Node = bincode::deserialize(bytes)?;
+        Ok(Self { hash: node.hash(), node })
+    }
+
+    // Non-empty subtrees below this node
+    pub fn children(&self) -> Vec<TrieHash> {
+        match &self.node {
+            Node::Leaf { .. } => Vec::new(),
+            Node::Internal { left, right } => {
+                [*left, *right].into_iter().filter(|child| *child != EMPTY_ROOT).collect()
+            }
+        }
+    }
+
+    pub fn stage(&self, txn: &mut StorageTransaction) -> Result<()> {
+        txn.put_raw(Column::State, node_key(&self.hash), bincode::serialize(&self.node)?);
+        Ok(())
+    }
+}
+
+pub struct StateTrie {
+    db: Arc<Database>,
+}
+
+impl StateTrie {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+
+    pub async fn get(&self, root: &TrieHash, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key_hash = hash(key);
+        let (_, leaf) = self.walk(root, &key_hash, &HashMap::new()).await?;
+        Ok(match leaf {
+            Some(Node::Leaf { key_hash: found, value }) if found == key_hash => Some(value),
+            _ => None,
+        })
+    }
+
+    pub async fn prove(&self, root: &TrieHash, key: &[u8]) -> Result<StateProof> {
+        let (siblings, leaf) = self.walk(root, &hash(key), &HashMap::new()).await?;
+        let leaf = match leaf {
+            Some(Node::Leaf { key_hash, value }) => Some((key_hash, hash(&value))),
+            _ => None,
+        };
+        Ok(StateProof { siblings, leaf })
+    }
+
+    // Encoded node as stored, for serving snapshots
+    pub async fn node_raw(&self, node_hash: &TrieHash) -> Result<Option<Vec<u8>>> {
+        self.db.get_raw(Some(Column::State), &node_key(node_hash)).await
+    }
+
+    // Applies `changes` (a `None` value deletes) on top of `root` and stages the new
+    // nodes into `txn`, so the state root commits atomically with the block that
+    // produced it. Returns the new root.
+    pub async fn stage(
+        &self,
+        root: &TrieHash,
+        changes: &[(Vec<u8>, Option<Vec<u8>>)],
+        txn: &mut StorageTransaction,
+    ) -> Result<TrieHash> {
+        let mut pending = HashMap::new();
+        let mut root = *root;
+        for (key, value) in changes {
+            root = self.apply(&root, hash(key), value.clone(), &mut pending).await?;
+        }
+        for (node_hash, node) in pending {
+            txn.put_raw(Column::State, node_key(&node_hash), bincode::serialize(&node)?);
+        }
+        Ok(root)
+    }
+
+    async fn apply(
+        &self,
+        root: &TrieHash,
+        key_hash: TrieHash,
+        value: Option<Vec<u8>>,
+        pending: &mut HashMap<TrieHash, Node>,
+    ) -> Result<TrieHash> {
+        let (mut siblings, found) = self.walk(root, &key_hash, pending).await?;
+
+        let mut current = match (found, value) {
+            (None, None) => return Ok(*root),
+            (None, Some(value)) => Some(Node::Leaf { key_hash, value }),
+            (Some(Node::Leaf { key_hash: existing, .. }), value) if existing == key_hash => {
+                value.map(|value| Node::Leaf { key_hash, value })
+            }
+            // A different key occupies our slot: push both leaves down to where they diverge
+            (Some(Node::Leaf { key_hash: existing_key, value: existing_value }), Some(value)) => {
+                while bit(&existing_key, siblings.len()) == bit(&key_hash, siblings.len()) {
+                    siblings.push(EMPTY_ROOT);
+                }
+                siblings.push(insert_node(pending, Node::Leaf { key_hash: existing_key, value: existing_value }));
+                Some(Node::Leaf { key_hash, value })
+            }
+            (Some(Node::Leaf { .. }), None) => return Ok(*root),
+            (Some(Node::Internal { .. }), _) => unreachable!("walk always ends at a leaf or empty slot"),
+        };
+
+        // Rebuild towards the root. A lone leaf is hoisted past empty siblings so
+        // deletions leave the tree in the same shape a fresh insert would.
+        let mut current_hash = current.clone().map_or(EMPTY_ROOT, |node| insert_node(pending, node));
+        for d in (0..siblings.len()).rev() {
+            let sibling = siblings[d];
+            let current_is_leaf = matches!(current, Some(Node::Leaf { .. }));
+            if sibling == EMPTY_ROOT && (current_is_leaf || current.is_none()) {
+                continue;
+            }
+            if current.is_none() {
+                if let Some(sibling_node @ Node::Leaf { .. }) = self.load(&sibling, pending).await? {
+                    current = Some(sibling_node);
+                    current_hash = sibling;
+                    continue;
+                }
+            }
+            let node = match bit(&key_hash, d) {
+                false => Node::Internal { left: current_hash, right: sibling },
+                true => Node::Internal { left: sibling, right: current_hash },
+            };
+            current_hash = insert_node(pending, node.clone());
+            current = Some(node);
+        }
+        Ok(current_hash)
+    }
+
+    // Follows `key_hash` from `root` until it reaches a leaf or an empty slot
+    async fn walk(
+        &self,
+        root: &TrieHash,
+        key_hash: &TrieHash,
+        pending: &HashMap<TrieHash, Node>,
+    ) -> Result<(Vec<TrieHash>, Option<Node>)> {
+        let mut siblings = Vec::new();
+        let mut current = *root;
+        loop {
+            match self.load(&current, pending).await? {
+                None => return Ok((siblings, None)),
+                Some(Node::Internal { left, right }) => {
+                    let (next, sibling) = match bit(key_hash, siblings.len()) {
+                        false => (left, right),
+                        true => (right, left),
+                    };
+                    siblings.push(sibling);
+                    current = next;
+                }
+                Some(leaf) => return Ok((siblings, Some(leaf))),
+            }
+        }
+    }
+
+    async fn load(&self, node_hash: &TrieHash, pending: &HashMap<TrieHash, Node>) -> Result<Option<Node>> {
+        if *node_hash == EMPTY_ROOT {
+            return Ok(None);
+        }
+        if let Some(node) = pending.get(node_hash) {
+            return Ok(Some(node.clone()));
+        }
+        let bytes = self
+            .db
+            .get_raw(Some(Column::State), &node_key(node_hash))
+            .await?
+            .ok_or_else(|| DatabaseError::KeyNotFound(node_hash.to_vec()))?;
+        Ok(Some(bincode::deserialize(&bytes)?))
+    }
+}
+
+fn insert_node(pending: &mut HashMap<TrieHash, Node>, node: Node) -> TrieHash {
+    let node_hash = node.hash();
+    pending.insert(node_hash, node);
+    node_hash
+}
+
+fn node_key(node_hash: &TrieHash) -> Vec<u8> {
+    domain_key(Domain::TrieNode, node_hash, &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn commit(trie: &StateTrie, db: &Database, root: &TrieHash, changes: &[(Vec<u8>, Option<Vec<u8>>)]) -> TrieHash {
+        let mut txn = StorageTransaction::new();
+        let root = trie.stage(root, changes, &mut txn).await.unwrap();
+        db.commit(txn).await.unwrap();
+        root
+    }
+
+    fn put(key: &str, value: &str) -> (Vec<u8>, Option<Vec<u8>>) {
+        (key.as_bytes().to_vec(), Some(value.as_bytes().to_vec()))
+    }
+
+    #[tokio::test]
+    async fn test_root_is_order_independent() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path()).unwrap());
+        let trie = StateTrie::new(Arc::clone(&db));
+
+        let changes: Vec<_> = (0..50).map(|i| put(&format!("account:{}", i), &format!("{}", i * 10))).collect();
+        let forward = commit(&trie, &db, &EMPTY_ROOT, &changes).await;
+        let reversed: Vec<_> = changes.iter().rev().cloned().collect();
+        let backward = commit(&trie, &db, &EMPTY_ROOT, &reversed).await;
+        assert_eq!(forward, backward);
+        assert_ne!(forward, EMPTY_ROOT);
+
+        assert_eq!(trie.get(&forward, b"account:7").await.unwrap(), Some(b"70".to_vec()));
+        assert_eq!(trie.get(&forward, b"account:99").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_delete_restores_previous_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path()).unwrap());
+        let trie = StateTrie::new(Arc::clone(&db));
+
+        let base = commit(&trie, &db, &EMPTY_ROOT, &[put("stake:a", "1"), put("model:b", "2")]).await;
+        let extended = commit(&trie, &db, &base, &[put("stake:c", "3")]).await;
+        assert_ne!(base, extended);
+
+        let pruned = commit(&trie, &db, &extended, &[(b"stake:c".to_vec(), None)]).await;
+        assert_eq!(pruned, base);
+        let emptied = commit(&trie, &db, &pruned, &[(b"stake:a".to_vec(), None), (b"model:b".to_vec(), None)]).await;
+        assert_eq!(emptied, EMPTY_ROOT);
+
+        // Old roots remain queryable
+        assert_eq!(trie.get(&extended, b"stake:c").await.unwrap(), Some(b"3".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_inclusion_and_exclusion_proofs() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path()).unwrap());
+        let trie = StateTrie::new(Arc::clone(&db));
+
+        let changes: Vec<_> = (0..20).map(|i| put(&format!("k{}", i), &format!("v{}", i))).collect();
+        let root = commit(&trie, &db, &EMPTY_ROOT, &changes).await;
+
+        let proof = trie.prove(&root, b"k3").await.unwrap();
+        assert!(proof.verify(&root, b"k3", Some(b"v3")));
+        assert!(!proof.verify(&root, b"k3", Some(b"forged")));
+        assert!(!proof.verify(&root, b"k3", None));
+
+        let absent = trie.prove(&root, b"missing").await.unwrap();
+        assert!(absent.verify(&root, b"missing", None));
+        assert!(!absent.verify(&root, b"missing", Some(b"v3")));
+        assert!(!proof.verify(&EMPTY_ROOT, b"k3", Some(b"v3")));
+    }
+}
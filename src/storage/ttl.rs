@@ -0,0 +1,153 @@
+// OmniTensor-Project/omnitensor-core/src/storage/ttl.rs
+
+use rocksdb::compaction_filter::Decision;
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::storage::backend::StorageBackend;
+use crate::storage::columns::Column;
+use crate::storage::db::{Database, Result};
+
+// Every value in the `ephemeral` column is
+//
+//     [expires at: u64 BE unix millis][payload]
+//
+// and keys are prefixed with a one-byte namespace tag. Expired entries read as
+// absent and are deleted lazily on read; the column's compaction filter drops
+// the rest in the background, so callers never write their own GC.
+const EXPIRY_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Namespace {
+    GossipSeen = 0x01,
+    PeerBan = 0x02,
+    JobAssignment = 0x03,
+}
+
+pub struct ExpiringStore {
+    db: Arc<Database>,
+    namespace: Namespace,
+}
+
+impl ExpiringStore {
+    pub fn new(db: Arc<Database>, namespace: Namespace) -> Self {
+        Self { db, namespace }
+    }
+
+    pub async fn put<V: Serialize>(&self, key: &[u8], value: &V, ttl: Duration) -> Result<()> {
+        let expires_at = now_millis().saturating_add(ttl.as_millis() as u64);
+        let mut raw = expires_at.to_be_bytes().to_vec();
+        raw.extend(bincode::serialize(value)?);
+        self.db.put_raw(Some(Column::Ephemeral), &self.key(key), &raw).await
+    }
+
+    pub async fn get<V: DeserializeOwned>(&self, key: &[u8]) -> Result<Option<V>> {
+        match self.get_live(key).await? {
+            Some(raw) => Ok(Some(bincode::deserialize(&raw[EXPIRY_LEN..])?)),
+            None => Ok(None),
+        }
+    }
+
+    // For seen-caches that only care about presence
+    pub async fn contains(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.get_live(key).await?.is_some())
+    }
+
+    // Live entries of this namespace, keys without the namespace tag
+    pub async fn entries<V: DeserializeOwned>(&self) -> Result<Vec<(Vec<u8>, V)>> {
+        let now = now_millis();
+        let mut entries = Vec::new();
+        for (key, raw) in self.db.scan_prefix_raw(Column::Ephemeral, &[self.namespace as u8]).await? {
+            if !is_expired(&raw, now) {
+                entries.push((key[1..].to_vec(), bincode::deserialize(&raw[EXPIRY_LEN..])?));
+            }
+        }
+        Ok(entries)
+    }
+
+    pub async fn remove(&self, key: &[u8]) -> Result<()> {
+        self.db.delete_raw(Some(Column::Ephemeral), &self.key(key)).await
+    }
+
+    async fn get_live(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = self.key(key);
+        let raw = match self.db.get_raw(Some(Column::Ephemeral), &key).await? {
+            Some(raw) => raw,
+            None => return Ok(None),
+        };
+        if is_expired(&raw, now_millis()) {
+            self.db.delete_raw(Some(Column::Ephemeral), &key).await?;
+            return Ok(None);
+        }
+        Ok(Some(raw))
+    }
+
+    fn key(&self, key: &[u8]) -> Vec<u8> {
+        let mut namespaced = Vec::with_capacity(1 + key.len());
+        namespaced.push(self.namespace as u8);
+        namespaced.extend_from_slice(key);
+        namespaced
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_millis() as u64).unwrap_or(0)
+}
+
+// Values too short to carry an expiry are not ours to judge and are kept
+fn is_expired(raw: &[u8], now: u64) -> bool {
+    match raw.get(..EXPIRY_LEN) {
+        Some(expiry) => u64::from_be_bytes(expiry.try_into().unwrap()) <= now,
+        None => false,
+    }
+}
+
+pub(crate) fn compaction_filter(_level: u32, _key: &[u8], value: &[u8]) -> Decision {
+    if is_expired(value, now_millis()) {
+        Decision::Remove
+    } else {
+        Decision::Keep
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_entries_expire() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path()).unwrap());
+        let bans = ExpiringStore::new(db.clone(), Namespace::PeerBan);
+        let seen = ExpiringStore::new(db.clone(), Namespace::GossipSeen);
+
+        bans.put(b"peer-1", &"spam".to_string(), Duration::from_secs(3600)).await.unwrap();
+        bans.put(b"peer-2", &"spam".to_string(), Duration::ZERO).await.unwrap();
+
+        assert_eq!(bans.get::<String>(b"peer-1").await.unwrap(), Some("spam".to_string()));
+        assert!(!bans.contains(b"peer-2").await.unwrap());
+        // Expired entry was deleted on read
+        assert_eq!(db.get_raw(Some(Column::Ephemeral), b"\x02peer-2").await.unwrap(), None);
+        // Namespaces don't overlap
+        assert!(!seen.contains(b"peer-1").await.unwrap());
+
+        let live: Vec<(Vec<u8>, String)> = bans.entries().await.unwrap();
+        assert_eq!(live, vec![(b"peer-1".to_vec(), "spam".to_string())]);
+
+        bans.remove(b"peer-1").await.unwrap();
+        assert!(!bans.contains(b"peer-1").await.unwrap());
+    }
+
+    #[test]
+    fn test_compaction_filter_drops_expired_values() {
+        let now = now_millis();
+        let expired = [(now - 1).to_be_bytes().to_vec(), vec![1]].concat();
+        let live = [(now + 60_000).to_be_bytes().to_vec(), vec![1]].concat();
+        assert!(matches!(compaction_filter(0, b"k", &expired), Decision::Remove));
+        assert!(matches!(compaction_filter(0, b"k", &live), Decision::Keep));
+        assert!(matches!(compaction_filter(0, b"k", &[1, 2]), Decision::Keep));
+    }
+}
@@ -0,0 +1,150 @@
+use ::config::{Config, ConfigError, File};
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// `OMNITENSOR_RPC__RATE_LIMIT__BURST=20` sets `[rpc.rate_limit] burst`
+const ENV_PREFIX: &str = "OMNITENSOR_";
+const ENV_SEPARATOR: &str = "__";
+
+#[derive(Debug, Error)]
+pub enum ConfigSourceError {
+    #[error("Failed to read {0}: {1}")]
+    Read(PathBuf, ConfigError),
+    #[error("Malformed override {0:?}, expected KEY=VALUE")]
+    MalformedOverride(String),
+    #[error("Invalid config value for {field}: {message}")]
+    Invalid { field: String, message: String },
+}
+
+// Where the config comes from, lowest precedence first: the defaults of
+// every section, the config file, `OMNITENSOR_*` environment variables and
+// `--set key=value` flags. Lists can only be set in the file.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    file: Option<(PathBuf, bool)>,
+    // Dotted keys, in the order they apply
+    overrides: Vec<(String, String)>,
+}
+
+impl ConfigSources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A file that isn't `required` may be missing
+    pub fn with_file(mut self, path: impl Into<PathBuf>, required: bool) -> Self {
+        self.file = Some((path.into(), required));
+        self
+    }
+
+    // Picks the `OMNITENSOR_*` variables out of `vars`, normally `std::env::vars()`.
+    // Section and field are separated by a double underscore.
+    pub fn with_env(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut vars: Vec<(String, String)> = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let key = name.strip_prefix(ENV_PREFIX)?;
+                Some((key.to_lowercase().replace(ENV_SEPARATOR, "."), value))
+            })
+            .collect();
+        vars.sort();
+        self.overrides.extend(vars);
+        self
+    }
+
+    // `--set` flags, applied after the environment
+    pub fn with_overrides<'a>(mut self, args: impl IntoIterator<Item = &'a str>) -> Result<Self, ConfigSourceError> {
+        for arg in args {
+            match arg.split_once('=') {
+                Some((key, value)) if !key.trim().is_empty() => {
+                    self.overrides.push((key.trim().to_string(), value.to_string()))
+                }
+                _ => return Err(ConfigSourceError::MalformedOverride(arg.to_string())),
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    // Merges the sources into `T`. Fields nobody set keep their
+    // `#[serde(default)]`; a value of the wrong type fails with its dotted key.
+    pub fn load<T: DeserializeOwned>(&self) -> Result<T, ConfigSourceError> {
+        let mut builder = Config::builder();
+        if let Some((path, required)) = &self.file {
+            builder = builder.add_source(File::from(path.as_path()).required(*required));
+        }
+        for (key, value) in &self.overrides {
+            builder = builder.set_override(key.as_str(), value.as_str()).map_err(|e| self.read_error(e))?;
+        }
+        let config = builder.build().map_err(|e| self.read_error(e))?;
+        serde_path_to_error::deserialize(config).map_err(|e| ConfigSourceError::Invalid {
+            field: e.path().to_string(),
+            message: match e.into_inner() {
+                ConfigError::Type { unexpected, expected, .. } => {
+                    format!("invalid type {}, expected {}", unexpected, expected)
+                }
+                e => e.to_string(),
+            },
+        })
+    }
+
+    fn read_error(&self, error: ConfigError) -> ConfigSourceError {
+        ConfigSourceError::Read(self.file().map(Path::to_path_buf).unwrap_or_default(), error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::rate_limit::RequestLimit;
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default)]
+    struct Settings {
+        storage_path: String,
+        rpc: Rpc,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default)]
+    struct Rpc {
+        port: u16,
+        rate_limit: RequestLimit,
+    }
+
+    #[test]
+    fn test_later_sources_override_earlier_ones() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        std::fs::write(&path, "storage_path = \"./data/db\"\n[rpc]\nport = 8545\n[rpc.rate_limit]\nburst = 5.0\n")
+            .unwrap();
+        let env = vec![
+            ("OMNITENSOR_RPC__PORT".to_string(), "9545".to_string()),
+            ("OMNITENSOR_RPC__RATE_LIMIT__BURST".to_string(), "7".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ];
+        let sources = ConfigSources::new().with_file(&path, true).with_env(env);
+
+        let settings: Settings = sources.clone().with_overrides(["rpc.port=10545"]).unwrap().load().unwrap();
+        assert_eq!(settings.storage_path, "./data/db");
+        assert_eq!(settings.rpc.port, 10545);
+        assert_eq!(settings.rpc.rate_limit.burst, 7.0);
+        assert_eq!(settings.rpc.rate_limit.requests_per_sec, RequestLimit::default().requests_per_sec);
+        let settings: Settings =
+            ConfigSources::new().with_file(temp_dir.path().join("none.toml"), false).load().unwrap();
+        assert_eq!(settings.rpc.port, 0);
+
+        let invalid = sources.with_overrides(["rpc.port=fast"]).unwrap().load::<Settings>().unwrap_err();
+        assert!(matches!(invalid, ConfigSourceError::Invalid { ref field, .. } if field == "rpc.port"), "{}", invalid);
+        assert!(matches!(
+            ConfigSources::new().with_overrides(["rpc.port"]),
+            Err(ConfigSourceError::MalformedOverride(_))
+        ));
+    }
+}
@@ -1,14 +1,26 @@
-use sha2::{Sha256, Digest};
 use base64::{encode, decode};
 
+use crate::crypto::hasher::HashAlgorithm;
+
+// `<algorithm>:<base64 digest>`, SHA3-256 like block hashes
 pub fn hash_data(data: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(data.as_bytes());
-    encode(hasher.finalize())
+    hash_data_with(HashAlgorithm::default(), data)
+}
+
+pub fn hash_data_with(algorithm: HashAlgorithm, data: &str) -> String {
+    format!("{}:{}", algorithm.name(), encode(algorithm.digest(data.as_bytes())))
 }
 
+// Untagged hashes are from before tagging, when this was always SHA-256
 pub fn verify_hash(data: &str, hash: &str) -> bool {
-    hash_data(data) == hash
+    let (algorithm, digest) = match hash.split_once(':') {
+        Some((name, digest)) => match name.parse::<HashAlgorithm>() {
+            Ok(algorithm) => (algorithm, digest),
+            Err(_) => return false,
+        },
+        None => (HashAlgorithm::Sha256, hash),
+    };
+    encode(algorithm.digest(data.as_bytes())) == digest
 }
 
 pub fn encode_base64(data: &[u8]) -> String {
@@ -27,7 +39,12 @@ mod tests {
     fn test_hash_data() {
         let data = "test_data";
         let hash = hash_data(data);
+        assert!(hash.starts_with("sha3-256:"));
         assert!(verify_hash(data, &hash));
+        assert!(verify_hash(data, &hash_data_with(HashAlgorithm::Blake3, data)));
+        assert!(!verify_hash("other_data", &hash_data_with(HashAlgorithm::Blake3, data)));
+        // As hashed before algorithms were tagged
+        assert!(verify_hash(data, &encode(HashAlgorithm::Sha256.digest(data.as_bytes()))));
     }
 
     #[test]
@@ -0,0 +1,293 @@
+use log::warn;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::chain::store::ChainStore;
+use crate::utils::metrics::{Metric, MetricsRegistry};
+use crate::utils::shutdown::Shutdown;
+
+// Gauges and counters the dashboard reads besides the per-peer network
+// metrics; the chain itself comes from the store. The subsystems report them to the registry the metrics endpoint
+// scrapes, so both always show the same numbers.
+pub const SYNC_TARGET_HEIGHT: &str = "sync_target_height";
+pub const MEMPOOL_TRANSACTIONS: &str = "mempool_transactions";
+pub const VALIDATOR_ACTIVE: &str = "consensus_validator_active";
+pub const VALIDATOR_STAKE: &str = "consensus_validator_stake";
+pub const JOBS_COMPLETED: &str = "ai_jobs_completed_total";
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const RECENT_BLOCKS: u64 = 8;
+const MAX_PEER_ROWS: usize = 12;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeerRow {
+    pub peer: String,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub latency_p99: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockRow {
+    pub height: u64,
+    pub hash: [u8; 32],
+    pub transactions: usize,
+    pub timestamp: i64,
+}
+
+// One frame's worth of node status. Sections whose source isn't running,
+// like the mempool on a light node, are `None`.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub head: Option<u64>,
+    pub sync_target: Option<u64>,
+    pub peers: Vec<PeerRow>,
+    pub mempool: Option<u64>,
+    pub blocks: Vec<BlockRow>,
+    pub validator_active: Option<bool>,
+    pub validator_stake: Option<f64>,
+    pub jobs_per_minute: Option<f64>,
+}
+
+// `omnitensor --tui`: live node status in the terminal, redrawn every second
+pub struct Dashboard {
+    metrics: MetricsRegistry,
+    chain: Arc<ChainStore>,
+    // Completed jobs at the previous refresh, for the throughput
+    last_jobs: Option<(Instant, f64)>,
+}
+
+impl Dashboard {
+    pub fn new(metrics: MetricsRegistry, chain: Arc<ChainStore>) -> Self {
+        Self { metrics, chain, last_jobs: None }
+    }
+
+    pub async fn snapshot(&mut self) -> Snapshot {
+        let metrics = self.metrics.collect();
+        let value = |name: &str| metrics.iter().find(|metric| metric.name == name).map(|metric| metric.value);
+
+        let mut snapshot = Snapshot {
+            sync_target: value(SYNC_TARGET_HEIGHT).map(|height| height as u64),
+            peers: peers(&metrics),
+            mempool: value(MEMPOOL_TRANSACTIONS).map(|depth| depth as u64),
+            validator_active: value(VALIDATOR_ACTIVE).map(|active| active > 0.0),
+            validator_stake: value(VALIDATOR_STAKE),
+            ..Snapshot::default()
+        };
+        if let Some(completed) = value(JOBS_COMPLETED) {
+            let now = Instant::now();
+            if let Some((then, before)) = self.last_jobs {
+                let minutes = now.duration_since(then).as_secs_f64() / 60.0;
+                if minutes > 0.0 {
+                    snapshot.jobs_per_minute = Some((completed - before).max(0.0) / minutes);
+                }
+            }
+            self.last_jobs = Some((now, completed));
+        }
+
+        match self.chain.head().await {
+            Ok(head) => snapshot.head = head.map(|head| head.height),
+            Err(e) => warn!("Dashboard failed to read the chain head: {}", e),
+        }
+        if let Some(head) = snapshot.head {
+            for height in (head.saturating_sub(RECENT_BLOCKS - 1)..=head).rev() {
+                match self.chain.block(height).await {
+                    Ok(Some(block)) => snapshot.blocks.push(BlockRow {
+                        height,
+                        hash: block.hash(),
+                        transactions: block.transactions.len(),
+                        timestamp: block.header.timestamp,
+                    }),
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!("Dashboard failed to read block {}: {}", height, e);
+                        break;
+                    }
+                }
+            }
+        }
+        snapshot
+    }
+
+    // Redraws until shutdown, on the terminal's alternate screen
+    pub async fn run(mut self, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+        print!("\x1b[?1049h\x1b[?25l");
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let frame = render(&self.snapshot().await);
+                    print!("\x1b[H\x1b[2J{}", frame);
+                    let _ = io::stdout().flush();
+                }
+                _ = shutdown.wait() => break,
+            }
+        }
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+    }
+}
+
+fn peers(metrics: &[Metric]) -> Vec<PeerRow> {
+    let label = |metric: &Metric, key: &str| {
+        metric.labels.iter().find(|(name, _)| *name == key).map(|(_, value)| value.clone()).unwrap_or_default()
+    };
+    let mut peers: BTreeMap<String, PeerRow> = BTreeMap::new();
+    for metric in metrics {
+        let peer = label(metric, "peer");
+        let row = || PeerRow { peer: peer.clone(), ..PeerRow::default() };
+        match metric.name {
+            "network_peer_bytes_total" => {
+                let row = peers.entry(peer.clone()).or_insert_with(row);
+                match label(metric, "direction").as_str() {
+                    "in" => row.bytes_in = metric.value as u64,
+                    _ => row.bytes_out = metric.value as u64,
+                }
+            }
+            "network_peer_latency_p99_seconds" => {
+                peers.entry(peer.clone()).or_insert_with(row).latency_p99 = Some(Duration::from_secs_f64(metric.value));
+            }
+            _ => {}
+        }
+    }
+    let mut peers: Vec<PeerRow> = peers.into_values().collect();
+    peers.sort_by(|a, b| (b.bytes_in + b.bytes_out).cmp(&(a.bytes_in + a.bytes_out)));
+    peers
+}
+
+pub fn render(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "OmniTensor Core\n");
+
+    let _ = match (snapshot.head, snapshot.sync_target) {
+        (Some(head), Some(target)) if target > head => {
+            let percent = head as f64 * 100.0 / target as f64;
+            writeln!(out, "Sync       {} / {} ({:.1}%, {} behind)", head, target, percent, target - head)
+        }
+        (Some(head), _) => writeln!(out, "Sync       {} (in sync)", head),
+        (None, _) => writeln!(out, "Sync       no blocks yet"),
+    };
+    let _ = writeln!(out, "Mempool    {}", optional(snapshot.mempool.map(|depth| format!("{} transactions", depth))));
+    let validator = match (snapshot.validator_active, snapshot.validator_stake) {
+        (Some(true), Some(stake)) => Some(format!("active, stake {}", stake)),
+        (Some(true), None) => Some("active".to_string()),
+        (Some(false), _) => Some("inactive".to_string()),
+        (None, _) => None,
+    };
+    let _ = writeln!(out, "Validator  {}", optional(validator));
+    let jobs = snapshot.jobs_per_minute.map(|rate| format!("{:.1} jobs/min", rate));
+    let _ = writeln!(out, "Inference  {}", optional(jobs));
+
+    let _ = writeln!(out, "\nPeers ({})", snapshot.peers.len());
+    let _ = writeln!(out, "  {:<54} {:>10} {:>10} {:>9}", "PEER", "IN", "OUT", "P99");
+    for peer in snapshot.peers.iter().take(MAX_PEER_ROWS) {
+        let latency = peer.latency_p99.map_or("-".to_string(), |latency| format!("{}ms", latency.as_millis()));
+        let _ = writeln!(
+            out,
+            "  {:<54} {:>10} {:>10} {:>9}",
+            peer.peer,
+            bytes(peer.bytes_in),
+            bytes(peer.bytes_out),
+            latency
+        );
+    }
+    if snapshot.peers.len() > MAX_PEER_ROWS {
+        let _ = writeln!(out, "  ... {} more", snapshot.peers.len() - MAX_PEER_ROWS);
+    }
+
+    let _ = writeln!(out, "\nRecent blocks");
+    let _ = writeln!(out, "  {:>10}  {:<18} {:>5}  {:>12}", "HEIGHT", "HASH", "TXS", "TIMESTAMP");
+    for block in &snapshot.blocks {
+        let hash: String = block.hash[..8].iter().map(|byte| format!("{:02x}", byte)).collect();
+        let _ = writeln!(
+            out,
+            "  {:>10}  {:<18} {:>5}  {:>12}",
+            block.height,
+            format!("{}..", hash),
+            block.transactions,
+            block.timestamp
+        );
+    }
+    out
+}
+
+fn optional(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "-".to_string())
+}
+
+fn bytes(count: u64) -> String {
+    match count {
+        count if count >= 1 << 30 => format!("{:.1}GiB", count as f64 / (1u64 << 30) as f64),
+        count if count >= 1 << 20 => format!("{:.1}MiB", count as f64 / (1u64 << 20) as f64),
+        count if count >= 1 << 10 => format!("{:.1}KiB", count as f64 / (1u64 << 10) as f64),
+        count => format!("{}B", count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::genesis::GenesisConfig;
+    use crate::storage::db::Database;
+    use crate::utils::metrics::MetricsSource;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tempfile::TempDir;
+
+    #[derive(Default)]
+    struct Node {
+        jobs: AtomicU64,
+    }
+
+    impl MetricsSource for Node {
+        fn collect(&self) -> Vec<Metric> {
+            vec![
+                Metric::gauge(SYNC_TARGET_HEIGHT, "", 40.0),
+                Metric::gauge(MEMPOOL_TRANSACTIONS, "", 17.0),
+                Metric::gauge(VALIDATOR_ACTIVE, "", 1.0),
+                Metric::counter(JOBS_COMPLETED, "", self.jobs.load(Ordering::Relaxed) as f64),
+                Metric::counter("network_peer_bytes_total", "", 2048.0)
+                    .with_label("peer", "12D3KooWA")
+                    .with_label("direction", "in"),
+                Metric::counter("network_peer_bytes_total", "", 10.0)
+                    .with_label("peer", "12D3KooWA")
+                    .with_label("direction", "out"),
+                Metric::gauge("network_peer_latency_p99_seconds", "", 0.25).with_label("peer", "12D3KooWA"),
+            ]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_metrics_and_recent_blocks() {
+        let temp_dir = TempDir::new().unwrap();
+        let chain = Arc::new(ChainStore::new(Arc::new(Database::new(temp_dir.path().join("db")).unwrap())));
+        GenesisConfig::default().initialize(&chain).await.unwrap();
+        let node = Arc::new(Node::default());
+        let metrics = MetricsRegistry::new();
+        metrics.register(node.clone());
+        let mut dashboard = Dashboard::new(metrics, chain);
+
+        let snapshot = dashboard.snapshot().await;
+        assert_eq!(snapshot.head, Some(0));
+        assert_eq!(snapshot.blocks.len(), 1);
+        assert_eq!(snapshot.mempool, Some(17));
+        assert_eq!(snapshot.jobs_per_minute, None);
+        let peer = PeerRow {
+            peer: "12D3KooWA".to_string(),
+            bytes_in: 2048,
+            bytes_out: 10,
+            latency_p99: Some(Duration::from_millis(250)),
+        };
+        assert_eq!(snapshot.peers, vec![peer]);
+
+        node.jobs.store(5, Ordering::Relaxed);
+        let snapshot = dashboard.snapshot().await;
+        assert!(snapshot.jobs_per_minute.unwrap() > 0.0);
+        let frame = render(&snapshot);
+        assert!(frame.contains("0 / 40 (0.0%, 40 behind)"));
+        assert!(frame.contains("17 transactions"));
+        assert!(frame.contains("2.0KiB") && frame.contains("250ms"));
+    }
+}
@@ -0,0 +1,192 @@
+use primitive_types::U256;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::types::Balance;
+
+pub const DECIMAL_PLACES: usize = 18;
+const SCALE: u128 = 1_000_000_000_000_000_000;
+const BASIS_POINTS: u128 = 10_000;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DecimalError {
+    #[error("Malformed decimal {0:?}")]
+    Malformed(String),
+    #[error("Decimal {0:?} has more than {DECIMAL_PLACES} decimal places")]
+    TooPrecise(String),
+    #[error("Decimal {0:?} is too large")]
+    Overflow(String),
+}
+
+// An unsigned fixed-point number with 18 decimal places, for rates, shares
+// and prices in consensus code. Unlike f64 it computes the same result on
+// every machine; results are rounded down. Serialized as a string such as
+// "0.05", so it reads the same in JSON config as in state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Decimal(u128);
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal(0);
+    pub const ONE: Decimal = Decimal(SCALE);
+    pub const MAX: Decimal = Decimal(u128::MAX);
+
+    // `raw` units of 10^-18
+    pub const fn from_raw(raw: u128) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(self) -> u128 {
+        self.0
+    }
+
+    pub fn from_integer(value: u64) -> Self {
+        Self(value as u128 * SCALE)
+    }
+
+    pub fn from_bps(bps: u32) -> Self {
+        Self(bps as u128 * (SCALE / BASIS_POINTS))
+    }
+
+    // `numerator / denominator`, or None if the denominator is zero or the
+    // quotient doesn't fit
+    pub fn from_ratio(numerator: u128, denominator: u128) -> Option<Self> {
+        mul_div(numerator, SCALE, denominator).map(Self)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        mul_div(self.0, other.0, SCALE).map(Self)
+    }
+
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        mul_div(self.0, SCALE, other.0).map(Self)
+    }
+
+    pub fn saturating_add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    pub fn saturating_mul(self, other: Self) -> Self {
+        self.checked_mul(other).unwrap_or(Self::MAX)
+    }
+
+    // `amount` times this, rounded down
+    pub fn checked_mul_int(self, amount: u128) -> Option<u128> {
+        mul_div(amount, self.0, SCALE)
+    }
+
+    pub fn checked_mul_balance(self, amount: Balance) -> Option<Balance> {
+        self.checked_mul_int(u128::from(amount)).and_then(|product| Balance::try_from(product).ok())
+    }
+
+    // The part of `amount` this fraction stands for, never more than all of it
+    pub fn share_of(self, amount: Balance) -> Balance {
+        if self >= Self::ONE {
+            return amount;
+        }
+        self.checked_mul_balance(amount).expect("a fraction of an amount fits")
+    }
+}
+
+fn mul_div(a: u128, b: u128, divisor: u128) -> Option<u128> {
+    if divisor == 0 {
+        return None;
+    }
+    u128::try_from(U256::from(a) * U256::from(b) / U256::from(divisor)).ok()
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (integer, fraction) = (self.0 / SCALE, self.0 % SCALE);
+        if fraction == 0 {
+            return write!(f, "{}", integer);
+        }
+        let fraction = format!("{:018}", fraction);
+        write!(f, "{}.{}", integer, fraction.trim_end_matches('0'))
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = DecimalError;
+
+    fn from_str(s: &str) -> Result<Self, DecimalError> {
+        let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if integer.is_empty() || !digits(integer) || !digits(fraction) || (s.contains('.') && fraction.is_empty()) {
+            return Err(DecimalError::Malformed(s.to_string()));
+        }
+        if fraction.len() > DECIMAL_PLACES {
+            return Err(DecimalError::TooPrecise(s.to_string()));
+        }
+        let overflow = || DecimalError::Overflow(s.to_string());
+        let integer: u128 = integer.parse().map_err(|_| overflow())?;
+        let fraction: u128 = format!("{:0<18}", fraction).parse().expect("18 digits fit");
+        integer.checked_mul(SCALE).and_then(|raw| raw.checked_add(fraction)).map(Self).ok_or_else(overflow)
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_point_arithmetic_is_exact_and_rounds_down() {
+        let rate: Decimal = "0.001".parse().unwrap();
+        assert_eq!(rate, Decimal::from_ratio(1, 1_000).unwrap());
+        assert_eq!(rate.to_string(), "0.001");
+        assert_eq!(Decimal::from_bps(250).to_string(), "0.025");
+        assert_eq!(
+            "12.5".parse::<Decimal>().unwrap().checked_mul(Decimal::from_integer(2)),
+            Some(Decimal::from_integer(25))
+        );
+        assert_eq!(Decimal::ONE.checked_div(Decimal::from_integer(3)).unwrap().to_string(), "0.333333333333333333");
+        assert_eq!(Decimal::ONE.checked_div(Decimal::ZERO), None);
+        assert_eq!(Decimal::MAX.checked_add(Decimal::ONE), None);
+        assert_eq!(Decimal::MAX.saturating_mul(Decimal::from_integer(2)), Decimal::MAX);
+        assert_eq!(Decimal::ZERO.saturating_sub(Decimal::ONE), Decimal::ZERO);
+
+        // Products wider than u128 are computed exactly before dividing back down
+        let large = Decimal::from_integer(u64::MAX);
+        assert_eq!(large.checked_mul(Decimal::ONE), Some(large));
+        assert_eq!(Decimal::from_bps(3_333).checked_mul_int(1_000), Some(333));
+        assert_eq!(Decimal::from_bps(20_000).share_of(Balance::from(7u64)), Balance::from(7u64));
+
+        for malformed in ["", ".5", "1.", "-1", "1e3", "0x10"] {
+            assert_eq!(malformed.parse::<Decimal>(), Err(DecimalError::Malformed(malformed.to_string())));
+        }
+        assert!(matches!("0.0000000000000000001".parse::<Decimal>(), Err(DecimalError::TooPrecise(_))));
+        assert_eq!(serde_json::to_string(&rate).unwrap(), "\"0.001\"");
+        assert_eq!(serde_json::from_str::<Decimal>("\"0.001\"").unwrap(), rate);
+        assert_eq!(bincode::deserialize::<Decimal>(&bincode::serialize(&large).unwrap()).unwrap(), large);
+    }
+}
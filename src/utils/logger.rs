@@ -1,45 +1,129 @@
-use log::{Record, Level, Metadata};
-use std::env;
+use std::fs::File;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use thiserror::Error;
+use tracing::Subscriber;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::{SubscriberInitExt, TryInitError};
+use tracing_subscriber::{fmt, reload, Layer, Registry};
 
-pub struct SimpleLogger;
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    // Human-readable lines, prefixed with the spans they happened in
+    #[default]
+    Text,
+    // One JSON object per line with the fields of the event and its spans,
+    // for Loki, ELK and the like
+    Json,
+}
 
-impl log::Log for SimpleLogger {
-    fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Info
-    }
+impl FromStr for LogFormat {
+    type Err = LoggerError;
 
-    fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            println!("{} - {}", record.level(), record.args());
+    fn from_str(s: &str) -> Result<Self, LoggerError> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(LoggerError::UnknownFormat(s.to_string())),
         }
     }
+}
 
-    fn flush(&self) {}
+#[derive(Debug, Error)]
+pub enum LoggerError {
+    #[error("Unknown log format {0:?}, expected text or json")]
+    UnknownFormat(String),
+    #[error("Logger already installed: {0}")]
+    AlreadyInstalled(#[from] TryInitError),
 }
 
-static LOGGER: SimpleLogger = SimpleLogger;
+// Overall level, changed at runtime by `set_max_level`
+static LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
 
-pub fn init_logger(default_level: &str) {
-    let log_level = env::var("LOG_LEVEL").unwrap_or_else(|_| default_level.to_string());
-    let level = match log_level.to_lowercase().as_str() {
-        "debug" => Level::Debug,
-        "info" => Level::Info,
-        "warn" => Level::Warn,
-        "error" => Level::Error,
-        _ => Level::Info,
+// Installs the process-wide logger, writing to stderr or `file`. Records
+// from the `log` macros go through it as well. RUST_LOG filters per module
+// on top of the overall level, which starts at trace until the config sets
+// it.
+pub fn init(format: LogFormat, file: Option<File>) -> Result<(), LoggerError> {
+    let (level, handle) = reload::Layer::new(LevelFilter::TRACE);
+    let modules = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("trace"));
+    let output = match file {
+        Some(file) => format_layer(format, BoxMakeWriter::new(Mutex::new(file)), false),
+        None => format_layer(format, BoxMakeWriter::new(std::io::stderr), true),
     };
-    log::set_logger(&LOGGER).unwrap();
-    log::set_max_level(level.to_level_filter());
+    tracing_subscriber::registry().with(level).with(modules).with(output).try_init()?;
+    let _ = LEVEL.set(handle);
+    Ok(())
+}
+
+// Sets the overall level for both `log` and `tracing`
+pub fn set_max_level(level: log::LevelFilter) {
+    log::set_max_level(level);
+    if let Some(handle) = LEVEL.get() {
+        let level = match level {
+            log::LevelFilter::Off => LevelFilter::OFF,
+            log::LevelFilter::Error => LevelFilter::ERROR,
+            log::LevelFilter::Warn => LevelFilter::WARN,
+            log::LevelFilter::Info => LevelFilter::INFO,
+            log::LevelFilter::Debug => LevelFilter::DEBUG,
+            log::LevelFilter::Trace => LevelFilter::TRACE,
+        };
+        let _ = handle.reload(level);
+    }
+}
+
+fn format_layer<S>(format: LogFormat, writer: BoxMakeWriter, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    match format {
+        LogFormat::Text => fmt::layer().with_writer(writer).with_ansi(ansi).boxed(),
+        LogFormat::Json => fmt::layer().json().with_current_span(true).with_span_list(true).with_writer(writer).boxed(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use log::info;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
 
     #[test]
-    fn test_logger() {
-        init_logger("info");
-        info!("Logger initialized successfully.");
+    fn test_json_lines_carry_span_fields() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert!(matches!("yaml".parse::<LogFormat>(), Err(LoggerError::UnknownFormat(_))));
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber =
+            Registry::default().with(format_layer(LogFormat::Json, BoxMakeWriter::new(move || writer.clone()), false));
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("block_import", height = 42).entered();
+            tracing::info!(txs = 3, "Imported block");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["fields"]["message"], "Imported block");
+        assert_eq!(line["fields"]["txs"], 3);
+        assert_eq!(line["span"]["name"], "block_import");
+        assert_eq!(line["span"]["height"], 42);
+        assert_eq!(line["spans"][0]["height"], 42);
     }
 }
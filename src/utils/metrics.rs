@@ -0,0 +1,179 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// Upper bounds of the latency buckets, in microseconds
+const LATENCY_BUCKETS_US: [u64; 16] = [
+    10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+#[derive(Debug, Clone)]
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+}
+
+impl Metric {
+    pub fn gauge(name: &'static str, help: &'static str, value: f64) -> Self {
+        Self { name, help, kind: MetricKind::Gauge, labels: Vec::new(), value }
+    }
+
+    pub fn counter(name: &'static str, help: &'static str, value: f64) -> Self {
+        Self { name, help, kind: MetricKind::Counter, labels: Vec::new(), value }
+    }
+
+    pub fn with_label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.labels.push((key, value.into()));
+        self
+    }
+}
+
+// Anything that can report a point-in-time set of metrics when scraped
+pub trait MetricsSource: Send + Sync {
+    fn collect(&self) -> Vec<Metric>;
+}
+
+#[derive(Default, Clone)]
+pub struct MetricsRegistry {
+    sources: Arc<RwLock<Vec<Arc<dyn MetricsSource>>>>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, source: Arc<dyn MetricsSource>) {
+        self.sources.write().unwrap().push(source);
+    }
+
+    pub fn collect(&self) -> Vec<Metric> {
+        self.sources.read().unwrap().iter().flat_map(|source| source.collect()).collect()
+    }
+
+    // Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut last_name = "";
+        for metric in self.collect() {
+            if metric.name != last_name {
+                let kind = match metric.kind {
+                    MetricKind::Counter => "counter",
+                    MetricKind::Gauge => "gauge",
+                };
+                let _ = writeln!(out, "# HELP {} {}", metric.name, metric.help);
+                let _ = writeln!(out, "# TYPE {} {}", metric.name, kind);
+                last_name = metric.name;
+            }
+            let labels: Vec<String> = metric.labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, v)).collect();
+            if labels.is_empty() {
+                let _ = writeln!(out, "{} {}", metric.name, metric.value);
+            } else {
+                let _ = writeln!(out, "{}{{{}}} {}", metric.name, labels.join(","), metric.value);
+            }
+        }
+        out
+    }
+}
+
+// Lock-free bucketed latency histogram; quantiles resolve to a bucket's upper bound
+#[derive(Default)]
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS_US.len() + 1],
+    count: AtomicU64,
+    total_us: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, elapsed: Duration) {
+        let us = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = LATENCY_BUCKETS_US.iter().position(|bound| us <= *bound).unwrap_or(LATENCY_BUCKETS_US.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(us, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn mean(&self) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_micros(self.total_us.load(Ordering::Relaxed) / count)
+    }
+
+    pub fn quantile(&self, q: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        let target = ((count as f64) * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= target {
+                let bound = LATENCY_BUCKETS_US.get(i).copied().unwrap_or(u64::MAX);
+                return Duration::from_micros(bound);
+            }
+        }
+        Duration::from_micros(u64::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed;
+
+    impl MetricsSource for Fixed {
+        fn collect(&self) -> Vec<Metric> {
+            vec![
+                Metric::gauge("db_size_bytes", "Size on disk", 10.0).with_label("column", "state"),
+                Metric::gauge("db_size_bytes", "Size on disk", 20.0).with_label("column", "bodies"),
+                Metric::counter("db_reads_total", "Reads", 3.0),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_render_prometheus_text() {
+        let registry = MetricsRegistry::new();
+        registry.register(Arc::new(Fixed));
+        let text = registry.render();
+        assert_eq!(text.matches("# TYPE db_size_bytes gauge").count(), 1);
+        assert!(text.contains("db_size_bytes{column=\"bodies\"} 20"));
+        assert!(text.contains("db_reads_total 3"));
+    }
+
+    #[test]
+    fn test_latency_quantiles() {
+        let histogram = LatencyHistogram::new();
+        for _ in 0..98 {
+            histogram.record(Duration::from_micros(40));
+        }
+        histogram.record(Duration::from_millis(3));
+        histogram.record(Duration::from_millis(3));
+
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.quantile(0.5), Duration::from_micros(50));
+        assert_eq!(histogram.quantile(0.99), Duration::from_micros(5_000));
+    }
+}
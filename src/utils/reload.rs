@@ -0,0 +1,179 @@
+use log::{info, warn, LevelFilter};
+use serde::Deserialize;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::watch;
+
+use crate::network::connection_limits::ConnectionLimitsConfig;
+use crate::network::rate_limit::{RequestLimit, RequestLimitsConfig};
+use crate::rpc::server::RpcConfig;
+use crate::utils::config_sources::{ConfigSourceError, ConfigSources};
+use crate::utils::logger;
+
+#[derive(Debug, Error)]
+pub enum ReloadError {
+    #[error(transparent)]
+    Sources(#[from] ConfigSourceError),
+    #[error("Invalid log level: {0}")]
+    LogLevel(String),
+}
+
+// The settings a running node picks up again on SIGHUP. Everything else in
+// the config file needs a restart. Environment and `--set` overrides still
+// win over the file after a reload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeConfig {
+    // `[core] log_level`
+    pub log_level: LevelFilter,
+    // `[rpc.rate_limit]`
+    pub rpc_rate_limit: RequestLimit,
+    // `[network.connections]`
+    pub connections: ConnectionLimitsConfig,
+    // `[network.request_limits]`
+    pub request_limits: RequestLimitsConfig,
+}
+
+// Only the sections above are read, so the rest of the file can't fail a reload
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Sections {
+    core: CoreSection,
+    rpc: RpcSection,
+    network: NetworkSection,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct CoreSection {
+    log_level: String,
+}
+
+impl Default for CoreSection {
+    fn default() -> Self {
+        Self { log_level: "info".to_string() }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct RpcSection {
+    rate_limit: RequestLimit,
+}
+
+impl Default for RpcSection {
+    fn default() -> Self {
+        Self { rate_limit: RpcConfig::default().rate_limit }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct NetworkSection {
+    connections: ConnectionLimitsConfig,
+    request_limits: RequestLimitsConfig,
+}
+
+impl RuntimeConfig {
+    pub fn load(sources: &ConfigSources) -> Result<Self, ReloadError> {
+        let sections: Sections = sources.load()?;
+        let log_level =
+            sections.core.log_level.parse().map_err(|_| ReloadError::LogLevel(sections.core.log_level.clone()))?;
+        Ok(Self {
+            log_level,
+            rpc_rate_limit: sections.rpc.rate_limit,
+            connections: sections.network.connections,
+            request_limits: sections.network.request_limits,
+        })
+    }
+}
+
+// Keeps the runtime settings in step with the config file. The log level is
+// applied here; the RPC server and the network hold a receiver from
+// `subscribe` and apply each change themselves.
+#[derive(Clone)]
+pub struct ConfigReloader {
+    sources: ConfigSources,
+    current: Arc<watch::Sender<RuntimeConfig>>,
+}
+
+impl ConfigReloader {
+    pub fn new(sources: ConfigSources) -> Result<Self, ReloadError> {
+        let config = RuntimeConfig::load(&sources)?;
+        logger::set_max_level(config.log_level);
+        let (current, _) = watch::channel(config);
+        Ok(Self { sources, current: Arc::new(current) })
+    }
+
+    pub fn current(&self) -> RuntimeConfig {
+        self.current.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<RuntimeConfig> {
+        self.current.subscribe()
+    }
+
+    // Re-reads the file and returns whether anything changed. A file that
+    // fails to parse leaves the running settings untouched.
+    pub fn reload(&self) -> Result<bool, ReloadError> {
+        let config = RuntimeConfig::load(&self.sources)?;
+        logger::set_max_level(config.log_level);
+        Ok(self.current.send_if_modified(|current| {
+            let changed = *current != config;
+            *current = config;
+            changed
+        }))
+    }
+
+    // Reloads on every SIGHUP
+    #[cfg(unix)]
+    pub fn listen_for_signals(&self) {
+        use tokio::signal::unix::{signal, SignalKind};
+        let reloader = self.clone();
+        tokio::spawn(async move {
+            let mut hangup = signal(SignalKind::hangup()).expect("SIGHUP handler can be installed");
+            while hangup.recv().await.is_some() {
+                match reloader.reload() {
+                    Ok(true) => info!("Reloaded runtime settings"),
+                    Ok(false) => info!("Reloaded the config, runtime settings unchanged"),
+                    Err(e) => warn!("Keeping the current runtime settings: {}", e),
+                }
+            }
+        });
+    }
+
+    // No SIGHUP here; `reload` can still be called directly
+    #[cfg(not(unix))]
+    pub fn listen_for_signals(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_reloads_runtime_settings_and_keeps_them_on_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        std::fs::write(&path, "[core]\nlog_level = \"info\"\n[storage]\nbackend = \"rocksdb\"\n").unwrap();
+        let reloader = ConfigReloader::new(ConfigSources::new().with_file(&path, true)).unwrap();
+        let mut changes = reloader.subscribe();
+        assert_eq!(reloader.current().connections, ConnectionLimitsConfig::default());
+        assert!(!reloader.reload().unwrap());
+
+        let edited = "[core]\nlog_level = \"debug\"\n[network.connections]\nmax_peers = 40\n\n\
+                      [rpc.rate_limit]\nrequests_per_sec = 5.0\nburst = 10.0\n";
+        std::fs::write(&path, edited).unwrap();
+        assert!(reloader.reload().unwrap());
+        assert!(changes.has_changed().unwrap());
+        let current = changes.borrow_and_update().clone();
+        assert_eq!((current.log_level, current.connections.max_peers), (LevelFilter::Debug, 40));
+        assert_eq!(current.connections.reserved_outbound, ConnectionLimitsConfig::default().reserved_outbound);
+        assert_eq!(current.rpc_rate_limit, RequestLimit { requests_per_sec: 5.0, burst: 10.0 });
+
+        std::fs::write(&path, "[core]\nlog_level = \"loud\"\n").unwrap();
+        assert!(matches!(reloader.reload(), Err(ReloadError::LogLevel(_))));
+        assert_eq!(reloader.current(), current);
+        assert!(!changes.has_changed().unwrap());
+    }
+}
@@ -0,0 +1,147 @@
+use futures::future::BoxFuture;
+use log::{info, warn};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+// Each hook gets this long before shutdown moves on without it
+pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Hooks run stage by stage, in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShutdownStage {
+    // Stop taking new work: RPC servers, block production, gossip intake
+    Intake,
+    // Write out what is buffered: mempool, import write batches
+    Flush,
+    // State that must survive the restart, e.g. consensus votes
+    Persist,
+    // Say goodbye to peers and close the swarm
+    Network,
+}
+
+type Hook = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+// Coordinates a graceful exit. Long-running tasks hold a clone and return
+// once `wait` resolves; components with something to flush or close
+// register a hook. The first SIGINT or SIGTERM triggers it, a second one
+// exits immediately.
+#[derive(Clone)]
+pub struct Shutdown {
+    trigger: Arc<watch::Sender<bool>>,
+    triggered: watch::Receiver<bool>,
+    hooks: Arc<Mutex<Vec<(ShutdownStage, &'static str, Hook)>>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (trigger, triggered) = watch::channel(false);
+        Self { trigger: Arc::new(trigger), triggered, hooks: Arc::new(Mutex::new(Vec::new())) }
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<F, Fut>(&self, stage: ShutdownStage, name: &'static str, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.hooks.lock().unwrap().push((stage, name, Box::new(move || Box::pin(hook()))));
+    }
+
+    pub fn trigger(&self) {
+        let _ = self.trigger.send(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.triggered.borrow()
+    }
+
+    pub async fn wait(&self) {
+        let mut triggered = self.triggered.clone();
+        while !*triggered.borrow_and_update() {
+            if triggered.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    // Triggers on the first signal and exits the process on the second
+    pub fn listen_for_signals(&self) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            let name = next_signal().await;
+            info!("Received {}, shutting down (send it again to exit immediately)", name);
+            shutdown.trigger();
+            let name = next_signal().await;
+            warn!("Received {} again, exiting without a clean shutdown", name);
+            std::process::exit(130);
+        });
+    }
+
+    // Triggers shutdown if that hasn't happened yet and runs every hook,
+    // stage by stage and in registration order within a stage
+    pub async fn run_hooks(&self, timeout: Duration) {
+        self.trigger();
+        let mut hooks = std::mem::take(&mut *self.hooks.lock().unwrap());
+        hooks.sort_by_key(|(stage, _, _)| *stage);
+        for (stage, name, hook) in hooks {
+            match tokio::time::timeout(timeout, hook()).await {
+                Ok(()) => info!("Shutdown: {} done ({:?})", name, stage),
+                Err(_) => warn!("Shutdown: {} did not finish within {:?}, skipping it", name, timeout),
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn next_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM handler can be installed");
+    let mut interrupt = signal(SignalKind::interrupt()).expect("SIGINT handler can be installed");
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    }
+}
+
+#[cfg(not(unix))]
+async fn next_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "Ctrl-C"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_runs_hooks_by_stage_and_skips_stuck_ones() {
+        let shutdown = Shutdown::new();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for (stage, name) in
+            [(ShutdownStage::Network, "swarm"), (ShutdownStage::Intake, "rpc"), (ShutdownStage::Flush, "mempool")]
+        {
+            let order = order.clone();
+            shutdown.register(stage, name, move || async move { order.lock().unwrap().push(name) });
+        }
+        shutdown.register(ShutdownStage::Persist, "votes", || futures::future::pending());
+
+        let worker = shutdown.clone();
+        let task = tokio::spawn(async move { worker.wait().await });
+        assert!(!shutdown.is_triggered());
+        shutdown.run_hooks(Duration::from_millis(50)).await;
+        task.await.unwrap();
+
+        assert!(shutdown.is_triggered());
+        assert_eq!(*order.lock().unwrap(), vec!["rpc", "mempool", "swarm"]);
+        // Hooks run once
+        shutdown.run_hooks(Duration::from_millis(50)).await;
+        assert_eq!(order.lock().unwrap().len(), 3);
+    }
+}
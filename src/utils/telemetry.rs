@@ -0,0 +1,219 @@
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Method, Request, Uri};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::chain::store::ChainStore;
+use crate::node::role::NodeRole;
+use crate::utils::dashboard::Dashboard;
+use crate::utils::metrics::MetricsRegistry;
+use crate::utils::shutdown::Shutdown;
+
+// `[telemetry]`. Off unless asked for: nothing leaves the node by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    // An http:// or https:// URL reports are POSTed to as JSON
+    pub endpoint: String,
+    pub interval_secs: u64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self { enabled: false, endpoint: String::new(), interval_secs: 300 }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TelemetryError {
+    #[error("Invalid telemetry endpoint {0:?}, expected an http:// or https:// URL")]
+    InvalidEndpoint(String),
+    #[error("HTTP error: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("Collector answered with HTTP status {0}")]
+    Status(u16),
+}
+
+// What a node reports. Nothing identifies the node or its operator: no peer
+// id, addresses or keys. `instance` is random per process, so the collector
+// can count nodes without following one across restarts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub instance: String,
+    pub version: &'static str,
+    pub chain: String,
+    pub role: NodeRole,
+    pub height: Option<u64>,
+    pub peers: usize,
+    pub os: &'static str,
+    pub arch: &'static str,
+}
+
+// Sends a `Report` every interval, from the same registry and store as the
+// dashboard
+pub struct Telemetry {
+    endpoint: Uri,
+    interval: Duration,
+    instance: String,
+    chain: String,
+    role: NodeRole,
+    status: Dashboard,
+    http: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl Telemetry {
+    pub fn new(
+        config: &TelemetryConfig,
+        chain: &str,
+        role: NodeRole,
+        metrics: MetricsRegistry,
+        store: Arc<ChainStore>,
+    ) -> Result<Self, TelemetryError> {
+        let endpoint: Uri =
+            config.endpoint.parse().map_err(|_| TelemetryError::InvalidEndpoint(config.endpoint.clone()))?;
+        if !matches!(endpoint.scheme_str(), Some("http" | "https")) || endpoint.host().is_none() {
+            return Err(TelemetryError::InvalidEndpoint(config.endpoint.clone()));
+        }
+        Ok(Self {
+            endpoint,
+            interval: Duration::from_secs(config.interval_secs.max(1)),
+            instance: format!("{:016x}", rand::random::<u64>()),
+            chain: chain.to_string(),
+            role,
+            status: Dashboard::new(metrics, store),
+            // Collectors are checked against the bundled web PKI roots, so
+            // reports go out the same from any host
+            http: Client::builder()
+                .build(HttpsConnectorBuilder::new().with_webpki_roots().https_or_http().enable_http1().build()),
+        })
+    }
+
+    pub async fn report(&mut self) -> Report {
+        let snapshot = self.status.snapshot().await;
+        Report {
+            instance: self.instance.clone(),
+            version: env!("CARGO_PKG_VERSION"),
+            chain: self.chain.clone(),
+            role: self.role,
+            height: snapshot.head,
+            peers: snapshot.peers.len(),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+        }
+    }
+
+    pub async fn send(&self, report: &Report) -> Result<(), TelemetryError> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.endpoint.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(report).expect("reports serialize")))
+            .expect("method, URI and header are valid");
+        let response = self.http.request(request).await?;
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(TelemetryError::Status(response.status().as_u16())),
+        }
+    }
+
+    // Reports until shutdown. A collector that is down only costs a warning;
+    // the next report goes out on schedule.
+    pub async fn run(mut self, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let report = self.report().await;
+                    match tokio::time::timeout(self.interval, self.send(&report)).await {
+                        Ok(Ok(())) => debug!("Sent telemetry to {}", self.endpoint),
+                        Ok(Err(e)) => warn!("Failed to send telemetry to {}: {}", self.endpoint, e),
+                        Err(_) => warn!("Telemetry to {} timed out", self.endpoint),
+                    }
+                }
+                _ = shutdown.wait() => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::genesis::GenesisConfig;
+    use crate::storage::db::Database;
+    use crate::utils::metrics::{Metric, MetricsSource};
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Response, Server};
+    use std::convert::Infallible;
+    use tempfile::TempDir;
+    use tokio::sync::mpsc;
+
+    struct Peers;
+
+    impl MetricsSource for Peers {
+        fn collect(&self) -> Vec<Metric> {
+            vec![
+                Metric::gauge("network_peer_latency_p99_seconds", "", 0.25).with_label("peer", "12D3KooWA"),
+                Metric::gauge("network_peer_latency_p99_seconds", "", 0.5).with_label("peer", "12D3KooWB"),
+            ]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_anonymized_stats_to_the_endpoint() {
+        let (sender, mut received) = mpsc::unbounded_channel();
+        let make_service = make_service_fn(move |_| {
+            let sender = sender.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let sender = sender.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                        sender.send(body).unwrap();
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let endpoint = format!("http://{}/submit", server.local_addr());
+        tokio::spawn(server);
+
+        let temp_dir = TempDir::new().unwrap();
+        let store = Arc::new(ChainStore::new(Arc::new(Database::new(temp_dir.path().join("db")).unwrap())));
+        GenesisConfig::default().initialize(&store).await.unwrap();
+        let metrics = MetricsRegistry::new();
+        metrics.register(Arc::new(Peers));
+        let config = TelemetryConfig { enabled: true, endpoint, interval_secs: 60 };
+        let mut telemetry = Telemetry::new(&config, "testnet", NodeRole::Full, metrics.clone(), store.clone()).unwrap();
+
+        let report = telemetry.report().await;
+        assert_eq!((report.height, report.peers, report.role), (Some(0), 2, NodeRole::Full));
+        telemetry.send(&report).await.unwrap();
+        let body = received.recv().await.unwrap();
+        let sent: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(sent["chain"], "testnet");
+        assert_eq!(sent["role"], "full");
+        assert_eq!(sent["os"], std::env::consts::OS);
+        assert!(!String::from_utf8_lossy(&body).contains("12D3KooW"));
+
+        let config = TelemetryConfig {
+            enabled: true,
+            endpoint: "https://telemetry.example/submit".to_string(),
+            interval_secs: 60,
+        };
+        assert!(Telemetry::new(&config, "testnet", NodeRole::Full, metrics.clone(), store.clone()).is_ok());
+        for endpoint in ["", "ftp://telemetry.example/submit", "not a url"] {
+            let config = TelemetryConfig { enabled: true, endpoint: endpoint.to_string(), interval_secs: 60 };
+            let result = Telemetry::new(&config, "testnet", NodeRole::Full, metrics.clone(), store.clone());
+            assert!(matches!(result, Err(TelemetryError::InvalidEndpoint(_))));
+        }
+    }
+}